@@ -0,0 +1,22 @@
+//! Greeting helpers used by the quickstart fixture
+
+/// Returns a friendly greeting for `name`
+pub fn greet(name: &str) -> String {
+    format!("Hello, {}!", name)
+}
+
+/// Counts the words in `text`
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greets() {
+        assert_eq!(greet("llama"), "Hello, llama!");
+        assert_eq!(word_count("one two three"), 3);
+    }
+}
@@ -0,0 +1,56 @@
+//! Benchmarks for the CPU-bound hot paths in `llamapackageservice::perf`
+//!
+//! CI runs these with `--output-format bencher` and fails the build when a
+//! benchmark regresses beyond the configured threshold.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use llamapackageservice::perf;
+use std::io::{Cursor, Write};
+
+fn sample_source(lines: usize) -> Vec<u8> {
+    "fn main() { println!(\"hello world\"); }\n".repeat(lines).into_bytes()
+}
+
+fn sample_archive(files: usize, lines_per_file: usize) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    let body = sample_source(lines_per_file);
+    for i in 0..files {
+        writer.start_file(format!("repo/src/module_{}.rs", i), options).unwrap();
+        writer.write_all(&body).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn bench_line_counting(c: &mut Criterion) {
+    let data = sample_source(200_000);
+    let mut group = c.benchmark_group("line_counting");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("count_lines", |b| b.iter(|| perf::count_lines(black_box(&data))));
+    group.finish();
+}
+
+fn bench_hashing(c: &mut Criterion) {
+    let data = sample_source(200_000);
+    let mut group = c.benchmark_group("hashing");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("sha256", |b| b.iter(|| perf::hash_bytes(black_box(&data))));
+    group.finish();
+}
+
+fn bench_extraction(c: &mut Criterion) {
+    let archive = sample_archive(200, 500);
+    let mut group = c.benchmark_group("extraction");
+    group.throughput(Throughput::Bytes(archive.len() as u64));
+    group.sample_size(20);
+    group.bench_function("extract_zip_parallel", |b| {
+        b.iter(|| {
+            let dir = tempfile::tempdir().unwrap();
+            perf::extract_zip_parallel(black_box(&archive), dir.path()).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_line_counting, bench_hashing, bench_extraction);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+fn main() {
+    // The gRPC service is generated from its protobuf contract; this needs
+    // `protoc`, so only builds with the `grpc` feature do it
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/jobs.proto");
+        tonic_build::compile_protos("proto/jobs.proto").expect("failed to compile proto/jobs.proto");
+    }
+}
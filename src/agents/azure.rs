@@ -0,0 +1,180 @@
+//! Azure OpenAI deployments
+//!
+//! Azure serves each model from a named deployment of an Azure OpenAI
+//! resource, at `{endpoint}/openai/deployments/{deployment}` with an
+//! `api-version` query parameter. Requests authenticate with the resource key
+//! in an `api-key` header, or with a Microsoft Entra ID (AAD) bearer token:
+//! one obtained elsewhere, or one requested for a service principal and
+//! reused until shortly before it expires.
+
+use std::time::{Duration, Instant};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use crate::error::{ProcessorError, Result};
+use crate::http;
+
+/// API version requested when none is configured
+pub const DEFAULT_API_VERSION: &str = "2024-06-01";
+
+/// Microsoft Entra ID in the public cloud
+const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+
+/// Scope of tokens for Azure OpenAI
+const TOKEN_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+
+/// How long before it expires a token is replaced
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(300);
+
+/// Which deployment of an Azure OpenAI resource to use, and how to sign in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AzureConfig {
+    /// Name of the deployment serving the model
+    pub deployment: String,
+    /// Name of the deployment serving the embedding model, if any
+    pub embedding_deployment: String,
+    /// Value of the `api-version` query parameter
+    pub api_version: String,
+    /// How requests authenticate
+    pub auth: AzureAuth,
+}
+
+/// How requests to an Azure OpenAI resource authenticate
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AzureAuth {
+    /// The resource key, taken from the agent's `api_key`
+    #[default]
+    ApiKey,
+    /// A Microsoft Entra ID access token obtained elsewhere
+    Token {
+        /// The bearer token
+        token: String,
+    },
+    /// A service principal whose client secret is exchanged for tokens
+    ClientCredentials {
+        /// Directory (tenant) the principal belongs to
+        tenant_id: String,
+        /// Application (client) ID of the principal
+        client_id: String,
+        /// Client secret of the principal
+        client_secret: String,
+        /// Entra ID endpoint, for sovereign clouds
+        authority_host: String,
+    },
+}
+
+impl Default for AzureConfig {
+    fn default() -> Self {
+        Self {
+            deployment: String::new(),
+            embedding_deployment: String::new(),
+            api_version: DEFAULT_API_VERSION.to_string(),
+            auth: AzureAuth::default(),
+        }
+    }
+}
+
+impl AzureConfig {
+    /// Reads the deployment and credentials from the variables the Azure SDKs use
+    ///
+    /// `AZURE_OPENAI_DEPLOYMENT` names the deployment,
+    /// `AZURE_OPENAI_EMBEDDING_DEPLOYMENT` the one for embeddings, and
+    /// `OPENAI_API_VERSION` overrides the API version. `AZURE_OPENAI_API_KEY`
+    /// signs in with the resource key; otherwise `AZURE_OPENAI_AD_TOKEN` is
+    /// used as a token, or `AZURE_TENANT_ID`, `AZURE_CLIENT_ID` and
+    /// `AZURE_CLIENT_SECRET` as a service principal.
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let deployment = var("AZURE_OPENAI_DEPLOYMENT")
+            .ok_or_else(|| ProcessorError::Config("AZURE_OPENAI_DEPLOYMENT environment variable not set".into()))?;
+        let auth = if var("AZURE_OPENAI_API_KEY").is_some() {
+            AzureAuth::ApiKey
+        } else if let Some(token) = var("AZURE_OPENAI_AD_TOKEN") {
+            AzureAuth::Token { token }
+        } else if let (Some(tenant_id), Some(client_id), Some(client_secret)) =
+            (var("AZURE_TENANT_ID"), var("AZURE_CLIENT_ID"), var("AZURE_CLIENT_SECRET"))
+        {
+            AzureAuth::ClientCredentials {
+                tenant_id,
+                client_id,
+                client_secret,
+                authority_host: var("AZURE_AUTHORITY_HOST").unwrap_or_else(|| DEFAULT_AUTHORITY_HOST.to_string()),
+            }
+        } else {
+            return Err(ProcessorError::Config(
+                "Azure OpenAI needs AZURE_OPENAI_API_KEY, AZURE_OPENAI_AD_TOKEN or a service principal \
+                 (AZURE_TENANT_ID, AZURE_CLIENT_ID, AZURE_CLIENT_SECRET)".into(),
+            ));
+        };
+        Ok(Self {
+            deployment,
+            embedding_deployment: var("AZURE_OPENAI_EMBEDDING_DEPLOYMENT").unwrap_or_default(),
+            api_version: var("OPENAI_API_VERSION").unwrap_or_else(|| DEFAULT_API_VERSION.to_string()),
+            auth,
+        })
+    }
+
+    /// URL of `operation`, `chat/completions` or `embeddings`, on its deployment at `endpoint`
+    pub fn url(&self, endpoint: &str, operation: &str) -> String {
+        let deployment = if operation == "embeddings" { &self.embedding_deployment } else { &self.deployment };
+        format!(
+            "{}/openai/deployments/{}/{}?api-version={}",
+            endpoint.trim_end_matches('/'), deployment, operation, self.api_version
+        )
+    }
+}
+
+/// Signs requests in, keeping service principal tokens until they are due to expire
+#[derive(Debug, Default)]
+pub struct Credentials {
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl Credentials {
+    /// Adds the credentials `auth` calls for to `request`
+    pub async fn sign(&self, client: &Client, auth: &AzureAuth, api_key: &str, request: RequestBuilder) -> Result<RequestBuilder> {
+        Ok(match auth {
+            AzureAuth::ApiKey => request.header("api-key", api_key),
+            AzureAuth::Token { token } => request.bearer_auth(token),
+            AzureAuth::ClientCredentials { .. } => request.bearer_auth(self.token(client, auth).await?),
+        })
+    }
+
+    async fn token(&self, client: &Client, auth: &AzureAuth) -> Result<String> {
+        let AzureAuth::ClientCredentials { tenant_id, client_id, client_secret, authority_host } = auth else {
+            unreachable!("only service principals request tokens");
+        };
+        // Held while requesting, so concurrent calls wait for the one token
+        let mut cached = self.token.lock().await;
+        if let Some((token, refresh_at)) = cached.as_ref() {
+            if Instant::now() < *refresh_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let url = format!("{}/{}/oauth2/v2.0/token", authority_host.trim_end_matches('/'), tenant_id);
+        let response = http::send(client.post(&url).form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", client_id.as_str()),
+            ("client_secret", client_secret.as_str()),
+            ("scope", TOKEN_SCOPE),
+        ])).await?;
+        let status = response.status();
+        let reply: Value = response.json().await.unwrap_or_default();
+        let token = match reply["access_token"].as_str() {
+            Some(token) if status.is_success() => token.to_string(),
+            _ => {
+                let reason = reply["error_description"].as_str().or(reply["error"].as_str()).unwrap_or("no details");
+                return Err(ProcessorError::Config(format!(
+                    "Microsoft Entra ID refused a token for {} ({}): {}", client_id, status, reason
+                )));
+            }
+        };
+        let lifetime = Duration::from_secs(reply["expires_in"].as_u64().unwrap_or(3600));
+        *cached = Some((token.clone(), Instant::now() + lifetime.saturating_sub(TOKEN_REFRESH_MARGIN)));
+        Ok(token)
+    }
+}
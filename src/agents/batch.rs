@@ -0,0 +1,450 @@
+//! Analyses through the OpenAI Batch API
+//!
+//! For runs over many repositories, the analysis requests are written as one
+//! JSONL file, uploaded, and queued as a batch that OpenAI answers within a
+//! day at half the price of the same requests made one by one. The batch's id
+//! and the repository behind each request are saved in
+//! `<output>/.ai-batches/` as soon as it is submitted, so a later run checks
+//! on the same batch instead of submitting another. Once the batch ends, its
+//! answers are written to `<output>/analyses` and cached like any other
+//! analysis. Repositories whose analysis is already cached are not sent.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::output_organizer::package_slug;
+use crate::perf;
+use crate::processors::common;
+use super::{provider, structured, usage, AgentConfig, AnalysisRequest, AnalysisResult, AnalysisType, OpenAIAgent, Provider};
+
+/// Directory, relative to the output directory, holding the state of batches
+pub const BATCH_DIR: &str = ".ai-batches";
+
+/// Directory, relative to the output directory, analyses are written to
+pub const ANALYSES_DIR: &str = "analyses";
+
+/// Share of the list price the Batch API charges
+const BATCH_DISCOUNT: f64 = 0.5;
+
+/// Endpoint every request of a batch is sent to
+const CHAT_ENDPOINT: &str = "/v1/chat/completions";
+
+/// Statuses after which a batch changes no more
+const FINAL_STATUSES: [&str; 4] = ["completed", "failed", "expired", "cancelled"];
+
+/// A request in a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequest {
+    /// Repository analyzed
+    pub repository: String,
+    /// Key of the analysis in the response cache
+    pub cache_key: String,
+}
+
+/// Progress of the batch analyzing one list of repositories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchState {
+    /// File the repositories were read from, or `stdin`
+    pub input: String,
+    /// Name of the analysis type, or the custom prompt
+    pub analysis_type: String,
+    /// Extra instructions sent with every request
+    pub context: Option<String>,
+    /// ID of the batch, empty when nothing had to be sent
+    pub batch_id: String,
+    /// Status of the batch as last seen, e.g. `in_progress` or `completed`
+    pub status: String,
+    /// When the batch was submitted
+    pub submitted_at: DateTime<Utc>,
+    /// When the batch was last checked on
+    pub updated_at: DateTime<Utc>,
+    /// Requests still waiting for an answer, by custom ID
+    pub pending: BTreeMap<String, BatchRequest>,
+    /// Markdown analysis written for each repository
+    pub written: BTreeMap<String, String>,
+    /// Why each repository that could not be analyzed failed
+    pub failed: BTreeMap<String, String>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl BatchState {
+    /// Path of the state of the `analysis_type` batch for `input` under `output_dir`
+    pub fn path_for(output_dir: &Path, input: &str, analysis_type: &str) -> PathBuf {
+        let name = match AnalysisType::from_name(analysis_type) {
+            AnalysisType::Custom(prompt) => format!("custom-{}", &perf::hash_bytes(prompt.as_bytes())[..12]),
+            known => known.name().to_string(),
+        };
+        output_dir.join(BATCH_DIR).join(format!("{}-{}.json", package_slug(input), name))
+    }
+
+    /// The state saved at `path`, if any
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        let mut state: Self = serde_json::from_slice(&bytes).ok()?;
+        state.path = path.to_path_buf();
+        Some(state)
+    }
+
+    /// Whether the batch has ended and its answers were written
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty() && FINAL_STATUSES.contains(&self.status.as_str())
+    }
+
+    fn save(&mut self) -> Result<()> {
+        self.updated_at = Utc::now();
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// The files and batches endpoints of the OpenAI API
+struct BatchApi {
+    client: Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl BatchApi {
+    fn new(config: &AgentConfig) -> Result<Self> {
+        if config.provider != Provider::OpenAI {
+            return Err(ProcessorError::Config(format!("{} does not offer the Batch API; use OpenAI", config.provider.name())));
+        }
+        Ok(Self {
+            client: http::client_builder()
+                .user_agent(concat!("llamapackageservice/", env!("CARGO_PKG_VERSION")))
+                .build()?,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+        })
+    }
+
+    /// Uploads `jsonl` as a batch input file, returning its ID
+    async fn upload(&self, jsonl: &str) -> Result<String> {
+        let boundary = format!("llamapackageservice-{}", uuid::Uuid::new_v4().simple());
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"requests.jsonl\"\r\n\
+             Content-Type: application/jsonl\r\n\r\n{}\r\n--{b}--\r\n",
+            jsonl, b = boundary
+        );
+        let request = self.client.post(format!("{}/files", self.base_url))
+            .header(reqwest::header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .body(body);
+        let file = self.send(request).await?;
+        id_of(&file, "file")
+    }
+
+    /// Queues the requests in the input file `file_id`, returning the batch
+    async fn create(&self, file_id: &str, input: &str) -> Result<Value> {
+        let request = self.client.post(format!("{}/batches", self.base_url)).json(&json!({
+            "input_file_id": file_id,
+            "endpoint": CHAT_ENDPOINT,
+            "completion_window": "24h",
+            "metadata": {"input": input},
+        }));
+        self.send(request).await
+    }
+
+    async fn batch(&self, batch_id: &str) -> Result<Value> {
+        self.send(self.client.get(format!("{}/batches/{}", self.base_url, batch_id))).await
+    }
+
+    /// Content of the file `file_id`, one JSON object per line
+    async fn lines(&self, file_id: &str) -> Result<Vec<Value>> {
+        let request = self.client.get(format!("{}/files/{}/content", self.base_url, file_id));
+        let response = http::send(self.authorize(request)).await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(ProcessorError::LLM(format!("OpenAI returned {} for file {}: {}", status, file_id, text)));
+        }
+        Ok(text.lines().filter(|line| !line.trim().is_empty()).filter_map(|line| serde_json::from_str(line).ok()).collect())
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value> {
+        let response = http::send(self.authorize(request)).await?;
+        let status = response.status();
+        let reply: Value = response.json().await.unwrap_or_default();
+        if !status.is_success() {
+            let message = reply["error"]["message"].as_str().unwrap_or("no details");
+            return Err(ProcessorError::LLM(format!("OpenAI returned {}: {}", status, message)));
+        }
+        Ok(reply)
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.api_key.is_empty() { request } else { request.bearer_auth(&self.api_key) }
+    }
+}
+
+impl OpenAIAgent {
+    /// Submits an `analysis_type` analysis of each of `repositories`, listed in `input`, as one batch
+    ///
+    /// When a batch for `input` is already under way, it is returned instead.
+    /// Repositories are processed `max_concurrent_analyses` at a time; those
+    /// that fail to process are recorded as failed, and those with a cached
+    /// analysis are written at once.
+    pub async fn submit_batch(
+        &self,
+        input: &str,
+        repositories: &[String],
+        analysis_type: &str,
+        context: Option<String>,
+    ) -> Result<BatchState> {
+        let output_dir = self.processing.output_dir.clone();
+        let path = BatchState::path_for(&output_dir, input, analysis_type);
+        if let Some(state) = BatchState::load(&path).filter(|state| !state.is_finished()) {
+            return Ok(state);
+        }
+        let api = BatchApi::new(&self.config)?;
+        usage::check_budget(&self.processing.ai_budget, &output_dir)?;
+
+        let mut state = BatchState {
+            input: input.to_string(),
+            analysis_type: analysis_type.to_string(),
+            context: context.clone(),
+            batch_id: String::new(),
+            status: "completed".to_string(),
+            submitted_at: Utc::now(),
+            updated_at: Utc::now(),
+            pending: BTreeMap::new(),
+            written: BTreeMap::new(),
+            failed: BTreeMap::new(),
+            path,
+        };
+        let requests: Vec<_> = repositories.iter()
+            .map(|repository| self.batch_line(repository, AnalysisType::from_name(analysis_type), context.clone()))
+            .collect();
+        let mut lines = Vec::new();
+        let mut prepared = stream::iter(requests).buffered(self.processing.processing.max_concurrent_analyses.max(1));
+        while let Some((repository, prepared)) = prepared.next().await {
+            match prepared {
+                Ok(Prepared::Cached(result, key)) => {
+                    let file = write_analysis(&output_dir, &repository, &result).await?;
+                    self.cache_analysis(&key, &result).await;
+                    state.written.insert(repository, file);
+                }
+                Ok(Prepared::Request(body, cache_key)) => {
+                    let custom_id = format!("request-{}", lines.len());
+                    lines.push(json!({"custom_id": custom_id, "method": "POST", "url": CHAT_ENDPOINT, "body": body}).to_string());
+                    state.pending.insert(custom_id, BatchRequest { repository, cache_key });
+                }
+                Err(e) => {
+                    state.failed.insert(repository, e.to_string());
+                }
+            }
+        }
+        if !lines.is_empty() {
+            let file_id = api.upload(&lines.join("\n")).await?;
+            let batch = api.create(&file_id, input).await?;
+            state.batch_id = id_of(&batch, "batch")?;
+            state.status = batch["status"].as_str().unwrap_or("validating").to_string();
+        }
+        state.save()?;
+        Ok(state)
+    }
+
+    /// Checks on the batch of `state`, writing its analyses once it has ended
+    ///
+    /// Returns whether the batch is finished. Answers cost half the list
+    /// price and are charged to the usage ledger as they are written.
+    pub async fn poll_batch(&self, state: &mut BatchState) -> Result<bool> {
+        if state.is_finished() {
+            return Ok(true);
+        }
+        let api = BatchApi::new(&self.config)?;
+        let batch = api.batch(&state.batch_id).await?;
+        state.status = batch["status"].as_str().unwrap_or_default().to_string();
+        if !FINAL_STATUSES.contains(&state.status.as_str()) {
+            state.save()?;
+            return Ok(false);
+        }
+
+        let output_dir = self.processing.output_dir.clone();
+        let analysis_type = AnalysisType::from_name(&state.analysis_type);
+        // Batches that expire or are cancelled still answer part of their requests
+        let mut lines = Vec::new();
+        for file in ["output_file_id", "error_file_id"] {
+            if let Some(file_id) = batch[file].as_str() {
+                lines.extend(api.lines(file_id).await?);
+            }
+        }
+        for line in lines {
+            let Some(request) = line["custom_id"].as_str().and_then(|id| state.pending.remove(id)) else {
+                continue;
+            };
+            let body = &line["response"]["body"];
+            let answered = line["response"]["status_code"].as_u64() == Some(200);
+            if !answered {
+                let reason = line["error"]["message"].as_str()
+                    .or(body["error"]["message"].as_str())
+                    .unwrap_or("no answer");
+                state.failed.insert(request.repository, reason.to_string());
+                continue;
+            }
+            let mut completion = match provider::read_completion(Provider::OpenAI, self.model(), body, &[]) {
+                Ok(completion) => completion,
+                Err(e) => {
+                    state.failed.insert(request.repository, e.to_string());
+                    continue;
+                }
+            };
+            if let Some(model) = body["model"].as_str() {
+                completion.usage = super::TokenUsage::new(model, completion.usage.prompt_tokens, completion.usage.completion_tokens);
+            }
+            completion.usage.estimated_cost_usd = completion.usage.estimated_cost_usd.map(|cost| cost * BATCH_DISCOUNT);
+            usage::record(&completion.usage);
+            let mut result = self.analysis_result(analysis_type.clone(), completion);
+            result.metadata.insert("batch_id".to_string(), state.batch_id.clone());
+            let file = write_analysis(&output_dir, &request.repository, &result).await?;
+            self.cache_analysis(&request.cache_key, &result).await;
+            state.written.insert(request.repository, file);
+        }
+        let status = state.status.clone();
+        for (_, request) in std::mem::take(&mut state.pending) {
+            state.failed.insert(request.repository, format!("the batch {} without answering", status));
+        }
+        state.save()?;
+        Ok(true)
+    }
+
+    /// The batch request analyzing `repository`, or its cached analysis
+    async fn batch_line(&self, repository: &str, analysis_type: AnalysisType, context: Option<String>) -> (String, Result<Prepared>) {
+        let prepared = async {
+            let request = AnalysisRequest {
+                repository: repository.to_string(),
+                analysis_type,
+                context,
+                parameters: Default::default(),
+            };
+            // Batches cannot fall back when a model refuses a schema, so the prompt alone asks for JSON
+            let schema = structured::schema_for(&request.analysis_type);
+            let messages = self.analysis_messages(&request, schema.as_ref().map(|(_, schema)| schema)).await?;
+            let key = self.analysis_key(&messages);
+            if let Some(cached) = self.cached_analysis(&key).await {
+                return Ok(Prepared::Cached(cached, key));
+            }
+            Ok(Prepared::Request(json!({
+                "model": self.model(),
+                "messages": messages,
+                "max_tokens": self.config.max_tokens,
+                "temperature": self.config.temperature,
+            }), key))
+        }.await;
+        (repository.to_string(), prepared)
+    }
+}
+
+/// A repository ready to be sent in a batch
+enum Prepared {
+    /// The body of its request, and the key its analysis is cached under
+    Request(Value, String),
+    /// Its analysis, found in the cache under the key
+    Cached(AnalysisResult, String),
+}
+
+/// Writes `result` for `repository` as Markdown and JSON, returning the Markdown file's name
+async fn write_analysis(output_dir: &Path, repository: &str, result: &AnalysisResult) -> Result<String> {
+    let dir = output_dir.join(ANALYSES_DIR);
+    tokio::fs::create_dir_all(&dir).await?;
+    let name = repository.split("://").last().unwrap_or(repository);
+    let stem = format!("{}_{}", result.analysis_type.name(), package_slug(name));
+    let markdown = common::write_artifact(&result.content, &dir.join(format!("{}.md", stem))).await?;
+    common::write_artifact(&serde_json::to_string_pretty(result)?, &dir.join(format!("{}.json", stem))).await?;
+    Ok(markdown.file_name().unwrap_or_default().to_string_lossy().into_owned())
+}
+
+fn id_of(reply: &Value, what: &str) -> Result<String> {
+    reply["id"].as_str().map(str::to_string)
+        .ok_or_else(|| ProcessorError::LLM(format!("OpenAI returned no {} ID", what)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_batches_are_submitted_once_and_collected_after_a_restart() {
+        let output = tempfile::tempdir().unwrap();
+        let repos = tempfile::tempdir().unwrap();
+        let repositories: Vec<String> = ["widgets", "gadgets"].iter().map(|name| {
+            let dir = repos.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("README.md"), format!("# {}\n", name)).unwrap();
+            dir.display().to_string()
+        }).collect();
+
+        let mut server = mockito::Server::new_async().await;
+        let upload = server.mock("POST", "/v1/files")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("name=\"purpose\"\r\n\r\nbatch".to_string()),
+                Matcher::Regex(r#""custom_id":"request-1""#.to_string()),
+            ]))
+            .with_body(json!({"id": "file-in"}).to_string())
+            .create_async().await;
+        let create = server.mock("POST", "/v1/batches")
+            .match_body(Matcher::PartialJson(json!({"input_file_id": "file-in", "endpoint": "/v1/chat/completions"})))
+            .with_body(json!({"id": "batch_1", "status": "validating"}).to_string())
+            .expect(1)
+            .create_async().await;
+        let running = server.mock("GET", "/v1/batches/batch_1")
+            .with_body(json!({"id": "batch_1", "status": "in_progress"}).to_string())
+            .expect(1)
+            .create_async().await;
+        let done = server.mock("GET", "/v1/batches/batch_1")
+            .with_body(json!({"id": "batch_1", "status": "completed", "output_file_id": "file-out"}).to_string())
+            .create_async().await;
+        let answers = [
+            json!({"custom_id": "request-0", "response": {"status_code": 200, "body": {
+                "model": "gpt-4o-mini",
+                "choices": [{"message": {"content": "Widgets widget."}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1000000, "completion_tokens": 0},
+            }}, "error": null}),
+            json!({"custom_id": "request-1", "response": {"status_code": 400, "body": {"error": {"message": "context too long"}}}, "error": null}),
+        ];
+        let output_file = server.mock("GET", "/v1/files/file-out/content")
+            .with_body(answers.iter().map(Value::to_string).collect::<Vec<_>>().join("\n"))
+            .create_async().await;
+
+        let mut processing = Config::default();
+        processing.output_dir = output.path().to_path_buf();
+        let agent = OpenAIAgent::new(AgentConfig {
+            model: "gpt-4o-mini".to_string(),
+            base_url: format!("{}/v1", server.url()),
+            ..AgentConfig::default()
+        }).unwrap().with_processing_config(processing);
+
+        let mut state = agent.submit_batch("repos.txt", &repositories, "documentation", None).await.unwrap();
+        assert_eq!((state.batch_id.as_str(), state.pending.len()), ("batch_1", 2));
+        assert!(!agent.poll_batch(&mut state).await.unwrap());
+
+        // Another run picks up the saved batch instead of submitting again
+        let mut state = agent.submit_batch("repos.txt", &repositories, "documentation", None).await.unwrap();
+        assert_eq!(state.status, "in_progress");
+        assert!(agent.poll_batch(&mut state).await.unwrap());
+        assert_eq!(state.written[&repositories[0]], format!("documentation_{}.md", package_slug(&repositories[0])));
+        assert_eq!(state.failed[&repositories[1]], "context too long");
+        let written = output.path().join(ANALYSES_DIR).join(&state.written[&repositories[0]]);
+        assert_eq!(std::fs::read_to_string(&written).unwrap(), "Widgets widget.");
+        let result: AnalysisResult = serde_json::from_slice(&std::fs::read(written.with_extension("json")).unwrap()).unwrap();
+        // Half the $0.15 a million gpt-4o-mini prompt tokens cost one by one
+        assert_eq!(result.usage.estimated_cost_usd, Some(0.075));
+        assert!(BatchState::load(&state.path).unwrap().is_finished());
+
+        for mock in [upload, create, running, done, output_file] {
+            mock.assert_async().await;
+        }
+    }
+}
@@ -1,10 +1,36 @@
 //! Conversation management for OpenAI agents
 //!
 //! This module handles interactive conversations with AI agents about repositories.
+//! [`ConversationStore`] keeps them in a SQLite database,
+//! `<output>/.conversations.sqlite`, with the embedded excerpts and files they
+//! were started with, so a conversation can be continued by a later command or
+//! another server without processing the repository again.
 
-use crate::error::Result;
+use crate::embeddings::VectorIndex;
+use crate::database::Database;
+use crate::error::{ProcessorError, Result};
+use super::tools::RepositoryFiles;
 use super::{ConversationContext, OpenAIAgent};
+use chrono::Utc;
+use rusqlite::{params, OptionalExtension};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// File name of the conversation database inside the output directory
+pub const CONVERSATIONS_DB_FILE: &str = ".conversations.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS conversations (
+        id TEXT PRIMARY KEY,
+        repository TEXT NOT NULL,
+        context TEXT NOT NULL,
+        vector_index TEXT,
+        files TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+";
 
 /// Conversation manager for interactive repository analysis
 pub struct ConversationManager {
@@ -52,4 +78,96 @@ impl ConversationManager {
     pub fn list_conversations(&self) -> Vec<String> {
         self.active_conversations.keys().cloned().collect()
     }
-} 
\ No newline at end of file
+} 
+
+/// SQLite-backed store of conversations
+pub struct ConversationStore {
+    db: Database,
+}
+
+impl ConversationStore {
+    /// Opens or creates the store at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = Database::open(path, SCHEMA)?;
+        Ok(Self { db })
+    }
+
+    /// Stores `context`, replacing an earlier version of the same conversation
+    pub fn save(&self, context: &ConversationContext) -> Result<()> {
+        let index = context.index.as_ref().map(serde_json::to_string).transpose()?;
+        let files = context.files.as_deref().map(serde_json::to_string).transpose()?;
+        let now = Utc::now();
+        self.db.lock().execute(
+            "INSERT INTO conversations (id, repository, context, vector_index, files, created_at, updated_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6) \
+             ON CONFLICT (id) DO UPDATE SET context = excluded.context, vector_index = excluded.vector_index, \
+             files = excluded.files, updated_at = excluded.updated_at",
+            params![context.id, context.repository, serde_json::to_string(context)?, index, files, now],
+        )?;
+        Ok(())
+    }
+
+    /// A conversation by id, ready to be continued
+    pub fn load(&self, id: &str) -> Result<Option<ConversationContext>> {
+        let row = self.db.lock().query_row(
+            "SELECT context, vector_index, files FROM conversations WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?)),
+        ).optional()?;
+        let Some((context, index, files)) = row else {
+            return Ok(None);
+        };
+        let mut context: ConversationContext = serde_json::from_str(&context)?;
+        context.index = index.map(|index| serde_json::from_str::<VectorIndex>(&index)).transpose()?;
+        context.files = files.map(|files| serde_json::from_str::<RepositoryFiles>(&files).map(Arc::new)).transpose()?;
+        Ok(Some(context))
+    }
+
+    /// Deletes a conversation, returning whether it was stored
+    pub fn delete(&self, id: &str) -> Result<bool> {
+        let deleted = self.db.lock().execute("DELETE FROM conversations WHERE id = ?1", params![id])?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_conversations_are_stored_with_what_they_were_started_with() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(CONVERSATIONS_DB_FILE);
+        let overview = "### `src/lib.rs`\n\n```rust\npub fn parse() {}\n```\n";
+        let mut context = ConversationContext {
+            id: "c1".to_string(),
+            repository: "https://github.com/acme/demo".to_string(),
+            messages: Vec::new(),
+            repository_context: "Repository: demo".to_string(),
+            session_state: HashMap::new(),
+            index: None,
+            files: Some(Arc::new(RepositoryFiles::from_artifacts(&[("demo.txt".to_string(), overview.to_string())]))),
+            workspace: None,
+        };
+        let store = ConversationStore::open(&path).unwrap();
+        store.save(&context).unwrap();
+        context.record("What does it parse?".to_string(), "Nothing yet.".to_string());
+        store.save(&context).unwrap();
+        drop(store);
+
+        // A later process continues where the last one stopped
+        let store = ConversationStore::open(&path).unwrap();
+        let resumed = store.load("c1").unwrap().unwrap();
+        assert_eq!(resumed.repository, "https://github.com/acme/demo");
+        assert_eq!(resumed.messages.len(), 2);
+        assert_eq!(resumed.messages[1].content, "Nothing yet.");
+        assert_eq!(resumed.files.unwrap().read("src/lib.rs"), Some("pub fn parse() {}"));
+        assert!(resumed.index.is_none());
+
+        assert!(store.load("c2").unwrap().is_none());
+        assert!(store.delete("c1").unwrap());
+        assert!(!store.delete("c1").unwrap());
+        assert!(store.load("c1").unwrap().is_none());
+    }
+}
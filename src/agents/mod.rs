@@ -3,27 +3,80 @@
 //! This module provides integration with OpenAI's API for intelligent
 //! code analysis, repository understanding, and automated documentation generation.
 //!
+//! Requests go to a [`CompletionProvider`]: the chat completions endpoint of
+//! one service, or a fallback chain of several. The repository is first run
+//! through the processor for its URL or path, and the text it produces, cut
+//! to a token budget, is sent along as context. Conversations instead embed
+//! the whole output and send the excerpts closest to each question, when the
+//! provider offers embeddings. Every call also has a streaming variant that
+//! yields the answer piece by piece as the model writes it. Conversations
+//! also offer the model [`tools`] for reading and searching the repository,
+//! which it may call for a few rounds before answering.
+//!
+//! Besides OpenAI, completions can come from a local Ollama server, or any
+//! other server with the chat completions API, so private repositories can be
+//! analyzed without their code leaving the machine, and from deployments on
+//! Azure OpenAI.
+//!
 //! Author: Nik Jois <nikjois@llamasearch.ai>
 
 use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use futures_util::stream::{self, BoxStream, StreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+use crate::cache::backend::{self, CacheBackend};
+use crate::chunking::{self, count_tokens, Boundary};
+use crate::compression;
+use crate::config::Config;
+use crate::embeddings::{self, Embedder, VectorIndex};
 use crate::error::{ProcessorError, Result};
+use crate::processors::{common, ProcessorFactory};
 
 pub mod analysis;
+pub mod azure;
+pub mod batch;
 pub mod conversation;
+pub mod prompts;
+pub mod provider;
+pub mod result_cache;
+pub mod structured;
+pub mod summarize;
 pub mod tools;
+pub mod usage;
+
+use provider::{ollama_base_url, ChatCompletions, CompletionProvider, FallbackChain, ProviderLimits};
+use prompts::PromptVariables;
+use structured::{CodeExample, CodeReviewReport, ExamplesReport, SecurityFinding, SecurityReport, StructuredOutput};
+use tools::{RepositoryFiles, ToolRegistry};
+#[cfg(test)]
+use provider::stream_event;
+
+/// Texts embedded per request
+const EMBEDDING_BATCH: usize = 64;
+
+/// Most excerpts sent with a question
+const RETRIEVED_EXCERPTS: usize = 8;
 
 /// OpenAI Agents client wrapper
 #[derive(Clone)]
 pub struct OpenAIAgent {
-    api_key: String,
     config: AgentConfig,
+    provider: Arc<dyn CompletionProvider>,
+    embedder: Option<Arc<dyn Embedder>>,
+    processing: Config,
+    cache: Option<Arc<dyn CacheBackend>>,
 }
 
 /// Configuration for OpenAI agents
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AgentConfig {
-    /// OpenAI API key
+    /// Service the completions come from
+    pub provider: Provider,
+    /// API key, sent as a bearer token unless empty
     pub api_key: String,
     /// Model to use for analysis
     pub model: String,
@@ -33,8 +86,102 @@ pub struct AgentConfig {
     pub temperature: f32,
     /// System prompt for repository analysis
     pub system_prompt: String,
+    /// Base URL of the API, up to but not including `/chat/completions`
+    pub base_url: String,
+    /// Seconds to wait for a completion before giving up
+    pub timeout_secs: u64,
+    /// Times a request is retried after a rate limit, server error or timeout
+    pub max_retries: u32,
+    /// Tokens of processed repository content sent as context
+    pub context_tokens: usize,
+    /// Model that embeds repository content for conversations
+    pub embedding_model: String,
+    /// Rounds of tool calls the model may make before it has to answer
+    pub tool_rounds: u32,
+    /// Whether analyses of repositories over `context_tokens` send map-reduce summaries instead of the start
+    pub summarize: bool,
+    /// Whether an analysis whose inputs have not changed returns the cached result instead of asking again
+    pub cache_analyses: bool,
+    /// Deployment and sign-in, when `provider` is Azure OpenAI
+    pub azure: azure::AzureConfig,
+}
+
+/// Service an agent requests completions from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// OpenAI's API, or another server with the same API at `base_url`
+    #[default]
+    OpenAI,
+    /// A local Ollama server, which needs no API key
+    Ollama,
+    /// A deployment on Azure OpenAI, whose resource endpoint is `base_url`
+    Azure,
+}
+
+impl Provider {
+    /// The provider called `name`, ignoring case
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "openai" => Ok(Self::OpenAI),
+            "ollama" => Ok(Self::Ollama),
+            "azure" => Ok(Self::Azure),
+            other => Err(ProcessorError::Config(format!(
+                "unknown AI provider '{}'; expected openai, ollama or azure", other
+            ))),
+        }
+    }
+
+    /// Name used in messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OpenAI => "OpenAI",
+            Self::Ollama => "Ollama",
+            Self::Azure => "Azure OpenAI",
+        }
+    }
+
+    /// Where the API is when no base URL is configured
+    pub fn default_base_url(self) -> &'static str {
+        match self {
+            Self::OpenAI => "https://api.openai.com/v1",
+            Self::Ollama => "http://localhost:11434/v1",
+            // Every resource has its own endpoint
+            Self::Azure => "",
+        }
+    }
+
+    /// Start of the names of the environment variables configuring it, e.g. `OLLAMA` for `OLLAMA_MODEL`
+    pub fn env_prefix(self) -> &'static str {
+        match self {
+            Self::OpenAI => "OPENAI",
+            Self::Ollama => "OLLAMA",
+            Self::Azure => "AZURE_OPENAI",
+        }
+    }
+
+    /// Model used when none is configured
+    pub fn default_model(self) -> &'static str {
+        match self {
+            Self::OpenAI | Self::Azure => "gpt-4",
+            Self::Ollama => "llama3.1",
+        }
+    }
+
+    /// Embedding model used when none is configured
+    pub fn default_embedding_model(self) -> &'static str {
+        match self {
+            Self::OpenAI => "text-embedding-3-small",
+            Self::Ollama => "nomic-embed-text",
+            // Embeddings need a deployment of their own
+            Self::Azure => "",
+        }
+    }
 }
 
+/// Pieces of an answer in the order the model writes them
+pub type TokenStream = BoxStream<'static, Result<String>>;
+
 /// Repository analysis request
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnalysisRequest {
@@ -49,7 +196,7 @@ pub struct AnalysisRequest {
 }
 
 /// Types of analysis that can be performed
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AnalysisType {
     /// Generate comprehensive repository documentation
     Documentation,
@@ -65,6 +212,92 @@ pub enum AnalysisType {
     Custom(String),
 }
 
+impl AnalysisType {
+    /// Parses the names used by the API: `documentation`, `security`,
+    /// `code_review`, `examples` and `api_docs`; anything else is a custom prompt
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "documentation" => Self::Documentation,
+            "security" => Self::SecurityAudit,
+            "code_review" => Self::CodeReview,
+            "examples" => Self::Examples,
+            "api_docs" => Self::ApiDocumentation,
+            custom => Self::Custom(custom.to_string()),
+        }
+    }
+
+    /// Name of the analysis type, and of its prompt template; `custom` for custom prompts
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Documentation => "documentation",
+            Self::SecurityAudit => "security",
+            Self::CodeReview => "code_review",
+            Self::Examples => "examples",
+            Self::ApiDocumentation => "api_docs",
+            Self::Custom(_) => "custom",
+        }
+    }
+}
+
+/// Tokens an analysis used and what they cost
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Model the tokens were counted for
+    pub model: String,
+    /// Tokens sent to the model
+    pub prompt_tokens: u64,
+    /// Tokens the model generated
+    pub completion_tokens: u64,
+    /// Estimated cost in US dollars, when the model's price is known
+    pub estimated_cost_usd: Option<f64>,
+}
+
+impl TokenUsage {
+    /// Usage of `model` for the given prompt and completion token counts
+    pub fn new(model: &str, prompt_tokens: u64, completion_tokens: u64) -> Self {
+        let estimated_cost_usd = model_prices(model).map(|(input, output)| {
+            (prompt_tokens as f64 * input + completion_tokens as f64 * output) / 1_000_000.0
+        });
+        Self { model: model.to_string(), prompt_tokens, completion_tokens, estimated_cost_usd }
+    }
+
+    /// Prompt and completion tokens together
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Adds `other` to this usage; the cost stays known only if both are
+    pub fn add(&mut self, other: &TokenUsage) {
+        let first = self.model.is_empty() && self.total_tokens() == 0;
+        self.estimated_cost_usd = match first {
+            true => other.estimated_cost_usd,
+            false => self.estimated_cost_usd.zip(other.estimated_cost_usd).map(|(a, b)| a + b),
+        };
+        if first || self.model == other.model {
+            self.model = other.model.clone();
+        } else {
+            self.model = "mixed".to_string();
+        }
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// List prices in US dollars per million input and output tokens
+fn model_prices(model: &str) -> Option<(f64, f64)> {
+    // Longest prefixes first, so "gpt-4o-mini" is not priced as "gpt-4o"
+    const PRICES: &[(&str, f64, f64)] = &[
+        ("gpt-4o-mini", 0.15, 0.60),
+        ("gpt-4o", 2.50, 10.00),
+        ("gpt-4-turbo", 10.00, 30.00),
+        ("gpt-4", 30.00, 60.00),
+        ("gpt-3.5-turbo", 0.50, 1.50),
+    ];
+    PRICES.iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|&(_, input, output)| (input, output))
+}
+
 /// Analysis result from OpenAI agent
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnalysisResult {
@@ -80,6 +313,23 @@ pub struct AnalysisResult {
     pub metadata: HashMap<String, String>,
     /// Timestamp of analysis
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Tokens used by the analysis
+    #[serde(default)]
+    pub usage: TokenUsage,
+    /// The report as JSON, for analysis types with a [`structured`] report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured: Option<Value>,
+}
+
+impl AnalysisResult {
+    /// The structured report of the analysis
+    pub fn report<T: StructuredOutput>(&self) -> Result<T> {
+        match &self.structured {
+            Some(report) => Ok(serde_json::from_value(report.clone())?),
+            None => Err(ProcessorError::Parse(self.metadata.get("parse_error").cloned()
+                .unwrap_or_else(|| format!("{} analyses have no structured report", self.analysis_type.name())))),
+        }
+    }
 }
 
 /// Conversation context for interactive analysis
@@ -87,12 +337,39 @@ pub struct AnalysisResult {
 pub struct ConversationContext {
     /// Conversation ID
     pub id: String,
+    /// Repository URL or path the conversation is about
+    #[serde(default)]
+    pub repository: String,
     /// Message history
     pub messages: Vec<Message>,
     /// Repository context
     pub repository_context: String,
     /// Analysis session state
     pub session_state: HashMap<String, String>,
+    /// Embedded repository content, searched for each question
+    #[serde(skip)]
+    pub index: Option<VectorIndex>,
+    /// Files of the processed repository, which the model's tools read
+    #[serde(skip)]
+    pub files: Option<Arc<RepositoryFiles>>,
+    /// Workspace the conversation belongs to; `None` for operators
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+}
+
+impl ConversationContext {
+    /// Whether a caller confined to `workspace` may see the conversation;
+    /// callers without a workspace see every conversation
+    pub fn is_visible_to(&self, workspace: Option<&str>) -> bool {
+        workspace.map_or(true, |name| self.workspace.as_deref() == Some(name))
+    }
+    
+    /// Adds a question and the reply to it to the history
+    pub fn record(&mut self, user_message: String, reply: String) {
+        let timestamp = chrono::Utc::now();
+        self.messages.push(Message { role: "user".to_string(), content: user_message, timestamp });
+        self.messages.push(Message { role: "assistant".to_string(), content: reply, timestamp });
+    }
 }
 
 /// Individual message in a conversation
@@ -109,59 +386,145 @@ pub struct Message {
 impl OpenAIAgent {
     /// Create a new OpenAI agent
     pub fn new(config: AgentConfig) -> Result<Self> {
-        Ok(Self {
-            api_key: config.api_key.clone(),
+        let provider = Arc::new(ChatCompletions::new(config.clone())?);
+        Ok(Self::with_provider(config, provider.clone()).with_embedder(provider))
+    }
+
+    /// An agent getting its completions from `provider`
+    ///
+    /// `config` supplies the system prompt and the context budget; where the
+    /// requests go is up to the provider.
+    pub fn with_provider(config: AgentConfig, provider: Arc<dyn CompletionProvider>) -> Self {
+        Self {
             config,
-        })
+            provider,
+            embedder: None,
+            processing: Config::default(),
+            cache: None,
+        }
+    }
+
+    /// Embeds repository content with `embedder` so conversations send what is relevant to each question
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Uses `config` when processing repositories into context, e.g. for its GitHub token
+    pub fn with_processing_config(mut self, config: Config) -> Self {
+        self.processing = config;
+        self
+    }
+
+    /// Sends summaries of repositories too large for the context, rather than their start
+    pub fn with_summaries(mut self, summarize: bool) -> Self {
+        self.config.summarize = summarize;
+        self
+    }
+
+    /// Caches summaries and analyses in `cache` rather than the process-wide response cache
+    pub fn with_cache(mut self, cache: Arc<dyn CacheBackend>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The cache summaries and analyses are kept in, if any
+    fn response_cache(&self) -> Option<Arc<dyn CacheBackend>> {
+        self.cache.clone().or_else(|| backend::shared_cache().cloned())
     }
 
     /// Create agent from environment variables
+    ///
+    /// `LLAMA_AI_PROVIDERS` lists the providers to try in order, e.g.
+    /// `openai,azure,ollama`; `LLAMA_AI_PROVIDER` names a single one, and
+    /// `openai` is the default. Each is configured as
+    /// [`AgentConfig::from_env`] reads it and limited as
+    /// [`ProviderLimits::from_env`] does. The first provider's settings shape
+    /// the prompt, and it embeds content for conversations.
     pub fn from_env() -> Result<Self> {
-        let api_key = std::env::var("OPENAI_API_KEY")
-            .map_err(|_| ProcessorError::new("OPENAI_API_KEY environment variable not set"))?;
-        
-        let config = AgentConfig {
-            api_key: api_key.clone(),
-            model: std::env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4".to_string()),
-            max_tokens: std::env::var("OPENAI_MAX_TOKENS")
-                .unwrap_or_else(|_| "4000".to_string())
-                .parse()
-                .unwrap_or(4000),
-            temperature: std::env::var("OPENAI_TEMPERATURE")
-                .unwrap_or_else(|_| "0.7".to_string())
-                .parse()
-                .unwrap_or(0.7),
-            system_prompt: include_str!("../../prompts/system_prompt.txt").to_string(),
-        };
-        
-        Self::new(config)
+        let names = std::env::var("LLAMA_AI_PROVIDERS")
+            .or_else(|_| std::env::var("LLAMA_AI_PROVIDER"))
+            .unwrap_or_else(|_| "openai".to_string());
+        let mut chain = FallbackChain::new();
+        let mut primary = None;
+        for name in names.split(',').filter(|name| !name.trim().is_empty()) {
+            let provider = Provider::from_name(name)?;
+            let config = AgentConfig::from_env(provider)?;
+            let completions = Arc::new(ChatCompletions::new(config.clone())?);
+            chain = chain.with_provider(completions.clone(), ProviderLimits::from_env(provider));
+            primary.get_or_insert((config, completions));
+        }
+        let (config, embedder) = primary.ok_or_else(|| ProcessorError::Config("LLAMA_AI_PROVIDERS names no provider".into()))?;
+        Ok(Self::with_provider(config, Arc::new(chain)).with_embedder(embedder))
     }
 
     /// Analyze a repository using OpenAI
     pub async fn analyze_repository(&self, request: AnalysisRequest) -> Result<AnalysisResult> {
-        // For now, return a mock result until we fix the OpenAI integration
-        let result = AnalysisResult {
-            id: uuid::Uuid::new_v4().to_string(),
-            analysis_type: request.analysis_type,
-            content: format!("Mock analysis for repository: {}", request.repository),
-            confidence: 0.8,
-            metadata: HashMap::new(),
-            timestamp: chrono::Utc::now(),
+        let schema = structured::schema_for(&request.analysis_type);
+        let messages = self.analysis_messages(&request, schema.as_ref().map(|(_, schema)| schema)).await?;
+        let key = self.analysis_key(&messages);
+        if let Some(cached) = self.cached_analysis(&key).await {
+            return Ok(cached);
+        }
+        let completion = match &schema {
+            Some((name, schema)) => self.complete_json(&messages, name, schema).await?,
+            None => self.complete(&messages, &[]).await?,
         };
-
+        let result = self.analysis_result(request.analysis_type, completion);
+        self.cache_analysis(&key, &result).await;
         Ok(result)
     }
 
+    /// Analyze a repository, streaming the analysis as it is written
+    ///
+    /// The analysis is written as prose, also for types with a structured report.
+    pub async fn analyze_repository_stream(&self, request: AnalysisRequest) -> Result<TokenStream> {
+        let messages = self.analysis_messages(&request, None).await?;
+        let key = self.analysis_key(&messages);
+        if let Some(cached) = self.cached_analysis(&key).await {
+            return Ok(stream::once(async move { Ok(cached.content) }).boxed());
+        }
+        let tokens = self.stream(&messages).await?;
+        Ok(self.caching(key, request.analysis_type, tokens))
+    }
+
+    /// Model the agent sends requests to
+    pub fn model(&self) -> &str {
+        self.provider.model()
+    }
+
     /// Start an interactive conversation about a repository
     pub async fn start_conversation(&self, repository: String) -> Result<ConversationContext> {
         let id = uuid::Uuid::new_v4().to_string();
-        let repository_context = self.load_repository_context(&repository).await?;
+        let artifacts = self.processed_artifacts(&repository).await?;
+        let repository_context = self.repository_context(&repository, &artifacts);
+        let index = match &self.embedder {
+            Some(embedder) => {
+                // Small enough for several excerpts to fit the context budget
+                let chunk_tokens = embeddings::CHUNK_TOKENS.min(self.config.context_tokens / 4).max(1);
+                let chunks = artifacts.iter()
+                    .flat_map(|(name, text)| embeddings::chunk_content(&repository, name, text, chunk_tokens))
+                    .collect();
+                match VectorIndex::build(embedder.as_ref(), chunks, EMBEDDING_BATCH).await {
+                    Ok(index) => Some(index),
+                    Err(e) => {
+                        warn!("Could not embed {}: {}; questions get the start of its content instead", repository, e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
         
         let context = ConversationContext {
             id,
+            repository,
             messages: Vec::new(),
             repository_context,
             session_state: HashMap::new(),
+            index,
+            files: Some(Arc::new(RepositoryFiles::from_artifacts(&artifacts))),
+            workspace: None,
         };
 
         Ok(context)
@@ -173,51 +536,73 @@ impl OpenAIAgent {
         context: &mut ConversationContext,
         user_message: String,
     ) -> Result<String> {
-        // Add user message to context
-        context.messages.push(Message {
-            role: "user".to_string(),
-            content: user_message.clone(),
-            timestamp: chrono::Utc::now(),
-        });
-
-        // For now, return a mock response until we fix the OpenAI integration
-        let response = format!("Mock response to: {}", user_message);
-
-        // Add assistant message to context
-        context.messages.push(Message {
-            role: "assistant".to_string(),
-            content: response.clone(),
-            timestamp: chrono::Utc::now(),
-        });
-
+        // The history is left as it was on failure, so the message can be sent again
+        let mut messages = self.conversation_messages(context, &user_message).await;
+        let tools = match &context.files {
+            Some(files) if self.config.tool_rounds > 0 => ToolRegistry::for_repository(files.clone())?,
+            _ => ToolRegistry::new(),
+        };
+        let definitions = tools.definitions();
+        let mut rounds = 0;
+        let response = loop {
+            // The last round offers no tools, so the model has to answer
+            let offered = if rounds < self.config.tool_rounds { definitions.as_slice() } else { &[] };
+            let completion = self.complete(&messages, offered).await?;
+            if completion.tool_calls.is_empty() || offered.is_empty() {
+                break completion.content;
+            }
+            messages.push(json!({
+                "role": "assistant",
+                "content": Some(completion.content).filter(|content| !content.is_empty()),
+                "tool_calls": completion.tool_calls.iter().map(provider::ToolCall::to_message).collect::<Vec<_>>(),
+            }));
+            for call in &completion.tool_calls {
+                let output = tools.call(&call.name, &call.arguments).await;
+                messages.push(json!({"role": "tool", "tool_call_id": call.id, "content": output}));
+            }
+            rounds += 1;
+        };
+        context.record(user_message, response.clone());
         Ok(response)
     }
 
+    /// Continue a conversation, streaming the reply as it is written
+    ///
+    /// The conversation is not changed; once the reply is complete, add the
+    /// exchange with [`ConversationContext::record`].
+    pub async fn continue_conversation_stream(
+        &self,
+        context: &ConversationContext,
+        user_message: &str,
+    ) -> Result<TokenStream> {
+        let messages = self.conversation_messages(context, user_message).await;
+        self.stream(&messages).await
+    }
+
     /// Generate code examples for a repository
-    pub async fn generate_examples(&self, repository: String) -> Result<Vec<String>> {
+    pub async fn generate_examples(&self, repository: String) -> Result<Vec<CodeExample>> {
         let request = AnalysisRequest {
-            repository: repository.clone(),
+            repository,
             analysis_type: AnalysisType::Examples,
             context: Some("Generate practical usage examples".to_string()),
             parameters: HashMap::new(),
         };
 
         let result = self.analyze_repository(request).await?;
-        
-        // Parse examples from the response
-        let examples: Vec<String> = result.content
-            .split("```")
-            .enumerate()
-            .filter_map(|(i, section)| {
-                if i % 2 == 1 { // Odd indices are code blocks
-                    Some(section.to_string())
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let report: ExamplesReport = result.report()?;
+        Ok(report.examples)
+    }
 
-        Ok(examples)
+    /// Review the code of a repository
+    pub async fn code_review(&self, repository: String) -> Result<CodeReviewReport> {
+        let request = AnalysisRequest {
+            repository,
+            analysis_type: AnalysisType::CodeReview,
+            context: None,
+            parameters: HashMap::new(),
+        };
+
+        self.analyze_repository(request).await?.report()
     }
 
     /// Perform security audit using AI
@@ -230,77 +615,184 @@ impl OpenAIAgent {
         };
 
         let result = self.analyze_repository(request).await?;
-        
-        // Parse security audit results
-        let audit_result = SecurityAuditResult {
+        let report: SecurityReport = result.report()?;
+        Ok(SecurityAuditResult {
             repository,
-            vulnerabilities: self.parse_vulnerabilities(&result.content),
-            recommendations: self.parse_recommendations(&result.content),
-            risk_score: self.calculate_risk_score(&result.content),
+            risk_score: report.risk_score(),
+            summary: report.summary,
+            vulnerabilities: report.findings,
+            recommendations: report.recommendations,
             timestamp: result.timestamp,
-        };
-
-        Ok(audit_result)
+        })
     }
 
     // Helper methods
-    fn build_system_prompt(&self, request: &AnalysisRequest) -> String {
-        let base_prompt = &self.config.system_prompt;
-        
-        let analysis_specific = match &request.analysis_type {
-            AnalysisType::Documentation => {
-                "Focus on creating comprehensive, well-structured documentation."
-            },
-            AnalysisType::CodeReview => {
-                "Analyze code quality, patterns, and suggest improvements."
-            },
-            AnalysisType::ApiDocumentation => {
-                "Generate detailed API documentation with examples."
-            },
-            AnalysisType::SecurityAudit => {
-                "Identify security vulnerabilities and best practices."
-            },
-            AnalysisType::Examples => {
-                "Create practical, working examples of how to use this code."
+
+    /// The result of an analysis of type `analysis_type` from the model's `completion`
+    ///
+    /// Structured reports are parsed out of the answer and rendered as Markdown.
+    fn analysis_result(&self, analysis_type: AnalysisType, completion: provider::Completion) -> AnalysisResult {
+        let mut metadata = HashMap::from([
+            ("model".to_string(), completion.usage.model.clone()),
+            ("provider".to_string(), completion.provider.clone()),
+        ]);
+        if let Some(reason) = &completion.finish_reason {
+            metadata.insert("finish_reason".to_string(), reason.clone());
+        }
+        // An answer cut off by the token limit is less trustworthy than a complete one
+        let confidence = if completion.finish_reason.as_deref() == Some("length") { 0.5 } else { 0.8 };
+        let (content, structured) = match structured::schema_for(&analysis_type) {
+            Some(_) => match structured::parse_answer(&analysis_type, &completion.content) {
+                Ok((report, markdown)) => (markdown, Some(report)),
+                // The answer is kept as written, for a person to read
+                Err(e) => {
+                    warn!("{}", e);
+                    metadata.insert("parse_error".to_string(), e.to_string());
+                    (completion.content, None)
+                }
             },
-            AnalysisType::Custom(prompt) => prompt,
+            None => (completion.content, None),
         };
+        AnalysisResult {
+            id: uuid::Uuid::new_v4().to_string(),
+            analysis_type,
+            content,
+            confidence,
+            metadata,
+            timestamp: chrono::Utc::now(),
+            usage: completion.usage,
+            structured,
+        }
+    }
 
-        format!("{}\n\nSpecific Instructions: {}", base_prompt, analysis_specific)
+    /// Asks the provider to answer `messages`, within the budget, and charges the answer to the ledger
+    async fn complete(&self, messages: &[Value], tools: &[Value]) -> Result<provider::Completion> {
+        usage::check_budget(&self.processing.ai_budget, &self.processing.output_dir)?;
+        let completion = self.provider.complete_with_tools(messages, tools).await?;
+        usage::record(&completion.usage);
+        Ok(completion)
     }
 
-    async fn load_repository_context(&self, repository: &str) -> Result<String> {
-        // This would load and summarize repository content
-        // For now, return a placeholder
-        Ok(format!("Repository: {}\nLoaded basic context", repository))
+    /// Like [`Self::complete`], asking for JSON matching `schema`
+    async fn complete_json(&self, messages: &[Value], name: &str, schema: &Value) -> Result<provider::Completion> {
+        usage::check_budget(&self.processing.ai_budget, &self.processing.output_dir)?;
+        let completion = self.provider.complete_json(messages, name, schema).await?;
+        usage::record(&completion.usage);
+        Ok(completion)
     }
 
-    fn parse_vulnerabilities(&self, content: &str) -> Vec<String> {
-        // Parse vulnerability information from AI response
-        content.lines()
-            .filter(|line| line.to_lowercase().contains("vulnerability") || 
-                          line.to_lowercase().contains("security"))
-            .map(|line| line.to_string())
-            .collect()
+    /// Streams the answer to `messages`, within the budget, charging it to the ledger as it is read
+    async fn stream(&self, messages: &[Value]) -> Result<TokenStream> {
+        usage::check_budget(&self.processing.ai_budget, &self.processing.output_dir)?;
+        let tokens = self.provider.stream(messages).await?;
+        let prompt_tokens = messages.iter().map(|m| count_tokens(m["content"].as_str().unwrap_or_default()) as u64).sum();
+        Ok(usage::metered(self.provider.model(), prompt_tokens, tokens))
+    }
+
+    /// The system prompt followed by the instructions the template for the analysis type renders
+    fn build_system_prompt(&self, request: &AnalysisRequest, files: &RepositoryFiles) -> String {
+        let mut variables = PromptVariables::for_repository(&request.repository, files);
+        variables.context = request.context.clone();
+        if let AnalysisType::Custom(prompt) = &request.analysis_type {
+            variables.instructions = prompt.clone();
+        }
+        format!("{}\n\n{}", self.config.system_prompt, prompts::render(&request.analysis_type, &variables))
     }
 
-    fn parse_recommendations(&self, content: &str) -> Vec<String> {
-        // Parse recommendations from AI response
-        content.lines()
-            .filter(|line| line.to_lowercase().contains("recommend") || 
-                          line.to_lowercase().contains("should"))
-            .map(|line| line.to_string())
+    /// System prompt and processed repository for an analysis
+    async fn analysis_messages(&self, request: &AnalysisRequest, schema: Option<&Value>) -> Result<Vec<Value>> {
+        let artifacts = self.processed_artifacts(&request.repository).await?;
+        let files = RepositoryFiles::from_artifacts(&artifacts);
+        let oversized = artifacts.iter().map(|(_, content)| count_tokens(content)).sum::<usize>() > self.config.context_tokens;
+        let repository_context = if self.config.summarize && oversized {
+            let cache = self.response_cache();
+            self.summarize_files(&request.repository, &files, cache.as_deref()).await?
+                .to_context(&request.repository, self.config.context_tokens)
+        } else {
+            self.repository_context(&request.repository, &artifacts)
+        };
+        let mut system = self.build_system_prompt(request, &files);
+        if let Some(schema) = schema {
+            system = format!("{}\n\n{}", system, structured::instructions(schema));
+        }
+        let instructions = match &request.context {
+            Some(context) => format!("{}\n\n{}", repository_context, context),
+            None => repository_context,
+        };
+        Ok(vec![
+            json!({"role": "system", "content": system}),
+            json!({"role": "user", "content": instructions}),
+        ])
+    }
+
+    /// The conversation so far followed by `user_message`
+    ///
+    /// The excerpts closest to `user_message` stand in for the repository when
+    /// the conversation has an index, and the start of its content otherwise.
+    async fn conversation_messages(&self, context: &ConversationContext, user_message: &str) -> Vec<Value> {
+        let excerpts = match (&context.index, &self.embedder) {
+            (Some(index), Some(embedder)) if !index.is_empty() && index.model == embedder.embedding_model() => {
+                self.retrieve(embedder.as_ref(), index, user_message).await
+                    .map_err(|e| warn!("Could not search the repository: {}; sending the start of its content", e))
+                    .ok()
+            }
+            _ => None,
+        };
+        let repository = excerpts.as_ref().unwrap_or(&context.repository_context);
+        let system = format!("{}\n\n{}", self.config.system_prompt, repository);
+        std::iter::once(json!({"role": "system", "content": system}))
+            .chain(context.messages.iter().map(|m| json!({"role": m.role, "content": m.content})))
+            .chain(std::iter::once(json!({"role": "user", "content": user_message})))
             .collect()
     }
 
-    fn calculate_risk_score(&self, content: &str) -> u8 {
-        // Simple risk calculation based on content
-        let risk_keywords = ["critical", "high", "vulnerability", "exploit"];
-        let risk_count = risk_keywords.iter()
-            .map(|keyword| content.to_lowercase().matches(keyword).count())
-            .sum::<usize>();
-        
-        std::cmp::min(risk_count as u8 * 20, 100)
+    /// The excerpts of `index` closest to `question` that fit the context budget
+    async fn retrieve(&self, embedder: &dyn Embedder, index: &VectorIndex, question: &str) -> Result<String> {
+        let query = embedder.embed(&[question.to_string()]).await?.pop()
+            .ok_or_else(|| ProcessorError::LLM("no embedding came back for the question".into()))?;
+        let mut excerpts = String::from("Excerpts of the repository most relevant to the question:\n");
+        let mut budget = self.config.context_tokens;
+        for found in index.search(&query, RETRIEVED_EXCERPTS) {
+            let excerpt = format!(
+                "\n==> {} (bytes {}..{}) <==\n{}\n", found.metadata.file, found.metadata.start, found.metadata.end, found.text
+            );
+            let tokens = count_tokens(&excerpt);
+            if tokens > budget {
+                break;
+            }
+            budget -= tokens;
+            excerpts.push_str(&excerpt);
+        }
+        Ok(excerpts)
+    }
+
+    /// The processed `artifacts` of `repository`, cut to the context budget
+    fn repository_context(&self, repository: &str, artifacts: &[(String, String)]) -> String {
+        let mut context = format!("Repository: {}\n", repository);
+        for (name, content) in artifacts {
+            context.push_str(&format!("\n==> {} <==\n{}\n", name, content));
+        }
+        chunking::truncate(&context, self.config.context_tokens, Boundary::Heading)
+    }
+
+    /// Runs the repository through its processor and returns the name and text of each artifact
+    async fn processed_artifacts(&self, repository: &str) -> Result<Vec<(String, String)>> {
+        let target = crate::utils::normalize_url_or_path(repository);
+        let scratch = tempfile::tempdir()?;
+        let mut config = self.processing.clone();
+        config.output_dir = scratch.path().to_path_buf();
+        ProcessorFactory::create_for(&target, None)?
+            .process(&target, scratch.path(), &config)
+            .await?;
+
+        let mut artifacts = Vec::new();
+        for path in common::content_artifacts(scratch.path()) {
+            let mut content = String::new();
+            compression::open_artifact(&path)?.read_to_string(&mut content)?;
+            let name = path.strip_prefix(scratch.path()).unwrap_or(&path);
+            artifacts.push((name.display().to_string(), content));
+        }
+        Ok(artifacts)
     }
 }
 
@@ -309,8 +801,10 @@ impl OpenAIAgent {
 pub struct SecurityAuditResult {
     /// Repository that was audited
     pub repository: String,
+    /// Overall assessment
+    pub summary: String,
     /// Identified vulnerabilities
-    pub vulnerabilities: Vec<String>,
+    pub vulnerabilities: Vec<SecurityFinding>,
     /// Security recommendations
     pub recommendations: Vec<String>,
     /// Risk score (0-100)
@@ -319,14 +813,112 @@ pub struct SecurityAuditResult {
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+impl AgentConfig {
+    /// The configuration of `provider` in environment variables
+    ///
+    /// OpenAI needs `OPENAI_API_KEY` unless `OPENAI_BASE_URL` points
+    /// elsewhere; Ollama is found at `OLLAMA_HOST`, or on localhost, and
+    /// needs no key. Azure OpenAI reads `AZURE_OPENAI_ENDPOINT` and what
+    /// [`azure::AzureConfig::from_env`] does. The model is read from
+    /// `OPENAI_MODEL`, `OLLAMA_MODEL` or `AZURE_OPENAI_MODEL`.
+    pub fn from_env(provider: Provider) -> Result<Self> {
+        let base_url = match provider {
+            Provider::Ollama => std::env::var("OLLAMA_HOST").ok().map(|host| ollama_base_url(&host)),
+            Provider::OpenAI => std::env::var("OPENAI_BASE_URL").ok(),
+            Provider::Azure => Some(std::env::var("AZURE_OPENAI_ENDPOINT").map_err(|_| {
+                ProcessorError::Config("AZURE_OPENAI_ENDPOINT environment variable not set".into())
+            })?),
+        };
+        let (api_key, azure) = match provider {
+            Provider::Azure => (std::env::var("AZURE_OPENAI_API_KEY").unwrap_or_default(), azure::AzureConfig::from_env()?),
+            _ => (std::env::var("OPENAI_API_KEY").unwrap_or_default(), azure::AzureConfig::default()),
+        };
+        if provider == Provider::OpenAI && base_url.is_none() && api_key.is_empty() {
+            return Err(ProcessorError::new(
+                "OPENAI_API_KEY environment variable not set (set LLAMA_AI_PROVIDER=ollama to use a local model)",
+            ));
+        }
+        let defaults = AgentConfig::for_provider(provider);
+
+        // Azure deployments are usually named after their model
+        let model = match provider {
+            Provider::Azure => azure.deployment.clone(),
+            _ => defaults.model.clone(),
+        };
+
+        Ok(AgentConfig {
+            api_key,
+            model: std::env::var(format!("{}_MODEL", provider.env_prefix())).unwrap_or(model),
+            max_tokens: std::env::var("OPENAI_MAX_TOKENS")
+                .ok()
+                .and_then(|tokens| tokens.parse().ok())
+                .unwrap_or(defaults.max_tokens),
+            temperature: std::env::var("OPENAI_TEMPERATURE")
+                .unwrap_or_else(|_| "0.7".to_string())
+                .parse()
+                .unwrap_or(0.7),
+            system_prompt: include_str!("../../prompts/system_prompt.txt").to_string(),
+            base_url: base_url.unwrap_or_else(|| defaults.base_url.clone()),
+            context_tokens: std::env::var("OPENAI_CONTEXT_TOKENS")
+                .ok()
+                .and_then(|tokens| tokens.parse().ok())
+                .unwrap_or(defaults.context_tokens),
+            embedding_model: std::env::var(format!("{}_EMBEDDING_MODEL", provider.env_prefix()))
+                .unwrap_or_else(|_| match provider {
+                    Provider::Azure => azure.embedding_deployment.clone(),
+                    _ => defaults.embedding_model.clone(),
+                }),
+            tool_rounds: std::env::var("OPENAI_TOOL_ROUNDS")
+                .ok()
+                .and_then(|rounds| rounds.parse().ok())
+                .unwrap_or(defaults.tool_rounds),
+            summarize: std::env::var("OPENAI_SUMMARIZE")
+                .is_ok_and(|value| matches!(value.as_str(), "1" | "true" | "yes")),
+            cache_analyses: !std::env::var("OPENAI_CACHE_ANALYSES")
+                .is_ok_and(|value| matches!(value.as_str(), "0" | "false" | "no")),
+            azure,
+            ..defaults
+        })
+    }
+
+    /// The defaults for `provider`
+    pub fn for_provider(provider: Provider) -> Self {
+        let mut config = Self {
+            provider,
+            model: provider.default_model().to_string(),
+            base_url: provider.default_base_url().to_string(),
+            embedding_model: provider.default_embedding_model().to_string(),
+            ..Self::default()
+        };
+        if provider == Provider::Ollama {
+            // Ollama gives models a 2048-token context unless told otherwise,
+            // about 600 of which the system prompt takes
+            config.context_tokens = 900;
+            config.max_tokens = 500;
+        }
+        config
+    }
+}
+
 impl Default for AgentConfig {
     fn default() -> Self {
         Self {
+            provider: Provider::OpenAI,
             api_key: String::new(),
             model: "gpt-4".to_string(),
             max_tokens: 4000,
             temperature: 0.7,
             system_prompt: "You are an expert software engineer and code analyst.".to_string(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            timeout_secs: 300,
+            max_retries: 3,
+            // Leaves room for the answer within gpt-4's 8k context
+            context_tokens: 3000,
+            embedding_model: "text-embedding-3-small".to_string(),
+            tool_rounds: 5,
+            summarize: false,
+            cache_analyses: true,
+            azure: azure::AzureConfig::default(),
         }
     }
 }
@@ -334,6 +926,7 @@ impl Default for AgentConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures_util::StreamExt;
 
     #[test]
     fn test_agent_config_default() {
@@ -355,4 +948,342 @@ mod tests {
         let deserialized: AnalysisRequest = serde_json::from_str(&serialized).unwrap();
         assert_eq!(deserialized.repository, request.repository);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_token_usage_costs() {
+        let usage = TokenUsage::new("gpt-4o-mini", 1_000_000, 500_000);
+        assert_eq!(usage.total_tokens(), 1_500_000);
+        assert!((usage.estimated_cost_usd.unwrap() - 0.45).abs() < 1e-9);
+        assert_eq!(TokenUsage::new("local-llama", 10, 10).estimated_cost_usd, None);
+
+        let mut total = TokenUsage::default();
+        total.add(&TokenUsage::new("gpt-4", 1000, 0));
+        total.add(&TokenUsage::new("gpt-4", 0, 1000));
+        assert_eq!(total.model, "gpt-4");
+        assert!((total.estimated_cost_usd.unwrap() - 0.09).abs() < 1e-9);
+        total.add(&TokenUsage::new("local-llama", 1, 1));
+        assert_eq!(total.model, "mixed");
+        assert_eq!(total.estimated_cost_usd, None);
+    }
+
+    fn agent_for(server: &mockito::Server) -> OpenAIAgent {
+        OpenAIAgent::new(AgentConfig {
+            api_key: "sk-test".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            base_url: format!("{}/v1", server.url()),
+            max_retries: 1,
+            ..AgentConfig::default()
+        }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_analysis_sends_the_processed_repository() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join("README.md"), "# Widgets\n\nFrobnicates the quux.\n").unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let completion = server.mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer sk-test")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::PartialJson(json!({"model": "gpt-4o-mini"})),
+                mockito::Matcher::Regex("Frobnicates the quux".to_string()),
+            ]))
+            .with_body(json!({
+                "choices": [{"message": {"role": "assistant", "content": "Widgets frobnicate."}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1200, "completion_tokens": 30},
+            }).to_string())
+            .create_async().await;
+
+        let result = agent_for(&server).analyze_repository(AnalysisRequest {
+            repository: repo.path().display().to_string(),
+            analysis_type: AnalysisType::Documentation,
+            context: None,
+            parameters: HashMap::new(),
+        }).await.unwrap();
+        completion.assert_async().await;
+        assert_eq!(result.content, "Widgets frobnicate.");
+        assert_eq!(result.usage, TokenUsage::new("gpt-4o-mini", 1200, 30));
+        assert_eq!(result.metadata["finish_reason"], "stop");
+
+        // A spent budget stops requests before they are sent
+        let mut processing = Config::default();
+        processing.ai_budget.run_usd = Some(0.0);
+        let refused = agent_for(&server).with_processing_config(processing).analyze_repository(AnalysisRequest {
+            repository: repo.path().display().to_string(),
+            analysis_type: AnalysisType::Documentation,
+            context: None,
+            parameters: HashMap::new(),
+        }).await;
+        assert!(matches!(refused, Err(ProcessorError::RateLimitExceeded(_))), "{:?}", refused.map(|r| r.content));
+        completion.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_completions_are_retried_and_mapped() {
+        let mut server = mockito::Server::new_async().await;
+        let agent = agent_for(&server);
+        let mut context = ConversationContext {
+            id: "c1".to_string(),
+            repository: String::new(),
+            messages: Vec::new(),
+            repository_context: "Repository: widgets".to_string(),
+            session_state: HashMap::new(),
+            index: None,
+            files: None,
+            workspace: None,
+        };
+
+        // The first attempt is refused, the retry answered
+        let overloaded = server.mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .with_header("retry-after", "0")
+            .expect(1)
+            .create_async().await;
+        let answer = server.mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("Repository: widgets".to_string()))
+            .with_body(r#"{"choices": [{"message": {"content": "Hello"}}]}"#)
+            .expect(1)
+            .create_async().await;
+        let reply = agent.continue_conversation(&mut context, "Hi".to_string()).await.unwrap();
+        assert_eq!(reply, "Hello");
+        assert_eq!(context.messages.len(), 2);
+        overloaded.assert_async().await;
+        answer.assert_async().await;
+
+        // Rejected keys are not retried, and the history is left as it was
+        server.reset();
+        server.mock("POST", "/v1/chat/completions")
+            .with_status(401)
+            .with_body(r#"{"error": {"message": "Incorrect API key provided"}}"#)
+            .create_async().await;
+        let messages = context.messages.len();
+        let error = agent.continue_conversation(&mut context, "Hi again".to_string()).await.unwrap_err();
+        assert!(matches!(&error, ProcessorError::Config(m) if m.contains("Incorrect API key")), "{}", error);
+        assert_eq!(context.messages.len(), messages);
+    }
+
+    #[tokio::test]
+    async fn test_replies_stream_as_they_are_written() {
+        let mut server = mockito::Server::new_async().await;
+        let event = |token: &str| format!("data: {}\n\n", json!({"choices": [{"delta": {"content": token}}]}));
+        let body = [": keep-alive\n\n".to_string(), event("Wid"), event(""), event("gets 🦙"), "data: [DONE]\n\n".to_string()].concat();
+        server.mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({"stream": true})))
+            .with_header("content-type", "text/event-stream")
+            // Cut in the middle of the llama, which spans four bytes
+            .with_chunked_body(move |out| {
+                let split = body.find('🦙').unwrap() + 2;
+                out.write_all(&body.as_bytes()[..split])?;
+                out.flush()?;
+                out.write_all(&body.as_bytes()[split..])
+            })
+            .create_async().await;
+
+        let mut context = ConversationContext {
+            id: "c1".to_string(),
+            repository: String::new(),
+            messages: Vec::new(),
+            repository_context: String::new(),
+            session_state: HashMap::new(),
+            index: None,
+            files: None,
+            workspace: None,
+        };
+        let tokens: Vec<String> = agent_for(&server).continue_conversation_stream(&context, "Hi").await.unwrap()
+            .map(|token| token.unwrap())
+            .collect().await;
+        assert_eq!(tokens, ["Wid", "gets 🦙"]);
+        context.record("Hi".to_string(), tokens.concat());
+        assert_eq!(context.messages[1].content, "Widgets 🦙");
+
+        assert!(matches!(stream_event(Provider::OpenAI, r#"data: {"error": {"message": "overloaded"}}"#), Err(ProcessorError::LLM(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_runs_without_a_key() {
+        let mut server = mockito::Server::new_async().await;
+        let agent = OpenAIAgent::new(AgentConfig {
+            base_url: ollama_base_url(&server.host_with_port()),
+            ..AgentConfig::for_provider(Provider::Ollama)
+        }).unwrap();
+        assert_eq!(agent.model(), "llama3.1");
+        assert_eq!(ollama_base_url("http://gpu-box:11434/"), "http://gpu-box:11434/v1");
+
+        let conversation = || ConversationContext {
+            id: "c1".to_string(),
+            repository: String::new(),
+            messages: Vec::new(),
+            repository_context: String::new(),
+            session_state: HashMap::new(),
+            index: None,
+            files: None,
+            workspace: None,
+        };
+        let answer = server.mock("POST", "/v1/chat/completions")
+            .match_header("authorization", mockito::Matcher::Missing)
+            .match_body(mockito::Matcher::PartialJson(json!({"model": "llama3.1"})))
+            .with_body(r#"{"choices": [{"message": {"content": "Hello"}}]}"#)
+            .create_async().await;
+        assert_eq!(agent.continue_conversation(&mut conversation(), "Hi".to_string()).await.unwrap(), "Hello");
+        answer.assert_async().await;
+
+        server.reset();
+        server.mock("POST", "/v1/chat/completions")
+            .with_status(404)
+            .with_body(r#"{"error": {"message": "model \"llama3.1\" not found, try pulling it first"}}"#)
+            .create_async().await;
+        let error = agent.continue_conversation(&mut conversation(), "Hi".to_string()).await.unwrap_err();
+        assert!(matches!(&error, ProcessorError::Config(m) if m.contains("ollama pull")), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_azure_deployments_sign_in_with_a_key_or_a_service_principal() {
+        let mut server = mockito::Server::new_async().await;
+        let endpoint = server.url();
+        let deployment = azure::AzureConfig { deployment: "gpt4o-prod".to_string(), ..Default::default() };
+        let agent = |auth: azure::AzureAuth| OpenAIAgent::new(AgentConfig {
+            api_key: "resource-key".to_string(),
+            base_url: endpoint.clone(),
+            azure: azure::AzureConfig { auth, ..deployment.clone() },
+            ..AgentConfig::for_provider(Provider::Azure)
+        }).unwrap();
+        let conversation = || ConversationContext {
+            id: "c1".to_string(),
+            repository: String::new(),
+            messages: Vec::new(),
+            repository_context: String::new(),
+            session_state: HashMap::new(),
+            index: None,
+            files: None,
+            workspace: None,
+        };
+        let path = "/openai/deployments/gpt4o-prod/chat/completions?api-version=2024-06-01";
+
+        let keyed = server.mock("POST", path)
+            .match_header("api-key", "resource-key")
+            .match_header("authorization", mockito::Matcher::Missing)
+            .with_body(r#"{"choices": [{"message": {"content": "Hello"}}]}"#)
+            .create_async().await;
+        let reply = agent(azure::AzureAuth::ApiKey).continue_conversation(&mut conversation(), "Hi".to_string()).await;
+        assert_eq!(reply.unwrap(), "Hello");
+        keyed.assert_async().await;
+
+        // One token serves both requests
+        let token = server.mock("POST", "/tenant-1/oauth2/v2.0/token")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("grant_type".into(), "client_credentials".into()),
+                mockito::Matcher::UrlEncoded("client_id".into(), "app-1".into()),
+                mockito::Matcher::UrlEncoded("scope".into(), "https://cognitiveservices.azure.com/.default".into()),
+            ]))
+            .with_body(r#"{"access_token": "entra-token", "expires_in": 3599, "token_type": "Bearer"}"#)
+            .expect(1)
+            .create_async().await;
+        let signed = server.mock("POST", path)
+            .match_header("authorization", "Bearer entra-token")
+            .match_header("api-key", mockito::Matcher::Missing)
+            .with_body(r#"{"choices": [{"message": {"content": "Hello"}}]}"#)
+            .expect(2)
+            .create_async().await;
+        let principal = agent(azure::AzureAuth::ClientCredentials {
+            tenant_id: "tenant-1".to_string(),
+            client_id: "app-1".to_string(),
+            client_secret: "secret".to_string(),
+            authority_host: endpoint.clone(),
+        });
+        for _ in 0..2 {
+            principal.continue_conversation(&mut conversation(), "Hi".to_string()).await.unwrap();
+        }
+        token.assert_async().await;
+        signed.assert_async().await;
+
+        let unnamed = AgentConfig { base_url: endpoint.clone(), ..AgentConfig::for_provider(Provider::Azure) };
+        assert!(matches!(OpenAIAgent::new(unnamed), Err(ProcessorError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_conversations_send_the_excerpts_closest_to_the_question() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::create_dir(repo.path().join("src")).unwrap();
+        std::fs::write(repo.path().join("src/parser.rs"), "/// Parses tokens\npub fn parse(tokens: &[Token]) -> Ast { parse_items(tokens) }\n").unwrap();
+        std::fs::write(repo.path().join("src/render.rs"), "/// Renders pages\npub fn render(page: &Page) -> Html { render_blocks(page) }\n").unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        // Vectors count the words "parse" and "render"
+        let embeddings = server.mock("POST", "/v1/embeddings")
+            .match_body(mockito::Matcher::PartialJson(json!({"model": "text-embedding-3-small"})))
+            .with_body_from_request(|request| {
+                let body: Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+                let data: Vec<Value> = body["input"].as_array().unwrap().iter().enumerate()
+                    .map(|(index, text)| {
+                        let text = text.as_str().unwrap().to_lowercase();
+                        json!({"index": index, "embedding": [text.matches("parse").count(), text.matches("render").count()]})
+                    })
+                    .rev()
+                    .collect();
+                json!({"data": data}).to_string().into_bytes()
+            })
+            .expect_at_least(2)
+            .create_async().await;
+        let sent = Arc::new(std::sync::Mutex::new(String::new()));
+        let captured = sent.clone();
+        server.mock("POST", "/v1/chat/completions")
+            .with_body_from_request(move |request| {
+                let body: Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+                *captured.lock().unwrap() = body["messages"][0]["content"].as_str().unwrap().to_string();
+                br#"{"choices": [{"message": {"content": "It walks the blocks."}}]}"#.to_vec()
+            })
+            .create_async().await;
+
+        let agent = OpenAIAgent::new(AgentConfig {
+            base_url: format!("{}/v1", server.url()),
+            context_tokens: 200,
+            ..AgentConfig::default()
+        }).unwrap();
+        let mut context = agent.start_conversation(repo.path().display().to_string()).await.unwrap();
+        assert!(context.index.as_ref().is_some_and(|index| index.len() > 2));
+        let reply = agent.continue_conversation(&mut context, "How does render work?".to_string()).await.unwrap();
+        assert_eq!(reply, "It walks the blocks.");
+        embeddings.assert_async().await;
+
+        let system = sent.lock().unwrap().clone();
+        let first = system.find("==> ").expect("excerpts were sent");
+        assert!(system[first..].starts_with("==> src/render.rs"), "{}", system);
+        assert!(system.find("render_blocks") < system.find("parse_items").or(Some(usize::MAX)), "{}", system);
+    }
+
+    #[tokio::test]
+    async fn test_conversations_let_the_model_read_files_before_answering() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::create_dir(repo.path().join("src")).unwrap();
+        std::fs::write(repo.path().join("src/parser.rs"), "pub fn parse(tokens: &[Token]) -> Ast { parse_items(tokens) }\n").unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        // Once the file has been read, the answer
+        let answer = server.mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex(r#""role":"tool","tool_call_id":"call_1""#.to_string()),
+                mockito::Matcher::Regex("1 pub fn parse.*parse_items".to_string()),
+            ]))
+            .with_body(r#"{"choices": [{"message": {"content": "parse delegates to parse_items."}, "finish_reason": "stop"}]}"#)
+            .expect(1)
+            .create_async().await;
+        let call = server.mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(r#""name":"read_file""#.to_string()))
+            .with_body(r#"{"choices": [{"message": {"content": null, "tool_calls": [{"id": "call_1", "type": "function",
+                "function": {"name": "read_file", "arguments": "{\"path\": \"src/parser.rs\"}"}}]}, "finish_reason": "tool_calls"}]}"#)
+            .expect(1)
+            .create_async().await;
+
+        let agent = OpenAIAgent::new(AgentConfig {
+            base_url: format!("{}/v1", server.url()),
+            embedding_model: String::new(),
+            ..AgentConfig::default()
+        }).unwrap();
+        let mut context = agent.start_conversation(repo.path().display().to_string()).await.unwrap();
+        let reply = agent.continue_conversation(&mut context, "What does parse call?".to_string()).await.unwrap();
+        assert_eq!(reply, "parse delegates to parse_items.");
+        call.assert_async().await;
+        answer.assert_async().await;
+        // Only the question and the answer are kept
+        assert_eq!(context.messages.len(), 2);
+    }
+}
@@ -0,0 +1,196 @@
+//! Prompt templates of analyses
+//!
+//! Each analysis type has a Tera template that renders the instructions sent
+//! after the system prompt. Teams can replace them by dropping a template of
+//! the same name, such as `security.md.tera`, into the `prompts` folder of the
+//! configuration directory (e.g. `~/.config/llama-package-service/prompts`).
+//! Templates see the repository name, its language mix and its declared
+//! dependencies, read from the processed output.
+
+use crate::batch_report::language_for;
+use crate::error::{ProcessorError, Result};
+use crate::run_diff::{manifest_dependencies, DEPENDENCY_MANIFESTS};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera};
+use super::tools::RepositoryFiles;
+use super::AnalysisType;
+
+/// Built-in templates by analysis type name
+const BUILTIN: [(&str, &str); 6] = [
+    ("documentation", include_str!("../../prompts/analysis/documentation.md.tera")),
+    ("security", include_str!("../../prompts/analysis/security.md.tera")),
+    ("code_review", include_str!("../../prompts/analysis/code_review.md.tera")),
+    ("examples", include_str!("../../prompts/analysis/examples.md.tera")),
+    ("api_docs", include_str!("../../prompts/analysis/api_docs.md.tera")),
+    ("custom", include_str!("../../prompts/analysis/custom.md.tera")),
+];
+
+/// What a prompt template can refer to
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PromptVariables {
+    /// Repository URL or path
+    pub repository: String,
+    /// Languages by number of files, most used first
+    pub languages: Vec<LanguageShare>,
+    /// Dependencies declared in the repository's manifests, by name
+    pub dependencies: Vec<Dependency>,
+    /// Context the request was made with, if any
+    pub context: Option<String>,
+    /// The prompt of a custom analysis
+    pub instructions: String,
+}
+
+/// Files of the repository in one language
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LanguageShare {
+    /// Language name
+    pub name: String,
+    /// Number of files
+    pub files: u64,
+}
+
+/// A declared dependency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Dependency {
+    /// Package name
+    pub name: String,
+    /// Version requirement, `*` when none is given
+    pub version: String,
+}
+
+impl PromptVariables {
+    /// The variables of `repository`, read from the files of its processed output
+    pub fn for_repository(repository: &str, files: &RepositoryFiles) -> Self {
+        let mut languages = BTreeMap::new();
+        let mut dependencies = BTreeMap::new();
+        for path in files.paths() {
+            if let Some(language) = language_for(path) {
+                *languages.entry(language).or_insert(0) += 1;
+            }
+            let name = path.rsplit('/').next().unwrap_or(path);
+            if DEPENDENCY_MANIFESTS.contains(&name) {
+                manifest_dependencies(name, files.read(path).unwrap_or_default(), &mut dependencies);
+            }
+        }
+        let mut languages: Vec<LanguageShare> = languages.into_iter()
+            .map(|(name, files)| LanguageShare { name: name.to_string(), files })
+            .collect();
+        languages.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.name.cmp(&b.name)));
+        Self {
+            repository: repository.to_string(),
+            languages,
+            dependencies: dependencies.into_iter().map(|(name, version)| Dependency { name, version }).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+/// The built-in prompt templates, with any replacements loaded over them
+pub struct PromptTemplates {
+    tera: Tera,
+}
+
+impl PromptTemplates {
+    /// Returns the default directory replacement templates are loaded from
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("llama-package-service").join("prompts"))
+    }
+
+    /// The built-in templates only
+    pub fn builtin() -> Self {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(BUILTIN.iter().map(|(name, template)| (template_name(name), *template)))
+            .expect("built-in prompt templates parse");
+        Self { tera }
+    }
+
+    /// The built-in templates, replaced by those present in `dir`
+    ///
+    /// Missing templates are not an error; only templates that fail to parse are.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut templates = Self::builtin();
+        for (name, _) in BUILTIN {
+            let path = dir.join(template_name(name));
+            if path.is_file() {
+                templates.tera.add_template_file(&path, Some(&template_name(name)))
+                    .map_err(|e| ProcessorError::Template(format!("{}: {}", path.display(), e)))?;
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Renders the instructions for `analysis_type`
+    pub fn render(&self, analysis_type: &AnalysisType, variables: &PromptVariables) -> Result<String> {
+        let name = template_name(analysis_type.name());
+        let context = Context::from_serialize(variables)
+            .map_err(|e| ProcessorError::Template(format!("{}: {}", name, e)))?;
+        self.tera.render(&name, &context)
+            .map(|rendered| rendered.trim().to_string())
+            .map_err(|e| ProcessorError::Template(format!("{}: {}", name, e)))
+    }
+}
+
+fn template_name(analysis_type: &str) -> String {
+    format!("{}.md.tera", analysis_type)
+}
+
+static TEMPLATES: Lazy<PromptTemplates> = Lazy::new(|| {
+    let Some(dir) = PromptTemplates::default_dir().filter(|dir| dir.is_dir()) else {
+        return PromptTemplates::builtin();
+    };
+    PromptTemplates::load(&dir).unwrap_or_else(|e| {
+        log::warn!("Ignoring prompt templates: {}", e);
+        PromptTemplates::builtin()
+    })
+});
+
+/// Renders the instructions for `analysis_type` from the installed templates
+///
+/// A replacement template that fails to render is logged, and the built-in
+/// one is used instead.
+pub fn render(analysis_type: &AnalysisType, variables: &PromptVariables) -> String {
+    TEMPLATES.render(analysis_type, variables).unwrap_or_else(|e| {
+        log::warn!("Falling back to the built-in prompt: {}", e);
+        PromptTemplates::builtin().render(analysis_type, variables).unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_prompts_render_the_repository_and_can_be_replaced() {
+        let overview = "### `Cargo.toml`\n\n```toml\n[dependencies]\nserde = \"1.0\"\ntokio = { version = \"1\" }\n```\n\n\
+                        ### `src/lib.rs`\n\n```rust\n```\n\n### `src/main.rs`\n\n```rust\n```\n\n### `build.py`\n\n```python\n```\n";
+        let files = RepositoryFiles::from_artifacts(&[("demo.txt".to_string(), overview.to_string())]);
+        let variables = PromptVariables::for_repository("https://github.com/acme/demo", &files);
+        assert_eq!(variables.languages[0], LanguageShare { name: "Rust".to_string(), files: 2 });
+
+        let builtin = PromptTemplates::builtin();
+        let documentation = builtin.render(&AnalysisType::Documentation, &variables).unwrap();
+        assert!(documentation.starts_with("Specific Instructions: Focus on creating"), "{}", documentation);
+        assert!(documentation.contains("Repository: https://github.com/acme/demo"));
+        assert!(documentation.contains("Languages: Rust (2 files), Python (1 file)"), "{}", documentation);
+        assert!(documentation.contains("Dependencies: serde 1.0, tokio 1"), "{}", documentation);
+        let custom = PromptVariables { instructions: "List the TODOs.".to_string(), ..variables.clone() };
+        assert!(builtin.render(&AnalysisType::Custom("List the TODOs.".to_string()), &custom).unwrap()
+            .starts_with("Specific Instructions: List the TODOs."));
+
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("security.md.tera"), "Audit {{ repository }}: {{ dependencies | length }} dependencies").unwrap();
+        let replaced = PromptTemplates::load(dir.path()).unwrap();
+        assert_eq!(
+            replaced.render(&AnalysisType::SecurityAudit, &variables).unwrap(),
+            "Audit https://github.com/acme/demo: 2 dependencies"
+        );
+        assert_eq!(replaced.render(&AnalysisType::Documentation, &variables).unwrap(), documentation);
+
+        std::fs::write(dir.path().join("examples.md.tera"), "{% if %}").unwrap();
+        assert!(matches!(PromptTemplates::load(dir.path()), Err(ProcessorError::Template(_))));
+    }
+}
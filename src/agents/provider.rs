@@ -0,0 +1,682 @@
+//! Services that write completions
+//!
+//! An agent asks a [`CompletionProvider`] for completions without knowing
+//! which service answers. [`ChatCompletions`] speaks the chat completions API
+//! of OpenAI, Ollama and Azure OpenAI, and embeds text through their
+//! embeddings API. A [`FallbackChain`] tries several
+//! providers in order: one that fails is passed over for the next, and one
+//! that is over its requests per minute or has spent its daily budget is
+//! skipped. When only rate limits stand in the way, the chain waits for the
+//! first provider to have room again.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use futures_util::stream::{self, StreamExt};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+use crate::chunking::count_tokens;
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::embeddings::Embedder;
+use super::{azure, AgentConfig, Provider, TokenStream, TokenUsage};
+
+/// Longest a retried request waits before trying again
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A service that answers chat messages
+#[async_trait]
+pub trait CompletionProvider: Send + Sync {
+    /// Name used in messages and results
+    fn name(&self) -> &str;
+
+    /// Model requests are sent to
+    fn model(&self) -> &str;
+
+    /// The answer to `messages`
+    async fn complete(&self, messages: &[Value]) -> Result<Completion>;
+
+    /// The answer to `messages`, or calls of the functions in `tools` to make first
+    ///
+    /// `tools` is in the format of the chat completions API. Providers that
+    /// cannot call functions answer without them.
+    async fn complete_with_tools(&self, messages: &[Value], tools: &[Value]) -> Result<Completion> {
+        let _ = tools;
+        self.complete(messages).await
+    }
+
+    /// The answer to `messages` as JSON matching `schema`, a JSON schema named `name`
+    ///
+    /// Providers that cannot hold the model to a schema answer as usual, so
+    /// the messages should ask for the JSON as well.
+    async fn complete_json(&self, messages: &[Value], name: &str, schema: &Value) -> Result<Completion> {
+        let _ = (name, schema);
+        self.complete(messages).await
+    }
+
+    /// The answer to `messages`, piece by piece as it is written
+    async fn stream(&self, messages: &[Value]) -> Result<TokenStream>;
+}
+
+/// A completion and the tokens it took
+#[derive(Debug, Clone)]
+pub struct Completion {
+    /// The answer
+    pub content: String,
+    /// Tokens the request and the answer took
+    pub usage: TokenUsage,
+    /// Why the model stopped, e.g. `stop` or `length`
+    pub finish_reason: Option<String>,
+    /// Provider that wrote the answer
+    pub provider: String,
+    /// Functions the model wants called before it answers
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// A function the model asked to have called
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    /// Identifies the call, for the message with its result
+    pub id: String,
+    /// Name of the function
+    pub name: String,
+    /// Arguments, as the JSON text the model wrote
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /// The call as it appears in an assistant message
+    pub fn to_message(&self) -> Value {
+        json!({
+            "id": self.id,
+            "type": "function",
+            "function": {"name": self.name, "arguments": self.arguments},
+        })
+    }
+}
+
+/// The chat completions API of OpenAI, Ollama or Azure OpenAI, as `config` describes
+pub struct ChatCompletions {
+    config: AgentConfig,
+    client: Client,
+    credentials: azure::Credentials,
+    /// Set once the model refused a response schema, so later requests go without one
+    schemas_refused: AtomicBool,
+}
+
+impl ChatCompletions {
+    /// A client for the provider `config` names
+    pub fn new(config: AgentConfig) -> Result<Self> {
+        if config.provider == Provider::Azure && (config.base_url.is_empty() || config.azure.deployment.is_empty()) {
+            return Err(ProcessorError::Config("Azure OpenAI needs the resource endpoint and a deployment name".into()));
+        }
+        let client = http::client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(concat!("llamapackageservice/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self { config, client, credentials: azure::Credentials::default(), schemas_refused: AtomicBool::new(false) })
+    }
+
+    fn chat_body(&self, messages: &[Value], tools: &[Value], format: Option<&Value>, stream: bool) -> Value {
+        let mut body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "max_tokens": self.config.max_tokens,
+            "temperature": self.config.temperature,
+            "stream": stream,
+        });
+        if !tools.is_empty() {
+            body["tools"] = json!(tools);
+        }
+        if let Some(format) = format {
+            body["response_format"] = format.clone();
+        }
+        body
+    }
+
+    /// Sends `messages` to the chat completions endpoint and reads the answer
+    async fn chat(&self, messages: &[Value], tools: &[Value], format: Option<&Value>) -> Result<Completion> {
+        let reply: Value = self.post("chat/completions", &self.chat_body(messages, tools, format, false)).await?.json().await?;
+        read_completion(self.config.provider, &self.config.model, &reply, messages)
+    }
+
+    /// Posts `body` to `operation`, e.g. `chat/completions`, retrying transient failures
+    async fn post(&self, operation: &str, body: &Value) -> Result<Response> {
+        let url = match self.config.provider {
+            Provider::Azure => self.config.azure.url(&self.config.base_url, operation),
+            _ => format!("{}/{}", self.config.base_url.trim_end_matches('/'), operation),
+        };
+        let mut attempt = 0;
+        loop {
+            let mut request = self.client.post(&url).json(body);
+            if self.config.provider == Provider::Azure {
+                request = self.credentials.sign(&self.client, &self.config.azure.auth, &self.config.api_key, request).await?;
+            } else if !self.config.api_key.is_empty() {
+                request = request.bearer_auth(&self.config.api_key);
+            }
+            let (retry_after, error) = match http::send(request).await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = response.headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok())
+                        .map(Duration::from_secs);
+                    let reply: Value = response.json().await.unwrap_or_default();
+                    let error = api_error(self.config.provider, status, reply["error"]["message"].as_str().unwrap_or("no details"));
+                    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        return Err(error);
+                    }
+                    (retry_after, error)
+                }
+                Err(ProcessorError::Http(e)) if e.is_timeout() => (None, ProcessorError::Network(format!(
+                    "{} did not answer within {}s", self.config.provider.name(), self.config.timeout_secs
+                ))),
+                Err(e @ (ProcessorError::Http(_) | ProcessorError::Network(_))) => (None, e),
+                Err(e) => return Err(e),
+            };
+            if attempt >= self.config.max_retries {
+                return Err(error);
+            }
+            let delay = retry_after.unwrap_or(Duration::from_secs(1 << attempt)).min(MAX_RETRY_DELAY);
+            warn!("{}; retrying in {}s", error, delay.as_secs());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for ChatCompletions {
+    fn name(&self) -> &str {
+        self.config.provider.name()
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn complete(&self, messages: &[Value]) -> Result<Completion> {
+        self.chat(messages, &[], None).await
+    }
+
+    async fn complete_with_tools(&self, messages: &[Value], tools: &[Value]) -> Result<Completion> {
+        self.chat(messages, tools, None).await
+    }
+
+    async fn complete_json(&self, messages: &[Value], name: &str, schema: &Value) -> Result<Completion> {
+        if self.schemas_refused.load(Ordering::Relaxed) {
+            return self.chat(messages, &[], None).await;
+        }
+        let format = json!({"type": "json_schema", "json_schema": {"name": name, "schema": schema, "strict": true}});
+        match self.chat(messages, &[], Some(&format)).await {
+            // Older models refuse schemas; the messages ask for the JSON anyway
+            Err(ProcessorError::LLM(message)) if message.contains("response_format") => {
+                warn!("{} does not take a response schema; asking without one", self.config.model);
+                self.schemas_refused.store(true, Ordering::Relaxed);
+                self.chat(messages, &[], None).await
+            }
+            answer => answer,
+        }
+    }
+
+    async fn stream(&self, messages: &[Value]) -> Result<TokenStream> {
+        let response = self.post("chat/completions", &self.chat_body(messages, &[], None, true)).await?;
+        Ok(completion_tokens(self.config.provider, response))
+    }
+}
+
+#[async_trait]
+impl Embedder for ChatCompletions {
+    fn embedding_model(&self) -> &str {
+        &self.config.embedding_model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if self.config.embedding_model.is_empty() {
+            return Err(ProcessorError::Config(format!("{} has no embedding model configured", self.name())));
+        }
+        let body = json!({"model": self.config.embedding_model, "input": texts});
+        let reply: Value = self.post("embeddings", &body).await?.json().await?;
+        let mut data: Vec<&Value> = reply["data"].as_array()
+            .ok_or_else(|| ProcessorError::LLM(format!("{} returned no embeddings", self.name())))?
+            .iter()
+            .collect();
+        // Each embedding says which input it belongs to
+        data.sort_by_key(|item| item["index"].as_u64().unwrap_or(0));
+        data.into_iter()
+            .map(|item| {
+                item["embedding"].as_array()
+                    .map(|values| values.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect())
+                    .ok_or_else(|| ProcessorError::LLM(format!("{} returned an embedding without a vector", self.name())))
+            })
+            .collect()
+    }
+}
+
+/// What a provider in a [`FallbackChain`] is allowed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderLimits {
+    /// Requests a minute, 0 for no limit
+    pub requests_per_minute: u32,
+    /// Estimated spending allowed per UTC day in US dollars, unlimited if unset
+    pub daily_budget_usd: Option<f64>,
+}
+
+impl ProviderLimits {
+    /// Reads `<PREFIX>_REQUESTS_PER_MINUTE` and `<PREFIX>_DAILY_BUDGET_USD`, e.g. `OLLAMA_REQUESTS_PER_MINUTE`
+    pub fn from_env(provider: Provider) -> Self {
+        let var = |name: &str| std::env::var(format!("{}_{}", provider.env_prefix(), name)).ok();
+        Self {
+            requests_per_minute: var("REQUESTS_PER_MINUTE").and_then(|rpm| rpm.parse().ok()).unwrap_or(0),
+            daily_budget_usd: var("DAILY_BUDGET_USD").and_then(|usd| usd.parse().ok()),
+        }
+    }
+}
+
+/// Why a provider is passed over for now
+enum Skip {
+    /// Out of requests for the minute; room again after this long
+    Busy(Duration),
+    /// The day's budget is spent
+    Spent(ProcessorError),
+}
+
+/// Requests and spending of one provider in a chain
+#[derive(Debug)]
+struct Spending {
+    recent: VecDeque<Instant>,
+    day: NaiveDate,
+    spent_usd: f64,
+}
+
+/// A provider in a chain along with what it may and did use
+struct Link {
+    provider: Arc<dyn CompletionProvider>,
+    limits: ProviderLimits,
+    spending: Arc<Mutex<Spending>>,
+}
+
+impl Link {
+    /// Counts a request against the limits, or says why there is none to make
+    fn admit(&self, now: Instant, today: NaiveDate) -> std::result::Result<(), Skip> {
+        let mut spending = self.spending.lock().unwrap();
+        if spending.day != today {
+            spending.day = today;
+            spending.spent_usd = 0.0;
+        }
+        if let Some(budget) = self.limits.daily_budget_usd {
+            if spending.spent_usd >= budget {
+                return Err(Skip::Spent(ProcessorError::RateLimitExceeded(format!(
+                    "{} has spent its daily budget of ${:.2}", self.provider.name(), budget
+                ))));
+            }
+        }
+        if self.limits.requests_per_minute > 0 {
+            while spending.recent.front().is_some_and(|&sent| now.duration_since(sent) >= Duration::from_secs(60)) {
+                spending.recent.pop_front();
+            }
+            if spending.recent.len() >= self.limits.requests_per_minute as usize {
+                let oldest = spending.recent[0];
+                return Err(Skip::Busy(Duration::from_secs(60).saturating_sub(now.duration_since(oldest))));
+            }
+            spending.recent.push_back(now);
+        }
+        Ok(())
+    }
+}
+
+/// Adds the estimated cost of `usage` to what a provider spent today
+fn charge(spending: &Mutex<Spending>, usage: &TokenUsage) {
+    spending.lock().unwrap().spent_usd += usage.estimated_cost_usd.unwrap_or(0.0);
+}
+
+/// Charges a streamed answer once the stream is dropped, however far it was read
+struct StreamCharge {
+    spending: Arc<Mutex<Spending>>,
+    model: String,
+    prompt_tokens: u64,
+    written: String,
+}
+
+impl Drop for StreamCharge {
+    fn drop(&mut self) {
+        let usage = TokenUsage::new(&self.model, self.prompt_tokens, count_tokens(&self.written) as u64);
+        charge(&self.spending, &usage);
+    }
+}
+
+/// Providers tried in order until one answers
+#[derive(Default)]
+pub struct FallbackChain {
+    links: Vec<Link>,
+}
+
+impl FallbackChain {
+    /// A chain without providers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `provider`, tried after those added before it, held to `limits`
+    pub fn with_provider(mut self, provider: Arc<dyn CompletionProvider>, limits: ProviderLimits) -> Self {
+        self.links.push(Link {
+            provider,
+            limits,
+            spending: Arc::new(Mutex::new(Spending { recent: VecDeque::new(), day: Utc::now().date_naive(), spent_usd: 0.0 })),
+        });
+        self
+    }
+
+    /// Runs `call` with each provider in turn until one succeeds
+    async fn first<'a, T, F, Fut>(&'a self, mut call: F) -> Result<T>
+    where
+        F: FnMut(&'a Link) -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut settled = vec![false; self.links.len()];
+        let mut last_error = None;
+        loop {
+            let mut soonest: Option<Duration> = None;
+            for (link, settled) in self.links.iter().zip(settled.iter_mut()) {
+                if *settled {
+                    continue;
+                }
+                match link.admit(Instant::now(), Utc::now().date_naive()) {
+                    Ok(()) => {}
+                    Err(Skip::Busy(wait)) => {
+                        soonest = Some(soonest.map_or(wait, |soonest| soonest.min(wait)));
+                        continue;
+                    }
+                    Err(Skip::Spent(error)) => {
+                        *settled = true;
+                        last_error = Some(error);
+                        continue;
+                    }
+                }
+                *settled = true;
+                match call(link).await {
+                    Ok(value) => return Ok(value),
+                    Err(e) => {
+                        warn!("{} failed: {}; trying the next provider", link.provider.name(), e);
+                        last_error = Some(e);
+                    }
+                }
+            }
+            match soonest {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => {
+                    return Err(last_error.unwrap_or_else(|| ProcessorError::Config("no AI provider is configured".into())));
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for FallbackChain {
+    fn name(&self) -> &str {
+        self.links.first().map_or("none", |link| link.provider.name())
+    }
+
+    fn model(&self) -> &str {
+        self.links.first().map_or("none", |link| link.provider.model())
+    }
+
+    async fn complete(&self, messages: &[Value]) -> Result<Completion> {
+        self.first(|link| async move {
+            let completion = link.provider.complete(messages).await?;
+            charge(&link.spending, &completion.usage);
+            Ok(completion)
+        }).await
+    }
+
+    async fn complete_with_tools(&self, messages: &[Value], tools: &[Value]) -> Result<Completion> {
+        self.first(|link| async move {
+            let completion = link.provider.complete_with_tools(messages, tools).await?;
+            charge(&link.spending, &completion.usage);
+            Ok(completion)
+        }).await
+    }
+
+    async fn complete_json(&self, messages: &[Value], name: &str, schema: &Value) -> Result<Completion> {
+        self.first(|link| async move {
+            let completion = link.provider.complete_json(messages, name, schema).await?;
+            charge(&link.spending, &completion.usage);
+            Ok(completion)
+        }).await
+    }
+
+    async fn stream(&self, messages: &[Value]) -> Result<TokenStream> {
+        let prompt_tokens: u64 = messages.iter().map(|m| count_tokens(m["content"].as_str().unwrap_or_default()) as u64).sum();
+        self.first(|link| async move {
+            let tokens = link.provider.stream(messages).await?;
+            let mut meter = StreamCharge {
+                spending: link.spending.clone(),
+                model: link.provider.model().to_string(),
+                prompt_tokens,
+                written: String::new(),
+            };
+            Ok(tokens
+                .map(move |token| {
+                    if let Ok(token) = &token {
+                        meter.written.push_str(token);
+                    }
+                    token
+                })
+                .boxed())
+        }).await
+    }
+}
+
+/// Something one line of a chat completions event stream says
+#[derive(Debug, PartialEq)]
+pub(super) enum StreamEvent {
+    /// The next piece of the answer
+    Token(String),
+    /// The answer is complete
+    Done,
+    /// Nothing to pass on: a comment, a blank line or a piece without text
+    Nothing,
+}
+
+/// Yields the pieces of the answer in a streamed chat completions response
+fn completion_tokens(provider: Provider, response: Response) -> TokenStream {
+    let state = (response.bytes_stream(), Vec::new());
+    stream::unfold(Some(state), move |state| async move {
+        let (mut events, mut buffer) = state?;
+        loop {
+            // Lines are split on bytes, so characters cut between chunks survive
+            if let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                match stream_event(provider, String::from_utf8_lossy(&line).trim_end()) {
+                    Ok(StreamEvent::Token(token)) => return Some((Ok(token), Some((events, buffer)))),
+                    Ok(StreamEvent::Done) => return None,
+                    Ok(StreamEvent::Nothing) => continue,
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+            match events.next().await {
+                Some(Ok(bytes)) => buffer.extend_from_slice(&bytes),
+                Some(Err(e)) => return Some((Err(e.into()), None)),
+                None => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Reads one line of a chat completions event stream
+pub(super) fn stream_event(provider: Provider, line: &str) -> Result<StreamEvent> {
+    let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+        return Ok(StreamEvent::Nothing);
+    };
+    if data == "[DONE]" {
+        return Ok(StreamEvent::Done);
+    }
+    let event: Value = serde_json::from_str(data)
+        .map_err(|e| ProcessorError::LLM(format!("unreadable event from {}: {}", provider.name(), e)))?;
+    if let Some(message) = event["error"]["message"].as_str() {
+        return Err(ProcessorError::LLM(format!("{} stopped the answer: {}", provider.name(), message)));
+    }
+    Ok(match event["choices"][0]["delta"]["content"].as_str() {
+        Some(token) if !token.is_empty() => StreamEvent::Token(token.to_string()),
+        _ => StreamEvent::Nothing,
+    })
+}
+
+/// Reads the answer and token usage out of a reply of `provider`'s chat completions API to `messages`
+pub(super) fn read_completion(provider: Provider, model: &str, reply: &Value, messages: &[Value]) -> Result<Completion> {
+    let choice = &reply["choices"][0];
+    let tool_calls: Vec<ToolCall> = choice["message"]["tool_calls"].as_array()
+        .map(|calls| calls.iter()
+            .map(|call| ToolCall {
+                id: call["id"].as_str().unwrap_or_default().to_string(),
+                name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                arguments: call["function"]["arguments"].as_str().unwrap_or_default().to_string(),
+            })
+            .collect())
+        .unwrap_or_default();
+    // A reply calling functions may come without text
+    let content = match choice["message"]["content"].as_str() {
+        Some(content) => content.to_string(),
+        None if !tool_calls.is_empty() => String::new(),
+        None => return Err(ProcessorError::LLM(format!("{} returned no answer", provider.name()))),
+    };
+    // Compatible servers do not all report usage; count the tokens ourselves then
+    let prompt_tokens = reply["usage"]["prompt_tokens"].as_u64().unwrap_or_else(|| {
+        messages.iter().map(|m| count_tokens(m["content"].as_str().unwrap_or_default()) as u64).sum()
+    });
+    let completion_tokens = reply["usage"]["completion_tokens"].as_u64()
+        .unwrap_or_else(|| count_tokens(&content) as u64);
+    Ok(Completion {
+        provider: provider.name().to_string(),
+        usage: TokenUsage::new(model, prompt_tokens, completion_tokens),
+        finish_reason: choice["finish_reason"].as_str().map(str::to_string),
+        content,
+        tool_calls,
+    })
+}
+
+/// Maps an error response of the API to the error it stands for
+fn api_error(provider: Provider, status: StatusCode, message: &str) -> ProcessorError {
+    let name = provider.name();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            ProcessorError::Config(format!("{} rejected the API key: {}", name, message))
+        }
+        StatusCode::TOO_MANY_REQUESTS => ProcessorError::RateLimitExceeded(format!("{}: {}", name, message)),
+        // Ollama answers a model that was never pulled with a 404
+        StatusCode::NOT_FOUND if provider == Provider::Ollama => ProcessorError::Config(format!(
+            "Ollama does not have the model: {} (run `ollama pull <model>` first)", message
+        )),
+        StatusCode::NOT_FOUND if provider == Provider::Azure => ProcessorError::Config(format!(
+            "Azure OpenAI has no such deployment: {}", message
+        )),
+        _ => ProcessorError::LLM(format!("{} returned {}: {}", name, status, message)),
+    }
+}
+
+/// The API base URL for an `OLLAMA_HOST` value such as `127.0.0.1:11434`
+pub(super) fn ollama_base_url(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/');
+    let host = if host.contains("://") { host.to_string() } else { format!("http://{}", host) };
+    if host.ends_with("/v1") { host } else { format!("{}/v1", host) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers with its own name, or fails, counting the requests it gets
+    struct Scripted {
+        name: &'static str,
+        fails: bool,
+        requests: AtomicUsize,
+    }
+
+    impl Scripted {
+        fn new(name: &'static str, fails: bool) -> Arc<Self> {
+            Arc::new(Self { name, fails, requests: AtomicUsize::new(0) })
+        }
+
+        fn request(&self) -> Result<()> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if self.fails {
+                return Err(ProcessorError::Network(format!("{} is down", self.name)));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl CompletionProvider for Scripted {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn model(&self) -> &str {
+            "gpt-4"
+        }
+
+        async fn complete(&self, _messages: &[Value]) -> Result<Completion> {
+            self.request()?;
+            Ok(Completion {
+                content: self.name.to_string(),
+                // $0.03 at gpt-4 prices
+                usage: TokenUsage::new("gpt-4", 1000, 0),
+                finish_reason: None,
+                provider: self.name.to_string(),
+                tool_calls: Vec::new(),
+            })
+        }
+
+        async fn stream(&self, _messages: &[Value]) -> Result<TokenStream> {
+            self.request()?;
+            Ok(stream::iter([Ok(self.name.to_string())]).boxed())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_falls_back_past_failing_and_limited_providers() {
+        // About a thousand tokens, which is $0.03 more at gpt-4 prices when streamed
+        let messages = [json!({"role": "user", "content": "word ".repeat(1000)})];
+        let (down, busy, cheap) = (Scripted::new("down", true), Scripted::new("busy", false), Scripted::new("cheap", false));
+        let chain = FallbackChain::new()
+            .with_provider(down.clone(), ProviderLimits::default())
+            .with_provider(busy.clone(), ProviderLimits { requests_per_minute: 1, ..Default::default() })
+            .with_provider(cheap.clone(), ProviderLimits { daily_budget_usd: Some(0.05), ..Default::default() });
+        assert_eq!(chain.model(), "gpt-4");
+
+        // A failure moves on to the next provider
+        assert_eq!(chain.complete(&messages).await.unwrap().provider, "busy");
+        // The rate limit passes the second request on
+        assert_eq!(chain.complete(&messages).await.unwrap().provider, "cheap");
+        assert_eq!(busy.requests.load(Ordering::SeqCst), 1);
+
+        // A streamed answer is charged too, once it is read
+        let tokens: Vec<String> = chain.stream(&messages).await.unwrap().map(|t| t.unwrap()).collect().await;
+        assert_eq!(tokens, ["cheap"]);
+        let spent = chain.links[2].spending.lock().unwrap().spent_usd;
+        assert!(spent > 0.05, "{}", spent);
+
+        assert_eq!(cheap.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(down.requests.load(Ordering::SeqCst), 3);
+
+        // Past its budget, a provider is not asked at all
+        let spent = Scripted::new("spent", false);
+        let chain = FallbackChain::new()
+            .with_provider(spent.clone(), ProviderLimits { daily_budget_usd: Some(0.0), ..Default::default() });
+        let error = chain.complete(&messages).await.unwrap_err();
+        assert!(matches!(&error, ProcessorError::RateLimitExceeded(m) if m.contains("daily budget")), "{}", error);
+        assert_eq!(spent.requests.load(Ordering::SeqCst), 0);
+    }
+}
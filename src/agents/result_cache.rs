@@ -0,0 +1,162 @@
+//! Cached analysis results
+//!
+//! An analysis is keyed by a hash of everything that shapes the model's
+//! answer: the messages, which hold the rendered prompt template and the
+//! processed repository content, and the model's settings. When a repository
+//! has not changed since it was last analyzed the same way, the earlier
+//! result is returned without a request. Results are kept in the response
+//! cache under the `analysis` namespace; only complete answers are stored.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use futures_util::stream::{self, StreamExt};
+use serde_json::Value;
+use tracing::warn;
+use crate::cache::backend::{namespaced_key, CacheBackend};
+use crate::perf;
+use super::{AnalysisResult, AnalysisType, OpenAIAgent, TokenStream, TokenUsage};
+
+/// Cache namespace of analysis results
+pub const ANALYSIS_NAMESPACE: &str = "analysis";
+
+/// Changed whenever what is stored changes, so older results are not reused
+const CACHE_VERSION: &str = "1";
+
+impl OpenAIAgent {
+    /// Key of the analysis `messages` ask for
+    pub(crate) fn analysis_key(&self, messages: &[Value]) -> String {
+        let inputs = format!(
+            "{}\n{}\n{}\n{}",
+            CACHE_VERSION, self.config.temperature, self.config.max_tokens, Value::from(messages.to_vec())
+        );
+        namespaced_key(ANALYSIS_NAMESPACE, self.model(), &perf::hash_bytes(inputs.as_bytes()))
+    }
+
+    /// The result stored under `key`, marked as cached and costing nothing
+    pub(crate) async fn cached_analysis(&self, key: &str) -> Option<AnalysisResult> {
+        let cache = self.analysis_cache()?;
+        let bytes = match cache.get(key).await {
+            Ok(bytes) => bytes?,
+            Err(e) => {
+                warn!("Could not read the analysis cache: {}", e);
+                return None;
+            }
+        };
+        let mut result: AnalysisResult = serde_json::from_slice(&bytes).ok()?;
+        result.metadata.insert("cached".to_string(), "true".to_string());
+        result.usage = TokenUsage::new(&result.usage.model, 0, 0);
+        Some(result)
+    }
+
+    /// Stores `result` under `key`, unless the answer was cut off
+    pub(crate) async fn cache_analysis(&self, key: &str, result: &AnalysisResult) {
+        let Some(cache) = self.analysis_cache() else {
+            return;
+        };
+        store(cache.as_ref(), key, result).await;
+    }
+
+    /// Passes `tokens` on, storing the analysis once the whole answer has been read
+    pub(crate) fn caching(&self, key: String, analysis_type: AnalysisType, tokens: TokenStream) -> TokenStream {
+        let Some(cache) = self.analysis_cache() else {
+            return tokens;
+        };
+        let model = self.model().to_string();
+        // `None` once a token failed, so a broken answer is not stored
+        let written = Arc::new(Mutex::new(Some(String::new())));
+        let recorder = Arc::clone(&written);
+        let finish = stream::once(async move {
+            let content = written.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(content) = content {
+                let result = AnalysisResult {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    analysis_type,
+                    content,
+                    confidence: 0.8,
+                    metadata: HashMap::from([("model".to_string(), model.clone())]),
+                    timestamp: chrono::Utc::now(),
+                    usage: TokenUsage::new(&model, 0, 0),
+                    structured: None,
+                };
+                store(cache.as_ref(), &key, &result).await;
+            }
+        });
+        tokens
+            .inspect(move |token| {
+                let mut written = recorder.lock().unwrap_or_else(|e| e.into_inner());
+                match (token, written.as_mut()) {
+                    (Ok(token), Some(content)) => content.push_str(token),
+                    (Err(_), _) => *written = None,
+                    _ => {}
+                }
+            })
+            .chain(finish.filter_map(|()| async { None }))
+            .boxed()
+    }
+
+    /// The cache analyses are kept in, unless caching them is off
+    fn analysis_cache(&self) -> Option<Arc<dyn CacheBackend>> {
+        self.config.cache_analyses.then(|| self.response_cache()).flatten()
+    }
+}
+
+async fn store(cache: &dyn CacheBackend, key: &str, result: &AnalysisResult) {
+    if result.metadata.get("finish_reason").is_some_and(|reason| reason == "length") {
+        return;
+    }
+    let stored = match serde_json::to_vec(result) {
+        Ok(bytes) => cache.set(key, bytes).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = stored {
+        warn!("Could not cache the analysis: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{AgentConfig, AnalysisRequest};
+    use crate::cache::backend::{CacheConfig, MemoryBackend};
+    use serde_json::json;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unchanged_repositories_are_not_analyzed_again() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join("README.md"), "# Widgets\n\nFrobnicates the quux.\n").unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let completions = server.mock("POST", "/v1/chat/completions")
+            .with_body(json!({
+                "choices": [{"message": {"content": "Widgets frobnicate."}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1200, "completion_tokens": 30},
+            }).to_string())
+            .expect(2)
+            .create_async().await;
+        let agent = OpenAIAgent::new(AgentConfig {
+            model: "gpt-4o-mini".to_string(),
+            base_url: format!("{}/v1", server.url()),
+            ..AgentConfig::default()
+        }).unwrap().with_cache(Arc::new(MemoryBackend::new(&CacheConfig::default())));
+        let request = || AnalysisRequest {
+            repository: repo.path().display().to_string(),
+            analysis_type: AnalysisType::Documentation,
+            context: None,
+            parameters: HashMap::new(),
+        };
+
+        let first = agent.analyze_repository(request()).await.unwrap();
+        assert_eq!(first.usage.prompt_tokens, 1200);
+        let again = agent.analyze_repository(request()).await.unwrap();
+        assert_eq!(again.content, "Widgets frobnicate.");
+        assert_eq!(again.metadata["cached"], "true");
+        assert_eq!(again.usage.total_tokens(), 0);
+        let streamed: Vec<String> = agent.analyze_repository_stream(request()).await.unwrap()
+            .map(|token| token.unwrap()).collect().await;
+        assert_eq!(streamed.concat(), "Widgets frobnicate.");
+
+        // A changed repository is analyzed afresh
+        std::fs::write(repo.path().join("README.md"), "# Widgets\n\nFrobnicates the quux twice.\n").unwrap();
+        assert!(!agent.analyze_repository(request()).await.unwrap().metadata.contains_key("cached"));
+        completions.assert_async().await;
+    }
+}
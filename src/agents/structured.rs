@@ -0,0 +1,380 @@
+//! Structured results of analyses
+//!
+//! Security audits, code reviews and examples ask the model for one JSON
+//! object matching a schema, through the `response_format` of the chat
+//! completions API where the model supports it and through the prompt
+//! everywhere. The answer is parsed into a typed report, kept as JSON in
+//! [`AnalysisResult::structured`](super::AnalysisResult::structured), and
+//! rendered as Markdown for the analysis content.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::error::{ProcessorError, Result};
+use super::AnalysisType;
+
+/// A report the model writes as JSON
+pub trait StructuredOutput: Serialize + DeserializeOwned {
+    /// Name of the schema, as the API asks for it
+    const NAME: &'static str;
+
+    /// JSON schema of the report, in the strict subset the API accepts
+    fn schema() -> Value;
+
+    /// The report as Markdown
+    fn to_markdown(&self) -> String;
+}
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Exploitable now, or breaks the build or data
+    Critical,
+    /// Needs fixing before the next release
+    High,
+    /// Worth fixing
+    Medium,
+    /// Minor
+    Low,
+    /// Noted for information only
+    Info,
+}
+
+impl Severity {
+    /// What a finding of this severity adds to a risk score
+    fn weight(self) -> u32 {
+        match self {
+            Severity::Critical => 40,
+            Severity::High => 20,
+            Severity::Medium => 10,
+            Severity::Low => 3,
+            Severity::Info => 0,
+        }
+    }
+
+    /// Name of the severity, as it is serialized
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::High => "high",
+            Severity::Medium => "medium",
+            Severity::Low => "low",
+            Severity::Info => "info",
+        }
+    }
+}
+
+/// A security weakness found in the repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityFinding {
+    /// Short name of the weakness
+    pub title: String,
+    /// How serious it is
+    pub severity: Severity,
+    /// File, and line where known, it was found in
+    pub location: Option<String>,
+    /// What is wrong and how it could be exploited
+    pub description: String,
+    /// How to fix it
+    pub recommendation: String,
+}
+
+/// Result of a security audit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityReport {
+    /// Overall assessment
+    pub summary: String,
+    /// Weaknesses found, most serious first
+    pub findings: Vec<SecurityFinding>,
+    /// Practices to adopt beyond fixing the findings
+    pub recommendations: Vec<String>,
+}
+
+impl SecurityReport {
+    /// Risk from 0 to 100, from the number and severity of the findings
+    pub fn risk_score(&self) -> u8 {
+        self.findings.iter().map(|finding| finding.severity.weight()).sum::<u32>().min(100) as u8
+    }
+}
+
+impl StructuredOutput for SecurityReport {
+    const NAME: &'static str = "security_report";
+
+    fn schema() -> Value {
+        object(&[
+            ("summary", json!({"type": "string"})),
+            ("findings", json!({"type": "array", "items": object(&[
+                ("title", json!({"type": "string"})),
+                ("severity", severity_schema()),
+                ("location", json!({"type": ["string", "null"]})),
+                ("description", json!({"type": "string"})),
+                ("recommendation", json!({"type": "string"})),
+            ])})),
+            ("recommendations", json!({"type": "array", "items": {"type": "string"}})),
+        ])
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("# Security Audit\n\n{}\n\nRisk score: {}/100\n", self.summary, self.risk_score());
+        if !self.findings.is_empty() {
+            out.push_str("\n## Findings\n");
+            for finding in &self.findings {
+                out.push_str(&format!("\n### {} ({})\n\n", finding.title, finding.severity.name()));
+                if let Some(location) = &finding.location {
+                    out.push_str(&format!("Location: `{}`\n\n", location));
+                }
+                out.push_str(&format!("{}\n\n**Fix:** {}\n", finding.description, finding.recommendation));
+            }
+        }
+        push_list(&mut out, "Recommendations", &self.recommendations);
+        out
+    }
+}
+
+/// A problem found in review
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewIssue {
+    /// Short name of the problem
+    pub title: String,
+    /// How serious it is
+    pub severity: Severity,
+    /// Kind of problem, such as `correctness`, `performance` or `maintainability`
+    pub category: String,
+    /// File, and line where known, it was found in
+    pub location: Option<String>,
+    /// What is wrong
+    pub description: String,
+    /// What to change
+    pub suggestion: String,
+}
+
+/// Result of a code review
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeReviewReport {
+    /// Overall assessment
+    pub summary: String,
+    /// What the code does well
+    pub strengths: Vec<String>,
+    /// Problems found, most serious first
+    pub issues: Vec<ReviewIssue>,
+}
+
+impl StructuredOutput for CodeReviewReport {
+    const NAME: &'static str = "code_review_report";
+
+    fn schema() -> Value {
+        object(&[
+            ("summary", json!({"type": "string"})),
+            ("strengths", json!({"type": "array", "items": {"type": "string"}})),
+            ("issues", json!({"type": "array", "items": object(&[
+                ("title", json!({"type": "string"})),
+                ("severity", severity_schema()),
+                ("category", json!({"type": "string"})),
+                ("location", json!({"type": ["string", "null"]})),
+                ("description", json!({"type": "string"})),
+                ("suggestion", json!({"type": "string"})),
+            ])})),
+        ])
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("# Code Review\n\n{}\n", self.summary);
+        push_list(&mut out, "Strengths", &self.strengths);
+        if !self.issues.is_empty() {
+            out.push_str("\n## Issues\n");
+            for issue in &self.issues {
+                out.push_str(&format!("\n### {} ({}, {})\n\n", issue.title, issue.severity.name(), issue.category));
+                if let Some(location) = &issue.location {
+                    out.push_str(&format!("Location: `{}`\n\n", location));
+                }
+                out.push_str(&format!("{}\n\n**Suggestion:** {}\n", issue.description, issue.suggestion));
+            }
+        }
+        out
+    }
+}
+
+/// A usage example
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeExample {
+    /// What the example shows
+    pub title: String,
+    /// When to use it
+    pub description: String,
+    /// Language of the code, as a Markdown fence names it
+    pub language: String,
+    /// The code
+    pub code: String,
+}
+
+/// Usage examples of a repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExamplesReport {
+    /// The examples, simplest first
+    pub examples: Vec<CodeExample>,
+}
+
+impl StructuredOutput for ExamplesReport {
+    const NAME: &'static str = "examples_report";
+
+    fn schema() -> Value {
+        object(&[
+            ("examples", json!({"type": "array", "items": object(&[
+                ("title", json!({"type": "string"})),
+                ("description", json!({"type": "string"})),
+                ("language", json!({"type": "string"})),
+                ("code", json!({"type": "string"})),
+            ])})),
+        ])
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = String::from("# Examples\n");
+        for example in &self.examples {
+            out.push_str(&format!(
+                "\n## {}\n\n{}\n\n```{}\n{}\n```\n",
+                example.title, example.description, example.language, example.code.trim_end()
+            ));
+        }
+        out
+    }
+}
+
+/// Name and schema of the report `analysis_type` asks for, if it asks for one
+pub fn schema_for(analysis_type: &AnalysisType) -> Option<(&'static str, Value)> {
+    match analysis_type {
+        AnalysisType::SecurityAudit => Some((SecurityReport::NAME, SecurityReport::schema())),
+        AnalysisType::CodeReview => Some((CodeReviewReport::NAME, CodeReviewReport::schema())),
+        AnalysisType::Examples => Some((ExamplesReport::NAME, ExamplesReport::schema())),
+        _ => None,
+    }
+}
+
+/// Reads the report of `analysis_type` out of the model's answer, as JSON and as Markdown
+pub fn parse_answer(analysis_type: &AnalysisType, answer: &str) -> Result<(Value, String)> {
+    fn read<T: StructuredOutput>(answer: &str) -> Result<(Value, String)> {
+        let report: T = parse(answer)?;
+        Ok((serde_json::to_value(&report)?, report.to_markdown()))
+    }
+    match analysis_type {
+        AnalysisType::SecurityAudit => read::<SecurityReport>(answer),
+        AnalysisType::CodeReview => read::<CodeReviewReport>(answer),
+        AnalysisType::Examples => read::<ExamplesReport>(answer),
+        other => Err(ProcessorError::Validation(format!("{} analyses have no structured report", other.name()))),
+    }
+}
+
+/// Parses a report out of `answer`, ignoring a Markdown fence or text around the object
+pub fn parse<T: StructuredOutput>(answer: &str) -> Result<T> {
+    let json = match (answer.find('{'), answer.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &answer[start..=end],
+        _ => answer,
+    };
+    serde_json::from_str(json)
+        .map_err(|e| ProcessorError::Parse(format!("the model's answer is not a valid {}: {}", T::NAME, e)))
+}
+
+/// Asks for the answer as JSON, for models that do not take a schema
+pub fn instructions(schema: &Value) -> String {
+    format!("Answer with one JSON object, and nothing else, matching this JSON schema:\n{}", schema)
+}
+
+/// An object schema in which every property is required and no others are allowed
+fn object(properties: &[(&str, Value)]) -> Value {
+    json!({
+        "type": "object",
+        "properties": properties.iter().cloned().map(|(name, schema)| (name.to_string(), schema)).collect::<serde_json::Map<_, _>>(),
+        "required": properties.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+        "additionalProperties": false,
+    })
+}
+
+fn severity_schema() -> Value {
+    json!({"type": "string", "enum": ["critical", "high", "medium", "low", "info"]})
+}
+
+fn push_list(out: &mut String, heading: &str, items: &[String]) {
+    if !items.is_empty() {
+        out.push_str(&format!("\n## {}\n\n", heading));
+        for item in items {
+            out.push_str(&format!("- {}\n", item));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{AgentConfig, AnalysisRequest, OpenAIAgent};
+    use mockito::Matcher;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_audits_and_reviews_are_read_from_json_answers() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join("app.py"), "import pickle\n\ndef load(data):\n    return pickle.loads(data)\n").unwrap();
+        let report = json!({
+            "summary": "Untrusted input is unpickled.",
+            "findings": [
+                {"title": "Unsafe deserialization", "severity": "critical", "location": "app.py:4",
+                 "description": "pickle.loads runs code from the data.", "recommendation": "Use json."},
+                {"title": "No input limits", "severity": "high", "location": null,
+                 "description": "Payloads are unbounded.", "recommendation": "Cap their size."},
+            ],
+            "recommendations": ["Run bandit in CI"],
+        });
+        let mut server = mockito::Server::new_async().await;
+        // The model refuses schemas, so questions are asked without one from then on
+        let refused = server.mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::PartialJson(json!({"response_format": {"type": "json_schema", "json_schema": {"name": "security_report"}}})))
+            .with_status(400)
+            .with_body(json!({"error": {"message": "Invalid parameter: 'response_format' of type 'json_schema' is not supported with this model."}}).to_string())
+            .create_async().await;
+        let review = server.mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::Regex("strengths".to_string()))
+            .with_body(json!({"choices": [{"message": {"content": "Looks fine to me."}}]}).to_string())
+            .create_async().await;
+        let answers = server.mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::Regex("matching this JSON schema".to_string()))
+            .with_body(json!({"choices": [{"message": {"content": format!("```json\n{}\n```", report)}, "finish_reason": "stop"}]}).to_string())
+            .expect(2)
+            .create_async().await;
+        let agent = OpenAIAgent::new(AgentConfig {
+            base_url: format!("{}/v1", server.url()),
+            ..AgentConfig::default()
+        }).unwrap();
+        let repository = repo.path().display().to_string();
+
+        let result = agent.analyze_repository(AnalysisRequest {
+            repository: repository.clone(),
+            analysis_type: AnalysisType::SecurityAudit,
+            context: None,
+            parameters: HashMap::new(),
+        }).await.unwrap();
+        assert!(result.content.starts_with("# Security Audit\n\nUntrusted input is unpickled.\n\nRisk score: 60/100\n"), "{}", result.content);
+        assert!(result.content.contains("### Unsafe deserialization (critical)\n\nLocation: `app.py:4`"));
+        let parsed: SecurityReport = result.report().unwrap();
+        assert_eq!(parsed.findings[1].location, None);
+
+        let audit = agent.security_audit(repository.clone()).await.unwrap();
+        assert_eq!(audit.risk_score, 60);
+        assert_eq!(audit.vulnerabilities[0].severity, Severity::Critical);
+        assert_eq!(audit.recommendations, ["Run bandit in CI"]);
+
+        // An answer that is not the report is an error, not a guess
+        let error = agent.code_review(repository).await.unwrap_err();
+        assert!(matches!(&error, ProcessorError::Parse(message) if message.contains("code_review_report")), "{}", error);
+        refused.assert_async().await;
+        review.assert_async().await;
+        answers.assert_async().await;
+    }
+
+    #[test]
+    fn test_schemas_require_every_property() {
+        let schema = CodeReviewReport::schema();
+        assert_eq!(schema["required"], json!(["summary", "strengths", "issues"]));
+        assert_eq!(schema["properties"]["issues"]["items"]["additionalProperties"], json!(false));
+        assert_eq!(schema_for(&AnalysisType::Documentation), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! Map-reduce summaries of repositories too large for the context
+//!
+//! Each source file quoted in the processed output is summarized on its own.
+//! The summaries of a directory's files and subdirectories are then
+//! summarized into one for the directory, and so on up to a summary of the
+//! whole repository. Summaries that do not fit one request together are
+//! reduced in groups first. Every summary is cached in the response cache
+//! under the `summary` namespace, keyed by the model and a hash of what was
+//! summarized, so a later run only summarizes files that changed and the
+//! directories above them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use tracing::warn;
+use crate::cache::backend::{namespaced_key, CacheBackend};
+use crate::chunking::{self, count_tokens, Boundary};
+use crate::error::Result;
+use crate::perf;
+use super::tools::{RepositoryFiles, PROCESSED_DIR};
+use super::OpenAIAgent;
+
+/// Cache namespace of summaries
+pub const SUMMARY_NAMESPACE: &str = "summary";
+
+/// Changed whenever the prompts change, so summaries written with older ones are not reused
+const PROMPT_VERSION: &str = "1";
+
+/// What a summary is of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    File,
+    Directory,
+    Repository,
+}
+
+impl Level {
+    fn instructions(self) -> &'static str {
+        match self {
+            Level::File => "Summarize this file in at most five sentences: what it is for, its main types and \
+                            functions, and what it depends on.",
+            Level::Directory => "Summarize this directory in at most five sentences from the summaries of its \
+                                 contents: what it is for and how its parts fit together.",
+            Level::Repository => "Summarize this repository from the summaries of its contents: what it does, \
+                                  how it is organized, its main components and how they interact.",
+        }
+    }
+}
+
+/// Summaries of a repository, its directories and its files
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RepositorySummary {
+    /// Summary of the whole repository
+    pub summary: String,
+    /// Summary of each directory, by path with a trailing `/`
+    pub directories: BTreeMap<String, String>,
+    /// Summary of each file, by path
+    pub files: BTreeMap<String, String>,
+    /// Summaries found in the cache
+    pub cached: usize,
+    /// Summaries requested from the model
+    pub requested: usize,
+}
+
+impl RepositorySummary {
+    /// The repository summary followed by the directory summaries, cut to `max_tokens`
+    pub fn to_context(&self, repository: &str, max_tokens: usize) -> String {
+        let mut context = format!("Repository: {} (summarized)\n\n{}\n", repository, self.summary);
+        if !self.directories.is_empty() {
+            context.push_str("\n## Directories\n\n");
+            for (path, summary) in &self.directories {
+                context.push_str(&format!("- `{}`: {}\n", path, summary.replace('\n', " ")));
+            }
+        }
+        chunking::truncate(&context, max_tokens, Boundary::Heading)
+    }
+}
+
+impl OpenAIAgent {
+    /// Summarizes `repository` file by file, then directory by directory
+    pub async fn summarize_repository(&self, repository: &str) -> Result<RepositorySummary> {
+        let artifacts = self.processed_artifacts(repository).await?;
+        let cache = self.response_cache();
+        self.summarize_files(repository, &RepositoryFiles::from_artifacts(&artifacts), cache.as_deref()).await
+    }
+
+    /// Summarizes the source files in `files`, caching summaries in `cache`
+    ///
+    /// When the processed output quotes no source files, its artifacts are
+    /// summarized instead.
+    pub async fn summarize_files(
+        &self,
+        repository: &str,
+        files: &RepositoryFiles,
+        cache: Option<&dyn CacheBackend>,
+    ) -> Result<RepositorySummary> {
+        let processed = format!("{}/", PROCESSED_DIR);
+        let mut paths: Vec<&str> = files.paths().filter(|path| !path.starts_with(&processed)).collect();
+        if paths.is_empty() {
+            paths = files.paths().collect();
+        }
+        let mut summary = RepositorySummary::default();
+
+        // Map: every file on its own
+        let budget = self.config.context_tokens.max(1);
+        let concurrency = self.processing.processing.max_concurrent_analyses.max(1);
+        // Built up front rather than in a `map` closure, so the stream stays `Send` for callers that spawn it
+        let summaries: Vec<_> = paths.iter()
+            .map(|path| self.summarize_file(repository, path, files.read(path).unwrap_or_default(), budget, cache))
+            .collect();
+        let mut outcomes = stream::iter(summaries).buffer_unordered(concurrency);
+        while let Some((path, outcome)) = outcomes.next().await {
+            let (text, cached) = outcome?;
+            summary.files.insert(path, text);
+            summary.tally(cached);
+        }
+        drop(outcomes);
+
+        // Reduce: directories from the deepest up, then the repository
+        let mut directories = BTreeSet::new();
+        for path in &paths {
+            let mut dir = parent(path);
+            while let Some(current) = dir {
+                directories.insert(current.to_string());
+                dir = parent(current.trim_end_matches('/'));
+            }
+        }
+        let mut directories: Vec<String> = directories.into_iter().collect();
+        directories.sort_by_key(|dir| std::cmp::Reverse(dir.matches('/').count()));
+        for dir in directories.iter().map(String::as_str).chain([""]) {
+            let parts: Vec<(String, String)> = summary.files.iter()
+                .chain(summary.directories.iter())
+                .filter(|(path, _)| parent(path.trim_end_matches('/')).unwrap_or("") == dir)
+                .map(|(path, text)| (path.clone(), text.clone()))
+                .collect();
+            let level = if dir.is_empty() { Level::Repository } else { Level::Directory };
+            let label = if dir.is_empty() { repository.to_string() } else { format!("`{}` of {}", dir, repository) };
+            let text = self.reduce(level, &label, parts, cache, &mut summary).await?;
+            if dir.is_empty() {
+                summary.summary = text;
+            } else {
+                summary.directories.insert(dir.to_string(), text);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// The summary of the file at `path`, cut to `budget` tokens first
+    async fn summarize_file(
+        &self,
+        repository: &str,
+        path: &str,
+        content: &str,
+        budget: usize,
+        cache: Option<&dyn CacheBackend>,
+    ) -> (String, Result<(String, bool)>) {
+        let content = chunking::truncate(content, budget, Boundary::Sentence);
+        let input = format!("File `{}` of {}:\n\n```\n{}\n```", path, repository, content);
+        (path.to_string(), self.summary_of(Level::File, &input, cache).await)
+    }
+
+    /// One summary of `parts`, summarizing them in groups that fit the context first when needed
+    async fn reduce(
+        &self,
+        level: Level,
+        label: &str,
+        mut parts: Vec<(String, String)>,
+        cache: Option<&dyn CacheBackend>,
+        summary: &mut RepositorySummary,
+    ) -> Result<String> {
+        let budget = self.config.context_tokens.max(1);
+        loop {
+            let mut groups: Vec<String> = vec![String::new()];
+            for (path, text) in &parts {
+                let entry = format!("\n### `{}`\n{}\n", path, text);
+                let current = groups.last_mut().expect("there is always a group");
+                if !current.is_empty() && count_tokens(current) + count_tokens(&entry) > budget {
+                    groups.push(entry);
+                } else {
+                    current.push_str(&entry);
+                }
+            }
+            if groups.len() == 1 {
+                let input = format!("Summaries of the contents of {}:\n{}", label, groups[0]);
+                let (text, cached) = self.summary_of(level, &input, cache).await?;
+                summary.tally(cached);
+                return Ok(text);
+            }
+            let mut reduced = Vec::new();
+            for (i, group) in groups.iter().enumerate() {
+                let input = format!("Summaries of part {} of the contents of {}:\n{}", i + 1, label, group);
+                let (text, cached) = self.summary_of(Level::Directory, &input, cache).await?;
+                summary.tally(cached);
+                reduced.push((format!("part {}", i + 1), text));
+            }
+            parts = reduced;
+        }
+    }
+
+    /// The summary of `input` at `level`, from the cache or the model, and whether it was cached
+    async fn summary_of(&self, level: Level, input: &str, cache: Option<&dyn CacheBackend>) -> Result<(String, bool)> {
+        let instructions = level.instructions();
+        let hash = perf::hash_bytes(format!("{}\n{}\n{}", PROMPT_VERSION, instructions, input).as_bytes());
+        let key = namespaced_key(SUMMARY_NAMESPACE, self.model(), &hash);
+        if let Some(cache) = cache {
+            match cache.get(&key).await {
+                Ok(Some(cached)) => return Ok((String::from_utf8_lossy(&cached).into_owned(), true)),
+                Ok(None) => {}
+                Err(e) => warn!("Could not read the summary cache: {}", e),
+            }
+        }
+        let messages = [
+            json!({"role": "system", "content": format!("You summarize software for other engineers. {}", instructions)}),
+            json!({"role": "user", "content": input}),
+        ];
+        let text = self.complete(&messages, &[]).await?.content.trim().to_string();
+        if let Some(cache) = cache {
+            if let Err(e) = cache.set(&key, text.clone().into_bytes()).await {
+                warn!("Could not cache a summary: {}", e);
+            }
+        }
+        Ok((text, false))
+    }
+}
+
+impl RepositorySummary {
+    fn tally(&mut self, cached: bool) {
+        if cached {
+            self.cached += 1;
+        } else {
+            self.requested += 1;
+        }
+    }
+}
+
+/// Directory of `path` with a trailing `/`, or `None` at the root
+fn parent(path: &str) -> Option<&str> {
+    path.rfind('/').map(|slash| &path[..=slash])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::backend::MemoryBackend;
+    use crate::cache::backend::CacheConfig;
+    use super::super::AgentConfig;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_repositories_are_summarized_bottom_up_and_cached() {
+        let overview = "## Full Source Files\n\n### `src/lib.rs`\n\n```rust\npub mod parse;\n```\n\n\
+                        ### `src/parse/mod.rs`\n\n```rust\npub fn parse() {}\n```\n\n### `README.md`\n\n```markdown\n# Demo\n```\n";
+        let files = RepositoryFiles::from_artifacts(&[("demo.txt".to_string(), overview.to_string())]);
+
+        let mut server = mockito::Server::new_async().await;
+        // Each summary names what it summarized
+        let requests = server.mock("POST", "/v1/chat/completions")
+            .with_body_from_request(|request| {
+                let body: Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+                let input = body["messages"][1]["content"].as_str().unwrap();
+                let subject = input.split('`').nth(1).filter(|_| !input.starts_with("Summaries of the contents of demo:"))
+                    .unwrap_or("demo");
+                json!({"choices": [{"message": {"content": format!("about {}", subject)}}]}).to_string().into_bytes()
+            })
+            .expect(6)
+            .create_async().await;
+        let agent = OpenAIAgent::new(AgentConfig {
+            base_url: format!("{}/v1", server.url()),
+            ..AgentConfig::default()
+        }).unwrap();
+        let cache = MemoryBackend::new(&CacheConfig::default());
+
+        let summary = agent.summarize_files("demo", &files, Some(&cache)).await.unwrap();
+        assert_eq!(summary.files["src/parse/mod.rs"], "about src/parse/mod.rs");
+        assert_eq!(summary.directories.keys().collect::<Vec<_>>(), ["src/", "src/parse/"]);
+        assert_eq!(summary.directories["src/"], "about src/");
+        assert_eq!(summary.summary, "about demo");
+        assert_eq!((summary.requested, summary.cached), (6, 0));
+        let context = summary.to_context("demo", 1000);
+        assert!(context.starts_with("Repository: demo (summarized)\n\nabout demo\n"), "{}", context);
+        assert!(context.contains("- `src/parse/`: about src/parse/\n"));
+
+        // Nothing changed, so nothing is summarized again
+        let again = agent.summarize_files("demo", &files, Some(&cache)).await.unwrap();
+        assert_eq!((again.requested, again.cached), (0, 6));
+        assert_eq!(again.summary, summary.summary);
+        requests.assert_async().await;
+    }
+}
@@ -1,10 +1,442 @@
 //! Tools and utilities for OpenAI agents
 //!
 //! This module provides utility functions and tools for enhancing AI agent capabilities.
+//!
+//! A [`ToolRegistry`] offers tools to the model through function calling, so
+//! a conversation can look into the repository instead of relying only on
+//! what was sent with the question. The repository tools work on
+//! [`RepositoryFiles`]: the processed output and the source files it quotes.
 
-use crate::error::Result;
+use crate::chunking::{self, Boundary};
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use async_trait::async_trait;
+use regex::RegexBuilder;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Directory the processed artifacts appear under
+pub const PROCESSED_DIR: &str = "processed";
+
+/// Most tokens a tool answers with
+const MAX_OUTPUT_TOKENS: usize = 2000;
+
+/// Most matches `grep` reports
+const MAX_MATCHES: usize = 50;
+
+/// Files of a processed repository, by path
+///
+/// The artifacts appear under [`PROCESSED_DIR`]. Each source file quoted in
+/// them, a heading naming its path in backticks followed by a fenced block,
+/// appears at that path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepositoryFiles {
+    files: BTreeMap<String, String>,
+}
+
+impl RepositoryFiles {
+    /// The files in `artifacts`, given by name and content
+    pub fn from_artifacts(artifacts: &[(String, String)]) -> Self {
+        let mut files = BTreeMap::new();
+        for (name, content) in artifacts {
+            files.insert(format!("{}/{}", PROCESSED_DIR, name.replace('\\', "/")), content.clone());
+            for (path, source) in quoted_files(content) {
+                files.entry(path).or_insert(source);
+            }
+        }
+        Self { files }
+    }
+
+    /// Number of files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether there are no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Content of the file at `path`
+    pub fn read(&self, path: &str) -> Option<&str> {
+        self.files.get(path.trim_start_matches("./").trim_start_matches('/')).map(String::as_str)
+    }
+
+    /// Files and directories (with a trailing `/`) directly in `dir`, the root when empty
+    pub fn list(&self, dir: &str) -> Vec<String> {
+        let dir = dir.trim_start_matches("./").trim_matches('/');
+        let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+        let mut entries: Vec<String> = self.files.keys()
+            .filter_map(|path| path.strip_prefix(&prefix))
+            .map(|rest| match rest.split_once('/') {
+                Some((subdir, _)) => format!("{}/", subdir),
+                None => rest.to_string(),
+            })
+            .collect();
+        entries.dedup();
+        entries
+    }
+
+    /// Paths of all files
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+}
+
+/// Source files quoted in a processed artifact, by path
+fn quoted_files(content: &str) -> Vec<(String, String)> {
+    let mut quoted = Vec::new();
+    let mut pending: Option<String> = None;
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        if line.starts_with('#') {
+            pending = line.split('`').nth(1)
+                .filter(|path| !path.is_empty() && !path.contains(' ') && (path.contains('.') || path.contains('/')))
+                .map(str::to_string);
+        } else if line.starts_with("```") {
+            let Some(path) = pending.take() else { continue };
+            // Fences with a language open blocks nested in the file, e.g. in a README
+            let mut depth = 1;
+            let mut body = Vec::new();
+            for line in lines.by_ref() {
+                let fence = line.trim_end();
+                if fence == "```" {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                } else if fence.starts_with("```") {
+                    depth += 1;
+                }
+                body.push(line);
+            }
+            quoted.push((path, body.join("\n")));
+        }
+    }
+    quoted
+}
+
+/// Something the model can call to find things out
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name the model calls the tool by
+    fn name(&self) -> &str;
+
+    /// What the tool does, for the model
+    fn description(&self) -> &str;
+
+    /// JSON schema of the arguments
+    fn parameters(&self) -> Value;
+
+    /// Runs the tool with `arguments` and returns what it found
+    async fn call(&self, arguments: Value) -> Result<String>;
+}
+
+/// The tools offered to the model
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// A registry without tools
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listing, reading and searching `files`, and looking up dependencies on their registries
+    pub fn for_repository(files: Arc<RepositoryFiles>) -> Result<Self> {
+        Ok(Self::new()
+            .with_tool(Arc::new(ListDirectory(files.clone())))
+            .with_tool(Arc::new(ReadFile(files.clone())))
+            .with_tool(Arc::new(Grep(files)))
+            .with_tool(Arc::new(DependencyInfo::new()?)))
+    }
+
+    /// Adds `tool`, replacing any tool of the same name
+    pub fn with_tool(mut self, tool: Arc<dyn Tool>) -> Self {
+        self.tools.retain(|existing| existing.name() != tool.name());
+        self.tools.push(tool);
+        self
+    }
+
+    /// Whether there are no tools
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// The tools in the `tools` format of the chat completions API
+    pub fn definitions(&self) -> Vec<Value> {
+        self.tools.iter()
+            .map(|tool| json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name(),
+                    "description": tool.description(),
+                    "parameters": tool.parameters(),
+                },
+            }))
+            .collect()
+    }
+
+    /// Runs the tool called `name` with the JSON `arguments` the model wrote
+    ///
+    /// Failures are reported as text, so the model can correct the call.
+    pub async fn call(&self, name: &str, arguments: &str) -> String {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name() == name) else {
+            return format!("error: there is no tool called {}", name);
+        };
+        let arguments = if arguments.trim().is_empty() { Ok(json!({})) } else { serde_json::from_str(arguments) };
+        let outcome = match arguments {
+            Ok(arguments) => tool.call(arguments).await,
+            Err(e) => Err(ProcessorError::Validation(format!("arguments are not JSON: {}", e))),
+        };
+        match outcome {
+            Ok(output) => chunking::truncate(&output, MAX_OUTPUT_TOKENS, Boundary::Sentence),
+            Err(e) => format!("error: {}", e),
+        }
+    }
+}
+
+/// The string argument `name`, if given
+fn argument<'a>(arguments: &'a Value, name: &str) -> Option<&'a str> {
+    arguments[name].as_str().filter(|value| !value.is_empty())
+}
+
+/// The string argument `name`, which must be given
+fn required<'a>(arguments: &'a Value, name: &str) -> Result<&'a str> {
+    argument(arguments, name).ok_or_else(|| ProcessorError::Validation(format!("`{}` is required", name)))
+}
+
+/// Lists a directory of the repository
+struct ListDirectory(Arc<RepositoryFiles>);
+
+#[async_trait]
+impl Tool for ListDirectory {
+    fn name(&self) -> &str {
+        "list_directory"
+    }
+
+    fn description(&self) -> &str {
+        "List the files and directories in a directory of the repository. \
+         The processed output, with the repository overview, is under `processed/`."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {"path": {"type": "string", "description": "Directory, the repository root if empty"}},
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let path = argument(&arguments, "path").unwrap_or("");
+        let entries = self.0.list(path);
+        if entries.is_empty() {
+            return Err(ProcessorError::Validation(format!("no directory {} in the repository", path)));
+        }
+        Ok(entries.join("\n"))
+    }
+}
+
+/// Reads a file of the repository
+struct ReadFile(Arc<RepositoryFiles>);
+
+#[async_trait]
+impl Tool for ReadFile {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn description(&self) -> &str {
+        "Read a file of the repository, with line numbers, optionally only some of its lines."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "description": "Path of the file"},
+                "start_line": {"type": "integer", "description": "First line to read, from 1"},
+                "end_line": {"type": "integer", "description": "Last line to read"},
+            },
+            "required": ["path"],
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let path = required(&arguments, "path")?;
+        let content = self.0.read(path)
+            .ok_or_else(|| ProcessorError::Validation(format!("no file {} in the repository; list_directory shows what there is", path)))?;
+        let start = arguments["start_line"].as_u64().unwrap_or(1).max(1) as usize;
+        let end = arguments["end_line"].as_u64().map_or(usize::MAX, |end| end as usize);
+        let lines: Vec<String> = content.lines()
+            .enumerate()
+            .skip(start - 1)
+            .take_while(|(i, _)| *i < end)
+            .map(|(i, line)| format!("{:>5} {}", i + 1, line))
+            .collect();
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Searches the repository for a pattern
+struct Grep(Arc<RepositoryFiles>);
+
+#[async_trait]
+impl Tool for Grep {
+    fn name(&self) -> &str {
+        "grep"
+    }
+
+    fn description(&self) -> &str {
+        "Search the files of the repository for a regular expression, ignoring case. \
+         Returns matching lines as path:line: text."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "pattern": {"type": "string", "description": "Regular expression to look for"},
+                "path": {"type": "string", "description": "Only search files under this path"},
+            },
+            "required": ["pattern"],
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let pattern = RegexBuilder::new(required(&arguments, "pattern")?)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| ProcessorError::Validation(format!("invalid pattern: {}", e)))?;
+        let under = argument(&arguments, "path").unwrap_or("").trim_start_matches("./");
+        let mut matches = Vec::new();
+        'files: for path in self.0.paths().filter(|path| path.starts_with(under)) {
+            for (i, line) in self.0.read(path).unwrap_or_default().lines().enumerate() {
+                if pattern.is_match(line) {
+                    if matches.len() == MAX_MATCHES {
+                        matches.push(format!("(stopped after {} matches)", MAX_MATCHES));
+                        break 'files;
+                    }
+                    let line: String = line.trim().chars().take(200).collect();
+                    matches.push(format!("{}:{}: {}", path, i + 1, line));
+                }
+            }
+        }
+        if matches.is_empty() {
+            return Ok("no matches".to_string());
+        }
+        Ok(matches.join("\n"))
+    }
+}
+
+/// Looks up a package on PyPI, npm or crates.io
+pub struct DependencyInfo {
+    client: Client,
+    /// Base URLs of the PyPI, npm and crates.io APIs
+    registries: [String; 3],
+}
+
+impl DependencyInfo {
+    /// A lookup against the public PyPI, npm and crates.io registries
+    pub fn new() -> Result<Self> {
+        Self::with_registries("https://pypi.org", "https://registry.npmjs.org", "https://crates.io")
+    }
+
+    /// A lookup against the given PyPI, npm and crates.io servers
+    pub fn with_registries(pypi: &str, npm: &str, crates: &str) -> Result<Self> {
+        let client = http::client_builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("llamapackageservice/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self { client, registries: [pypi, npm, crates].map(|url| url.trim_end_matches('/').to_string()) })
+    }
+}
+
+#[async_trait]
+impl Tool for DependencyInfo {
+    fn name(&self) -> &str {
+        "dependency_info"
+    }
+
+    fn description(&self) -> &str {
+        "Look up a dependency on its registry: latest version, description, license and links."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "ecosystem": {"type": "string", "enum": ["pypi", "npm", "crates"]},
+                "name": {"type": "string", "description": "Package name"},
+            },
+            "required": ["ecosystem", "name"],
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let name = required(&arguments, "name")?;
+        let [pypi, npm, crates] = &self.registries;
+        // The name is one path segment, so `/`, `?` and `#` in it cannot reach other endpoints
+        let (registry, segments) = match required(&arguments, "ecosystem")? {
+            "pypi" => (pypi, vec!["pypi", name, "json"]),
+            "npm" => (npm, vec![name]),
+            "crates" => (crates, vec!["api", "v1", "crates", name]),
+            other => return Err(ProcessorError::Validation(format!("unknown ecosystem {}; use pypi, npm or crates", other))),
+        };
+        let mut url = Url::parse(registry)?;
+        url.path_segments_mut()
+            .map_err(|_| ProcessorError::Config(format!("{} cannot be a registry URL", registry)))?
+            .pop_if_empty()
+            .extend(segments);
+        let response = http::fetch(self.client.get(url.clone())).await?;
+        if !response.status().is_success() {
+            return Err(ProcessorError::Validation(format!("{} answered {} for {}", url, response.status(), name)));
+        }
+        let package: Value = response.json()?;
+        let summary = match required(&arguments, "ecosystem")? {
+            "pypi" => json!({
+                "version": package["info"]["version"],
+                "description": package["info"]["summary"],
+                "license": package["info"]["license"],
+                "requires_python": package["info"]["requires_python"],
+                "dependencies": package["info"]["requires_dist"],
+                "homepage": package["info"]["home_page"],
+                "links": package["info"]["project_urls"],
+            }),
+            "npm" => {
+                let latest = package["dist-tags"]["latest"].as_str().unwrap_or_default();
+                let release = &package["versions"][latest];
+                json!({
+                    "version": latest,
+                    "description": package["description"],
+                    "license": package["license"],
+                    "dependencies": release["dependencies"],
+                    "homepage": package["homepage"],
+                    "repository": package["repository"]["url"],
+                    "published": package["time"][latest],
+                })
+            }
+            _ => json!({
+                "version": package["crate"]["max_stable_version"],
+                "description": package["crate"]["description"],
+                "license": package["versions"][0]["license"],
+                "downloads": package["crate"]["downloads"],
+                "homepage": package["crate"]["homepage"],
+                "repository": package["crate"]["repository"],
+                "updated": package["crate"]["updated_at"],
+            }),
+        };
+        Ok(serde_json::to_string_pretty(&summary)?)
+    }
+}
 
 /// Repository analysis tools
 pub struct RepositoryTools;
@@ -172,4 +604,60 @@ pub struct ComplexityMetrics {
     pub comment_lines: usize,
     /// Estimated cyclomatic complexity
     pub cyclomatic_complexity: u32,
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tools_read_list_and_search_the_processed_repository() {
+        let overview = "# demo\n\n## Full Source Files\n\n### `src/lib.rs`\n\n```rust\npub fn parse() {}\npub fn render() {}\n```\n\n\
+                        ### `README.md`\n\n```markdown\nUsage:\n```sh\ncargo run\n```\nMore\n```\n";
+        let files = Arc::new(RepositoryFiles::from_artifacts(&[("demo_analysis.txt".to_string(), overview.to_string())]));
+        assert_eq!(files.read("src/lib.rs"), Some("pub fn parse() {}\npub fn render() {}"));
+        assert_eq!(files.read("./README.md"), Some("Usage:\n```sh\ncargo run\n```\nMore"));
+        assert_eq!(files.list(""), ["README.md", "processed/", "src/"]);
+
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/api/v1/crates/serde")
+            .with_body(r#"{"crate": {"max_stable_version": "1.0.210", "description": "A serialization framework"}, "versions": [{"license": "MIT OR Apache-2.0"}]}"#)
+            .create_async().await;
+        let registry = ToolRegistry::for_repository(files).unwrap()
+            .with_tool(Arc::new(DependencyInfo::with_registries(&server.url(), &server.url(), &server.url()).unwrap()));
+        let names: Vec<Value> = registry.definitions().iter().map(|tool| tool["function"]["name"].clone()).collect();
+        assert_eq!(names, [json!("list_directory"), json!("read_file"), json!("grep"), json!("dependency_info")]);
+
+        assert_eq!(registry.call("read_file", r#"{"path": "src/lib.rs", "start_line": 2}"#).await, "    2 pub fn render() {}");
+        assert_eq!(registry.call("list_directory", r#"{"path": "src"}"#).await, "lib.rs");
+        assert_eq!(registry.call("grep", r#"{"pattern": "RENDER", "path": "src"}"#).await, "src/lib.rs:2: pub fn render() {}");
+        assert!(registry.call("read_file", r#"{"path": "src/main.rs"}"#).await.starts_with("error: "));
+        assert!(registry.call("grep", "{\"pattern\": \"(\"}").await.contains("invalid pattern"));
+        assert!(registry.call("delete_file", "{}").await.starts_with("error: "));
+
+        let serde = registry.call("dependency_info", r#"{"ecosystem": "crates", "name": "serde"}"#).await;
+        let serde: Value = serde_json::from_str(&serde).unwrap();
+        assert_eq!(serde["version"], "1.0.210");
+        assert_eq!(serde["license"], "MIT OR Apache-2.0");
+        assert!(registry.call("dependency_info", r#"{"ecosystem": "maven", "name": "junit"}"#).await.contains("unknown ecosystem"));
+    }
+
+    #[tokio::test]
+    async fn test_dependency_names_are_percent_encoded() {
+        let mut server = mockito::Server::new_async().await;
+        let scoped = server.mock("GET", "/@types%2Fnode")
+            .with_body(r#"{"dist-tags": {"latest": "22.7.0"}, "versions": {"22.7.0": {}}}"#)
+            .expect(1)
+            .create_async().await;
+        let escaped = server.mock("GET", "/api/v1/crates/serde%2F..%2F..%3Fadmin=1")
+            .with_status(404)
+            .expect(1)
+            .create_async().await;
+        let info = DependencyInfo::with_registries(&server.url(), &server.url(), &server.url()).unwrap();
+
+        let node: Value = serde_json::from_str(&info.call(json!({"ecosystem": "npm", "name": "@types/node"})).await.unwrap()).unwrap();
+        assert_eq!(node["version"], "22.7.0");
+        assert!(info.call(json!({"ecosystem": "crates", "name": "serde/../..?admin=1"})).await.is_err());
+        scoped.assert_async().await;
+        escaped.assert_async().await;
+    }
+}
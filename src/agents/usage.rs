@@ -0,0 +1,258 @@
+//! Token usage and spending of AI requests
+//!
+//! Every completion an agent receives is charged to a process-wide
+//! [`Ledger`], which adds it up by model for the current run and for each
+//! day. The run's figures are written into its manifest, which is also where
+//! the spending of earlier runs on the same day is found. Before each request
+//! the agent checks the [`AiBudget`]: once the run or the day has spent its
+//! cap, requests fail instead of being sent.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{NaiveDate, Utc};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use crate::chunking::count_tokens;
+use crate::error::{ProcessorError, Result};
+use crate::manifest::{self, RunManifest, RUNS_DIR, MANIFEST_FILE_NAME};
+use crate::metrics::PrometheusText;
+use super::{TokenStream, TokenUsage};
+
+/// Spending caps on AI requests, the `[ai_budget]` section of the config
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiBudget {
+    /// Most one run may spend, in US dollars
+    pub run_usd: Option<f64>,
+    /// Most all runs writing to the output directory may spend in a day (UTC), in US dollars
+    pub daily_usd: Option<f64>,
+}
+
+/// Requests, tokens and estimated cost, overall and by model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageSummary {
+    /// Completions received
+    pub requests: u64,
+    /// Tokens sent to the models
+    pub prompt_tokens: u64,
+    /// Tokens the models generated
+    pub completion_tokens: u64,
+    /// Estimated cost in US dollars; models without a known price count as free
+    pub estimated_cost_usd: f64,
+    /// Usage of each model
+    pub by_model: BTreeMap<String, TokenUsage>,
+}
+
+impl UsageSummary {
+    /// Whether nothing was used
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0
+    }
+
+    /// Adds one completion's `usage`
+    pub fn add(&mut self, usage: &TokenUsage) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.estimated_cost_usd += usage.estimated_cost_usd.unwrap_or(0.0);
+        self.by_model.entry(usage.model.clone()).or_default().add(usage);
+    }
+
+    /// Adds everything in `other`
+    pub fn merge(&mut self, other: &UsageSummary) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.estimated_cost_usd += other.estimated_cost_usd;
+        for (model, usage) in &other.by_model {
+            self.by_model.entry(model.clone()).or_default().add(usage);
+        }
+    }
+}
+
+/// Usage of the current run and of each day
+#[derive(Debug, Default)]
+pub struct Ledger {
+    run: UsageSummary,
+    days: BTreeMap<NaiveDate, UsageSummary>,
+}
+
+impl Ledger {
+    /// Charges `usage` to the run and to `day`
+    pub fn record(&mut self, usage: &TokenUsage, day: NaiveDate) {
+        self.run.add(usage);
+        self.days.entry(day).or_default().add(usage);
+    }
+
+    /// Usage of the current run
+    pub fn run(&self) -> &UsageSummary {
+        &self.run
+    }
+
+    /// Usage recorded on `day`
+    pub fn day(&self, day: NaiveDate) -> UsageSummary {
+        self.days.get(&day).cloned().unwrap_or_default()
+    }
+
+    /// Usage recorded on every day
+    pub fn total(&self) -> UsageSummary {
+        let mut total = UsageSummary::default();
+        for usage in self.days.values() {
+            total.merge(usage);
+        }
+        total
+    }
+
+    /// Fails once the run, or `day` with `filed_usd` spent by earlier runs, has reached its cap
+    pub fn check(&self, budget: &AiBudget, day: NaiveDate, filed_usd: f64) -> Result<()> {
+        if let Some(cap) = budget.run_usd {
+            if self.run.estimated_cost_usd >= cap {
+                return Err(ProcessorError::RateLimitExceeded(format!(
+                    "this run has spent its AI budget of ${:.2} (ai_budget.run_usd)", cap
+                )));
+            }
+        }
+        if let Some(cap) = budget.daily_usd {
+            let spent = filed_usd + self.day(day).estimated_cost_usd;
+            if spent >= cap {
+                return Err(ProcessorError::RateLimitExceeded(format!(
+                    "${:.2} of AI requests were made on {}, reaching the daily budget of ${:.2} (ai_budget.daily_usd)",
+                    spent, day, cap
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+static LEDGER: Lazy<Mutex<Ledger>> = Lazy::new(Default::default);
+
+/// Charges `usage` to the process-wide ledger
+pub fn record(usage: &TokenUsage) {
+    LEDGER.lock().unwrap_or_else(|e| e.into_inner()).record(usage, Utc::now().date_naive());
+}
+
+/// Forgets what was recorded; called when a new run starts
+pub fn start_run() {
+    *LEDGER.lock().unwrap_or_else(|e| e.into_inner()) = Ledger::default();
+}
+
+/// Usage of the current run
+pub fn run_usage() -> UsageSummary {
+    LEDGER.lock().unwrap_or_else(|e| e.into_inner()).run().clone()
+}
+
+/// Fails once the current run, or today in `output_dir`, has spent its share of `budget`
+pub fn check_budget(budget: &AiBudget, output_dir: &Path) -> Result<()> {
+    let today = Utc::now().date_naive();
+    // Earlier runs are only looked up when there is a daily cap to hold them to
+    let filed_usd = match budget.daily_usd {
+        Some(_) => filed_usage(output_dir, today).estimated_cost_usd,
+        None => 0.0,
+    };
+    LEDGER.lock().unwrap_or_else(|e| e.into_inner()).check(budget, today, filed_usd)
+}
+
+/// Usage recorded in the manifests of runs into `output_dir` that started on `day`, other than the current one
+pub fn filed_usage(output_dir: &Path, day: NaiveDate) -> UsageSummary {
+    let prefix = day.format("%Y%m%d").to_string();
+    let current = manifest::current_run_id();
+    let mut filed = UsageSummary::default();
+    let Ok(runs) = std::fs::read_dir(output_dir.join(RUNS_DIR)) else {
+        return filed;
+    };
+    for run in runs.filter_map(|run| run.ok()) {
+        let run_id = run.file_name().to_string_lossy().into_owned();
+        if !run_id.starts_with(&prefix) || run_id == current {
+            continue;
+        }
+        if let Ok(RunManifest { ai_usage: Some(usage), .. }) = RunManifest::load(&run.path().join(MANIFEST_FILE_NAME)) {
+            filed.merge(&usage);
+        }
+    }
+    filed
+}
+
+/// Passes `tokens` on, charging the answer to the ledger once the stream is dropped
+pub fn metered(model: &str, prompt_tokens: u64, tokens: TokenStream) -> TokenStream {
+    let mut meter = Meter { model: model.to_string(), prompt_tokens, written: String::new() };
+    tokens
+        .map(move |token| {
+            if let Ok(token) = &token {
+                meter.written.push_str(token);
+            }
+            token
+        })
+        .boxed()
+}
+
+/// Charges a streamed answer, however far it was read
+struct Meter {
+    model: String,
+    prompt_tokens: u64,
+    written: String,
+}
+
+impl Drop for Meter {
+    fn drop(&mut self) {
+        record(&TokenUsage::new(&self.model, self.prompt_tokens, count_tokens(&self.written) as u64));
+    }
+}
+
+/// Adds `<prefix>_ai_requests_total`, `<prefix>_ai_tokens_total` and
+/// `<prefix>_ai_cost_usd_total` by model for everything the process recorded
+pub fn render_metrics(prefix: &str, text: &mut PrometheusText) {
+    let total = LEDGER.lock().unwrap_or_else(|e| e.into_inner()).total();
+
+    let name = format!("{}_ai_tokens_total", prefix);
+    text.family(&name, "counter", "Tokens of AI requests by model and kind");
+    for (model, usage) in &total.by_model {
+        text.sample(&name, &[("model", model), ("kind", "prompt")], usage.prompt_tokens as f64)
+            .sample(&name, &[("model", model), ("kind", "completion")], usage.completion_tokens as f64);
+    }
+    let name = format!("{}_ai_cost_usd_total", prefix);
+    text.family(&name, "counter", "Estimated cost of AI requests in US dollars by model");
+    for (model, usage) in &total.by_model {
+        text.sample(&name, &[("model", model)], usage.estimated_cost_usd.unwrap_or(0.0));
+    }
+    let name = format!("{}_ai_requests_total", prefix);
+    text.family(&name, "counter", "AI completions received")
+        .sample(&name, &[], total.requests as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_adds_up_runs_and_days_and_holds_them_to_the_budget() {
+        let monday = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+        let tuesday = monday.succ_opt().unwrap();
+        let mut ledger = Ledger::default();
+        // $0.03 and $0.06 at gpt-4 prices
+        ledger.record(&TokenUsage::new("gpt-4", 1000, 0), monday);
+        ledger.record(&TokenUsage::new("gpt-4", 0, 1000), tuesday);
+        ledger.record(&TokenUsage::new("llama3.1", 500, 100), tuesday);
+
+        let run = ledger.run();
+        assert_eq!((run.requests, run.prompt_tokens, run.completion_tokens), (3, 1500, 1100));
+        assert!((run.estimated_cost_usd - 0.09).abs() < 1e-9);
+        assert_eq!(run.by_model["gpt-4"].total_tokens(), 2000);
+        assert_eq!(run.by_model["llama3.1"].estimated_cost_usd, None);
+        assert_eq!(ledger.day(tuesday).requests, 2);
+        assert!(ledger.day(tuesday.succ_opt().unwrap()).is_empty());
+        assert_eq!(ledger.total().requests, 3);
+
+        assert!(ledger.check(&AiBudget::default(), tuesday, 100.0).is_ok());
+        assert!(ledger.check(&AiBudget { run_usd: Some(0.10), daily_usd: None }, tuesday, 0.0).is_ok());
+        assert!(ledger.check(&AiBudget { run_usd: Some(0.05), daily_usd: None }, tuesday, 0.0).is_err());
+        let daily = AiBudget { run_usd: None, daily_usd: Some(0.10) };
+        assert!(ledger.check(&daily, tuesday, 0.0).is_ok());
+        let refused = ledger.check(&daily, tuesday, 0.05).unwrap_err();
+        assert!(refused.to_string().contains("daily budget of $0.10"), "{}", refused);
+        assert!(ledger.check(&daily, monday, 0.05).is_ok());
+    }
+}
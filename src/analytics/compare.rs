@@ -0,0 +1,306 @@
+//! Side-by-side comparison of processed targets
+//!
+//! Every processed repository leaves a [`RunSnapshot`] under the output
+//! directory; the comparison reads the latest snapshot of each target for its
+//! size, languages, function complexity and declared dependencies. Counts of
+//! vulnerabilities come from a security audit of the target saved by
+//! `analyze-batch --type security`, when there is one.
+
+use crate::agents::batch::ANALYSES_DIR;
+use crate::agents::structured::{SecurityReport, Severity};
+use crate::agents::AnalysisResult;
+use crate::batch_report::language_for;
+use crate::compression;
+use crate::error::{ProcessorError, Result};
+use crate::output_organizer::package_slug;
+use crate::run_diff::RunSnapshot;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What is known about one compared target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetProfile {
+    /// Target as recorded when it was processed
+    pub target: String,
+    /// When the target was last processed
+    pub processed_at: DateTime<Utc>,
+    /// Number of files
+    pub files: u64,
+    /// Lines in all files
+    pub lines: u64,
+    /// Size of all files in bytes
+    pub bytes: u64,
+    /// Language to number of files
+    pub languages: BTreeMap<String, u64>,
+    /// Complexity score from 0 to 100, when functions were measured
+    pub complexity_score: Option<f32>,
+    /// Highest cyclomatic complexity of a function, when functions were measured
+    pub max_cyclomatic: Option<u32>,
+    /// Dependency name to declared version requirement
+    pub dependencies: BTreeMap<String, String>,
+    /// Findings of the saved security audit by severity, when the target was audited
+    pub vulnerabilities: Option<BTreeMap<Severity, usize>>,
+}
+
+impl TargetProfile {
+    /// Profiles a target from its snapshot and saved security audit
+    fn new(snapshot: RunSnapshot, audit: Option<SecurityReport>) -> Self {
+        let mut languages = BTreeMap::new();
+        for path in snapshot.files.keys() {
+            if let Some(language) = language_for(path) {
+                *languages.entry(language.to_string()).or_insert(0) += 1;
+            }
+        }
+        let vulnerabilities = audit.map(|report| {
+            let mut counts = BTreeMap::new();
+            for finding in report.findings {
+                *counts.entry(finding.severity).or_insert(0) += 1;
+            }
+            counts
+        });
+        Self {
+            processed_at: snapshot.generated_at,
+            files: snapshot.metrics.get("files").copied().unwrap_or(0),
+            lines: snapshot.metrics.get("lines").copied().unwrap_or(0),
+            bytes: snapshot.metrics.get("bytes").copied().unwrap_or(0),
+            languages,
+            complexity_score: snapshot.complexity.as_ref().map(|c| c.score),
+            max_cyclomatic: snapshot.complexity.as_ref().map(|c| c.max_cyclomatic),
+            dependencies: snapshot.dependencies,
+            vulnerabilities,
+            target: snapshot.target,
+        }
+    }
+
+    /// Language with the most files
+    pub fn main_language(&self) -> Option<&str> {
+        self.languages.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(language, _)| language.as_str())
+    }
+}
+
+/// Comparison of two or more processed targets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    /// When the comparison was made
+    pub generated_at: DateTime<Utc>,
+    /// Targets in the order they were given
+    pub targets: Vec<TargetProfile>,
+    /// Dependencies declared by more than one target, with each target's requirement
+    pub common_dependencies: BTreeMap<String, Vec<Option<String>>>,
+}
+
+impl Comparison {
+    /// Compares the profiled `targets`, in order
+    pub fn new(targets: Vec<TargetProfile>) -> Self {
+        let mut common_dependencies = BTreeMap::new();
+        for (i, profile) in targets.iter().enumerate() {
+            for (dependency, version) in &profile.dependencies {
+                common_dependencies.entry(dependency.clone())
+                    .or_insert_with(|| vec![None; targets.len()])[i] = Some(version.clone());
+            }
+        }
+        common_dependencies.retain(|_, versions: &mut Vec<Option<String>>| versions.iter().flatten().count() > 1);
+        Self { generated_at: Utc::now(), targets, common_dependencies }
+    }
+
+    /// Compares the latest processed runs of `targets` recorded under `output_dir`
+    ///
+    /// A target is found by the URL or path it was processed with, or for
+    /// GitHub repositories by `owner/repo`.
+    pub fn load(output_dir: &Path, targets: &[String]) -> Result<Self> {
+        let profiles = targets.iter()
+            .map(|target| {
+                let names = names_for(target);
+                let snapshot = names.iter()
+                    .find_map(|name| RunSnapshot::load(output_dir, name))
+                    .ok_or_else(|| ProcessorError::Validation(format!(
+                        "No processed run of {} in {}; process it first",
+                        target,
+                        output_dir.display()
+                    )))?;
+                let audit = names.iter().find_map(|name| load_audit(output_dir, name));
+                Ok(TargetProfile::new(snapshot, audit))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(profiles))
+    }
+
+    /// The comparison as a Markdown report
+    pub fn to_markdown(&self) -> String {
+        let names: Vec<&str> = self.targets.iter().map(|t| t.target.as_str()).collect();
+        let mut out = format!("# Comparison: {}\n\n", names.join(" vs "));
+        out.push_str(&format!("**Generated:** {}\n\n", self.generated_at.format("%Y-%m-%d %H:%M:%S UTC")));
+
+        out.push_str("## Overview\n\n");
+        out.push_str(&header("", &names));
+        let row = |label: &str, cell: &dyn Fn(&TargetProfile) -> String| {
+            let cells: Vec<String> = self.targets.iter().map(cell).collect();
+            format!("| {} | {} |\n", label, cells.join(" | "))
+        };
+        out.push_str(&row("Processed", &|t| t.processed_at.format("%Y-%m-%d %H:%M").to_string()));
+        out.push_str(&row("Files", &|t| t.files.to_string()));
+        out.push_str(&row("Lines", &|t| t.lines.to_string()));
+        out.push_str(&row("Main language", &|t| t.main_language().unwrap_or("-").to_string()));
+        out.push_str(&row("Dependencies", &|t| t.dependencies.len().to_string()));
+        out.push_str(&row("Complexity score", &|t| t.complexity_score.map_or("-".to_string(), |s| format!("{:.0}/100", s))));
+        out.push_str(&row("Highest cyclomatic", &|t| t.max_cyclomatic.map_or("-".to_string(), |c| c.to_string())));
+        out.push_str(&row("Vulnerabilities", &|t| match &t.vulnerabilities {
+            None => "not audited".to_string(),
+            Some(counts) if counts.is_empty() => "0".to_string(),
+            Some(counts) => {
+                let by_severity: Vec<String> = counts.iter()
+                    .map(|(severity, n)| format!("{} {}", n, severity.name()))
+                    .collect();
+                format!("{} ({})", counts.values().sum::<usize>(), by_severity.join(", "))
+            }
+        }));
+        out.push('\n');
+
+        out.push_str("## Languages\n\n");
+        let mut languages: Vec<&String> = self.targets.iter().flat_map(|t| t.languages.keys()).collect();
+        languages.sort_unstable();
+        languages.dedup();
+        if languages.is_empty() {
+            out.push_str("No language information available.\n\n");
+        } else {
+            out.push_str(&header("Language", &names));
+            for language in languages {
+                out.push_str(&row(language, &|t| {
+                    let total: u64 = t.languages.values().sum();
+                    match t.languages.get(language) {
+                        Some(&n) => format!("{} ({:.1}%)", n, n as f64 * 100.0 / total as f64),
+                        None => "-".to_string(),
+                    }
+                }));
+            }
+            out.push('\n');
+        }
+
+        out.push_str("## Dependencies in Common\n\n");
+        if self.common_dependencies.is_empty() {
+            out.push_str("No dependencies are declared by more than one target.\n\n");
+        } else {
+            out.push_str(&header("Dependency", &names));
+            for (dependency, versions) in &self.common_dependencies {
+                let cells: Vec<&str> = versions.iter().map(|v| v.as_deref().unwrap_or("-")).collect();
+                out.push_str(&format!("| {} | {} |\n", dependency, cells.join(" | ")));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Header and separator rows of a table with a column per target
+fn header(first: &str, names: &[&str]) -> String {
+    format!("| {} | {} |\n|{}|\n", first, names.join(" | "), vec!["---"; names.len() + 1].join("|"))
+}
+
+/// Names a target may have been recorded under
+pub(super) fn names_for(target: &str) -> Vec<String> {
+    let mut names = vec![target.to_string()];
+    if let Some(rest) = target.split_once("://").map(|(_, rest)| rest) {
+        names.push(rest.to_string());
+    }
+    if let Some(rest) = target.find("github.com").map(|i| &target[i + "github.com".len()..]) {
+        let mut parts = rest.trim_start_matches([':', '/']).split('/');
+        if let (Some(owner), Some(repo)) = (parts.next(), parts.next()) {
+            names.push(format!("{}/{}", owner, repo.trim_end_matches(".git")));
+            names.push(format!("github.com/{}/{}", owner, repo.trim_end_matches(".git")));
+        }
+    }
+    if let Ok(path) = Path::new(target).canonicalize() {
+        names.push(path.to_string_lossy().into_owned());
+    }
+    names
+}
+
+/// The security audit of `name` saved by a batch analysis, if any
+fn load_audit(output_dir: &Path, name: &str) -> Option<SecurityReport> {
+    let file_name = format!("security_{}.json", package_slug(name));
+    // Saved compressed or encrypted when the output is
+    let path = std::fs::read_dir(output_dir.join(ANALYSES_DIR)).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| compression::logical_name(&path.file_name().unwrap_or_default().to_string_lossy()) == file_name)?;
+    let result: AnalysisResult = serde_json::from_slice(&compression::read_artifact(&path).ok()?).ok()?;
+    result.report().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::AnalysisType;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_processed_targets_are_compared_side_by_side() {
+        let output = tempdir().unwrap();
+        let repos = tempdir().unwrap();
+        let repo = |name: &str, files: &[(&str, &str)]| {
+            let dir = repos.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            for (path, content) in files {
+                std::fs::write(dir.join(path), content).unwrap();
+            }
+            RunSnapshot::capture(name, &dir).save(output.path()).unwrap();
+        };
+        repo("acme/api", &[
+            ("Cargo.toml", "[dependencies]\nserde = \"1.0\"\ntokio = \"1\"\n"),
+            ("main.rs", "fn main() {\n    if ready() && armed() {\n        fire();\n    }\n}\n"),
+            ("lib.rs", "fn ready() -> bool {\n    true\n}\n"),
+        ]);
+        repo("acme/cli", &[
+            ("Cargo.toml", "[dependencies]\nserde = \"1.0.190\"\nclap = \"4\"\n"),
+            ("main.py", "def main():\n    pass\n"),
+        ]);
+
+        // An audit of the second target, saved as a batch analysis would be
+        let findings = json!({
+            "summary": "Two problems",
+            "findings": [
+                {"title": "Injection", "severity": "high", "location": null, "description": "", "recommendation": ""},
+                {"title": "Verbose errors", "severity": "low", "location": null, "description": "", "recommendation": ""},
+            ],
+            "recommendations": [],
+        });
+        let audit = json!({
+            "id": "1",
+            "analysis_type": serde_json::to_value(AnalysisType::SecurityAudit).unwrap(),
+            "content": "",
+            "confidence": 0.8,
+            "metadata": {},
+            "timestamp": Utc::now(),
+            "structured": findings,
+        });
+        std::fs::create_dir_all(output.path().join(ANALYSES_DIR)).unwrap();
+        std::fs::write(output.path().join(ANALYSES_DIR).join("security_github.com-acme-cli.json"), audit.to_string()).unwrap();
+
+        let targets = ["acme/api".to_string(), "https://github.com/acme/cli".to_string()];
+        let comparison = Comparison::load(output.path(), &targets).unwrap();
+        let [api, cli] = &comparison.targets[..] else { panic!("expected two targets") };
+        assert_eq!((api.files, cli.files), (3, 2));
+        assert_eq!((api.main_language(), cli.main_language()), (Some("Rust"), Some("Python")));
+        assert_eq!(api.max_cyclomatic, Some(3));
+        assert_eq!(api.vulnerabilities, None);
+        assert_eq!(cli.vulnerabilities, Some(BTreeMap::from([(Severity::High, 1), (Severity::Low, 1)])));
+        assert_eq!(comparison.common_dependencies, BTreeMap::from([
+            ("serde".to_string(), vec![Some("1.0".to_string()), Some("1.0.190".to_string())]),
+        ]));
+
+        let markdown = comparison.to_markdown();
+        assert!(markdown.contains("| Main language | Rust | Python |"));
+        assert!(markdown.contains("| Vulnerabilities | not audited | 2 (1 high, 1 low) |"));
+        assert!(markdown.contains("| Rust | 2 (100.0%) | - |"));
+        assert!(markdown.contains("| serde | 1.0 | 1.0.190 |"));
+        assert!(serde_json::to_value(&comparison).unwrap()["targets"][1]["vulnerabilities"]["high"] == 1);
+
+        let missing = Comparison::load(output.path(), &["acme/gone".to_string(), "acme/api".to_string()]);
+        assert!(matches!(missing, Err(ProcessorError::Validation(_))));
+    }
+}
@@ -0,0 +1,826 @@
+//! Per-function complexity of source files
+//!
+//! Functions are found and measured from a lexical scan of each file, with
+//! comments and string literals blanked out first, so no grammar is needed
+//! per language. Brace-delimited languages (Rust, Go, C and C++, Java, C#,
+//! JavaScript and TypeScript, Kotlin, Swift, PHP and Scala) and Python are
+//! supported. Each function gets a cyclomatic complexity, one plus its
+//! decision points, and a cognitive complexity, which weights branches by how
+//! deeply they are nested as SonarSource defines it. The repository's score
+//! weights the lines of each function by the risk band of its cyclomatic
+//! complexity.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Functions listed as hotspots in reports
+pub const HOTSPOTS: usize = 10;
+
+/// Complexity of one function
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionComplexity {
+    /// File the function is in
+    pub path: String,
+    /// Name of the function
+    pub name: String,
+    /// Line the function starts on
+    pub line: usize,
+    /// Lines the function spans
+    pub lines: usize,
+    /// One plus the number of decision points
+    pub cyclomatic: u32,
+    /// Branches and boolean operator sequences, weighted by nesting
+    pub cognitive: u32,
+}
+
+impl FunctionComplexity {
+    /// Share of the function's lines that count against the score, from 0.0 to 1.0
+    ///
+    /// Up to 10 the risk grows slowly with the complexity; past that the
+    /// usual bands apply: moderate to 20, high to 50, untestable beyond.
+    pub fn risk(&self) -> f64 {
+        match self.cyclomatic {
+            0..=10 => 0.25 * f64::from(self.cyclomatic.saturating_sub(1)) / 9.0,
+            11..=20 => 0.5,
+            21..=50 => 0.75,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Complexity of the functions in a repository
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComplexityReport {
+    /// Risk-weighted share of function lines, from 0 for simple code to 100
+    pub score: f32,
+    /// Functions measured
+    pub functions: usize,
+    /// Mean cyclomatic complexity
+    pub average_cyclomatic: f32,
+    /// Highest cyclomatic complexity
+    pub max_cyclomatic: u32,
+    /// Most complex functions with any branches, most complex first
+    pub hotspots: Vec<FunctionComplexity>,
+    /// Lines in all measured functions
+    pub function_lines: u64,
+    /// Function lines weighted by their function's risk
+    pub risk_lines: f64,
+    /// Sum of the cyclomatic complexities
+    pub total_cyclomatic: u64,
+}
+
+impl ComplexityReport {
+    /// Report on `functions`, keeping the `top` most complex as hotspots
+    pub fn from_functions(functions: Vec<FunctionComplexity>, top: usize) -> Self {
+        let mut report = Self {
+            functions: functions.len(),
+            max_cyclomatic: functions.iter().map(|f| f.cyclomatic).max().unwrap_or(0),
+            function_lines: functions.iter().map(|f| f.lines as u64).sum(),
+            risk_lines: functions.iter().map(|f| f.lines as f64 * f.risk()).sum(),
+            total_cyclomatic: functions.iter().map(|f| u64::from(f.cyclomatic)).sum(),
+            hotspots: functions,
+            ..Self::default()
+        };
+        report.rank(top);
+        report
+    }
+
+    /// Report on the functions of each `(path, content)` source
+    pub fn from_sources<'a>(sources: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let functions = sources.into_iter().flat_map(|(path, content)| analyze(path, content)).collect();
+        Self::from_functions(functions, HOTSPOTS)
+    }
+
+    /// Adds the functions `other` measured
+    pub fn merge(&mut self, other: &ComplexityReport) {
+        let top = self.hotspots.len().max(other.hotspots.len());
+        self.functions += other.functions;
+        self.max_cyclomatic = self.max_cyclomatic.max(other.max_cyclomatic);
+        self.function_lines += other.function_lines;
+        self.risk_lines += other.risk_lines;
+        self.total_cyclomatic += other.total_cyclomatic;
+        self.hotspots.extend(other.hotspots.iter().cloned());
+        self.rank(top);
+    }
+
+    /// "Complexity" report section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Complexity\n\n");
+        out.push_str(&format!(
+            "**Score:** {:.0}/100 across {} functions (average cyclomatic complexity {:.1}, highest {})\n\n",
+            self.score, self.functions, self.average_cyclomatic, self.max_cyclomatic
+        ));
+        if !self.hotspots.is_empty() {
+            out.push_str("### Hotspots\n\n");
+            out.push_str("| Function | Location | Cyclomatic | Cognitive | Lines |\n");
+            out.push_str("|----------|----------|------------|-----------|-------|\n");
+            for f in &self.hotspots {
+                out.push_str(&format!(
+                    "| `{}` | `{}:{}` | {} | {} | {} |\n",
+                    f.name, f.path, f.line, f.cyclomatic, f.cognitive, f.lines
+                ));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Derives the score and average, and keeps the `top` most complex functions that branch
+    fn rank(&mut self, top: usize) {
+        self.score = if self.function_lines == 0 { 0.0 } else { (100.0 * self.risk_lines / self.function_lines as f64) as f32 };
+        self.average_cyclomatic = if self.functions == 0 { 0.0 } else { self.total_cyclomatic as f32 / self.functions as f32 };
+        self.hotspots.sort_by(|a, b| {
+            b.cyclomatic.cmp(&a.cyclomatic)
+                .then(b.cognitive.cmp(&a.cognitive))
+                .then_with(|| (&a.path, a.line).cmp(&(&b.path, b.line)))
+        });
+        self.hotspots.retain(|f| f.cyclomatic > 1);
+        self.hotspots.truncate(top);
+    }
+}
+
+/// Measures the functions in `content`, the source file at `path`
+///
+/// Files in unsupported languages have no functions.
+pub fn analyze(path: &str, content: &str) -> Vec<FunctionComplexity> {
+    let Some(syntax) = Syntax::for_path(Path::new(path)) else {
+        return Vec::new();
+    };
+    let blanked = blank(content, syntax);
+    let mut functions = if syntax == Syntax::Python {
+        analyze_python(&blanked)
+    } else {
+        analyze_braces(&tokenize(&blanked), syntax)
+    };
+    for function in &mut functions {
+        function.path = path.to_string();
+    }
+    functions
+}
+
+/// Whether functions are measured in the language of the file at `path`
+pub(super) fn supports(path: &str) -> bool {
+    Syntax::for_path(Path::new(path)).is_some()
+}
+
+/// Tokens of `content`, the source file at `path`, each with its line, or `None` for unsupported languages
+///
+/// Comments and the contents of string literals are left out.
+pub(super) fn source_tokens(path: &str, content: &str) -> Option<Vec<(String, usize)>> {
+    let syntax = Syntax::for_path(Path::new(path))?;
+    let blanked = blank(content, syntax);
+    Some(tokenize(&blanked).into_iter().map(|token| (token.text.to_string(), token.line)).collect())
+}
+
+/// Family of languages scanned the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Rust,
+    Go,
+    /// C, C++, Java and C#, whose functions have no keyword
+    CLike,
+    JavaScript,
+    Kotlin,
+    Swift,
+    Php,
+    Scala,
+    Python,
+}
+
+impl Syntax {
+    fn for_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        Some(match ext.as_str() {
+            "rs" => Self::Rust,
+            "go" => Self::Go,
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "java" | "cs" => Self::CLike,
+            "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => Self::JavaScript,
+            "kt" | "kts" => Self::Kotlin,
+            "swift" => Self::Swift,
+            "php" => Self::Php,
+            "scala" => Self::Scala,
+            "py" => Self::Python,
+            _ => return None,
+        })
+    }
+
+    /// Keyword introducing a function, if the language has one
+    fn function_keyword(self) -> Option<&'static str> {
+        match self {
+            Self::Rust => Some("fn"),
+            Self::Go | Self::Swift => Some("func"),
+            Self::Kotlin => Some("fun"),
+            Self::Php | Self::JavaScript => Some("function"),
+            Self::Scala | Self::Python => Some("def"),
+            Self::CLike => None,
+        }
+    }
+
+    /// Whether `?` is a conditional operator rather than, say, Rust's error propagation
+    fn has_ternary(self) -> bool {
+        matches!(self, Self::CLike | Self::JavaScript | Self::Php | Self::Swift)
+    }
+
+    /// Whether `'` opens a string rather than a character literal or a Rust lifetime
+    fn single_quoted_strings(self) -> bool {
+        matches!(self, Self::JavaScript | Self::Php | Self::Python)
+    }
+}
+
+/// `content` with comments and the contents of string literals replaced by spaces, keeping line breaks
+fn blank(content: &str, syntax: Syntax) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = String::with_capacity(content.len());
+    let hash_comments = matches!(syntax, Syntax::Python | Syntax::Php);
+    let mut i = 0;
+    // Blanks `chars[from..to]`, keeping line breaks
+    let blank_range = |out: &mut String, from: usize, to: usize| {
+        for &c in &chars[from..to.min(chars.len())] {
+            out.push(if c == '\n' { '\n' } else { ' ' });
+        }
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let end = if (c == '/' && next == Some('/') && syntax != Syntax::Python) || (c == '#' && hash_comments) {
+            chars[i..].iter().position(|&c| c == '\n').map_or(chars.len(), |n| i + n)
+        } else if c == '/' && next == Some('*') && syntax != Syntax::Python {
+            find(&chars, i + 2, &['*', '/']).map_or(chars.len(), |n| n + 2)
+        } else if syntax == Syntax::Python && (c == '"' || c == '\'') && next == Some(c) && chars.get(i + 2) == Some(&c) {
+            let quote = [c, c, c];
+            out.extend(quote);
+            let close = find(&chars, i + 3, &quote).unwrap_or(chars.len());
+            blank_range(&mut out, i + 3, close);
+            out.extend(&chars[close.min(chars.len())..(close + 3).min(chars.len())]);
+            i = close + 3;
+            continue;
+        } else if syntax == Syntax::Rust && c == 'r' && (next == Some('"') || next == Some('#'))
+            && !chars.get(i.wrapping_sub(1)).is_some_and(|p| p.is_alphanumeric() || *p == '_')
+        {
+            let hashes = chars[i + 1..].iter().take_while(|&&c| c == '#').count();
+            if chars.get(i + 1 + hashes) != Some(&'"') {
+                out.push(c);
+                i += 1;
+                continue;
+            }
+            let mut closing = vec!['"'];
+            closing.extend(std::iter::repeat('#').take(hashes));
+            let close = find(&chars, i + 2 + hashes, &closing).map_or(chars.len(), |n| n + closing.len());
+            out.push('"');
+            blank_range(&mut out, i + 1, close.saturating_sub(1));
+            out.push('"');
+            i = close;
+            continue;
+        } else if c == '"'
+            || (c == '`' && matches!(syntax, Syntax::JavaScript | Syntax::Go))
+            || (c == '\'' && (syntax.single_quoted_strings() || is_char_literal(&chars, i)))
+        {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != c {
+                // Only backquoted strings span lines; an unclosed quote ends with its line
+                if chars[j] == '\n' && c != '`' {
+                    break;
+                }
+                j += if chars[j] == '\\' && c != '`' { 2 } else { 1 };
+            }
+            out.push(c);
+            blank_range(&mut out, i + 1, j);
+            if chars.get(j) == Some(&c) {
+                out.push(c);
+            }
+            i = j + 1;
+            continue;
+        } else {
+            out.push(c);
+            i += 1;
+            continue;
+        };
+        blank_range(&mut out, i, end);
+        i = end;
+    }
+    out
+}
+
+/// Whether the `'` at `i` opens a character literal such as `'a'` or `'\n'`, not a lifetime
+fn is_char_literal(chars: &[char], i: usize) -> bool {
+    match chars.get(i + 1) {
+        Some('\\') => true,
+        Some(_) => chars.get(i + 2) == Some(&'\''),
+        None => false,
+    }
+}
+
+/// Index in `chars`, from `from`, where `pattern` starts
+fn find(chars: &[char], from: usize, pattern: &[char]) -> Option<usize> {
+    (from..chars.len()).find(|&i| chars[i..].starts_with(pattern))
+}
+
+/// A word or punctuation mark and the line it is on
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+impl Token<'_> {
+    fn is_word(&self) -> bool {
+        self.text.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+    }
+}
+
+/// Operators scanned as one token
+const OPERATORS: [&str; 6] = ["&&", "||", "??", "=>", "?.", "::"];
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            rest = &rest[start..];
+            let len = if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$') {
+                rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len())
+            } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                op.len()
+            } else {
+                rest.chars().next().map_or(1, char::len_utf8)
+            };
+            tokens.push(Token { text: &rest[..len], line: n + 1 });
+            rest = &rest[len..];
+        }
+    }
+    tokens
+}
+
+/// Words that look like calls but start control flow
+const CONTROL_WORDS: [&str; 14] = [
+    "if", "for", "while", "switch", "catch", "match", "return", "sizeof", "foreach", "using", "lock", "synchronized",
+    "when", "elseif",
+];
+
+/// Words that cannot come right before the name of a C-like function
+const NOT_TYPES: [&str; 12] = ["return", "new", "throw", "else", "case", "do", "yield", "await", "in", "of", "typeof", "delete"];
+
+/// Modifiers that can come right before a JavaScript method name
+const METHOD_MODIFIERS: [&str; 8] = ["async", "static", "get", "set", "public", "private", "protected", "override"];
+
+/// A function whose body is being read
+struct OpenFunction {
+    name: String,
+    line: usize,
+    /// Index in the brace stack of the brace opening its body
+    body: usize,
+    /// Decision points, and `match` expressions whose first arm is not one
+    decisions: i64,
+    cognitive: u32,
+    /// Last boolean operator of the current expression, for counting sequences
+    last_operator: Option<&'static str>,
+}
+
+/// An open brace, and whether it opens the block of a branch or loop
+struct Brace {
+    nesting: bool,
+}
+
+/// Measures the functions of a brace-delimited language
+fn analyze_braces(tokens: &[Token<'_>], syntax: Syntax) -> Vec<FunctionComplexity> {
+    let mut done = Vec::new();
+    let mut open: Vec<OpenFunction> = Vec::new();
+    let mut braces: Vec<Brace> = Vec::new();
+    // Name and line of a function whose body the next `{` opens
+    let mut signature: Option<(String, usize)> = None;
+    // Whether the next `{` opens the block of a branch or loop
+    let mut branch_block = false;
+    let mut after_else = false;
+    let mut parens = 0;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        let text = token.text;
+        let next = tokens.get(i + 1).map(|t| t.text);
+
+        // Functions start with a keyword, or for C-like languages and JavaScript methods, a signature
+        if Some(text) == syntax.function_keyword() {
+            if let Some(name) = function_name(tokens, i + 1, syntax) {
+                signature = Some((name.to_string(), token.line));
+            }
+        } else if next == Some("(") && token.is_word() && !CONTROL_WORDS.contains(&text) && open.is_empty() {
+            let before = i.checked_sub(1).map(|b| tokens[b].text);
+            let starts_function = match syntax {
+                Syntax::CLike => before.is_some_and(|b| {
+                    ["*", "&", ">", "]", "::", "~"].contains(&b)
+                        || b.starts_with(|c: char| c.is_alphabetic() || c == '_') && !NOT_TYPES.contains(&b)
+                }),
+                Syntax::JavaScript => before.is_none_or(|b| ["{", "}", ";", "*"].contains(&b) || METHOD_MODIFIERS.contains(&b)),
+                _ => false,
+            };
+            if starts_function && body_follows(tokens, i + 1) {
+                signature = Some((text.to_string(), token.line));
+            }
+        }
+
+        match text {
+            "{" => {
+                if let Some((name, line)) = signature.take() {
+                    open.push(OpenFunction { name, line, body: braces.len(), decisions: 0, cognitive: 0, last_operator: None });
+                }
+                braces.push(Brace { nesting: std::mem::take(&mut branch_block) });
+            }
+            "}" => {
+                braces.pop();
+                if open.last().is_some_and(|f| f.body == braces.len()) {
+                    let f = open.pop().expect("checked above");
+                    done.push(FunctionComplexity {
+                        path: String::new(),
+                        name: f.name,
+                        line: f.line,
+                        lines: token.line - f.line + 1,
+                        cyclomatic: (1 + f.decisions).max(1) as u32,
+                        cognitive: f.cognitive,
+                    });
+                }
+            }
+            ";" => {
+                signature = None;
+                branch_block = false;
+            }
+            "=>" if syntax == Syntax::JavaScript && next == Some("{") => {
+                if let Some(name) = arrow_function_name(tokens, i) {
+                    signature = Some((name.to_string(), tokens[i].line));
+                }
+            }
+            "(" => parens += 1,
+            ")" => parens -= 1,
+            // An expression body, as in `fun twice(x: Int = 1) = x * 2`
+            "=" if parens == 0 && matches!(syntax, Syntax::Kotlin | Syntax::Scala) => signature = None,
+            _ => {}
+        }
+
+        if let Some(f) = open.last_mut() {
+            let nesting = braces[f.body + 1..].iter().filter(|b| b.nesting).count() as u32;
+            if matches!(text, "{" | "}" | ";" | ")") {
+                f.last_operator = None;
+            }
+            match text {
+                "if" | "guard" => {
+                    f.decisions += 1;
+                    if !after_else {
+                        f.cognitive += 1 + nesting;
+                    }
+                    branch_block = true;
+                }
+                "for" | "foreach" | "while" | "catch" | "loop" => {
+                    f.decisions += i64::from(text != "loop");
+                    f.cognitive += 1 + nesting;
+                    branch_block = true;
+                }
+                "switch" | "select" | "match" | "when" => {
+                    f.cognitive += 1 + nesting;
+                    branch_block = true;
+                    if syntax == Syntax::Rust {
+                        f.decisions -= 1;
+                    }
+                }
+                "case" => f.decisions += 1,
+                "=>" if syntax == Syntax::Rust => f.decisions += 1,
+                "->" if syntax == Syntax::Kotlin => f.decisions += 1,
+                "else" => {
+                    f.cognitive += 1;
+                    branch_block = next != Some("if");
+                }
+                "?" if syntax.has_ternary() && !matches!(next, Some(":" | ")" | "," | ">" | "=" | "?" | ";")) => {
+                    f.decisions += 1;
+                    f.cognitive += 1 + nesting;
+                }
+                "&&" | "||" | "??" => {
+                    f.decisions += 1;
+                    let operator = OPERATORS.iter().find(|op| **op == text).copied();
+                    if f.last_operator != operator {
+                        f.cognitive += 1;
+                    }
+                    f.last_operator = operator;
+                }
+                _ => {}
+            }
+        }
+        after_else = text == "else";
+        i += 1;
+    }
+    done.sort_by_key(|f| f.line);
+    done
+}
+
+/// Name of the function whose keyword comes right before `from`, as in `fn name<T>(` or `fun Type.name(`
+///
+/// Anonymous functions have none.
+fn function_name<'a>(tokens: &[Token<'a>], from: usize, syntax: Syntax) -> Option<&'a str> {
+    let mut j = from;
+    // Go methods name their receiver first: `func (s *Server) Serve(`
+    if syntax == Syntax::Go && tokens.get(j)?.text == "(" {
+        j = closing(tokens, j)? + 1;
+    }
+    let mut generics = 0;
+    let mut name = None;
+    for token in &tokens[j.min(tokens.len())..] {
+        match token.text {
+            "(" => return name,
+            "<" | "[" => generics += 1,
+            ">" | "]" => generics -= 1,
+            "{" | "}" | ";" | "=" => return None,
+            _ if generics == 0 && token.is_word() => name = Some(token.text),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Index of the token closing the bracket opened at `open`
+fn closing(tokens: &[Token<'_>], open: usize) -> Option<usize> {
+    let (left, right) = match tokens[open].text {
+        "(" => ("(", ")"),
+        "[" => ("[", "]"),
+        _ => ("{", "}"),
+    };
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.text == left {
+            depth += 1;
+        } else if token.text == right {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Whether the parameter list opened at `paren` is followed by a body rather than a `;`
+fn body_follows(tokens: &[Token<'_>], paren: usize) -> bool {
+    let Some(close) = closing(tokens, paren) else {
+        return false;
+    };
+    let mut j = close + 1;
+    while let Some(token) = tokens.get(j) {
+        match token.text {
+            "{" => return true,
+            ";" | "}" | "=" | "=>" | "," | "&&" | "||" => return false,
+            "(" => j = closing(tokens, j).unwrap_or(j),
+            _ => {}
+        }
+        j += 1;
+    }
+    false
+}
+
+/// Name an arrow function whose `=>` is at `arrow` is assigned to, as in `const f = (x) => {`
+fn arrow_function_name<'a>(tokens: &[Token<'a>], arrow: usize) -> Option<&'a str> {
+    let mut j = arrow.checked_sub(1)?;
+    if tokens[j].text == ")" {
+        let mut depth = 0;
+        loop {
+            match tokens[j].text {
+                ")" => depth += 1,
+                "(" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            j = j.checked_sub(1)?;
+        }
+    }
+    j = j.checked_sub(1)?;
+    if tokens[j].text == "async" {
+        j = j.checked_sub(1)?;
+    }
+    if !matches!(tokens[j].text, "=" | ":") {
+        return None;
+    }
+    tokens.get(j.checked_sub(1)?).filter(|t| t.is_word()).map(|t| t.text)
+}
+
+/// Python statements whose blocks add to the nesting of what is inside them
+const PYTHON_NESTING: [&str; 7] = ["if", "elif", "else", "for", "while", "except", "match"];
+
+/// An indented Python block
+struct PythonBlock {
+    indent: usize,
+    /// Index in the open functions of the function this block is the body of
+    function: Option<usize>,
+    nesting: bool,
+}
+
+/// Measures the functions of Python source, following its indentation
+fn analyze_python(source: &str) -> Vec<FunctionComplexity> {
+    let mut done = Vec::new();
+    let mut open: Vec<OpenFunction> = Vec::new();
+    let mut blocks: Vec<PythonBlock> = Vec::new();
+    let mut last_code_line = 0;
+    let mut depth = 0i32;
+    let close = |open: &mut Vec<OpenFunction>, done: &mut Vec<FunctionComplexity>, end: usize| {
+        if let Some(f) = open.pop() {
+            done.push(FunctionComplexity {
+                path: String::new(),
+                name: f.name,
+                line: f.line,
+                lines: end - f.line + 1,
+                cyclomatic: (1 + f.decisions).max(1) as u32,
+                cognitive: f.cognitive,
+            });
+        }
+    };
+
+    for (n, line) in source.lines().enumerate() {
+        let number = n + 1;
+        let code = line.trim();
+        if code.is_empty() {
+            continue;
+        }
+        let tokens = tokenize(line);
+        let logical_start = depth == 0;
+        for token in &tokens {
+            match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth = (depth - 1).max(0),
+                _ => {}
+            }
+        }
+        if logical_start {
+            let indent = line.len() - line.trim_start().len();
+            while blocks.last().is_some_and(|b| b.indent >= indent) {
+                if blocks.pop().and_then(|b| b.function).is_some() {
+                    close(&mut open, &mut done, last_code_line);
+                }
+            }
+            let words: Vec<&str> = tokens.iter().map(|t| t.text).collect();
+            let first = words.iter().position(|w| *w != "async").map_or("", |p| words[p]);
+            if first == "def" {
+                if let Some(name) = words.iter().skip_while(|w| **w != "def").nth(1) {
+                    open.push(OpenFunction {
+                        name: name.to_string(),
+                        line: number,
+                        body: 0,
+                        decisions: 0,
+                        cognitive: 0,
+                        last_operator: None,
+                    });
+                }
+            }
+            // Only compound statements ending their line open a block; `if x: return` does not
+            if code.ends_with(':') || first == "def" {
+                blocks.push(PythonBlock {
+                    indent,
+                    function: (first == "def").then(|| open.len() - 1),
+                    nesting: PYTHON_NESTING.contains(&first),
+                });
+            }
+        }
+        last_code_line = number;
+
+        let Some(f) = open.last_mut() else {
+            continue;
+        };
+        let body = blocks.iter().rposition(|b| b.function.is_some()).unwrap_or(0);
+        // The statement's own block does not nest it
+        let own = usize::from(logical_start && blocks.last().is_some_and(|b| b.nesting && b.indent == line.len() - line.trim_start().len()));
+        let nesting = (blocks[body..].iter().filter(|b| b.nesting).count() - own) as u32;
+        f.last_operator = None;
+        for (position, token) in tokens.iter().enumerate() {
+            let statement = logical_start && position == 0;
+            match token.text {
+                "if" | "while" | "except" => {
+                    f.decisions += 1;
+                    f.cognitive += 1 + nesting;
+                }
+                "for" => {
+                    f.decisions += 1;
+                    f.cognitive += if statement { 1 + nesting } else { 0 };
+                }
+                "elif" => {
+                    f.decisions += 1;
+                    f.cognitive += 1;
+                }
+                "else" if statement => f.cognitive += 1,
+                "match" if statement => f.cognitive += 1 + nesting,
+                "case" if statement => f.decisions += 1,
+                "and" | "or" => {
+                    f.decisions += 1;
+                    let operator = if token.text == "and" { "&&" } else { "||" };
+                    if f.last_operator != Some(operator) {
+                        f.cognitive += 1;
+                    }
+                    f.last_operator = Some(operator);
+                }
+                _ => {}
+            }
+        }
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut done, last_code_line);
+    }
+    done.sort_by_key(|f| f.line);
+    done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(path: &str, source: &str) -> Vec<(String, u32, u32)> {
+        analyze(path, source).into_iter().map(|f| (f.name, f.cyclomatic, f.cognitive)).collect()
+    }
+
+    #[test]
+    fn test_functions_are_measured_in_each_language() {
+        let rust = r#"
+/// Parses "if x { }" // not code
+fn parse<'a>(input: &'a str, strict: bool) -> Result<Token<'a>> {
+    let quote = '"';
+    for c in input.chars() {
+        if c == quote && strict || c == '{' {
+            return Err(error());
+        } else if c.is_digit(10) {
+            continue;
+        }
+    }
+    match input.len() {
+        0 => Ok(Token::Empty),
+        1 => Ok(Token::One),
+        _ => Ok(Token::Many),
+    }
+}
+
+fn simple() -> u8 { 1 }
+"#;
+        // Cyclomatic: for, if, &&, ||, else if and three match arms less one;
+        // cognitive: for 1, nested if 2, && and || 2, else 1, match 1
+        assert_eq!(measured("src/lib.rs", rust), [("parse".to_string(), 8, 7), ("simple".to_string(), 1, 0)]);
+
+        let python = r#"
+def handle(event, retries=3):
+    """Handles "event" if it can."""
+    if not event:
+        return None
+    for attempt in range(retries):
+        try:
+            if event.ready and event.valid:
+                return event
+        except Timeout:
+            pass
+    return [e for e in event.children if e]
+
+class Queue:
+    def push(self, item):
+        self.items.append(item)
+"#;
+        // Cyclomatic: if, for, if, and, except, and the comprehension's for and if;
+        // cognitive: if 1, for 1, if in for 2, and 1, except in for 2, comprehension if 1
+        assert_eq!(measured("app/events.py", python), [("handle".to_string(), 8, 8), ("push".to_string(), 1, 0)]);
+
+        let javascript = r#"
+const load = async (url) => {
+  const res = await fetch(url ?? "/default");
+  return res.ok ? res.json() : null;
+};
+class Store {
+  get(key) {
+    if (!this.items[key]) { throw new Error(`missing ${key}`); }
+    return this.items[key];
+  }
+}
+function main() { items.forEach(item => { if (item) save(item); }); }
+"#;
+        assert_eq!(measured("web/store.ts", javascript), [
+            ("load".to_string(), 3, 2),
+            ("get".to_string(), 2, 1),
+            ("main".to_string(), 2, 1),
+        ]);
+
+        let java = "public class Parser {\n  public int parse(String s) throws IOException {\n    switch (s) {\n      case \"a\": return 1;\n      case \"b\": return 2;\n      default: return s.isEmpty() ? 0 : -1;\n    }\n  }\n  abstract void skip();\n}\n";
+        assert_eq!(measured("src/Parser.java", java), [("parse".to_string(), 4, 3)]);
+        assert!(analyze("README.md", "if (x) { }").is_empty());
+    }
+
+    #[test]
+    fn test_score_weights_function_lines_by_risk() {
+        let function = |name: &str, cyclomatic: u32, lines: usize| FunctionComplexity {
+            path: "src/lib.rs".to_string(),
+            name: name.to_string(),
+            line: 1,
+            lines,
+            cyclomatic,
+            cognitive: cyclomatic,
+        };
+        let mut report = ComplexityReport::from_functions(vec![function("simple", 1, 30), function("tangled", 25, 10)], 1);
+        // A quarter of the lines are in a high-risk function
+        assert_eq!(report.score, 18.75);
+        assert_eq!((report.functions, report.max_cyclomatic, report.average_cyclomatic), (2, 25, 13.0));
+        assert_eq!(report.hotspots.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["tangled"]);
+
+        report.merge(&ComplexityReport::from_functions(vec![function("huge", 60, 40)], 1));
+        assert_eq!(report.score, 59.375);
+        assert_eq!(report.hotspots[0].name, "huge");
+        assert!(report.to_markdown().contains("| `huge` | `src/lib.rs:1` | 60 | 60 | 40 |"));
+    }
+}
@@ -0,0 +1,278 @@
+//! Duplicated code across source files
+//!
+//! Each file is reduced to its tokens, leaving out whitespace, comments and
+//! the contents of string literals, and every run of [`MIN_TOKENS`] tokens is
+//! fingerprinted with a rolling hash. A run whose fingerprint was seen
+//! before, in the same file or another, starts a duplicated block, which
+//! grows for as long as the tokens keep matching. Files are read in the
+//! languages [`super::complexity`] supports.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use super::complexity;
+
+/// Shortest run of tokens counted as duplicated
+pub const MIN_TOKENS: usize = 50;
+
+/// File pairs listed in reports
+pub const WORST_PAIRS: usize = 10;
+
+/// Multiplier of the rolling hash
+const BASE: u64 = 0x0100_0000_01b3;
+
+/// Code two files share
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicatePair {
+    /// One file, the first in path order
+    pub first: String,
+    /// The other file; the same as `first` for code repeated within a file
+    pub second: String,
+    /// Duplicated blocks the files share
+    pub blocks: usize,
+    /// Lines of `first` in those blocks
+    pub lines: usize,
+    /// Line the longest block starts on in `first`
+    pub first_line: usize,
+    /// Line the longest block starts on in `second`
+    pub second_line: usize,
+}
+
+/// Duplicated code in a repository
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DuplicationReport {
+    /// Source files scanned
+    pub files: usize,
+    /// Lines of code scanned, leaving out blank and comment lines
+    pub lines: usize,
+    /// Lines in duplicated blocks, counting every copy
+    pub duplicated_lines: usize,
+    /// Share of lines in duplicated blocks, from 0 to 100
+    pub percentage: f32,
+    /// Duplicated blocks found
+    pub blocks: usize,
+    /// File pairs sharing the most lines, most first
+    pub pairs: Vec<DuplicatePair>,
+}
+
+/// A file reduced to its tokens
+struct Scanned {
+    path: String,
+    tokens: Vec<String>,
+    /// Line of each token
+    lines: Vec<usize>,
+}
+
+impl Scanned {
+    /// Lines holding the tokens in `range`
+    fn lines_of(&self, range: std::ops::Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        self.lines[range].iter().copied()
+    }
+}
+
+impl DuplicationReport {
+    /// Finds the code duplicated in and across each `(path, content)` source
+    pub fn from_sources<'a>(sources: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        Self::scan(sources).0
+    }
+
+    /// Lines in duplicated blocks of each `(path, content)` source with any, by path
+    pub fn lines_by_file<'a>(sources: impl IntoIterator<Item = (&'a str, &'a str)>) -> BTreeMap<String, usize> {
+        Self::scan(sources).1
+    }
+
+    /// The report, and the duplicated lines of each file with any
+    fn scan<'a>(sources: impl IntoIterator<Item = (&'a str, &'a str)>) -> (Self, BTreeMap<String, usize>) {
+        let files: Vec<Scanned> = sources.into_iter()
+            .filter_map(|(path, content)| {
+                let (tokens, lines) = complexity::source_tokens(path, content)?.into_iter().unzip();
+                Some(Scanned { path: path.to_string(), tokens, lines })
+            })
+            .collect();
+        let mut duplicated: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); files.len()];
+        // Pairs by file indexes, with the length of their longest block
+        let mut pairs: HashMap<(usize, usize), (DuplicatePair, usize)> = HashMap::new();
+        let mut blocks = 0;
+        // Where each fingerprint was first seen
+        let mut seen: HashMap<u64, (usize, usize)> = HashMap::new();
+
+        for (f, file) in files.iter().enumerate() {
+            let hashes = window_hashes(&file.tokens);
+            let mut s = 0;
+            while s < hashes.len() {
+                let earlier = seen.get(&hashes[s]).copied().filter(|&(g, t)| {
+                    // A run does not duplicate one it overlaps
+                    (g != f || t + MIN_TOKENS <= s)
+                        && files[g].tokens[t..t + MIN_TOKENS] == file.tokens[s..s + MIN_TOKENS]
+                });
+                seen.entry(hashes[s]).or_insert((f, s));
+                let Some((g, t)) = earlier else {
+                    s += 1;
+                    continue;
+                };
+                let other = &files[g];
+                let mut len = MIN_TOKENS;
+                while s + len < file.tokens.len()
+                    && t + len < other.tokens.len()
+                    && (g != f || t + len < s)
+                    && file.tokens[s + len] == other.tokens[t + len]
+                {
+                    len += 1;
+                }
+                blocks += 1;
+                duplicated[f].extend(file.lines_of(s..s + len));
+                duplicated[g].extend(other.lines_of(t..t + len));
+
+                // Later copies of the block find it through any of its runs
+                for k in s + 1..(s + len).min(hashes.len()) {
+                    seen.entry(hashes[k]).or_insert((f, k));
+                }
+
+                let ((a, a_start), (b, b_start)) = if (&other.path, t) <= (&file.path, s) { ((g, t), (f, s)) } else { ((f, s), (g, t)) };
+                let (pair, longest) = pairs.entry((a, b)).or_insert_with(|| (DuplicatePair {
+                    first: files[a].path.clone(),
+                    second: files[b].path.clone(),
+                    blocks: 0,
+                    lines: 0,
+                    first_line: 0,
+                    second_line: 0,
+                }, 0));
+                pair.blocks += 1;
+                pair.lines += files[a].lines_of(a_start..a_start + len).collect::<BTreeSet<_>>().len();
+                if len > *longest {
+                    *longest = len;
+                    pair.first_line = files[a].lines[a_start];
+                    pair.second_line = files[b].lines[b_start];
+                }
+                s += len;
+            }
+        }
+
+        let mut report = Self {
+            files: files.len(),
+            lines: files.iter().map(|file| file.lines.iter().collect::<BTreeSet<_>>().len()).sum(),
+            duplicated_lines: duplicated.iter().map(BTreeSet::len).sum(),
+            blocks,
+            pairs: pairs.into_values().map(|(pair, _)| pair).collect(),
+            ..Self::default()
+        };
+        report.rank(WORST_PAIRS);
+        let by_file = files.iter().zip(&duplicated)
+            .filter(|(_, lines)| !lines.is_empty())
+            .map(|(file, lines)| (file.path.clone(), lines.len()))
+            .collect();
+        (report, by_file)
+    }
+
+    /// Adds the files `other` scanned; code shared between the two sets is not found
+    pub fn merge(&mut self, other: &DuplicationReport) {
+        let top = self.pairs.len().max(other.pairs.len());
+        self.files += other.files;
+        self.lines += other.lines;
+        self.duplicated_lines += other.duplicated_lines;
+        self.blocks += other.blocks;
+        self.pairs.extend(other.pairs.iter().cloned());
+        self.rank(top);
+    }
+
+    /// "Duplication" report section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Duplication\n\n");
+        out.push_str(&format!(
+            "**Duplicated:** {:.1}% of {} lines ({} lines in {} blocks across {} files)\n\n",
+            self.percentage, self.lines, self.duplicated_lines, self.blocks, self.files
+        ));
+        if !self.pairs.is_empty() {
+            out.push_str("### Most Duplicated Files\n\n");
+            out.push_str("| Files | Blocks | Lines |\n");
+            out.push_str("|-------|--------|-------|\n");
+            for pair in &self.pairs {
+                out.push_str(&format!(
+                    "| `{}:{}` and `{}:{}` | {} | {} |\n",
+                    pair.first, pair.first_line, pair.second, pair.second_line, pair.blocks, pair.lines
+                ));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Derives the percentage, and keeps the `top` pairs sharing the most lines
+    fn rank(&mut self, top: usize) {
+        self.percentage = if self.lines == 0 { 0.0 } else { (100.0 * self.duplicated_lines as f64 / self.lines as f64) as f32 };
+        self.pairs.sort_by(|a, b| {
+            b.lines.cmp(&a.lines)
+                .then(b.blocks.cmp(&a.blocks))
+                .then_with(|| (&a.first, &a.second).cmp(&(&b.first, &b.second)))
+        });
+        self.pairs.truncate(top);
+    }
+}
+
+/// Fingerprint of each run of [`MIN_TOKENS`] tokens, by where it starts
+fn window_hashes(tokens: &[String]) -> Vec<u64> {
+    if tokens.len() < MIN_TOKENS {
+        return Vec::new();
+    }
+    let token_hashes: Vec<u64> = tokens.iter()
+        .map(|token| {
+            let mut hasher = DefaultHasher::new();
+            token.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    // BASE to the power of the run length, less one: the weight of a run's first token
+    let first_weight = (1..MIN_TOKENS).fold(1u64, |weight, _| weight.wrapping_mul(BASE));
+    let mut hash = token_hashes[..MIN_TOKENS].iter().fold(0u64, |hash, &h| hash.wrapping_mul(BASE).wrapping_add(h));
+    let mut hashes = vec![hash];
+    for s in 1..=tokens.len() - MIN_TOKENS {
+        hash = hash.wrapping_sub(token_hashes[s - 1].wrapping_mul(first_weight))
+            .wrapping_mul(BASE)
+            .wrapping_add(token_hashes[s + MIN_TOKENS - 1]);
+        hashes.push(hash);
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copied_code_is_found_within_and_across_files() {
+        let function = |name: &str| format!(
+            "fn {}(items: &[Item], limit: usize) -> Vec<String> {{\n    let mut out = Vec::new();\n    \
+             for item in items.iter().filter(|item| item.enabled) {{\n        if out.len() >= limit {{\n            break;\n        }}\n        \
+             out.push(format!(\"{{}}: {{}}\", item.name, item.value));\n    }}\n    out.sort();\n    out.dedup();\n    out\n}}\n",
+            name
+        );
+        let original = format!("use crate::Item;\n\n{}", function("labels"));
+        // The same code under another name, with a comment and other strings
+        let copy = format!("// Copied from labels\nuse crate::Item;\n\n{}", function("names").replace("{}: {}", "{} = {}"));
+        // A different function, twice in one file
+        let variant = |name: &str| function(name).replace("Vec::new()", "Vec::with_capacity(limit)").replace("break;", "return out;");
+        let repeated = format!("{}\n{}", variant("first"), variant("second"));
+        let unique = "fn main() {\n    println!(\"hello\");\n}\n";
+
+        let report = DuplicationReport::from_sources([
+            ("src/labels.rs", original.as_str()),
+            ("src/names.rs", copy.as_str()),
+            ("src/twice.rs", repeated.as_str()),
+            ("src/main.rs", unique),
+            ("README.md", original.as_str()),
+        ]);
+        // Blank and comment lines are not counted
+        assert_eq!((report.files, report.lines), (4, 13 + 13 + 24 + 3));
+        // Each copy is duplicated from its parameter list on, all 12 lines of the function
+        assert_eq!((report.duplicated_lines, report.blocks), (12 * 4, 2));
+        assert_eq!(report.pairs.iter().map(|p| (p.first.as_str(), p.second.as_str(), p.lines)).collect::<Vec<_>>(), [
+            ("src/labels.rs", "src/names.rs", 12),
+            ("src/twice.rs", "src/twice.rs", 12),
+        ]);
+        assert_eq!((report.pairs[0].first_line, report.pairs[0].second_line), (3, 4));
+        assert_eq!((report.pairs[1].first_line, report.pairs[1].second_line), (1, 14));
+        assert!(report.to_markdown().contains("| `src/labels.rs:3` and `src/names.rs:4` | 1 | 12 |"));
+        assert!(DuplicationReport::from_sources([("src/main.rs", unique)]).pairs.is_empty());
+    }
+}
@@ -0,0 +1,455 @@
+//! How far declared dependencies lag their latest releases
+//!
+//! Dependencies are read from the manifests at a repository's root, the ones
+//! [`crate::run_diff`] compares between runs. The version in use comes from
+//! `Cargo.lock` or `package-lock.json` when they resolve it, and otherwise is
+//! the lowest one the declared requirement allows. Each is compared with the
+//! latest release on crates.io, npm, PyPI or the Go module proxy, fetched
+//! through the shared cache. Dependencies whose registry cannot be reached,
+//! or declared without a version, are listed but not scored.
+
+use crate::http;
+use crate::processors::common;
+use crate::run_diff::{manifest_dependencies, DEPENDENCY_MANIFESTS};
+use futures::stream::{self, StreamExt};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Client;
+use semver::{Prerelease, Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+use tracing::debug;
+
+/// Registry lookups in flight at once
+pub const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
+/// First version number in a requirement, such as `1.2` in `>=1.2, <2`
+static VERSION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(\d+)(?:\.(\d+))?(?:\.(\d+))?(?:-([0-9A-Za-z.-]+))?").unwrap()
+});
+
+/// Registry a dependency is published on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ecosystem {
+    /// crates.io, for `Cargo.toml`
+    Crates,
+    /// The npm registry, for `package.json`
+    Npm,
+    /// PyPI, for `requirements.txt`
+    Pypi,
+    /// The Go module proxy, for `go.mod`
+    Go,
+}
+
+impl Ecosystem {
+    /// Ecosystem of the dependencies a manifest declares
+    fn of_manifest(manifest: &str) -> Option<Self> {
+        match manifest {
+            "Cargo.toml" => Some(Self::Crates),
+            "package.json" => Some(Self::Npm),
+            "requirements.txt" => Some(Self::Pypi),
+            "go.mod" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    /// Short name, as used by the registry processors' caches
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Crates => "crates",
+            Self::Npm => "npm",
+            Self::Pypi => "pypi",
+            Self::Go => "go",
+        }
+    }
+}
+
+/// How far a dependency is behind the latest release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Staleness {
+    /// On the latest release
+    Current,
+    /// Behind by patch releases
+    Patch,
+    /// Behind by minor releases
+    Minor,
+    /// Behind by at least one major release; minor releases of 0.x count as major
+    Major,
+    /// Not compared: no version declared, or the registry did not answer
+    Unknown,
+}
+
+/// One declared dependency compared with its registry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyFreshness {
+    /// Package name on the registry
+    pub name: String,
+    /// Registry the package is published on
+    pub ecosystem: Ecosystem,
+    /// Requirement as declared in the manifest
+    pub declared: String,
+    /// Version in use: the locked one, or the lowest the requirement allows
+    pub current: Option<String>,
+    /// Latest release on the registry
+    pub latest: Option<String>,
+    /// How far the version in use is behind the latest
+    pub staleness: Staleness,
+    /// Major releases between the version in use and the latest
+    pub majors_behind: u64,
+}
+
+impl DependencyFreshness {
+    /// Freshness from 0 to 1, or `None` for dependencies not compared
+    pub fn freshness(&self) -> Option<f64> {
+        match self.staleness {
+            Staleness::Current => Some(1.0),
+            Staleness::Patch => Some(0.9),
+            Staleness::Minor => Some(0.7),
+            Staleness::Major if self.majors_behind == 1 => Some(0.3),
+            Staleness::Major => Some(0.0),
+            Staleness::Unknown => None,
+        }
+    }
+}
+
+/// Freshness of a repository's declared dependencies
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FreshnessReport {
+    /// Mean freshness of the dependencies compared, from 0 to 100
+    pub score: f32,
+    /// Every dependency declared, stalest first
+    pub dependencies: Vec<DependencyFreshness>,
+    /// Dependencies compared with their registry
+    pub compared: usize,
+    /// Names of the dependencies behind by a major release
+    pub majorly_outdated: Vec<String>,
+}
+
+impl FreshnessReport {
+    /// Scores `dependencies`, compared or not
+    pub fn from_dependencies(dependencies: Vec<DependencyFreshness>) -> Self {
+        let mut report = Self { dependencies, ..Self::default() };
+        report.rank();
+        report
+    }
+
+    /// Adds the dependencies `other` compared
+    pub fn merge(&mut self, other: &FreshnessReport) {
+        self.dependencies.extend(other.dependencies.iter().cloned());
+        self.rank();
+    }
+
+    /// "Dependency Freshness" report section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Dependency Freshness\n\n");
+        out.push_str(&format!(
+            "**Score:** {:.0}/100 across {} of {} dependencies ({} majorly outdated)\n\n",
+            self.score, self.compared, self.dependencies.len(), self.majorly_outdated.len()
+        ));
+        if !self.majorly_outdated.is_empty() {
+            out.push_str("### Majorly Outdated\n\n");
+            out.push_str("| Dependency | Registry | In Use | Latest | Majors Behind |\n");
+            out.push_str("|------------|----------|--------|--------|---------------|\n");
+            for dependency in self.dependencies.iter().filter(|d| d.staleness == Staleness::Major) {
+                out.push_str(&format!(
+                    "| `{}` | {} | {} | {} | {} |\n",
+                    dependency.name,
+                    dependency.ecosystem.name(),
+                    dependency.current.as_deref().unwrap_or("?"),
+                    dependency.latest.as_deref().unwrap_or("?"),
+                    dependency.majors_behind
+                ));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Derives the score and counts, and sorts the stalest dependencies first
+    fn rank(&mut self) {
+        self.dependencies.sort_by(|a, b| {
+            let freshness = |d: &DependencyFreshness| d.freshness().unwrap_or(f64::INFINITY);
+            freshness(a).total_cmp(&freshness(b))
+                .then(b.majors_behind.cmp(&a.majors_behind))
+                .then_with(|| (a.ecosystem, &a.name).cmp(&(b.ecosystem, &b.name)))
+        });
+        let scores: Vec<f64> = self.dependencies.iter().filter_map(DependencyFreshness::freshness).collect();
+        self.compared = scores.len();
+        self.score = if scores.is_empty() { 100.0 } else { (100.0 * scores.iter().sum::<f64>() / scores.len() as f64) as f32 };
+        self.majorly_outdated = self.dependencies.iter()
+            .filter(|d| d.staleness == Staleness::Major)
+            .map(|d| d.name.clone())
+            .collect();
+    }
+}
+
+/// Compares a repository's dependencies with their latest releases
+pub struct FreshnessChecker {
+    client: Client,
+    /// Base URLs of crates.io, the npm registry, PyPI and the Go module proxy
+    registries: [String; 4],
+}
+
+impl Default for FreshnessChecker {
+    fn default() -> Self {
+        Self::with_registries("https://crates.io", "https://registry.npmjs.org", "https://pypi.org", "https://proxy.golang.org")
+    }
+}
+
+impl FreshnessChecker {
+    /// A checker against the given crates.io, npm, PyPI and Go proxy servers
+    pub fn with_registries(crates: &str, npm: &str, pypi: &str, go: &str) -> Self {
+        let client = http::client_builder()
+            .timeout(Duration::from_secs(15))
+            .user_agent(concat!("llamapackageservice/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Self { client, registries: [crates, npm, pypi, go].map(|url| url.trim_end_matches('/').to_string()) }
+    }
+
+    /// Compares every dependency declared at `root` with its registry
+    pub async fn check_repository(&self, root: &Path) -> FreshnessReport {
+        let lookups: Vec<_> = declared_dependencies(root).into_iter()
+            .map(|dependency| self.compare(dependency))
+            .collect();
+        let dependencies = stream::iter(lookups).buffer_unordered(MAX_CONCURRENT_LOOKUPS).collect().await;
+        FreshnessReport::from_dependencies(dependencies)
+    }
+
+    /// Fills in the latest release of `dependency` and how far behind it is
+    async fn compare(&self, mut dependency: DependencyFreshness) -> DependencyFreshness {
+        let Some(current) = dependency.current.as_deref().and_then(parse_version) else {
+            return dependency;
+        };
+        let Some(latest) = self.latest(dependency.ecosystem, &dependency.name).await else {
+            return dependency;
+        };
+        (dependency.staleness, dependency.majors_behind) = staleness(&current, &latest);
+        dependency.latest = Some(latest.to_string());
+        dependency
+    }
+
+    /// Latest release of a package, or `None` when the registry does not say
+    async fn latest(&self, ecosystem: Ecosystem, name: &str) -> Option<Version> {
+        let [crates, npm, pypi, go] = &self.registries;
+        let url = match ecosystem {
+            Ecosystem::Crates => format!("{}/api/v1/crates/{}", crates, name),
+            Ecosystem::Npm => format!("{}/{}", npm, name.replace('/', "%2f")),
+            Ecosystem::Pypi => format!("{}/pypi/{}/json", pypi, name),
+            Ecosystem::Go => format!("{}/{}/@latest", go, escape_module(name)),
+        };
+        let package = match common::fetch_registry_json(&self.client, ecosystem.name(), name, &url).await {
+            Ok(package) => package,
+            Err(e) => {
+                debug!("No latest version of {} {}: {}", ecosystem.name(), name, e);
+                return None;
+            }
+        };
+        let latest = match ecosystem {
+            Ecosystem::Crates => package["crate"]["max_stable_version"].as_str()
+                .or_else(|| package["crate"]["max_version"].as_str()),
+            Ecosystem::Npm => package["dist-tags"]["latest"].as_str(),
+            Ecosystem::Pypi => package["info"]["version"].as_str(),
+            Ecosystem::Go => package["Version"].as_str(),
+        };
+        latest.and_then(parse_version)
+    }
+}
+
+/// Dependencies declared by the manifests at `root`, with the versions in use
+pub fn declared_dependencies(root: &Path) -> Vec<DependencyFreshness> {
+    let locked = locked_versions(root);
+    let mut dependencies = Vec::new();
+    for manifest in DEPENDENCY_MANIFESTS {
+        let (Some(ecosystem), Ok(content)) = (Ecosystem::of_manifest(manifest), std::fs::read_to_string(root.join(manifest))) else {
+            continue;
+        };
+        let mut declared = BTreeMap::new();
+        manifest_dependencies(manifest, &content, &mut declared);
+        for (name, spec) in declared {
+            let current = current_version(&spec, locked.get(&(ecosystem, name.clone())).map(Vec::as_slice).unwrap_or_default());
+            dependencies.push(DependencyFreshness {
+                name,
+                ecosystem,
+                declared: spec,
+                current: current.map(|version| version.to_string()),
+                latest: None,
+                staleness: Staleness::Unknown,
+                majors_behind: 0,
+            });
+        }
+    }
+    dependencies
+}
+
+/// Version in use for a requirement: the highest locked one it allows, or its lowest
+fn current_version(spec: &str, locked: &[Version]) -> Option<Version> {
+    let requirement = VersionReq::parse(spec).ok();
+    let allowed = locked.iter()
+        .filter(|version| requirement.as_ref().map_or(true, |req| req.matches(version)))
+        .max();
+    if let Some(version) = allowed.or_else(|| locked.iter().max()) {
+        return Some(version.clone());
+    }
+    // Git, path and URL sources have no registry version
+    if spec.contains(':') || spec.contains('/') {
+        return None;
+    }
+    parse_version(spec)
+}
+
+/// Versions resolved by `Cargo.lock` and `package-lock.json`, by ecosystem and name
+fn locked_versions(root: &Path) -> HashMap<(Ecosystem, String), Vec<Version>> {
+    let mut locked: HashMap<(Ecosystem, String), Vec<Version>> = HashMap::new();
+    let mut add = |ecosystem, name: &str, version: Option<&str>| {
+        if let Some(version) = version.and_then(|v| Version::parse(v).ok()) {
+            locked.entry((ecosystem, name.to_string())).or_default().push(version);
+        }
+    };
+
+    let cargo_lock = std::fs::read_to_string(root.join("Cargo.lock")).ok()
+        .and_then(|content| content.parse::<toml::Value>().ok());
+    if let Some(packages) = cargo_lock.as_ref().and_then(|lock| lock.get("package")).and_then(|p| p.as_array()) {
+        for package in packages {
+            if let Some(name) = package.get("name").and_then(|n| n.as_str()) {
+                add(Ecosystem::Crates, name, package.get("version").and_then(|v| v.as_str()));
+            }
+        }
+    }
+
+    let npm_lock = std::fs::read_to_string(root.join("package-lock.json")).ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok());
+    if let Some(lock) = npm_lock {
+        // Lockfile v2 and v3 key top-level packages by their node_modules path; v1 by name
+        if let Some(packages) = lock["packages"].as_object() {
+            for (path, package) in packages {
+                if let Some(name) = path.strip_prefix("node_modules/").filter(|name| !name.contains("/node_modules/")) {
+                    add(Ecosystem::Npm, name, package["version"].as_str());
+                }
+            }
+        } else if let Some(packages) = lock["dependencies"].as_object() {
+            for (name, package) in packages {
+                add(Ecosystem::Npm, name, package["version"].as_str());
+            }
+        }
+    }
+    locked
+}
+
+/// Reads the first version number in `text`, padding missing parts with zeros
+fn parse_version(text: &str) -> Option<Version> {
+    let captures = VERSION.captures(text)?;
+    let part = |i: usize| captures.get(i).map_or(Some(0), |m| m.as_str().parse::<u64>().ok());
+    let mut version = Version::new(part(1)?, part(2)?, part(3)?);
+    if let Some(pre) = captures.get(4) {
+        version.pre = Prerelease::new(pre.as_str()).unwrap_or(Prerelease::EMPTY);
+    }
+    Some(version)
+}
+
+/// How far `current` is behind `latest`, with the major releases between them
+fn staleness(current: &Version, latest: &Version) -> (Staleness, u64) {
+    if current >= latest {
+        (Staleness::Current, 0)
+    } else if latest.major > current.major {
+        (Staleness::Major, latest.major - current.major)
+    } else if current.major == 0 && latest.minor > current.minor {
+        // Before 1.0 a minor release may break compatibility
+        (Staleness::Major, latest.minor - current.minor)
+    } else if latest.minor > current.minor {
+        (Staleness::Minor, 0)
+    } else {
+        (Staleness::Patch, 0)
+    }
+}
+
+/// Escapes a module path for the Go proxy, which marks capitals with `!`
+fn escape_module(module: &str) -> String {
+    module.chars()
+        .flat_map(|c| if c.is_ascii_uppercase() { vec!['!', c.to_ascii_lowercase()] } else { vec![c] })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_dependencies_are_scored_against_their_latest_release() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), r#"
+[package]
+name = "demo"
+
+[dependencies]
+serde = "1.0"
+reqwest = { version = "0.11", features = ["json"] }
+local = { path = "../local" }
+"#).unwrap();
+        // The lockfile resolves serde past its requirement
+        std::fs::write(dir.path().join("Cargo.lock"), r#"
+[[package]]
+name = "serde"
+version = "1.0.200"
+
+[[package]]
+name = "reqwest"
+version = "0.11.27"
+"#).unwrap();
+        std::fs::write(dir.path().join("package.json"), r#"{"dependencies": {"@types/node": "^16.4.0", "left-pad": "1.3.0"}}"#).unwrap();
+        std::fs::write(dir.path().join("requirements.txt"), "requests==2.31.0\nflask>=1.1\n").unwrap();
+        std::fs::write(dir.path().join("go.mod"), "module demo\n\nrequire github.com/BurntSushi/toml v1.2.1\n").unwrap();
+
+        let mut crates = mockito::Server::new_async().await;
+        let mut npm = mockito::Server::new_async().await;
+        let mut pypi = mockito::Server::new_async().await;
+        let mut go = mockito::Server::new_async().await;
+        let _serde = crates.mock("GET", "/api/v1/crates/serde")
+            .with_body(r#"{"crate": {"max_stable_version": "1.0.200", "max_version": "1.0.200"}}"#)
+            .create_async().await;
+        let _reqwest = crates.mock("GET", "/api/v1/crates/reqwest")
+            .with_body(r#"{"crate": {"max_stable_version": "0.12.4", "max_version": "0.13.0-alpha.1"}}"#)
+            .create_async().await;
+        let _types = npm.mock("GET", "/@types%2fnode")
+            .with_body(r#"{"dist-tags": {"latest": "20.11.5"}}"#)
+            .create_async().await;
+        let _left_pad = npm.mock("GET", "/left-pad").with_status(404).create_async().await;
+        let _requests = pypi.mock("GET", "/pypi/requests/json")
+            .with_body(r#"{"info": {"version": "2.31.1"}}"#)
+            .create_async().await;
+        let _flask = pypi.mock("GET", "/pypi/flask/json")
+            .with_body(r#"{"info": {"version": "1.4.0"}}"#)
+            .create_async().await;
+        let _toml = go.mock("GET", "/github.com/!burnt!sushi/toml/@latest")
+            .with_body(r#"{"Version": "v1.3.2"}"#)
+            .create_async().await;
+
+        let checker = FreshnessChecker::with_registries(&crates.url(), &npm.url(), &pypi.url(), &go.url());
+        let report = checker.check_repository(dir.path()).await;
+
+        let summary: Vec<_> = report.dependencies.iter()
+            .map(|d| (d.name.as_str(), d.current.as_deref(), d.latest.as_deref(), d.staleness, d.majors_behind))
+            .collect();
+        assert_eq!(summary, [
+            ("@types/node", Some("16.4.0"), Some("20.11.5"), Staleness::Major, 4),
+            ("reqwest", Some("0.11.27"), Some("0.12.4"), Staleness::Major, 1),
+            ("flask", Some("1.1.0"), Some("1.4.0"), Staleness::Minor, 0),
+            ("github.com/BurntSushi/toml", Some("1.2.1"), Some("1.3.2"), Staleness::Minor, 0),
+            ("requests", Some("2.31.0"), Some("2.31.1"), Staleness::Patch, 0),
+            ("serde", Some("1.0.200"), Some("1.0.200"), Staleness::Current, 0),
+            // Path dependencies have no version; left-pad's registry had no answer
+            ("local", None, None, Staleness::Unknown, 0),
+            ("left-pad", Some("1.3.0"), None, Staleness::Unknown, 0),
+        ]);
+        // (0 + 0.3 + 0.7 + 0.7 + 0.9 + 1) / 6
+        assert_eq!(report.compared, 6);
+        assert_eq!(format!("{:.1}", report.score), "60.0");
+        assert_eq!(report.majorly_outdated, ["@types/node", "reqwest"]);
+        assert!(report.to_markdown().contains("| `reqwest` | crates | 0.11.27 | 0.12.4 | 1 |"));
+    }
+}
@@ -0,0 +1,181 @@
+//! Metrics of every run of a target, and how they trend
+//!
+//! [`MetricsHistory`] keeps the [`RepositoryMetrics`] of each run in a SQLite
+//! database, `<output>/.metrics.sqlite`, keyed by the target they describe.
+//! With two or more runs recorded, [`trend_section`] summarizes how the size
+//! and complexity of the target moved since the last run and since the
+//! first one.
+
+use super::RepositoryMetrics;
+use crate::database::Database;
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use std::path::Path;
+
+/// File name of the metrics database inside the output directory
+pub const METRICS_DB_FILE: &str = ".metrics.sqlite";
+
+/// Changes smaller than this, in percent or score points, are reported as stable
+pub const STABLE_WITHIN: f64 = 1.0;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        target TEXT NOT NULL,
+        recorded_at TEXT NOT NULL,
+        metrics TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_by_target ON runs (target, id);
+";
+
+/// SQLite-backed store of the metrics of every run
+pub struct MetricsHistory {
+    db: Database,
+}
+
+impl MetricsHistory {
+    /// Opens or creates the store at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = Database::open(path, SCHEMA)?;
+        Ok(Self { db })
+    }
+
+    /// Adds the metrics of a run of the target they are named after
+    pub fn record(&self, metrics: &RepositoryMetrics) -> Result<()> {
+        let recorded_at = metrics.timestamp.as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map_or_else(Utc::now, |t| t.with_timezone(&Utc));
+        self.db.lock().execute(
+            "INSERT INTO runs (target, recorded_at, metrics) VALUES (?1, ?2, ?3)",
+            params![metrics.name, recorded_at, serde_json::to_string(metrics)?],
+        )?;
+        Ok(())
+    }
+
+    /// The last `limit` runs of `target`, oldest first
+    pub fn runs(&self, target: &str, limit: usize) -> Result<Vec<RepositoryMetrics>> {
+        let conn = self.db.lock();
+        let mut statement = conn
+            .prepare("SELECT metrics FROM runs WHERE target = ?1 ORDER BY id DESC LIMIT ?2")?;
+        let rows = statement
+            .query_map(params![target, limit as i64], |row| row.get::<_, String>(0))?;
+        let mut runs = Vec::new();
+        for row in rows {
+            runs.push(serde_json::from_str(&row?)?);
+        }
+        runs.reverse();
+        Ok(runs)
+    }
+
+    /// The last `limit` runs of a target named as it was processed, or for GitHub as `owner/repo`
+    pub fn find_runs(&self, target: &str, limit: usize) -> Result<Vec<RepositoryMetrics>> {
+        for name in super::compare::names_for(target) {
+            let runs = self.runs(&name, limit)?;
+            if !runs.is_empty() {
+                return Ok(runs);
+            }
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// "Trends" report section for runs of one target, oldest first
+///
+/// Returns `None` until there are two runs to compare.
+pub fn trend_section(runs: &[RepositoryMetrics]) -> Option<String> {
+    let [.., previous, current] = runs else {
+        return None;
+    };
+    let first = &runs[0];
+    let since = first.timestamp.as_deref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.format("%Y-%m-%d").to_string());
+    let mut lines = Vec::new();
+    let mut trend = |label: &str, value: String, change: &dyn Fn(&RepositoryMetrics) -> Option<String>| {
+        let Some(last) = change(previous) else { return };
+        let mut text = format!("- **{}:** {} ({} since the last run", label, value, last);
+        if runs.len() > 2 {
+            if let (Some(overall), Some(since)) = (change(first), &since) {
+                text.push_str(&format!(", {} over {} runs since {}", overall, runs.len(), since));
+            }
+        }
+        text.push(')');
+        lines.push(text);
+    };
+
+    trend("Lines", current.total_lines.to_string(), &|before| Some(relative(before.total_lines as f64, current.total_lines as f64)));
+    trend("Files", current.files_processed.to_string(), &|before| Some(relative(before.files_processed as f64, current.files_processed as f64)));
+    if let Some(complexity) = &current.complexity {
+        trend("Functions", complexity.functions.to_string(), &|before| {
+            Some(relative(before.complexity.as_ref()?.functions as f64, complexity.functions as f64))
+        });
+        trend("Complexity", format!("{:.0}/100", complexity.score), &|before| {
+            Some(points(f64::from(before.complexity.as_ref()?.score), f64::from(complexity.score)))
+        });
+    }
+    if let Some(duplication) = &current.duplication {
+        trend("Duplication", format!("{:.1}%", duplication.percentage), &|before| {
+            Some(points(f64::from(before.duplication.as_ref()?.percentage), f64::from(duplication.percentage)))
+        });
+    }
+    if let Some(freshness) = &current.dependency_freshness {
+        trend("Dependency freshness", format!("{:.0}/100", freshness.score), &|before| {
+            Some(points(f64::from(before.dependency_freshness.as_ref()?.score), f64::from(freshness.score)))
+        });
+    }
+
+    Some(format!("## Trends\n\n{}\n\n", lines.join("\n")))
+}
+
+/// Relative change from `before` to `after`, such as `+4.0%` or `stable`
+fn relative(before: f64, after: f64) -> String {
+    if before == 0.0 {
+        return if after == 0.0 { "stable".to_string() } else { "new".to_string() };
+    }
+    let percent = (after - before) * 100.0 / before;
+    if percent.abs() < STABLE_WITHIN { "stable".to_string() } else { format!("{:+.1}%", percent) }
+}
+
+/// Change of a score from `before` to `after`, such as `+3.0 points` or `stable`
+fn points(before: f64, after: f64) -> String {
+    let change = after - before;
+    if change.abs() < STABLE_WITHIN { "stable".to_string() } else { format!("{:+.1} points", change) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::complexity::ComplexityReport;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_runs_are_kept_per_target_and_trends_compare_them() {
+        let dir = tempdir().unwrap();
+        let history = MetricsHistory::open(&dir.path().join(METRICS_DB_FILE)).unwrap();
+        let run = |name: &str, day: u32, lines: usize, score: f32| {
+            let mut metrics = RepositoryMetrics::new(name);
+            metrics.timestamp = Some(format!("2026-09-{:02}T12:00:00Z", day));
+            metrics.total_lines = lines;
+            metrics.files_processed = 10;
+            metrics.complexity = Some(ComplexityReport { functions: 40, score, ..ComplexityReport::default() });
+            metrics
+        };
+        for metrics in [run("acme/api", 1, 1000, 20.0), run("acme/cli", 2, 50, 0.0), run("acme/api", 3, 1100, 24.0), run("acme/api", 5, 1144, 24.3)] {
+            history.record(&metrics).unwrap();
+        }
+
+        let runs = history.runs("acme/api", 10).unwrap();
+        assert_eq!(runs.iter().map(|r| r.total_lines).collect::<Vec<_>>(), [1000, 1100, 1144]);
+        assert_eq!(history.runs("acme/api", 2).unwrap()[0].total_lines, 1100);
+        assert_eq!(trend_section(&runs[..1]), None);
+
+        let section = trend_section(&runs).unwrap();
+        assert!(section.starts_with("## Trends\n\n"));
+        assert!(section.contains("- **Lines:** 1144 (+4.0% since the last run, +14.4% over 3 runs since 2026-09-01)"));
+        assert!(section.contains("- **Files:** 10 (stable since the last run, stable over 3 runs since 2026-09-01)"));
+        assert!(section.contains("- **Complexity:** 24/100 (stable since the last run, +4.3 points over 3 runs since 2026-09-01)"));
+        // Nothing was recorded about duplication to compare
+        assert!(!section.contains("Duplication"));
+    }
+}
@@ -0,0 +1,290 @@
+//! Code, comment and blank lines of source files
+//!
+//! Each language's comment and string syntax is followed line by line, the
+//! way tokei counts: a line with any code on it is code, even with a comment
+//! after it, a line with only comments is a comment, and a line with only
+//! whitespace is blank. Comment markers inside string literals are not
+//! comments, block comments may span lines (and nest where the language lets
+//! them), and Python docstrings count as comments. Files in languages without
+//! comments count every non-blank line as code.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Lines of one or more files by kind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineCounts {
+    /// Lines with code on them, whether or not they also have a comment
+    pub code: usize,
+    /// Lines with only comments
+    pub comments: usize,
+    /// Lines with only whitespace
+    pub blanks: usize,
+}
+
+impl LineCounts {
+    /// Every line counted
+    pub fn total(&self) -> usize {
+        self.code + self.comments + self.blanks
+    }
+
+    /// Adds the lines `other` counted
+    pub fn add(&mut self, other: LineCounts) {
+        self.code += other.code;
+        self.comments += other.comments;
+        self.blanks += other.blanks;
+    }
+}
+
+/// A string literal: what opens it, what closes it, and whether it may span lines
+type StringSyntax = (&'static str, &'static str, bool);
+
+/// Comment and string syntax of a family of languages
+struct Syntax {
+    /// Markers of comments running to the end of the line
+    line: &'static [&'static str],
+    /// Openers and closers of block comments
+    block: &'static [(&'static str, &'static str)],
+    /// Whether block comments nest, as in Rust
+    nested: bool,
+    /// String literals, longest openers first
+    strings: &'static [StringSyntax],
+    /// Strings that are comments when nothing precedes them on the line, as Python docstrings are
+    doc_strings: &'static [&'static str],
+    /// Whether `'` opens a character literal such as `'a'`, not a string
+    char_literals: bool,
+}
+
+const DOUBLE: StringSyntax = ("\"", "\"", false);
+const SINGLE: StringSyntax = ("'", "'", false);
+
+const RUST: Syntax = Syntax {
+    line: &["//"],
+    block: &[("/*", "*/")],
+    nested: true,
+    strings: &[("\"", "\"", true)],
+    doc_strings: &[],
+    char_literals: true,
+};
+
+const C_LIKE: Syntax = Syntax {
+    line: &["//"],
+    block: &[("/*", "*/")],
+    nested: false,
+    strings: &[DOUBLE],
+    doc_strings: &[],
+    char_literals: true,
+};
+
+const GO: Syntax = Syntax { strings: &[("`", "`", true), DOUBLE], ..C_LIKE };
+
+const JAVASCRIPT: Syntax = Syntax { strings: &[("`", "`", true), DOUBLE, SINGLE], char_literals: false, ..C_LIKE };
+
+/// Kotlin, Scala and Swift, with nested comments and multi-line strings
+const JVM_LIKE: Syntax = Syntax { nested: true, strings: &[("\"\"\"", "\"\"\"", true), DOUBLE], ..C_LIKE };
+
+const PYTHON: Syntax = Syntax {
+    line: &["#"],
+    block: &[],
+    nested: false,
+    strings: &[("\"\"\"", "\"\"\"", true), ("'''", "'''", true), DOUBLE, SINGLE],
+    doc_strings: &["\"\"\"", "'''"],
+    char_literals: false,
+};
+
+/// Shell, Ruby, Perl, R, YAML, TOML and other languages commenting with `#`
+const HASH: Syntax = Syntax {
+    line: &["#"],
+    block: &[],
+    nested: false,
+    strings: &[DOUBLE, SINGLE],
+    doc_strings: &[],
+    char_literals: false,
+};
+
+const PHP: Syntax = Syntax { line: &["//", "#"], strings: &[DOUBLE, SINGLE], char_literals: false, ..C_LIKE };
+
+const SQL: Syntax = Syntax { line: &["--"], strings: &[SINGLE], char_literals: false, ..C_LIKE };
+
+const LUA: Syntax = Syntax { line: &["--"], block: &[("--[[", "]]")], strings: &[DOUBLE, SINGLE], ..HASH };
+
+const HASKELL: Syntax = Syntax { line: &["--"], block: &[("{-", "-}")], nested: true, strings: &[DOUBLE], ..HASH };
+
+/// HTML, XML and other markup, whose text is not quoted
+const MARKUP: Syntax = Syntax { line: &[], block: &[("<!--", "-->")], strings: &[], ..HASH };
+
+const CSS: Syntax = Syntax { line: &[], strings: &[DOUBLE, SINGLE], char_literals: false, ..C_LIKE };
+
+/// Sass and Less, which add line comments to CSS
+const CSS_LINE_COMMENTS: Syntax = Syntax { line: &["//"], ..CSS };
+
+/// Lisps and assembly
+const SEMICOLON: Syntax = Syntax { line: &[";"], strings: &[DOUBLE], ..HASH };
+
+impl Syntax {
+    fn for_path(path: &Path) -> Option<&'static Self> {
+        let name = path.file_name()?.to_str()?;
+        if matches!(name, "Dockerfile" | "Makefile" | "makefile" | "CMakeLists.txt") {
+            return Some(&HASH);
+        }
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        Some(match ext.as_str() {
+            "rs" => &RUST,
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "java" | "cs" | "dart" | "proto" => &C_LIKE,
+            "go" => &GO,
+            "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => &JAVASCRIPT,
+            "kt" | "kts" | "scala" | "swift" => &JVM_LIKE,
+            "py" | "pyi" => &PYTHON,
+            "sh" | "bash" | "zsh" | "rb" | "pl" | "r" | "yml" | "yaml" | "toml" | "cmake" | "mk" => &HASH,
+            "php" => &PHP,
+            "sql" => &SQL,
+            "lua" => &LUA,
+            "hs" => &HASKELL,
+            "html" | "htm" | "xml" | "svg" | "vue" => &MARKUP,
+            "css" => &CSS,
+            "scss" | "sass" | "less" => &CSS_LINE_COMMENTS,
+            "clj" | "cljs" | "lisp" | "el" | "asm" | "s" => &SEMICOLON,
+            _ => return None,
+        })
+    }
+}
+
+/// What the scan is inside of at the end of a line
+#[derive(Clone, Copy)]
+enum State {
+    Code,
+    Comment { open: &'static str, close: &'static str, depth: usize },
+    String { close: &'static str, multiline: bool },
+}
+
+/// Counts the code, comment and blank lines of `content`, the file at `path`
+pub fn count(path: &str, content: &str) -> LineCounts {
+    let mut counts = LineCounts::default();
+    let Some(syntax) = Syntax::for_path(Path::new(path)) else {
+        for line in content.lines() {
+            if line.trim().is_empty() { counts.blanks += 1 } else { counts.code += 1 }
+        }
+        return counts;
+    };
+
+    let mut state = State::Code;
+    for line in content.lines() {
+        let mut code = matches!(state, State::String { .. });
+        let mut comment = false;
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            match state {
+                State::Comment { open, close, depth } => {
+                    comment |= !rest.starts_with(char::is_whitespace);
+                    if rest.starts_with(close) {
+                        i += close.len();
+                        state = if depth > 1 { State::Comment { open, close, depth: depth - 1 } } else { State::Code };
+                    } else if syntax.nested && open != close && rest.starts_with(open) {
+                        i += open.len();
+                        state = State::Comment { open, close, depth: depth + 1 };
+                    } else {
+                        i += char_len(rest);
+                    }
+                }
+                State::String { close, .. } => {
+                    if rest.starts_with('\\') {
+                        i += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                    } else if rest.starts_with(close) {
+                        i += close.len();
+                        state = State::Code;
+                    } else {
+                        i += char_len(rest);
+                    }
+                }
+                State::Code => {
+                    if rest.starts_with(char::is_whitespace) {
+                        i += char_len(rest);
+                        continue;
+                    }
+                    let doc_string = syntax.doc_strings.iter().find(|quote| !code && rest.starts_with(**quote));
+                    let block = doc_string.map(|&quote| (quote, quote))
+                        .or_else(|| syntax.block.iter().copied().find(|(open, _)| rest.starts_with(open)));
+                    if let Some((open, close)) = block {
+                        comment = true;
+                        i += open.len();
+                        state = State::Comment { open, close, depth: 1 };
+                        continue;
+                    }
+                    if syntax.line.iter().any(|marker| rest.starts_with(marker)) {
+                        comment = true;
+                        break;
+                    }
+                    code = true;
+                    if let Some(&(open, close, multiline)) = syntax.strings.iter().find(|(open, _, _)| rest.starts_with(open)) {
+                        i += open.len();
+                        state = State::String { close, multiline };
+                    } else if syntax.char_literals && rest.starts_with('\'') {
+                        i += char_literal_len(rest).unwrap_or(1);
+                    } else {
+                        i += char_len(rest);
+                    }
+                }
+            }
+        }
+        // Quotes left open end with their line unless the language lets strings span lines
+        if let State::String { multiline: false, .. } = state {
+            state = State::Code;
+        }
+
+        if code {
+            counts.code += 1;
+        } else if comment {
+            counts.comments += 1;
+        } else {
+            counts.blanks += 1;
+        }
+    }
+    counts
+}
+
+/// Length in bytes of the first character of `rest`
+fn char_len(rest: &str) -> usize {
+    rest.chars().next().map_or(1, char::len_utf8)
+}
+
+/// Length in bytes of the character literal `rest` starts with, such as `'a'` or `'\n'`,
+/// or `None` if its `'` is a Rust lifetime or label
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, first) = chars.next()?;
+    if first == '\\' {
+        return rest[2..].find('\'').map(|end| end + 3);
+    }
+    match chars.next() {
+        Some((at, '\'')) => Some(at + 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_are_told_apart_by_each_languages_comment_syntax() {
+        let rust = "//! Crate docs\n\nuse std::fmt; // trailing\n\n/* block\n   /* nested */\n   still comment */\n\
+                    fn name<'a>(s: &'a str) -> char {\n    let url = \"http://example.com/*\";\n    '\"'\n}\n";
+        assert_eq!(count("src/lib.rs", rust), LineCounts { code: 5, comments: 4, blanks: 2 });
+
+        let python = "#!/usr/bin/env python\n\"\"\"Module docs\n\nspanning lines.\n\"\"\"\n\nQUERY = \"\"\"\n# not a comment\n\"\"\"\n\
+                      def main():  # entry point\n    print('# not a comment either')\n";
+        assert_eq!(count("tool.py", python), LineCounts { code: 5, comments: 4, blanks: 2 });
+
+        let javascript = "const tpl = `\n// inside a template\n`;\n/**\n * Docs\n */\nexport default tpl;\n";
+        assert_eq!(count("index.js", javascript), LineCounts { code: 4, comments: 3, blanks: 0 });
+
+        let html = "<!-- header -->\n<p>Don't</p>\n<!--\n  note\n-->\n";
+        assert_eq!(count("index.html", html), LineCounts { code: 1, comments: 4, blanks: 0 });
+
+        // Languages without comments count every line with text as code
+        let json = "{\n  \"url\": \"http://example.com\"\n}\n\n";
+        let counts = count("package.json", json);
+        assert_eq!((counts, counts.total()), (LineCounts { code: 3, comments: 0, blanks: 1 }, 4));
+    }
+}
@@ -0,0 +1,333 @@
+//! Maintainability index and tech-debt estimate of source code
+//!
+//! Every directory of source files gets an index from 0 to 100, higher being
+//! easier to maintain. It starts at 100 and loses points for risky function
+//! complexity, duplicated lines, too few comments and code held in very large
+//! files, with the weights below. The same problems are listed as tech-debt
+//! items, each with a rough estimate of the time it takes to fix, and the
+//! items costing the most are reported. Files are read in the languages
+//! [`super::complexity`] supports.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use super::complexity::{self, ComplexityReport, FunctionComplexity};
+use super::duplication::DuplicationReport;
+use super::lines::{self, LineCounts};
+
+/// Points of the index lost when every function line is at full complexity risk
+pub const COMPLEXITY_WEIGHT: f32 = 40.0;
+
+/// Points of the index lost when every line of code is duplicated
+pub const DUPLICATION_WEIGHT: f32 = 30.0;
+
+/// Points of the index lost when there are no comments at all
+pub const COMMENT_WEIGHT: f32 = 15.0;
+
+/// Points of the index lost when all code is in files over [`LARGE_FILE_LINES`]
+pub const SIZE_WEIGHT: f32 = 15.0;
+
+/// Share of comment lines, among code and comment lines, below which comments count as too few
+pub const COMMENT_TARGET: f32 = 0.1;
+
+/// Lines of code above which a file counts as large
+pub const LARGE_FILE_LINES: usize = 500;
+
+/// Cyclomatic complexity above which a function is a tech-debt item
+pub const COMPLEX_FUNCTION: u32 = 10;
+
+/// Tech-debt items listed in reports
+pub const DEBT_ITEMS: usize = 15;
+
+/// Directories listed in reports, least maintainable first
+pub const MODULES_LISTED: usize = 20;
+
+/// What a tech-debt item is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebtKind {
+    /// A function over [`COMPLEX_FUNCTION`]
+    ComplexFunction,
+    /// A file with duplicated lines
+    Duplication,
+    /// A file over [`LARGE_FILE_LINES`]
+    LargeFile,
+}
+
+/// One thing worth fixing, with a rough estimate of the effort
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebtItem {
+    /// What the item is about
+    pub kind: DebtKind,
+    /// File the item is in
+    pub path: String,
+    /// Line of the function, for complex functions
+    pub line: Option<usize>,
+    /// What is wrong, such as "`parse` has cyclomatic complexity 26"
+    pub description: String,
+    /// Estimated minutes to fix
+    pub minutes: u64,
+}
+
+/// Maintainability of one directory of source files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleMaintainability {
+    /// Directory, or `.` for files at the root
+    pub module: String,
+    /// Source files in the directory itself
+    pub files: usize,
+    /// Lines of code in them
+    pub code_lines: usize,
+    /// Maintainability index, from 0 to 100
+    pub index: f32,
+    /// Complexity score of the functions, from 0 to 100
+    pub complexity: f32,
+    /// Share of lines of code that are duplicated, from 0 to 100
+    pub duplication: f32,
+    /// Share of comment lines among code and comment lines, from 0 to 100
+    pub comment_density: f32,
+    /// Files over [`LARGE_FILE_LINES`]
+    pub large_files: usize,
+    /// Estimated minutes to fix the directory's tech-debt items
+    pub debt_minutes: u64,
+}
+
+/// Maintainability of a repository's source code
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaintainabilityReport {
+    /// Index of all the source files together, from 0 to 100
+    pub index: f32,
+    /// Source files measured
+    pub files: usize,
+    /// Estimated minutes to fix every tech-debt item
+    pub debt_minutes: u64,
+    /// Tech-debt items found
+    pub debt_count: usize,
+    /// Directories, least maintainable first
+    pub modules: Vec<ModuleMaintainability>,
+    /// Tech-debt items costing the most, most first
+    pub debt: Vec<DebtItem>,
+}
+
+/// What was measured of one source file
+struct Measured<'a> {
+    path: &'a str,
+    lines: LineCounts,
+    functions: Vec<FunctionComplexity>,
+    duplicated: usize,
+}
+
+impl MaintainabilityReport {
+    /// Rates the source files among each `(path, content)` source
+    pub fn from_sources<'a>(sources: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let sources: Vec<(&str, &str)> = sources.into_iter()
+            .filter(|(path, _)| complexity::supports(path))
+            .collect();
+        let duplicated = DuplicationReport::lines_by_file(sources.iter().copied());
+        let files: Vec<Measured> = sources.iter()
+            .map(|&(path, content)| Measured {
+                path,
+                lines: lines::count(path, content),
+                functions: complexity::analyze(path, content),
+                duplicated: duplicated.get(path).copied().unwrap_or(0),
+            })
+            .collect();
+
+        let mut debt: Vec<DebtItem> = files.iter().flat_map(debt_items).collect();
+        let mut by_module: BTreeMap<String, Vec<&Measured>> = BTreeMap::new();
+        for file in &files {
+            by_module.entry(module_of(file.path)).or_default().push(file);
+        }
+        let mut modules: Vec<ModuleMaintainability> = by_module.into_iter()
+            .map(|(module, files)| {
+                let debt_minutes = debt.iter()
+                    .filter(|item| files.iter().any(|file| file.path == item.path))
+                    .map(|item| item.minutes)
+                    .sum();
+                ModuleMaintainability { debt_minutes, ..rate(module, &files) }
+            })
+            .collect();
+        modules.sort_by(|a, b| a.index.total_cmp(&b.index).then_with(|| a.module.cmp(&b.module)));
+        modules.truncate(MODULES_LISTED);
+
+        debt.sort_by(|a, b| b.minutes.cmp(&a.minutes).then_with(|| (&a.path, a.line).cmp(&(&b.path, b.line))));
+        Self {
+            index: rate(String::new(), &files.iter().collect::<Vec<_>>()).index,
+            files: files.len(),
+            debt_minutes: debt.iter().map(|item| item.minutes).sum(),
+            debt_count: debt.len(),
+            modules,
+            debt: debt.into_iter().take(DEBT_ITEMS).collect(),
+        }
+    }
+
+    /// "Maintainability" report section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Maintainability\n\n");
+        out.push_str(&format!(
+            "**Index:** {:.0}/100 across {} files (higher is easier to maintain)\n",
+            self.index, self.files
+        ));
+        out.push_str(&format!(
+            "**Estimated tech debt:** {} in {} items\n\n",
+            effort(self.debt_minutes), self.debt_count
+        ));
+        if !self.modules.is_empty() {
+            out.push_str("### Modules\n\n");
+            out.push_str("| Module | Files | Code Lines | Index | Complexity | Duplication | Comments | Large Files | Debt |\n");
+            out.push_str("|--------|-------|------------|-------|------------|-------------|----------|-------------|------|\n");
+            for module in &self.modules {
+                out.push_str(&format!(
+                    "| `{}` | {} | {} | {:.0} | {:.0} | {:.1}% | {:.1}% | {} | {} |\n",
+                    module.module, module.files, module.code_lines, module.index, module.complexity,
+                    module.duplication, module.comment_density, module.large_files, effort(module.debt_minutes)
+                ));
+            }
+            out.push('\n');
+        }
+        if !self.debt.is_empty() {
+            out.push_str("### Tech Debt\n\n");
+            out.push_str("| Item | Location | Effort |\n");
+            out.push_str("|------|----------|--------|\n");
+            for item in &self.debt {
+                let location = match item.line {
+                    Some(line) => format!("{}:{}", item.path, line),
+                    None => item.path.clone(),
+                };
+                out.push_str(&format!("| {} | `{}` | {} |\n", item.description, location, effort(item.minutes)));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// The index and measurements of `files` taken together, without their debt
+fn rate(module: String, files: &[&Measured]) -> ModuleMaintainability {
+    let code: usize = files.iter().map(|file| file.lines.code).sum();
+    let comments: usize = files.iter().map(|file| file.lines.comments).sum();
+    let duplicated: usize = files.iter().map(|file| file.duplicated).sum();
+    let large: Vec<&&Measured> = files.iter().filter(|file| file.lines.code > LARGE_FILE_LINES).collect();
+    let functions = files.iter().flat_map(|file| file.functions.iter().cloned()).collect();
+
+    let complexity = ComplexityReport::from_functions(functions, 0).score / 100.0;
+    let duplication = if code == 0 { 0.0 } else { (duplicated as f32 / code as f32).min(1.0) };
+    let density = if code + comments == 0 { 0.0 } else { comments as f32 / (code + comments) as f32 };
+    let too_few_comments = if code == 0 { 0.0 } else { (1.0 - density / COMMENT_TARGET).max(0.0) };
+    let in_large_files = if code == 0 { 0.0 } else { large.iter().map(|file| file.lines.code).sum::<usize>() as f32 / code as f32 };
+
+    let index = 100.0
+        - COMPLEXITY_WEIGHT * complexity
+        - DUPLICATION_WEIGHT * duplication
+        - COMMENT_WEIGHT * too_few_comments
+        - SIZE_WEIGHT * in_large_files;
+    ModuleMaintainability {
+        module,
+        files: files.len(),
+        code_lines: code,
+        index: index.clamp(0.0, 100.0),
+        complexity: complexity * 100.0,
+        duplication: duplication * 100.0,
+        comment_density: density * 100.0,
+        large_files: large.len(),
+        debt_minutes: 0,
+    }
+}
+
+/// Tech-debt items of one file
+///
+/// Efforts follow the usual rules of thumb: a complex function takes 10
+/// minutes plus 5 for each point of complexity past [`COMPLEX_FUNCTION`] to
+/// break up, a duplicated line 2 minutes to factor out, and a large file 30
+/// minutes plus one for every 10 lines past [`LARGE_FILE_LINES`] to split.
+fn debt_items(file: &Measured) -> Vec<DebtItem> {
+    let mut items: Vec<DebtItem> = file.functions.iter()
+        .filter(|f| f.cyclomatic > COMPLEX_FUNCTION)
+        .map(|f| DebtItem {
+            kind: DebtKind::ComplexFunction,
+            path: file.path.to_string(),
+            line: Some(f.line),
+            description: format!("`{}` has cyclomatic complexity {}", f.name, f.cyclomatic),
+            minutes: 10 + 5 * u64::from(f.cyclomatic - COMPLEX_FUNCTION),
+        })
+        .collect();
+    if file.duplicated > 0 {
+        items.push(DebtItem {
+            kind: DebtKind::Duplication,
+            path: file.path.to_string(),
+            line: None,
+            description: format!("{} duplicated lines", file.duplicated),
+            minutes: 2 * file.duplicated as u64,
+        });
+    }
+    if file.lines.code > LARGE_FILE_LINES {
+        items.push(DebtItem {
+            kind: DebtKind::LargeFile,
+            path: file.path.to_string(),
+            line: None,
+            description: format!("{} lines of code in one file", file.lines.code),
+            minutes: 30 + (file.lines.code - LARGE_FILE_LINES) as u64 / 10,
+        });
+    }
+    items
+}
+
+/// Directory of the file at `path`, or `.` for the root
+fn module_of(path: &str) -> String {
+    match Path::new(path).parent().map(|dir| dir.to_string_lossy().replace('\\', "/")) {
+        Some(dir) if !dir.is_empty() => dir,
+        _ => ".".to_string(),
+    }
+}
+
+/// `minutes` as working time, such as `2h 15m`
+fn effort(minutes: u64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modules_are_rated_and_their_debt_ranked() {
+        let branches = (0..25).map(|i| format!("    if x == {} {{ return {}; }}\n", i, i)).collect::<String>();
+        let tangled = format!("fn parse(x: u8) -> u8 {{\n{}    0\n}}\n", branches);
+        let tidy = "// Adds one\nfn next(x: u8) -> u8 {\n    x + 1\n}\n";
+        let large = (0..520).map(|i| format!("const C{}: u8 = {};\n", i, i % 200)).collect::<String>();
+
+        let report = MaintainabilityReport::from_sources([
+            ("src/parse/mod.rs", tangled.as_str()),
+            ("src/lib.rs", tidy),
+            ("src/consts.rs", large.as_str()),
+            ("README.md", "# Not source\n"),
+        ]);
+        assert_eq!((report.files, report.debt_count), (3, 2));
+        assert_eq!(report.modules.iter().map(|m| m.module.as_str()).collect::<Vec<_>>(), ["src/parse", "src"]);
+
+        // All of parse is high risk and uncommented: 100 - 40 * 0.75 - 15
+        let parse = &report.modules[0];
+        assert_eq!((parse.files, parse.code_lines, parse.complexity), (1, 28, 75.0));
+        assert!((parse.index - 55.0).abs() < 0.01, "{}", parse.index);
+        assert_eq!(parse.debt_minutes, 10 + 5 * 16);
+
+        let src = &report.modules[1];
+        assert_eq!((src.files, src.large_files), (2, 1));
+        assert!(src.index > parse.index && src.index < 100.0);
+
+        assert_eq!(report.debt[0].kind, DebtKind::ComplexFunction);
+        assert_eq!((report.debt[0].line, report.debt[0].minutes), (Some(1), 90));
+        assert_eq!((report.debt[1].kind, report.debt[1].minutes), (DebtKind::LargeFile, 32));
+        assert_eq!(report.debt_minutes, 122);
+
+        let markdown = report.to_markdown();
+        assert!(markdown.starts_with("## Maintainability\n\n"));
+        assert!(markdown.contains("**Estimated tech debt:** 2h 2m in 2 items"));
+        assert!(markdown.contains("| `parse` has cyclomatic complexity 26 | `src/parse/mod.rs:1` | 1h 30m |"));
+        assert!(MaintainabilityReport::from_sources([("README.md", "# Docs\n")]).modules.is_empty());
+    }
+}
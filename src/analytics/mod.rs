@@ -1,8 +1,22 @@
+pub mod compare;
+pub mod complexity;
+pub mod duplication;
+pub mod freshness;
+pub mod history;
+pub mod lines;
+pub mod maintainability;
+pub mod ownership;
+
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use std::fs;
+use complexity::ComplexityReport;
+use duplication::DuplicationReport;
+use freshness::FreshnessReport;
+use crate::batch_report::language_for;
+use crate::run_diff::RunSnapshot;
 
 /// Metrics collected during repository processing
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,14 +33,29 @@ pub struct RepositoryMetrics {
     pub memory_mb: f64,
     /// Lines of code (excluding comments and blank lines)
     pub lines_of_code: usize,
+    /// Lines holding only comments
+    #[serde(default)]
+    pub comment_lines: usize,
+    /// Lines holding only whitespace
+    #[serde(default)]
+    pub blank_lines: usize,
     /// Number of commits in the repository
     pub commit_count: u32,
     /// Number of unique contributors
     pub contributor_count: u32,
     /// Distribution of languages in the repository
     pub language_distribution: HashMap<String, f32>,
-    /// Overall complexity score (0-100)
+    /// Overall complexity score (0-100), from the complexity of the functions measured
     pub complexity_score: f32,
+    /// Complexity of the functions measured, when source was recorded
+    #[serde(default)]
+    pub complexity: Option<ComplexityReport>,
+    /// Code duplicated in and across the files recorded with their source
+    #[serde(default)]
+    pub duplication: Option<DuplicationReport>,
+    /// How far the declared dependencies lag their latest releases, when they were checked
+    #[serde(default)]
+    pub dependency_freshness: Option<FreshnessReport>,
     /// Timestamp of when the metrics were collected
     pub timestamp: Option<String>,
 }
@@ -41,14 +70,48 @@ impl RepositoryMetrics {
             duration_secs: 0.0,
             memory_mb: 0.0,
             lines_of_code: 0,
+            comment_lines: 0,
+            blank_lines: 0,
             commit_count: 0,
             contributor_count: 0,
             language_distribution: HashMap::new(),
             complexity_score: 0.0,
+            complexity: None,
+            duplication: None,
+            dependency_freshness: None,
             timestamp: Some(chrono::Utc::now().to_rfc3339()),
         }
     }
 
+    /// Metrics of a run from the snapshot taken at its end
+    ///
+    /// Snapshots taken before lines were told apart count every line as code,
+    /// and languages are weighted by their number of files.
+    pub fn from_snapshot(snapshot: &RunSnapshot) -> Self {
+        let mut metrics = Self::new(&snapshot.target);
+        let metric = |name: &str| snapshot.metrics.get(name).map(|&value| value as usize);
+        metrics.files_processed = metric("files").unwrap_or(0);
+        metrics.total_lines = metric("lines").unwrap_or(0);
+        metrics.lines_of_code = metric("code").unwrap_or(metrics.total_lines);
+        metrics.comment_lines = metric("comments").unwrap_or(0);
+        metrics.blank_lines = metric("blanks").unwrap_or(0);
+        for path in snapshot.files.keys() {
+            if let Some(language) = language_for(path) {
+                metrics.update_language(language, 1.0);
+            }
+        }
+        let files: f32 = metrics.language_distribution.values().sum();
+        for share in metrics.language_distribution.values_mut() {
+            *share /= files;
+        }
+        if let Some(complexity) = &snapshot.complexity {
+            metrics.complexity_score = complexity.score;
+            metrics.complexity = Some(complexity.clone());
+        }
+        metrics.timestamp = Some(snapshot.generated_at.to_rfc3339());
+        metrics
+    }
+
     /// Export metrics to JSON file
     pub fn export(&self, output_dir: &Path) -> anyhow::Result<()> {
         let metrics_file = output_dir.join("metrics.json");
@@ -78,6 +141,8 @@ impl RepositoryMetrics {
         self.files_processed += other.files_processed;
         self.total_lines += other.total_lines;
         self.lines_of_code += other.lines_of_code;
+        self.comment_lines += other.comment_lines;
+        self.blank_lines += other.blank_lines;
         
         // We keep the max duration as an approximation (as operations might be parallelized)
         self.duration_secs = self.duration_secs.max(other.duration_secs);
@@ -96,7 +161,20 @@ impl RepositoryMetrics {
             *entry += weight;
         }
         
-        // Recalculate complexity score as weighted average
+        if let Some(other_duplication) = &other.duplication {
+            self.duplication.get_or_insert_with(DuplicationReport::default).merge(other_duplication);
+        }
+        if let Some(other_freshness) = &other.dependency_freshness {
+            self.dependency_freshness.get_or_insert_with(FreshnessReport::default).merge(other_freshness);
+        }
+        
+        // Combine the functions measured, or failing that average the scores by size
+        if let Some(other_complexity) = &other.complexity {
+            let complexity = self.complexity.get_or_insert_with(ComplexityReport::default);
+            complexity.merge(other_complexity);
+            self.complexity_score = complexity.score;
+            return;
+        }
         let total_loc = self.lines_of_code as f32;
         if total_loc > 0.0 {
             let self_weight = self.lines_of_code as f32 / total_loc;
@@ -112,6 +190,8 @@ impl RepositoryMetrics {
 pub struct AnalyticsProcessor {
     metrics: Option<RepositoryMetrics>,
     start_time: Option<Instant>,
+    /// Path and content of each source file recorded
+    sources: Vec<(String, String)>,
 }
 
 impl AnalyticsProcessor {
@@ -120,6 +200,7 @@ impl AnalyticsProcessor {
         Self {
             metrics: None,
             start_time: None,
+            sources: Vec::new(),
         }
     }
     
@@ -127,17 +208,18 @@ impl AnalyticsProcessor {
     pub fn start_repository(&mut self, repo_name: &str) {
         self.metrics = Some(RepositoryMetrics::new(repo_name));
         self.start_time = Some(Instant::now());
+        self.sources.clear();
     }
     
-    /// Record file processing
-    pub fn record_file(&mut self, file_path: &Path, line_count: usize) {
+    /// Record a processed file, counting its code, comment and blank lines
+    pub fn record_file(&mut self, file_path: &Path, content: &str) {
         if let Some(metrics) = &mut self.metrics {
+            let lines = lines::count(&file_path.to_string_lossy(), content);
             metrics.files_processed += 1;
-            metrics.total_lines += line_count;
-            
-            // Estimate actual code lines (non-blank, non-comment) as 70% of total
-            // This is just a heuristic - in a real implementation you'd want to actually count
-            metrics.lines_of_code += (line_count as f32 * 0.7) as usize;
+            metrics.total_lines += lines.total();
+            metrics.lines_of_code += lines.code;
+            metrics.comment_lines += lines.comments;
+            metrics.blank_lines += lines.blanks;
             
             // Update language based on file extension
             if let Some(ext) = file_path.extension() {
@@ -162,24 +244,43 @@ impl AnalyticsProcessor {
                         _ => "Other",
                     };
                     
-                    metrics.update_language(language, line_count as f32);
+                    metrics.update_language(language, lines.code as f32);
                 }
             }
         }
     }
     
+    /// Record a source file, to be measured for complexity and duplication when processing finishes
+    pub fn record_source(&mut self, file_path: &Path, content: &str) {
+        self.record_file(file_path, content);
+        if self.metrics.is_some() {
+            self.sources.push((file_path.to_string_lossy().into_owned(), content.to_string()));
+        }
+    }
+    
+    /// Record how far the repository's dependencies lag their latest releases
+    pub fn record_dependency_freshness(&mut self, report: FreshnessReport) {
+        if let Some(metrics) = &mut self.metrics {
+            metrics.dependency_freshness = Some(report);
+        }
+    }
+    
     /// Finish processing and return the metrics
     pub fn finish(&mut self) -> Option<RepositoryMetrics> {
         if let (Some(metrics), Some(start_time)) = (&mut self.metrics, self.start_time) {
             metrics.duration_secs = start_time.elapsed().as_secs_f64();
             
-            // Calculate complexity score based on various factors
-            // This is a simplified heuristic
-            let loc_factor = (metrics.lines_of_code as f32).log10() / 5.0; // 0.0-1.0 based on code size
-            let lang_factor = metrics.language_distribution.len() as f32 / 5.0; // 0.0-1.0 based on language diversity
-            
-            metrics.complexity_score = (loc_factor * 0.7 + lang_factor * 0.3) * 100.0;
-            metrics.complexity_score = metrics.complexity_score.min(100.0);
+            // Score the functions measured; files recorded without their source have none
+            let sources = || self.sources.iter().map(|(path, content)| (path.as_str(), content.as_str()));
+            let complexity = ComplexityReport::from_sources(sources());
+            if complexity.functions > 0 {
+                metrics.complexity_score = complexity.score;
+                metrics.complexity = Some(complexity);
+            }
+            let duplication = DuplicationReport::from_sources(sources());
+            if duplication.files > 0 {
+                metrics.duplication = Some(duplication);
+            }
             
             // Normalize language distribution percentages
             let total_lines: f32 = metrics.language_distribution.values().sum();
@@ -243,8 +344,8 @@ mod tests {
         let rust_file = Path::new("src/main.rs");
         let python_file = Path::new("scripts/test.py");
         
-        processor.record_file(rust_file, 100);
-        processor.record_file(python_file, 50);
+        processor.record_file(rust_file, &"// Entry point\nfn main() {\n}\n\n".repeat(25));
+        processor.record_file(python_file, &"# Helpers\n\ndef main():\n    pass\n\n".repeat(10));
         
         // Get final metrics
         let metrics = processor.finish().unwrap();
@@ -252,6 +353,7 @@ mod tests {
         assert_eq!(metrics.name, "test-repo");
         assert_eq!(metrics.files_processed, 2);
         assert_eq!(metrics.total_lines, 150);
+        assert_eq!((metrics.lines_of_code, metrics.comment_lines, metrics.blank_lines), (70, 35, 45));
         assert!(metrics.duration_secs > 0.0);
         
         // Check language distribution
@@ -267,4 +369,29 @@ mod tests {
         let total: f32 = metrics.language_distribution.values().sum();
         assert!((total - 1.0).abs() < 0.001);
     }
+    
+    #[test]
+    fn test_complexity_score_comes_from_the_functions_recorded() {
+        let mut processor = AnalyticsProcessor::new();
+        processor.start_repository("test-repo");
+        processor.record_source(Path::new("src/lib.rs"), "fn plain() -> u8 {\n    1\n}\n");
+        processor.record_source(Path::new("README.md"), "# Test\n");
+        let simple = processor.finish().unwrap();
+        assert_eq!(simple.complexity_score, 0.0);
+        assert_eq!(simple.duplication.as_ref().map(|d| (d.files, d.duplicated_lines)), Some((1, 0)));
+        
+        let branches = (0..25).map(|i| format!("    if x == {} {{ return {}; }}\n", i, i)).collect::<String>();
+        processor.start_repository("other-repo");
+        processor.record_source(Path::new("src/parse.rs"), &format!("fn parse(x: u8) -> u8 {{\n{}    0\n}}\n", branches));
+        let tangled = processor.finish().unwrap();
+        assert_eq!(tangled.complexity.as_ref().unwrap().hotspots[0].cyclomatic, 26);
+        assert_eq!(tangled.complexity_score, 75.0);
+        
+        // Merging weighs both repositories' function lines: 3 simple, 28 high-risk
+        let mut merged = simple.clone();
+        merged.merge(&tangled);
+        assert_eq!(merged.complexity.unwrap().functions, 2);
+        assert!((merged.complexity_score - 75.0 * 28.0 / 31.0).abs() < 0.001);
+        assert_eq!(merged.files_processed, 3);
+    }
 }
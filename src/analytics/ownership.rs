@@ -0,0 +1,324 @@
+//! Code ownership and bus factor from `git blame`
+//!
+//! Each source file of a git checkout is blamed, and the lines each author
+//! last touched are counted, telling authors apart by email. An author owns a
+//! file when they wrote at least [`OWNER_SHARE`] of it, or wrote the most of
+//! it when nobody did. The bus factor is how many authors would have to leave,
+//! taking the ones owning the most files first, before more than half of the
+//! files had no owner left. Directories where one author wrote almost all of
+//! a sizeable amount of code are flagged as critical paths.
+
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use tokio::process::Command;
+use super::complexity;
+
+/// Share of a file's lines that makes its author an owner
+pub const OWNER_SHARE: f64 = 0.25;
+
+/// Share of a directory's lines one author must have written for it to be a critical path
+pub const SINGLE_OWNER_SHARE: f64 = 0.9;
+
+/// Lines a directory needs before it can be a critical path
+pub const CRITICAL_PATH_LINES: usize = 100;
+
+/// Most files blamed in one repository, the first in path order
+pub const MAX_BLAMED_FILES: usize = 2000;
+
+/// Directories listed in reports, most concentrated ownership first
+pub const DIRECTORIES_LISTED: usize = 20;
+
+/// `git blame` processes run at once
+const CONCURRENT_BLAMES: usize = 8;
+
+/// Lines one author last touched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorLines {
+    /// Author's name as git records it
+    pub name: String,
+    /// Author's email, which tells authors apart
+    pub email: String,
+    /// Lines they last touched
+    pub lines: usize,
+}
+
+/// Who wrote one directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryOwnership {
+    /// Directory, or `.` for files at the root
+    pub directory: String,
+    /// Blamed lines of the files in the directory itself
+    pub lines: usize,
+    /// Authors of those lines
+    pub contributors: usize,
+    /// Author of the most lines
+    pub owner: String,
+    /// Share of the lines the owner wrote, from 0 to 100
+    pub owner_share: f32,
+    /// Whether one author wrote nearly all of a sizeable directory
+    pub critical: bool,
+}
+
+/// Code ownership of a repository
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OwnershipReport {
+    /// Authors who would have to leave before most files had no owner
+    pub bus_factor: usize,
+    /// Those authors, by name, in the order they were taken
+    pub key_contributors: Vec<String>,
+    /// Files blamed
+    pub files: usize,
+    /// Lines blamed
+    pub lines: usize,
+    /// Authors by the lines they wrote, most first
+    pub authors: Vec<AuthorLines>,
+    /// Directories, most concentrated ownership first
+    pub directories: Vec<DirectoryOwnership>,
+}
+
+/// Lines by author email, with the name last seen for each
+type Blame = BTreeMap<String, (String, usize)>;
+
+impl OwnershipReport {
+    /// Blames the source files of the git checkout at `root`
+    ///
+    /// Returns `None` when `root` is not in a git checkout, git is not
+    /// installed, or no source file has committed lines.
+    pub async fn from_git(root: &Path) -> Option<Self> {
+        let listed = Command::new("git").arg("-C").arg(root).args(["ls-files", "-z"]).output().await.ok()?;
+        if !listed.status.success() {
+            return None;
+        }
+        let mut paths: Vec<String> = String::from_utf8_lossy(&listed.stdout)
+            .split('\0')
+            .filter(|path| complexity::supports(path))
+            .map(str::to_string)
+            .collect();
+        paths.sort();
+        paths.truncate(MAX_BLAMED_FILES);
+
+        let blamed: Vec<(String, Blame)> = stream::iter(paths)
+            .map(|path| async move {
+                let output = Command::new("git").arg("-C").arg(root)
+                    .args(["blame", "--line-porcelain", "-w", "--"])
+                    .arg(&path)
+                    .output().await.ok()?;
+                output.status.success().then(|| (path, parse_blame(&String::from_utf8_lossy(&output.stdout))))
+            })
+            .buffer_unordered(CONCURRENT_BLAMES)
+            .filter_map(|blamed| async move { blamed })
+            .collect()
+            .await;
+        let report = Self::from_blame(blamed);
+        (report.lines > 0).then_some(report)
+    }
+
+    /// Ownership of each `(path, blame)` file
+    fn from_blame(files: Vec<(String, Blame)>) -> Self {
+        let files: Vec<(String, Blame)> = files.into_iter().filter(|(_, blame)| !blame.is_empty()).collect();
+        let mut authors: Blame = BTreeMap::new();
+        let mut directories: BTreeMap<String, Blame> = BTreeMap::new();
+        for (path, blame) in &files {
+            let directory = directories.entry(directory_of(path)).or_default();
+            for (email, (name, lines)) in blame {
+                for totals in [&mut authors, &mut *directory] {
+                    let entry = totals.entry(email.clone()).or_insert_with(|| (name.clone(), 0));
+                    entry.1 += lines;
+                }
+            }
+        }
+
+        let mut directories: Vec<DirectoryOwnership> = directories.into_iter()
+            .map(|(directory, blame)| {
+                let lines: usize = blame.values().map(|(_, lines)| lines).sum();
+                let (owner, owned) = blame.values()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                    .map(|(name, lines)| (name.clone(), *lines))
+                    .unwrap_or_default();
+                let share = owned as f64 / lines.max(1) as f64;
+                DirectoryOwnership {
+                    directory,
+                    lines,
+                    contributors: blame.len(),
+                    owner,
+                    owner_share: (share * 100.0) as f32,
+                    critical: share >= SINGLE_OWNER_SHARE && lines >= CRITICAL_PATH_LINES,
+                }
+            })
+            .collect();
+        directories.sort_by(|a, b| {
+            b.owner_share.total_cmp(&a.owner_share)
+                .then(b.lines.cmp(&a.lines))
+                .then_with(|| a.directory.cmp(&b.directory))
+        });
+
+        let (bus_factor, key_contributors) = bus_factor(&files);
+        let mut authors: Vec<AuthorLines> = authors.into_iter()
+            .map(|(email, (name, lines))| AuthorLines { name, email, lines })
+            .collect();
+        authors.sort_by(|a, b| b.lines.cmp(&a.lines).then_with(|| a.email.cmp(&b.email)));
+        Self {
+            bus_factor,
+            key_contributors,
+            files: files.len(),
+            lines: authors.iter().map(|author| author.lines).sum(),
+            authors,
+            directories,
+        }
+    }
+
+    /// "Ownership" report section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Ownership\n\n");
+        out.push_str(&format!(
+            "**Bus factor:** {} ({} would have to leave before most files had no owner)\n",
+            self.bus_factor, self.key_contributors.join(", ")
+        ));
+        out.push_str(&format!("**Contributors:** {} across {} files and {} lines\n\n", self.authors.len(), self.files, self.lines));
+
+        let critical: Vec<&DirectoryOwnership> = self.directories.iter().filter(|d| d.critical).collect();
+        if let [author] = self.authors.as_slice() {
+            out.push_str(&format!("All committed lines were written by {}, so every path depends on them.\n\n", author.name));
+        } else if !critical.is_empty() {
+            out.push_str("### Critical Paths\n\n");
+            out.push_str(&format!(
+                "Directories where one contributor wrote at least {:.0}% of {} or more lines:\n\n",
+                SINGLE_OWNER_SHARE * 100.0, CRITICAL_PATH_LINES
+            ));
+            for directory in critical {
+                out.push_str(&format!(
+                    "- `{}`: {:.0}% of {} lines by {}\n",
+                    directory.directory, directory.owner_share, directory.lines, directory.owner
+                ));
+            }
+            out.push('\n');
+        }
+
+        out.push_str("### Directories\n\n");
+        out.push_str("| Directory | Lines | Contributors | Main Owner | Share |\n");
+        out.push_str("|-----------|-------|--------------|------------|-------|\n");
+        for directory in self.directories.iter().take(DIRECTORIES_LISTED) {
+            out.push_str(&format!(
+                "| `{}` | {} | {} | {} | {:.0}% |\n",
+                directory.directory, directory.lines, directory.contributors, directory.owner, directory.owner_share
+            ));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// How many authors, taking those owning the most files first, would leave most files without an owner, and who they are
+fn bus_factor(files: &[(String, Blame)]) -> (usize, Vec<String>) {
+    let mut names = BTreeMap::new();
+    let mut owners: Vec<BTreeSet<&str>> = files.iter()
+        .map(|(_, blame)| {
+            let total: usize = blame.values().map(|(_, lines)| lines).sum();
+            let mut owners: BTreeSet<&str> = blame.iter()
+                .filter(|(_, (_, lines))| *lines as f64 >= OWNER_SHARE * total as f64)
+                .map(|(email, _)| email.as_str())
+                .collect();
+            if owners.is_empty() {
+                owners.extend(blame.iter().max_by_key(|(_, (_, lines))| *lines).map(|(email, _)| email.as_str()));
+            }
+            for (email, (name, _)) in blame {
+                names.insert(email.as_str(), name.as_str());
+            }
+            owners
+        })
+        .collect();
+
+    let mut gone = Vec::new();
+    while owners.iter().filter(|owners| owners.is_empty()).count() * 2 <= files.len() {
+        let mut owned: BTreeMap<&str, usize> = BTreeMap::new();
+        for email in owners.iter().flatten() {
+            *owned.entry(email).or_insert(0) += 1;
+        }
+        let Some((leaving, _)) = owned.into_iter().max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0))) else {
+            break;
+        };
+        for owners in &mut owners {
+            owners.remove(leaving);
+        }
+        gone.push(names[leaving].to_string());
+    }
+    (gone.len(), gone)
+}
+
+/// Lines of `git blame --line-porcelain` output by author email, leaving out uncommitted lines
+fn parse_blame(porcelain: &str) -> Blame {
+    let mut blame = Blame::new();
+    let mut name = "";
+    for line in porcelain.lines() {
+        if let Some(author) = line.strip_prefix("author ") {
+            name = author;
+        } else if let Some(email) = line.strip_prefix("author-mail ") {
+            let email = email.trim_start_matches('<').trim_end_matches('>').to_lowercase();
+            if email == "not.committed.yet" {
+                continue;
+            }
+            let entry = blame.entry(email).or_insert_with(|| (name.to_string(), 0));
+            entry.0 = name.to_string();
+            entry.1 += 1;
+        }
+    }
+    blame
+}
+
+/// Directory of the file at `path`, or `.` for the root
+fn directory_of(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((directory, _)) => directory.to_string(),
+        None => ".".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ownership_and_bus_factor_come_from_blame() {
+        let porcelain = |authors: &[(&str, usize)]| {
+            authors.iter()
+                .flat_map(|&(name, lines)| (0..lines).map(move |i| format!(
+                    "{:040x} {} {} 1\nauthor {}\nauthor-mail <{}@example.com>\nauthor-time 1700000000\nsummary Change\nfilename f\n\tline\n",
+                    i, i + 1, i + 1, name, name.to_lowercase()
+                )))
+                .collect::<String>()
+        };
+        let file = |path: &str, authors: &[(&str, usize)]| (path.to_string(), parse_blame(&porcelain(authors)));
+        let uncommitted = "0000000000000000000000000000000000000000 1 1 1\nauthor Not Committed Yet\nauthor-mail <not.committed.yet>\n\tline\n";
+        assert!(parse_blame(uncommitted).is_empty());
+
+        let report = OwnershipReport::from_blame(vec![
+            file("src/core/engine.rs", &[("Ada", 150), ("Grace", 5)]),
+            file("src/core/state.rs", &[("Ada", 60)]),
+            file("src/cli.rs", &[("Grace", 40), ("Linus", 30)]),
+            file("src/web.rs", &[("Linus", 50), ("Ada", 10)]),
+            file("build.rs", &[("Grace", 20)]),
+            ("empty.rs".to_string(), Blame::new()),
+        ]);
+        assert_eq!((report.files, report.lines), (5, 365));
+        assert_eq!(report.authors.iter().map(|a| (a.name.as_str(), a.lines)).collect::<Vec<_>>(), [("Ada", 220), ("Linus", 80), ("Grace", 65)]);
+
+        // Ada owns both core files, Grace cli.rs and build.rs, Linus cli.rs and web.rs:
+        // without Ada and Grace, three of five files have no owner
+        assert_eq!((report.bus_factor, report.key_contributors.as_slice()), (2, ["Ada".to_string(), "Grace".to_string()].as_slice()));
+
+        let directory = |name: &str| report.directories.iter().find(|d| d.directory == name).unwrap();
+        let core = directory("src/core");
+        assert_eq!((core.directory.as_str(), core.lines, core.contributors, core.owner.as_str()), ("src/core", 215, 2, "Ada"));
+        assert!(core.critical);
+        // build.rs is all Grace's, but too small to be critical
+        assert_eq!((directory(".").owner_share, directory(".").critical), (100.0, false));
+        assert_eq!(report.directories.iter().map(|d| d.directory.as_str()).collect::<Vec<_>>(), [".", "src/core", "src"]);
+
+        let markdown = report.to_markdown();
+        assert!(markdown.starts_with("## Ownership\n\n**Bus factor:** 2 (Ada, Grace would have to leave"));
+        assert!(markdown.contains("- `src/core`: 98% of 215 lines by Ada\n"));
+        assert!(markdown.contains("| `src` | 130 | 3 | Linus | 62% |"));
+    }
+}
@@ -1,13 +1,27 @@
-use crate::config::Config;
+use crate::agents::{AnalysisType, OpenAIAgent, TokenUsage};
+use crate::agents::conversation::ConversationStore;
+use crate::config::{Config, WorkspaceQuota};
 use crate::error::{ProcessorError, Result};
 use crate::processors::{ProcessorFactory, PackageProcessor};
 use crate::output_organizer::{self, OutputPaths};
+use crate::manifest;
+use crate::metrics;
+use crate::pipeline;
+use crate::job_queue::{Cursor, JobQuery, JobQueue, JobSort, JOBS_DB_FILE};
+use crate::progress::{self, ProgressAggregator, ProgressEvent};
+use crate::workspace;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex, Notify};
+use tokio::task::{AbortHandle, JoinHandle};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
+use walkdir::WalkDir;
 
 /// Request payload for processing a package
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +32,9 @@ pub struct ProcessRequest {
     pub output_dir: Option<String>,
     /// Optional configuration overrides
     pub config: Option<ProcessConfig>,
+    /// Processor to use instead of detecting one from the URL
+    #[serde(default)]
+    pub processor: Option<String>,
 }
 
 /// Configuration options for processing
@@ -69,8 +86,258 @@ pub struct JobStatus {
     pub current_operation: Option<String>,
     /// Any error message if the job failed
     pub error_message: Option<String>,
-    /// List of output files generated
+    /// Output files generated, relative to the output directory
     pub output_files: Vec<String>,
+    /// Workspace the job was submitted from
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub workspace: Option<String>,
+    /// Tokens used by an AI analysis job
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub usage: Option<TokenUsage>,
+    /// Estimated seconds until a running job finishes processing
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub eta_seconds: Option<u64>,
+}
+
+impl JobStatus {
+    /// Whether the job is still waiting or running
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, JobStatusType::Queued | JobStatusType::Processing)
+    }
+
+    /// Whether a caller confined to `workspace` may see the job; callers
+    /// without a workspace see every job
+    pub fn is_visible_to(&self, workspace: Option<&str>) -> bool {
+        workspace.map_or(true, |name| self.workspace.as_deref() == Some(name))
+    }
+}
+
+/// What a queued job does
+///
+/// Stored as the request's own JSON; requests queued before analysis jobs
+/// existed are processing requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JobRequest {
+    /// AI analysis of a repository
+    Analysis(AnalysisRequest),
+    /// Processing of a URL or path
+    Process(ProcessRequest),
+}
+
+impl From<ProcessRequest> for JobRequest {
+    fn from(request: ProcessRequest) -> Self {
+        Self::Process(request)
+    }
+}
+
+/// `url_type` of AI analysis jobs
+pub const ANALYSIS_JOB_TYPE: &str = "analysis";
+
+/// Live update about a job, streamed to API clients
+///
+/// Each event carries everything known about the job at that moment, so a
+/// client only needs the latest one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JobEvent {
+    /// Job the event is about
+    pub job_id: String,
+    /// Current status of the job
+    pub status: JobStatusType,
+    /// Progress percentage (0-100)
+    pub progress: u8,
+    /// Stage the processor reported last
+    pub stage: Option<String>,
+    /// Files processed so far
+    pub files_processed: Option<usize>,
+    /// Files to process in total
+    pub files_total: Option<usize>,
+    /// Bytes downloaded so far
+    pub bytes_downloaded: Option<u64>,
+    /// Estimated seconds until processing finishes
+    pub eta_seconds: Option<u64>,
+    /// Error message if the job failed
+    pub error_message: Option<String>,
+}
+
+impl JobEvent {
+    /// Whether no more events will follow
+    pub fn is_final(&self) -> bool {
+        !matches!(self.status, JobStatusType::Queued | JobStatusType::Processing)
+    }
+}
+
+impl From<&JobStatus> for JobEvent {
+    fn from(job: &JobStatus) -> Self {
+        Self {
+            job_id: job.job_id.clone(),
+            status: job.status.clone(),
+            progress: job.progress,
+            stage: job.current_operation.clone(),
+            files_processed: None,
+            files_total: None,
+            bytes_downloaded: None,
+            eta_seconds: job.eta_seconds,
+            error_message: job.error_message.clone(),
+        }
+    }
+}
+
+/// A file generated by a job
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JobArtifact {
+    /// Path relative to the job's output directory
+    pub path: String,
+    /// Size on disk in bytes
+    pub size: u64,
+}
+
+/// A queued job handed to a remote worker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimedJob {
+    /// Job to report progress and upload artifacts for
+    pub job_id: String,
+    /// What the job does
+    pub request: JobRequest,
+}
+
+/// How far a remote worker has got with a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerProgress {
+    /// Percent done
+    pub progress: u8,
+    /// What the worker is doing
+    pub operation: String,
+    /// Estimated seconds until processing finishes
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub eta_seconds: Option<u64>,
+}
+
+/// Outcome of a job a remote worker ran
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerOutcome {
+    /// Uploaded files the job generated, relative to its output directory
+    #[serde(default)]
+    pub output_files: Vec<String>,
+    /// Why the job failed, if it did
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Tokens an AI analysis used
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// Longest a remote worker may go without reporting before its job is queued again
+pub const REMOTE_LEASE: Duration = Duration::from_secs(300);
+
+/// Most jobs returned on one page of `GET /api/jobs`
+pub const MAX_JOBS_PER_PAGE: usize = 500;
+
+/// Query parameters of `GET /api/jobs`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobListParams {
+    /// Comma-separated statuses, such as `queued,processing`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Text the job's URL type contains, such as `github`, `pypi` or `analysis`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Only jobs submitted since this RFC 3339 time or `YYYY-MM-DD` date (UTC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    /// Text the job's URL must contain, ignoring case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    /// `created` (the default), `updated` or `url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// `desc` (the default) or `asc`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+    /// Jobs per page, 50 unless given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// `next_page` token of the previous page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+}
+
+impl JobListParams {
+    /// The query these parameters describe, confined to `workspace`
+    pub fn to_query(&self, workspace: Option<&str>) -> Result<JobQuery> {
+        let states = match self.status.as_deref().filter(|status| !status.is_empty()) {
+            Some(status) => status.split(',')
+                .map(|name| {
+                    let name = name.trim();
+                    JobStatusType::ALL.into_iter().find(|status| status.name() == name).ok_or_else(|| {
+                        ProcessorError::Validation(format!("unknown job status '{}'", name))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        let since = self.since.as_deref().map(parse_since).transpose()?;
+        let sort = self.sort.as_deref().map(JobSort::parse).transpose()?.unwrap_or_default();
+        let ascending = match self.order.as_deref() {
+            None | Some("desc") => false,
+            Some("asc") => true,
+            Some(other) => {
+                return Err(ProcessorError::Validation(format!("unknown order '{}' (use asc or desc)", other)));
+            }
+        };
+        let limit = self.limit.unwrap_or(JobQuery::default().limit);
+        if limit == 0 || limit > MAX_JOBS_PER_PAGE {
+            return Err(ProcessorError::Validation(format!("limit must be between 1 and {}", MAX_JOBS_PER_PAGE)));
+        }
+        Ok(JobQuery {
+            workspace: workspace.map(str::to_string),
+            states,
+            source: self.source.clone().filter(|source| !source.is_empty()),
+            since,
+            url_contains: self.q.clone().filter(|q| !q.is_empty()),
+            sort,
+            ascending,
+            limit,
+            after: self.page.as_deref().map(Cursor::decode).transpose()?,
+        })
+    }
+}
+
+fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+        .map_err(|_| ProcessorError::Validation(format!(
+            "since must be an RFC 3339 time or a YYYY-MM-DD date, not '{}'", since
+        )))
+}
+
+/// A page of `GET /api/jobs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobList {
+    /// Jobs on this page
+    pub jobs: Vec<JobStatus>,
+    /// Jobs matching the filters across every page
+    pub total: usize,
+    /// Token for the next page, absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub next_page: Option<String>,
+}
+
+/// A workspace's use of its quota
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkspaceUsage {
+    /// Workspace name
+    pub workspace: String,
+    /// Jobs submitted in the last 24 hours
+    pub jobs_last_day: usize,
+    /// Disk space used by the workspace's output, in bytes
+    pub storage_bytes: u64,
+    /// Limits that apply to the workspace
+    pub quota: WorkspaceQuota,
 }
 
 /// Possible job status types
@@ -89,6 +356,28 @@ pub enum JobStatusType {
     Cancelled,
 }
 
+impl JobStatusType {
+    /// Every status, in lifecycle order
+    pub const ALL: [JobStatusType; 5] = [
+        JobStatusType::Queued,
+        JobStatusType::Processing,
+        JobStatusType::Completed,
+        JobStatusType::Failed,
+        JobStatusType::Cancelled,
+    ];
+
+    /// The status as it appears in JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatusType::Queued => "queued",
+            JobStatusType::Processing => "processing",
+            JobStatusType::Completed => "completed",
+            JobStatusType::Failed => "failed",
+            JobStatusType::Cancelled => "cancelled",
+        }
+    }
+}
+
 /// Health check response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
@@ -109,43 +398,124 @@ pub struct HealthResponse {
 }
 
 /// Job manager for tracking processing jobs
+///
+/// Jobs are kept in a persistent [`JobQueue`] and processed by a pool of
+/// workers started with [`JobManager::spawn_workers`].
 pub struct JobManager {
-    /// Map of job ID to job status
-    jobs: Arc<Mutex<HashMap<String, JobStatus>>>,
-    /// Configuration for the service
-    config: Arc<Config>,
+    /// Persistent store of every job
+    queue: Arc<JobQueue>,
+    /// Abort handles of the jobs workers are processing right now
+    running: Arc<Mutex<HashMap<String, AbortHandle>>>,
+    /// Wakes an idle worker when a job is submitted
+    wake: Arc<Notify>,
+    /// Held while a workspace's quota is checked and its job queued, so
+    /// simultaneous submissions cannot both take the last slot
+    admission: Mutex<()>,
+    /// Latest event of each job being processed
+    live: Arc<std::sync::Mutex<HashMap<String, JobEvent>>>,
+    /// Every job event, for streaming to clients
+    events: broadcast::Sender<JobEvent>,
+    /// Configuration for the service; reloadable settings change while serving
+    config: std::sync::RwLock<Arc<Config>>,
+    /// Output directory jobs write under unless their workspace's
+    output_dir: PathBuf,
+    /// Local workers running, 0 until [`JobManager::spawn_workers`] is called
+    workers: std::sync::Mutex<usize>,
     /// Service start time for uptime calculation
     start_time: DateTime<Utc>,
 }
 
 impl JobManager {
-    /// Create a new job manager
-    pub fn new(config: Config) -> Self {
-        Self {
-            jobs: Arc::new(Mutex::new(HashMap::new())),
-            config: Arc::new(config),
+    /// Create a job manager, opening the job queue in the output directory
+    pub fn new(config: Config) -> Result<Self> {
+        let queue = JobQueue::open(&config.output_dir.join(JOBS_DB_FILE))?;
+        Ok(Self {
+            queue: Arc::new(queue),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            wake: Arc::new(Notify::new()),
+            admission: Mutex::new(()),
+            live: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            events: broadcast::channel(256).0,
+            output_dir: config.output_dir.clone(),
+            config: std::sync::RwLock::new(Arc::new(config)),
+            workers: std::sync::Mutex::new(0),
             start_time: Utc::now(),
+        })
+    }
+
+    /// Starts `processing.max_concurrent_jobs` workers that process queued jobs until dropped
+    ///
+    /// The pool follows later changes to the setting made with [`JobManager::set_config`].
+    pub fn spawn_workers(self: Arc<Self>) -> Vec<JoinHandle<()>> {
+        let mut workers = self.workers.lock().unwrap_or_else(|e| e.into_inner());
+        let wanted = self.config().processing.max_concurrent_jobs.max(1);
+        let added = wanted.saturating_sub(*workers);
+        *workers += added;
+        (0..added)
+            .map(|_| {
+                let manager = Arc::clone(&self);
+                tokio::spawn(async move { manager.work().await })
+            })
+            .collect()
+    }
+
+    /// Switches to `config` for jobs started from now on
+    ///
+    /// A running worker pool grows or shrinks to the new
+    /// `processing.max_concurrent_jobs`; workers beyond it stop once their
+    /// current job is done. The output directory stays as it was.
+    pub fn set_config(self: &Arc<Self>, config: Config) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        let started = *self.workers.lock().unwrap_or_else(|e| e.into_inner()) > 0;
+        if started {
+            Arc::clone(self).spawn_workers();
+            // Idle workers check whether they are still needed
+            self.wake.notify_waiters();
+        }
+    }
+
+    /// Stops the calling worker if the pool is larger than configured
+    fn retire_worker(&self) -> bool {
+        let mut workers = self.workers.lock().unwrap_or_else(|e| e.into_inner());
+        let surplus = *workers > self.config().processing.max_concurrent_jobs.max(1);
+        if surplus {
+            *workers -= 1;
         }
+        surplus
     }
 
-    /// Submit a new processing job
-    pub async fn submit_job(&self, request: ProcessRequest) -> Result<ProcessResponse> {
+    /// Submit a new processing job, from `workspace` if the caller is confined to one
+    ///
+    /// A workspace's jobs write under its own directory and count against its
+    /// quota, and may not process local paths or plugins; see [`crate::workspace`].
+    pub async fn submit_job(&self, request: ProcessRequest, workspace: Option<&str>) -> Result<ProcessResponse> {
         use uuid::Uuid;
         let job_id = Uuid::new_v4().to_string();
         // Normalize user input to handle trailing spaces and quoted paths for local processing
         let mut request = request;
         let normalized_url = crate::utils::normalize_url_or_path(&request.url);
         request.url = normalized_url;
-        let url_type = ProcessorFactory::detect_url_type(&request.url);
+        if let Some(name) = workspace {
+            workspace::check_source(name, &request.url, request.processor.as_deref())?;
+        }
+        let url_type = ProcessorFactory::url_type(&request.url, request.processor.as_deref());
         
         // Validate the URL first
-        let processor = ProcessorFactory::create_processor(&request.url)?;
+        let processor = ProcessorFactory::create_for(&request.url, request.processor.as_deref())?;
         processor.validate(&request.url).await?;
 
-        let output_dir = if let Some(ref custom_dir) = request.output_dir {
-            PathBuf::from(custom_dir)
-        } else {
-            self.config.output_dir.clone()
+        let output_dir = match workspace {
+            Some(name) => {
+                let root = workspace::root(&self.output_dir, name);
+                let output_dir = workspace::resolve_output_dir(&root, request.output_dir.as_deref())?;
+                // Workers read the output directory from the stored request
+                request.output_dir = Some(output_dir.to_string_lossy().to_string());
+                output_dir
+            }
+            None => match request.output_dir {
+                Some(ref custom_dir) => PathBuf::from(custom_dir),
+                None => self.output_dir.clone(),
+            },
         };
 
         let job_status = JobStatus {
@@ -157,56 +527,262 @@ impl JobManager {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             progress: 0,
-            current_operation: Some("Validating URL".to_string()),
+            current_operation: Some("Waiting for a worker".to_string()),
             error_message: None,
             output_files: Vec::new(),
+            workspace: workspace.map(str::to_string),
+            usage: None,
+            eta_seconds: None,
         };
+        self.enqueue(&job_status, &request.into()).await?;
 
-        // Store the job
-        {
-            let mut jobs = self.jobs.lock().await;
-            jobs.insert(job_id.clone(), job_status);
+        Ok(ProcessResponse {
+            job_id,
+            status: "queued".to_string(),
+            url_type,
+            output_dir: output_dir.to_string_lossy().to_string(),
+            message: "Job queued for processing".to_string(),
+        })
+    }
+
+    /// Submit an AI analysis of a repository, run as a job like processing
+    ///
+    /// The result is written to the `analyses` directory of the output (or of
+    /// the workspace) and the tokens it used are recorded with the job.
+    pub async fn submit_analysis(&self, request: AnalysisRequest, workspace: Option<&str>) -> Result<ProcessResponse> {
+        if request.repository.trim().is_empty() || request.analysis_type.trim().is_empty() {
+            return Err(ProcessorError::Validation("repository and analysis_type are required".into()));
         }
+        if let Some(name) = workspace {
+            workspace::check_source(name, &crate::utils::normalize_url_or_path(&request.repository), None)?;
+        }
+        // Fail now rather than in the worker when analysis is not configured
+        OpenAIAgent::from_env()?;
 
-        // Start processing in background
-        let jobs_clone = Arc::clone(&self.jobs);
-        let config_clone = Arc::clone(&self.config);
-        let job_id_clone = job_id.clone();
-        let request_clone = request.clone();
-        
-        tokio::spawn(async move {
-            Self::process_job(jobs_clone, config_clone, job_id_clone, request_clone).await;
-        });
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let base = match workspace {
+            Some(name) => workspace::root(&self.output_dir, name),
+            None => self.output_dir.clone(),
+        };
+        let output_dir = base.join("analyses");
+        let job_status = JobStatus {
+            job_id: job_id.clone(),
+            status: JobStatusType::Queued,
+            url: request.repository.clone(),
+            url_type: ANALYSIS_JOB_TYPE.to_string(),
+            output_dir: output_dir.clone(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            progress: 0,
+            current_operation: Some("Waiting for a worker".to_string()),
+            error_message: None,
+            output_files: Vec::new(),
+            workspace: workspace.map(str::to_string),
+            usage: None,
+            eta_seconds: None,
+        };
+        self.enqueue(&job_status, &JobRequest::Analysis(request)).await?;
 
         Ok(ProcessResponse {
             job_id,
             status: "queued".to_string(),
-            url_type,
+            url_type: ANALYSIS_JOB_TYPE.to_string(),
             output_dir: output_dir.to_string_lossy().to_string(),
-            message: "Job queued for processing".to_string(),
+            message: "Analysis queued".to_string(),
         })
     }
 
-    /// Get the status of a job
+    /// Analysis jobs visible from `workspace`, newest first, with the tokens they used in total
+    pub async fn list_analyses(&self, workspace: Option<&str>) -> Result<(Vec<JobStatus>, TokenUsage)> {
+        let analyses: Vec<JobStatus> = self.list_jobs(workspace).await?
+            .into_iter()
+            .filter(|job| job.url_type == ANALYSIS_JOB_TYPE)
+            .collect();
+        let mut total = TokenUsage::default();
+        for usage in analyses.iter().filter_map(|job| job.usage.as_ref()) {
+            total.add(usage);
+        }
+        Ok((analyses, total))
+    }
+
+    /// Stores a job, checking its workspace's quota first; a worker picks it up from the queue
+    async fn enqueue(&self, job: &JobStatus, request: &JobRequest) -> Result<()> {
+        {
+            let _admission = self.admission.lock().await;
+            if let Some(name) = job.workspace.as_deref() {
+                self.check_quota(name).await?;
+            }
+            self.queue.push(job, request)?;
+        }
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Get the status of a job, with its live progress and estimated time left while it is processed
     pub async fn get_job_status(&self, job_id: &str) -> Result<JobStatus> {
-        let jobs = self.jobs.lock().await;
-        jobs.get(job_id)
-            .cloned()
-            .ok_or_else(|| ProcessorError::Message(format!("Job not found: {}", job_id)))
+        let mut job = self.queue.get(job_id)?
+            .ok_or_else(|| ProcessorError::Message(format!("Job not found: {}", job_id)))?;
+        if job.is_active() {
+            if let Some(event) = self.live.lock().unwrap_or_else(|e| e.into_inner()).get(job_id) {
+                job.progress = job.progress.max(event.progress);
+                job.eta_seconds = event.eta_seconds;
+            }
+        }
+        Ok(job)
+    }
+
+    /// Latest event of a job: its live progress while processing, otherwise its stored status
+    pub async fn get_job_event(&self, job_id: &str) -> Result<JobEvent> {
+        if let Some(event) = self.live.lock().unwrap_or_else(|e| e.into_inner()).get(job_id) {
+            return Ok(event.clone());
+        }
+        Ok(JobEvent::from(&self.get_job_status(job_id).await?))
+    }
+
+    /// Receives the events of every job from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    /// A job's current event followed by every change, ending after the final event
+    ///
+    /// A slow consumer skips intermediate events; every event is a full
+    /// snapshot, so nothing is lost but granularity.
+    pub async fn watch_job(&self, job_id: &str) -> Result<impl Stream<Item = JobEvent> + Send + 'static> {
+        // Subscribe before reading the current state so no change falls in between
+        let receiver = self.subscribe();
+        let current = self.get_job_event(job_id).await?;
+        let job_id = job_id.to_string();
+        Ok(futures::stream::unfold(
+            (Some(current), receiver, false),
+            move |(next, mut receiver, finished)| {
+                let job_id = job_id.clone();
+                async move {
+                    if finished {
+                        return None;
+                    }
+                    let event = match next {
+                        Some(event) => event,
+                        None => loop {
+                            match receiver.recv().await {
+                                Ok(event) if event.job_id == job_id => break event,
+                                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                                Err(RecvError::Closed) => return None,
+                            }
+                        },
+                    };
+                    let finished = event.is_final();
+                    Some((event, (None, receiver, finished)))
+                }
+            },
+        ))
+    }
+
+    /// Jobs visible from `workspace` (every job for callers without one), newest first
+    pub async fn list_jobs(&self, workspace: Option<&str>) -> Result<Vec<JobStatus>> {
+        match workspace {
+            Some(name) => self.queue.list_workspace(name),
+            None => self.queue.list(),
+        }
+    }
+
+    /// One page of the jobs visible from `workspace` that match `params`
+    pub async fn query_jobs(&self, params: &JobListParams, workspace: Option<&str>) -> Result<JobList> {
+        let page = self.queue.query(&params.to_query(workspace)?)?;
+        Ok(JobList { jobs: page.jobs, total: page.total, next_page: page.next.map(|cursor| cursor.encode()) })
+    }
+
+    /// What a workspace has used of its quota
+    pub async fn workspace_usage(&self, name: &str) -> Result<WorkspaceUsage> {
+        let since = Utc::now() - chrono::Duration::days(1);
+        let jobs_last_day = self.queue.count_submitted(name, since)?;
+        let root = workspace::root(&self.output_dir, name);
+        let storage_bytes = tokio::task::spawn_blocking(move || workspace::storage_used(&root))
+            .await
+            .map_err(|e| ProcessorError::Message(e.to_string()))?;
+        Ok(WorkspaceUsage {
+            workspace: name.to_string(),
+            jobs_last_day,
+            storage_bytes,
+            quota: self.config().server.quota_for(name).clone(),
+        })
+    }
+
+    /// Refuses a job from a workspace that has used up its quota
+    async fn check_quota(&self, name: &str) -> Result<()> {
+        let usage = self.workspace_usage(name).await?;
+        workspace::check_quota(name, &usage.quota, usage.jobs_last_day, usage.storage_bytes)
+    }
+
+    /// Files generated by a job that are still on disk
+    pub async fn get_job_artifacts(&self, job_id: &str) -> Result<Vec<JobArtifact>> {
+        let job = self.get_job_status(job_id).await?;
+        Ok(job.output_files.iter()
+            .filter_map(|path| {
+                let metadata = std::fs::metadata(job.output_dir.join(path)).ok()?;
+                Some(JobArtifact { path: path.clone(), size: metadata.len() })
+            })
+            .collect())
+    }
+
+    /// A job processing `url` for `workspace` that is still waiting for a worker
+    pub async fn queued_job(&self, url: &str, workspace: Option<&str>) -> Result<Option<JobStatus>> {
+        Ok(self.list_jobs(workspace).await?.into_iter().find(|job| {
+            job.status == JobStatusType::Queued
+                && job.url == url
+                && job.url_type != ANALYSIS_JOB_TYPE
+                && job.workspace.as_deref() == workspace
+        }))
+    }
+
+    /// Where a file a job generated is stored
+    ///
+    /// Only paths listed among the job's output files resolve, so a request
+    /// cannot reach anything else in the output directory.
+    pub async fn artifact_file(&self, job_id: &str, path: &str) -> Result<PathBuf> {
+        let job = self.get_job_status(job_id).await?;
+        if !job.output_files.iter().any(|file| file == path) {
+            return Err(ProcessorError::Message(format!("Artifact not found: {}", path)));
+        }
+        Ok(job.output_dir.join(path))
+    }
+
+    /// Cancels a queued or running job, or forgets a finished one
+    ///
+    /// Returns the job as it was left: `cancelled` for a job that was still
+    /// active, otherwise its final status. Generated files are kept.
+    pub async fn delete_job(&self, job_id: &str) -> Result<JobStatus> {
+        let job = self.get_job_status(job_id).await?;
+        if job.is_active() {
+            self.queue.cancel(job_id)?;
+            if let Some(handle) = self.running.lock().await.remove(job_id) {
+                handle.abort();
+            }
+            let job = self.get_job_status(job_id).await?;
+            let _ = self.events.send(JobEvent::from(&job));
+            return Ok(job);
+        }
+        self.queue.remove(job_id)?;
+        Ok(job)
     }
 
     /// Get output directory
     pub fn output_dir(&self) -> &std::path::Path {
-        &self.config.output_dir
+        &self.output_dir
+    }
+
+    /// Configuration the service runs with
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap_or_else(|e| e.into_inner()))
     }
 
     /// Get service health information
     pub async fn get_health(&self) -> HealthResponse {
-        let jobs = self.jobs.lock().await;
-        let active_jobs = jobs.values()
-            .filter(|job| job.status == JobStatusType::Processing || job.status == JobStatusType::Queued)
+        let jobs = self.queue.list().unwrap_or_default();
+        let active_jobs = jobs.iter()
+            .filter(|job| job.is_active())
             .count();
-        let completed_jobs = jobs.values()
+        let completed_jobs = jobs.iter()
             .filter(|job| job.status == JobStatusType::Completed)
             .count();
 
@@ -221,89 +797,395 @@ impl JobManager {
         }
     }
 
-    /// Internal method to process a job
-    async fn process_job(
-        jobs: Arc<Mutex<HashMap<String, JobStatus>>>,
-        config: Arc<Config>,
-        job_id: String,
-        request: ProcessRequest,
-    ) {
-        // Update job status to processing
-        {
-            let mut jobs_guard = jobs.lock().await;
-            if let Some(job) = jobs_guard.get_mut(&job_id) {
-                job.status = JobStatusType::Processing;
-                job.updated_at = Utc::now();
-                job.progress = 10;
-                job.current_operation = Some("Starting processing".to_string());
+    /// Starts the task that queues jobs again when their remote worker stops reporting
+    ///
+    /// Used instead of [`JobManager::spawn_workers`] when `server.remote_workers`
+    /// leaves the jobs to workers on other machines.
+    pub fn spawn_lease_keeper(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(REMOTE_LEASE / 5).await;
+                let before = Utc::now() - chrono::Duration::from_std(REMOTE_LEASE).unwrap_or_default();
+                match self.queue.requeue_stale(before) {
+                    Ok(0) => {}
+                    Ok(requeued) => warn!("Requeued {} job(s) whose worker stopped reporting", requeued),
+                    Err(e) => warn!("Failed to requeue stale jobs: {}", e),
+                }
             }
-        }
+        })
+    }
 
-        // Determine output directory
-        let output_dir = if let Some(custom_dir) = request.output_dir {
-            PathBuf::from(custom_dir)
-        } else {
-            config.output_dir.clone()
+    /// Hands the oldest queued job to a remote worker
+    pub async fn claim_remote(&self) -> Result<Option<ClaimedJob>> {
+        let Some((job, request)) = self.queue.claim_next()? else {
+            return Ok(None);
         };
+        self.reporter(&job.job_id).update(|event| *event = JobEvent::from(&job));
+        Ok(Some(ClaimedJob { job_id: job.job_id, request }))
+    }
 
-        // Process the request
-        let result = async {
-            // Create processor
-            let processor = ProcessorFactory::create_processor(&request.url)?;
-            
-            // Update progress
-            {
-                let mut jobs_guard = jobs.lock().await;
-                if let Some(job) = jobs_guard.get_mut(&job_id) {
-                    job.progress = 30;
-                    job.current_operation = Some("Processing package".to_string());
-                }
-            }
+    /// Records a remote worker's progress, returning whether the job should go on
+    ///
+    /// A job that was cancelled, or queued again after its lease ran out, is
+    /// no longer the worker's to finish.
+    pub fn report_remote(&self, job_id: &str, progress: &WorkerProgress) -> Result<bool> {
+        if !self.queue.set_progress(job_id, progress.progress.min(99), &progress.operation)? {
+            return Ok(false);
+        }
+        self.reporter(job_id).update(|event| {
+            event.status = JobStatusType::Processing;
+            event.progress = progress.progress.min(99);
+            event.stage = Some(progress.operation.clone());
+            event.eta_seconds = progress.eta_seconds;
+        });
+        Ok(true)
+    }
+
+    /// Stores a file a remote worker generated for a running job, returning whether the job is still running
+    ///
+    /// `path` is relative to the job's output directory and may not leave it.
+    pub async fn store_remote_artifact(&self, job_id: &str, path: &str, data: &[u8]) -> Result<bool> {
+        let job = self.get_job_status(job_id).await?;
+        if job.status != JobStatusType::Processing {
+            return Ok(false);
+        }
+        let relative = Path::new(path);
+        if path.is_empty() || !relative.components().all(|part| matches!(part, std::path::Component::Normal(_))) {
+            return Err(ProcessorError::Validation(format!("artifact path must stay inside the output directory: {}", path)));
+        }
+        let target = job.output_dir.join(relative);
+        if let Some(dir) = target.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&target, data).await?;
+        crate::output::storage::upload_artifact(&target).await?;
+        Ok(true)
+    }
 
-            // Process the package
-            processor.process(&request.url, &output_dir, &config).await?;
+    /// Records the outcome a remote worker reports, returning whether the job was still running
+    ///
+    /// Only output files that were uploaded are listed as the job's artifacts.
+    pub async fn finish_remote(&self, job_id: &str, outcome: WorkerOutcome) -> Result<bool> {
+        let job = self.get_job_status(job_id).await?;
+        if job.status != JobStatusType::Processing {
+            return Ok(false);
+        }
+        let reporter = self.reporter(job_id);
+        if let Some(usage) = &outcome.usage {
+            reporter.set_usage(usage);
+        }
+        reporter.finish(match outcome.error {
+            Some(error) => Err(error),
+            None => Ok(outcome.output_files.into_iter()
+                .filter(|file| job.output_dir.join(file).is_file())
+                .collect()),
+        });
+        self.live.lock().unwrap_or_else(|e| e.into_inner()).remove(job_id);
+        Ok(true)
+    }
 
-            // Update progress
-            {
-                let mut jobs_guard = jobs.lock().await;
-                if let Some(job) = jobs_guard.get_mut(&job_id) {
-                    job.progress = 80;
-                    job.current_operation = Some("Organizing output".to_string());
-                }
-            }
+    /// Reporter recording a job's progress in the queue and streaming it to clients
+    fn reporter(&self, job_id: &str) -> JobReporter {
+        JobReporter {
+            job_id: job_id.to_string(),
+            queue: Arc::clone(&self.queue),
+            live: Arc::clone(&self.live),
+            events: self.events.clone(),
+            last_sent: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
 
-            // Organize output if requested
-            if request.config.as_ref()
-                .and_then(|c| c.organize_output)
-                .unwrap_or(true) {
-                if let Err(e) = output_organizer::organize_output(&output_dir) {
-                    eprintln!("Warning: Failed to organize output: {}", e);
+    /// Worker loop: process queued jobs one at a time, oldest first, until the pool shrinks
+    async fn work(&self) {
+        while !self.retire_worker() {
+            match self.queue.claim_next() {
+                Ok(Some((job, request))) => {
+                    let reporter = self.reporter(&job.job_id);
+                    reporter.update(|event| *event = JobEvent::from(&job));
+                    let task = match request {
+                        JobRequest::Process(request) => {
+                            tokio::spawn(Self::process_job(reporter, self.config(), request))
+                        }
+                        JobRequest::Analysis(request) => {
+                            tokio::spawn(Self::analyze_job(reporter, self.config(), job.output_dir.clone(), request))
+                        }
+                    };
+                    self.running.lock().await.insert(job.job_id.clone(), task.abort_handle());
+                    // An aborted task means the job was cancelled, which is already recorded
+                    let _ = task.await;
+                    self.running.lock().await.remove(&job.job_id);
+                    self.live.lock().unwrap_or_else(|e| e.into_inner()).remove(&job.job_id);
+                }
+                // Submissions wake a worker; the timeout covers jobs pushed by other means
+                Ok(None) => {
+                    let _ = tokio::time::timeout(Duration::from_secs(5), self.wake.notified()).await;
+                }
+                Err(e) => {
+                    warn!("Failed to read the job queue: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
                 }
             }
+        }
+    }
+
+    /// Runs an AI analysis job, writing the result as Markdown and JSON
+    async fn analyze_job(reporter: JobReporter, config: Arc<Config>, output_dir: PathBuf, request: AnalysisRequest) {
+        let step = |percent: u8, operation: &str| reporter.set_progress(percent, operation);
+        let result = run_analysis(&config, &request, &reporter.job_id, &output_dir, step).await;
+        if let Ok((_, usage)) = &result {
+            reporter.set_usage(usage);
+        }
+        reporter.finish(result.map(|(files, _)| files).map_err(|e| e.to_string()));
+    }
+
+    /// Internal method to process a job
+    async fn process_job(reporter: JobReporter, config: Arc<Config>, request: ProcessRequest) {
+        // Determine output directory
+        let output_dir = match &request.output_dir {
+            Some(custom_dir) => PathBuf::from(custom_dir),
+            None => config.output_dir.clone(),
+        };
 
-            Ok::<(), ProcessorError>(())
-        }.await;
+        let step = |percent: u8, operation: &str| reporter.set_progress(percent, operation);
+        let result = run_process(&config, &request, &output_dir, reporter.sink(), step).await;
 
         // Update final job status
-        {
-            let mut jobs_guard = jobs.lock().await;
-            if let Some(job) = jobs_guard.get_mut(&job_id) {
-                match result {
-                    Ok(_) => {
-                        job.status = JobStatusType::Completed;
-                        job.progress = 100;
-                        job.current_operation = Some("Completed successfully".to_string());
-                    },
-                    Err(e) => {
-                        job.status = JobStatusType::Failed;
-                        job.error_message = Some(e.to_string());
-                        job.current_operation = Some("Failed".to_string());
+        reporter.finish(result.map_err(|e| e.to_string()));
+    }
+}
+
+/// Runs an AI analysis of `request.repository`, writing the result as Markdown and JSON to `output_dir`
+///
+/// Returns the files written, relative to `output_dir`, and the tokens the
+/// analysis used. `step` is told how far the analysis has got.
+pub(crate) async fn run_analysis(
+    config: &Config,
+    request: &AnalysisRequest,
+    job_id: &str,
+    output_dir: &Path,
+    step: impl Fn(u8, &str),
+) -> Result<(Vec<String>, TokenUsage)> {
+    step(10, "Starting analysis");
+    // The repository is processed into context with the server's settings
+    let agent = OpenAIAgent::from_env()?.with_processing_config(config.clone());
+    let analysis_type = AnalysisType::from_name(&request.analysis_type);
+    // Custom analysis types are prompts, too long for a file name
+    let kind = match analysis_type {
+        AnalysisType::Custom(_) => "custom",
+        _ => request.analysis_type.as_str(),
+    };
+    let stem = format!("{}_{}", kind, job_id);
+    step(30, "Analyzing repository");
+    let analysis = agent.analyze_repository(crate::agents::AnalysisRequest {
+        repository: request.repository.clone(),
+        analysis_type,
+        context: request.context.clone(),
+        parameters: HashMap::new(),
+    }).await?;
+
+    step(80, "Saving analysis");
+    tokio::fs::create_dir_all(output_dir).await?;
+    let markdown = format!("{}.md", stem);
+    let json = format!("{}.json", stem);
+    tokio::fs::write(output_dir.join(&markdown), &analysis.content).await?;
+    tokio::fs::write(output_dir.join(&json), serde_json::to_vec_pretty(&analysis)?).await?;
+    Ok((vec![markdown, json], analysis.usage))
+}
+
+/// Processes `request.url` into `output_dir`, returning the files written relative to `output_dir`
+///
+/// What the processor reports goes to `sink`, and `step` is told how far
+/// processing has got.
+pub(crate) async fn run_process(
+    config: &Config,
+    request: &ProcessRequest,
+    output_dir: &Path,
+    sink: progress::ProgressSink,
+    step: impl Fn(u8, &str),
+) -> Result<Vec<String>> {
+    step(10, "Starting processing");
+    // Create processor
+    let processor = ProcessorFactory::create_for(&request.url, request.processor.as_deref())?;
+    
+    // Update progress
+    step(30, "Processing package");
+
+    // Process the package, noting which files it writes and streaming what it reports
+    let processing = pipeline::staged(processor.process(&request.url, output_dir, config));
+    let processing = manifest::collect(metrics::record_processing(processor.name(), processing));
+    let (processed, written) = progress::with_sink(sink, processing).await;
+    processed?;
+
+    // Update progress
+    step(80, "Organizing output");
+
+    // Organize output if requested
+    if request.config.as_ref()
+        .and_then(|c| c.organize_output)
+        .unwrap_or(true) {
+        if let Err(e) = output_organizer::organize_output(output_dir) {
+            eprintln!("Warning: Failed to organize output: {}", e);
+        }
+    }
+    
+    // Generate index if requested
+    if request.config.as_ref()
+        .and_then(|c| c.generate_index)
+        .unwrap_or(false) {
+        if let Err(e) = output_organizer::generate_index(output_dir) {
+            eprintln!("Warning: Failed to generate index: {}", e);
+        }
+    }
+
+    Ok(locate_artifacts(output_dir, &written))
+}
+
+/// Records a running job's progress in the queue and streams it as [`JobEvent`]s
+#[derive(Clone)]
+struct JobReporter {
+    job_id: String,
+    queue: Arc<JobQueue>,
+    live: Arc<std::sync::Mutex<HashMap<String, JobEvent>>>,
+    events: broadcast::Sender<JobEvent>,
+    /// When a file or download count was last sent
+    last_sent: Arc<std::sync::Mutex<Option<std::time::Instant>>>,
+}
+
+impl JobReporter {
+    /// Minimum time between streamed file and download counts
+    const COUNT_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Records an overall progress step
+    fn set_progress(&self, percent: u8, operation: &str) {
+        if let Err(e) = self.queue.set_progress(&self.job_id, percent, operation) {
+            warn!("Failed to record progress of job {}: {}", self.job_id, e);
+        }
+        self.update(|event| {
+            event.status = JobStatusType::Processing;
+            event.progress = percent;
+            event.stage = Some(operation.to_string());
+            // Estimates only cover the processor's work
+            event.eta_seconds = None;
+        });
+    }
+
+    /// Sink for the progress the processor reports
+    ///
+    /// Processing runs from 30% to 80% of the job; counts move the job's
+    /// progress through that range and update its estimated time left.
+    fn sink(&self) -> progress::ProgressSink {
+        let reporter = self.clone();
+        let aggregator = ProgressAggregator::new(1);
+        let record = aggregator.sink(0);
+        Arc::new(move |reported| {
+            record(reported.clone());
+            let overall = aggregator.snapshot();
+            let estimate = |event: &mut JobEvent| {
+                event.progress = event.progress.max(30 + overall.percent / 2);
+                event.eta_seconds = overall.eta.map(|eta| eta.as_secs());
+            };
+            match reported {
+                ProgressEvent::Stage { stage } => reporter.update(|event| {
+                    event.stage = Some(stage);
+                    estimate(event);
+                }),
+                ProgressEvent::Files { processed, total } => {
+                    if processed == total || reporter.count_due() {
+                        reporter.update(|event| {
+                            event.files_processed = Some(processed);
+                            event.files_total = Some(total);
+                            estimate(event);
+                        });
+                    }
+                }
+                ProgressEvent::Download { bytes } => {
+                    if reporter.count_due() {
+                        reporter.update(|event| {
+                            event.bytes_downloaded = Some(bytes);
+                            estimate(event);
+                        });
                     }
                 }
-                job.updated_at = Utc::now();
             }
+        })
+    }
+
+    /// Records the tokens the job used
+    fn set_usage(&self, usage: &TokenUsage) {
+        if let Err(e) = self.queue.set_usage(&self.job_id, usage) {
+            warn!("Failed to record token usage of job {}: {}", self.job_id, e);
         }
     }
+
+    /// Records the outcome and sends the final event
+    fn finish(&self, outcome: std::result::Result<Vec<String>, String>) {
+        if let Err(e) = self.queue.finish(&self.job_id, outcome) {
+            warn!("Failed to record outcome of job {}: {}", self.job_id, e);
+        }
+        if let Ok(Some(job)) = self.queue.get(&self.job_id) {
+            self.update(|event| {
+                let live = std::mem::replace(event, JobEvent::from(&job));
+                event.files_processed = live.files_processed;
+                event.files_total = live.files_total;
+                event.bytes_downloaded = live.bytes_downloaded;
+            });
+        }
+    }
+
+    /// Applies `change` to the job's latest event and sends the result
+    fn update(&self, change: impl FnOnce(&mut JobEvent)) {
+        let event = {
+            let mut live = self.live.lock().unwrap_or_else(|e| e.into_inner());
+            let event = live.entry(self.job_id.clone()).or_insert_with(|| JobEvent {
+                job_id: self.job_id.clone(),
+                status: JobStatusType::Processing,
+                progress: 0,
+                stage: None,
+                files_processed: None,
+                files_total: None,
+                bytes_downloaded: None,
+                eta_seconds: None,
+                error_message: None,
+            });
+            change(event);
+            event.clone()
+        };
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Whether enough time has passed to send another count
+    fn count_due(&self) -> bool {
+        let mut last_sent = self.last_sent.lock().unwrap_or_else(|e| e.into_inner());
+        let due = last_sent.map_or(true, |sent| sent.elapsed() >= Self::COUNT_INTERVAL);
+        if due {
+            *last_sent = Some(std::time::Instant::now());
+        }
+        due
+    }
+}
+
+/// Where recorded artifacts ended up, relative to `output_dir`
+///
+/// Organizing the output moves files into category directories, so files no
+/// longer at their recorded path are looked up by name.
+fn locate_artifacts(output_dir: &Path, written: &[PathBuf]) -> Vec<String> {
+    let mut by_name: HashMap<String, PathBuf> = HashMap::new();
+    if written.iter().any(|path| !path.exists()) {
+        for entry in WalkDir::new(output_dir).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+            by_name.insert(entry.file_name().to_string_lossy().into_owned(), entry.into_path());
+        }
+    }
+    let mut located: Vec<String> = written.iter()
+        .filter_map(|path| match path.exists() {
+            true => Some(path.clone()),
+            false => by_name.get(path.file_name()?.to_string_lossy().as_ref()).cloned(),
+        })
+        .map(|path| path.strip_prefix(output_dir).unwrap_or(&path).to_string_lossy().into_owned())
+        .collect();
+    located.sort();
+    located.dedup();
+    located
 }
 
 impl Clone for ProcessRequest {
@@ -312,12 +1194,13 @@ impl Clone for ProcessRequest {
             url: self.url.clone(),
             output_dir: self.output_dir.clone(),
             config: self.config.clone(),
+            processor: self.processor.clone(),
         }
     }
 }
 
 /// AI Analysis endpoints
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisRequest {
     /// Repository URL to analyze
     pub repository: String,
@@ -338,6 +1221,8 @@ pub struct AnalysisResponse {
     pub confidence: f32,
     /// Analysis type
     pub analysis_type: String,
+    /// Tokens used by the analysis
+    pub usage: TokenUsage,
 }
 
 /// Request to start a conversation about a repository
@@ -357,7 +1242,8 @@ pub struct ConversationResponse {
 /// Request to send a message in a conversation
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageRequest {
-    /// Conversation ID
+    /// Conversation ID; taken from the path by the server
+    #[serde(default)]
     pub conversation_id: String,
     /// Message to send
     pub message: String,
@@ -371,8 +1257,15 @@ pub struct MessageResponse {
 }
 
 /// Analyze repository using AI
-pub async fn analyze_repository(request: AnalysisRequest) -> Result<AnalysisResponse> {
-    use crate::agents::{OpenAIAgent, AnalysisRequest as AgentRequest, AnalysisType};
+///
+/// Callers confined to `workspace` may only analyze sources fetched from the
+/// network, as for jobs.
+pub async fn analyze_repository(request: AnalysisRequest, workspace: Option<&str>) -> Result<AnalysisResponse> {
+    use crate::agents::AnalysisRequest as AgentRequest;
+    
+    if let Some(name) = workspace {
+        workspace::check_source(name, &crate::utils::normalize_url_or_path(&request.repository), None)?;
+    }
     
     // Create OpenAI agent
     let agent = OpenAIAgent::from_env().map_err(|e| {
@@ -380,14 +1273,7 @@ pub async fn analyze_repository(request: AnalysisRequest) -> Result<AnalysisResp
     })?;
     
     // Parse analysis type
-    let analysis_type = match request.analysis_type.as_str() {
-        "documentation" => AnalysisType::Documentation,
-        "security" => AnalysisType::SecurityAudit,
-        "code_review" => AnalysisType::CodeReview,
-        "examples" => AnalysisType::Examples,
-        "api_docs" => AnalysisType::ApiDocumentation,
-        custom => AnalysisType::Custom(custom.to_string()),
-    };
+    let analysis_type = AnalysisType::from_name(&request.analysis_type);
     
     // Create analysis request
     let agent_request = AgentRequest {
@@ -405,29 +1291,254 @@ pub async fn analyze_repository(request: AnalysisRequest) -> Result<AnalysisResp
         result: result.content,
         confidence: result.confidence,
         analysis_type: request.analysis_type,
+        usage: result.usage,
     })
 }
 
-/// Start a conversation about a repository
-pub async fn start_conversation(request: ConversationRequest) -> Result<ConversationResponse> {
-    use crate::agents::OpenAIAgent;
+/// Start a conversation about a repository, storing it in `store` for `workspace`
+pub async fn start_conversation(
+    store: &ConversationStore,
+    config: &Config,
+    request: ConversationRequest,
+    workspace: Option<&str>,
+) -> Result<ConversationResponse> {
+    if let Some(name) = workspace {
+        workspace::check_source(name, &crate::utils::normalize_url_or_path(&request.repository), None)?;
+    }
     
     // Create OpenAI agent
     let agent = OpenAIAgent::from_env().map_err(|e| {
         ProcessorError::new(&format!("Failed to create OpenAI agent: {}", e))
-    })?;
+    })?.with_processing_config(config.clone());
     
     // Start conversation
-    let context = agent.start_conversation(request.repository).await?;
+    let mut context = agent.start_conversation(request.repository).await?;
+    context.workspace = workspace.map(str::to_string);
+    store.save(&context)?;
     
     Ok(ConversationResponse {
         conversation_id: context.id,
     })
 }
 
-/// Send a message in a conversation
-pub async fn send_message(_request: MessageRequest) -> Result<MessageResponse> {
-    // For now, return a placeholder response
-    // In a full implementation, this would manage conversation state
-    Err(ProcessorError::new("Conversation management not yet implemented"))
+/// Send a message in a conversation stored in `store`, or `None` if there is
+/// no such conversation visible from `workspace`
+pub async fn send_message(
+    store: &ConversationStore,
+    config: &Config,
+    request: MessageRequest,
+    workspace: Option<&str>,
+) -> Result<Option<MessageResponse>> {
+    let Some(mut context) = store.load(&request.conversation_id)?.filter(|c| c.is_visible_to(workspace)) else {
+        return Ok(None);
+    };
+    let agent = OpenAIAgent::from_env().map_err(|e| {
+        ProcessorError::new(&format!("Failed to create OpenAI agent: {}", e))
+    })?.with_processing_config(config.clone());
+    
+    let response = agent.continue_conversation(&mut context, request.message).await?;
+    store.save(&context)?;
+    
+    Ok(Some(MessageResponse { response }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_locate_artifacts_follows_organized_files() {
+        let dir = tempdir().unwrap();
+        let kept = dir.path().join("kept.txt");
+        std::fs::write(&kept, "a").unwrap();
+        std::fs::create_dir_all(dir.path().join("github_repos")).unwrap();
+        std::fs::write(dir.path().join("github_repos").join("moved.txt"), "b").unwrap();
+
+        let written = [kept, dir.path().join("moved.txt"), dir.path().join("gone.txt")];
+        let located = locate_artifacts(dir.path(), &written);
+        assert_eq!(located, ["github_repos/moved.txt", "kept.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_worker_pool_follows_the_configured_size() {
+        let dir = tempdir().unwrap();
+        let mut config = Config { output_dir: dir.path().to_path_buf(), ..Config::default() };
+        config.processing.max_concurrent_jobs = 3;
+        let jobs = Arc::new(JobManager::new(config.clone()).unwrap());
+        assert_eq!(Arc::clone(&jobs).spawn_workers().len(), 3);
+
+        // Let the workers find the queue empty and wait
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        config.processing.max_concurrent_jobs = 1;
+        config.output_dir = dir.path().join("elsewhere");
+        jobs.set_config(config.clone());
+        assert_eq!(jobs.output_dir(), dir.path());
+        // Idle workers beyond the new size stop once woken
+        for _ in 0..50 {
+            if *jobs.workers.lock().unwrap() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(*jobs.workers.lock().unwrap(), 1);
+
+        config.processing.max_concurrent_jobs = 4;
+        jobs.set_config(config);
+        assert_eq!(*jobs.workers.lock().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_reporter_streams_snapshots() {
+        let dir = tempdir().unwrap();
+        let queue = Arc::new(JobQueue::open(&dir.path().join(JOBS_DB_FILE)).unwrap());
+        let job = JobStatus {
+            job_id: "job".to_string(),
+            status: JobStatusType::Queued,
+            url: "./repo".to_string(),
+            url_type: "local".to_string(),
+            output_dir: dir.path().to_path_buf(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            progress: 0,
+            current_operation: None,
+            error_message: None,
+            output_files: Vec::new(),
+            workspace: None,
+            usage: None,
+            eta_seconds: None,
+        };
+        let request = ProcessRequest { url: job.url.clone(), output_dir: None, config: None, processor: None };
+        queue.push(&job, &request.into()).unwrap();
+        queue.claim_next().unwrap();
+
+        let (events, mut receiver) = broadcast::channel(16);
+        let reporter = JobReporter {
+            job_id: job.job_id.clone(),
+            queue,
+            live: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            events,
+            last_sent: Arc::new(std::sync::Mutex::new(None)),
+        };
+        reporter.set_progress(30, "Processing package");
+        let sink = reporter.sink();
+        sink(ProgressEvent::Files { processed: 1, total: 3 });
+        // Throttled: too soon after the previous count, and not the last file
+        sink(ProgressEvent::Files { processed: 2, total: 3 });
+        sink(ProgressEvent::Files { processed: 3, total: 3 });
+        reporter.finish(Ok(vec!["repo_analysis.txt".to_string()]));
+
+        let received: Vec<JobEvent> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        let files: Vec<Option<usize>> = received.iter().map(|e| e.files_processed).collect();
+        assert_eq!(files, [None, Some(1), Some(3), Some(3)]);
+        let last = received.last().unwrap();
+        assert_eq!(last.status, JobStatusType::Completed);
+        assert_eq!(last.progress, 100);
+        assert!(last.is_final());
+        assert!(!received[0].is_final());
+    }
+
+    #[tokio::test]
+    async fn test_only_listed_artifacts_resolve() {
+        let dir = tempdir().unwrap();
+        let config = Config { output_dir: dir.path().to_path_buf(), ..Config::default() };
+        let jobs = JobManager::new(config).unwrap();
+        let job = JobStatus {
+            job_id: "job".to_string(),
+            status: JobStatusType::Queued,
+            url: "./repo".to_string(),
+            url_type: "local".to_string(),
+            output_dir: dir.path().to_path_buf(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            progress: 0,
+            current_operation: None,
+            error_message: None,
+            output_files: Vec::new(),
+            workspace: None,
+            usage: None,
+            eta_seconds: None,
+        };
+        let request = ProcessRequest { url: job.url.clone(), output_dir: None, config: None, processor: None };
+        jobs.queue.push(&job, &request.into()).unwrap();
+        jobs.queue.claim_next().unwrap();
+        jobs.queue.finish("job", Ok(vec!["local_repos/repo.txt".to_string()])).unwrap();
+
+        let file = jobs.artifact_file("job", "local_repos/repo.txt").await.unwrap();
+        assert_eq!(file, dir.path().join("local_repos/repo.txt"));
+        assert!(jobs.artifact_file("job", "../.jobs.sqlite").await.is_err());
+        assert!(jobs.artifact_file("job", ".jobs.sqlite").await.is_err());
+        assert!(jobs.artifact_file("missing", "local_repos/repo.txt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remote_workers_upload_before_completing() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("repo");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("lib.py"), "print('hi')\n").unwrap();
+        let config = Config { output_dir: dir.path().join("out"), ..Config::default() };
+        let jobs = JobManager::new(config).unwrap();
+        let request = ProcessRequest { url: source.to_string_lossy().to_string(), output_dir: None, config: None, processor: None };
+        let submitted = jobs.submit_job(request, None).await.unwrap();
+
+        let claimed = jobs.claim_remote().await.unwrap().unwrap();
+        assert_eq!(claimed.job_id, submitted.job_id);
+        assert!(matches!(claimed.request, JobRequest::Process(_)));
+        assert!(jobs.claim_remote().await.unwrap().is_none());
+
+        let id = claimed.job_id.as_str();
+        let reading = WorkerProgress { progress: 40, operation: "Reading files".to_string(), eta_seconds: Some(12) };
+        assert!(jobs.report_remote(id, &reading).unwrap());
+        let job = jobs.get_job_status(id).await.unwrap();
+        assert_eq!((job.progress, job.eta_seconds), (40, Some(12)));
+
+        assert!(jobs.store_remote_artifact(id, "../escape.txt", b"x").await.is_err());
+        assert!(jobs.store_remote_artifact(id, "local_repos/repo.txt", b"summary").await.unwrap());
+        let outcome = WorkerOutcome {
+            output_files: vec!["local_repos/repo.txt".to_string(), "never_uploaded.txt".to_string()],
+            ..WorkerOutcome::default()
+        };
+        assert!(jobs.finish_remote(id, outcome).await.unwrap());
+        let job = jobs.get_job_status(id).await.unwrap();
+        assert_eq!(job.status, JobStatusType::Completed);
+        assert_eq!(job.output_files, ["local_repos/repo.txt"]);
+        assert_eq!(std::fs::read(jobs.artifact_file(id, "local_repos/repo.txt").await.unwrap()).unwrap(), b"summary");
+
+        // A cancelled job is no longer the worker's
+        let request = ProcessRequest { url: source.to_string_lossy().to_string(), output_dir: None, config: None, processor: None };
+        jobs.submit_job(request, None).await.unwrap();
+        let claimed = jobs.claim_remote().await.unwrap().unwrap();
+        jobs.delete_job(&claimed.job_id).await.unwrap();
+        assert!(!jobs.report_remote(&claimed.job_id, &reading).unwrap());
+        assert!(!jobs.store_remote_artifact(&claimed.job_id, "late.txt", b"x").await.unwrap());
+        assert!(!jobs.finish_remote(&claimed.job_id, WorkerOutcome::default()).await.unwrap());
+    }
+
+    #[test]
+    fn test_job_list_params() {
+        let params = JobListParams {
+            status: Some("queued, processing".to_string()),
+            since: Some("2024-05-01".to_string()),
+            sort: Some("url".to_string()),
+            order: Some("asc".to_string()),
+            ..JobListParams::default()
+        };
+        let query = params.to_query(Some("team")).unwrap();
+        assert_eq!(query.states, [JobStatusType::Queued, JobStatusType::Processing]);
+        assert_eq!(query.since.unwrap().to_rfc3339(), "2024-05-01T00:00:00+00:00");
+        assert_eq!((query.sort, query.ascending, query.limit), (JobSort::Url, true, 50));
+        assert_eq!(query.workspace.as_deref(), Some("team"));
+
+        let invalid = [
+            JobListParams { status: Some("done".to_string()), ..JobListParams::default() },
+            JobListParams { since: Some("yesterday".to_string()), ..JobListParams::default() },
+            JobListParams { order: Some("up".to_string()), ..JobListParams::default() },
+            JobListParams { limit: Some(MAX_JOBS_PER_PAGE + 1), ..JobListParams::default() },
+            JobListParams { page: Some("garbage".to_string()), ..JobListParams::default() },
+        ];
+        for params in invalid {
+            assert!(matches!(params.to_query(None), Err(ProcessorError::Validation(_))), "{:?}", params);
+        }
+    }
 }
@@ -0,0 +1,456 @@
+//! Authentication and authorization for the REST API
+//!
+//! Clients send `Authorization: Bearer <token>` (or `X-API-Key: <key>`), where
+//! the token is either an API key issued by the server or an HS256 JWT signed
+//! with `LLAMA_API_JWT_SECRET`. Every credential carries scopes:
+//!
+//! - `read` for job status, artifacts and monitoring data
+//! - `submit` for submitting and cancelling jobs, AI analyses and monitoring changes
+//! - `work` for remote workers claiming jobs and uploading what they generate
+//! - `admin` for managing API keys; admin credentials may do everything
+//!
+//! API keys are stored hashed in `api-keys.json` next to the config file (or at
+//! `LLAMA_API_KEYS_FILE`). `LLAMA_API_ADMIN_KEY` is an admin key that is never
+//! stored, for creating the first keys. JWTs take their scopes from a
+//! space-separated `scope` claim and are rejected after their `exp`.
+//!
+//! Keys issued for a workspace, and JWTs with a `workspace` claim, are
+//! confined to that workspace (see [`crate::workspace`]) and never get the
+//! `admin` or `work` scope.
+//!
+//! Authentication is enforced once any credential is configured, and stays
+//! enforced after the last key is revoked; a server that never had keys, an
+//! admin key or a JWT secret stays open.
+
+use crate::config::Config;
+use crate::error::{ProcessorError, Result};
+use crate::workspace;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine as _};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+/// Environment variable naming the API key file
+pub const KEYS_FILE_ENV_VAR: &str = "LLAMA_API_KEYS_FILE";
+/// Environment variable holding an admin key that is never stored
+pub const ADMIN_KEY_ENV_VAR: &str = "LLAMA_API_ADMIN_KEY";
+/// Environment variable holding the secret JWTs are signed with
+pub const JWT_SECRET_ENV_VAR: &str = "LLAMA_API_JWT_SECRET";
+
+/// Prefix of generated API keys, so leaked keys are easy to search for
+const KEY_PREFIX: &str = "lps_";
+
+/// What a credential may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Read jobs, artifacts and monitoring data
+    Read,
+    /// Submit and cancel work
+    Submit,
+    /// Claim queued jobs of any workspace as a remote worker
+    Work,
+    /// Manage API keys; implies every other scope
+    Admin,
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Read => "read",
+            Self::Submit => "submit",
+            Self::Work => "work",
+            Self::Admin => "admin",
+        })
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = ProcessorError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "read" => Ok(Self::Read),
+            "submit" => Ok(Self::Submit),
+            "work" => Ok(Self::Work),
+            "admin" => Ok(Self::Admin),
+            other => Err(ProcessorError::Validation(format!("unknown scope '{}' (read, submit, work or admin)", other))),
+        }
+    }
+}
+
+/// Who made a request and what they may do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// Key id or JWT subject
+    pub subject: String,
+    /// Scopes granted to the credential
+    pub scopes: Vec<Scope>,
+    /// Workspace the credential is confined to; `None` for operators
+    pub workspace: Option<String>,
+}
+
+impl Principal {
+    /// Whether the credential grants `scope`
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|&granted| granted == scope || granted == Scope::Admin)
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No credential was sent
+    Missing,
+    /// The credential is unknown, malformed or expired
+    Invalid,
+    /// The credential is valid but lacks the scope
+    Forbidden(Scope),
+}
+
+/// An issued API key, without the key itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// Identifier used to revoke the key
+    pub id: String,
+    /// What the key is for
+    pub name: String,
+    /// Scopes granted to the key
+    pub scopes: Vec<Scope>,
+    /// Workspace the key is confined to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub workspace: Option<String>,
+    /// When the key was issued
+    pub created_at: DateTime<Utc>,
+    /// SHA-256 of the key, hex encoded; cleared before keys are shown, which
+    /// leaves it out of the JSON
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    key_hash: String,
+}
+
+/// A newly issued key; the plaintext is only available here
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedKey {
+    /// The key to hand to the client
+    pub key: String,
+    /// The stored key
+    #[serde(flatten)]
+    pub info: ApiKey,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: Option<String>,
+    exp: Option<i64>,
+    #[serde(default)]
+    scope: String,
+    workspace: Option<String>,
+}
+
+/// Checks credentials against the issued keys, the admin key and the JWT secret
+pub struct Authenticator {
+    path: Option<PathBuf>,
+    keys: RwLock<Vec<ApiKey>>,
+    /// Set once a key file exists, so revoking the last key does not open the API
+    keys_issued: AtomicBool,
+    admin_key_hash: Option<String>,
+    jwt_secret: Option<Vec<u8>>,
+}
+
+impl Authenticator {
+    /// Loads keys from `path` (if any) and takes the admin key and JWT secret given
+    pub fn new(path: Option<PathBuf>, admin_key: Option<&str>, jwt_secret: Option<&str>) -> Result<Self> {
+        let keys = match &path {
+            Some(path) if path.exists() => serde_json::from_slice(&std::fs::read(path)?)?,
+            _ => Vec::new(),
+        };
+        let keys_issued = path.as_ref().is_some_and(|path| path.exists());
+        Ok(Self {
+            path,
+            keys: RwLock::new(keys),
+            keys_issued: AtomicBool::new(keys_issued),
+            admin_key_hash: admin_key.filter(|k| !k.is_empty()).map(hash_key),
+            jwt_secret: jwt_secret.filter(|s| !s.is_empty()).map(|s| s.as_bytes().to_vec()),
+        })
+    }
+
+    /// Authenticator configured from the environment and the default key file
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var_os(KEYS_FILE_ENV_VAR)
+            .map(PathBuf::from)
+            .or_else(|| Some(Config::config_path().ok()?.parent()?.join("api-keys.json")));
+        Self::new(
+            path,
+            std::env::var(ADMIN_KEY_ENV_VAR).ok().as_deref(),
+            std::env::var(JWT_SECRET_ENV_VAR).ok().as_deref(),
+        )
+    }
+
+    /// Whether requests need credentials
+    pub fn is_enabled(&self) -> bool {
+        self.admin_key_hash.is_some() || self.jwt_secret.is_some() || self.keys_issued.load(Ordering::Relaxed)
+    }
+
+    /// Identifies the sender of a bearer token or API key and checks it grants `scope`
+    pub fn authorize(&self, token: Option<&str>, scope: Scope) -> std::result::Result<Principal, AuthError> {
+        if !self.is_enabled() {
+            return Ok(Principal { subject: "anonymous".into(), scopes: vec![Scope::Admin], workspace: None });
+        }
+        let token = token.map(str::trim).filter(|t| !t.is_empty()).ok_or(AuthError::Missing)?;
+        let principal = match token.matches('.').count() {
+            2 => self.verify_jwt(token)?,
+            _ => self.verify_key(token)?,
+        };
+        if principal.allows(scope) {
+            Ok(principal)
+        } else {
+            Err(AuthError::Forbidden(scope))
+        }
+    }
+
+    /// Issued keys, newest last
+    pub fn list_keys(&self) -> Vec<ApiKey> {
+        self.read_keys().iter().cloned().map(|mut key| {
+            key.key_hash.clear();
+            key
+        }).collect()
+    }
+
+    /// Issues a key with `scopes`, optionally confined to `workspace`, and stores its hash
+    pub fn create_key(&self, name: &str, scopes: Vec<Scope>, workspace: Option<String>) -> Result<IssuedKey> {
+        if scopes.is_empty() {
+            return Err(ProcessorError::Validation("a key needs at least one scope".into()));
+        }
+        if let Some(name) = &workspace {
+            workspace::validate_name(name)?;
+            if let Some(scope) = scopes.iter().find(|scope| matches!(scope, Scope::Admin | Scope::Work)) {
+                return Err(ProcessorError::Validation(format!("workspace keys cannot have the {} scope", scope)));
+            }
+        }
+        let mut secret = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let key = format!("{}{}", KEY_PREFIX, hex::encode(secret));
+        let mut id = [0u8; 6];
+        rand::thread_rng().fill_bytes(&mut id);
+        let stored = ApiKey {
+            id: hex::encode(id),
+            name: name.to_string(),
+            scopes,
+            workspace,
+            created_at: Utc::now(),
+            key_hash: hash_key(&key),
+        };
+        let mut keys = self.keys.write().unwrap_or_else(|e| e.into_inner());
+        keys.push(stored.clone());
+        self.save(&keys)?;
+        self.keys_issued.store(true, Ordering::Relaxed);
+        let mut info = stored;
+        info.key_hash.clear();
+        Ok(IssuedKey { key, info })
+    }
+
+    /// Revokes a key, returning whether it existed
+    pub fn revoke_key(&self, id: &str) -> Result<bool> {
+        let mut keys = self.keys.write().unwrap_or_else(|e| e.into_inner());
+        let before = keys.len();
+        keys.retain(|key| key.id != id);
+        if keys.len() == before {
+            return Ok(false);
+        }
+        self.save(&keys)?;
+        Ok(true)
+    }
+
+    fn verify_key(&self, key: &str) -> std::result::Result<Principal, AuthError> {
+        let hash = hash_key(key);
+        if self.admin_key_hash.as_deref() == Some(hash.as_str()) {
+            return Ok(Principal { subject: "admin".into(), scopes: vec![Scope::Admin], workspace: None });
+        }
+        self.read_keys().iter()
+            .find(|stored| stored.key_hash == hash)
+            .map(|stored| Principal {
+                subject: stored.id.clone(),
+                scopes: stored.scopes.clone(),
+                workspace: stored.workspace.clone(),
+            })
+            .ok_or(AuthError::Invalid)
+    }
+
+    fn verify_jwt(&self, token: &str) -> std::result::Result<Principal, AuthError> {
+        let secret = self.jwt_secret.as_ref().ok_or(AuthError::Invalid)?;
+        let mut parts = token.splitn(3, '.');
+        let (Some(header), Some(payload), Some(signature)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(AuthError::Invalid);
+        };
+        let header: serde_json::Value = decode_segment(header)?;
+        if header["alg"] != "HS256" {
+            return Err(AuthError::Invalid);
+        }
+        let signature = BASE64_URL.decode(signature).map_err(|_| AuthError::Invalid)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+        mac.update(signed_part(token).as_bytes());
+        mac.verify_slice(&signature).map_err(|_| AuthError::Invalid)?;
+
+        let claims: Claims = decode_segment(payload)?;
+        if claims.exp.is_some_and(|exp| exp <= Utc::now().timestamp()) {
+            return Err(AuthError::Invalid);
+        }
+        // A token meant for a workspace must not pass as an operator's
+        if claims.workspace.as_deref().is_some_and(|name| workspace::validate_name(name).is_err()) {
+            return Err(AuthError::Invalid);
+        }
+        let workspace = claims.workspace;
+        let scopes = claims.scope.split_whitespace()
+            .filter_map(|s| s.parse().ok())
+            .filter(|&scope| !matches!(scope, Scope::Admin | Scope::Work) || workspace.is_none())
+            .collect();
+        Ok(Principal { subject: claims.sub.unwrap_or_else(|| "jwt".into()), scopes, workspace })
+    }
+
+    fn read_keys(&self) -> std::sync::RwLockReadGuard<'_, Vec<ApiKey>> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn save(&self, keys: &[ApiKey]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_private(path, &serde_json::to_vec_pretty(keys)?)
+    }
+}
+
+/// `header.payload` of a JWT, the part the signature covers
+fn signed_part(token: &str) -> &str {
+    token.rsplit_once('.').map_or(token, |(signed, _)| signed)
+}
+
+fn decode_segment<T: serde::de::DeserializeOwned>(segment: &str) -> std::result::Result<T, AuthError> {
+    let bytes = BASE64_URL.decode(segment).map_err(|_| AuthError::Invalid)?;
+    serde_json::from_slice(&bytes).map_err(|_| AuthError::Invalid)
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Writes a file only the owner can read
+///
+/// New files are created with mode 0600, so the keys are never readable by
+/// others, even briefly; existing files are narrowed to it as well.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn jwt(secret: &str, claims: serde_json::Value) -> String {
+        let header = BASE64_URL.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = BASE64_URL.encode(claims.to_string());
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.{}", header, payload).as_bytes());
+        format!("{}.{}.{}", header, payload, BASE64_URL.encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_open_without_credentials() {
+        let auth = Authenticator::new(None, None, None).unwrap();
+        assert!(!auth.is_enabled());
+        assert!(auth.authorize(None, Scope::Admin).is_ok());
+    }
+
+    #[test]
+    fn test_api_keys_and_scopes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("api-keys.json");
+        let auth = Authenticator::new(Some(path.clone()), Some("bootstrap"), None).unwrap();
+        assert_eq!(auth.authorize(None, Scope::Read), Err(AuthError::Missing));
+        assert!(auth.authorize(Some("bootstrap"), Scope::Admin).is_ok());
+
+        let issued = auth.create_key("ci", vec![Scope::Submit], None).unwrap();
+        assert!(issued.key.starts_with(KEY_PREFIX));
+        assert!(auth.authorize(Some(&issued.key), Scope::Submit).is_ok());
+        assert_eq!(auth.authorize(Some(&issued.key), Scope::Read), Err(AuthError::Forbidden(Scope::Read)));
+        assert_eq!(auth.authorize(Some("lps_wrong"), Scope::Submit), Err(AuthError::Invalid));
+
+        // Keys survive a restart and are stored hashed
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains(&issued.key));
+        let reloaded = Authenticator::new(Some(path), None, None).unwrap();
+        assert!(reloaded.authorize(Some(&issued.key), Scope::Submit).is_ok());
+        assert!(reloaded.list_keys().iter().all(|key| key.key_hash.is_empty()));
+
+        assert!(reloaded.revoke_key(&issued.info.id).unwrap());
+        assert_eq!(reloaded.authorize(Some(&issued.key), Scope::Submit), Err(AuthError::Invalid));
+    }
+
+    #[test]
+    fn test_jwt_bearer_tokens() {
+        let auth = Authenticator::new(None, None, Some("secret")).unwrap();
+        let valid = jwt("secret", serde_json::json!({"sub": "ci", "scope": "read submit", "exp": Utc::now().timestamp() + 60}));
+        let principal = auth.authorize(Some(&valid), Scope::Submit).unwrap();
+        assert_eq!(principal.subject, "ci");
+        assert_eq!(auth.authorize(Some(&valid), Scope::Admin), Err(AuthError::Forbidden(Scope::Admin)));
+
+        let expired = jwt("secret", serde_json::json!({"scope": "read", "exp": Utc::now().timestamp() - 1}));
+        assert_eq!(auth.authorize(Some(&expired), Scope::Read), Err(AuthError::Invalid));
+        let forged = jwt("other", serde_json::json!({"scope": "admin"}));
+        assert_eq!(auth.authorize(Some(&forged), Scope::Read), Err(AuthError::Invalid));
+    }
+
+    #[test]
+    fn test_workspace_credentials() {
+        let auth = Authenticator::new(None, None, Some("secret")).unwrap();
+        assert!(auth.create_key("team", vec![Scope::Admin], Some("team-a".into())).is_err());
+        assert!(auth.create_key("team", vec![Scope::Read], Some("Team A".into())).is_err());
+
+        let issued = auth.create_key("team", vec![Scope::Submit], Some("team-a".into())).unwrap();
+        let principal = auth.authorize(Some(&issued.key), Scope::Submit).unwrap();
+        assert_eq!(principal.workspace.as_deref(), Some("team-a"));
+
+        // A workspace token cannot claim its way to admin
+        let token = jwt("secret", serde_json::json!({"sub": "bob", "scope": "admin read", "workspace": "team-b"}));
+        let principal = auth.authorize(Some(&token), Scope::Read).unwrap();
+        assert_eq!(principal.workspace.as_deref(), Some("team-b"));
+        assert_eq!(auth.authorize(Some(&token), Scope::Admin), Err(AuthError::Forbidden(Scope::Admin)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("api-keys.json");
+        write_private(&path, b"[]").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, b"[]").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
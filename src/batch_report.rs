@@ -0,0 +1,305 @@
+//! Combined report for batches of packages
+//!
+//! When several packages are processed together (a URL list or a GitHub
+//! organization) this aggregates what is known about each of them into one
+//! `*_batch_report.md`: total lines of code, language mix, dependencies shared
+//! by more than one package, and a per-package table. Per-package artifacts
+//! are still written as usual.
+//!
+//! [`BatchResults`] is the machine-readable counterpart: whether each URL of a
+//! batch succeeded, how long it took and why it failed.
+
+use crate::compression;
+use crate::error::Result;
+use crate::output_organizer::package_slug;
+use crate::processors::common;
+use crate::run_diff::RunSnapshot;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What is known about one package of a batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageSummary {
+    /// Package or repository identifier
+    pub name: String,
+    /// Lines of code, when the source was analyzed
+    pub lines: Option<u64>,
+    /// Number of files, when the source was analyzed
+    pub files: Option<u64>,
+    /// Language to number of files (or 1 for a primary language only)
+    pub languages: BTreeMap<String, u64>,
+    /// Dependency name to declared version requirement
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl PackageSummary {
+    /// Summarizes a run snapshot of a package's source tree
+    pub fn from_snapshot(snapshot: &RunSnapshot) -> Self {
+        let mut languages = BTreeMap::new();
+        for path in snapshot.files.keys() {
+            if let Some(language) = language_for(path) {
+                *languages.entry(language.to_string()).or_insert(0) += 1;
+            }
+        }
+        Self {
+            name: snapshot.target.clone(),
+            lines: snapshot.metrics.get("code").copied(),
+            files: snapshot.metrics.get("files").copied(),
+            languages,
+            dependencies: snapshot.dependencies.clone(),
+        }
+    }
+}
+
+/// Outcome of processing one URL of a batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UrlResult {
+    /// URL or path as processed
+    pub url: String,
+    /// Processor detected for the URL
+    pub processor: String,
+    /// Whether processing succeeded
+    pub succeeded: bool,
+    /// Error message when processing failed
+    pub error: Option<String>,
+    /// Time spent on the URL in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Results of a batch run, written as JSON for other tools to consume
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResults {
+    /// File the URLs were read from, or `stdin`
+    pub input: String,
+    /// When the batch started
+    pub started_at: DateTime<Utc>,
+    /// When the batch finished
+    pub finished_at: DateTime<Utc>,
+    /// Number of URLs processed successfully
+    pub succeeded: usize,
+    /// Number of URLs that failed
+    pub failed: usize,
+    /// Per-URL outcomes, in input order
+    pub results: Vec<UrlResult>,
+}
+
+impl BatchResults {
+    /// Collects per-URL outcomes of a batch that started at `started_at` and has just finished
+    pub fn new(input: &str, started_at: DateTime<Utc>, results: Vec<UrlResult>) -> Self {
+        let succeeded = results.iter().filter(|r| r.succeeded).count();
+        Self {
+            input: input.to_string(),
+            started_at,
+            finished_at: Utc::now(),
+            succeeded,
+            failed: results.len() - succeeded,
+            results,
+        }
+    }
+
+    /// URLs that failed
+    pub fn failures(&self) -> impl Iterator<Item = &UrlResult> {
+        self.results.iter().filter(|r| !r.succeeded)
+    }
+
+    /// Writes the results as pretty-printed JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Loads summaries for every snapshot recorded under `output_dir` at or after `since`
+pub fn summaries_since(output_dir: &Path, since: DateTime<Utc>) -> Vec<PackageSummary> {
+    let Ok(entries) = std::fs::read_dir(output_dir.join("_snapshots")) else {
+        return Vec::new();
+    };
+    let mut summaries: Vec<PackageSummary> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| compression::read_artifact(&e.path()).ok())
+        .filter_map(|bytes| serde_json::from_slice::<RunSnapshot>(&bytes).ok())
+        .filter(|snapshot| snapshot.generated_at >= since)
+        .map(|snapshot| PackageSummary::from_snapshot(&snapshot))
+        .collect();
+    summaries.sort_by(|a, b| a.name.cmp(&b.name));
+    summaries
+}
+
+/// Renders the combined markdown report for a batch
+pub fn render(title: &str, packages: &[PackageSummary]) -> String {
+    let mut out = format!("# Batch Report: {}\n\n", title);
+    out.push_str(&format!("**Generated:** {}\n", Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("**Packages:** {}\n", packages.len()));
+
+    let analyzed: Vec<&PackageSummary> = packages.iter().filter(|p| p.lines.is_some()).collect();
+    if !analyzed.is_empty() {
+        let lines: u64 = analyzed.iter().filter_map(|p| p.lines).sum();
+        let files: u64 = analyzed.iter().filter_map(|p| p.files).sum();
+        out.push_str(&format!("**Total lines of code:** {} across {} files ({} of {} packages analyzed from source)\n",
+            lines, files, analyzed.len(), packages.len()));
+    }
+    out.push('\n');
+
+    // Language mix
+    let mut languages: BTreeMap<&str, u64> = BTreeMap::new();
+    for package in packages {
+        for (language, count) in &package.languages {
+            *languages.entry(language).or_insert(0) += count;
+        }
+    }
+    out.push_str("## Language Mix\n\n");
+    if languages.is_empty() {
+        out.push_str("No language information available.\n\n");
+    } else {
+        let total: u64 = languages.values().sum();
+        let mut sorted: Vec<_> = languages.into_iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        out.push_str("| Language | Files | Share |\n|----------|-------|-------|\n");
+        for (language, count) in sorted {
+            out.push_str(&format!("| {} | {} | {:.1}% |\n", language, count, count as f64 * 100.0 / total as f64));
+        }
+        out.push('\n');
+    }
+
+    // Dependencies used by more than one package
+    let mut users: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for package in packages {
+        for (dependency, version) in &package.dependencies {
+            users.entry(dependency).or_default().push((&package.name, version));
+        }
+    }
+    let mut shared: Vec<_> = users.into_iter().filter(|(_, users)| users.len() > 1).collect();
+    shared.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(b.0)));
+    out.push_str("## Shared Dependencies\n\n");
+    if shared.is_empty() {
+        out.push_str("No dependencies are shared between packages.\n\n");
+    } else {
+        out.push_str("| Dependency | Used by | Versions |\n|------------|---------|----------|\n");
+        for (dependency, users) in &shared {
+            let mut versions: Vec<&str> = users.iter().map(|(_, v)| *v).collect();
+            versions.sort_unstable();
+            versions.dedup();
+            let drift = if versions.len() > 1 { " (differ)" } else { "" };
+            out.push_str(&format!("| {} | {} | {}{} |\n", dependency, users.len(), versions.join(", "), drift));
+        }
+        out.push('\n');
+    }
+
+    out.push_str("## Vulnerabilities\n\n");
+    out.push_str("Vulnerability data is not collected by the processors, so no common vulnerabilities are reported.\n\n");
+
+    out.push_str("## Packages\n\n| Package | Lines | Files | Main language | Dependencies |\n|---------|-------|-------|---------------|--------------|\n");
+    for package in packages {
+        let main_language = package.languages.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map_or("-", |(language, _)| language.as_str());
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            package.name,
+            package.lines.map_or("-".to_string(), |n| n.to_string()),
+            package.files.map_or("-".to_string(), |n| n.to_string()),
+            main_language,
+            package.dependencies.len(),
+        ));
+    }
+
+    out
+}
+
+/// Writes the combined report for a batch into `output_dir`, returning the path written
+pub async fn write(output_dir: &Path, title: &str, packages: &[PackageSummary]) -> Result<PathBuf> {
+    let file_name = format!(
+        "{}_{}_batch_report.md",
+        Utc::now().format("%Y%m%d_%H%M%S"),
+        package_slug(title),
+    );
+    common::write_artifact(&render(title, packages), &output_dir.join(file_name)).await
+}
+
+/// Maps a file path to a language name by extension
+pub(crate) fn language_for(path: &str) -> Option<&'static str> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "mjs" | "cjs" | "jsx" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "swift" => "Swift",
+        "scala" => "Scala",
+        "sh" | "bash" => "Shell",
+        "ipynb" => "Jupyter Notebook",
+        "html" | "htm" => "HTML",
+        "css" | "scss" => "CSS",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(name: &str, lines: u64, languages: &[(&str, u64)], deps: &[(&str, &str)]) -> PackageSummary {
+        PackageSummary {
+            name: name.to_string(),
+            lines: Some(lines),
+            files: Some(languages.iter().map(|(_, n)| n).sum()),
+            languages: languages.iter().map(|(l, n)| (l.to_string(), *n)).collect(),
+            dependencies: deps.iter().map(|(d, v)| (d.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_render_aggregates_across_packages() {
+        let packages = vec![
+            summary("a/one", 1000, &[("Rust", 3)], &[("serde", "1.0"), ("tokio", "1")]),
+            summary("a/two", 500, &[("Rust", 1), ("Python", 4)], &[("serde", "1.0.190")]),
+            PackageSummary { name: "a/three".into(), languages: [("Go".to_string(), 1)].into(), ..Default::default() },
+        ];
+        let report = render("a", &packages);
+
+        assert!(report.contains("**Packages:** 3"));
+        assert!(report.contains("**Total lines of code:** 1500 across 8 files (2 of 3 packages analyzed from source)"));
+        assert!(report.contains("| Python | 4 | 44.4% |"));
+        assert!(report.contains("| serde | 2 | 1.0, 1.0.190 (differ) |"));
+        assert!(!report.contains("| tokio |"));
+        assert!(report.contains("| a/three | - | - | Go | 0 |"));
+    }
+
+    #[test]
+    fn test_batch_results_counts_failures() {
+        let result = |url: &str, error: Option<&str>| UrlResult {
+            url: url.to_string(),
+            processor: "github".to_string(),
+            succeeded: error.is_none(),
+            error: error.map(str::to_string),
+            duration_ms: 10,
+        };
+        let results = BatchResults::new("urls.txt", Utc::now(), vec![
+            result("https://github.com/a/one", None),
+            result("https://github.com/a/gone", Some("Not found")),
+        ]);
+
+        assert_eq!((results.succeeded, results.failed), (1, 1));
+        let failed: Vec<&str> = results.failures().map(|r| r.url.as_str()).collect();
+        assert_eq!(failed, ["https://github.com/a/gone"]);
+        let json = serde_json::to_value(&results).unwrap();
+        assert_eq!(json["results"][1]["error"], "Not found");
+    }
+
+    #[test]
+    fn test_language_for() {
+        assert_eq!(language_for("src/main.rs"), Some("Rust"));
+        assert_eq!(language_for("web/App.TSX"), Some("TypeScript"));
+        assert_eq!(language_for("README"), None);
+    }
+}
@@ -1,14 +1,29 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime, Instant};
 use serde::{Serialize, Deserialize};
 use crate::error::{ProcessorError, Result};
+use crate::metrics::{self, CacheCounters};
 use std::fs;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tokio::fs as tokio_fs;
 use chrono;
 use std::io::Read;
+use lru::LruCache;
+use rusqlite::{params, Connection};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+pub mod backend;
+pub mod warm;
+
+/// Default directory of the persistent string cache
+pub const DEFAULT_CACHE_DIR: &str = "./cache";
+
+/// TTL of entries that never expire, such as versioned archives
+pub const NEVER_EXPIRES: Duration = Duration::MAX;
 
 /// Represents a single entry in the file cache
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,106 +125,327 @@ impl FileCache {
     }
 }
 
+/// Bounds on the size of an in-memory [`Cache`]; `None` leaves that dimension unbounded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheLimits {
+    /// Most entries kept at once
+    pub max_entries: Option<usize>,
+    /// Most bytes of keys and values kept at once, as estimated by the cache's weigher
+    pub max_bytes: Option<u64>,
+}
+
+/// Counters of an in-memory [`Cache`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// Entries currently held, expired ones included until they are dropped
+    pub entries: usize,
+    /// Estimated bytes of keys and values currently held
+    pub bytes: u64,
+    /// Lookups that found a live entry
+    pub hits: u64,
+    /// Lookups that found nothing or an expired entry
+    pub misses: u64,
+    /// Entries evicted, least recently used first, to stay within the limits
+    pub evictions: u64,
+    /// Entries dropped because their TTL ran out
+    pub expirations: u64,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    value: T,
+    stored: Instant,
+    weight: u64,
+    /// The entry's own TTL, if it was stored with one
+    ttl: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Store<T> {
+    /// Entries, least recently used first
+    entries: LruCache<String, Entry<T>>,
+    /// Total weight of the entries, kept within `max_bytes`
+    bytes: u64,
+}
+
+impl<T> Store<T> {
+    fn drop_entry(&mut self, key: &str) -> Option<Entry<T>> {
+        let entry = self.entries.pop(key)?;
+        self.bytes -= entry.weight;
+        Some(entry)
+    }
+}
+
 /// A generic in-memory cache for any serializable type
+///
+/// Entries expire after the TTL, or after their own TTL when stored with
+/// [`Cache::set_with_ttl`]. With [`CacheLimits`] the cache also stays within a
+/// number of entries and an estimate of their size in bytes, evicting the
+/// least recently used entries to make room.
 #[derive(Debug)]
 pub struct Cache<T> {
-    store: Arc<RwLock<HashMap<String, (T, Instant)>>>,
+    store: Arc<RwLock<Store<T>>>,
     ttl: Duration,
+    limits: CacheLimits,
+    weigh: fn(&T) -> usize,
+    counters: Arc<CacheCounters>,
 }
 
 impl<T: Clone + Send + Sync + 'static> Cache<T> {
     /// Creates a new in-memory cache with the specified TTL
     pub fn new(ttl: Duration) -> Self {
+        Self::with_limits(ttl, CacheLimits::default())
+    }
+
+    /// Creates a cache that also keeps within `limits`
+    ///
+    /// Values are weighed as `size_of::<T>()` unless a weigher is given with
+    /// [`Cache::with_weigher`].
+    pub fn with_limits(ttl: Duration, limits: CacheLimits) -> Self {
         Self {
-            store: Arc::new(RwLock::new(HashMap::new())),
+            store: Arc::new(RwLock::new(Store { entries: LruCache::unbounded(), bytes: 0 })),
             ttl,
+            limits,
+            weigh: |_| std::mem::size_of::<T>(),
+            counters: Arc::new(CacheCounters::default()),
         }
     }
 
-    /// Retrieves a value from the cache by its key
+    /// Estimates the bytes a value takes with `weigh`, such as `String::len`
+    pub fn with_weigher(mut self, weigh: fn(&T) -> usize) -> Self {
+        self.weigh = weigh;
+        self
+    }
+
+    /// Reports the cache's counters under `name` through [`metrics::cache_stats`],
+    /// adding them up with those of other caches of that name
+    pub fn named(mut self, name: &str) -> Self {
+        self.counters = metrics::cache_counters(name);
+        self
+    }
+
+    fn is_live(&self, entry: &Entry<T>) -> bool {
+        entry.stored.elapsed() < entry.ttl.unwrap_or(self.ttl)
+    }
+
+    /// Retrieves a value from the cache by its key, marking it recently used
     pub async fn get(&self, key: &str) -> Option<T> {
-        let store = self.store.read().await;
-        store.get(key)
-            .filter(|(_, time)| time.elapsed() < self.ttl)
-            .map(|(value, _)| value.clone())
+        let mut store = self.store.write().await;
+        let expired = match store.entries.get(key) {
+            Some(entry) if self.is_live(entry) => {
+                let value = entry.value.clone();
+                self.counters.hit();
+                return Some(value);
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            if let Some(entry) = store.drop_entry(key) {
+                self.counters.expired(1, entry.weight);
+            }
+        }
+        self.counters.miss();
+        None
     }
 
-    /// Stores a value in the cache with the specified key
+    /// Stores a value in the cache with the specified key, evicting the least
+    /// recently used entries when the cache is full
+    ///
+    /// A value heavier than `max_bytes` on its own is not kept.
     pub async fn set(&self, key: &str, value: T) {
+        self.insert(key, value, None).await;
+    }
+
+    /// Stores a value that expires after `ttl` rather than the cache's TTL
+    ///
+    /// Short-lived metadata and immutable artifacts, such as a versioned
+    /// archive stored with [`NEVER_EXPIRES`], can share one cache this way.
+    /// The entry is still evicted when the cache is full.
+    pub async fn set_with_ttl(&self, key: &str, value: T, ttl: Duration) {
+        self.insert(key, value, Some(ttl)).await;
+    }
+
+    async fn insert(&self, key: &str, value: T, ttl: Option<Duration>) {
+        let weight = (key.len() + (self.weigh)(&value)) as u64;
         let mut store = self.store.write().await;
-        store.insert(key.to_string(), (value, Instant::now()));
+        if let Some(replaced) = store.drop_entry(key) {
+            self.counters.removed(1, replaced.weight);
+        }
+        store.entries.put(key.to_string(), Entry { value, stored: Instant::now(), weight, ttl });
+        store.bytes += weight;
+        self.counters.added(weight);
+
+        let over_limits = |store: &Store<T>| {
+            self.limits.max_entries.is_some_and(|max| store.entries.len() > max)
+                || self.limits.max_bytes.is_some_and(|max| store.bytes > max)
+        };
+        while over_limits(&store) {
+            let Some((_, entry)) = store.entries.pop_lru() else { break };
+            store.bytes -= entry.weight;
+            if self.is_live(&entry) {
+                self.counters.evicted(entry.weight);
+            } else {
+                self.counters.expired(1, entry.weight);
+            }
+        }
     }
 
     /// Removes an entry from the cache by its key
     pub async fn remove(&self, key: &str) -> bool {
         let mut store = self.store.write().await;
-        store.remove(key).is_some()
+        let removed = store.drop_entry(key);
+        if let Some(entry) = &removed {
+            self.counters.removed(1, entry.weight);
+        }
+        removed.is_some()
     }
 
     /// Clears all entries from the cache
     pub async fn clear(&self) {
         let mut store = self.store.write().await;
-        store.clear();
+        self.counters.removed(store.entries.len() as u64, store.bytes);
+        store.entries.clear();
+        store.bytes = 0;
     }
 
     /// Checks if the cache contains the specified key
     pub async fn contains_key(&self, key: &str) -> bool {
         let store = self.store.read().await;
-        store.contains_key(key)
+        store.entries.contains(key)
     }
 
     /// Returns the number of entries in the cache
     pub async fn len(&self) -> usize {
         let store = self.store.read().await;
-        store.len()
+        store.entries.len()
     }
 
     /// Checks if the cache is empty
     pub async fn is_empty(&self) -> bool {
         let store = self.store.read().await;
-        store.is_empty()
+        store.entries.is_empty()
     }
     
     /// Removes expired entries from the cache and returns the count of removed entries
     pub async fn cleanup_expired(&self) -> usize {
         let mut store = self.store.write().await;
-        let before_len = store.len();
-        store.retain(|_, (_, time)| time.elapsed() < self.ttl);
-        before_len - store.len()
+        let expired = self.drop_where(&mut store, |entry| !self.is_live(entry));
+        self.counters.expired(expired.0, expired.1);
+        expired.0 as usize
+    }
+
+    /// Calls `f` with the key, value and age of every live entry, without
+    /// marking them used
+    pub async fn for_each(&self, mut f: impl FnMut(&str, &T, Duration)) {
+        let store = self.store.read().await;
+        for (key, entry) in store.entries.iter() {
+            if self.is_live(entry) {
+                f(key, &entry.value, entry.stored.elapsed());
+            }
+        }
+    }
+
+    /// Keeps only the entries for which `keep` returns true given their key,
+    /// value and age, returning how many were removed
+    pub async fn retain(&self, mut keep: impl FnMut(&str, &T, Duration) -> bool) -> usize {
+        let mut store = self.store.write().await;
+        let mut keys = Vec::new();
+        for (key, entry) in store.entries.iter() {
+            if !keep(key, &entry.value, entry.stored.elapsed()) {
+                keys.push(key.clone());
+            }
+        }
+        let removed = Self::drop_keys(&mut store, &keys);
+        self.counters.removed(removed.0, removed.1);
+        removed.0 as usize
+    }
+
+    /// Drops the entries matching `drop`, returning their number and weight
+    fn drop_where(&self, store: &mut Store<T>, drop: impl Fn(&Entry<T>) -> bool) -> (u64, u64) {
+        let keys: Vec<String> = store.entries.iter()
+            .filter(|(_, entry)| drop(entry))
+            .map(|(key, _)| key.clone())
+            .collect();
+        Self::drop_keys(store, &keys)
+    }
+
+    fn drop_keys(store: &mut Store<T>, keys: &[String]) -> (u64, u64) {
+        let mut bytes = 0;
+        for key in keys {
+            if let Some(entry) = store.drop_entry(key) {
+                bytes += entry.weight;
+            }
+        }
+        (keys.len() as u64, bytes)
+    }
+
+    /// Size, hit and eviction counters of the cache
+    ///
+    /// Entries and bytes are this cache's own; the other counters add up
+    /// those of every cache sharing its [name](Cache::named).
+    pub async fn stats(&self) -> CacheStats {
+        let store = self.store.read().await;
+        CacheStats { entries: store.entries.len(), bytes: store.bytes, ..self.counters.snapshot() }
     }
     
     /// Sets a new TTL for the cache
+    ///
+    /// Entries stored with their own TTL keep it.
     pub fn set_ttl(&mut self, ttl: Duration) {
         self.ttl = ttl;
     }
 }
 
+/// File name of the database [`StringCache`] saves its entries to
+pub const STRING_CACHE_DB_FILE: &str = "cache.sqlite";
+
+/// Name [`StringCache`] counters are reported under in the metrics module
+pub const STRING_CACHE_METRICS_NAME: &str = "strings";
+
+/// Single JSON file entries were saved to before; [`StringCache::load`] imports it once
+const LEGACY_CACHE_FILE: &str = "cache.json";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct StoredCacheEntry {
     value: String,
     created_at: u64,
 }
 
+/// How long [`StringCache`] waits for changes to stop before saving them
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest a change waits to be saved while further changes keep coming
+const MAX_SAVE_DELAY: Duration = Duration::from_secs(5);
+
 /// A specialized cache for storing string values with persistent storage
+///
+/// Entries are saved to a SQLite database in the cache directory, one row per
+/// entry, writing only the entries changed since the last save. Changes are
+/// saved in the background once they stop coming for a moment;
+/// [`StringCache::save`] saves them right away, and [`StringCache::shutdown`]
+/// saves what is left before the cache goes away.
 #[derive(Debug)]
 pub struct StringCache {
     store: Arc<RwLock<HashMap<String, (String, Instant)>>>,
     ttl: Duration,
     cache_dir: PathBuf,
+    /// Keys set or removed since the last save
+    dirty: Arc<RwLock<HashSet<String>>>,
+    /// Whether the cache was cleared since the last save
+    cleared: Arc<AtomicBool>,
+    /// Held while saving, so saves reach the database in the order they were taken
+    saving: Arc<Mutex<()>>,
+    /// Reported as the `strings` cache in the metrics module
+    counters: Arc<CacheCounters>,
+    /// Wakes the background writer after a change
+    changes: mpsc::UnboundedSender<()>,
 }
 
 impl StringCache {
     /// Creates a new StringCache with the specified cache directory
     pub async fn new(cache_dir: &Path) -> Result<Self> {
-        tokio::fs::create_dir_all(cache_dir)
-            .await
-            .map_err(|e| ProcessorError::IO(e))?;
-        
-        Ok(Self {
-            store: Arc::new(RwLock::new(HashMap::new())),
-            ttl: Duration::from_secs(3600), // Default 1 hour TTL
-            cache_dir: cache_dir.to_path_buf(),
-        })
+        Self::with_ttl(cache_dir, Duration::from_secs(3600)).await // Default 1 hour TTL
     }
 
     /// Creates a new StringCache with the specified cache directory and TTL
@@ -218,11 +454,63 @@ impl StringCache {
             .await
             .map_err(|e| ProcessorError::IO(e))?;
         
-        Ok(Self {
+        let (changes, pending) = mpsc::unbounded_channel();
+        let cache = Self {
             store: Arc::new(RwLock::new(HashMap::new())),
             ttl,
             cache_dir: cache_dir.to_path_buf(),
-        })
+            dirty: Arc::new(RwLock::new(HashSet::new())),
+            cleared: Arc::new(AtomicBool::new(false)),
+            saving: Arc::new(Mutex::new(())),
+            counters: metrics::cache_counters(STRING_CACHE_METRICS_NAME),
+            changes,
+        };
+        cache.spawn_writer(pending);
+        Ok(cache)
+    }
+
+    /// Saves changes in the background, `SAVE_DELAY` after the last one
+    fn spawn_writer(&self, mut pending: mpsc::UnboundedReceiver<()>) {
+        let writer = Self {
+            store: self.store.clone(),
+            ttl: self.ttl,
+            cache_dir: self.cache_dir.clone(),
+            dirty: self.dirty.clone(),
+            cleared: self.cleared.clone(),
+            saving: self.saving.clone(),
+            counters: self.counters.clone(),
+            // Only the caller's sender keeps the channel open
+            changes: mpsc::unbounded_channel().0,
+        };
+        tokio::spawn(async move {
+            let mut open = true;
+            while open && pending.recv().await.is_some() {
+                let first = Instant::now();
+                loop {
+                    tokio::select! {
+                        change = pending.recv() => {
+                            // Once the cache is gone, save what is left and stop
+                            if change.is_none() {
+                                open = false;
+                                break;
+                            }
+                            if first.elapsed() >= MAX_SAVE_DELAY {
+                                break;
+                            }
+                        }
+                        _ = tokio::time::sleep(SAVE_DELAY) => break,
+                    }
+                }
+                if let Err(e) = writer.save().await {
+                    warn!("Failed to save the cache in {}: {}", writer.cache_dir.display(), e);
+                }
+            }
+        });
+    }
+
+    fn changed(&self) {
+        // The writer only stops once the cache is gone
+        let _ = self.changes.send(());
     }
 
     /// Sets a new TTL for the cache
@@ -239,8 +527,10 @@ impl StringCache {
             let content = tokio::fs::read_to_string(&path)
                 .await
                 .map_err(|e| ProcessorError::IO(e))?;
+            self.counters.hit();
             Ok(Some(content))
         } else {
+            self.counters.miss();
             Ok(None)
         }
     }
@@ -248,19 +538,35 @@ impl StringCache {
     /// Stores a value in the cache with the specified key
     pub async fn set_value(&self, key: &str, value: String) {
         let mut store = self.store.write().await;
-        store.insert(key.to_string(), (value, Instant::now()));
+        self.counters.added(weight(key, &value));
+        if let Some((replaced, _)) = store.insert(key.to_string(), (value, Instant::now())) {
+            self.counters.removed(1, weight(key, &replaced));
+        }
+        self.dirty.write().await.insert(key.to_string());
+        self.changed();
     }
     
     /// Removes an entry from the cache by its key
     pub async fn remove(&self, key: &str) -> bool {
         let mut store = self.store.write().await;
-        store.remove(key).is_some()
+        let Some((value, _)) = store.remove(key) else {
+            return false;
+        };
+        self.counters.removed(1, weight(key, &value));
+        self.dirty.write().await.insert(key.to_string());
+        self.changed();
+        true
     }
 
     /// Clears all entries from the cache
     pub async fn clear(&self) {
         let mut store = self.store.write().await;
+        let bytes = store.iter().map(|(key, (value, _))| weight(key, value)).sum();
+        self.counters.removed(store.len() as u64, bytes);
         store.clear();
+        self.dirty.write().await.clear();
+        self.cleared.store(true, Ordering::SeqCst);
+        self.changed();
     }
 
     /// Checks if the cache contains the specified key
@@ -284,75 +590,104 @@ impl StringCache {
     /// Removes expired entries from the cache and returns the count of removed entries
     pub async fn cleanup_expired(&self) -> usize {
         let mut store = self.store.write().await;
-        let before_len = store.len();
-        store.retain(|_, (_, time)| time.elapsed() < self.ttl);
-        before_len - store.len()
+        let expired: Vec<String> = store.iter()
+            .filter(|(_, (_, time))| time.elapsed() >= self.ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut bytes = 0;
+        for key in &expired {
+            if let Some((value, _)) = store.remove(key) {
+                bytes += weight(key, &value);
+            }
+        }
+        self.counters.expired(expired.len() as u64, bytes);
+        if !expired.is_empty() {
+            self.dirty.write().await.extend(expired.iter().cloned());
+            self.changed();
+        }
+        expired.len()
     }
 
-    /// Saves the cache to disk
+    /// Saves the entries set or removed since the last save to disk now,
+    /// rather than waiting for the background writer
+    ///
+    /// Only changed entries are written, so saving stays cheap however large
+    /// the cache grows. The changes are copied out under the locks and written
+    /// on a blocking thread once they are released.
     pub async fn save(&self) -> Result<()> {
-        let cache_file = self.cache_dir.join("cache.json");
-        
-        let store = self.store.read().await;
-        let serializable: HashMap<String, StoredCacheEntry> = store
-            .iter()
-            .map(|(k, (v, time))| {
-                let created_at = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as u64 - time.elapsed().as_millis() as u64;
-                
-                (k.clone(), StoredCacheEntry {
-                    value: v.clone(),
-                    created_at,
-                })
-            })
-            .collect();
-            
-        let content = serde_json::to_string(&serializable)
-            .map_err(|e| ProcessorError::Config(e.to_string()))?;
-        
-        tokio::fs::write(cache_file, content)
-            .await
-            .map_err(|e| ProcessorError::IO(e))?;
-        
-        Ok(())
+        let _saving = self.saving.lock().await;
+        let (keys, changes, cleared) = {
+            let store = self.store.read().await;
+            let mut dirty = self.dirty.write().await;
+            let cleared = self.cleared.swap(false, Ordering::SeqCst);
+            if dirty.is_empty() && !cleared {
+                return Ok(());
+            }
+            let keys = std::mem::take(&mut *dirty);
+            let changes: Vec<_> = keys.iter().map(|key| (key.clone(), store.get(key).cloned())).collect();
+            (keys, changes, cleared)
+        };
+
+        let cache_dir = self.cache_dir.clone();
+        let written = tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = open_db(&cache_dir)?;
+            Ok(write_changes(&mut conn, &changes, cleared)?)
+        })
+        .await
+        .map_err(|e| ProcessorError::Cache(format!("saving the cache failed: {}", e)))
+        .and_then(|written| written);
+        if written.is_err() {
+            // Try again on the next save
+            self.dirty.write().await.extend(keys);
+            self.cleared.fetch_or(cleared, Ordering::SeqCst);
+        }
+        written
+    }
+
+    /// Saves the changes not saved yet and stops the background writer
+    ///
+    /// Call this before the runtime shuts down, which may stop the writer
+    /// before it has saved the last changes.
+    pub async fn shutdown(self) -> Result<()> {
+        self.save().await
     }
     
-    /// Loads the cache from disk
+    /// Loads the entries saved earlier that have not expired
+    ///
+    /// A `cache.json` written by older versions is imported into the database
+    /// and removed.
     pub async fn load(&self) -> Result<()> {
-        let cache_file = self.cache_dir.join("cache.json");
-        
-        if cache_file.exists() {
-            let content = tokio::fs::read_to_string(&cache_file)
-                .await
-                .map_err(|e| ProcessorError::IO(e))?;
-            
+        let mut conn = open_db(&self.cache_dir)?;
+        let legacy_file = self.cache_dir.join(LEGACY_CACHE_FILE);
+        if legacy_file.exists() {
+            let content = tokio::fs::read_to_string(&legacy_file).await?;
             let entries: HashMap<String, StoredCacheEntry> = serde_json::from_str(&content)
                 .map_err(|e| ProcessorError::Config(e.to_string()))?;
-            
-            let now = Instant::now();
-            let current_time = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64;
-                
-            let mut store = self.store.write().await;
+            let tx = conn.transaction()?;
             for (key, entry) in entries {
-                // Calculate how old this entry is
-                let age = current_time - entry.created_at;
-                let age_duration = Duration::from_millis(age);
-                
-                // Create an Instant that represents when this entry was created
-                let creation_instant = if age < now.elapsed().as_millis() as u64 {
-                    // Entry is older than our program has been running
-                    now - age_duration
-                } else {
-                    // Handle edge case - use a very old timestamp
-                    now - now.elapsed()
-                };
-                
-                store.insert(key, (entry.value, creation_instant));
+                tx.execute(
+                    "INSERT OR IGNORE INTO entries (key, value, created_at) VALUES (?1, ?2, ?3)",
+                    params![key, entry.value, entry.created_at as i64],
+                )?;
+            }
+            tx.commit()?;
+            tokio::fs::remove_file(&legacy_file).await?;
+        }
+
+        let now = unix_millis(Instant::now());
+        let oldest = now.saturating_sub(i64::try_from(self.ttl.as_millis()).unwrap_or(i64::MAX));
+        let mut statement = conn
+            .prepare("SELECT key, value, created_at FROM entries WHERE created_at > ?1")?;
+        let rows = statement
+            .query_map(params![oldest], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?)))?;
+        let mut store = self.store.write().await;
+        for row in rows {
+            let (key, value, created_at) = row?;
+            let age = Duration::from_millis(u64::try_from(now - created_at).unwrap_or_default());
+            let created = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+            self.counters.added(weight(&key, &value));
+            if let Some((replaced, _)) = store.insert(key.clone(), (value, created)) {
+                self.counters.removed(1, weight(&key, &replaced));
             }
         }
         
@@ -432,6 +767,52 @@ impl StringCache {
     }
 }
 
+fn open_db(cache_dir: &Path) -> Result<Connection> {
+    let conn = Connection::open(cache_dir.join(STRING_CACHE_DB_FILE))?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS entries (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );",
+    )?;
+    Ok(conn)
+}
+
+/// Writes changed entries, deleting those that are `None`, after deleting every row if `cleared`
+fn write_changes(
+    conn: &mut Connection,
+    changes: &[(String, Option<(String, Instant)>)],
+    cleared: bool,
+) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    if cleared {
+        tx.execute("DELETE FROM entries", [])?;
+    }
+    for (key, entry) in changes {
+        match entry {
+            Some((value, time)) => tx.execute(
+                "INSERT OR REPLACE INTO entries (key, value, created_at) VALUES (?1, ?2, ?3)",
+                params![key, value, unix_millis(*time)],
+            )?,
+            None => tx.execute("DELETE FROM entries WHERE key = ?1", params![key])?,
+        };
+    }
+    tx.commit()
+}
+
+/// Bytes a [`StringCache`] entry counts for in its metrics
+fn weight(key: &str, value: &str) -> u64 {
+    (key.len() + value.len()) as u64
+}
+
+/// Milliseconds since the Unix epoch at `time`
+fn unix_millis(time: Instant) -> i64 {
+    let then = SystemTime::now() - time.elapsed();
+    then.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| i64::try_from(since.as_millis()).unwrap_or(i64::MAX))
+}
+
 /// Metadata for cache entries with additional information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheMetadata {
@@ -452,4 +833,114 @@ impl CacheMetadata {
             created_at: chrono::Utc::now(),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cache_evicts_least_recently_used() {
+        let limits = CacheLimits { max_entries: Some(2), max_bytes: None };
+        let cache: Cache<i32> = Cache::with_limits(Duration::from_secs(60), limits).named("test_lru");
+        cache.set("a", 1).await;
+        cache.set("b", 2).await;
+        assert_eq!(cache.get("a").await, Some(1));
+        cache.set("c", 3).await;
+
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.get("a").await, Some(1));
+        assert_eq!(cache.get("c").await, Some(3));
+        let stats = cache.stats().await;
+        assert_eq!((stats.entries, stats.evictions, stats.hits, stats.misses), (2, 1, 3, 1));
+        assert_eq!(metrics::cache_stats()["test_lru"], stats);
+    }
+
+    #[tokio::test]
+    async fn test_entries_with_their_own_ttl() {
+        let mut cache: Cache<&str> = Cache::new(Duration::from_secs(60));
+        cache.set("metadata", "short").await;
+        cache.set_with_ttl("archive", "immutable", NEVER_EXPIRES).await;
+        cache.set_with_ttl("gone", "instantly", Duration::ZERO).await;
+        assert_eq!(cache.get("gone").await, None);
+
+        // Lowering the cache's TTL leaves entries with their own alone
+        cache.set_ttl(Duration::ZERO);
+        assert_eq!(cache.get("metadata").await, None);
+        assert_eq!(cache.get("archive").await, Some("immutable"));
+        assert_eq!(cache.cleanup_expired().await, 0);
+        assert_eq!(cache.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_string_cache_saves_changed_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(LEGACY_CACHE_FILE),
+            format!(r#"{{"legacy": {{"value": "old", "created_at": {}}}}}"#, unix_millis(Instant::now())),
+        ).unwrap();
+
+        let cache = StringCache::new(dir.path()).await.unwrap();
+        cache.load().await.unwrap();
+        assert!(cache.contains_key("legacy").await);
+        assert!(!dir.path().join(LEGACY_CACHE_FILE).exists());
+        cache.set_value("kept", "a".to_string()).await;
+        cache.set_value("dropped", "b".to_string()).await;
+        cache.save().await.unwrap();
+        cache.remove("dropped").await;
+        cache.save().await.unwrap();
+
+        let reloaded = StringCache::new(dir.path()).await.unwrap();
+        reloaded.load().await.unwrap();
+        assert_eq!(reloaded.len().await, 2);
+        assert!(reloaded.contains_key("kept").await && !reloaded.contains_key("dropped").await);
+
+        reloaded.clear().await;
+        reloaded.save().await.unwrap();
+        let cleared = StringCache::new(dir.path()).await.unwrap();
+        cleared.load().await.unwrap();
+        assert!(cleared.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_string_cache_saves_in_the_background_and_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloaded = || async {
+            let cache = StringCache::new(dir.path()).await.unwrap();
+            cache.load().await.unwrap();
+            cache
+        };
+
+        let cache = StringCache::new(dir.path()).await.unwrap();
+        cache.set_value("written", "later".to_string()).await;
+        tokio::time::sleep(SAVE_DELAY * 3).await;
+        assert!(reloaded().await.contains_key("written").await);
+
+        cache.set_value("shutdown", "now".to_string()).await;
+        cache.shutdown().await.unwrap();
+        let cache = reloaded().await;
+        assert!(cache.contains_key("shutdown").await && cache.contains_key("written").await);
+    }
+
+    #[tokio::test]
+    async fn test_cache_stays_within_max_bytes() {
+        let limits = CacheLimits { max_entries: None, max_bytes: Some(20) };
+        let cache: Cache<String> = Cache::with_limits(Duration::from_secs(60), limits).with_weigher(String::len);
+        cache.set("a", "x".repeat(8)).await;
+        cache.set("b", "x".repeat(8)).await;
+        assert_eq!(cache.stats().await.bytes, 18);
+
+        // Replacing an entry reweighs it rather than counting it twice
+        cache.set("b", "x".repeat(4)).await;
+        assert_eq!(cache.stats().await.bytes, 14);
+
+        cache.set("c", "x".repeat(8)).await;
+        assert!(!cache.contains_key("a").await);
+        assert_eq!(cache.stats().await.bytes, 14);
+
+        // Too heavy to keep at all
+        cache.set("d", "x".repeat(30)).await;
+        assert!(cache.is_empty().await);
+        let stats = cache.stats().await;
+        assert_eq!((stats.bytes, stats.evictions), (0, 4));
+    }
+}
@@ -0,0 +1,646 @@
+//! Backends for the cache of registry responses
+//!
+//! Package metadata fetched from PyPI, npm and crates.io, and repository and
+//! organization details from the GitHub API, are kept for `cache.ttl_secs`
+//! together with their `ETag` and `Last-Modified` headers. For
+//! `cache.revalidate_secs` a cached response is used as is; after that the
+//! request is sent again as a conditional request, and a `304 Not Modified`
+//! reuses the cached body, which GitHub does not count against the rate
+//! limit. The `memory` backend keeps responses for the life of the process;
+//! the `sqlite` backend keeps them in `<cache.dir>/responses.sqlite` across
+//! runs, reading and writing one entry at a time; the `redis` backend (built
+//! with the `redis` feature) shares them between every server instance behind
+//! a load balancer. Keys carry `cache.key_prefix`, so several deployments can
+//! use one Redis without seeing each other's data.
+//!
+//! Every key is namespaced as `<processor>:<target>:<url>`, such as
+//! `pypi:requests:https://pypi.org/pypi/requests/json`, so `cache stats` can
+//! report per processor and `cache clear --processor pypi --older-than 7d`
+//! purges only what is stale.
+//!
+//! ```toml
+//! [cache]
+//! backend = "redis"
+//! redis_url = "redis://cache.internal:6379/0"   # or LLAMA_REDIS_URL
+//! key_prefix = "llamapackageservice:"
+//! ttl_secs = 604800        # keep responses for a week
+//! revalidate_secs = 3600   # ask whether they changed after an hour
+//! ```
+
+use super::{Cache, CacheLimits, DEFAULT_CACHE_DIR, NEVER_EXPIRES};
+use crate::database::Database;
+use crate::error::{ProcessorError, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use once_cell::sync::OnceCell;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Environment variable holding the Redis URL, preferred over the config file
+pub const REDIS_URL_ENV_VAR: &str = "LLAMA_REDIS_URL";
+
+/// File name of the `sqlite` backend's database inside `cache.dir`
+pub const RESPONSES_DB_FILE: &str = "responses.sqlite";
+
+static SHARED_CACHE: OnceCell<Arc<dyn CacheBackend>> = OnceCell::new();
+static REVALIDATE_AFTER: OnceCell<Duration> = OnceCell::new();
+
+/// Where cached responses are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackendKind {
+    /// Nothing is cached
+    None,
+    /// In the memory of this process
+    #[default]
+    Memory,
+    /// In a SQLite database in `cache.dir`, kept across runs
+    Sqlite,
+    /// In Redis, shared by every instance using the same server and prefix
+    Redis,
+}
+
+/// Settings of the response cache, the `[cache]` section of the config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Backend responses are cached in
+    pub backend: CacheBackendKind,
+    /// Redis server, such as `redis://localhost:6379/0`; `LLAMA_REDIS_URL` takes precedence
+    pub redis_url: Option<String>,
+    /// Directory of the `sqlite` backend's database
+    pub dir: PathBuf,
+    /// Prefix of every cache key
+    pub key_prefix: String,
+    /// How long a cached response is kept, in seconds
+    pub ttl_secs: u64,
+    /// How long a cached response is used before asking the server whether it changed, in seconds
+    pub revalidate_secs: u64,
+    /// Memory the `memory` backend may use, in megabytes
+    pub memory_mb: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: CacheBackendKind::Memory,
+            redis_url: None,
+            dir: PathBuf::from(DEFAULT_CACHE_DIR),
+            key_prefix: "llamapackageservice:".to_string(),
+            ttl_secs: 7 * 24 * 3600,
+            revalidate_secs: 3600,
+            memory_mb: 256,
+        }
+    }
+}
+
+impl CacheConfig {
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+
+    /// How long a cached response is used without asking the server
+    pub fn revalidate_after(&self) -> Duration {
+        Duration::from_secs(self.revalidate_secs)
+    }
+}
+
+/// Store that cached responses are kept in
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    /// The value stored under `key`, unless it is missing or has expired
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Stores `value` under `key` until the TTL runs out
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()>;
+    /// Stores `value` under `key` for `ttl` rather than `cache.ttl_secs`;
+    /// [`NEVER_EXPIRES`] keeps it until it is cleared
+    async fn set_with_ttl(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<()>;
+    /// Deletes `key`, returning whether it was stored
+    async fn remove(&self, key: &str) -> Result<bool>;
+    /// Live entries per processor namespace, ordered by processor
+    async fn stats(&self) -> Result<Vec<NamespaceStats>>;
+    /// Deletes the entries matching `filter`, returning how many were deleted
+    async fn clear(&self, filter: &ClearFilter) -> Result<u64>;
+}
+
+/// Key of the response to `resource` fetched by `processor` for `target`,
+/// such as the npm package or GitHub repository being processed
+pub fn namespaced_key(processor: &str, target: &str, resource: &str) -> String {
+    format!("{}:{}:{}", processor, target, resource)
+}
+
+/// The processor namespace of `key`
+fn processor_of(key: &str) -> &str {
+    key.split(':').next().unwrap_or_default()
+}
+
+/// Cached responses of one processor
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NamespaceStats {
+    /// Processor that fetched the responses, such as `pypi` or `github`
+    pub processor: String,
+    /// Number of responses
+    pub entries: u64,
+    /// Bytes of the stored responses
+    pub bytes: u64,
+    /// When the oldest response was stored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+/// Tallies `(key, bytes, stored_at)` rows into per-processor stats
+fn tally(rows: impl IntoIterator<Item = (String, u64, DateTime<Utc>)>) -> Vec<NamespaceStats> {
+    let mut namespaces: BTreeMap<String, NamespaceStats> = BTreeMap::new();
+    for (key, bytes, stored_at) in rows {
+        let processor = processor_of(&key);
+        let stats = namespaces.entry(processor.to_string()).or_insert_with(|| NamespaceStats {
+            processor: processor.to_string(),
+            ..NamespaceStats::default()
+        });
+        stats.entries += 1;
+        stats.bytes += bytes;
+        stats.oldest = Some(stats.oldest.map_or(stored_at, |oldest| oldest.min(stored_at)));
+    }
+    namespaces.into_values().collect()
+}
+
+/// Which entries [`CacheBackend::clear`] deletes; the default matches every entry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClearFilter {
+    /// Only entries of this processor
+    pub processor: Option<String>,
+    /// Only entries stored at least this long ago
+    pub older_than: Option<Duration>,
+}
+
+impl ClearFilter {
+    /// Whether the entry at `key` stored `age` ago matches
+    pub fn matches(&self, key: &str, age: Duration) -> bool {
+        self.processor.as_deref().map_or(true, |processor| processor_of(key) == processor)
+            && self.older_than.map_or(true, |older_than| age >= older_than)
+    }
+}
+
+/// Parses an age such as `7d`, `12h`, `30m`, `2w` or `90` (seconds)
+pub fn parse_age(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid age '{}'", s))?;
+    let seconds = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        other => return Err(format!("unknown age unit '{}' (expected s, m, h, d or w)", other)),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// Builds the backend described by `config`, or `None` when caching is off
+pub fn from_config(config: &CacheConfig) -> Result<Option<Arc<dyn CacheBackend>>> {
+    let backend: Arc<dyn CacheBackend> = match config.backend {
+        CacheBackendKind::None => return Ok(None),
+        CacheBackendKind::Memory => Arc::new(MemoryBackend::new(config)),
+        CacheBackendKind::Sqlite => Arc::new(SqliteBackend::open(&config.dir.join(RESPONSES_DB_FILE), config)?),
+        CacheBackendKind::Redis => redis_backend(config)?,
+    };
+    Ok(Some(backend))
+}
+
+#[cfg(feature = "redis")]
+fn redis_backend(config: &CacheConfig) -> Result<Arc<dyn CacheBackend>> {
+    let url = std::env::var(REDIS_URL_ENV_VAR).ok()
+        .or_else(|| config.redis_url.clone())
+        .ok_or_else(|| ProcessorError::Config(format!(
+            "cache.redis_url or {} must be set for the redis cache", REDIS_URL_ENV_VAR
+        )))?;
+    Ok(Arc::new(RedisBackend::new(&url, config)?))
+}
+
+#[cfg(not(feature = "redis"))]
+fn redis_backend(_config: &CacheConfig) -> Result<Arc<dyn CacheBackend>> {
+    Err(ProcessorError::Config("the redis cache needs a build with the `redis` feature".to_string()))
+}
+
+/// Sets the backend HTTP responses are cached in during this run, and how
+/// long they are used before being revalidated
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_shared_cache(backend: Arc<dyn CacheBackend>, revalidate_after: Duration) {
+    if SHARED_CACHE.set(backend).is_ok() {
+        let _ = REVALIDATE_AFTER.set(revalidate_after);
+    }
+}
+
+/// The backend HTTP responses are cached in, if one was set
+pub fn shared_cache() -> Option<&'static Arc<dyn CacheBackend>> {
+    SHARED_CACHE.get()
+}
+
+/// How long a cached response is used before it is revalidated
+pub fn revalidate_after() -> Duration {
+    REVALIDATE_AFTER.get().copied().unwrap_or_else(|| CacheConfig::default().revalidate_after())
+}
+
+/// Responses cached in this process, least recently used evicted first
+pub struct MemoryBackend {
+    cache: Cache<Vec<u8>>,
+    prefix: String,
+}
+
+impl MemoryBackend {
+    /// A backend holding up to `memory_mb` of responses for `ttl_secs`
+    pub fn new(config: &CacheConfig) -> Self {
+        let limits = CacheLimits { max_entries: None, max_bytes: Some(config.memory_mb.saturating_mul(1024 * 1024)) };
+        Self {
+            cache: Cache::with_limits(config.ttl(), limits).with_weigher(Vec::len).named("responses"),
+            prefix: config.key_prefix.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheBackend for MemoryBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.cache.get(&format!("{}{}", self.prefix, key)).await)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.cache.set(&format!("{}{}", self.prefix, key), value).await;
+        Ok(())
+    }
+
+    async fn set_with_ttl(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.cache.set_with_ttl(&format!("{}{}", self.prefix, key), value, ttl).await;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<bool> {
+        Ok(self.cache.remove(&format!("{}{}", self.prefix, key)).await)
+    }
+
+    async fn stats(&self) -> Result<Vec<NamespaceStats>> {
+        let now = Utc::now();
+        let mut rows = Vec::new();
+        self.cache.for_each(|key, value, age| {
+            let stored_at = now - chrono::Duration::from_std(age).unwrap_or_else(|_| chrono::Duration::zero());
+            rows.push((key.strip_prefix(self.prefix.as_str()).unwrap_or(key).to_string(), value.len() as u64, stored_at));
+        }).await;
+        Ok(tally(rows))
+    }
+
+    async fn clear(&self, filter: &ClearFilter) -> Result<u64> {
+        let removed = self.cache.retain(|key, _, age| {
+            !filter.matches(key.strip_prefix(self.prefix.as_str()).unwrap_or(key), age)
+        }).await;
+        Ok(removed as u64)
+    }
+}
+
+/// Responses cached in a SQLite database on disk
+///
+/// Every entry is its own row, so a lookup or store touches only that entry
+/// however large the cache grows. Expired rows are deleted when the database
+/// is opened.
+pub struct SqliteBackend {
+    db: Database,
+    prefix: String,
+    ttl_secs: i64,
+}
+
+impl SqliteBackend {
+    /// Opens or creates the database at `path`
+    pub fn open(path: &Path, config: &CacheConfig) -> Result<Self> {
+        let db = Database::open(
+            path,
+            "CREATE TABLE IF NOT EXISTS responses (
+                key TEXT PRIMARY KEY,
+                value BLOB NOT NULL,
+                stored_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS responses_by_expiry ON responses (expires_at);",
+        )?;
+        db.lock().execute("DELETE FROM responses WHERE expires_at <= ?1", params![Utc::now().timestamp()])?;
+        Ok(Self {
+            db,
+            prefix: config.key_prefix.clone(),
+            ttl_secs: i64::try_from(config.ttl_secs).unwrap_or(i64::MAX / 2),
+        })
+    }
+
+    fn store(&self, key: &str, value: Vec<u8>, ttl_secs: i64) -> Result<()> {
+        let now = Utc::now().timestamp();
+        self.db.lock().execute(
+            "INSERT OR REPLACE INTO responses (key, value, stored_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+            params![format!("{}{}", self.prefix, key), value, now, now.saturating_add(ttl_secs)],
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CacheBackend for SqliteBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.db.lock().query_row(
+            "SELECT value FROM responses WHERE key = ?1 AND expires_at > ?2",
+            params![format!("{}{}", self.prefix, key), Utc::now().timestamp()],
+            |row| row.get(0),
+        ).optional().map_err(ProcessorError::from)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.store(key, value, self.ttl_secs)
+    }
+
+    async fn set_with_ttl(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.store(key, value, i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX))
+    }
+
+    async fn remove(&self, key: &str) -> Result<bool> {
+        let removed = self.db.lock()
+            .execute("DELETE FROM responses WHERE key = ?1", params![format!("{}{}", self.prefix, key)])?;
+        Ok(removed > 0)
+    }
+
+    async fn stats(&self) -> Result<Vec<NamespaceStats>> {
+        let conn = self.db.lock();
+        let mut statement = conn.prepare(
+            "SELECT substr(key, ?1), length(value), stored_at FROM responses
+             WHERE substr(key, 1, ?2) = ?3 AND expires_at > ?4",
+        )?;
+        let rows = statement.query_map(
+            params![self.prefix.chars().count() + 1, self.prefix.chars().count(), self.prefix, Utc::now().timestamp()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?, row.get::<_, i64>(2)?)),
+        )?;
+        let rows = rows
+            .map(|row| row.map(|(key, bytes, stored_at)| (key, bytes, Utc.timestamp_opt(stored_at, 0).single().unwrap_or_default())))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(tally(rows))
+    }
+
+    async fn clear(&self, filter: &ClearFilter) -> Result<u64> {
+        let namespace = format!("{}{}", self.prefix, filter.processor.as_deref().map(|p| format!("{}:", p)).unwrap_or_default());
+        let stored_before = filter.older_than.map_or(i64::MAX, |older_than| {
+            Utc::now().timestamp().saturating_sub(i64::try_from(older_than.as_secs()).unwrap_or(i64::MAX))
+        });
+        let removed = self.db.lock().execute(
+            "DELETE FROM responses WHERE substr(key, 1, ?1) = ?2 AND stored_at <= ?3",
+            params![namespace.chars().count(), namespace, stored_before],
+        )?;
+        Ok(removed as u64)
+    }
+}
+
+/// Responses cached in Redis with `SET ... EX`, so Redis expires them
+///
+/// The connection is opened on first use and re-established after failures.
+#[cfg(feature = "redis")]
+pub struct RedisBackend {
+    client: redis::Client,
+    connection: tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    prefix: String,
+    ttl_secs: u64,
+}
+
+#[cfg(feature = "redis")]
+impl RedisBackend {
+    /// A backend using the Redis server at `url`
+    pub fn new(url: &str, config: &CacheConfig) -> Result<Self> {
+        let client = redis::Client::open(url)
+            .map_err(|e| ProcessorError::Config(format!("invalid redis URL: {}", e)))?;
+        Ok(Self {
+            client,
+            connection: tokio::sync::OnceCell::new(),
+            prefix: config.key_prefix.clone(),
+            ttl_secs: config.ttl_secs.max(1),
+        })
+    }
+
+    async fn connection(&self) -> Result<redis::aio::ConnectionManager> {
+        self.connection
+            .get_or_try_init(|| redis::aio::ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+            .map_err(redis_error)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl CacheBackend for RedisBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut connection = self.connection().await?;
+        redis::cmd("GET").arg(format!("{}{}", self.prefix, key))
+            .query_async(&mut connection).await
+            .map_err(redis_error)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.set_with_ttl(key, value, Duration::from_secs(self.ttl_secs)).await
+    }
+
+    async fn set_with_ttl(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let mut command = redis::cmd("SET");
+        command.arg(format!("{}{}", self.prefix, key)).arg(value);
+        if ttl != NEVER_EXPIRES {
+            command.arg("EX").arg(ttl.as_secs().max(1));
+        }
+        command.query_async(&mut connection).await.map_err(redis_error)
+    }
+
+    async fn remove(&self, key: &str) -> Result<bool> {
+        let mut connection = self.connection().await?;
+        let removed: u64 = redis::cmd("DEL").arg(format!("{}{}", self.prefix, key))
+            .query_async(&mut connection).await
+            .map_err(redis_error)?;
+        Ok(removed > 0)
+    }
+
+    async fn stats(&self) -> Result<Vec<NamespaceStats>> {
+        let now = Utc::now();
+        let rows = self.scan().await?.into_iter().map(|(key, bytes, age)| {
+            (key, bytes, now - chrono::Duration::from_std(age).unwrap_or_else(|_| chrono::Duration::zero()))
+        });
+        Ok(tally(rows))
+    }
+
+    async fn clear(&self, filter: &ClearFilter) -> Result<u64> {
+        let keys: Vec<String> = self.scan().await?.into_iter()
+            .filter(|(key, _, age)| filter.matches(key, *age))
+            .map(|(key, _, _)| format!("{}{}", self.prefix, key))
+            .collect();
+        let mut connection = self.connection().await?;
+        let mut removed = 0;
+        for batch in keys.chunks(500) {
+            let deleted: u64 = redis::cmd("DEL").arg(batch).query_async(&mut connection).await.map_err(redis_error)?;
+            removed += deleted;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl RedisBackend {
+    /// Every key under the prefix, without it, with its size and age
+    ///
+    /// Redis keeps no write time, so the age is worked out from how much of
+    /// `cache.ttl_secs` the key has left. Keys stored with their own TTL get
+    /// only an estimate, and keys that never expire count as that old.
+    async fn scan(&self) -> Result<Vec<(String, u64, Duration)>> {
+        let mut connection = self.connection().await?;
+        let pattern = format!("{}*", escape_glob(&self.prefix));
+        let mut keys = Vec::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(1000)
+                .query_async(&mut connection).await
+                .map_err(redis_error)?;
+            keys.extend(batch);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let ttl = Duration::from_secs(self.ttl_secs);
+        let mut entries = Vec::with_capacity(keys.len());
+        for batch in keys.chunks(500) {
+            let mut pipeline = redis::pipe();
+            for key in batch {
+                pipeline.cmd("STRLEN").arg(key).cmd("PTTL").arg(key);
+            }
+            let replies: Vec<i64> = pipeline.query_async(&mut connection).await.map_err(redis_error)?;
+            for (key, reply) in batch.iter().zip(replies.chunks(2)) {
+                let (bytes, remaining_ms) = (reply[0], reply[1]);
+                // Gone since the scan
+                if remaining_ms == -2 {
+                    continue;
+                }
+                let remaining = Duration::from_millis(u64::try_from(remaining_ms).unwrap_or_default());
+                let key = key.strip_prefix(self.prefix.as_str()).unwrap_or(key).to_string();
+                entries.push((key, u64::try_from(bytes).unwrap_or_default(), ttl.saturating_sub(remaining)));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Escapes the characters `SCAN MATCH` treats as a pattern
+#[cfg(feature = "redis")]
+fn escape_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(feature = "redis")]
+fn redis_error(e: redis::RedisError) -> ProcessorError {
+    ProcessorError::Database(format!("redis: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_backend_prefixes_and_expires() {
+        let config = CacheConfig { ttl_secs: 0, ..CacheConfig::default() };
+        let backend = from_config(&config).unwrap().unwrap();
+        backend.set("pypi:requests", b"{}".to_vec()).await.unwrap();
+        assert_eq!(backend.get("pypi:requests").await.unwrap(), None);
+
+        let backend = MemoryBackend::new(&CacheConfig::default());
+        backend.set("pypi:requests", b"{}".to_vec()).await.unwrap();
+        assert_eq!(backend.get("pypi:requests").await.unwrap().as_deref(), Some(&b"{}"[..]));
+        assert!(backend.cache.contains_key("llamapackageservice:pypi:requests").await);
+        assert!(backend.remove("pypi:requests").await.unwrap());
+        assert!(!backend.remove("pypi:requests").await.unwrap());
+
+        let off = CacheConfig { backend: CacheBackendKind::None, ..CacheConfig::default() };
+        assert!(from_config(&off).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_backend_keeps_entries_across_opens() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CacheConfig { backend: CacheBackendKind::Sqlite, dir: dir.path().to_path_buf(), ..CacheConfig::default() };
+        {
+            let backend = from_config(&config).unwrap().unwrap();
+            backend.set("npm:left-pad", b"{}".to_vec()).await.unwrap();
+            backend.set("npm:gone", b"{}".to_vec()).await.unwrap();
+            assert!(backend.remove("npm:gone").await.unwrap());
+        }
+
+        let backend = SqliteBackend::open(&dir.path().join(RESPONSES_DB_FILE), &config).unwrap();
+        assert_eq!(backend.get("npm:left-pad").await.unwrap().as_deref(), Some(&b"{}"[..]));
+        assert_eq!(backend.get("npm:gone").await.unwrap(), None);
+
+        // Entries stored with no TTL are never returned, and are purged on open
+        let expiring = CacheConfig { ttl_secs: 0, ..config };
+        let backend = SqliteBackend::open(&dir.path().join(RESPONSES_DB_FILE), &expiring).unwrap();
+        backend.set("npm:left-pad", b"{}".to_vec()).await.unwrap();
+        assert_eq!(backend.get("npm:left-pad").await.unwrap(), None);
+        let rows: i64 = SqliteBackend::open(&dir.path().join(RESPONSES_DB_FILE), &expiring).unwrap()
+            .db.lock().query_row("SELECT COUNT(*) FROM responses", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[tokio::test]
+    async fn test_namespaces_are_reported_and_cleared() {
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 3600)));
+        assert_eq!(parse_age("90"), Ok(Duration::from_secs(90)));
+        assert!(parse_age("7y").is_err() && parse_age("d").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = CacheConfig { dir: dir.path().to_path_buf(), ..CacheConfig::default() };
+        let sqlite = SqliteBackend::open(&dir.path().join(RESPONSES_DB_FILE), &config).unwrap();
+        let memory = MemoryBackend::new(&config);
+        let backends: [&dyn CacheBackend; 2] = [&sqlite, &memory];
+        for backend in backends {
+            backend.set(&namespaced_key("pypi", "requests", "https://pypi.org/pypi/requests/json"), b"{}".to_vec()).await.unwrap();
+            backend.set(&namespaced_key("pypi", "flask", "https://pypi.org/pypi/flask/json"), b"{}".to_vec()).await.unwrap();
+            backend.set(&namespaced_key("github", "owner/repo", "https://api.github.com/repos/owner/repo"), b"[]".to_vec()).await.unwrap();
+
+            let stats = backend.stats().await.unwrap();
+            let counts: Vec<_> = stats.iter().map(|s| (s.processor.as_str(), s.entries, s.bytes)).collect();
+            assert_eq!(counts, [("github", 1, 2), ("pypi", 2, 4)]);
+
+            let stale = ClearFilter { processor: Some("pypi".to_string()), older_than: Some(Duration::from_secs(3600)) };
+            assert_eq!(backend.clear(&stale).await.unwrap(), 0);
+            let pypi = ClearFilter { processor: Some("pypi".to_string()), older_than: None };
+            assert_eq!(backend.clear(&pypi).await.unwrap(), 2);
+            assert_eq!(backend.stats().await.unwrap().len(), 1);
+            assert_eq!(backend.clear(&ClearFilter::default()).await.unwrap(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entries_keep_their_own_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CacheConfig { ttl_secs: 0, dir: dir.path().to_path_buf(), ..CacheConfig::default() };
+        let sqlite = SqliteBackend::open(&dir.path().join(RESPONSES_DB_FILE), &config).unwrap();
+        let memory = MemoryBackend::new(&config);
+        let backends: [&dyn CacheBackend; 2] = [&sqlite, &memory];
+        for backend in backends {
+            backend.set("crates:serde:metadata", b"{}".to_vec()).await.unwrap();
+            backend.set_with_ttl("crates:serde:serde-1.0.0.crate", b"archive".to_vec(), NEVER_EXPIRES).await.unwrap();
+            backend.set_with_ttl("crates:serde:expired", b"{}".to_vec(), Duration::ZERO).await.unwrap();
+            assert_eq!(backend.get("crates:serde:metadata").await.unwrap(), None);
+            assert_eq!(backend.get("crates:serde:serde-1.0.0.crate").await.unwrap().as_deref(), Some(&b"archive"[..]));
+            assert_eq!(backend.get("crates:serde:expired").await.unwrap(), None);
+        }
+    }
+}
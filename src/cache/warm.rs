@@ -0,0 +1,143 @@
+//! Fetching registry metadata ahead of a batch run
+//!
+//! `cache warm <urls-file>` requests the package metadata or repository
+//! details each URL stands for, the same requests the processors make, so a
+//! later run finds them in the response cache. Archives are not downloaded.
+//! Requests draw from the same per-host budget as the processors, and GitHub
+//! requests carry the configured token.
+
+use super::backend::{namespaced_key, CacheBackend};
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::processors::common::{self, CachedJson};
+use futures_util::{stream, StreamExt};
+use reqwest::Client;
+use serde::Serialize;
+use std::time::Duration;
+use url::Url;
+
+/// The metadata request a target URL stands for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataRequest {
+    /// Processor whose namespace the response is cached in
+    pub processor: &'static str,
+    /// Package or repository, as the processor names it
+    pub target: String,
+    /// API URL the processor fetches
+    pub url: String,
+}
+
+impl MetadataRequest {
+    /// The request for a GitHub, PyPI, npm or crates.io URL
+    pub fn for_url(target_url: &str) -> Result<Self> {
+        let unsupported = || ProcessorError::Validation(format!("no registry metadata to fetch for {}", target_url));
+        let parsed = Url::parse(target_url).map_err(|_| unsupported())?;
+        let segments: Vec<&str> = parsed.path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        let host = parsed.host_str().unwrap_or_default().trim_start_matches("www.");
+        let request = |processor, target: String, url: String| Ok(Self { processor, target, url });
+        match (host, segments.as_slice()) {
+            ("github.com", [owner, repo, ..]) => {
+                let target = format!("{}/{}", owner, repo.trim_end_matches(".git"));
+                request("github", target.clone(), format!("https://api.github.com/repos/{}", target))
+            }
+            ("github.com", [org]) => request("github", org.to_string(), format!("https://api.github.com/orgs/{}", org)),
+            ("pypi.org", ["project", name, ..]) => {
+                request("pypi", name.to_string(), format!("https://pypi.org/pypi/{}/json", name))
+            }
+            ("npmjs.com", ["package", scope, name, ..]) if scope.starts_with('@') => {
+                let package = format!("{}/{}", scope, name);
+                request("npm", package.clone(), format!("https://registry.npmjs.org/{}", package))
+            }
+            ("npmjs.com", ["package", name, ..]) => {
+                request("npm", name.to_string(), format!("https://registry.npmjs.org/{}", name))
+            }
+            ("crates.io", ["crates", name, ..]) => {
+                request("crates", name.to_string(), format!("https://crates.io/api/v1/crates/{}", name))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+/// How warming went
+#[derive(Debug, Default, Serialize)]
+pub struct WarmReport {
+    /// Targets whose metadata is now cached
+    pub warmed: u64,
+    /// Targets the registry had nothing for, or that failed, with why
+    pub failed: Vec<(String, String)>,
+    /// URLs that are not on a registry with metadata to cache
+    pub skipped: Vec<String>,
+}
+
+/// Fetches the metadata of every URL in `urls` into `cache`, `concurrency` at a time
+///
+/// `github_token` authenticates GitHub requests, which raises their rate limit.
+pub async fn warm(
+    cache: &dyn CacheBackend,
+    urls: &[String],
+    concurrency: usize,
+    github_token: Option<&str>,
+) -> Result<WarmReport> {
+    let registries = http::client_builder()
+        .timeout(Duration::from_secs(30))
+        .user_agent(concat!("llamapackageservice/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let github = common::create_github_client_with_retry(github_token)?;
+
+    let mut report = WarmReport::default();
+    let mut requests = Vec::new();
+    for url in urls {
+        match MetadataRequest::for_url(url) {
+            Ok(request) => requests.push(request),
+            Err(_) => report.skipped.push(url.clone()),
+        }
+    }
+    // The same package listed twice is fetched once
+    requests.sort_by(|a, b| a.url.cmp(&b.url));
+    requests.dedup_by(|a, b| a.url == b.url);
+
+    let (registries, github) = (&registries, &github);
+    let mut outcomes = stream::iter(requests)
+        .map(|request| async move {
+            let client = if request.processor == "github" { github } else { registries };
+            let key = namespaced_key(request.processor, &request.target, &request.url);
+            // Always asking the registry refreshes entries cached earlier
+            let fetched = common::fetch_json_through(Some(cache), Duration::ZERO, client.get(&request.url), &key, &request.url).await;
+            (request, fetched)
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some((request, fetched)) = outcomes.next().await {
+        match fetched {
+            Ok(CachedJson::Found(_)) => report.warmed += 1,
+            Ok(CachedJson::Failed(status)) => report.failed.push((request.url, format!("HTTP {}", status))),
+            Err(e) => report.failed.push((request.url, e.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_requests_match_the_processors() {
+        let request = |url: &str| MetadataRequest::for_url(url).map(|r| (r.processor, r.target, r.url));
+        assert_eq!(
+            request("https://github.com/rust-lang/cargo.git").unwrap(),
+            ("github", "rust-lang/cargo".to_string(), "https://api.github.com/repos/rust-lang/cargo".to_string())
+        );
+        assert_eq!(request("https://github.com/rust-lang").unwrap().2, "https://api.github.com/orgs/rust-lang");
+        assert_eq!(request("https://pypi.org/project/requests/").unwrap().2, "https://pypi.org/pypi/requests/json");
+        assert_eq!(
+            request("https://www.npmjs.com/package/@types/node").unwrap(),
+            ("npm", "@types/node".to_string(), "https://registry.npmjs.org/@types/node".to_string())
+        );
+        assert_eq!(request("https://crates.io/crates/serde").unwrap().2, "https://crates.io/api/v1/crates/serde");
+        assert!(request("https://pkg.go.dev/golang.org/x/net").is_err());
+        assert!(request("./local/dir").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Graceful cancellation on Ctrl-C
+//!
+//! The first Ctrl-C cancels the run instead of killing the process: network
+//! transfers wrapped in [`or_cancelled`] stop at once, processors that check
+//! [`is_cancelled`] between files stop early and write what they have so far
+//! marked [`INCOMPLETE_MARKER`], and the command still writes its run manifest
+//! before exiting with [`exit_code::INTERRUPTED`]. Work that does not finish
+//! within [`GRACE_PERIOD`] is dropped. A second Ctrl-C exits immediately.
+
+use crate::error::{exit_code, ProcessorError, Result};
+use log::warn;
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Marker placed at the top of output cut short by cancellation
+pub const INCOMPLETE_MARKER: &str = "(incomplete)";
+
+/// How long cancelled work may take to write its partial output
+pub const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+static TOKEN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+
+/// Cancels the current run
+pub fn cancel() {
+    TOKEN.cancel();
+}
+
+/// Whether the run has been cancelled
+pub fn is_cancelled() -> bool {
+    TOKEN.is_cancelled()
+}
+
+/// Resolves once the run is cancelled
+pub async fn cancelled() {
+    TOKEN.cancelled().await
+}
+
+/// Runs `fut` unless the run is cancelled first, in which case `fut` is dropped
+pub async fn or_cancelled<T>(fut: impl Future<Output = Result<T>>) -> Result<T> {
+    race(&TOKEN, fut).await
+}
+
+/// Runs `fut`, giving it [`GRACE_PERIOD`] to wind down once the run is cancelled
+pub async fn with_grace_period<T>(fut: impl Future<Output = Result<T>>) -> Result<T> {
+    wind_down(&TOKEN, GRACE_PERIOD, fut).await
+}
+
+async fn race<T>(token: &CancellationToken, fut: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(ProcessorError::Cancelled),
+        result = fut => result,
+    }
+}
+
+async fn wind_down<T>(token: &CancellationToken, grace: Duration, fut: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::pin!(fut);
+    tokio::select! {
+        result = &mut fut => return result,
+        _ = token.cancelled() => {}
+    }
+    tokio::time::timeout(grace, fut).await.unwrap_or(Err(ProcessorError::Cancelled))
+}
+
+/// Notice placed above partial output, saying how far processing got
+pub fn incomplete_notice(done: usize, total: usize, what: &str) -> String {
+    format!("> {} Processing was interrupted after {} of {} {}.\n\n", INCOMPLETE_MARKER, done, total, what)
+}
+
+/// Cancels the run on the first Ctrl-C and exits on the second
+pub fn install_ctrl_c_handler() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("Interrupted; saving partial output (press Ctrl-C again to exit immediately)");
+        cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(exit_code::INTERRUPTED);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The process-wide token is left alone so other tests are not cancelled
+    #[tokio::test]
+    async fn test_cancellation_stops_work() {
+        let token = CancellationToken::new();
+        assert_eq!(race(&token, async { Ok(1) }).await.unwrap(), 1);
+
+        token.cancel();
+        let pending = race(&token, std::future::pending::<Result<()>>()).await;
+        assert!(matches!(pending, Err(ProcessorError::Cancelled)));
+
+        // Work that winds down in time reports its own result; work that does not is dropped
+        let flushed = wind_down(&token, Duration::from_millis(50), async { Ok("partial") }).await;
+        assert_eq!(flushed.unwrap(), "partial");
+        let stuck = wind_down(&token, Duration::from_millis(50), std::future::pending::<Result<()>>()).await;
+        assert!(matches!(stuck, Err(ProcessorError::Cancelled)));
+    }
+}
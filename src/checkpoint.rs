@@ -0,0 +1,185 @@
+//! Checkpoints for resumable batch runs
+//!
+//! A batch appends the outcome of every URL to
+//! `<output>/.batch-checkpoints/<hash>.jsonl` as soon as the URL finishes,
+//! one JSON line each, where the hash is that of the batch's URL list. An
+//! interrupted or crashed run of the same list can be resumed without
+//! reprocessing what already completed, and URLs that failed can be retried
+//! on their own. Appending keeps each write small however long the batch;
+//! opening a checkpoint drops a line the crash left half-written and compacts
+//! the file down to the latest outcome of each URL.
+
+use crate::batch_report::UrlResult;
+use crate::error::Result;
+use crate::perf;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Directory, relative to the output directory, holding batch checkpoints
+pub const CHECKPOINT_DIR: &str = ".batch-checkpoints";
+
+/// Which URLs of a batch are processed again when a checkpoint exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResumeMode {
+    /// Start over, discarding any checkpoint
+    #[default]
+    Restart,
+    /// Skip every URL with a recorded outcome
+    Resume,
+    /// Skip URLs that succeeded; process failed and unfinished ones
+    RetryFailed,
+}
+
+/// Recorded progress of one list of batch URLs
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// Latest outcome of each finished URL
+    pub results: BTreeMap<String, UrlResult>,
+    path: PathBuf,
+    journal: File,
+}
+
+impl Checkpoint {
+    /// Path of the checkpoint for the batch of `urls` under `output_dir`
+    ///
+    /// The same URLs in the same order share a checkpoint wherever they were
+    /// read from, and a changed list starts a fresh one.
+    pub fn path_for(output_dir: &Path, urls: &[String]) -> PathBuf {
+        let hash = perf::hash_bytes(urls.join("\n").as_bytes());
+        output_dir.join(CHECKPOINT_DIR).join(format!("{}.jsonl", &hash[..16]))
+    }
+
+    /// Opens the checkpoint for `urls`, loading recorded progress unless the mode restarts
+    pub fn open(output_dir: &Path, urls: &[String], mode: ResumeMode) -> Result<Self> {
+        let path = Self::path_for(output_dir, urls);
+        let results = if mode != ResumeMode::Restart && path.exists() {
+            Self::replay(&path)?
+        } else {
+            BTreeMap::new()
+        };
+
+        // Compact to one line per URL, replacing the journal atomically
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut compacted = Vec::new();
+        for result in results.values() {
+            serde_json::to_writer(&mut compacted, result)?;
+            compacted.push(b'\n');
+        }
+        fs::write(&tmp, compacted)?;
+        fs::rename(&tmp, &path)?;
+
+        let journal = OpenOptions::new().append(true).open(&path)?;
+        Ok(Checkpoint { results, path, journal })
+    }
+
+    /// Outcomes in the journal at `path`, the latest for each URL
+    ///
+    /// A line that does not parse is the one being written when the previous
+    /// run died; its URL counts as unfinished.
+    fn replay(path: &Path) -> Result<BTreeMap<String, UrlResult>> {
+        let mut results = BTreeMap::new();
+        for line in fs::read_to_string(path)?.lines() {
+            if let Ok(result) = serde_json::from_str::<UrlResult>(line) {
+                results.insert(result.url.clone(), result);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Where the checkpoint is stored
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// URLs from `urls` that still need processing under `mode`, in input order
+    pub fn pending(&self, urls: &[String], mode: ResumeMode) -> Vec<String> {
+        urls.iter()
+            .filter(|url| match (self.results.get(*url), mode) {
+                (None, _) | (_, ResumeMode::Restart) => true,
+                (Some(result), ResumeMode::RetryFailed) => !result.succeeded,
+                (Some(_), ResumeMode::Resume) => false,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Records the outcome of a URL, appending it to the checkpoint
+    pub fn record(&mut self, result: UrlResult) -> Result<()> {
+        let mut line = serde_json::to_vec(&result)?;
+        line.push(b'\n');
+        // One write per line, so a crash can tear at most the last one
+        self.journal.write_all(&line)?;
+        self.results.insert(result.url.clone(), result);
+        Ok(())
+    }
+
+    /// Outcomes recorded for `urls`, in input order
+    pub fn results_for(&self, urls: &[String]) -> Vec<UrlResult> {
+        urls.iter().filter_map(|url| self.results.get(url).cloned()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn outcome(url: &str, succeeded: bool) -> UrlResult {
+        UrlResult {
+            url: url.to_string(),
+            processor: "github".to_string(),
+            succeeded,
+            error: (!succeeded).then(|| "timed out".to_string()),
+            duration_ms: 5,
+        }
+    }
+
+    #[test]
+    fn test_resume_skips_recorded_urls() {
+        let dir = tempdir().unwrap();
+        let urls: Vec<String> = ["a", "b", "c"].iter().map(|u| u.to_string()).collect();
+
+        let mut checkpoint = Checkpoint::open(dir.path(), &urls, ResumeMode::Restart).unwrap();
+        checkpoint.record(outcome("a", true)).unwrap();
+        checkpoint.record(outcome("b", false)).unwrap();
+
+        let resumed = Checkpoint::open(dir.path(), &urls, ResumeMode::Resume).unwrap();
+        assert_eq!(resumed.pending(&urls, ResumeMode::Resume), ["c"]);
+        assert_eq!(resumed.pending(&urls, ResumeMode::RetryFailed), ["b", "c"]);
+        assert_eq!(resumed.results_for(&urls).len(), 2);
+
+        // A different list of URLs has its own checkpoint
+        let other: Vec<String> = vec!["a".to_string()];
+        assert!(Checkpoint::open(dir.path(), &other, ResumeMode::Resume).unwrap().results.is_empty());
+
+        let restarted = Checkpoint::open(dir.path(), &urls, ResumeMode::Restart).unwrap();
+        assert!(restarted.results.is_empty());
+        assert_eq!(restarted.pending(&urls, ResumeMode::Restart), urls);
+    }
+
+    #[test]
+    fn test_crashed_runs_resume_from_the_journal() {
+        let dir = tempdir().unwrap();
+        let urls: Vec<String> = ["a", "b", "c"].iter().map(|u| u.to_string()).collect();
+
+        let mut checkpoint = Checkpoint::open(dir.path(), &urls, ResumeMode::Resume).unwrap();
+        checkpoint.record(outcome("a", false)).unwrap();
+        checkpoint.record(outcome("a", true)).unwrap();
+        checkpoint.record(outcome("b", false)).unwrap();
+        drop(checkpoint);
+
+        // The run died halfway through writing the outcome of "c"
+        let path = Checkpoint::path_for(dir.path(), &urls);
+        let mut journal = OpenOptions::new().append(true).open(&path).unwrap();
+        journal.write_all(br#"{"url":"c","proc"#).unwrap();
+
+        let resumed = Checkpoint::open(dir.path(), &urls, ResumeMode::RetryFailed).unwrap();
+        assert_eq!(resumed.pending(&urls, ResumeMode::RetryFailed), ["b", "c"]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
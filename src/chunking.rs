@@ -0,0 +1,197 @@
+//! Token-aware chunking and truncation
+//!
+//! Content embedded in reports (README excerpts, descriptions, AI context) is
+//! limited by a token budget rather than a raw character count, and cuts are
+//! made at the most natural boundary that fits: a heading, a paragraph break,
+//! a sentence end, and only as a last resort an arbitrary token.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+
+/// Marker appended to truncated content
+pub const TRUNCATION_MARKER: &str = "\n\n... (truncated)";
+
+static TOKENIZER: Lazy<Option<CoreBPE>> = Lazy::new(|| tiktoken_rs::cl100k_base().ok());
+
+/// Preferred place to cut text when it exceeds its budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    /// Cut before a markdown heading
+    Heading,
+    /// Cut at a blank line
+    Paragraph,
+    /// Cut after a sentence terminator or line break
+    Sentence,
+    /// Cut at any token
+    Token,
+}
+
+impl Boundary {
+    /// The next finer boundary to fall back to when nothing fits
+    fn finer(self) -> Option<Boundary> {
+        match self {
+            Boundary::Heading => Some(Boundary::Paragraph),
+            Boundary::Paragraph => Some(Boundary::Sentence),
+            Boundary::Sentence => Some(Boundary::Token),
+            Boundary::Token => None,
+        }
+    }
+}
+
+/// Token budget and cut preference for one output target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TruncationLimit {
+    /// Maximum number of tokens to keep
+    pub max_tokens: usize,
+    /// Preferred boundary to cut at
+    pub boundary: Boundary,
+}
+
+impl TruncationLimit {
+    /// Creates a new limit
+    pub fn new(max_tokens: usize, boundary: Boundary) -> Self {
+        Self { max_tokens, boundary }
+    }
+
+    /// Truncates `text` to this limit
+    pub fn apply(&self, text: &str) -> String {
+        truncate(text, self.max_tokens, self.boundary)
+    }
+}
+
+/// Counts the tokens in `text` using the cl100k tokenizer
+///
+/// Falls back to a four-characters-per-token estimate if the tokenizer
+/// cannot be loaded.
+pub fn count_tokens(text: &str) -> usize {
+    match TOKENIZER.as_ref() {
+        Some(bpe) => bpe.encode_with_special_tokens(text).len(),
+        None => text.chars().count().div_ceil(4),
+    }
+}
+
+/// Truncates `text` to at most `max_tokens`, cutting at the preferred boundary
+///
+/// A truncation marker is appended when anything was removed.
+pub fn truncate(text: &str, max_tokens: usize, boundary: Boundary) -> String {
+    let (head, tail) = split_at_budget(text, max_tokens, boundary);
+    if tail.is_empty() {
+        head.to_string()
+    } else {
+        format!("{}{}", head.trim_end(), TRUNCATION_MARKER)
+    }
+}
+
+/// Splits `text` into consecutive chunks of at most `max_tokens` each
+pub fn chunk(text: &str, max_tokens: usize, boundary: Boundary) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (head, tail) = split_at_budget(rest, max_tokens.max(1), boundary);
+        chunks.push(head.to_string());
+        rest = tail;
+    }
+    chunks
+}
+
+/// Returns the longest prefix within budget that ends on a boundary, and the remainder
+fn split_at_budget(text: &str, max_tokens: usize, boundary: Boundary) -> (&str, &str) {
+    if count_tokens(text) <= max_tokens {
+        return (text, "");
+    }
+
+    let mut current = Some(boundary);
+    while let Some(boundary) = current {
+        let cuts = cut_points(text, boundary);
+        // Prefixes only grow as the cut moves right, so binary search for the last one that fits
+        let fitting = cuts.partition_point(|&cut| count_tokens(&text[..cut]) <= max_tokens);
+        if fitting > 0 {
+            let cut = cuts[fitting - 1];
+            return (&text[..cut], &text[cut..]);
+        }
+        current = boundary.finer();
+    }
+
+    // Not even a single character fits; always make progress
+    let first = text.chars().next().map_or(0, char::len_utf8);
+    (&text[..first], &text[first..])
+}
+
+/// Byte offsets (in increasing order) where `text` may be cut at the given boundary
+fn cut_points(text: &str, boundary: Boundary) -> Vec<usize> {
+    match boundary {
+        Boundary::Heading => {
+            let mut cuts = Vec::new();
+            let mut offset = 0;
+            for line in text.split_inclusive('\n') {
+                if offset > 0 && line.trim_start().starts_with('#') {
+                    cuts.push(offset);
+                }
+                offset += line.len();
+            }
+            cuts
+        }
+        Boundary::Paragraph => text.match_indices("\n\n").map(|(i, _)| i + 2).collect(),
+        Boundary::Sentence => {
+            let bytes = text.as_bytes();
+            let mut cuts = Vec::new();
+            for (i, &b) in bytes.iter().enumerate() {
+                let at_sentence_end = matches!(b, b'.' | b'!' | b'?')
+                    && bytes.get(i + 1).is_some_and(|next| next.is_ascii_whitespace());
+                if b == b'\n' || at_sentence_end {
+                    cuts.push(i + 1);
+                }
+            }
+            cuts
+        }
+        Boundary::Token => text.char_indices().map(|(i, _)| i).skip(1).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_text_is_untouched() {
+        assert_eq!(truncate("Hello world.", 100, Boundary::Sentence), "Hello world.");
+    }
+
+    #[test]
+    fn test_truncates_at_sentence_boundary() {
+        let text = "First sentence here. Second sentence is a bit longer than the first one. Third.";
+        let budget = count_tokens("First sentence here.");
+        let truncated = truncate(text, budget, Boundary::Sentence);
+        assert_eq!(truncated, format!("First sentence here.{}", TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_truncates_before_heading() {
+        let text = "# Title\n\nIntro text.\n\n## Install\n\nRun the installer and follow every step carefully.\n";
+        let budget = count_tokens("# Title\n\nIntro text.\n\n## Install\n\nRun");
+        let truncated = truncate(text, budget, Boundary::Heading);
+        assert!(truncated.starts_with("# Title\n\nIntro text."));
+        assert!(!truncated.contains("## Install"));
+    }
+
+    #[test]
+    fn test_falls_back_to_finer_boundary() {
+        let text = "one two three four five six seven eight nine ten eleven twelve";
+        let truncated = truncate(text, 3, Boundary::Heading);
+        assert!(truncated.ends_with(TRUNCATION_MARKER));
+        assert!(count_tokens(truncated.trim_end_matches(TRUNCATION_MARKER)) <= 3);
+    }
+
+    #[test]
+    fn test_chunks_cover_input() {
+        let text = "Alpha beta. Gamma delta. Epsilon zeta. Eta theta. Iota kappa.";
+        let chunks = chunk(text, 4, Boundary::Sentence);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), text);
+        for chunk in &chunks {
+            assert!(count_tokens(chunk) <= 4);
+        }
+    }
+}
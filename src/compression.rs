@@ -0,0 +1,312 @@
+//! Transparent compression of output artifacts
+//!
+//! Processed repository dumps can run to hundreds of megabytes. When
+//! compression is enabled, artifacts are written as `.txt.zst` / `.json.gz`
+//! next to where the plain file would have gone, and every directory gets a
+//! small index recording the compressed and uncompressed size of each file.
+//! Readers go through [`read_artifact`], which decompresses based on the
+//! file extension, so the rest of the tool does not care how a file was stored.
+//! Encrypted artifacts (see [`crate::encryption`]) are handled the same way.
+
+use crate::encryption::{self, EncryptionKey};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Name of the per-directory index of compressed artifacts
+pub const INDEX_FILE_NAME: &str = ".compression-index.json";
+
+/// zstd level used for artifacts; favours speed over the last few percent of ratio
+const ZSTD_LEVEL: i32 = 3;
+
+static OUTPUT_COMPRESSION: OnceCell<Compression> = OnceCell::new();
+
+/// Serializes index updates from concurrent writers within this process
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Compression applied to output artifacts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Write artifacts as plain files
+    #[default]
+    None,
+    /// gzip (`.gz`), readable by every standard tool
+    Gzip,
+    /// Zstandard (`.zst`), faster and smaller than gzip
+    Zstd,
+}
+
+impl Compression {
+    /// File extension appended to compressed artifacts, if any
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Detects the compression of a file from its extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" | "off" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            other => Err(format!("unknown compression '{}' (expected none, gzip or zstd)", other)),
+        }
+    }
+}
+
+/// Sets the compression used for artifacts written during this run
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_output_compression(compression: Compression) {
+    let _ = OUTPUT_COMPRESSION.set(compression);
+}
+
+/// Returns the compression used for artifacts written during this run
+pub fn output_compression() -> Compression {
+    OUTPUT_COMPRESSION.get().copied().unwrap_or_default()
+}
+
+/// Size information recorded for a compressed artifact
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Compression the artifact was written with
+    pub algorithm: Compression,
+    /// Size of the file on disk
+    pub compressed_size: u64,
+    /// Size of the content once decompressed
+    pub uncompressed_size: u64,
+    /// Whether the artifact is encrypted at rest
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// Per-directory record of compressed artifacts, keyed by file name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompressionIndex {
+    /// Entries keyed by the compressed file name
+    pub files: BTreeMap<String, IndexEntry>,
+}
+
+impl CompressionIndex {
+    /// Loads the index for `dir`, returning an empty index if there is none
+    pub fn load(dir: &Path) -> Self {
+        fs::read(dir.join(INDEX_FILE_NAME))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the index for `dir`
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        fs::write(dir.join(INDEX_FILE_NAME), json)
+    }
+
+    /// Looks up the entry for a file in this directory
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        let name = path.file_name()?.to_str()?;
+        self.files.get(name)
+    }
+}
+
+/// Compresses `data` with the given algorithm
+pub fn compress(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Compression::Zstd => zstd::encode_all(data, ZSTD_LEVEL),
+    }
+}
+
+/// Decompresses `data` that was written with the given algorithm
+pub fn decompress(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Gzip => {
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(data).read_to_end(&mut decoded)?;
+            Ok(decoded)
+        }
+        Compression::Zstd => zstd::decode_all(data),
+    }
+}
+
+/// Writes an artifact using the configured output compression and encryption
+///
+/// Returns the path actually written, which carries the compression and
+/// encryption extensions when those are enabled.
+pub fn write_artifact(path: &Path, data: &[u8]) -> io::Result<PathBuf> {
+    write_artifact_with(path, data, output_compression())
+}
+
+/// Writes an artifact with an explicit compression, updating the directory index
+///
+/// The artifact is still encrypted if an output key is configured.
+pub fn write_artifact_with(path: &Path, data: &[u8], compression: Compression) -> io::Result<PathBuf> {
+    write_sealed(path, data, compression, encryption::output_encryption())
+}
+
+fn write_sealed(path: &Path, data: &[u8], compression: Compression, key: Option<&EncryptionKey>) -> io::Result<PathBuf> {
+    if compression == Compression::None && key.is_none() {
+        fs::write(path, data)?;
+        return Ok(path.to_path_buf());
+    }
+
+    let mut target = path.to_path_buf();
+    if let Some(extension) = compression.extension() {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(extension);
+        target = path.with_file_name(file_name);
+    }
+    let mut encoded = compress(data, compression)?;
+    if let Some(key) = key {
+        encoded = encryption::encrypt(&encoded, key)?;
+        target = encryption::encrypted_path(&target);
+    }
+    fs::write(&target, &encoded)?;
+
+    let dir = target.parent().unwrap_or_else(|| Path::new("."));
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut index = CompressionIndex::load(dir);
+    index.files.insert(
+        target.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        IndexEntry {
+            algorithm: compression,
+            compressed_size: encoded.len() as u64,
+            uncompressed_size: data.len() as u64,
+            encrypted: key.is_some(),
+        },
+    );
+    index.save(dir)?;
+
+    Ok(target)
+}
+
+/// Reads an artifact, decrypting and decompressing it as its extensions say
+pub fn read_artifact(path: &Path) -> io::Result<Vec<u8>> {
+    read_sealed(path, encryption::output_encryption())
+}
+
+fn read_sealed(path: &Path, key: Option<&EncryptionKey>) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    if !encryption::is_encrypted(path) {
+        return decompress(&data, Compression::from_path(path));
+    }
+    let key = key.ok_or_else(|| io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} is encrypted; set {} to read it", path.display(), encryption::KEY_ENV_VAR),
+    ))?;
+    decompress(&encryption::decrypt(&data, key)?, Compression::from_path(&path.with_extension("")))
+}
+
+/// Opens an artifact for streaming reads, decompressing on the fly
+///
+/// Encrypted artifacts are authenticated as a whole, so they are decrypted
+/// into memory before being handed out.
+pub fn open_artifact(path: &Path) -> io::Result<Box<dyn Read>> {
+    if encryption::is_encrypted(path) {
+        return Ok(Box::new(io::Cursor::new(read_artifact(path)?)));
+    }
+    let file = fs::File::open(path)?;
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::read::GzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+    })
+}
+
+/// Returns the file name with any encryption and compression extensions removed
+pub fn logical_name(file_name: &str) -> &str {
+    let file_name = file_name.strip_suffix(".enc").unwrap_or(file_name);
+    file_name
+        .strip_suffix(".zst")
+        .or_else(|| file_name.strip_suffix(".gz"))
+        .unwrap_or(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_round_trip_records_sizes() {
+        let dir = tempdir().unwrap();
+        let content = "fn main() {}\n".repeat(1000);
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let written = write_artifact_with(&dir.path().join("dump.txt"), content.as_bytes(), compression).unwrap();
+            assert_eq!(written.extension().unwrap(), compression.extension().unwrap());
+            assert_eq!(read_artifact(&written).unwrap(), content.as_bytes());
+
+            let entry = *CompressionIndex::load(dir.path()).get(&written).unwrap();
+            assert_eq!(entry.algorithm, compression);
+            assert_eq!(entry.uncompressed_size, content.len() as u64);
+            assert_eq!(entry.compressed_size, fs::metadata(&written).unwrap().len());
+            assert!(entry.compressed_size < entry.uncompressed_size);
+        }
+    }
+
+    #[test]
+    fn test_uncompressed_artifacts_are_plain() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        let written = write_artifact_with(&path, b"{}", Compression::None).unwrap();
+
+        assert_eq!(written, path);
+        assert_eq!(read_artifact(&written).unwrap(), b"{}");
+        assert!(!dir.path().join(INDEX_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_logical_name() {
+        assert_eq!(logical_name("a_github_repo.txt.zst"), "a_github_repo.txt");
+        assert_eq!(logical_name("index.json.gz"), "index.json");
+        assert_eq!(logical_name("plain.txt"), "plain.txt");
+        assert_eq!(logical_name("secret.txt.zst.enc"), "secret.txt");
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let dir = tempdir().unwrap();
+        let key: EncryptionKey = "11".repeat(32).parse().unwrap();
+        let content = "let token = \"hunter2\";\n".repeat(100);
+
+        let written = write_sealed(&dir.path().join("dump.txt"), content.as_bytes(), Compression::Zstd, Some(&key)).unwrap();
+        assert_eq!(written.file_name().unwrap(), "dump.txt.zst.enc");
+        assert!(!String::from_utf8_lossy(&fs::read(&written).unwrap()).contains("hunter2"));
+        assert_eq!(read_sealed(&written, Some(&key)).unwrap(), content.as_bytes());
+        assert!(read_sealed(&written, None).is_err());
+        assert!(CompressionIndex::load(dir.path()).get(&written).unwrap().encrypted);
+
+        let plain = write_sealed(&dir.path().join("notes.md"), b"# notes", Compression::None, Some(&key)).unwrap();
+        assert_eq!(plain.file_name().unwrap(), "notes.md.enc");
+        assert_eq!(read_sealed(&plain, Some(&key)).unwrap(), b"# notes");
+    }
+}
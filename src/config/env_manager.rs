@@ -11,6 +11,7 @@ use dialoguer;
 
 /// Stores API keys for various services
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ApiKeys {
     /// GitHub API token for authenticated requests
     pub github_token: Option<String>,
@@ -1,9 +1,26 @@
 mod env_manager;
+pub mod reload;
+mod resolve;
+mod validate;
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use crate::error::{ProcessorError, Result};
+use crate::chunking::{Boundary, TruncationLimit};
+use crate::compression::Compression;
+use crate::encryption::EncryptionConfig;
+use crate::output_organizer::OutputNaming;
+use crate::retention::RetentionPolicy;
+use crate::output::storage::StorageConfig;
+use crate::scheduling::ScheduleConfig;
+use crate::webhook::GitHubWebhookConfig;
+use crate::cache::backend::CacheConfig;
+use crate::http::{CircuitBreakerConfig, ProxyConfig, TlsConfig};
+use crate::agents::usage::AiBudget;
+use crate::metrics::MetricsConfig;
+use crate::logging::LoggingConfig;
 use std::fs;
 use toml;
 use regex;
@@ -11,12 +28,15 @@ use std::io;
 use tokio;
 
 pub use env_manager::ApiKeys;
+pub use resolve::{set_config_path, ConfigSource, ResolvedConfig, CONFIG_ENV_VAR, ENV_ALIASES, ENV_PREFIX};
+pub use validate::{problems_error, ConfigProblem};
 
 /// Main configuration struct for the application
 ///
 /// This structure holds all configuration settings including API tokens,
 /// output directories, processing limits, and other settings.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     /// GitHub API token for authenticated requests
     pub github_token: Option<String>,
@@ -32,10 +52,38 @@ pub struct Config {
     pub api_keys: ApiKeys,
     /// Patterns for files to exclude from processing
     pub excluded_files: Vec<String>,
+    /// Token budgets for content embedded in generated output
+    #[serde(default)]
+    pub truncation: TruncationConfig,
+    /// REST API server settings
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Cache of registry responses
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Skipping hosts that keep failing
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Proxy for outbound requests
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Root certificates trusted for HTTPS
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// Spending caps on AI requests
+    #[serde(default)]
+    pub ai_budget: AiBudget,
+    /// Exporting metrics to Prometheus
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Structured logs written to a rotating file
+    #[serde(default)]
+    pub logging: LoggingConfig,
 }
 
 /// Configuration for parallel processing operations
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ProcessingConfig {
     /// Maximum number of concurrent downloads
     pub max_concurrent_downloads: usize,
@@ -43,21 +91,118 @@ pub struct ProcessingConfig {
     pub max_concurrent_extractions: usize,
     /// Maximum number of concurrent code analyses
     pub max_concurrent_analyses: usize,
+    /// Number of API jobs processed at the same time
+    pub max_concurrent_jobs: usize,
+    /// Batch URLs on one host processed at the same time (0 for no limit)
+    pub max_concurrent_per_host: usize,
+    /// Longest a plugin may take to describe itself (seconds)
+    pub plugin_describe_timeout_secs: u64,
+    /// Longest a plugin may take to process one URL (seconds, 0 for no limit)
+    pub plugin_timeout_secs: u64,
+    /// Probe the external links in processed repositories' documentation
+    pub check_links: bool,
+    /// Fail processing a repository whose README scores below this documentation health (0 to 100, 0 for no gate)
+    pub docs_min_score: u8,
 }
 
-/// Rate limit settings for various APIs
+/// Rate limit settings for outbound requests
+///
+/// Requests follow the budget GitHub and the registries report in their
+/// `X-RateLimit-*` and `Retry-After` headers, within each host's own limit.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RateLimits {
-    /// GitHub API rate limit (requests per hour)
-    pub github_api: u32,
-    /// PyPI API rate limit (requests per minute)
-    pub pypi_api: u32,
-    /// NPM API rate limit (requests per minute)
-    pub npm_api: u32,
+    /// Longest a request waits for a host's rate limit to reset before failing (seconds)
+    pub max_pause_secs: u64,
+    /// Limits of individual hosts, by host name, on top of the built-in ones
+    pub hosts: BTreeMap<String, HostRateLimit>,
+}
+
+/// Budget shared by every request to one host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostRateLimit {
+    /// Requests per minute on average (0 for no limit)
+    pub requests_per_minute: u32,
+    /// Requests allowed in a burst
+    #[serde(default = "default_host_burst")]
+    pub burst: u32,
+}
+
+fn default_host_burst() -> u32 {
+    1
+}
+
+impl RateLimits {
+    /// Longest a request waits for a host's rate limit
+    pub fn max_pause(&self) -> Duration {
+        Duration::from_secs(self.max_pause_secs)
+    }
+
+    /// Built-in host limits overridden by the configured ones
+    pub fn host_limits(&self) -> BTreeMap<String, HostRateLimit> {
+        let mut limits: BTreeMap<String, HostRateLimit> = crate::rate_limiter::DEFAULT_HOST_LIMITS
+            .iter()
+            .map(|&(host, requests_per_minute, burst)| (host.to_string(), HostRateLimit { requests_per_minute, burst }))
+            .collect();
+        limits.extend(self.hosts.clone());
+        limits
+    }
+}
+
+/// Settings for the REST API server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Requests per minute allowed for each API key or token (0 for no limit)
+    pub key_requests_per_minute: u32,
+    /// Requests an API key may make in a burst
+    pub key_burst: u32,
+    /// Requests per minute allowed for each IP address sending no credential (0 for no limit)
+    pub ip_requests_per_minute: u32,
+    /// Requests an IP address may make in a burst
+    pub ip_burst: u32,
+    /// Quota of workspaces without their own entry in `workspaces`
+    pub workspace_quota: WorkspaceQuota,
+    /// Quotas of individual workspaces, by name
+    pub workspaces: HashMap<String, WorkspaceQuota>,
+    /// Repositories processed when GitHub reports a change
+    pub github_webhook: GitHubWebhookConfig,
+    /// Jobs queued on a schedule
+    pub schedules: Vec<ScheduleConfig>,
+    /// Leave queued jobs to remote workers (`serve --worker`) instead of processing them in the server
+    pub remote_workers: bool,
+}
+
+impl ServerConfig {
+    /// Quota that applies to the workspace `name`
+    pub fn quota_for(&self, name: &str) -> &WorkspaceQuota {
+        self.workspaces.get(name).unwrap_or(&self.workspace_quota)
+    }
+}
+
+/// Limits on what one workspace may use; 0 means no limit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceQuota {
+    /// Jobs that may be submitted in any 24 hours
+    pub jobs_per_day: u32,
+    /// Disk space the workspace's output may take up, in megabytes
+    pub storage_mb: u64,
+}
+
+/// Token budgets for each kind of embedded content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TruncationConfig {
+    /// README excerpts embedded in organization overviews
+    pub readme_excerpt: TruncationLimit,
+    /// Repository descriptions shown in overview tables
+    pub description: TruncationLimit,
 }
 
 /// Configuration for output files and directories
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct OutputConfig {
     /// Base directory for all output
     pub base_dir: PathBuf,
@@ -65,6 +210,21 @@ pub struct OutputConfig {
     pub temp_dir: PathBuf,
     /// Duration to cache results before refreshing
     pub cache_duration: Duration,
+    /// Compression applied to generated artifacts
+    #[serde(default)]
+    pub compression: Compression,
+    /// Naming scheme, overwrite policy and de-duplication for generated artifacts
+    #[serde(default)]
+    pub naming: OutputNaming,
+    /// Limits on the age, count and total size of kept artifacts
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    /// Remote object storage artifacts are uploaded to
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// At-rest encryption of generated artifacts
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
 }
 
 impl Config {
@@ -85,27 +245,126 @@ impl Config {
                 r"node_modules/".to_string(),
                 r"\.env".to_string(),
             ],
+            truncation: TruncationConfig::default(),
+            server: ServerConfig::default(),
+            cache: CacheConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            proxy: ProxyConfig::default(),
+            tls: TlsConfig::default(),
+            ai_budget: AiBudget::default(),
+            metrics: MetricsConfig::default(),
+            logging: LoggingConfig::default(),
         }
     }
 
-    /// Loads configuration from the default config file location
+    /// Loads the defaults overridden by the config file, without the environment
     ///
     /// If the config file doesn't exist, returns the default configuration.
     /// The config file is expected to be in TOML format.
     pub fn load() -> Result<Self> {
+        Ok(ResolvedConfig::resolve_from(&Self::config_path()?, [])?.config)
+    }
+
+    /// Loads the effective configuration: the config file, then the environment, then `output_dir` if given
+    ///
+    /// See [`ResolvedConfig`] for the layers and where each setting came from.
+    pub fn load_effective(output_dir: Option<PathBuf>) -> Result<Self> {
+        let mut resolved = ResolvedConfig::resolve()?;
+        if let Some(output_dir) = output_dir {
+            resolved.set_from_flag("output_dir", output_dir, "--output")?;
+        }
+        Ok(resolved.config)
+    }
+
+    /// Returns the path of the config file read by [`Config::load`]
+    ///
+    /// `--config` or `LLAMA_CONFIG` replace the default location.
+    pub fn config_path() -> Result<PathBuf> {
+        if let Some(path) = resolve::config_path_override() {
+            return Ok(path);
+        }
         let config_dir = dirs::config_dir()
             .ok_or_else(|| ProcessorError::Config("Could not find config directory".into()))?;
-        let config_path = config_dir.join("llama-package-service").join("config.toml");
+        Ok(config_dir.join("llama-package-service").join("config.toml"))
+    }
+
+    /// Contents of a starter config file listing every setting at its default
+    ///
+    /// Tokens are left out; they are better supplied through the environment.
+    pub fn starter_toml() -> Result<String> {
+        let mut config = Self::default();
+        config.github_token = None;
+        config.api_keys = ApiKeys::default();
+        config.output_config.base_dir = PathBuf::from(".");
+        let body = toml::to_string_pretty(&config)
+            .map_err(|e| ProcessorError::Config(format!("Failed to render config: {}", e)))?;
+        Ok(format!(
+            "# llama-package-service configuration\n\
+             # Settings left out of this file keep their defaults. Prefer GITHUB_TOKEN and\n\
+             # LLAMA_OUTPUT_KEY in the environment over storing secrets here.\n\n{}",
+            body
+        ))
+    }
+
+    /// Looks up a setting by dotted key, such as `output_config.compression`
+    pub fn get(&self, key: &str) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        for part in key.split('.') {
+            value = value.get_mut(part)
+                .map(serde_json::Value::take)
+                .ok_or_else(|| ProcessorError::Config(format!("Unknown config key '{}'", key)))?;
+        }
+        Ok(value)
+    }
+
+    /// Sets one setting by dotted key in the config file at `path`, creating the file if needed
+    ///
+    /// `value` is read as a TOML value (`true`, `5`, `["a"]`) and otherwise
+    /// taken as a plain string. The file is only written if it still loads as
+    /// a valid configuration afterwards.
+    pub fn set_in_file(path: &Path, key: &str, value: &str) -> Result<()> {
+        Self::default().get(key)?;
+
+        let mut document: toml::Table = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| ProcessorError::Config(format!("Failed to parse config file: {}", e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let value = parse_setting(value);
 
-        if !config_path.exists() {
-            return Ok(Self::default());
+        let parts: Vec<&str> = key.split('.').collect();
+        let (leaf, parents) = parts.split_last().expect("split yields at least one part");
+        let mut table = &mut document;
+        for part in parents {
+            table = table.entry(part.to_string())
+                .or_insert(toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| ProcessorError::Config(format!("'{}' is not a table in the config file", part)))?;
         }
+        table.insert(leaf.to_string(), value);
 
-        let content = fs::read_to_string(&config_path)
-            .map_err(|e| ProcessorError::Message(format!("Failed to read config file: {}", e)))?;
+        let content = toml::to_string_pretty(&document)
+            .map_err(|e| ProcessorError::Config(format!("Failed to render config: {}", e)))?;
+        toml::from_str::<Config>(&content)
+            .map_err(|e| ProcessorError::Config(format!("Invalid value for '{}': {}", key, e)))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
 
-        toml::from_str(&content)
-            .map_err(|e| ProcessorError::Message(format!("Failed to parse config file: {}", e)))
+    /// Returns a copy with tokens and keys masked, safe to print or log
+    pub fn redacted(&self) -> Self {
+        let mask = |secret: &Option<String>| secret.as_ref().map(|_| "********".to_string());
+        let mut config = self.clone();
+        config.github_token = mask(&self.github_token);
+        config.api_keys.github_token = mask(&self.api_keys.github_token);
+        config.api_keys.pypi_token = mask(&self.api_keys.pypi_token);
+        config.output_config.encryption.key = mask(&self.output_config.encryption.key);
+        config.proxy = self.proxy.redacted();
+        config
     }
 
     /// Validates the configuration by ensuring necessary directories exist and API tokens are valid
@@ -176,6 +435,12 @@ impl Default for ProcessingConfig {
             max_concurrent_downloads: 5,
             max_concurrent_extractions: 3,
             max_concurrent_analyses: 2,
+            max_concurrent_jobs: 2,
+            max_concurrent_per_host: 2,
+            plugin_describe_timeout_secs: 10,
+            plugin_timeout_secs: 600,
+            check_links: false,
+            docs_min_score: 0,
         }
     }
 }
@@ -183,9 +448,42 @@ impl Default for ProcessingConfig {
 impl Default for RateLimits {
     fn default() -> Self {
         Self {
-            github_api: 5000,
-            pypi_api: 100,
-            npm_api: 100,
+            max_pause_secs: crate::rate_limiter::DEFAULT_MAX_PAUSE.as_secs(),
+            hosts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            key_requests_per_minute: 120,
+            key_burst: 30,
+            ip_requests_per_minute: 60,
+            ip_burst: 20,
+            workspace_quota: WorkspaceQuota::default(),
+            workspaces: HashMap::new(),
+            github_webhook: GitHubWebhookConfig::default(),
+            schedules: Vec::new(),
+            remote_workers: false,
+        }
+    }
+}
+
+impl Default for WorkspaceQuota {
+    fn default() -> Self {
+        Self {
+            jobs_per_day: 100,
+            storage_mb: 1024,
+        }
+    }
+}
+
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        Self {
+            readme_excerpt: TruncationLimit::new(500, Boundary::Heading),
+            description: TruncationLimit::new(20, Boundary::Sentence),
         }
     }
 }
@@ -196,10 +494,23 @@ impl Default for OutputConfig {
             base_dir: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
             temp_dir: std::env::temp_dir(),
             cache_duration: Duration::from_secs(3600), // 1 hour
+            compression: Compression::None,
+            naming: OutputNaming::default(),
+            retention: RetentionPolicy::default(),
+            storage: StorageConfig::default(),
+            encryption: EncryptionConfig::default(),
         }
     }
 }
 
+/// Reads a setting given as text: as a TOML value if it is one, otherwise as a plain string
+fn parse_setting(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self::new(PathBuf::from("output"))
@@ -220,6 +531,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_redacted_masks_secrets() {
+        let mut config = Config::default();
+        config.github_token = Some("ghp_secret".to_string());
+        config.output_config.encryption.key = Some("c2VjcmV0".to_string());
+
+        let redacted = config.redacted();
+        assert_eq!(redacted.github_token.as_deref(), Some("********"));
+        assert_eq!(redacted.output_config.encryption.key.as_deref(), Some("********"));
+        assert!(redacted.api_keys.pypi_token.is_none());
+        assert_eq!(redacted.output_dir, config.output_dir);
+    }
+
+    #[test]
+    fn test_set_in_file_and_get() -> Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("config.toml");
+
+        Config::set_in_file(&path, "output_config.compression", "zstd")?;
+        Config::set_in_file(&path, "processing.max_concurrent_downloads", "8")?;
+        let config: Config = toml::from_str(&fs::read_to_string(&path)?).unwrap();
+        assert_eq!(config.get("output_config.compression")?, "zstd");
+        assert_eq!(config.get("processing.max_concurrent_downloads")?, 8);
+        assert_eq!(config.rate_limits.max_pause_secs, RateLimits::default().max_pause_secs);
+
+        assert!(Config::set_in_file(&path, "output_config.colour", "red").is_err());
+        assert!(Config::set_in_file(&path, "processing.max_concurrent_downloads", "many").is_err());
+        assert!(config.get("no.such.key").is_err());
+
+        let starter: Config = toml::from_str(&Config::starter_toml()?).unwrap();
+        assert!(starter.github_token.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_github_token() -> Result<()> {
         let config = Config::default();
@@ -233,6 +578,15 @@ mod tests {
             output_config: OutputConfig::default(),
             api_keys: ApiKeys::default(),
             excluded_files: vec![],
+            truncation: TruncationConfig::default(),
+            server: ServerConfig::default(),
+            cache: CacheConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            proxy: ProxyConfig::default(),
+            tls: TlsConfig::default(),
+            ai_budget: AiBudget::default(),
+            metrics: MetricsConfig::default(),
+            logging: LoggingConfig::default(),
         };
         assert_eq!(config.github_token()?, "test_token");
         
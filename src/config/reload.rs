@@ -0,0 +1,179 @@
+//! Picking up edits to the config file while the server runs
+//!
+//! `serve` checks the config file every [`RELOAD_INTERVAL`]. When its content
+//! changed, the file is resolved again with the environment (see
+//! [`ResolvedConfig`]) and compared with the previous version setting by
+//! setting. Changes to the settings in [`RELOADABLE`] are applied to the
+//! running server, each with an entry in the `audit` log target. Other changes
+//! take effect after a restart. A file that no longer loads is reported and the
+//! running configuration kept.
+
+use super::resolve::{flatten, set_path};
+use super::{Config, ConfigSource, ResolvedConfig};
+use crate::error::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often the server checks the config file for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Log target of the entries recording applied changes
+pub const AUDIT_TARGET: &str = "audit";
+
+/// Settings, and sections of settings, a running server applies
+pub const RELOADABLE: &[&str] = &[
+    "github_token",
+    "api_keys",
+    "rate_limits",
+    "server.key_requests_per_minute",
+    "server.key_burst",
+    "server.ip_requests_per_minute",
+    "server.ip_burst",
+    "processing.max_concurrent_jobs",
+];
+
+/// Whether a running server applies changes to the setting at dotted `key`
+pub fn is_reloadable(key: &str) -> bool {
+    RELOADABLE.iter().any(|prefix| {
+        key.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// One setting that changed in the config file
+#[derive(Debug, Clone)]
+pub struct ConfigChange {
+    /// Dotted key of the setting
+    pub key: String,
+    /// Previous value, secrets masked; null when it was unset
+    pub old: Value,
+    /// New value, secrets masked; null when it was removed
+    pub new: Value,
+    /// Layer the new value comes from
+    pub source: ConfigSource,
+    /// New value as written
+    value: Value,
+}
+
+impl ConfigChange {
+    /// Whether a running server applies this change
+    pub fn is_reloadable(&self) -> bool {
+        is_reloadable(&self.key)
+    }
+}
+
+/// Applies the reloadable `changes` to `config`, leaving every other setting as it is
+pub fn apply(changes: &[ConfigChange], config: &mut Config) -> Result<()> {
+    let mut tree = serde_json::to_value(&*config)?;
+    for change in changes.iter().filter(|change| change.is_reloadable()) {
+        set_path(&mut tree, &change.key, change.value.clone())?;
+    }
+    *config = serde_json::from_value(tree)?;
+    Ok(())
+}
+
+/// Watches the config file for edits
+pub struct ConfigWatcher {
+    path: PathBuf,
+    content: Option<String>,
+    current: ResolvedConfig,
+}
+
+impl ConfigWatcher {
+    /// Watches the config file read by [`Config::load`], starting from its current content
+    pub fn new() -> Result<Self> {
+        Self::for_path(Config::config_path()?)
+    }
+
+    /// Watches the config file at `path`, starting from its current content
+    pub fn for_path(path: PathBuf) -> Result<Self> {
+        let content = fs::read_to_string(&path).ok();
+        let current = ResolvedConfig::resolve_from(&path, std::env::vars())?;
+        Ok(Self { path, content, current })
+    }
+
+    /// Path of the watched file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Settings that changed since the last check, if the file was edited
+    ///
+    /// A file that fails to load is reported again only once it is edited.
+    pub fn poll(&mut self) -> Result<Option<Vec<ConfigChange>>> {
+        let content = fs::read_to_string(&self.path).ok();
+        if content == self.content {
+            return Ok(None);
+        }
+        self.content = content;
+        let next = ResolvedConfig::resolve_from(&self.path, std::env::vars())?;
+        let changes = diff(&self.current, &next)?;
+        self.current = next;
+        Ok(Some(changes))
+    }
+}
+
+/// Settings whose values differ between `old` and `new`
+fn diff(old: &ResolvedConfig, new: &ResolvedConfig) -> Result<Vec<ConfigChange>> {
+    let leaves = |config: &Config| -> Result<BTreeMap<String, Value>> {
+        let mut leaves = Vec::new();
+        flatten(serde_json::to_value(config)?, String::new(), &mut leaves);
+        Ok(leaves.into_iter().collect())
+    };
+    let (before, after) = (leaves(&old.config)?, leaves(&new.config)?);
+    let (masked_before, masked_after) = (leaves(&old.config.redacted())?, leaves(&new.config.redacted())?);
+
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    Ok(keys.into_iter()
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| ConfigChange {
+            key: key.clone(),
+            old: masked_before.get(key).cloned().unwrap_or(Value::Null),
+            new: masked_after.get(key).cloned().unwrap_or(Value::Null),
+            source: new.source(key).clone(),
+            value: after.get(key).cloned().unwrap_or(Value::Null),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_are_reported_and_only_reloadable_ones_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "github_token = \"old-token\"\n[server]\nkey_requests_per_minute = 60\n").unwrap();
+        let mut watcher = ConfigWatcher::for_path(path.clone()).unwrap();
+        assert!(watcher.poll().unwrap().is_none());
+
+        fs::write(&path, concat!(
+            "github_token = \"new-token\"\noutput_dir = \"elsewhere\"\n",
+            "[server]\nkey_requests_per_minute = 120\n[processing]\nmax_concurrent_jobs = 4\n",
+        )).unwrap();
+        let changes = watcher.poll().unwrap().unwrap();
+        let keys: Vec<&str> = changes.iter().map(|change| change.key.as_str()).collect();
+        assert_eq!(keys, ["github_token", "output_dir", "processing.max_concurrent_jobs", "server.key_requests_per_minute"]);
+        // Secrets are masked in what gets logged
+        assert_eq!((&changes[0].old, &changes[0].new), (&Value::from("********"), &Value::from("********")));
+        assert_eq!(changes[3].source, ConfigSource::File(path.clone()));
+        assert!(!changes[1].is_reloadable());
+
+        let mut running = Config { output_dir: PathBuf::from("from-flag"), ..Config::default() };
+        apply(&changes, &mut running).unwrap();
+        assert_eq!(running.github_token.as_deref(), Some("new-token"));
+        assert_eq!(running.server.key_requests_per_minute, 120);
+        assert_eq!(running.processing.max_concurrent_jobs, 4);
+        assert_eq!(running.output_dir, PathBuf::from("from-flag"));
+
+        // A broken edit keeps the last good version as the baseline
+        fs::write(&path, "[server\n").unwrap();
+        assert!(watcher.poll().is_err());
+        assert!(watcher.poll().unwrap().is_none());
+    }
+}
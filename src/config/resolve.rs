@@ -0,0 +1,302 @@
+//! Layered resolution of the configuration
+//!
+//! Each setting takes its value from the last of these layers that sets it:
+//!
+//! 1. the built-in defaults
+//! 2. the config file: the one given with `--config`, else `LLAMA_CONFIG`,
+//!    else `config.toml` in the platform config directory
+//! 3. the environment: `LLAMA__<SECTION>__<KEY>` for any setting, such as
+//!    `LLAMA__PROCESSING__MAX_CONCURRENT_DOWNLOADS=8`, after the established
+//!    variables in [`ENV_ALIASES`]
+//! 4. command-line flags, such as `--output` and `--compress`
+//!
+//! Environment values are read as TOML (`true`, `8`, `["a", "b"]`) and
+//! otherwise taken as plain strings, like `config set`. A [`ResolvedConfig`]
+//! remembers which layer each setting came from, which `config show --sources`
+//! prints.
+
+use super::validate::{check_file, problems_error, ConfigProblem};
+use super::Config;
+use crate::error::{ProcessorError, Result};
+use crate::logging::LOG_FILE_ENV_VAR;
+use crate::metrics::PUSHGATEWAY_URL_ENV_VAR;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming the config file to read
+pub const CONFIG_ENV_VAR: &str = "LLAMA_CONFIG";
+
+/// Prefix of environment variables setting any setting, with `__` between its parts
+pub const ENV_PREFIX: &str = "LLAMA__";
+
+/// Established environment variables and the settings they set
+pub const ENV_ALIASES: &[(&str, &str)] = &[
+    ("GITHUB_TOKEN", "github_token"),
+    (LOG_FILE_ENV_VAR, "logging.file"),
+    (PUSHGATEWAY_URL_ENV_VAR, "metrics.pushgateway_url"),
+];
+
+static CONFIG_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// Reads the config file at `path` during this run instead of the default one
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+/// The config file given with [`set_config_path`] or `LLAMA_CONFIG`, if any
+pub(super) fn config_path_override() -> Option<PathBuf> {
+    CONFIG_PATH.get().cloned().or_else(|| {
+        std::env::var_os(CONFIG_ENV_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+/// Layer a setting's effective value came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "layer", content = "from", rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// The built-in default
+    Default,
+    /// The config file at this path
+    File(PathBuf),
+    /// This environment variable
+    Env(String),
+    /// This command-line flag
+    Flag(String),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Env(name) => write!(f, "env {}", name),
+            Self::Flag(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// The effective configuration and the layer each setting came from
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    /// The effective configuration
+    pub config: Config,
+    /// Source of every setting not left at its default, by dotted key
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl ResolvedConfig {
+    /// Resolves the defaults, the config file and the environment of this process
+    pub fn resolve() -> Result<Self> {
+        Self::resolve_from(&Config::config_path()?, std::env::vars())
+    }
+
+    /// Resolves the defaults, the config file at `path` if it exists, and `env`
+    ///
+    /// Fails listing every unknown setting and every value of the wrong type
+    /// in the file and the environment; see [`super::validate`].
+    pub fn resolve_from(path: &Path, env: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut resolved = Self { config: Config::default(), sources: BTreeMap::new() };
+        let mut value = serde_json::to_value(&resolved.config)?;
+        let mut problems = Vec::new();
+
+        if path.exists() {
+            let content = fs::read_to_string(path)
+                .map_err(|e| ProcessorError::Config(format!("Failed to read config file {}: {}", path.display(), e)))?;
+            let file: toml::Table = toml::from_str(&content)
+                .map_err(|e| ProcessorError::Config(format!("Failed to parse config file: {}", e)))?;
+            let file = serde_json::to_value(file)?;
+            let defaults = value.clone();
+            problems = check_file(&defaults, &file);
+            let source = ConfigSource::File(path.to_path_buf());
+            merge(&mut value, file, "", &mut |key| {
+                resolved.sources.insert(key, source.clone());
+            });
+            // Values of the wrong type go back to their defaults, so the environment can still be checked
+            for problem in &problems {
+                if let Some(default) = defaults.pointer(&format!("/{}", problem.key.replace('.', "/"))) {
+                    set_path(&mut value, &problem.key, default.clone())?;
+                }
+            }
+        }
+
+        // Aliases first, so the generic variables win; each group in name order
+        let mut settings: Vec<(usize, String, String, String)> = env.into_iter()
+            .filter(|(_, raw)| !raw.is_empty())
+            .filter_map(|(name, raw)| {
+                if let Some(path) = name.strip_prefix(ENV_PREFIX) {
+                    let key = path.split("__").map(str::to_lowercase).collect::<Vec<_>>().join(".");
+                    return Some((1, name, key, raw));
+                }
+                ENV_ALIASES.iter()
+                    .find(|(alias, _)| *alias == name)
+                    .map(|(_, key)| (0, name, key.to_string(), raw))
+            })
+            .collect();
+        settings.sort();
+        for (rank, name, key, raw) in settings {
+            if value.pointer(&format!("/{}", key.replace('.', "/"))).is_none() {
+                problems.push(ConfigProblem::new(name, format!("names no setting ('{}')", key)));
+                continue;
+            }
+            let parsed = serde_json::to_value(super::parse_setting(&raw))?;
+            let candidates = match rank {
+                // Aliases name string settings, so a numeric token stays a string
+                0 => vec![Value::String(raw)],
+                _ if parsed.is_string() => vec![parsed],
+                _ => vec![parsed, Value::String(raw)],
+            };
+            let accepted = candidates.into_iter().find_map(|candidate| {
+                let mut attempt = value.clone();
+                set_path(&mut attempt, &key, candidate).ok()?;
+                serde_json::from_value::<Config>(attempt.clone()).ok().map(|_| attempt)
+            });
+            match accepted {
+                Some(accepted) => value = accepted,
+                None => {
+                    problems.push(ConfigProblem::new(name, format!("is not a valid value for '{}'", key)));
+                    continue;
+                }
+            }
+            resolved.sources.insert(key, ConfigSource::Env(name));
+        }
+
+
+        resolved.config = serde_json::from_value(value)
+            .map_err(|e| ProcessorError::Config(format!("Invalid configuration: {}", e)))?;
+        if !problems.is_empty() {
+            // Everything else that is wrong, so all of it can be fixed at once
+            problems.extend(resolved.problems());
+            return Err(problems_error(&problems));
+        }
+        Ok(resolved)
+    }
+
+    /// Sets the setting at dotted `key` to `value`, given with the command-line `flag`
+    pub fn set_from_flag(&mut self, key: &str, value: impl Serialize, flag: &str) -> Result<()> {
+        let mut tree = serde_json::to_value(&self.config)?;
+        set_path(&mut tree, key, serde_json::to_value(value)?)?;
+        self.config = serde_json::from_value(tree)
+            .map_err(|e| ProcessorError::Config(format!("Invalid value for '{}' from {}: {}", key, flag, e)))?;
+        self.sources.insert(key.to_string(), ConfigSource::Flag(flag.to_string()));
+        Ok(())
+    }
+
+    /// Layer the setting at dotted `key` came from
+    ///
+    /// Settings inside a table set as a whole come from where the table was set.
+    pub fn source(&self, key: &str) -> &ConfigSource {
+        let mut key = key;
+        loop {
+            if let Some(source) = self.sources.get(key) {
+                return source;
+            }
+            match key.rsplit_once('.') {
+                Some((parent, _)) => key = parent,
+                None => return &ConfigSource::Default,
+            }
+        }
+    }
+
+    /// Every setting with a value, secrets masked, with its dotted key and source
+    pub fn annotated(&self) -> Result<Vec<(String, Value, ConfigSource)>> {
+        let mut leaves = Vec::new();
+        flatten(serde_json::to_value(self.config.redacted())?, String::new(), &mut leaves);
+        Ok(leaves.into_iter()
+            .map(|(key, value)| {
+                let source = self.source(&key).clone();
+                (key, value, source)
+            })
+            .collect())
+    }
+}
+
+/// Merges `overlay` into `target`, calling `record` with the dotted key of each value it sets
+fn merge(target: &mut Value, overlay: Value, prefix: &str, record: &mut impl FnMut(String)) {
+    let Value::Object(entries) = overlay else {
+        return;
+    };
+    for (name, value) in entries {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match (target.get_mut(&name), value) {
+            (Some(existing @ Value::Object(_)), value @ Value::Object(_)) => merge(existing, value, &key, record),
+            (_, value) => {
+                if let Value::Object(map) = target {
+                    map.insert(name, value);
+                }
+                record(key);
+            }
+        }
+    }
+}
+
+/// Replaces the existing setting at dotted `key` in `tree`
+pub(super) fn set_path(tree: &mut Value, key: &str, value: Value) -> Result<()> {
+    let slot = key.split('.')
+        .try_fold(tree, |node, part| node.get_mut(part))
+        .ok_or_else(|| ProcessorError::Config(format!("Unknown config key '{}'", key)))?;
+    *slot = value;
+    Ok(())
+}
+
+/// Collects the non-null values below `value` with their dotted keys
+pub(super) fn flatten(value: Value, key: String, leaves: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Null => {}
+        Value::Object(entries) => {
+            for (name, value) in entries {
+                let key = if key.is_empty() { name } else { format!("{}.{}", key, name) };
+                flatten(value, key, leaves);
+            }
+        }
+        value => leaves.push((key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_later_layers_win_and_are_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "output_dir = \"from-file\"\n[processing]\nmax_concurrent_downloads = 7\nmax_concurrent_jobs = 3\n").unwrap();
+        let env = [
+            ("LLAMA__PROCESSING__MAX_CONCURRENT_JOBS", "9"),
+            ("GITHUB_TOKEN", "12345"),
+            ("LLAMA__OUTPUT_CONFIG__COMPRESSION", "zstd"),
+            ("LLAMA_UNRELATED", "x"),
+        ].map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let mut resolved = ResolvedConfig::resolve_from(&path, env).unwrap();
+        resolved.set_from_flag("output_dir", "from-flag", "--output").unwrap();
+        let config = &resolved.config;
+        assert_eq!(config.output_dir, PathBuf::from("from-flag"));
+        assert_eq!(config.processing.max_concurrent_downloads, 7);
+        assert_eq!(config.processing.max_concurrent_jobs, 9);
+        assert_eq!(config.github_token.as_deref(), Some("12345"));
+        assert_eq!(config.output_config.compression, crate::compression::Compression::Zstd);
+
+        assert_eq!(*resolved.source("output_dir"), ConfigSource::Flag("--output".into()));
+        assert_eq!(*resolved.source("processing.max_concurrent_downloads"), ConfigSource::File(path.clone()));
+        assert_eq!(*resolved.source("processing.max_concurrent_jobs"), ConfigSource::Env("LLAMA__PROCESSING__MAX_CONCURRENT_JOBS".into()));
+        assert_eq!(*resolved.source("processing.max_concurrent_extractions"), ConfigSource::Default);
+        let annotated = resolved.annotated().unwrap();
+        let token = annotated.iter().find(|(key, _, _)| key == "github_token").unwrap();
+        assert_eq!((&token.1, token.2.to_string().as_str()), (&Value::from("********"), "env GITHUB_TOKEN"));
+
+        let typo = [("LLAMA__PROCESSING__MAX_DOWNLOADS".to_string(), "2".to_string())];
+        assert!(matches!(ResolvedConfig::resolve_from(&path, typo), Err(ProcessorError::Config(_))));
+        let invalid = [("LLAMA__PROCESSING__MAX_CONCURRENT_JOBS".to_string(), "many".to_string())];
+        assert!(matches!(ResolvedConfig::resolve_from(&path, invalid), Err(ProcessorError::Config(_))));
+    }
+}
@@ -0,0 +1,263 @@
+//! Checking the whole configuration before anything runs
+//!
+//! Every problem is collected with the dotted path of the setting it concerns,
+//! so a single run reports all of them instead of failing on the first one,
+//! or later, halfway through processing:
+//!
+//! - settings the config file names that do not exist, with the closest name that does
+//! - values of the wrong type, such as text where a number belongs
+//! - `excluded_files` patterns that are not regular expressions
+//! - output and temp directories that cannot be created or written to
+//! - tokens, keys, proxy URLs, certificates and log levels that cannot be what they claim to be
+//! - schedules whose cron expression, time zone or blackouts do not parse
+//!
+//! The first two make resolving the configuration fail (see
+//! [`ResolvedConfig::resolve`]), listing the others along with them; on their
+//! own the others are found by [`ResolvedConfig::check`], which commands run
+//! before they start.
+
+use super::{Config, ConfigSource, ResolvedConfig};
+use crate::error::{ProcessorError, Result};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+/// One problem with one setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// Dotted path of the setting, with `[n]` for list items
+    pub key: String,
+    /// What is wrong and how to fix it
+    pub message: String,
+}
+
+impl ConfigProblem {
+    /// A problem with the setting at `key`
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { key: key.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// A config error listing every one of `problems`, one per line
+pub fn problems_error(problems: &[ConfigProblem]) -> ProcessorError {
+    let count = match problems.len() {
+        1 => "1 problem".to_string(),
+        n => format!("{} problems", n),
+    };
+    let lines: Vec<String> = problems.iter().map(|problem| format!("  {}", problem)).collect();
+    ProcessorError::Config(format!("{} in the configuration:\n{}", count, lines.join("\n")))
+}
+
+/// Settings in `file` that do not exist or hold a value of the wrong type
+///
+/// `defaults` is the default configuration as JSON, which names every setting.
+pub(super) fn check_file(defaults: &Value, file: &Value) -> Vec<ConfigProblem> {
+    let mut problems = Vec::new();
+    check_entries(defaults, defaults, file, "", &mut problems);
+    problems
+}
+
+fn check_entries(root: &Value, defaults: &Value, file: &Value, prefix: &str, problems: &mut Vec<ConfigProblem>) {
+    let (Value::Object(known), Value::Object(entries)) = (defaults, file) else {
+        return;
+    };
+    for (name, value) in entries {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match known.get(name) {
+            None => {
+                let hint = closest(name, known.keys())
+                    .map_or_else(String::new, |suggestion| format!("; did you mean `{}`?", suggestion));
+                problems.push(ConfigProblem::new(&key, format!("unknown setting{}", hint)));
+            }
+            // Sections are checked setting by setting; maps and optional
+            // sections, which have nothing to compare against, as a whole
+            Some(section @ Value::Object(fields)) if !fields.is_empty() && value.is_object() => {
+                check_entries(root, section, value, &key, problems);
+            }
+            Some(_) => {
+                let mut attempt = root.clone();
+                if super::resolve::set_path(&mut attempt, &key, value.clone()).is_ok() {
+                    if let Err(e) = serde_json::from_value::<Config>(attempt) {
+                        problems.push(ConfigProblem::new(&key, e.to_string()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The name in `names` closest to `name`, if any is close enough to be a typo
+fn closest<'a>(name: &str, names: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+    names
+        .map(|candidate| (strsim::damerau_levenshtein(name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 4))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+impl Config {
+    /// Problems with the values of settings: patterns, directories, tokens, keys, proxies, certificates, schedules and log levels
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        for (index, pattern) in self.excluded_files.iter().enumerate() {
+            if let Err(e) = regex::Regex::new(pattern) {
+                let reason = e.to_string().lines().last().unwrap_or_default().trim().trim_start_matches("error: ").to_string();
+                problems.push(ConfigProblem::new(
+                    format!("excluded_files[{}]", index),
+                    format!("'{}' is not a valid regular expression ({})", pattern, reason),
+                ));
+            }
+        }
+        for (key, dir) in [("output_dir", &self.output_dir), ("output_config.temp_dir", &self.output_config.temp_dir)] {
+            if let Some(message) = check_dir(dir) {
+                problems.push(ConfigProblem::new(key, message));
+            }
+        }
+        for (key, token) in [("github_token", &self.github_token), ("api_keys.github_token", &self.api_keys.github_token)] {
+            if let Some(message) = token.as_deref().and_then(check_github_token) {
+                problems.push(ConfigProblem::new(key, message));
+            }
+        }
+        if let Some(token) = &self.api_keys.pypi_token {
+            if !token.starts_with("pypi-") || token.contains(char::is_whitespace) {
+                problems.push(ConfigProblem::new("api_keys.pypi_token", "is not a PyPI API token, which starts with `pypi-`"));
+            }
+        }
+        if let Err(e) = self.output_config.encryption.resolve_key() {
+            problems.push(ConfigProblem::new("output_config.encryption.key", e));
+        }
+        if let Err(ProcessorError::Config(message)) = self.proxy.proxy() {
+            problems.push(ConfigProblem::new("proxy.url", message));
+        }
+        if let Err(ProcessorError::Config(message)) = self.tls.certificates() {
+            problems.push(ConfigProblem::new("tls.ca_certificates", message));
+        }
+        for (index, schedule) in self.server.schedules.iter().enumerate() {
+            if let Err(ProcessorError::Config(message)) = crate::scheduling::Schedule::new(schedule) {
+                problems.push(ConfigProblem::new(format!("server.schedules[{}]", index), message));
+            }
+        }
+        if self.logging.level.parse::<tracing_subscriber::filter::LevelFilter>().is_err() {
+            problems.push(ConfigProblem::new(
+                "logging.level",
+                format!("unknown level '{}'; use error, warn, info, debug or trace", self.logging.level),
+            ));
+        }
+        problems
+    }
+}
+
+impl ResolvedConfig {
+    /// Every problem [`Config::problems`] finds, each naming where its value came from
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        self.config.problems().into_iter()
+            .map(|mut problem| {
+                let setting = problem.key.split('[').next().unwrap_or_default();
+                if let source @ (ConfigSource::File(_) | ConfigSource::Env(_) | ConfigSource::Flag(_)) = self.source(setting) {
+                    problem.message = format!("{} (set by {})", problem.message, source);
+                }
+                problem
+            })
+            .collect()
+    }
+
+    /// Fails listing every one of [`ResolvedConfig::problems`]
+    pub fn check(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems_error(&problems))
+        }
+    }
+}
+
+/// Why `dir` cannot be used as a directory to write to, if it cannot
+///
+/// Nothing is created: a missing directory only needs a parent it can be created in.
+fn check_dir(dir: &Path) -> Option<String> {
+    let existing = dir.ancestors()
+        .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+        .unwrap_or_else(|| Path::new("."));
+    let metadata = match std::fs::metadata(existing) {
+        Ok(metadata) => metadata,
+        Err(e) => return Some(format!("cannot access {}: {}", existing.display(), e)),
+    };
+    let blocked = if !metadata.is_dir() {
+        format!("{} is a file, not a directory", existing.display())
+    } else if metadata.permissions().readonly() {
+        format!("{} is read-only", existing.display())
+    } else {
+        return None;
+    };
+    Some(if existing == dir {
+        blocked
+    } else {
+        format!("{} cannot be created: {}", dir.display(), blocked)
+    })
+}
+
+/// Why `token` cannot be a GitHub token, if it cannot
+fn check_github_token(token: &str) -> Option<String> {
+    const PREFIXES: &[&str] = &["ghp_", "gho_", "ghu_", "ghs_", "ghr_", "github_pat_"];
+    if token.trim().is_empty() {
+        return Some("is empty; set a token or remove the setting".to_string());
+    }
+    if token.contains(char::is_whitespace) {
+        return Some("contains whitespace; copy the token again without spaces or line breaks".to_string());
+    }
+    let classic = token.len() == 40 && token.chars().all(|c| c.is_ascii_hexdigit());
+    let prefixed = PREFIXES.iter().any(|prefix| token.starts_with(prefix))
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (!classic && !prefixed).then(|| {
+        "is not a GitHub token, which starts with `ghp_` or `github_pat_` (or is 40 hex characters)".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_all_problems_are_reported_with_their_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, concat!(
+            "excluded_files = [\"\\\\.git/\", \"[unclosed\"]\n",
+            "[processing]\nmax_concurent_downloads = 4\nmax_concurrent_jobs = \"many\"\n",
+            "[output_config]\ncompression = \"rar\"\n",
+            "[rate_limits.hosts]\n\"example.com\" = { requests_per_minute = 10 }\n",
+        )).unwrap();
+
+        let error = ResolvedConfig::resolve_from(&path, []).unwrap_err().to_string();
+        assert!(error.contains("4 problems"), "{}", error);
+        assert!(error.contains("excluded_files[1]: '[unclosed' is not a valid regular expression"), "{}", error);
+        assert!(error.contains("processing.max_concurent_downloads: unknown setting; did you mean `max_concurrent_downloads`?"), "{}", error);
+        assert!(error.contains("processing.max_concurrent_jobs: invalid type: string \"many\""), "{}", error);
+        assert!(error.contains("output_config.compression: unknown variant `rar`"), "{}", error);
+
+        fs::write(&path, "excluded_files = [\"\\\\.git/\", \"[unclosed\"]\ngithub_token = \"ghp_abc def\"\n").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let env = [
+            ("LLAMA__OUTPUT_DIR".to_string(), file.join("out").display().to_string()),
+            ("LLAMA__LOGGING__LEVEL".to_string(), "loud".to_string()),
+        ];
+        let resolved = ResolvedConfig::resolve_from(&path, env).unwrap();
+        let keys: Vec<String> = resolved.config.problems().into_iter().map(|problem| problem.key).collect();
+        assert_eq!(keys, ["excluded_files[1]", "output_dir", "github_token", "logging.level"]);
+        let error = resolved.check().unwrap_err().to_string();
+        assert!(error.contains("is a file, not a directory (set by env LLAMA__OUTPUT_DIR)"), "{}", error);
+
+        assert!(check_github_token(&"z".repeat(40)).is_some());
+        assert!(check_github_token(&"0f".repeat(20)).is_none());
+        assert!(check_github_token("github_pat_11ABC_def").is_none());
+    }
+}
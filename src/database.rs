@@ -0,0 +1,58 @@
+//! SQLite connections shared by the persistent stores
+//!
+//! The job queue, the conversation store, the analysis history and the string
+//! cache each keep their own database file. [`Database`] opens one, applies its
+//! schema and hands out the connection to one thread at a time.
+
+use crate::error::Result;
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// SQLite connection shared between threads
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// Opens or creates the database at `path` and applies `schema`
+    pub fn open(path: &Path, schema: &str) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(schema)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Locks the connection, recovering it if a previous holder panicked
+    pub fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProcessorError;
+
+    #[test]
+    fn test_open_creates_the_directory_and_applies_the_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("store.sqlite");
+        let db = Database::open(&path, "CREATE TABLE IF NOT EXISTS items (name TEXT NOT NULL);").unwrap();
+        db.lock().execute("INSERT INTO items (name) VALUES ('a')", []).unwrap();
+        drop(db);
+
+        let db = Database::open(&path, "CREATE TABLE IF NOT EXISTS items (name TEXT NOT NULL);").unwrap();
+        let count: i64 = db.lock().query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_sqlite_errors_become_database_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = Database::open(&dir.path().join("store.sqlite"), "NOT SQL;");
+        assert!(matches!(result, Err(ProcessorError::Database(_))));
+    }
+}
@@ -0,0 +1,431 @@
+//! README quality audit
+//!
+//! Grades a project's README on four checks — installation clarity, presence
+//! of usage examples, badge freshness and link health — and turns the result
+//! into a "Documentation Health" report section with concrete suggestions.
+//! The report serializes to JSON and exposes [`DocsHealthReport::gate`] so it
+//! can feed a policy check in CI; `processing.docs_min_score` (or
+//! `--docs-min-score`) fails processing when a README scores below it.
+
+use crate::error::{ProcessorError, Result};
+use crate::link_checker::{LinkChecker, LinkStatus};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Points available per check; four checks make up a score out of 100
+const CHECK_WEIGHT: u8 = 25;
+
+/// Badge hosts that have shut down or stopped updating
+const STALE_BADGE_HOSTS: &[&str] = &[
+    "travis-ci.org",
+    "david-dm.org",
+    "gemnasium.com",
+    "dependencyci.com",
+    "bithound.io",
+    "codeship.com",
+    "img.shields.io/travis/",
+    "img.shields.io/david/",
+];
+
+const INSTALL_COMMANDS: &[&str] = &[
+    "cargo install", "cargo add", "pip install", "pipx install", "npm install", "npm i ",
+    "yarn add", "pnpm add", "go get", "go install", "brew install", "apt install",
+    "apt-get install", "docker run", "docker pull", "gem install", "composer require",
+];
+
+static LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[([^\]]*)\]\(\s*<?([^)\s>]*)>?[^)]*\)").unwrap());
+static HEADING_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?m)^#{1,6}\s+(.+?)\s*#*\s*$").unwrap());
+static STATIC_VERSION_BADGE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"img\.shields\.io/badge/version-v?([0-9][0-9A-Za-z.\-]*)-").unwrap());
+
+/// Result of one audit check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocsCheck {
+    /// Check name
+    pub name: String,
+    /// Points earned out of 25
+    pub score: u8,
+    /// What was found
+    pub detail: String,
+}
+
+/// Outcome of a README audit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocsHealthReport {
+    /// Overall score out of 100
+    pub score: u8,
+    /// How much of the audit could actually be verified (0.0 to 1.0)
+    pub confidence: f32,
+    /// Individual check results
+    pub checks: Vec<DocsCheck>,
+    /// Concrete improvements, most valuable first
+    pub suggestions: Vec<String>,
+    /// External links that were not verified over the network
+    pub unchecked_links: Vec<String>,
+}
+
+impl DocsHealthReport {
+    /// Fails with a validation error when the score is below `min_score`
+    pub fn gate(&self, min_score: u8) -> Result<()> {
+        if self.score >= min_score {
+            Ok(())
+        } else {
+            Err(ProcessorError::Validation(format!(
+                "Documentation health {} is below the required {}",
+                self.score, min_score
+            )))
+        }
+    }
+
+    /// Renders the report as a markdown section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Documentation Health\n\n");
+        out.push_str(&format!(
+            "**Score:** {}/100 (confidence {:.0}%)\n\n",
+            self.score,
+            self.confidence * 100.0
+        ));
+        out.push_str("| Check | Score | Details |\n|-------|-------|---------|\n");
+        for check in &self.checks {
+            out.push_str(&format!("| {} | {}/{} | {} |\n", check.name, check.score, CHECK_WEIGHT, check.detail));
+        }
+        out.push('\n');
+
+        if !self.suggestions.is_empty() {
+            out.push_str("### Suggested Improvements\n\n");
+            for suggestion in &self.suggestions {
+                out.push_str(&format!("- {}\n", suggestion));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Audits README content
+///
+/// `root` is the directory the README lives in; when given, relative links
+/// are resolved against it. `current_version` lets hard-coded version badges
+/// be compared with the actual release. External links are only checked for
+/// obvious problems here; use [`verify_external_links`] to probe them.
+pub fn audit_readme(content: &str, root: Option<&Path>, current_version: Option<&str>) -> DocsHealthReport {
+    let headings: Vec<String> = HEADING_RE.captures_iter(content)
+        .map(|c| c[1].to_lowercase())
+        .collect();
+    let code_blocks = fenced_code_blocks(content);
+    let mut suggestions = Vec::new();
+
+    let install = check_installation(&headings, &code_blocks, &mut suggestions);
+    let examples = check_examples(&headings, &code_blocks, &mut suggestions);
+    let badges = check_badges(content, current_version, &mut suggestions);
+    let (links, unchecked_links) = check_links(content, &headings, root, &mut suggestions);
+
+    let checks = vec![install, examples, badges, links];
+    let score = checks.iter().map(|c| c.score).sum();
+
+    let mut report = DocsHealthReport {
+        score,
+        confidence: 0.0,
+        checks,
+        suggestions,
+        unchecked_links,
+    };
+    report.confidence = confidence(&report, content);
+    report
+}
+
+/// Fails with a validation error when a repository's README scores below `min_score`
+///
+/// A repository without a README fails any gate; a `min_score` of 0 lets everything pass.
+pub fn require_score(report: Option<&DocsHealthReport>, min_score: u8) -> Result<()> {
+    match report {
+        _ if min_score == 0 => Ok(()),
+        Some(report) => report.gate(min_score),
+        None => Err(ProcessorError::Validation(format!(
+            "No README to grade; documentation health 0 is below the required {}",
+            min_score
+        ))),
+    }
+}
+
+/// Version the project in `root` declares in its Cargo.toml, package.json or pyproject.toml
+pub fn declared_version(root: &Path) -> Option<String> {
+    let read = |name: &str| std::fs::read_to_string(root.join(name)).ok();
+    let from_toml = |name: &str, tables: &[&str]| {
+        let manifest: toml::Value = read(name)?.parse().ok()?;
+        tables.iter().find_map(|table| {
+            let mut value = &manifest;
+            for key in table.split('.') {
+                value = value.get(key)?;
+            }
+            value.get("version")?.as_str().map(str::to_string)
+        })
+    };
+    from_toml("Cargo.toml", &["package"])
+        .or_else(|| {
+            let manifest: serde_json::Value = serde_json::from_str(&read("package.json")?).ok()?;
+            manifest["version"].as_str().map(str::to_string)
+        })
+        .or_else(|| from_toml("pyproject.toml", &["project", "tool.poetry"]))
+}
+
+/// Probes the report's unchecked external links and folds the result back in
+///
+/// Only dead links count as broken; redirected and moved ones still resolve.
+pub async fn verify_external_links(report: &mut DocsHealthReport, links: &LinkChecker) {
+    let mut broken = Vec::new();
+    for url in std::mem::take(&mut report.unchecked_links) {
+        if matches!(links.check(&url).await, LinkStatus::Dead { .. }) {
+            broken.push(url);
+        }
+    }
+
+    if let Some(check) = report.checks.iter_mut().find(|c| c.name == "Links") {
+        if !broken.is_empty() {
+            check.score = check.score.saturating_sub((broken.len().min(CHECK_WEIGHT as usize) as u8).saturating_mul(5));
+            check.detail.push_str(&format!(", {} unreachable", broken.len()));
+        }
+    }
+    for url in &broken {
+        report.suggestions.push(format!("Fix or remove the unreachable link {}", url));
+    }
+    report.score = report.checks.iter().map(|c| c.score).sum();
+    report.confidence = report.confidence.max(0.95);
+}
+
+fn fenced_code_blocks(content: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<String> = None;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(block) => blocks.push(block),
+                None => current = Some(String::new()),
+            }
+        } else if let Some(block) = current.as_mut() {
+            block.push_str(line);
+            block.push('\n');
+        }
+    }
+    blocks
+}
+
+fn check_installation(headings: &[String], code_blocks: &[String], suggestions: &mut Vec<String>) -> DocsCheck {
+    let has_heading = headings.iter().any(|h| {
+        h.contains("install") || h.contains("getting started") || h.contains("setup") || h.contains("set up")
+    });
+    let has_command = code_blocks.iter().any(|b| INSTALL_COMMANDS.iter().any(|cmd| b.contains(cmd)));
+
+    let (score, detail) = match (has_heading, has_command) {
+        (true, true) => (CHECK_WEIGHT, "Installation section with copy-pasteable commands"),
+        (false, true) => (15, "Install commands present but no installation heading"),
+        (true, false) => (12, "Installation section without a command to run"),
+        (false, false) => (0, "No installation instructions"),
+    };
+    if !has_heading {
+        suggestions.push("Add an \"Installation\" section so newcomers can find setup steps quickly".into());
+    }
+    if !has_command {
+        suggestions.push("Include the exact install command (e.g. `cargo install`, `pip install`) in a code block".into());
+    }
+    DocsCheck { name: "Installation".into(), score, detail: detail.into() }
+}
+
+fn check_examples(headings: &[String], code_blocks: &[String], suggestions: &mut Vec<String>) -> DocsCheck {
+    let has_heading = headings.iter().any(|h| {
+        h.contains("usage") || h.contains("example") || h.contains("quick start") || h.contains("quickstart")
+    });
+    // Install one-liners are not usage examples
+    let examples = code_blocks.iter()
+        .filter(|b| !b.lines().all(|l| l.trim().is_empty() || INSTALL_COMMANDS.iter().any(|cmd| l.contains(cmd))))
+        .count();
+
+    let score = match (has_heading, examples) {
+        (true, n) if n >= 2 => CHECK_WEIGHT,
+        (true, 1) => 18,
+        (false, n) if n >= 1 => 10,
+        (true, _) => 5,
+        _ => 0,
+    };
+    if examples == 0 {
+        suggestions.push("Add at least one runnable usage example in a fenced code block".into());
+    } else if !has_heading {
+        suggestions.push("Group examples under a \"Usage\" or \"Examples\" heading".into());
+    } else if examples == 1 {
+        suggestions.push("Show a second example covering a common non-trivial use case".into());
+    }
+    DocsCheck {
+        name: "Examples".into(),
+        score,
+        detail: format!("{} example block(s){}", examples, if has_heading { " under a usage heading" } else { "" }),
+    }
+}
+
+fn check_badges(content: &str, current_version: Option<&str>, suggestions: &mut Vec<String>) -> DocsCheck {
+    let badges: Vec<&str> = LINK_RE.captures_iter(content)
+        .filter_map(|c| c.get(2).map(|m| m.as_str()))
+        .filter(|url| url.contains("shields.io") || url.contains("badge") || url.contains(".svg"))
+        .collect();
+
+    if badges.is_empty() {
+        return DocsCheck { name: "Badges".into(), score: CHECK_WEIGHT, detail: "No badges (nothing to go stale)".into() };
+    }
+
+    let mut stale = 0u8;
+    for badge in &badges {
+        if let Some(host) = STALE_BADGE_HOSTS.iter().find(|host| badge.contains(*host)) {
+            stale += 1;
+            suggestions.push(format!("Replace the badge from {} — the service no longer updates", host));
+        } else if let (Some(current), Some(caps)) = (current_version, STATIC_VERSION_BADGE_RE.captures(badge)) {
+            if &caps[1] != current.trim_start_matches('v') {
+                stale += 1;
+                suggestions.push(format!(
+                    "The version badge says {} but the latest release is {}; use a dynamic badge",
+                    &caps[1], current
+                ));
+            }
+        }
+    }
+
+    DocsCheck {
+        name: "Badges".into(),
+        score: CHECK_WEIGHT.saturating_sub(stale.saturating_mul(8)),
+        detail: format!("{} badge(s), {} stale", badges.len(), stale),
+    }
+}
+
+fn check_links(
+    content: &str,
+    headings: &[String],
+    root: Option<&Path>,
+    suggestions: &mut Vec<String>,
+) -> (DocsCheck, Vec<String>) {
+    let mut total = 0usize;
+    let mut broken = 0usize;
+    let mut unchecked = Vec::new();
+
+    for caps in LINK_RE.captures_iter(content) {
+        let url = caps[2].trim();
+        total += 1;
+
+        let problem = if url.is_empty() {
+            Some("empty link target".to_string())
+        } else if let Some(anchor) = url.strip_prefix('#') {
+            let exists = headings.iter().any(|h| slugify(h) == anchor.to_lowercase());
+            (!exists).then(|| format!("anchor #{} has no matching heading", anchor))
+        } else if url.starts_with("http://localhost") || url.contains("example.com") {
+            Some(format!("placeholder link {}", url))
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            unchecked.push(url.to_string());
+            None
+        } else if url.starts_with("mailto:") {
+            None
+        } else if let Some(root) = root {
+            let path = url.split('#').next().unwrap_or(url);
+            (!root.join(path).exists()).then(|| format!("relative link {} points to a missing file", url))
+        } else {
+            None
+        };
+
+        if let Some(problem) = problem {
+            broken += 1;
+            suggestions.push(format!("Fix {}", problem));
+        }
+    }
+
+    let score = if total == 0 {
+        CHECK_WEIGHT
+    } else {
+        (CHECK_WEIGHT as usize * (total - broken) / total) as u8
+    };
+    let detail = format!("{} link(s), {} broken, {} external unchecked", total, broken, unchecked.len());
+    (DocsCheck { name: "Links".into(), score, detail }, unchecked)
+}
+
+/// GitHub-style heading anchor
+fn slugify(heading: &str) -> String {
+    heading.chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() => Some(c.to_ascii_lowercase()),
+            ' ' | '-' => Some('-'),
+            '_' => Some('_'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Lower confidence when parts of the audit are unverified or the README is too thin to judge
+fn confidence(report: &DocsHealthReport, content: &str) -> f32 {
+    let mut confidence: f32 = 1.0;
+    let link_count = LINK_RE.captures_iter(content).count().max(1);
+    confidence -= 0.3 * report.unchecked_links.len() as f32 / link_count as f32;
+    if content.lines().filter(|l| !l.trim().is_empty()).count() < 10 {
+        confidence -= 0.2;
+    }
+    confidence.clamp(0.3, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const GOOD_README: &str = "# Tool\n\n[Docs](docs/guide.md) · [Usage](#usage)\n\n## Installation\n\n```sh\ncargo install tool\n```\n\n## Usage\n\n```rust\ntool::run();\n```\n\n```sh\ntool --help\n```\n";
+
+    #[test]
+    fn test_complete_readme_scores_high() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/guide.md"), "guide").unwrap();
+
+        let report = audit_readme(GOOD_README, Some(dir.path()), None);
+        assert_eq!(report.score, 100, "{:#?}", report);
+        assert!(report.gate(80).is_ok());
+    }
+
+    #[test]
+    fn test_bare_readme_gets_suggestions() {
+        let report = audit_readme("# Tool\n\nA tool.\n", None, None);
+        assert!(report.score <= 50);
+        assert!(report.confidence < 1.0);
+        assert!(report.suggestions.iter().any(|s| s.contains("Installation")));
+        assert!(report.suggestions.iter().any(|s| s.contains("usage example")));
+        assert!(matches!(report.gate(60), Err(ProcessorError::Validation(_))));
+    }
+
+    #[test]
+    fn test_flags_stale_badges_and_broken_links() {
+        let readme = "# Tool\n\n[![Build](https://travis-ci.org/a/b.svg)](https://travis-ci.org/a/b)\n\
+            [![Version](https://img.shields.io/badge/version-1.0.0-blue.svg)](https://crates.io/crates/tool)\n\n\
+            See [the guide](docs/missing.md) and [setup](#nowhere).\n";
+        let dir = tempdir().unwrap();
+        let report = audit_readme(readme, Some(dir.path()), Some("2.1.0"));
+
+        let badges = report.checks.iter().find(|c| c.name == "Badges").unwrap();
+        assert!(badges.detail.contains("2 stale"), "{}", badges.detail);
+        let links = report.checks.iter().find(|c| c.name == "Links").unwrap();
+        assert!(links.score < CHECK_WEIGHT);
+        assert!(report.suggestions.iter().any(|s| s.contains("docs/missing.md")));
+        assert!(report.suggestions.iter().any(|s| s.contains("#nowhere")));
+    }
+
+    #[test]
+    fn test_require_score() {
+        let bare = audit_readme("# Tool\n\nA tool.\n", None, None);
+        assert!(matches!(require_score(Some(&bare), 60), Err(ProcessorError::Validation(_))));
+        assert!(matches!(require_score(None, 1), Err(ProcessorError::Validation(_))));
+        assert!(require_score(None, 0).is_ok());
+    }
+
+    #[test]
+    fn test_declared_version() {
+        let dir = tempdir().unwrap();
+        assert_eq!(declared_version(dir.path()), None);
+        std::fs::write(dir.path().join("pyproject.toml"), "[tool.poetry]\nname = \"tool\"\nversion = \"0.3.1\"\n").unwrap();
+        assert_eq!(declared_version(dir.path()).as_deref(), Some("0.3.1"));
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"tool\"\nversion = \"1.2.0\"\n").unwrap();
+        assert_eq!(declared_version(dir.path()).as_deref(), Some("1.2.0"));
+    }
+}
@@ -0,0 +1,370 @@
+//! Chunk embeddings and their export for downstream ML pipelines
+//!
+//! Every embedded chunk carries enough metadata (repository, version, file
+//! and byte span) to trace a vector back to the source it came from. Exports
+//! are written in formats that load without this tool: a NumPy `.npy` matrix
+//! with a row-aligned `metadata.jsonl`, or a single self-describing JSONL file.
+//!
+//! Processed content is split into chunks attributed to the source file they
+//! came from, embedded by an [`Embedder`], and kept in a [`VectorIndex`] that
+//! finds the chunks closest to a question. A repository has at most a few
+//! thousand chunks, so the index compares against every one of them, which
+//! is exact and still takes well under a millisecond.
+
+use crate::chunking::{self, Boundary};
+use crate::error::{ProcessorError, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File name of the embedding matrix in an `npy` export
+pub const NPY_FILE_NAME: &str = "embeddings.npy";
+/// File name of the row-aligned metadata in an `npy` export
+pub const METADATA_FILE_NAME: &str = "metadata.jsonl";
+/// File name of a `jsonl` export
+pub const JSONL_FILE_NAME: &str = "embeddings.jsonl";
+
+/// Tokens per chunk when content is split for embedding
+pub const CHUNK_TOKENS: usize = 400;
+
+/// Where an embedded chunk came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkMetadata {
+    /// Repository or package identifier
+    pub repo: String,
+    /// Version or ref the content was taken from
+    pub version: Option<String>,
+    /// Path of the source file, relative to the repository root
+    pub file: String,
+    /// Byte offset where the chunk starts
+    pub start: usize,
+    /// Byte offset where the chunk ends (exclusive)
+    pub end: usize,
+}
+
+/// A chunk of repository content and its embedding vector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddedChunk {
+    /// Source of the chunk
+    #[serde(flatten)]
+    pub metadata: ChunkMetadata,
+    /// Embedding vector
+    pub vector: Vec<f32>,
+}
+
+/// A chunk of processed content, before it is embedded
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentChunk {
+    /// Source of the chunk
+    pub metadata: ChunkMetadata,
+    /// The content
+    pub text: String,
+}
+
+/// Splits the processed artifact `text` into chunks of at most `max_tokens`
+///
+/// Chunks are cut at headings where possible. Each is attributed to the
+/// source file whose section (a ``### `path` `` heading) it starts in, or to
+/// `artifact` before the first one; offsets are bytes into `text`.
+pub fn chunk_content(repo: &str, artifact: &str, text: &str, max_tokens: usize) -> Vec<ContentChunk> {
+    let mut sections = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let heading = line.trim_end().strip_prefix("### `").and_then(|rest| rest.strip_suffix('`'));
+        if let Some(path) = heading {
+            sections.push((offset, path.to_string()));
+        }
+        offset += line.len();
+    }
+
+    let mut start = 0;
+    chunking::chunk(text, max_tokens, Boundary::Heading)
+        .into_iter()
+        .map(|piece| {
+            let end = start + piece.len();
+            let file = sections.iter()
+                .take_while(|(offset, _)| *offset <= start)
+                .last()
+                .map_or(artifact, |(_, path)| path.as_str());
+            let chunk = ContentChunk {
+                metadata: ChunkMetadata { repo: repo.to_string(), version: None, file: file.to_string(), start, end },
+                text: piece,
+            };
+            start = end;
+            chunk
+        })
+        .filter(|chunk| !chunk.text.trim().is_empty())
+        .collect()
+}
+
+/// Turns text into vectors
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Model the vectors come from; vectors of different models do not compare
+    fn embedding_model(&self) -> &str;
+
+    /// One vector for each of `texts`, in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// A chunk found for a query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Retrieved {
+    /// Source of the chunk
+    #[serde(flatten)]
+    pub metadata: ChunkMetadata,
+    /// The content
+    pub text: String,
+    /// Cosine similarity to the query, higher is closer
+    pub score: f32,
+}
+
+/// Embedded chunks of a repository, searched by cosine similarity
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorIndex {
+    /// Model the vectors come from
+    pub model: String,
+    chunks: Vec<EmbeddedChunk>,
+    texts: Vec<String>,
+}
+
+impl VectorIndex {
+    /// Embeds `chunks` with `embedder`, `batch_size` at a time
+    pub async fn build(embedder: &dyn Embedder, chunks: Vec<ContentChunk>, batch_size: usize) -> Result<Self> {
+        let mut index = Self { model: embedder.embedding_model().to_string(), ..Self::default() };
+        for batch in chunks.chunks(batch_size.max(1)) {
+            let texts: Vec<String> = batch.iter().map(|chunk| chunk.text.clone()).collect();
+            let vectors = embedder.embed(&texts).await?;
+            if vectors.len() != batch.len() {
+                return Err(ProcessorError::LLM(format!(
+                    "asked for {} embeddings and got {}", batch.len(), vectors.len()
+                )));
+            }
+            for (chunk, vector) in batch.iter().zip(vectors) {
+                index.chunks.push(EmbeddedChunk { metadata: chunk.metadata.clone(), vector });
+                index.texts.push(chunk.text.clone());
+            }
+        }
+        Ok(index)
+    }
+
+    /// Number of chunks in the index
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Whether the index has no chunks
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The embedded chunks, e.g. for [`export`]
+    pub fn chunks(&self) -> &[EmbeddedChunk] {
+        &self.chunks
+    }
+
+    /// The `limit` chunks closest to `query`, closest first
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<Retrieved> {
+        let mut scored: Vec<(usize, f32)> = self.chunks.iter()
+            .enumerate()
+            .map(|(i, chunk)| (i, cosine_similarity(query, &chunk.vector)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter()
+            .take(limit)
+            .map(|(i, score)| Retrieved {
+                metadata: self.chunks[i].metadata.clone(),
+                text: self.texts[i].clone(),
+                score,
+            })
+            .collect()
+    }
+}
+
+/// Cosine of the angle between `a` and `b`, 0 when either is all zeros or their dimensions differ
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Format of an embedding export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// `embeddings.npy` (float32, one row per chunk) plus `metadata.jsonl`
+    #[default]
+    Npy,
+    /// `embeddings.jsonl` with metadata and vector on every line
+    Jsonl,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "npy" | "numpy" => Ok(ExportFormat::Npy),
+            "jsonl" | "ndjson" => Ok(ExportFormat::Jsonl),
+            other => Err(format!("unknown embedding export format '{}' (expected npy or jsonl)", other)),
+        }
+    }
+}
+
+/// Writes `chunks` to `dir` in the given format, returning the files written
+///
+/// All vectors must have the same dimension.
+pub fn export(chunks: &[EmbeddedChunk], dir: &Path, format: ExportFormat) -> Result<Vec<PathBuf>> {
+    let dimension = chunks.first().map_or(0, |c| c.vector.len());
+    if let Some(bad) = chunks.iter().find(|c| c.vector.len() != dimension) {
+        return Err(ProcessorError::Validation(format!(
+            "embedding for {}:{}..{} has dimension {}, expected {}",
+            bad.metadata.file, bad.metadata.start, bad.metadata.end, bad.vector.len(), dimension
+        )));
+    }
+    fs::create_dir_all(dir)?;
+
+    match format {
+        ExportFormat::Npy => {
+            let matrix_path = dir.join(NPY_FILE_NAME);
+            let mut matrix = BufWriter::new(File::create(&matrix_path)?);
+            write_npy_header(&mut matrix, chunks.len(), dimension)?;
+            for value in chunks.iter().flat_map(|c| &c.vector) {
+                matrix.write_all(&value.to_le_bytes())?;
+            }
+            matrix.flush()?;
+
+            let metadata_path = dir.join(METADATA_FILE_NAME);
+            let mut metadata = BufWriter::new(File::create(&metadata_path)?);
+            for chunk in chunks {
+                serde_json::to_writer(&mut metadata, &chunk.metadata)?;
+                metadata.write_all(b"\n")?;
+            }
+            metadata.flush()?;
+
+            Ok(vec![matrix_path, metadata_path])
+        }
+        ExportFormat::Jsonl => {
+            let path = dir.join(JSONL_FILE_NAME);
+            let mut out = BufWriter::new(File::create(&path)?);
+            for chunk in chunks {
+                serde_json::to_writer(&mut out, chunk)?;
+                out.write_all(b"\n")?;
+            }
+            out.flush()?;
+            Ok(vec![path])
+        }
+    }
+}
+
+/// Writes a version 1.0 `.npy` header for a little-endian float32 matrix
+fn write_npy_header(out: &mut impl Write, rows: usize, cols: usize) -> std::io::Result<()> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, cols
+    );
+    // Magic + 2-byte length + header (terminated by a newline) must be a multiple of 64 bytes
+    let unpadded = MAGIC.len() + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    out.write_all(MAGIC)?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn chunk(file: &str, vector: Vec<f32>) -> EmbeddedChunk {
+        EmbeddedChunk {
+            metadata: ChunkMetadata {
+                repo: "owner/repo".into(),
+                version: Some("v1.0.0".into()),
+                file: file.into(),
+                start: 0,
+                end: 10,
+            },
+            vector,
+        }
+    }
+
+    #[test]
+    fn test_npy_export_layout() {
+        let dir = tempdir().unwrap();
+        let chunks = vec![chunk("a.rs", vec![1.0, 2.0, 3.0]), chunk("b.rs", vec![4.0, 5.0, 6.0])];
+        let files = export(&chunks, dir.path(), ExportFormat::Npy).unwrap();
+        assert_eq!(files.len(), 2);
+
+        let bytes = fs::read(dir.path().join(NPY_FILE_NAME)).unwrap();
+        assert_eq!(&bytes[..6], b"\x93NUMPY");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.contains("'shape': (2, 3)"));
+        assert_eq!(bytes.len(), 10 + header_len + 6 * 4);
+        let last = &bytes[bytes.len() - 4..];
+        assert_eq!(f32::from_le_bytes(last.try_into().unwrap()), 6.0);
+
+        let metadata = fs::read_to_string(dir.path().join(METADATA_FILE_NAME)).unwrap();
+        let rows: Vec<ChunkMetadata> = metadata.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(rows[1].file, "b.rs");
+    }
+
+    #[test]
+    fn test_jsonl_round_trip_and_dimension_check() {
+        let dir = tempdir().unwrap();
+        let chunks = vec![chunk("a.rs", vec![0.5, 0.25])];
+        export(&chunks, dir.path(), ExportFormat::Jsonl).unwrap();
+        let line = fs::read_to_string(dir.path().join(JSONL_FILE_NAME)).unwrap();
+        let parsed: EmbeddedChunk = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(parsed, chunks[0]);
+
+        let mixed = vec![chunk("a.rs", vec![1.0]), chunk("b.rs", vec![1.0, 2.0])];
+        assert!(export(&mixed, dir.path(), ExportFormat::Npy).is_err());
+    }
+
+    /// Counts how often each of a few words appears
+    struct WordCounts;
+
+    #[async_trait]
+    impl Embedder for WordCounts {
+        fn embedding_model(&self) -> &str {
+            "word-counts"
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter()
+                .map(|text| ["parse", "render", "network"].iter().map(|word| text.matches(word).count() as f32).collect())
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunks_are_attributed_and_retrieved_by_similarity() {
+        let artifact = "# Analysis\n\nOverview.\n\n### `src/parser.rs`\n\nfn parse() { parse_all() }\n\n\
+                        ### `src/render.rs`\n\nfn render() { render_page() }\n";
+        let chunks = chunk_content("owner/repo", "analysis.txt", artifact, 16);
+        let files: Vec<&str> = chunks.iter().map(|c| c.metadata.file.as_str()).collect();
+        assert_eq!(files, ["analysis.txt", "src/parser.rs", "src/render.rs"]);
+        for chunk in &chunks {
+            assert_eq!(&artifact[chunk.metadata.start..chunk.metadata.end], chunk.text);
+        }
+
+        let index = VectorIndex::build(&WordCounts, chunks, 2).await.unwrap();
+        assert_eq!((index.len(), index.model.as_str()), (3, "word-counts"));
+        let query = WordCounts.embed(&["how does render work".to_string()]).await.unwrap().remove(0);
+        let found = index.search(&query, 2);
+        assert_eq!(found[0].metadata.file, "src/render.rs");
+        assert!(found[0].score > 0.99 && found[1].score == 0.0, "{:?}", found);
+    }
+}
@@ -0,0 +1,169 @@
+//! At-rest encryption of output artifacts
+//!
+//! When a key is configured, artifacts are encrypted with AES-256-GCM after
+//! compression and get a `.enc` suffix (`dump.txt.zst.enc`). The file holds a
+//! short magic header, the random 96-bit nonce and the ciphertext with its
+//! authentication tag. [`crate::compression::read_artifact`] and
+//! [`crate::compression::open_artifact`] decrypt transparently when the key is
+//! available.
+//!
+//! The key is 32 bytes, given as base64 or hex in `LLAMA_OUTPUT_KEY` or in
+//! `output_config.encryption.key`; `openssl rand -base64 32` makes a new one.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File extension appended to encrypted artifacts
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Environment variable holding the artifact key
+pub const KEY_ENV_VAR: &str = "LLAMA_OUTPUT_KEY";
+
+/// Header identifying the file format and its version
+const MAGIC: &[u8; 8] = b"LPSENC01";
+const NONCE_LEN: usize = 12;
+
+static OUTPUT_KEY: OnceCell<Option<EncryptionKey>> = OnceCell::new();
+
+/// Encryption settings for output artifacts; encryption is on whenever a key is set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// Key as base64 or hex; `LLAMA_OUTPUT_KEY` takes precedence
+    pub key: Option<String>,
+}
+
+impl EncryptionConfig {
+    /// Resolves the key to use, preferring the environment over the config file
+    pub fn resolve_key(&self) -> Result<Option<EncryptionKey>, String> {
+        match std::env::var(KEY_ENV_VAR).ok().or_else(|| self.key.clone()) {
+            Some(key) => key.parse().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// 256-bit artifact encryption key
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl FromStr for EncryptionKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let bytes = if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+            hex::decode(s).map_err(|e| e.to_string())?
+        } else {
+            BASE64.decode(s).map_err(|_| "encryption key must be 32 bytes as base64 or hex".to_string())?
+        };
+        let key: [u8; 32] = bytes.try_into()
+            .map_err(|b: Vec<u8>| format!("encryption key must be 32 bytes, got {}", b.len()))?;
+        Ok(Self(key))
+    }
+}
+
+/// Sets the key artifacts are encrypted and decrypted with during this run; `None` disables encryption
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_output_encryption(key: Option<EncryptionKey>) {
+    let _ = OUTPUT_KEY.set(key);
+}
+
+/// Returns the key artifacts are encrypted with, if encryption is enabled
+pub fn output_encryption() -> Option<&'static EncryptionKey> {
+    OUTPUT_KEY.get().and_then(Option::as_ref)
+}
+
+/// Whether a path names an encrypted artifact
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == ENCRYPTED_EXTENSION)
+}
+
+/// Path of the encrypted file for `path`
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// Encrypts `data` with a fresh random nonce
+pub fn encrypt(data: &[u8], key: &EncryptionKey) -> io::Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "artifact encryption failed"))?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypts data produced by [`encrypt`], failing if it was tampered with or the key is wrong
+pub fn decrypt(data: &[u8], key: &EncryptionKey) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let rest = data.strip_prefix(MAGIC.as_slice()).ok_or_else(|| invalid("not an encrypted artifact"))?;
+    if rest.len() < NONCE_LEN {
+        return Err(invalid("encrypted artifact is truncated"));
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid("artifact decryption failed (wrong key or corrupted file)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> EncryptionKey {
+        EncryptionKey([7u8; 32])
+    }
+
+    #[test]
+    fn test_round_trip_and_tamper_detection() {
+        let sealed = encrypt(b"private source", &key()).unwrap();
+        assert!(sealed.starts_with(MAGIC));
+        assert!(!sealed.windows(7).any(|w| w == b"private"));
+        assert_eq!(decrypt(&sealed, &key()).unwrap(), b"private source");
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&tampered, &key()).is_err());
+        assert!(decrypt(&sealed, &EncryptionKey([8u8; 32])).is_err());
+    }
+
+    #[test]
+    fn test_key_parsing() {
+        let hex_key = "00".repeat(32);
+        assert!(hex_key.parse::<EncryptionKey>().is_ok());
+        assert!(BASE64.encode([1u8; 32]).parse::<EncryptionKey>().is_ok());
+        assert!(BASE64.encode([1u8; 16]).parse::<EncryptionKey>().is_err());
+        assert!("not a key".parse::<EncryptionKey>().is_err());
+    }
+
+    #[test]
+    fn test_encrypted_path() {
+        let path = encrypted_path(Path::new("out/dump.txt.zst"));
+        assert_eq!(path, Path::new("out/dump.txt.zst.enc"));
+        assert!(is_encrypted(&path));
+    }
+}
@@ -6,6 +6,24 @@ use async_openai::error::OpenAIError;
 /// Custom result type alias for the application
 pub type Result<T> = std::result::Result<T, ProcessorError>;
 
+/// Exit codes of the command-line tool, one per kind of failure
+pub mod exit_code {
+    /// Everything succeeded
+    pub const SUCCESS: i32 = 0;
+    /// Unexpected or internal error
+    pub const INTERNAL: i32 = 1;
+    /// Invalid arguments, input or configuration (also used by argument parsing)
+    pub const VALIDATION: i32 = 2;
+    /// A remote service could not be reached or answered with an error
+    pub const NETWORK: i32 = 3;
+    /// A rate limit was exhausted
+    pub const RATE_LIMITED: i32 = 4;
+    /// A batch finished but some of its items failed
+    pub const PARTIAL_FAILURE: i32 = 5;
+    /// Interrupted with Ctrl-C; partial output was kept
+    pub const INTERRUPTED: i32 = 130;
+}
+
 /// Errors that can occur during package processing
 #[derive(Debug, Error)]
 pub enum ProcessorError {
@@ -92,6 +110,22 @@ pub enum ProcessorError {
     /// Cache errors
     #[error("Cache error: {0}")]
     Cache(String),
+    
+    /// Template loading/rendering errors
+    #[error("Template error: {0}")]
+    Template(String),
+    
+    /// The run was cancelled, e.g. with Ctrl-C
+    #[error("Interrupted")]
+    Cancelled,
+    
+    /// A workspace used up its job or storage quota
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+    
+    /// The caller's credentials do not allow the request
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
 }
 
 impl ProcessorError {
@@ -115,6 +149,58 @@ impl ProcessorError {
     pub fn is_fatal(&self) -> bool {
         !self.is_transient()
     }
+    
+    /// Short name of the kind of error, such as `network`, for metrics labels
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::IO(_) => "io",
+            Self::Http(_) => "http",
+            Self::Json(_) => "json",
+            Self::Zip(_) => "zip",
+            Self::Walkdir(_) => "walkdir",
+            Self::UrlParse(_) => "url_parse",
+            Self::Download(_) => "download",
+            Self::Message(_) => "message",
+            Self::Network(_) => "network",
+            Self::Validation(_) => "validation",
+            Self::Processing(_) => "processing",
+            Self::Config(_) => "config",
+            Self::RateLimitExceeded(_) => "rate_limit_exceeded",
+            Self::GitHubApi(_) => "github_api",
+            Self::LLM(_) => "llm",
+            Self::Parse(_) => "parse",
+            Self::Database(_) => "database",
+            Self::OpenAI(_) => "openai",
+            Self::PyPiApi(_) => "pypi_api",
+            Self::NpmApi(_) => "npm_api",
+            Self::Cache(_) => "cache",
+            Self::Template(_) => "template",
+            Self::Cancelled => "cancelled",
+            Self::QuotaExceeded(_) => "quota_exceeded",
+            Self::Forbidden(_) => "forbidden",
+        }
+    }
+
+    /// Exit code the command-line tool reports for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::RateLimitExceeded(_) => exit_code::RATE_LIMITED,
+            Self::Http(e) if e.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) => exit_code::RATE_LIMITED,
+            Self::GitHubApi(msg) | Self::PyPiApi(msg) | Self::NpmApi(msg)
+                if msg.to_lowercase().contains("rate limit") => exit_code::RATE_LIMITED,
+            Self::Http(_) | Self::Network(_) | Self::Download(_) |
+            Self::GitHubApi(_) | Self::PyPiApi(_) | Self::NpmApi(_) => exit_code::NETWORK,
+            Self::Validation(_) | Self::Config(_) | Self::UrlParse(_) | Self::Forbidden(_) => exit_code::VALIDATION,
+            Self::Cancelled => exit_code::INTERRUPTED,
+            _ => exit_code::INTERNAL,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ProcessorError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Database(e.to_string())
+    }
 }
 
 #[cfg(test)]
@@ -139,4 +225,33 @@ mod tests {
         assert!(transient.is_transient());
         assert!(!fatal.is_transient());
     }
+    
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(ProcessorError::Validation("bad url".into()).exit_code(), exit_code::VALIDATION);
+        assert_eq!(ProcessorError::Network("timeout".into()).exit_code(), exit_code::NETWORK);
+        assert_eq!(ProcessorError::RateLimitExceeded("github".into()).exit_code(), exit_code::RATE_LIMITED);
+        assert_eq!(ProcessorError::GitHubApi("API rate limit exceeded".into()).exit_code(), exit_code::RATE_LIMITED);
+        assert_eq!(ProcessorError::GitHubApi("Not Found".into()).exit_code(), exit_code::NETWORK);
+        assert_eq!(ProcessorError::new("boom").exit_code(), exit_code::INTERNAL);
+        assert_eq!(ProcessorError::Cancelled.exit_code(), exit_code::INTERRUPTED);
+        assert_eq!(ProcessorError::Config("no output_dir".into()).exit_code(), exit_code::VALIDATION);
+        assert_eq!(ProcessorError::from(url::Url::parse("not a url").unwrap_err()).exit_code(), exit_code::VALIDATION);
+        assert_eq!(ProcessorError::Download("truncated archive".into()).exit_code(), exit_code::NETWORK);
+        assert_eq!(ProcessorError::Forbidden("read scope required".into()).exit_code(), exit_code::VALIDATION);
+    }
+    
+    #[tokio::test]
+    async fn test_exit_code_of_http_errors() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/limited").with_status(429).create_async().await;
+        server.mock("GET", "/broken").with_status(502).create_async().await;
+        
+        let status_error = |path: &'static str| {
+            let url = format!("{}{}", server.url(), path);
+            async move { reqwest::get(url).await.unwrap().error_for_status().unwrap_err() }
+        };
+        assert_eq!(ProcessorError::from(status_error("/limited").await).exit_code(), exit_code::RATE_LIMITED);
+        assert_eq!(ProcessorError::from(status_error("/broken").await).exit_code(), exit_code::NETWORK);
+    }
 }
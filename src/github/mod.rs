@@ -1,4 +1,5 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -12,7 +13,7 @@ pub struct GitHubClient {
 
 impl GitHubClient {
     pub fn new(token: Option<String>) -> Result<Self> {
-        let client = Client::builder()
+        let client = http::client_builder()
             .timeout(Duration::from_secs(30))
             .build()
             .map_err(|e| ProcessorError::Network(e.to_string()))?;
@@ -0,0 +1,246 @@
+//! gRPC interface to processing jobs
+//!
+//! The `Jobs` service defined in `proto/jobs.proto` offers the REST job
+//! endpoints to services that prefer protobuf contracts: submit, status,
+//! listing, artifacts, cancellation and a stream of progress events. It is
+//! served next to the REST API by `serve --grpc-port` in builds with the
+//! `grpc` feature, and shares its job queue, credentials, scopes and
+//! workspaces.
+
+use crate::api::{JobArtifact, JobEvent, JobManager, JobStatus, JobStatusType, ProcessConfig, ProcessRequest};
+use crate::auth::{AuthError, Authenticator, Principal, Scope};
+use crate::error::{ProcessorError, Result};
+use futures::{Stream, StreamExt};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+/// Types and service traits generated from `proto/jobs.proto`
+#[allow(missing_docs, clippy::all)]
+pub mod pb {
+    tonic::include_proto!("llamapackageservice.jobs.v1");
+}
+
+use pb::jobs_server::{Jobs, JobsServer};
+
+/// The `Jobs` service, backed by the same job manager as the REST API
+pub struct JobsService {
+    jobs: Arc<JobManager>,
+    auth: Arc<Authenticator>,
+}
+
+impl JobsService {
+    /// Serves `jobs` to callers `auth` accepts
+    pub fn new(jobs: Arc<JobManager>, auth: Arc<Authenticator>) -> Self {
+        Self { jobs, auth }
+    }
+
+    /// The caller, if its credential grants `scope`
+    fn authorize<T>(&self, request: &Request<T>, scope: Scope) -> std::result::Result<Principal, Status> {
+        let metadata = request.metadata();
+        let bearer = metadata.get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let api_key = metadata.get("x-api-key").and_then(|value| value.to_str().ok());
+        match self.auth.authorize(bearer.or(api_key), scope) {
+            Ok(principal) => Ok(principal),
+            Err(AuthError::Forbidden(scope)) => {
+                Err(Status::permission_denied(format!("this credential lacks the '{}' scope", scope)))
+            }
+            Err(AuthError::Missing) => Err(Status::unauthenticated("credentials required")),
+            Err(AuthError::Invalid) => Err(Status::unauthenticated("invalid or expired credentials")),
+        }
+    }
+
+    /// A job the caller's workspace may see; others are reported as missing
+    async fn visible_job(&self, principal: &Principal, job_id: &str) -> std::result::Result<JobStatus, Status> {
+        self.jobs.get_job_status(job_id).await
+            .ok()
+            .filter(|job| job.is_visible_to(principal.workspace.as_deref()))
+            .ok_or_else(|| Status::not_found(format!("Job not found: {}", job_id)))
+    }
+}
+
+#[tonic::async_trait]
+impl Jobs for JobsService {
+    async fn submit_job(
+        &self,
+        request: Request<pb::SubmitJobRequest>,
+    ) -> std::result::Result<Response<pb::SubmitJobResponse>, Status> {
+        let principal = self.authorize(&request, Scope::Submit)?;
+        let submit = request.into_inner();
+        let request = ProcessRequest {
+            url: submit.url,
+            output_dir: submit.output_dir,
+            config: Some(ProcessConfig {
+                generate_index: submit.generate_index,
+                organize_output: submit.organize_output,
+                max_concurrent: None,
+            }),
+            processor: submit.processor,
+        };
+        let response = self.jobs.submit_job(request, principal.workspace.as_deref()).await
+            .map_err(status_from_error)?;
+        Ok(Response::new(pb::SubmitJobResponse {
+            job_id: response.job_id,
+            url_type: response.url_type,
+            output_dir: response.output_dir,
+        }))
+    }
+
+    async fn get_job(&self, request: Request<pb::JobRef>) -> std::result::Result<Response<pb::Job>, Status> {
+        let principal = self.authorize(&request, Scope::Read)?;
+        let job = self.visible_job(&principal, &request.get_ref().job_id).await?;
+        Ok(Response::new(pb::Job::from(&job)))
+    }
+
+    async fn list_jobs(
+        &self,
+        request: Request<pb::ListJobsRequest>,
+    ) -> std::result::Result<Response<pb::ListJobsResponse>, Status> {
+        let principal = self.authorize(&request, Scope::Read)?;
+        let jobs = self.jobs.list_jobs(principal.workspace.as_deref()).await.map_err(status_from_error)?;
+        Ok(Response::new(pb::ListJobsResponse { jobs: jobs.iter().map(pb::Job::from).collect() }))
+    }
+
+    async fn get_artifacts(
+        &self,
+        request: Request<pb::JobRef>,
+    ) -> std::result::Result<Response<pb::ArtifactsResponse>, Status> {
+        let principal = self.authorize(&request, Scope::Read)?;
+        let job_id = request.into_inner().job_id;
+        self.visible_job(&principal, &job_id).await?;
+        let artifacts = self.jobs.get_job_artifacts(&job_id).await.map_err(status_from_error)?;
+        Ok(Response::new(pb::ArtifactsResponse {
+            job_id,
+            artifacts: artifacts.into_iter().map(|JobArtifact { path, size }| pb::Artifact { path, size }).collect(),
+        }))
+    }
+
+    async fn cancel_job(&self, request: Request<pb::JobRef>) -> std::result::Result<Response<pb::Job>, Status> {
+        let principal = self.authorize(&request, Scope::Submit)?;
+        let job_id = &request.get_ref().job_id;
+        self.visible_job(&principal, job_id).await?;
+        let job = self.jobs.delete_job(job_id).await.map_err(status_from_error)?;
+        Ok(Response::new(pb::Job::from(&job)))
+    }
+
+    type WatchJobStream = Pin<Box<dyn Stream<Item = std::result::Result<pb::JobEvent, Status>> + Send>>;
+
+    async fn watch_job(
+        &self,
+        request: Request<pb::JobRef>,
+    ) -> std::result::Result<Response<Self::WatchJobStream>, Status> {
+        let principal = self.authorize(&request, Scope::Read)?;
+        let job_id = &request.get_ref().job_id;
+        self.visible_job(&principal, job_id).await?;
+        let events = self.jobs.watch_job(job_id).await.map_err(status_from_error)?;
+        Ok(Response::new(Box::pin(events.map(|event| Ok(pb::JobEvent::from(&event))))))
+    }
+}
+
+/// Serves the `Jobs` service on `addr` until the listener fails
+pub async fn serve(addr: SocketAddr, jobs: Arc<JobManager>, auth: Arc<Authenticator>) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(JobsServer::new(JobsService::new(jobs, auth)))
+        .serve(addr)
+        .await
+        .map_err(|e| ProcessorError::Network(format!("gRPC server failed: {}", e)))
+}
+
+fn status_from_error(error: ProcessorError) -> Status {
+    match error {
+        ProcessorError::QuotaExceeded(message) => Status::resource_exhausted(message),
+        ProcessorError::Forbidden(message) => Status::permission_denied(message),
+        ProcessorError::Validation(message) | ProcessorError::Config(message) => Status::invalid_argument(message),
+        ProcessorError::Message(message) if message.starts_with("Job not found") => Status::not_found(message),
+        other => Status::internal(other.to_string()),
+    }
+}
+
+impl From<&JobStatusType> for pb::JobState {
+    fn from(status: &JobStatusType) -> Self {
+        match status {
+            JobStatusType::Queued => Self::Queued,
+            JobStatusType::Processing => Self::Processing,
+            JobStatusType::Completed => Self::Completed,
+            JobStatusType::Failed => Self::Failed,
+            JobStatusType::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+impl From<&JobStatus> for pb::Job {
+    fn from(job: &JobStatus) -> Self {
+        Self {
+            job_id: job.job_id.clone(),
+            state: pb::JobState::from(&job.status).into(),
+            url: job.url.clone(),
+            url_type: job.url_type.clone(),
+            output_dir: job.output_dir.to_string_lossy().to_string(),
+            created_at: job.created_at.to_rfc3339(),
+            updated_at: job.updated_at.to_rfc3339(),
+            progress: job.progress.into(),
+            current_operation: job.current_operation.clone(),
+            error_message: job.error_message.clone(),
+            output_files: job.output_files.clone(),
+            workspace: job.workspace.clone(),
+            eta_seconds: job.eta_seconds,
+        }
+    }
+}
+
+impl From<&JobEvent> for pb::JobEvent {
+    fn from(event: &JobEvent) -> Self {
+        Self {
+            job_id: event.job_id.clone(),
+            state: pb::JobState::from(&event.status).into(),
+            progress: event.progress.into(),
+            stage: event.stage.clone(),
+            files_processed: event.files_processed.map(|n| n as u64),
+            files_total: event.files_total.map(|n| n as u64),
+            bytes_downloaded: event.bytes_downloaded,
+            eta_seconds: event.eta_seconds,
+            error_message: event.error_message.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_jobs_convert_to_protobuf() {
+        let job = JobStatus {
+            job_id: "job".to_string(),
+            status: JobStatusType::Processing,
+            url: "https://github.com/owner/repo".to_string(),
+            url_type: "github".to_string(),
+            output_dir: PathBuf::from("output"),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            progress: 30,
+            current_operation: Some("Processing package".to_string()),
+            error_message: None,
+            output_files: Vec::new(),
+            workspace: Some("team-a".to_string()),
+            usage: None,
+            eta_seconds: None,
+        };
+        let message = pb::Job::from(&job);
+        assert_eq!(message.state(), pb::JobState::Processing);
+        assert_eq!(message.progress, 30);
+        assert_eq!(message.workspace.as_deref(), Some("team-a"));
+
+        let mut event = JobEvent::from(&job);
+        event.files_processed = Some(3);
+        assert_eq!(pb::JobEvent::from(&event).files_processed, Some(3));
+
+        assert_eq!(status_from_error(ProcessorError::QuotaExceeded("full".into())).code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status_from_error(ProcessorError::Message("Job not found: x".into())).code(), tonic::Code::NotFound);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::error::ProcessorError;
+use crate::http;
 use indicatif::{ProgressBar, ProgressStyle};
 use colored::*;
 
@@ -45,7 +46,7 @@ async fn check_github_api() -> Result<(), ProcessorError> {
     }
 
     // Test the API connection
-    let client = reqwest::Client::new();
+    let client = http::client()?;
     let mut request = client.get("https://api.github.com/rate_limit");
     
     if let Some(token) = config.github_token.as_ref() {
@@ -0,0 +1,629 @@
+//! Persistent queue behind the REST API's processing jobs
+//!
+//! Jobs are stored in a SQLite database, `<output>/.jobs.sqlite`, in one of
+//! the states `pending`, `running`, `done`, `failed` or `cancelled`. Workers
+//! claim the oldest pending job, so jobs run in submission order. Opening the
+//! queue puts jobs left `running` by a server that crashed or was stopped back
+//! to `pending`, so they run again instead of being lost.
+//!
+//! Jobs submitted from a [workspace](crate::workspace) record its name, which
+//! is how the API keeps teams from seeing each other's jobs and counts their
+//! daily quota. AI analysis jobs also record the tokens they used.
+//!
+//! [`JobQueue::query`] pages through jobs with keyset pagination: each page
+//! ends with an opaque [`Cursor`] holding the sort value and id of its last
+//! job, so pages stay consistent while new jobs arrive.
+
+use crate::agents::TokenUsage;
+use crate::api::{JobRequest, JobStatus, JobStatusType};
+use crate::database::Database;
+use crate::error::{ProcessorError, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine as _};
+use chrono::{DateTime, Utc};
+use rusqlite::types::ToSql;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File name of the job database inside the output directory
+pub const JOBS_DB_FILE: &str = ".jobs.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        url TEXT NOT NULL,
+        url_type TEXT NOT NULL,
+        output_dir TEXT NOT NULL,
+        request TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        progress INTEGER NOT NULL DEFAULT 0,
+        current_operation TEXT,
+        error_message TEXT,
+        output_files TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE INDEX IF NOT EXISTS jobs_by_state ON jobs (state, created_at);
+";
+
+/// Columns added after the first release; older databases gain them when opened
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("workspace", "TEXT"),
+    ("usage", "TEXT"),
+];
+
+const JOB_COLUMNS: &str = "id, state, url, url_type, output_dir, created_at, updated_at, \
+    progress, current_operation, error_message, output_files, workspace, usage";
+
+/// Order of a job listing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobSort {
+    /// When the job was submitted
+    #[default]
+    Created,
+    /// When the job last changed
+    Updated,
+    /// The URL being processed
+    Url,
+}
+
+impl JobSort {
+    /// Parses `created`, `updated` or `url`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "created" => Ok(Self::Created),
+            "updated" => Ok(Self::Updated),
+            "url" => Ok(Self::Url),
+            other => Err(ProcessorError::Validation(format!(
+                "unknown sort '{}' (use created, updated or url)", other
+            ))),
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            Self::Created => "created_at",
+            Self::Updated => "updated_at",
+            Self::Url => "url",
+        }
+    }
+}
+
+/// Where a page of jobs ended: the sort value and id of its last job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    sort: JobSort,
+    ascending: bool,
+    value: String,
+    id: String,
+}
+
+impl Cursor {
+    /// The cursor as an opaque, URL-safe page token
+    pub fn encode(&self) -> String {
+        BASE64_URL.encode(serde_json::to_vec(self).expect("cursors serialize"))
+    }
+
+    /// Reads a page token made by [`Cursor::encode`]
+    pub fn decode(token: &str) -> Result<Self> {
+        BASE64_URL.decode(token).ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| ProcessorError::Validation("invalid page token".to_string()))
+    }
+}
+
+/// Which jobs to list, and how
+#[derive(Debug, Clone)]
+pub struct JobQuery {
+    /// Only jobs of this workspace
+    pub workspace: Option<String>,
+    /// Only jobs in one of these states; empty means any
+    pub states: Vec<JobStatusType>,
+    /// Only jobs whose URL type contains this text, ignoring ASCII case, such
+    /// as `github` for "GitHub Repository" or `analysis`
+    pub source: Option<String>,
+    /// Only jobs submitted at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only jobs whose URL contains this text, ignoring ASCII case
+    pub url_contains: Option<String>,
+    /// Order of the listing
+    pub sort: JobSort,
+    /// Oldest or smallest first rather than newest or largest
+    pub ascending: bool,
+    /// Most jobs on one page
+    pub limit: usize,
+    /// Continue after the page that ended here
+    pub after: Option<Cursor>,
+}
+
+impl Default for JobQuery {
+    fn default() -> Self {
+        Self {
+            workspace: None,
+            states: Vec::new(),
+            source: None,
+            since: None,
+            url_contains: None,
+            sort: JobSort::Created,
+            ascending: false,
+            limit: 50,
+            after: None,
+        }
+    }
+}
+
+/// One page of a job listing
+#[derive(Debug)]
+pub struct JobPage {
+    /// Jobs on this page
+    pub jobs: Vec<JobStatus>,
+    /// Jobs matching the filters across every page
+    pub total: usize,
+    /// Where the next page starts, if there is one
+    pub next: Option<Cursor>,
+}
+
+/// SQLite-backed store of API jobs
+pub struct JobQueue {
+    db: Database,
+}
+
+impl JobQueue {
+    /// Opens or creates the queue at `path`, requeueing jobs that were left running
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = Database::open(path, SCHEMA)?;
+        {
+            let conn = db.lock();
+            for (column, kind) in ADDED_COLUMNS {
+                if conn.prepare(&format!("SELECT {} FROM jobs LIMIT 0", column)).is_err() {
+                    conn.execute_batch(&format!("ALTER TABLE jobs ADD COLUMN {} {};", column, kind))?;
+                }
+            }
+            conn.execute_batch("CREATE INDEX IF NOT EXISTS jobs_by_workspace ON jobs (workspace, created_at);")?;
+        }
+        let queue = Self { db };
+        queue.recover()?;
+        Ok(queue)
+    }
+
+    /// Adds a pending job
+    pub fn push(&self, job: &JobStatus, request: &JobRequest) -> Result<()> {
+        let conn = self.db.lock();
+        conn.execute(
+            "INSERT INTO jobs (id, state, url, url_type, output_dir, request, created_at, updated_at, \
+             progress, current_operation, error_message, output_files, workspace) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                job.job_id,
+                state_name(&job.status),
+                job.url,
+                job.url_type,
+                job.output_dir.to_string_lossy(),
+                serde_json::to_string(request)?,
+                job.created_at,
+                job.updated_at,
+                job.progress,
+                job.current_operation,
+                job.error_message,
+                serde_json::to_string(&job.output_files)?,
+                job.workspace,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Marks the oldest pending job as running and returns it with its request
+    pub fn claim_next(&self) -> Result<Option<(JobStatus, JobRequest)>> {
+        let conn = self.db.lock();
+        let next = conn.query_row(
+            "SELECT id, request FROM jobs WHERE state = 'pending' ORDER BY created_at LIMIT 1",
+            [],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        ).optional()?;
+        let Some((id, request)) = next else {
+            return Ok(None);
+        };
+        conn.execute(
+            "UPDATE jobs SET state = 'running', updated_at = ?2 WHERE id = ?1",
+            params![id, Utc::now()],
+        )?;
+        let job = Self::fetch(&conn, &id)?
+            .ok_or_else(|| ProcessorError::Database(format!("job {} vanished", id)))?;
+        Ok(Some((job, serde_json::from_str(&request)?)))
+    }
+
+    /// A job by id
+    pub fn get(&self, id: &str) -> Result<Option<JobStatus>> {
+        Self::fetch(&self.db.lock(), id)
+    }
+
+    /// All jobs, newest first
+    pub fn list(&self) -> Result<Vec<JobStatus>> {
+        let conn = self.db.lock();
+        let mut statement = conn
+            .prepare(&format!("SELECT {} FROM jobs ORDER BY created_at DESC", JOB_COLUMNS))?;
+        let jobs = statement.query_map([], job_from_row)?;
+        jobs.collect::<rusqlite::Result<Vec<_>>>().map_err(ProcessorError::from)
+    }
+
+    /// Jobs of one workspace, newest first
+    pub fn list_workspace(&self, workspace: &str) -> Result<Vec<JobStatus>> {
+        let conn = self.db.lock();
+        let mut statement = conn
+            .prepare(&format!("SELECT {} FROM jobs WHERE workspace = ?1 ORDER BY created_at DESC", JOB_COLUMNS))?;
+        let jobs = statement.query_map(params![workspace], job_from_row)?;
+        jobs.collect::<rusqlite::Result<Vec<_>>>().map_err(ProcessorError::from)
+    }
+
+    /// One page of the jobs matching `query`, with how many match in all
+    pub fn query(&self, query: &JobQuery) -> Result<JobPage> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(workspace) = &query.workspace {
+            conditions.push("workspace = ?".to_string());
+            values.push(Box::new(workspace.clone()));
+        }
+        if !query.states.is_empty() {
+            conditions.push(format!("state IN ({})", vec!["?"; query.states.len()].join(", ")));
+            values.extend(query.states.iter().map(|state| Box::new(state_name(state)) as Box<dyn ToSql>));
+        }
+        if let Some(source) = &query.source {
+            conditions.push("url_type LIKE ? ESCAPE '\\'".to_string());
+            values.push(Box::new(like_pattern(source)));
+        }
+        if let Some(since) = query.since {
+            conditions.push("created_at >= ?".to_string());
+            values.push(Box::new(since));
+        }
+        if let Some(text) = &query.url_contains {
+            conditions.push("url LIKE ? ESCAPE '\\'".to_string());
+            values.push(Box::new(like_pattern(text)));
+        }
+
+        let conn = self.db.lock();
+        let filter = where_clause(&conditions);
+        let total = conn.query_row(
+            &format!("SELECT COUNT(*) FROM jobs{}", filter),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        let column = query.sort.column();
+        let (direction, after) = if query.ascending { ("ASC", ">") } else { ("DESC", "<") };
+        if let Some(cursor) = &query.after {
+            if cursor.sort != query.sort || cursor.ascending != query.ascending {
+                return Err(ProcessorError::Validation("page token is for a different sort order".to_string()));
+            }
+            conditions.push(format!("({}, id) {} (?, ?)", column, after));
+            values.push(Box::new(cursor.value.clone()));
+            values.push(Box::new(cursor.id.clone()));
+        }
+        let mut statement = conn.prepare(&format!(
+            "SELECT {}, {} FROM jobs{} ORDER BY {} {}, id {} LIMIT {}",
+            JOB_COLUMNS, column, where_clause(&conditions), column, direction, direction, query.limit + 1
+        ))?;
+        let mut rows = statement
+            .query_map(params_from_iter(values.iter()), |row| Ok((job_from_row(row)?, row.get::<_, String>(13)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let next = if rows.len() > query.limit {
+            rows.truncate(query.limit);
+            rows.last().map(|(job, value)| Cursor {
+                sort: query.sort,
+                ascending: query.ascending,
+                value: value.clone(),
+                id: job.job_id.clone(),
+            })
+        } else {
+            None
+        };
+        Ok(JobPage { jobs: rows.into_iter().map(|(job, _)| job).collect(), total, next })
+    }
+
+    /// Jobs a workspace has submitted since `since`, whatever became of them
+    pub fn count_submitted(&self, workspace: &str, since: DateTime<Utc>) -> Result<usize> {
+        self.db.lock().query_row(
+            "SELECT COUNT(*) FROM jobs WHERE workspace = ?1 AND created_at >= ?2",
+            params![workspace, since],
+            |row| row.get(0),
+        ).map_err(ProcessorError::from)
+    }
+
+    /// Records how far a running job has got, returning whether it is still running
+    pub fn set_progress(&self, id: &str, progress: u8, operation: &str) -> Result<bool> {
+        let changed = self.db.lock().execute(
+            "UPDATE jobs SET progress = ?2, current_operation = ?3, updated_at = ?4 \
+             WHERE id = ?1 AND state = 'running'",
+            params![id, progress, operation, Utc::now()],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Records the tokens a job used
+    pub fn set_usage(&self, id: &str, usage: &TokenUsage) -> Result<()> {
+        self.db.lock().execute(
+            "UPDATE jobs SET usage = ?2, updated_at = ?3 WHERE id = ?1",
+            params![id, serde_json::to_string(usage)?, Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    /// Records the outcome of a running job, returning whether it was still running
+    ///
+    /// Cancelled jobs stay cancelled.
+    pub fn finish(&self, id: &str, outcome: std::result::Result<Vec<String>, String>) -> Result<bool> {
+        let now = Utc::now();
+        let conn = self.db.lock();
+        let changed = match outcome {
+            Ok(output_files) => conn.execute(
+                "UPDATE jobs SET state = 'done', progress = 100, current_operation = 'Completed successfully', \
+                 output_files = ?2, updated_at = ?3 WHERE id = ?1 AND state = 'running'",
+                params![id, serde_json::to_string(&output_files)?, now],
+            ),
+            Err(message) => conn.execute(
+                "UPDATE jobs SET state = 'failed', current_operation = 'Failed', error_message = ?2, \
+                 updated_at = ?3 WHERE id = ?1 AND state = 'running'",
+                params![id, message, now],
+            ),
+        }?;
+        Ok(changed > 0)
+    }
+
+    /// Puts running jobs that have not reported progress since `before` back in the queue
+    ///
+    /// This is how jobs of remote workers that crashed or lost their
+    /// connection get another worker.
+    pub fn requeue_stale(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.db.lock().execute(
+            "UPDATE jobs SET state = 'pending', progress = 0, \
+             current_operation = 'Requeued after its worker stopped reporting', updated_at = ?2 \
+             WHERE state = 'running' AND updated_at < ?1",
+            params![before, Utc::now()],
+        ).map_err(ProcessorError::from)
+    }
+
+    /// Cancels a pending or running job, returning whether it was still active
+    pub fn cancel(&self, id: &str) -> Result<bool> {
+        let changed = self.db.lock().execute(
+            "UPDATE jobs SET state = 'cancelled', current_operation = 'Cancelled', updated_at = ?2 \
+             WHERE id = ?1 AND state IN ('pending', 'running')",
+            params![id, Utc::now()],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Deletes a job, returning whether it existed
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let changed = self.db.lock().execute("DELETE FROM jobs WHERE id = ?1", params![id])?;
+        Ok(changed > 0)
+    }
+
+    /// Puts jobs left running by a previous server back in the queue
+    fn recover(&self) -> Result<usize> {
+        let requeued = self.db.lock().execute(
+            "UPDATE jobs SET state = 'pending', progress = 0, \
+             current_operation = 'Requeued after restart', updated_at = ?1 WHERE state = 'running'",
+            params![Utc::now()],
+        )?;
+        if requeued > 0 {
+            log::info!("Requeued {} job(s) interrupted by a restart", requeued);
+        }
+        Ok(requeued)
+    }
+
+    fn fetch(conn: &Connection, id: &str) -> Result<Option<JobStatus>> {
+        conn.query_row(
+            &format!("SELECT {} FROM jobs WHERE id = ?1", JOB_COLUMNS),
+            params![id],
+            job_from_row,
+        ).optional().map_err(ProcessorError::from)
+    }
+}
+
+/// `LIKE` pattern matching text that contains `text`, wildcards included literally
+fn like_pattern(text: &str) -> String {
+    format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"))
+}
+
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+fn job_from_row(row: &Row<'_>) -> rusqlite::Result<JobStatus> {
+    let output_files: String = row.get(10)?;
+    Ok(JobStatus {
+        job_id: row.get(0)?,
+        status: parse_state(&row.get::<_, String>(1)?),
+        url: row.get(2)?,
+        url_type: row.get(3)?,
+        output_dir: PathBuf::from(row.get::<_, String>(4)?),
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        progress: row.get(7)?,
+        current_operation: row.get(8)?,
+        error_message: row.get(9)?,
+        output_files: serde_json::from_str(&output_files).unwrap_or_default(),
+        workspace: row.get(11)?,
+        usage: row.get::<_, Option<String>>(12)?.and_then(|usage| serde_json::from_str(&usage).ok()),
+        eta_seconds: None,
+    })
+}
+
+/// Name of a job state in the database
+fn state_name(status: &JobStatusType) -> &'static str {
+    match status {
+        JobStatusType::Queued => "pending",
+        JobStatusType::Processing => "running",
+        JobStatusType::Completed => "done",
+        JobStatusType::Failed => "failed",
+        JobStatusType::Cancelled => "cancelled",
+    }
+}
+
+fn parse_state(name: &str) -> JobStatusType {
+    match name {
+        "pending" => JobStatusType::Queued,
+        "running" => JobStatusType::Processing,
+        "done" => JobStatusType::Completed,
+        "cancelled" => JobStatusType::Cancelled,
+        _ => JobStatusType::Failed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{AnalysisRequest, ProcessRequest};
+    use tempfile::tempdir;
+
+    fn pending(id: &str, created_at: chrono::DateTime<Utc>) -> (JobStatus, JobRequest) {
+        let job = JobStatus {
+            job_id: id.to_string(),
+            status: JobStatusType::Queued,
+            url: format!("https://github.com/owner/{}", id),
+            url_type: "github".to_string(),
+            output_dir: PathBuf::from("output"),
+            created_at,
+            updated_at: created_at,
+            progress: 0,
+            current_operation: None,
+            error_message: None,
+            output_files: Vec::new(),
+            workspace: None,
+            usage: None,
+            eta_seconds: None,
+        };
+        let request = ProcessRequest { url: job.url.clone(), output_dir: None, config: None, processor: None };
+        (job, request.into())
+    }
+
+    #[test]
+    fn test_jobs_run_in_order_and_survive_restart() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(JOBS_DB_FILE);
+        let now = Utc::now();
+        {
+            let queue = JobQueue::open(&path).unwrap();
+            for (id, age) in [("first", 2), ("second", 1)] {
+                let (job, request) = pending(id, now - chrono::Duration::seconds(age));
+                queue.push(&job, &request).unwrap();
+            }
+            let (claimed, request) = queue.claim_next().unwrap().unwrap();
+            assert_eq!(claimed.job_id, "first");
+            assert_eq!(claimed.status, JobStatusType::Processing);
+            assert!(matches!(request, JobRequest::Process(request) if request.url == "https://github.com/owner/first"));
+        }
+
+        // The server went away while "first" was running
+        let queue = JobQueue::open(&path).unwrap();
+        assert_eq!(queue.get("first").unwrap().unwrap().status, JobStatusType::Queued);
+
+        let (claimed, _) = queue.claim_next().unwrap().unwrap();
+        assert_eq!(claimed.job_id, "first");
+        queue.finish("first", Ok(vec!["first.txt".to_string()])).unwrap();
+        let done = queue.get("first").unwrap().unwrap();
+        assert_eq!(done.status, JobStatusType::Completed);
+        assert_eq!(done.output_files, ["first.txt"]);
+
+        assert!(queue.cancel("second").unwrap());
+        assert!(queue.claim_next().unwrap().is_none());
+        assert!(!queue.cancel("first").unwrap());
+
+        let ids: Vec<String> = queue.list().unwrap().into_iter().map(|job| job.job_id).collect();
+        assert_eq!(ids, ["second", "first"]);
+        assert!(queue.remove("first").unwrap());
+        assert!(queue.get("first").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_workspace_jobs_are_listed_and_counted_apart() {
+        let dir = tempdir().unwrap();
+        let queue = JobQueue::open(&dir.path().join(JOBS_DB_FILE)).unwrap();
+        let now = Utc::now();
+        for (id, workspace, age_hours) in [("old", "team-a", 30), ("new", "team-a", 1), ("other", "team-b", 1)] {
+            let (mut job, request) = pending(id, now - chrono::Duration::hours(age_hours));
+            job.workspace = Some(workspace.to_string());
+            queue.push(&job, &request).unwrap();
+        }
+
+        let ids: Vec<String> = queue.list_workspace("team-a").unwrap().into_iter().map(|job| job.job_id).collect();
+        assert_eq!(ids, ["new", "old"]);
+        assert_eq!(queue.count_submitted("team-a", now - chrono::Duration::hours(24)).unwrap(), 1);
+        assert_eq!(queue.count_submitted("team-c", now - chrono::Duration::hours(24)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_analysis_jobs_keep_their_request_and_usage() {
+        let dir = tempdir().unwrap();
+        let queue = JobQueue::open(&dir.path().join(JOBS_DB_FILE)).unwrap();
+        let (job, _) = pending("analysis", Utc::now());
+        let request = JobRequest::Analysis(AnalysisRequest {
+            repository: job.url.clone(),
+            analysis_type: "security".to_string(),
+            context: None,
+        });
+        queue.push(&job, &request).unwrap();
+
+        let (_, claimed) = queue.claim_next().unwrap().unwrap();
+        assert!(matches!(claimed, JobRequest::Analysis(request) if request.analysis_type == "security"));
+        let usage = TokenUsage::new("gpt-4", 100, 20);
+        queue.set_usage("analysis", &usage).unwrap();
+        assert_eq!(queue.get("analysis").unwrap().unwrap().usage, Some(usage));
+    }
+
+    #[test]
+    fn test_query_filters_and_pages() {
+        let dir = tempdir().unwrap();
+        let queue = JobQueue::open(&dir.path().join(JOBS_DB_FILE)).unwrap();
+        let now = Utc::now();
+        for (id, age_hours) in [("alpha", 5), ("beta_1", 4), ("beta-2", 3), ("gamma", 2), ("delta", 1)] {
+            let (job, request) = pending(id, now - chrono::Duration::hours(age_hours));
+            queue.push(&job, &request).unwrap();
+        }
+        queue.cancel("gamma").unwrap();
+
+        let mut query = JobQuery { limit: 2, ..JobQuery::default() };
+        let mut pages = Vec::new();
+        loop {
+            let page = queue.query(&query).unwrap();
+            assert_eq!(page.total, 5);
+            pages.push(page.jobs.into_iter().map(|job| job.job_id).collect::<Vec<_>>());
+            match page.next {
+                Some(cursor) => query.after = Some(Cursor::decode(&cursor.encode()).unwrap()),
+                None => break,
+            }
+        }
+        assert_eq!(pages, [vec!["delta", "gamma"], vec!["beta-2", "beta_1"], vec!["alpha"]]);
+
+        // `_` is matched literally, and case is ignored
+        let query = JobQuery { url_contains: Some("BETA_".to_string()), ..JobQuery::default() };
+        let ids: Vec<String> = queue.query(&query).unwrap().jobs.into_iter().map(|job| job.job_id).collect();
+        assert_eq!(ids, ["beta_1"]);
+
+        let query = JobQuery {
+            states: vec![JobStatusType::Queued],
+            source: Some("GitHub".to_string()),
+            since: Some(now - chrono::Duration::minutes(270)),
+            sort: JobSort::Url,
+            ascending: true,
+            ..JobQuery::default()
+        };
+        let page = queue.query(&query).unwrap();
+        let ids: Vec<String> = page.jobs.into_iter().map(|job| job.job_id).collect();
+        assert_eq!(ids, ["beta-2", "beta_1", "delta"]);
+        assert!(page.next.is_none());
+        let query = JobQuery { source: Some("pypi".to_string()), ..JobQuery::default() };
+        assert_eq!(queue.query(&query).unwrap().total, 0);
+
+        // A token only continues the listing it came from
+        let after = queue.query(&JobQuery { limit: 1, ..JobQuery::default() }).unwrap().next;
+        let query = JobQuery { sort: JobSort::Url, after, ..JobQuery::default() };
+        assert!(matches!(queue.query(&query), Err(ProcessorError::Validation(_))));
+        assert!(Cursor::decode("not a token").is_err());
+    }
+}
@@ -24,7 +24,7 @@
 //!
 //! async fn example() -> Result<(), Box<dyn std::error::Error>> {
 //!     let config = Config::default();
-//!     let processor = GitHubProcessor::new();
+//!     let processor = GitHubProcessor::new()?;
 //!     
 //!     processor.process("https://github.com/rust-lang/rust", 
 //!                    Path::new("output"), 
@@ -50,8 +50,12 @@ pub mod metrics;
 pub mod processors;
 /// Parallel processing utilities
 pub mod parallel;
+/// Backpressure between the download, extract, analyze and write stages
+pub mod pipeline;
 /// Rate limiting functionality to respect API limits
 pub mod rate_limiter;
+/// Shared layer for outbound requests to GitHub and the registries
+pub mod http;
 /// Output organization and indexing utilities
 pub mod output_organizer;
 /// REST API functionality for web service
@@ -60,6 +64,67 @@ pub mod api;
 pub mod agents;
 /// Utilities (path normalization, retry helpers, cache helpers)
 pub mod utils;
+/// User-overridable report templates
+pub mod templates;
+/// Optimized extraction, hashing and line counting
+pub mod perf;
+/// Token-aware chunking and truncation
+pub mod chunking;
+/// Transparent compression of output artifacts
+pub mod compression;
+/// Release monitoring and "what's new" feeds
+pub mod monitor;
+/// README quality audit
+pub mod docs_health;
+/// Repository metrics and per-function code complexity
+pub mod analytics;
+/// Broken link and dead reference checker
+pub mod link_checker;
+/// Diff reports between successive runs of the same target
+pub mod run_diff;
+/// Chunk embeddings and their export
+pub mod embeddings;
+/// Offline end-to-end demo on a bundled fixture repository
+pub mod quickstart;
+/// Static documentation site for the output directory
+pub mod site;
+/// Per-run manifest of generated artifacts
+pub mod manifest;
+/// Retention limits for the output directory
+pub mod retention;
+/// Combined report for batches of packages
+pub mod batch_report;
+/// Output destinations, including remote object storage
+pub mod output;
+/// At-rest encryption of output artifacts
+pub mod encryption;
+/// Checkpoints for resumable batch runs
+pub mod checkpoint;
+/// REST API server
+pub mod server;
+/// Readiness checks for orchestrators
+pub mod readiness;
+/// GitHub webhooks that queue processing of changed repositories
+pub mod webhook;
+/// Jobs the API server queues on a schedule
+pub mod scheduling;
+/// Shared SQLite connections of the persistent stores
+pub mod database;
+/// Persistent queue of API jobs
+pub mod job_queue;
+/// Progress reported by processors
+pub mod progress;
+/// REST API authentication
+pub mod auth;
+/// Graceful cancellation on Ctrl-C
+pub mod cancellation;
+/// Workspaces isolating teams on a shared API server
+pub mod workspace;
+/// Remote workers processing the API server's jobs
+pub mod worker;
+/// gRPC interface to processing jobs
+#[cfg(feature = "grpc")]
+pub mod grpc;
 
 // Re-export common types
 pub use config::Config;
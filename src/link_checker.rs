@@ -0,0 +1,381 @@
+//! Broken link and dead reference checker
+//!
+//! Scans a processed repository's README and `docs/` tree for external links
+//! and probes each one with an HTTP HEAD request. Links to GitHub repositories
+//! are resolved through the API instead, which also reveals repositories that
+//! have been archived or renamed. Results are cached per URL and requests are
+//! rate limited per host so large documentation sets do not hammer anyone.
+//!
+//! Checking is opt-in (`processing.check_links` or `--check-links`); one
+//! checker, and so one cache, is shared by every repository of a run.
+
+use crate::cache::{Cache, CacheLimits};
+use crate::config::Config;
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::rate_limiter::RateLimiter;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use reqwest::{redirect, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// How long a link result is reused before it is probed again
+const CACHE_TTL: Duration = Duration::from_secs(6 * 3600);
+
+/// Most link results kept, so huge documentation sets cannot grow the cache without bound
+const CACHE_ENTRIES: usize = 10_000;
+
+/// Per-host request budget
+const REQUESTS_PER_HOST: usize = 20;
+
+static SHARED: OnceCell<LinkChecker> = OnceCell::new();
+
+static URL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://[^\s<>()\[\]"'`]+"#).unwrap());
+
+/// Outcome of checking one link
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LinkStatus {
+    /// The link resolves
+    Ok,
+    /// The link redirects to a different domain
+    Redirected {
+        /// Final location of the redirect
+        location: String,
+    },
+    /// The link no longer resolves
+    Dead {
+        /// HTTP status, if a response was received
+        code: Option<u16>,
+        /// Short explanation
+        reason: String,
+    },
+    /// The link points at an archived GitHub repository
+    ArchivedRepo,
+    /// The link points at a GitHub repository that has moved
+    RenamedRepo {
+        /// Current `owner/name` of the repository
+        new_name: String,
+    },
+}
+
+/// A link with a problem, and where it was found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkFinding {
+    /// The link as written
+    pub url: String,
+    /// Documentation files (relative to the repository root) containing the link
+    pub files: Vec<String>,
+    /// What is wrong with it
+    pub status: LinkStatus,
+}
+
+/// Results of a link check pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LinkReport {
+    /// Number of distinct links checked
+    pub checked: usize,
+    /// Links that are not plainly OK
+    pub findings: Vec<LinkFinding>,
+}
+
+impl LinkReport {
+    /// Renders the report as a subsection of a findings section
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("### Links\n\n");
+        if self.findings.is_empty() {
+            out.push_str(&format!("All {} external link(s) in the documentation resolve.\n\n", self.checked));
+            return out;
+        }
+
+        out.push_str(&format!(
+            "{} of {} external link(s) need attention.\n\n",
+            self.findings.len(),
+            self.checked
+        ));
+        out.push_str("| Link | Problem | Found in |\n|------|---------|----------|\n");
+        for finding in &self.findings {
+            let problem = match &finding.status {
+                LinkStatus::Ok => continue,
+                LinkStatus::Redirected { location } => format!("Redirects to another domain: {}", location),
+                LinkStatus::Dead { code: Some(code), reason } => format!("Dead (HTTP {}): {}", code, reason),
+                LinkStatus::Dead { code: None, reason } => format!("Dead: {}", reason),
+                LinkStatus::ArchivedRepo => "Repository is archived".to_string(),
+                LinkStatus::RenamedRepo { new_name } => format!("Repository moved to {}", new_name),
+            };
+            out.push_str(&format!("| {} | {} | {} |\n", finding.url, problem, finding.files.join(", ")));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Checks documentation links with caching and per-host rate limits
+pub struct LinkChecker {
+    client: Client,
+    github: Client,
+    cache: Cache<LinkStatus>,
+    limiter: RateLimiter,
+}
+
+impl LinkChecker {
+    /// Creates a checker; `github_token` is used for repository lookups when set
+    pub fn new(github_token: Option<&str>) -> Result<Self> {
+        let client = http::client_builder()
+            .user_agent("llama-package-service")
+            .timeout(Duration::from_secs(15))
+            .redirect(redirect::Policy::none())
+            .build()?;
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(token) = github_token {
+            if let Ok(value) = reqwest::header::HeaderValue::from_str(&format!("token {}", token)) {
+                headers.insert(reqwest::header::AUTHORIZATION, value);
+            }
+        }
+        let github = http::client_builder()
+            .user_agent("llama-package-service")
+            .timeout(Duration::from_secs(15))
+            .default_headers(headers)
+            .build()?;
+
+        let mut limiter = RateLimiter::new();
+        limiter.set_default_limit(REQUESTS_PER_HOST, Duration::from_secs(10));
+
+        Ok(Self {
+            client,
+            github,
+            cache: Cache::with_limits(CACHE_TTL, CacheLimits { max_entries: Some(CACHE_ENTRIES), max_bytes: None })
+                .named("links"),
+            limiter,
+        })
+    }
+
+    /// The checker shared by the whole run, created with the configured GitHub token on first use
+    pub fn shared(config: &Config) -> Result<&'static Self> {
+        SHARED.get_or_try_init(|| Self::new(config.github_token.as_deref()))
+    }
+
+    /// Checks every external link in the repository's README and `docs/` directory
+    pub async fn check_repository(&self, root: &Path) -> LinkReport {
+        let links = collect_doc_links(root);
+        let mut report = LinkReport { checked: links.len(), findings: Vec::new() };
+
+        for (url, files) in links {
+            let status = self.check(&url).await;
+            if status != LinkStatus::Ok {
+                report.findings.push(LinkFinding { url, files, status });
+            }
+        }
+        report
+    }
+
+    /// Checks a single URL, using the cached result when available
+    pub async fn check(&self, url: &str) -> LinkStatus {
+        if let Some(status) = self.cache.get(url).await {
+            return status;
+        }
+
+        let status = match github_repo(url) {
+            Some((owner, repo)) => self.check_github_repo(&owner, &repo).await,
+            None => self.check_http(url).await,
+        };
+        self.cache.set(url, status.clone()).await;
+        status
+    }
+
+    async fn check_http(&self, url: &str) -> LinkStatus {
+        let Ok(parsed) = url::Url::parse(url) else {
+            return LinkStatus::Dead { code: None, reason: "malformed URL".into() };
+        };
+        let host = parsed.host_str().unwrap_or_default().to_string();
+        self.limiter.acquire(&host).await;
+
+        let mut response = http::send(self.client.head(url)).await;
+        // Plenty of servers reject HEAD; give them a GET before calling the link dead
+        if matches!(&response, Ok(r) if r.status() == StatusCode::METHOD_NOT_ALLOWED || r.status() == StatusCode::FORBIDDEN) {
+            self.limiter.acquire(&host).await;
+            response = http::send(self.client.get(url)).await;
+        }
+
+        match response {
+            Ok(r) if r.status().is_redirection() => {
+                let location = r.headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|l| l.to_str().ok())
+                    .and_then(|l| parsed.join(l).ok());
+                // Redirects within a site (or to/from its www. alias) are routine
+                let same_site = |other: &str| other.trim_start_matches("www.") == host.trim_start_matches("www.");
+                match location {
+                    Some(location) if !location.host_str().is_some_and(same_site) => {
+                        LinkStatus::Redirected { location: location.to_string() }
+                    }
+                    _ => LinkStatus::Ok,
+                }
+            }
+            Ok(r) if r.status().is_success() => LinkStatus::Ok,
+            // Rate limiting and auth walls say nothing about whether the page exists
+            Ok(r) if r.status() == StatusCode::TOO_MANY_REQUESTS || r.status() == StatusCode::UNAUTHORIZED => LinkStatus::Ok,
+            Ok(r) => LinkStatus::Dead {
+                code: Some(r.status().as_u16()),
+                reason: r.status().canonical_reason().unwrap_or("error").to_string(),
+            },
+            Err(ProcessorError::Http(e)) if e.is_timeout() => LinkStatus::Dead { code: None, reason: "timed out".into() },
+            Err(_) => LinkStatus::Dead { code: None, reason: "unreachable".into() },
+        }
+    }
+
+    async fn check_github_repo(&self, owner: &str, repo: &str) -> LinkStatus {
+        self.limiter.acquire("github").await;
+        let url = format!("https://api.github.com/repos/{}/{}", owner, repo);
+        let response = match http::send(self.github.get(&url)).await {
+            Ok(response) => response,
+            Err(_) => return LinkStatus::Dead { code: None, reason: "unreachable".into() },
+        };
+
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                return LinkStatus::Dead { code: Some(404), reason: "repository not found".into() }
+            }
+            // Out of API quota: fall back to a plain request rather than reporting a false positive
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+                return self.check_http(&format!("https://github.com/{}/{}", owner, repo)).await
+            }
+            status if !status.is_success() => return LinkStatus::Ok,
+            _ => {}
+        }
+
+        let Ok(body) = response.json::<Value>().await else {
+            return LinkStatus::Ok;
+        };
+        let full_name = body["full_name"].as_str().unwrap_or_default();
+        if !full_name.is_empty() && !full_name.eq_ignore_ascii_case(&format!("{}/{}", owner, repo)) {
+            LinkStatus::RenamedRepo { new_name: full_name.to_string() }
+        } else if body["archived"].as_bool().unwrap_or(false) {
+            LinkStatus::ArchivedRepo
+        } else {
+            LinkStatus::Ok
+        }
+    }
+}
+
+/// Collects distinct external links from README files and the `docs/` tree
+///
+/// Returns each URL with the documentation files it appears in.
+pub fn collect_doc_links(root: &Path) -> BTreeMap<String, Vec<String>> {
+    let mut docs: Vec<PathBuf> = std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.file_name().is_some_and(|n| n.to_string_lossy().to_lowercase().starts_with("readme")))
+        .collect();
+
+    for dir in ["docs", "doc"] {
+        docs.extend(
+            WalkDir::new(root.join(dir))
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .filter(|e| matches!(
+                    e.path().extension().and_then(|x| x.to_str()),
+                    Some("md" | "markdown" | "rst" | "txt" | "adoc")
+                ))
+                .map(|e| e.into_path()),
+        );
+    }
+
+    let mut links: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for doc in docs {
+        let Ok(content) = std::fs::read_to_string(&doc) else { continue };
+        let rel = doc.strip_prefix(root).unwrap_or(&doc).display().to_string();
+        for url in extract_urls(&content) {
+            let files = links.entry(url).or_default();
+            if !files.contains(&rel) {
+                files.push(rel.clone());
+            }
+        }
+    }
+    links
+}
+
+/// Extracts external URLs from text, dropping trailing punctuation and placeholders
+pub fn extract_urls(content: &str) -> Vec<String> {
+    URL_RE.find_iter(content)
+        .map(|m| m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?', '*', '_']).to_string())
+        .filter(|url| !url.contains("localhost") && !url.contains("127.0.0.1") && !url.contains("example.com"))
+        .collect()
+}
+
+/// Returns `(owner, repo)` when the URL is the root of, or a path inside, a GitHub repository
+fn github_repo(url: &str) -> Option<(String, String)> {
+    let parsed = url::Url::parse(url).ok()?;
+    if parsed.host_str()? != "github.com" {
+        return None;
+    }
+    let mut segments = parsed.path_segments()?;
+    let owner = segments.next().filter(|s| !s.is_empty())?;
+    let repo = segments.next().filter(|s| !s.is_empty())?.trim_end_matches(".git");
+    // Site pages such as github.com/features/actions are not repositories
+    if ["features", "orgs", "topics", "sponsors", "marketplace", "settings", "about"].contains(&owner) {
+        return None;
+    }
+    Some((owner.to_string(), repo.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_extract_urls_trims_punctuation() {
+        let urls = extract_urls("See https://docs.rs/serde. Or (https://serde.rs/derive.html), not http://localhost:8000.");
+        assert_eq!(urls, vec!["https://docs.rs/serde", "https://serde.rs/derive.html"]);
+    }
+
+    #[test]
+    fn test_github_repo_detection() {
+        assert_eq!(
+            github_repo("https://github.com/rust-lang/rust/blob/master/README.md"),
+            Some(("rust-lang".into(), "rust".into()))
+        );
+        assert_eq!(github_repo("https://github.com/features/actions"), None);
+        assert_eq!(github_repo("https://github.com/rust-lang"), None);
+        assert_eq!(github_repo("https://gitlab.com/a/b"), None);
+    }
+
+    #[test]
+    fn test_collects_links_from_readme_and_docs() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("README.md"), "[site](https://a.dev) https://b.dev/x").unwrap();
+        std::fs::create_dir_all(dir.path().join("docs/guide")).unwrap();
+        std::fs::write(dir.path().join("docs/guide/intro.md"), "Visit https://a.dev").unwrap();
+        std::fs::write(dir.path().join("src.rs"), "// https://ignored.dev").unwrap();
+
+        let links = collect_doc_links(dir.path());
+        assert_eq!(links.len(), 2);
+        assert_eq!(links["https://a.dev"].len(), 2);
+        assert!(!links.contains_key("https://ignored.dev"));
+    }
+
+    #[test]
+    fn test_report_markdown_lists_findings() {
+        let report = LinkReport {
+            checked: 3,
+            findings: vec![LinkFinding {
+                url: "https://github.com/old/name".into(),
+                files: vec!["README.md".into()],
+                status: LinkStatus::RenamedRepo { new_name: "new/name".into() },
+            }],
+        };
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("1 of 3 external link(s)"));
+        assert!(markdown.contains("Repository moved to new/name"));
+    }
+}
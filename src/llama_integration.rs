@@ -1,4 +1,5 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use std::time::Duration;
@@ -21,13 +22,12 @@ pub struct LlamaResponse {
 }
 
 impl LlamaClient {
-    pub fn new(endpoint: String) -> Self {
-        let client = Client::builder()
+    pub fn new(endpoint: String) -> Result<Self> {
+        let client = http::client_builder()
             .timeout(Duration::from_secs(30))
-            .build()
-            .unwrap_or_else(|_| Client::new());
+            .build()?;
             
-        Self { client, endpoint }
+        Ok(Self { client, endpoint })
     }
 
     pub async fn generate(&self, prompt: &str) -> Result<String> {
@@ -274,7 +274,7 @@ impl LlamaUI {
                     let url = self.get_package_url("GitHub")?;
                     let pb = self.create_progress_bar();
                     
-                    match github::process_github_url(&url, &output_dir, &pb).await {
+                    match github::process_github_url(&url, &output_dir, &pb, config).await {
                         Ok(_) => self.print_success(&format!("Successfully processed GitHub URL: {}", url)),
                         Err(e) => self.print_error(&format!("Error processing GitHub URL: {}", e)),
                     }
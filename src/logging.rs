@@ -1,10 +1,33 @@
-use crate::error::Result;
+//! Console logging, and structured JSON logs written to a rotating file
+//!
+//! With `[logging] file` set, every event at or above `logging.level` is also
+//! appended to that file as one JSON object per line, so long-running servers
+//! leave logs that can be grepped or shipped to a log store. The file starts
+//! over every day and whenever it reaches `max_size_mb`; the previous file is
+//! renamed after the time of its last entry, such as
+//! `server.2024-05-01T23-59-58-120.log`, and only the newest `max_files` of
+//! those are kept.
+
+use crate::error::{ProcessorError, Result};
 use env_logger::{Builder, Env};
 use log::{self, LevelFilter};
-use chrono::Local;
-use std::io::Write;
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
 use yansi::Paint;
 
+/// Environment variable naming the JSON log file, taking precedence over `logging.file`
+pub const LOG_FILE_ENV_VAR: &str = "LLAMA_LOG_FILE";
+
 /// Initializes the application's logging system with the specified log level
 ///
 /// Valid log levels are: error, warn, info, debug, trace
@@ -64,6 +87,272 @@ pub fn parse_log_level(level: &str) -> LevelFilter {
     }
 }
 
+/// Writing structured logs to a file, the `[logging]` section of the config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// File JSON logs are appended to besides the console; `LLAMA_LOG_FILE` takes precedence
+    pub file: Option<PathBuf>,
+    /// Least severe level written to the file: error, warn, info, debug or trace
+    pub level: String,
+    /// Size at which the file starts over (0 for no limit)
+    pub max_size_mb: u64,
+    /// Start the file over when the day changes
+    pub daily: bool,
+    /// Rotated files kept besides the current one (0 keeps them all)
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self { file: None, level: "info".to_string(), max_size_mb: 100, daily: true, max_files: 7 }
+    }
+}
+
+impl LoggingConfig {
+    /// The file to write JSON logs to, from the environment or the config
+    pub fn file(&self) -> Option<PathBuf> {
+        std::env::var_os(LOG_FILE_ENV_VAR)
+            .filter(|file| !file.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| self.file.clone())
+    }
+}
+
+/// Layer appending JSON logs to the configured file, or `None` when no file is configured
+///
+/// Add it to a `tracing_subscriber::registry()` next to the console layer.
+pub fn file_layer<S>(config: &LoggingConfig) -> Result<Option<impl Layer<S>>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some(path) = config.file() else {
+        return Ok(None);
+    };
+    let level: tracing_subscriber::filter::LevelFilter = config.level.parse()
+        .map_err(|_| ProcessorError::Config(format!("Unknown log level '{}'", config.level)))?;
+    let file = RotatingFile::open(&path, config)?;
+    Ok(Some(JsonLayer::new(file).with_filter(level)))
+}
+
+/// Tracing layer writing each event as a JSON object on its own line
+///
+/// Objects carry `timestamp` (UTC), `level`, `target` and `message`, the
+/// event's other fields under `fields`, and the names of the spans it
+/// happened in under `spans`. Records from the `log` crate keep their target.
+pub struct JsonLayer<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write> JsonLayer<W> {
+    /// Layer writing to `writer`
+    pub fn new(writer: W) -> Self {
+        Self { writer: Mutex::new(writer) }
+    }
+}
+
+impl<S, W> Layer<S> for JsonLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: Write + Send + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+
+        let mut line = Map::new();
+        line.insert("timestamp".into(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+        line.insert("level".into(), metadata.level().as_str().into());
+        line.insert("target".into(), fields.log_target.take().unwrap_or_else(|| metadata.target().to_string()).into());
+        line.insert("message".into(), fields.message.take().unwrap_or_default().into());
+        if !fields.values.is_empty() {
+            line.insert("fields".into(), Value::Object(std::mem::take(&mut fields.values)));
+        }
+        if let Some(scope) = ctx.event_scope(event) {
+            let spans: Vec<Value> = scope.from_root().map(|span| span.name().into()).collect();
+            line.insert("spans".into(), spans.into());
+        }
+
+        let mut line = Value::Object(line).to_string();
+        line.push('\n');
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        // Logging has nowhere to report its own failures
+        let _ = writer.write_all(line.as_bytes());
+        let _ = writer.flush();
+    }
+}
+
+/// Fields of one event, collected as JSON values
+#[derive(Default)]
+struct JsonFields {
+    message: Option<String>,
+    log_target: Option<String>,
+    values: Map<String, Value>,
+}
+
+impl JsonFields {
+    fn insert(&mut self, field: &Field, value: Value) {
+        match field.name() {
+            "message" => self.message = Some(value.as_str().map_or_else(|| value.to_string(), str::to_string)),
+            "log.target" => self.log_target = value.as_str().map(str::to_string),
+            // The rest of what the `log` bridge adds repeats the target
+            name if name.starts_with("log.") => {}
+            name => {
+                self.values.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
+
+/// Timestamp format in the names of rotated log files
+const ROTATED_STAMP: &str = "%Y-%m-%dT%H-%M-%S-%3f";
+
+/// Log file that starts over by day and by size, keeping a number of old files
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// Local time of the last write, which names the file once it is rotated
+    last_write: DateTime<Local>,
+    /// Whether the last write finished a line; files only start over between lines
+    at_line_start: bool,
+    max_size: u64,
+    daily: bool,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending, creating it and its directory if needed
+    pub fn open(path: &Path, config: &LoggingConfig) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let last_write = metadata.modified().map_or_else(|_| Local::now(), DateTime::<Local>::from);
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size: metadata.len(),
+            last_write,
+            at_line_start: true,
+            max_size: config.max_size_mb.saturating_mul(1024 * 1024),
+            daily: config.daily,
+            max_files: config.max_files,
+        })
+    }
+
+    /// Starts a new file if the day changed or `incoming` bytes would make it too big
+    fn rotate_if_due(&mut self, incoming: usize, now: DateTime<Local>) -> io::Result<()> {
+        let new_day = self.daily && now.date_naive() != self.last_write.date_naive();
+        let too_big = self.max_size > 0 && self.size > 0 && self.size + incoming as u64 > self.max_size;
+        if self.size == 0 || !self.at_line_start || !(new_day || too_big) {
+            return Ok(());
+        }
+        let rotated = self.rotated_path(self.last_write);
+        fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.prune()
+    }
+
+    /// Where the current file goes when rotated after a last write at `time`
+    ///
+    /// A file already rotated in the same millisecond is not overwritten; the
+    /// new one is stamped a millisecond later.
+    fn rotated_path(&self, mut time: DateTime<Local>) -> PathBuf {
+        let (stem, extension) = self.name_parts();
+        loop {
+            let path = self.path.with_file_name(format!("{}.{}{}", stem, time.format(ROTATED_STAMP), extension));
+            if !path.exists() {
+                return path;
+            }
+            time += chrono::Duration::milliseconds(1);
+        }
+    }
+
+    /// The file name before and after where rotated files put their timestamp
+    fn name_parts(&self) -> (String, String) {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let extension = self.path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+        (stem, extension)
+    }
+
+    /// Deletes the oldest rotated files beyond `max_files`
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+        let (stem, extension) = self.name_parts();
+        let prefix = format!("{}.", stem);
+        let dir = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let current = self.path.file_name().unwrap_or_default();
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name() != current)
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix(&extension))
+                    .is_some_and(|stamp| NaiveDateTime::parse_from_str(stamp, ROTATED_STAMP).is_ok())
+            })
+            .map(|entry| entry.path())
+            .collect();
+        // Timestamps sort oldest first
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for old in &rotated[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = Local::now();
+        self.rotate_if_due(buf.len(), now)?;
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        self.last_write = now;
+        if written > 0 {
+            self.at_line_start = buf[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -77,4 +366,49 @@ mod tests {
         assert_eq!(parse_log_level("trace"), LevelFilter::Trace);
         assert_eq!(parse_log_level("invalid"), LevelFilter::Info);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_json_logs_rotate_by_size_and_day() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("server.log");
+        let config = LoggingConfig { file: Some(path.clone()), max_size_mb: 0, max_files: 2, ..LoggingConfig::default() };
+        let mut file = RotatingFile::open(&path, &config).unwrap();
+        // A few hundred bytes stand in for megabytes
+        file.max_size = 300;
+
+        let subscriber = tracing_subscriber::registry().with(JsonLayer::new(file));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("job");
+            let _entered = span.enter();
+            for n in 0..8 {
+                tracing::info!(job_id = "abc", attempt = n, "processing {}", "owner/repo");
+            }
+        });
+
+        let first: Value = serde_json::from_str(fs::read_to_string(&path).unwrap().lines().next().unwrap()).unwrap();
+        assert_eq!(first["level"], "INFO");
+        assert_eq!(first["message"], "processing owner/repo");
+        assert_eq!(first["fields"]["job_id"], "abc");
+        assert_eq!(first["spans"], serde_json::json!(["job"]));
+        let logs = || {
+            let mut names: Vec<String> = fs::read_dir(dir.path().join("logs")).unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        // The oldest rotated files are gone
+        let names = logs();
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert!(names[0].starts_with("server.") && names[0].ends_with(".log") && names[2] == "server.log");
+
+        // A new day starts a new file even when it is small
+        let mut file = RotatingFile::open(&path, &LoggingConfig { max_files: 0, ..config }).unwrap();
+        let tomorrow = Local::now() + chrono::Duration::days(1);
+        file.rotate_if_due(1, tomorrow).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert_eq!(logs().len(), 4);
+    }
+}
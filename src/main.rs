@@ -1,15 +1,46 @@
 use llamapackageservice::{
     Config,
-    error::{ProcessorError, Result},
-    processors::{self, github, pypi, npm, crates, ProcessorFactory, common},
-    parallel::ParallelProcessor,
-    cache::{StringCache, Cache},
-    output_organizer::{self, list_output_files, organize_output, generate_index},
+    error::{exit_code, ProcessorError, Result},
+    processors::{self, github, pypi, npm, crates, ProcessorFactory, common, plugin},
+    parallel::{ParallelProcessor, Task},
+    pipeline::{self, StageLimits},
+    logging::{self, LoggingConfig},
+    config::{self, ResolvedConfig},
+    progress::{self, format_duration, ProgressAggregator},
+    cache::{self, StringCache, Cache, CacheStats, DEFAULT_CACHE_DIR},
+    cache::backend::{CacheBackendKind, CacheConfig, ClearFilter},
+    output_organizer::{self, list_output_files, organize_output, generate_index, NamingScheme, OverwritePolicy},
+    compression::{self, Compression},
+    manifest::{self, RunManifest},
+    retention::{self, RetentionPolicy},
+    batch_report::{self, BatchResults, UrlResult},
+    checkpoint::{Checkpoint, ResumeMode},
+    cancellation,
+    output::storage,
+    encryption,
+    metrics,
+    rate_limiter::{self, UpstreamLimits},
+    http::{self, CircuitBreaker},
+    server::{self, ServeOptions},
+    worker::Worker,
+    api::{self, JobList, JobListParams},
+    agents::{batch, AnalysisRequest, AnalysisType, OpenAIAgent},
+    agents::conversation::{ConversationStore, CONVERSATIONS_DB_FILE},
+    analytics::compare::Comparison,
+    analytics::history::{self, MetricsHistory, METRICS_DB_FILE},
+    chunking,
+    embeddings::{self, ExportFormat},
 };
+use futures_util::StreamExt;
 use std::path::{PathBuf, Path};
-use log::{info, error};
+use std::net::IpAddr;
+use log::{info, warn};
 use colored::*;
-use clap::{Parser, Subcommand, Args};
+use clap::{ArgAction, Parser, Subcommand, Args, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio;
 use yansi::Paint;
 use std::process;
@@ -18,83 +49,925 @@ use chrono;
 use std::time::Duration;
 use tokio::time::sleep;
 use std::io::{self, Write};
-use tokio::process::Command as TokioCommand;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 use std::process::Stdio;
 
 mod llama_ui;
 use llama_ui::LlamaUI;
 
+/// Set by `--quiet` to silence [`status!`] messages
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Prints a progress or status message to stderr unless `--quiet` was given,
+/// keeping stdout for results
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Set by `--format json`, whose report is then the only thing on stdout
+static JSON_REPORT: AtomicBool = AtomicBool::new(false);
+
+/// Prints a command's result to stdout, or to stderr when stdout carries the
+/// `--format json` report
+macro_rules! output {
+    ($($arg:tt)*) => {
+        if JSON_REPORT.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Exit codes, listed at the end of `--help`
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+    0  Success
+    1  Internal error
+    2  Invalid arguments, input or configuration
+    3  Network failure or error response from a package registry
+    4  Rate limit exhausted
+    5  Batch finished with some URLs failed
+  130  Interrupted with Ctrl-C; partial output was saved";
+
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Cli {
-    /// URL to process directly (skip interactive mode)
-    #[arg(short, long)]
-    url: Option<String>,
-    
     /// Output directory
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     output: Option<PathBuf>,
     
-    /// Run in legacy interactive mode with UI
-    #[arg(short, long)]
-    legacy_ui: bool,
+    /// Config file to read instead of the default one (defaults to LLAMA_CONFIG)
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+    
+    /// Number of worker threads for extraction, hashing and analysis (defaults to all cores)
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+    
+    #[command(flatten)]
+    output_options: OutputOptions,
+    
+    /// Probe the external links in processed repositories' documentation
+    #[arg(long, global = true)]
+    check_links: bool,
+    
+    /// Fail when a processed repository's README scores below this documentation health (0-100)
+    #[arg(long, value_name = "SCORE", global = true, value_parser = clap::value_parser!(u8).range(0..=100))]
+    docs_min_score: Option<u8>,
+    
+    /// Only print results and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    
+    /// Log more detail to stderr (-v for info, -vv for debug, -vvv for trace)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    
+    /// Print results as colored text or as a single JSON document
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+    format: OutputFormat,
+    
+    /// Deprecated: use `process <URL>`
+    #[arg(short, long, hide = true)]
+    url: Option<String>,
     
-    /// Generate index file after processing
-    #[arg(short = 'x', long)]
-    index: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize memory limits
-    llamapackageservice::limit_memory_usage();
+/// How generated artifacts are written and how long they are kept
+#[derive(Args)]
+struct OutputOptions {
+    /// Compress generated artifacts (none, gzip or zstd)
+    #[arg(long, value_name = "ALGORITHM", global = true)]
+    compress: Option<Compression>,
+    
+    /// Output file naming (timestamped, or stable per package and version)
+    #[arg(long, value_name = "SCHEME", global = true)]
+    naming: Option<NamingScheme>,
+    
+    /// What to do when an output file already exists (overwrite, skip or version)
+    #[arg(long, value_name = "POLICY", global = true)]
+    on_conflict: Option<OverwritePolicy>,
+    
+    /// Write output even when identical content was already saved
+    #[arg(long, global = true)]
+    no_dedupe: bool,
+    
+    /// Delete artifacts older than this many days
+    #[arg(long, value_name = "DAYS", global = true)]
+    max_age_days: Option<u64>,
+    
+    /// Keep the output directory under this size (e.g. 500MB, 2GB), deleting the oldest artifacts first
+    #[arg(long, value_name = "SIZE", value_parser = retention::parse_size, global = true)]
+    max_total_size: Option<u64>,
+    
+    /// Keep at most this many versions of each package
+    #[arg(long, value_name = "COUNT", global = true)]
+    max_versions: Option<usize>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process a single URL or local path
+    Process {
+        /// URL or local path to process
+        url: String,
+        
+        /// Stream the processed content to stdout instead of writing output files
+        #[arg(long)]
+        stdout: bool,
+        
+        /// Generate the index after processing
+        #[arg(short = 'x', long)]
+        index: bool,
+        
+        /// Use this processor instead of detecting one from the URL
+        /// (local, github, pypi, pypi-profile, npm, crates, go, or a plugin name)
+        #[arg(long, value_name = "NAME")]
+        processor: Option<String>,
+    },
+    /// Process a list of URLs, one per line, from a file or stdin
+    Batch {
+        /// File listing the URLs; reads stdin when omitted or "-"
+        input: Option<PathBuf>,
+        
+        /// Number of URLs processed at the same time
+        #[arg(long, default_value_t = 5)]
+        concurrency: usize,
+        
+        /// Where to write the per-URL results as JSON (defaults to <output>/<timestamp>_batch_results.json)
+        #[arg(long, value_name = "FILE")]
+        results: Option<PathBuf>,
+        
+        /// Continue an interrupted run, skipping URLs that already finished
+        #[arg(long)]
+        resume: bool,
+        
+        /// Continue an earlier run, processing again only the URLs that failed or never finished
+        #[arg(long, conflicts_with = "resume")]
+        retry_failed: bool,
+        
+        /// Use this processor for every URL instead of detecting one per URL
+        #[arg(long, value_name = "NAME")]
+        processor: Option<String>,
+    },
+    /// Download a GitHub repository and save its raw and processed content
+    Analyze {
+        /// Repository URL
+        repo_url: String,
+    },
+    /// Run the REST API server in the foreground until interrupted
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = server::DEFAULT_PORT)]
+        port: u16,
+        
+        /// Address to bind to (0.0.0.0 to accept connections from other hosts)
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: IpAddr,
+        
+        /// Number of runtime worker threads (defaults to one per core)
+        #[arg(long)]
+        workers: Option<usize>,
+        
+        /// Also serve the gRPC job API on this port (builds with the `grpc` feature;
+        /// defaults to `GRPC_PORT`)
+        #[arg(long)]
+        grpc_port: Option<u16>,
+        
+        /// Instead of serving, process jobs for the API server at this URL, which
+        /// needs `server.remote_workers` (defaults to LLAMA_API_URL)
+        #[arg(long, value_name = "URL", num_args = 0..=1, default_missing_value = "")]
+        worker: Option<String>,
+        
+        /// API key or JWT with the work scope for --worker (defaults to LLAMA_API_KEY)
+        #[arg(long, value_name = "KEY", requires = "worker")]
+        api_key: Option<String>,
+    },
+    /// Inspect or clear the persistent cache
+    Cache {
+        /// Cache directory (defaults to `cache.dir`)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Create, inspect, edit and validate the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Search the text of generated artifacts
+    Search {
+        /// Text to look for (case-insensitive)
+        query: String,
+        
+        /// Maximum number of matching lines to print
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Generate the index and static site of processed packages
+    Index,
+    /// Start the interactive prompt (the default when no command is given)
+    Interactive {
+        /// Use the legacy interactive UI
+        #[arg(short, long)]
+        legacy_ui: bool,
+    },
+    /// Process a bundled demo repository offline, generate every output format and open the web UI
+    Quickstart {
+        /// Do not start the web server
+        #[arg(long)]
+        no_server: bool,
+        
+        /// Do not open the web UI in a browser
+        #[arg(long)]
+        no_open: bool,
+    },
+    /// Delete output that falls outside the retention limits
+    Clean {
+        /// List what would be deleted without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Build a browsable static site from the output directory
+    Site {
+        /// Directory to write the site to (defaults to <output>/_site); must be empty or hold an earlier site
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Decrypt an encrypted artifact using LLAMA_OUTPUT_KEY
+    Decrypt {
+        /// Encrypted artifact (*.enc)
+        file: PathBuf,
+        
+        /// Write the plaintext here instead of stdout
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// List external processor plugins and the URL prefixes they handle
+    Plugins,
+    /// Inspect jobs on a running API server
+    Jobs {
+        #[command(subcommand)]
+        command: JobsCommand,
+    },
+    /// Ask an AI model about a repository, printing the answer as it is written (needs OPENAI_API_KEY or a local Ollama)
+    Explain {
+        /// Repository URL or local path
+        target: String,
+        /// What to write: documentation, security, code_review, examples, api_docs, or a custom prompt
+        #[arg(long = "type", value_name = "TYPE", default_value = "documentation")]
+        analysis_type: String,
+        /// Extra instructions for the model
+        #[arg(long, value_name = "TEXT")]
+        context: Option<String>,
+        /// Summarize a repository too large for the context file by file and directory by directory
+        #[arg(long)]
+        summarize: bool,
+    },
+    /// Ask an AI model a question about a repository, keeping the conversation for follow-up questions
+    Chat {
+        /// The question
+        message: String,
+        /// Repository URL or local path to start a conversation about
+        #[arg(long, value_name = "TARGET", required_unless_present = "resume", conflicts_with = "resume")]
+        repo: Option<String>,
+        /// ID of a stored conversation to continue
+        #[arg(long, value_name = "ID")]
+        resume: Option<String>,
+        /// Also export the repository's chunk embeddings and their metadata to this directory
+        #[arg(long, value_name = "DIR", conflicts_with = "resume")]
+        export_embeddings: Option<PathBuf>,
+        /// Format of the embedding export: npy (with metadata.jsonl) or jsonl
+        #[arg(long, value_name = "FORMAT", default_value = "npy")]
+        embedding_format: ExportFormat,
+    },
+    /// Analyze a list of repositories, one per line, through the OpenAI Batch API at half the price
+    AnalyzeBatch {
+        /// File listing the repositories; reads stdin when omitted or "-"
+        input: Option<PathBuf>,
+        /// What to write: documentation, security, code_review, examples, api_docs, or a custom prompt
+        #[arg(long = "type", value_name = "TYPE", default_value = "documentation")]
+        analysis_type: String,
+        /// Extra instructions for the model
+        #[arg(long, value_name = "TEXT")]
+        context: Option<String>,
+        /// Seconds between checks on the batch
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        poll_interval: u64,
+        /// Submit the batch and return; run the same command again later to collect the analyses
+        #[arg(long)]
+        no_wait: bool,
+    },
+    /// Compare and track metrics of processed repositories
+    Analytics {
+        #[command(subcommand)]
+        command: AnalyticsCommand,
+    },
+}
+
+/// Environment variable with the base URL of the API server `jobs` talks to
+const API_URL_ENV_VAR: &str = "LLAMA_API_URL";
+
+/// Environment variable with the API key or JWT `jobs` sends
+const API_KEY_ENV_VAR: &str = "LLAMA_API_KEY";
+
+#[derive(Subcommand)]
+enum JobsCommand {
+    /// List jobs, newest first, filtered by status, source, age or URL
+    List {
+        /// Base URL of the server (defaults to LLAMA_API_URL, then http://127.0.0.1:8000)
+        #[arg(long = "server", value_name = "URL")]
+        server_url: Option<String>,
+        
+        /// API key or JWT (defaults to LLAMA_API_KEY)
+        #[arg(long, value_name = "KEY")]
+        api_key: Option<String>,
+        
+        /// Only jobs with these statuses (queued, processing, completed, failed, cancelled)
+        #[arg(long, value_delimiter = ',')]
+        status: Vec<String>,
+        
+        /// Only jobs whose URL type contains this text (github, pypi, npm, crate, go, local, analysis, ...)
+        #[arg(long)]
+        source: Option<String>,
+        
+        /// Only jobs submitted since this RFC 3339 time or YYYY-MM-DD date
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+        
+        /// Only jobs whose URL contains this text (case-insensitive)
+        #[arg(long, value_name = "TEXT")]
+        search: Option<String>,
+        
+        /// Sort by created, updated or url
+        #[arg(long)]
+        sort: Option<String>,
+        
+        /// Sort order, desc or asc
+        #[arg(long)]
+        order: Option<String>,
+        
+        /// Jobs per page (at most 500)
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        
+        /// Continue from the page token printed after the previous page
+        #[arg(long, value_name = "TOKEN")]
+        page: Option<String>,
+        
+        /// Fetch every page instead of only the first
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AnalyticsCommand {
+    /// Compare two or more processed targets side by side, writing a Markdown and a JSON report
+    Compare {
+        /// Repository URLs or paths, as they were processed
+        #[arg(required = true, num_args = 2..)]
+        targets: Vec<String>,
+    },
+    /// Show the metrics recorded for each run of a target and how they trend
+    History {
+        /// Repository URL or path, as it was processed
+        target: String,
+        /// Most recent runs to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Show the number of entries and the size on disk, and cached responses per processor
+    Stats,
+    /// Delete cached entries; with filters, only the matching cached responses
+    Clear {
+        /// Only responses fetched by this processor, such as pypi, npm, crates or github
+        #[arg(long)]
+        processor: Option<String>,
+        /// Only responses stored at least this long ago, such as 7d, 12h or 30m
+        #[arg(long, value_name = "AGE", value_parser = cache::backend::parse_age)]
+        older_than: Option<Duration>,
+    },
+    /// Fetch registry metadata for the URLs in a file (one per line, `-` for stdin) ahead of a batch run
+    Warm {
+        /// File of GitHub, PyPI, npm and crates.io URLs
+        file: PathBuf,
+        /// Requests in flight at once; each registry's rate limit still applies
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Write a starter config file listing every setting at its default
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the effective configuration (file, environment and flags merged) as TOML
+    Show {
+        /// Print each setting with the layer it came from: default, file, env or flag
+        #[arg(long)]
+        sources: bool,
+    },
+    /// Print one setting by dotted key, e.g. output_config.compression
+    Get {
+        /// Dotted key of the setting
+        key: String,
+    },
+    /// Set one setting in the config file by dotted key
+    Set {
+        /// Dotted key of the setting
+        key: String,
+        /// New value, as TOML (true, 8, ["a", "b"]) or a plain string
+        value: String,
+    },
+    /// Check that the config file loads, directories are writable and tokens are accepted
+    Validate,
+    /// Print where the configuration file is read from
+    Where,
+}
+
+/// How command results are printed
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum OutputFormat {
+    /// Colored text for people
+    #[default]
+    Text,
+    /// A single JSON document on stdout; progress and messages go to stderr
+    Json,
+}
+
+/// Structured result of a command, printed with `--format json`
+#[derive(Debug, Default, Serialize)]
+struct CommandReport {
+    /// Command that ran
+    command: &'static str,
+    /// Whether the command did everything it was asked to
+    success: bool,
+    /// URL, path or query the command worked on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    /// Files written or selected by the command
+    artifacts: Vec<PathBuf>,
+    /// Counters such as artifact count, bytes and duration
+    metrics: BTreeMap<&'static str, u64>,
+    /// Error messages
+    errors: Vec<String>,
+    /// Process exit code; see `--help` for their meaning
+    exit_code: i32,
+    /// Command-specific results
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    details: serde_json::Value,
+    /// Hit, miss and size counters of the caches used during the run
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    caches: BTreeMap<String, CacheStats>,
+}
+
+impl CommandReport {
+    fn new(command: &'static str) -> Self {
+        Self { command, success: true, ..Default::default() }
+    }
     
-    // Attempt to launch the web server in the background (if not already running)
-    if std::env::var("LLAMA_DISABLE_SERVER").is_err() {
-        if let Err(e) = spawn_server_background() {
-            eprintln!("[WARNING] Unable to launch embedded web server: {}", e);
+    /// Exit code for the outcome, treating an unexplained failure as internal
+    fn exit_code(&self) -> i32 {
+        match (self.success, self.exit_code) {
+            (true, _) => exit_code::SUCCESS,
+            (false, exit_code::SUCCESS) => exit_code::INTERNAL,
+            (false, code) => code,
         }
     }
     
+    /// Adds the artifacts listed in the manifest of the run that just finished
+    fn finish_run(&mut self, output_dir: &Path) {
+        match manifest::finish_run(output_dir) {
+            Ok(path) => {
+                status!("Run manifest written to {}", path.display());
+                if let Ok(run) = RunManifest::load(&path) {
+                    self.metrics.insert("artifact_bytes", run.artifacts.iter().map(|a| a.size).sum());
+                    if let Some(usage) = &run.ai_usage {
+                        self.metrics.insert("ai_prompt_tokens", usage.prompt_tokens);
+                        self.metrics.insert("ai_completion_tokens", usage.completion_tokens);
+                    }
+                    self.artifacts.extend(run.artifacts.into_iter().map(|a| output_dir.join(a.path)));
+                }
+                self.artifacts.push(path);
+            }
+            Err(e) => warn!("Failed to write run manifest: {}", e),
+        }
+        self.metrics.insert("artifacts", self.artifacts.len() as u64);
+    }
+}
+
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Process { .. } => "process",
+            Command::Batch { .. } => "batch",
+            Command::Analyze { .. } => "analyze",
+            Command::Serve { .. } => "serve",
+            Command::Cache { .. } => "cache",
+            Command::Config { .. } => "config",
+            Command::Search { .. } => "search",
+            Command::Index => "index",
+            Command::Interactive { .. } => "interactive",
+            Command::Quickstart { .. } => "quickstart",
+            Command::Clean { .. } => "clean",
+            Command::Site { .. } => "site",
+            Command::Decrypt { .. } => "decrypt",
+            Command::Plugins => "plugins",
+            Command::Jobs { .. } => "jobs",
+            Command::Explain { .. } => "explain",
+            Command::Chat { .. } => "chat",
+            Command::AnalyzeBatch { .. } => "analyze-batch",
+            Command::Analytics { .. } => "analytics",
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    // Initialize memory limits
+    llamapackageservice::limit_memory_usage();
+    
     // Parse command line arguments
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    if let Some(path) = cli.config.take() {
+        config::set_config_path(path);
+    }
+    // A broken config file is reported once the command runs
+    let logging = ResolvedConfig::resolve().map(|resolved| resolved.config.logging).unwrap_or_default();
+    init_logging(cli.quiet, cli.verbose, &logging);
+    let command = match (cli.command.take(), cli.url.take()) {
+        (Some(command), _) => command,
+        (None, Some(url)) => {
+            warn!("--url is deprecated; use `process {}`", url);
+            Command::Process { url, stdout: false, index: false, processor: None }
+        }
+        (None, None) => Command::Interactive { legacy_ui: false },
+    };
+    
+    // `serve --workers` sizes the runtime the API runs on
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if let Command::Serve { workers: Some(workers), .. } = &command {
+        runtime.worker_threads((*workers).max(1));
+    }
+    runtime.build()?.block_on(execute(cli, command))
+}
+
+/// Run `command` and report its outcome in the requested format, exiting with its exit code
+async fn execute(cli: Cli, command: Command) -> Result<()> {
+    if cli.format == OutputFormat::Text {
+        let code = match run(cli, command).await {
+            Ok(report) => report.exit_code(),
+            Err(e) => {
+                eprintln!("{} {}", "[ERROR]".bright_red(), e);
+                e.exit_code()
+            }
+        };
+        // Distinct exit codes per kind of failure, so scripts can tell
+        if code != exit_code::SUCCESS {
+            process::exit(code);
+        }
+        return Ok(());
+    }
     
-    // Create default configuration
-    let output_dir = cli.output.unwrap_or_else(|| PathBuf::from("./output"));
-    let config = Config::new(output_dir.clone());
+    JSON_REPORT.store(true, Ordering::Relaxed);
+    let name = command.name();
+    let result = match command {
+        Command::Interactive { .. } => Err(ProcessorError::Validation("the interactive prompt has no JSON output".into())),
+        Command::Process { stdout: true, .. } => Err(ProcessorError::Validation("--stdout cannot be combined with --format json".into())),
+        Command::Decrypt { out: None, .. } => Err(ProcessorError::Validation("decrypt needs --out with --format json".into())),
+        // Everything printed along the way goes to stderr; stdout carries only the report
+        command => run(cli, command).await,
+    };
+    let mut report = result.unwrap_or_else(|e| CommandReport {
+        success: false,
+        errors: vec![e.to_string()],
+        exit_code: e.exit_code(),
+        ..CommandReport::new(name)
+    });
+    report.exit_code = report.exit_code();
+    report.caches = metrics::cache_stats();
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if report.exit_code != exit_code::SUCCESS {
+        process::exit(report.exit_code);
+    }
+    Ok(())
+}
+
+/// Log to stderr at the level picked by `-q`/`-v`, unless RUST_LOG says otherwise,
+/// and to the JSON log file if one is configured
+fn init_logging(quiet: bool, verbose: u8, logging: &LoggingConfig) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    common::set_progress_hidden(quiet);
+    let directives = match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        _ => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(directives));
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_target(false)
+        .with_filter(filter);
+    let file = logging::file_layer(logging).unwrap_or_else(|e| {
+        eprintln!("{} Not writing the log file: {}", "[WARNING]".bright_yellow(), e);
+        None
+    });
+    let _ = tracing_subscriber::registry().with(console).with(file).try_init();
+}
+
+/// Apply the output flags on top of the configured output settings
+fn apply_output_options(config: &mut ResolvedConfig, options: &OutputOptions) -> Result<()> {
+    if let Some(compression) = options.compress {
+        config.set_from_flag("output_config.compression", compression, "--compress")?;
+    }
+    if let Some(scheme) = options.naming {
+        config.set_from_flag("output_config.naming.scheme", scheme, "--naming")?;
+    }
+    if let Some(policy) = options.on_conflict {
+        config.set_from_flag("output_config.naming.policy", policy, "--on-conflict")?;
+    }
+    if options.no_dedupe {
+        config.set_from_flag("output_config.naming.dedupe", false, "--no-dedupe")?;
+    }
+    if let Some(days) = options.max_age_days {
+        config.set_from_flag("output_config.retention.max_age_days", days, "--max-age-days")?;
+    }
+    if let Some(bytes) = options.max_total_size {
+        config.set_from_flag("output_config.retention.max_total_bytes", bytes, "--max-total-size")?;
+    }
+    if let Some(versions) = options.max_versions {
+        config.set_from_flag("output_config.retention.max_versions", versions, "--max-versions")?;
+    }
+    Ok(())
+}
+
+/// Set up the run from the global flags and execute `command`
+async fn run(cli: Cli, command: Command) -> Result<CommandReport> {
+    let started = std::time::Instant::now();
+    let mut report = CommandReport::new(command.name());
+    
+    // Size the worker pool used by the CPU-bound hot paths
+    llamapackageservice::perf::configure_threads(cli.threads)?;
+    
+    // Merge the config file, environment and flags; config management has to
+    // keep working when the file itself is broken
+    let config = ResolvedConfig::resolve().and_then(|mut config| {
+        if let Some(output) = &cli.output {
+            config.set_from_flag("output_dir", output, "--output")?;
+        }
+        apply_output_options(&mut config, &cli.output_options)?;
+        if cli.check_links {
+            config.set_from_flag("processing.check_links", true, "--check-links")?;
+        }
+        if let Some(score) = cli.docs_min_score {
+            config.set_from_flag("processing.docs_min_score", score, "--docs-min-score")?;
+        }
+        Ok(config)
+    });
+    if let Command::Config { command } = &command {
+        return run_config(command, config).await;
+    }
+    // Report every problem with the settings now rather than one at a time mid-run
+    let config = config?;
+    config.check()?;
+    let config = config.config;
+    http::set_proxy(&config.proxy)?;
+    http::set_tls(&config.tls)?;
+    let output_dir = config.output_dir.clone();
+    compression::set_output_compression(config.output_config.compression);
+    output_organizer::set_output_naming(config.output_config.naming);
+    let retention_policy = config.output_config.retention;
+    retention::set_retention_policy(retention_policy);
+    // Pace outbound requests by each host's budget and skip hosts that keep failing
+    rate_limiter::set_upstream_limits(UpstreamLimits::from_config(&config.rate_limits));
+    http::set_circuit_breaker(CircuitBreaker::new(config.circuit_breaker.clone()));
+    // Keep batches from downloading faster than they can analyze
+    pipeline::set_stage_limits(StageLimits::from_config(&config.processing));
+    plugin::set_describe_timeout(Duration::from_secs(config.processing.plugin_describe_timeout_secs));
+    
+    // Commands that never touch the output directory
+    match &command {
+        // Pipe mode leaves the output directory untouched
+        Command::Process { url, stdout: true, processor, .. } => {
+            stream_to_stdout(url, processor.as_deref(), &config).await?;
+            return Ok(report);
+        }
+        Command::Cache { dir, command } => {
+            let cache_config = CacheConfig { dir: dir.clone().unwrap_or_else(|| config.cache.dir.clone()), ..config.cache.clone() };
+            return run_cache(&cache_config, command, config.github_token.as_deref()).await;
+        }
+        Command::Plugins => return Ok(run_plugins()),
+        Command::Jobs { command } => return run_jobs(command).await,
+        Command::Explain { target, analysis_type, context, summarize } => {
+            return run_explain(target, analysis_type, context.clone(), *summarize, &config).await;
+        }
+        Command::Chat { message, repo, resume, export_embeddings, embedding_format } => {
+            let export = export_embeddings.as_deref().map(|dir| (dir, *embedding_format));
+            return run_chat(message, repo.as_deref(), resume.as_deref(), export, &config).await;
+        }
+        Command::AnalyzeBatch { input, analysis_type, context, poll_interval, no_wait } => {
+            let poll_interval = (!*no_wait).then(|| Duration::from_secs((*poll_interval).max(1)));
+            return run_analyze_batch(input.as_deref(), analysis_type, context.clone(), poll_interval, &config).await;
+        }
+        _ => {}
+    }
     
     // Create output directory if it doesn't exist
     tokio::fs::create_dir_all(&config.output_dir).await?;
     
-    // Process according to mode
-    if let Some(url) = cli.url {
-        // Process URL directly if provided
-        process_url(&url, &config).await?;
-    } else if cli.legacy_ui {
-        // Use the legacy UI if explicitly requested
-        let ui = LlamaUI::new();
-        ui.run(&config).await?;
-    } else {
-        // Use the new simple interactive mode
-        run_simple_interactive(&config).await?;
+    // Mirror artifacts to remote object storage when configured
+    if let Some(backend) = storage::from_config(&config.output_config.storage.clone().with_env()?)? {
+        storage::set_output_storage(backend, &config.output_dir);
+    }
+    
+    // Reuse API responses fetched recently, here or by other instances
+    share_response_cache(&config)?;
+    
+    // Encrypt artifacts at rest when a key is configured
+    let key = config.output_config.encryption.resolve_key().map_err(ProcessorError::Config)?;
+    encryption::set_output_encryption(key);
+    
+    // Ctrl-C stops long runs gracefully, keeping what was processed so far
+    if matches!(command, Command::Process { .. } | Command::Batch { .. } | Command::Analyze { .. } | Command::Serve { worker: Some(_), .. }) {
+        cancellation::install_ctrl_c_handler();
     }
     
+    let index_requested = match command {
+        Command::Process { index, .. } => index,
+        Command::Interactive { .. } | Command::Analyze { .. } => false,
+        Command::Quickstart { no_server, no_open } => {
+            return run_quickstart(&config, no_server, no_open).await;
+        }
+        Command::Clean { dry_run } => {
+            return run_clean(&output_dir, &retention_policy, dry_run);
+        }
+        Command::Site { out } => {
+            let index = llamapackageservice::site::build(&output_dir, out.as_deref())?;
+            status!("{} Site generated at {}", "[SUCCESS]".bright_green(), index.display());
+            report.artifacts.push(index);
+            return Ok(report);
+        }
+        Command::Index => {
+            output_organizer::generate_index(&output_dir)?;
+            report.artifacts.push(output_dir.join("_index").join("index.json"));
+            return Ok(report);
+        }
+        Command::Search { query, limit } => {
+            return run_search(&output_dir, &query, limit);
+        }
+        Command::Analytics { command } => {
+            return run_analytics(&output_dir, command).await;
+        }
+        Command::Serve { worker: Some(server_url), api_key, .. } => {
+            let server_url = Some(server_url).filter(|url| !url.is_empty())
+                .or_else(|| std::env::var(API_URL_ENV_VAR).ok())
+                .ok_or_else(|| ProcessorError::Validation(format!("--worker needs the server's URL or {}", API_URL_ENV_VAR)))?;
+            let api_key = api_key.or_else(|| std::env::var(API_KEY_ENV_VAR).ok());
+            status!("{} Processing jobs for {} (Ctrl-C to stop)", "[WORKER]".bright_blue(), server_url);
+            Worker::new(config, &server_url)?.with_api_key(api_key).run().await?;
+            report.target = Some(server_url);
+            return Ok(report);
+        }
+        Command::Serve { port, bind, grpc_port, .. } => {
+            let defaults = ServeOptions::default();
+            let options = ServeOptions { bind, port, grpc_port: grpc_port.or(defaults.grpc_port), ..defaults };
+            status!("{} http://{} (Ctrl-C to stop)", "[SERVER]".bright_blue(), options.addr());
+            server::run(config, options).await?;
+            return Ok(report);
+        }
+        Command::Batch { input, concurrency, results, resume, retry_failed, processor } => {
+            let mode = match (resume, retry_failed) {
+                (_, true) => ResumeMode::RetryFailed,
+                (true, false) => ResumeMode::Resume,
+                (false, false) => ResumeMode::Restart,
+            };
+            manifest::start_run();
+            let batch = process_batch(input.as_deref(), concurrency, results.as_deref(), mode, processor.as_deref(), &config).await;
+            push_run_metrics(&config).await;
+            let (outcome, results_path) = batch?;
+            report.finish_run(&output_dir);
+            report.target = Some(outcome.input.clone());
+            report.success = outcome.failed == 0 && !cancellation::is_cancelled();
+            report.exit_code = exit_code::PARTIAL_FAILURE;
+            if cancellation::is_cancelled() {
+                report.exit_code = exit_code::INTERRUPTED;
+                status!("{} Batch interrupted; run it again with --resume to finish the remaining URLs", "[WARNING]".bright_yellow());
+            }
+            report.errors = outcome.failures()
+                .map(|f| format!("{}: {}", f.url, f.error.as_deref().unwrap_or_default()))
+                .collect();
+            report.artifacts.push(results_path);
+            report.metrics.insert("succeeded", outcome.succeeded as u64);
+            report.metrics.insert("failed", outcome.failed as u64);
+            report.metrics.insert("duration_ms", started.elapsed().as_millis() as u64);
+            report.details = serde_json::to_value(&outcome)?;
+            return Ok(report);
+        }
+        Command::Decrypt { file, out } => {
+            let plaintext = compression::read_artifact(&file)?;
+            report.target = Some(file.display().to_string());
+            report.metrics.insert("bytes", plaintext.len() as u64);
+            match out {
+                Some(out) => {
+                    std::fs::write(&out, plaintext)?;
+                    report.artifacts.push(out);
+                }
+                None => io::stdout().write_all(&plaintext)?,
+            }
+            return Ok(report);
+        }
+        Command::Config { .. } | Command::Cache { .. } | Command::Plugins | Command::Jobs { .. } | Command::Explain { .. }
+        | Command::Chat { .. } | Command::AnalyzeBatch { .. } => {
+            unreachable!("handled above")
+        }
+    };
+    
+    // Track every artifact written from here on for the run manifest
+    manifest::start_run();
+    
+    let outcome = match command {
+        Command::Process { url, processor, .. } => {
+            report.target = Some(url.clone());
+            let outcome = cancellation::with_grace_period(process_url(&url, processor.as_deref(), &config)).await;
+            push_run_metrics(&config).await;
+            outcome
+        }
+        Command::Analyze { repo_url } => {
+            report.target = Some(repo_url.clone());
+            cancellation::with_grace_period(analyze_repo(&repo_url, &config)).await
+        }
+        // Use the legacy UI if explicitly requested
+        Command::Interactive { legacy_ui: true } => LlamaUI::new().run(&config).await,
+        _ => run_simple_interactive(&config).await,
+    };
+    
+    // An interrupted run still files and records the partial output it wrote
+    let interrupted = match outcome {
+        Err(ProcessorError::Cancelled) => true,
+        other => {
+            other?;
+            false
+        }
+    };
+    
     // Organize output files into appropriate directories
     if let Err(e) = output_organizer::organize_output(&output_dir) {
-        println!("Warning: Failed to organize output files: {}", e);
+        warn!("Failed to organize output files: {}", e);
     }
     
     // Generate index if requested
-    if cli.index {
+    if index_requested && !interrupted {
         if let Err(e) = output_organizer::generate_index(&output_dir) {
-            println!("Failed to generate index: {}", e);
+            warn!("Failed to generate index: {}", e);
         }
     }
     
-    // Display the output files
-    output_organizer::list_output_files(&output_dir);
+    // Record checksums of everything this run generated
+    report.finish_run(&output_dir);
+    report.metrics.insert("duration_ms", started.elapsed().as_millis() as u64);
+    if interrupted {
+        report.success = false;
+        report.exit_code = exit_code::INTERRUPTED;
+        report.errors.push(ProcessorError::Cancelled.to_string());
+        status!("{} Interrupted; partial output is marked {}", "[WARNING]".bright_yellow(), cancellation::INCOMPLETE_MARKER);
+    }
     
-    Ok(())
+    // Display the output files; the JSON report lists them itself
+    if !JSON_REPORT.load(Ordering::Relaxed) {
+        output_organizer::list_output_files(&output_dir);
+    }
+    
+    Ok(report)
+}
+
+/// Push the metrics of this run to the Prometheus Pushgateway, when one is configured
+async fn push_run_metrics(config: &Config) {
+    let Some(url) = config.metrics.pushgateway_url() else { return };
+    match metrics::push(&url, &config.metrics.job, metrics::run_metrics()).await {
+        Ok(()) => info!("Pushed run metrics to {}", url),
+        Err(e) => warn!("Failed to push run metrics to {}: {}", url, e),
+    }
 }
 
 /// Run a simple interactive command-line interface
@@ -147,7 +1020,7 @@ async fn run_simple_interactive(config: &Config) -> Result<()> {
         }
         
         // Try to process the URL
-        match process_url(input, config).await {
+        match process_url(input, None, config).await {
             Ok(_) => {
                 println!("\n{}", "Processing completed successfully.".bright_green());
                 println!("{}", "Enter another URL or 'q' to quit.".bright_cyan());
@@ -210,39 +1083,112 @@ fn print_examples() {
 }
 
 /// Process a URL directly (non-interactive mode)
-async fn process_url(url: &str, config: &Config) -> Result<()> {
+async fn process_url(url: &str, processor: Option<&str>, config: &Config) -> Result<()> {
     // Normalize the input first to handle trailing spaces and quoted paths
     let normalized = llamapackageservice::utils::normalize_url_or_path(url);
-    let url_type = processors::ProcessorFactory::detect_url_type(&normalized);
-    println!("{} {} ({})", "Processing URL:".bright_green(), normalized.bright_white(), url_type.bright_cyan());
+    let url_type = processors::ProcessorFactory::url_type(&normalized, processor);
+    status!("{} {} ({})", "Processing URL:".bright_green(), normalized.bright_white(), url_type.bright_cyan());
     
     let pb = ProgressBar::new_spinner();
     processors::common::setup_progress_style(&pb);
-    pb.set_message(format!("Processing {}", &normalized));
+    let aggregator = ProgressAggregator::new(1);
+    let ticker = show_progress(&pb, format!("Processing {}", &normalized), &aggregator);
     
-    let result = match processors::ProcessorFactory::create_processor(&normalized) {
+    let result = match processors::ProcessorFactory::create_for(&normalized, processor) {
         Ok(processor) => {
-            processor.process(&normalized, &config.output_dir, config).await
+            let processing = manifest::with_target(&normalized, &url_type, processor.process(&normalized, &config.output_dir, config));
+            metrics::record_processing(processor.name(), progress::with_sink(aggregator.sink(0), processing)).await
         },
         Err(e) => Err(e)
     };
+    ticker.abort();
+    pb.finish_and_clear();
     
     match &result {
         Ok(_) => {
-            println!("{} {}", "[SUCCESS]".bright_green(), "Processing completed successfully".bright_white());
-            println!("{} {}", "[SAVED]".bright_blue(), format!("Results saved to {}", config.output_dir.display()).bright_white());
+            status!("{} {}", "[SUCCESS]".bright_green(), "Processing completed successfully".bright_white());
+            status!("{} {}", "[SAVED]".bright_blue(), format!("Results saved to {}", config.output_dir.display()).bright_white());
         },
         Err(e) => {
-            println!("{} {}", "[ERROR]".bright_red(), format!("Error: {}", e).bright_red());
+            status!("{} {}", "[ERROR]".bright_red(), format!("Error: {}", e).bright_red());
         }
     }
     
     result
 }
 
+/// Process a single target into a scratch directory, streaming each artifact to stdout as it is written
+///
+/// Every artifact is preceded by a `==> name <==` line. Status messages go to
+/// stderr, so stdout carries only the processed content and can be piped
+/// into other tools.
+async fn stream_to_stdout(url: &str, processor: Option<&str>, config: &Config) -> Result<()> {
+    let normalized = llamapackageservice::utils::normalize_url_or_path(url);
+    let processor = ProcessorFactory::create_for(&normalized, processor)?;
+    let scratch = tempfile::tempdir()?;
+    let mut scratch_config = config.clone();
+    scratch_config.output_dir = scratch.path().to_path_buf();
+    
+    let (sink, mut written) = tokio::sync::mpsc::unbounded_channel();
+    let processing = manifest::stream(sink, processor.process(&normalized, scratch.path(), &scratch_config));
+    tokio::pin!(processing);
+    let mut sent = HashSet::new();
+    let result = loop {
+        tokio::select! {
+            result = &mut processing => break result,
+            Some(path) = written.recv() => {
+                if let Err(e) = send_artifact(&path, scratch.path(), &mut sent) {
+                    return ignore_broken_pipe(e);
+                }
+            }
+        }
+    };
+    result?;
+    
+    // Files written without going through the output helpers show up only on disk
+    let remaining: Vec<PathBuf> = std::iter::from_fn(|| written.try_recv().ok())
+        .chain(common::content_artifacts(scratch.path()))
+        .collect();
+    for path in remaining {
+        if let Err(e) = send_artifact(&path, scratch.path(), &mut sent) {
+            return ignore_broken_pipe(e);
+        }
+    }
+    if sent.is_empty() {
+        return Err(ProcessorError::Processing(format!("No output was produced for {}", normalized)));
+    }
+    Ok(())
+}
+
+/// Copies the artifact at `path` to stdout under its name relative to `dir`, unless it was sent already
+///
+/// Bookkeeping such as `_snapshots` and hidden indexes is not content and is skipped.
+fn send_artifact(path: &Path, dir: &Path, sent: &mut HashSet<PathBuf>) -> io::Result<()> {
+    let name = path.strip_prefix(dir).unwrap_or(path);
+    let bookkeeping = name.components().any(|c| c.as_os_str().to_string_lossy().starts_with(['.', '_']));
+    if bookkeeping || !path.is_file() || !sent.insert(path.to_path_buf()) {
+        return Ok(());
+    }
+    let mut out = io::stdout().lock();
+    writeln!(out, "{}==> {} <==", if sent.len() > 1 { "\n" } else { "" }, name.display())?;
+    io::copy(&mut compression::open_artifact(path)?, &mut out)?;
+    out.flush()
+}
+
+/// The reader going away (e.g. `| head`) is not a failure
+fn ignore_broken_pipe(e: io::Error) -> Result<()> {
+    match e.kind() {
+        io::ErrorKind::BrokenPipe => Ok(()),
+        _ => Err(e.into()),
+    }
+}
+
 // Helper function to create a progress bar
 fn create_progress_bar() -> ProgressBar {
     let pb = ProgressBar::new_spinner();
+    if QUIET.load(Ordering::Relaxed) {
+        pb.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+    }
     pb.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.green} [{elapsed_precise}] {msg}")
@@ -253,10 +1199,21 @@ fn create_progress_bar() -> ProgressBar {
     pb
 }
 
+/// Keeps `pb`'s message at `label` followed by `aggregator`'s overall progress until the returned task is aborted
+fn show_progress(pb: &ProgressBar, label: String, aggregator: &ProgressAggregator) -> tokio::task::JoinHandle<()> {
+    let (pb, aggregator) = (pb.clone(), aggregator.clone());
+    tokio::spawn(async move {
+        loop {
+            pb.set_message(format!("{} ({})", label, aggregator.snapshot()));
+            sleep(Duration::from_millis(250)).await;
+        }
+    })
+}
+
 // The following functions are kept for reference but not actively used in the main flow
 
 async fn process_single_url(url: &str, config: &Config) -> Result<()> {
-    println!("{} {}", Paint::green("Processing:"), Paint::blue(url));
+    status!("{} {}", Paint::green("Processing:"), Paint::blue(url));
     
     let pb = create_progress_bar();
     
@@ -266,7 +1223,7 @@ async fn process_single_url(url: &str, config: &Config) -> Result<()> {
     
     pb.finish_with_message(format!("{} {}", Paint::green("[OK]"), Paint::blue("Processing completed successfully")));
     
-    println!("\n{} Results saved to {}", 
+    status!("\n{} Results saved to {}", 
         Paint::green("[OK]"),
         Paint::blue(config.output_dir.display())
     );
@@ -274,67 +1231,196 @@ async fn process_single_url(url: &str, config: &Config) -> Result<()> {
     Ok(())
 }
 
-async fn process_batch(input_file: &str, config: &Config) -> Result<()> {
-    let content = tokio::fs::read_to_string(input_file).await?;
-    let urls: Vec<_> = content.lines()
-        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
-        .collect();
+/// Process every URL listed in `input` (or on stdin), at most `concurrency` at a time
+///
+/// Failures do not stop the batch; every URL's outcome is checkpointed as it
+/// finishes, reported, and saved as JSON to `results_path` (defaults to
+/// `<output>/<timestamp>_batch_results.json`). `mode` decides which URLs with
+/// a checkpointed outcome are skipped, and `processor` forces one processor
+/// for every URL.
+async fn process_batch(
+    input: Option<&Path>,
+    concurrency: usize,
+    results_path: Option<&Path>,
+    mode: ResumeMode,
+    processor: Option<&str>,
+    config: &Config,
+) -> Result<(BatchResults, PathBuf)> {
+    let (source, content) = match input {
+        Some(path) if path != Path::new("-") => (path.display().to_string(), tokio::fs::read_to_string(path).await?),
+        _ => {
+            let mut content = String::new();
+            tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut content).await?;
+            ("stdin".to_string(), content)
+        }
+    };
+    // A number after a URL is its priority; higher ones start first
+    let mut priorities = BTreeMap::new();
+    let mut urls = Vec::new();
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (url, priority) = match line.split_once(char::is_whitespace) {
+            Some((url, priority)) => (url, priority.trim().parse::<i32>().map_err(|_| {
+                ProcessorError::Validation(format!("Invalid priority \"{}\" for {} in {}", priority.trim(), url, source))
+            })?),
+            None => (line, 0),
+        };
+        let url = llamapackageservice::utils::normalize_url_or_path(url);
+        priorities.insert(url.clone(), priority);
+        urls.push(url);
+    }
+    
+    // An unknown processor name would fail every URL; reject it up front
+    if let Some(name) = processor {
+        ProcessorFactory::create_named(name)?;
+    }
     
-    println!("{} {} URLs from {}", 
+    let checkpoint = Checkpoint::open(&config.output_dir, &urls, mode)?;
+    let pending = checkpoint.pending(&urls, mode);
+    if pending.len() < urls.len() {
+        status!("{} {} of {} URLs already done according to {}",
+            Paint::green("Resuming:"),
+            urls.len() - pending.len(),
+            urls.len(),
+            checkpoint.path().display()
+        );
+    }
+    status!("{} {} URLs from {} ({} at a time)", 
         Paint::green("Processing"),
-        Paint::blue(urls.len()),
-        Paint::blue(input_file)
+        Paint::blue(pending.len()),
+        Paint::blue(&source),
+        concurrency
     );
     
-    // Default to 5 if not specified in config
-    let parallelism = 5;
-    let parallel = ParallelProcessor::new(parallelism);
-    
-    let tasks = urls.iter()
-        .map(|url| {
-            let url = url.to_string();
+    let started = chrono::Utc::now();
+    let parallel = ParallelProcessor::new(concurrency.max(1))
+        .with_host_limit(config.processing.max_concurrent_per_host);
+    let checkpoint = Mutex::new(checkpoint);
+    let aggregator = ProgressAggregator::new(pending.len());
+    let pb = create_progress_bar();
+    let ticker = show_progress(&pb, format!("Processing {} URLs", pending.len()), &aggregator);
+    
+    let tasks = pending.iter()
+        .enumerate()
+        .map(|(index, url)| {
+            let priority = priorities.get(url).copied().unwrap_or_default();
+            let host = url::Url::parse(url).ok().and_then(|parsed| parsed.host_str().map(str::to_string));
+            let url = url.clone();
             let config = config.clone();
-            async move {
-                let processor = ProcessorFactory::create_processor(&url)?;
-                processor.process(&url, &config.output_dir, &config).await
+            let processor = processor.map(str::to_string);
+            let aggregator = aggregator.clone();
+            let task = async move {
+                // URLs not started before Ctrl-C are left for --resume
+                if cancellation::is_cancelled() {
+                    return Err(ProcessorError::Cancelled);
+                }
+                let url_type = ProcessorFactory::url_type(&url, processor.as_deref());
+                let start = std::time::Instant::now();
+                let result = match ProcessorFactory::create_for(&url, processor.as_deref()) {
+                    Ok(processor) => {
+                        let processing = manifest::with_target(&url, &url_type, processor.process(&url, &config.output_dir, &config));
+                        let processing = progress::with_sink(aggregator.sink(index), processing);
+                        let processing = pipeline::staged(cancellation::with_grace_period(processing));
+                        metrics::record_processing(processor.name(), processing).await
+                    }
+                    Err(e) => Err(e),
+                };
+                // Interrupted URLs stay out of the checkpoint so --resume redoes them
+                if let Err(ProcessorError::Cancelled) = result {
+                    return Err(ProcessorError::Cancelled);
+                }
+                aggregator.finish(index);
+                Ok::<_, ProcessorError>(UrlResult {
+                    succeeded: result.is_ok(),
+                    error: result.err().map(|e| e.to_string()),
+                    duration_ms: start.elapsed().as_millis() as u64,
+                    url,
+                    processor: url_type,
+                })
+            };
+            let task = Task::new(task).with_priority(priority);
+            match host {
+                Some(host) => task.with_host(host),
+                None => task,
             }
         })
         .collect();
     
-    let results = parallel.process(tasks).await;
+    // Each outcome is journaled as it finishes, so a crashed batch resumes where it stopped
+    parallel.process_checkpointed(tasks, &checkpoint).await;
+    ticker.abort();
+    pb.finish_and_clear();
+    let overall = aggregator.snapshot();
     
-    // Report results
-    let success_count = results.iter().filter(|r| r.is_ok()).count();
-    let error_count = results.len() - success_count;
+    // Outcomes of URLs skipped on resume are reported alongside the new ones
+    let outcomes = checkpoint.lock().unwrap_or_else(|e| e.into_inner()).results_for(&urls);
+    let results = BatchResults::new(&source, started, outcomes);
     
-    println!("\n{} {} URLs processed successfully, {} failed", 
+    // Report results
+    output!();
+    for result in &results.results {
+        let status = if result.succeeded { "[OK]".bright_green() } else { "[FAILED]".bright_red() };
+        output!("  {} {} ({}, {:.1}s)", status, result.url, result.processor, result.duration_ms as f64 / 1000.0);
+    }
+    output!("\n{} {} URLs processed successfully, {} failed", 
         Paint::green("[OK]"),
-        Paint::blue(success_count),
-        Paint::red(error_count)
+        Paint::blue(results.succeeded),
+        Paint::red(results.failed)
+    );
+    let downloaded = match overall.bytes_downloaded {
+        0 => String::new(),
+        bytes => format!(", {} downloaded", output_organizer::format_file_size(bytes)),
+    };
+    output!("{} {} files processed{} in {}",
+        Paint::blue("[STATS]"),
+        overall.files_processed,
+        downloaded,
+        format_duration(overall.elapsed)
     );
+    if results.failed > 0 {
+        output!("\n{}", "Failures:".bright_red());
+        for failure in results.failures() {
+            output!("  {} - {}", failure.url, failure.error.as_deref().unwrap_or_default());
+        }
+    }
+    
+    let default_path = config.output_dir.join(format!("{}_batch_results.json", started.format("%Y%m%d_%H%M%S")));
+    let results_path = results_path.unwrap_or(&default_path).to_path_buf();
+    results.save(&results_path)?;
+    status!("{} Results saved to {}", Paint::green("[OK]"), Paint::blue(results_path.display()));
+    
+    // Combine what was learned about each package into one report
+    let summaries = batch_report::summaries_since(&config.output_dir, started);
+    if !summaries.is_empty() {
+        match batch_report::write(&config.output_dir, &source, &summaries).await {
+            Ok(path) => status!("{} Batch report saved to {}", Paint::green("[OK]"), Paint::blue(path.display())),
+            Err(e) => warn!("Failed to write batch report: {}", e),
+        }
+    }
     
     // Organize output files
     if let Err(e) = output_organizer::organize_output(&config.output_dir) {
-        println!("Warning: Failed to organize output files: {}", e);
+        warn!("Failed to organize output files: {}", e);
     }
     
-    // Generate index
-    if let Err(e) = output_organizer::generate_index(&config.output_dir) {
-        println!("Warning: Failed to generate index: {}", e);
+    // Generate index, unless the user is waiting for an interrupted batch to exit
+    if !cancellation::is_cancelled() {
+        if let Err(e) = output_organizer::generate_index(&config.output_dir) {
+            warn!("Failed to generate index: {}", e);
+        }
     }
     
-    Ok(())
+    Ok((results, results_path))
 }
 
 async fn analyze_repo(repo_url: &str, config: &Config) -> Result<()> {
-    println!("{} {}", Paint::green("Analyzing repository:"), Paint::blue(repo_url));
+    status!("{} {}", Paint::green("Analyzing repository:"), Paint::blue(repo_url));
     
     // First download the repository
     let pb = create_progress_bar();
     pb.set_message("Downloading repository...");
     
     // Download repo content
-    let content = github::download_repository(repo_url).await?;
+    let content = cancellation::or_cancelled(github::download_repository(repo_url)).await?;
     pb.set_message("Repository downloaded");
     
     // Save raw content
@@ -355,13 +1441,13 @@ async fn analyze_repo(repo_url: &str, config: &Config) -> Result<()> {
     
     pb.finish_with_message(format!("{} {}", Paint::green("[OK]"), Paint::blue("Repository processed successfully")));
     
-    println!("\n{} Results saved to:", Paint::green("[OK]"));
-    println!("  - Raw content: {}", Paint::blue(output_path.display()));
-    println!("  - Processed content: {}", Paint::blue(processed_path.display()));
+    status!("\n{} Results saved to:", Paint::green("[OK]"));
+    status!("  - Raw content: {}", Paint::blue(output_path.display()));
+    status!("  - Processed content: {}", Paint::blue(processed_path.display()));
     
     // Organize output files
     if let Err(e) = output_organizer::organize_output(&config.output_dir) {
-        println!("Warning: Failed to organize output files: {}", e);
+        warn!("Failed to organize output files: {}", e);
     }
     
     Ok(())
@@ -416,31 +1502,32 @@ async fn demo_cache() {
 
 async fn demo_string_cache() -> Result<()> {
     println!("\n=== String Cache Demo ===");
-    let cache_dir = Path::new("./cache");
+    let cache_dir = Path::new(DEFAULT_CACHE_DIR);
     let mut cache = StringCache::new(cache_dir).await?;
     
     // Set a shorter TTL for the demo
     cache.set_ttl(Duration::from_secs(5));
     
-    // Set some values
-    cache.set("persistent1", "This value will be saved").await?;
-    cache.set("persistent2", "This value will also be saved").await?;
+    // Set some values; they are saved in the background
+    cache.set_value("persistent1", "This value will be saved".to_string()).await;
+    cache.set_value("persistent2", "This value will also be saved".to_string()).await;
     
     println!("Cache size: {}", cache.len().await);
-    println!("persistent1 value: {:?}", cache.get("persistent1").await?);
     
-    // Save to disk
-    cache.save().await?;
+    // Save anything the background writer has not saved yet
+    cache.shutdown().await?;
     println!("Cache saved to disk");
     
     // Create a new cache instance to simulate program restart
     println!("Creating new cache instance (simulating restart)");
-    let new_cache = StringCache::new(cache_dir).await?;
+    let mut new_cache = StringCache::new(cache_dir).await?;
+    new_cache.set_ttl(Duration::from_secs(5));
     
     // Load from disk
     println!("Loading cache from disk");
+    new_cache.load().await?;
     println!("New cache size after load: {}", new_cache.len().await);
-    println!("persistent1 value from new cache: {:?}", new_cache.get("persistent1").await?);
+    println!("persistent1 in new cache: {}", new_cache.contains_key("persistent1").await);
     
     // Wait for expiration
     println!("Waiting for 6 seconds to test expiration...");
@@ -454,20 +1541,782 @@ async fn demo_string_cache() -> Result<()> {
     Ok(())
 }
 
-/// Spawn the `server` binary (Axum REST API) in the background so the user can access it while
-/// interacting with the CLI. If the binary cannot be found or fails to start, an error is
-/// returned. When the web-server port (8000) is already in use the child exits immediately – we
-/// ignore that fact and continue running the CLI.
-fn spawn_server_background() -> std::io::Result<()> {
-    // Determine path to the compiled `server` binary next to the current executable
-    let current_exe = std::env::current_exe()?;
-    let server_exe = current_exe.with_file_name("server");
+/// Process the bundled demo repository offline, then serve the results and open the web UI
+async fn run_quickstart(config: &Config, no_server: bool, no_open: bool) -> Result<CommandReport> {
+    status!("{}", "Running quickstart on the bundled demo repository (offline)".bright_green().bold());
+    
+    let quickstart = llamapackageservice::quickstart::run(&config.output_dir.join("quickstart")).await?;
+    status!("{} {}", "[FIXTURE]".bright_blue(), quickstart.fixture_dir.display());
+    for artifact in &quickstart.artifacts {
+        output!("  {}", artifact.display());
+    }
+    status!("{} {} artifacts in {}", "[SUCCESS]".bright_green(), quickstart.artifacts.len(), quickstart.output_dir.display());
+    
+    let mut report = CommandReport::new("quickstart");
+    report.target = Some(quickstart.fixture_dir.display().to_string());
+    report.artifacts = quickstart.artifacts.iter().map(|a| quickstart.output_dir.join(a)).collect();
+    report.metrics.insert("artifacts", report.artifacts.len() as u64);
+    if no_server {
+        return Ok(report);
+    }
+    
+    // Serve the quickstart results in the foreground
+    let mut server_config = config.clone();
+    server_config.output_dir = quickstart.output_dir.clone();
+    let options = ServeOptions::default();
+    let ui_url = format!("http://{}/", options.addr());
+    let server = tokio::spawn(server::run(server_config, options));
+    if !wait_for_server(&format!("{}health", ui_url), Duration::from_secs(15)).await {
+        server.abort();
+        return Err(ProcessorError::Network(format!(
+            "web server did not become healthy on port {} (is another instance running?)", server::DEFAULT_PORT
+        )));
+    }
+    status!("{} {} (Ctrl-C to stop)", "[SERVER]".bright_blue(), ui_url.bright_white());
+    
+    if !no_open {
+        if let Err(e) = open_in_browser(&ui_url) {
+            warn!("Could not open a browser ({}); visit {} manually", e, ui_url);
+        }
+    }
+    
+    server.await.map_err(|e| ProcessorError::Message(format!("web server stopped: {}", e)))??;
+    Ok(report)
+}
+
+/// Apply the retention policy to the output directory, or list what it would delete
+fn run_clean(output_dir: &Path, policy: &RetentionPolicy, dry_run: bool) -> Result<CommandReport> {
+    let mut report = CommandReport::new("clean");
+    if !policy.is_enabled() {
+        status!("No retention limits configured; use --max-age-days, --max-total-size or --max-versions");
+        return Ok(report);
+    }
+    
+    let deletions = retention::plan(output_dir, policy)?;
+    for deletion in &deletions {
+        output!("  {} {} ({}, {})",
+            if dry_run { "would delete" } else { "deleting" }.bright_yellow(),
+            deletion.path.display(),
+            output_organizer::format_file_size(deletion.size),
+            deletion.reason,
+        );
+    }
+    
+    let total: u64 = deletions.iter().map(|d| d.size).sum();
+    if dry_run {
+        status!("{} {} artifacts ({}) would be deleted", "[DRY RUN]".bright_blue(), deletions.len(), output_organizer::format_file_size(total));
+    } else {
+        let freed = retention::apply(&deletions)?;
+        status!("{} Deleted {} artifacts, freed {}", "[SUCCESS]".bright_green(), deletions.len(), output_organizer::format_file_size(freed));
+        report.metrics.insert("freed_bytes", freed);
+    }
+    
+    report.metrics.insert("artifacts", deletions.len() as u64);
+    report.metrics.insert("bytes", total);
+    report.details = serde_json::json!({
+        "dry_run": dry_run,
+        "deletions": deletions.iter()
+            .map(|d| serde_json::json!({ "path": d.path, "size": d.size, "reason": d.reason.to_string() }))
+            .collect::<Vec<_>>(),
+    });
+    report.artifacts = deletions.into_iter().map(|d| d.path).collect();
+    Ok(report)
+}
+
+/// Manage the config file and print the effective configuration
+async fn run_config(command: &ConfigCommand, config: Result<ResolvedConfig>) -> Result<CommandReport> {
+    let mut report = CommandReport::new("config");
+    let path = Config::config_path()?;
+    match command {
+        ConfigCommand::Init { force } => {
+            if path.exists() && !force {
+                return Err(ProcessorError::Config(format!(
+                    "{} already exists; pass --force to replace it", path.display()
+                )));
+            }
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, Config::starter_toml()?)?;
+            status!("{} Wrote {}", "[SUCCESS]".bright_green(), path.display());
+            report.artifacts.push(path);
+        }
+        ConfigCommand::Show { sources: true } => {
+            let settings = config?.annotated()?;
+            let width = settings.iter().map(|(key, value, _)| key.len() + value.to_string().len()).max().unwrap_or(0);
+            for (key, value, source) in &settings {
+                let setting = format!("{} = {}", key, value);
+                output!("{:<width$}  {}", setting, format!("# {}", source).bright_black(), width = width + 3);
+            }
+            report.details = settings.iter()
+                .map(|(key, value, source)| (key.clone(), serde_json::json!({ "value": value, "source": source })))
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        ConfigCommand::Show { sources: false } => {
+            let redacted = config?.config.redacted();
+            let toml = toml::to_string_pretty(&redacted)
+                .map_err(|e| ProcessorError::Config(format!("Failed to serialize config: {}", e)))?;
+            output!("{}", toml.trim_end());
+            report.details = serde_json::to_value(&redacted)?;
+        }
+        ConfigCommand::Get { key } => {
+            let value = config?.config.redacted().get(key)?;
+            match &value {
+                serde_json::Value::String(s) => output!("{}", s),
+                other => output!("{}", other),
+            }
+            report.target = Some(key.clone());
+            report.details = value;
+        }
+        ConfigCommand::Set { key, value } => {
+            Config::set_in_file(&path, key, value)?;
+            status!("{} Set {} in {}", "[SUCCESS]".bright_green(), key, path.display());
+            report.target = Some(key.clone());
+            report.artifacts.push(path);
+        }
+        ConfigCommand::Validate => {
+            let checks = validate_config(&path, config).await;
+            for (check, outcome) in &checks {
+                match outcome {
+                    Ok(message) => output!("{} {}: {}", "[OK]".bright_green(), check, message),
+                    Err(message) => output!("{} {}: {}", "[FAIL]".bright_red(), check, message),
+                }
+            }
+            report.errors = checks.iter()
+                .filter_map(|(check, outcome)| outcome.as_ref().err().map(|e| format!("{}: {}", check, e)))
+                .collect();
+            report.success = report.errors.is_empty();
+            report.exit_code = exit_code::VALIDATION;
+            report.details = checks.iter()
+                .map(|(check, outcome)| serde_json::json!({
+                    "check": check,
+                    "ok": outcome.is_ok(),
+                    "message": outcome.as_ref().unwrap_or_else(|e| e),
+                }))
+                .collect();
+        }
+        ConfigCommand::Where => {
+            output!("{}", path.display());
+            report.artifacts.push(path);
+        }
+    }
+    Ok(report)
+}
+
+/// Run every configuration check, stopping early if the config file does not load
+async fn validate_config(path: &Path, config: Result<ResolvedConfig>) -> Vec<(&'static str, std::result::Result<String, String>)> {
+    let resolved = match config {
+        Ok(resolved) => resolved,
+        Err(e) => return vec![("config file", Err(e.to_string()))],
+    };
+    let config = &resolved.config;
+    let mut checks = vec![(
+        "config file",
+        Ok(if path.exists() {
+            format!("loaded {}", path.display())
+        } else {
+            format!("{} not found, using defaults", path.display())
+        }),
+    )];
+
+    checks.push(("output directory", check_writable(&config.output_dir)));
+    checks.push(("temp directory", check_writable(&config.output_config.temp_dir)));
+
+    checks.push(("settings", match resolved.check() {
+        Ok(()) => Ok(format!("no problems, {} excluded file patterns", config.excluded_files.len())),
+        Err(ProcessorError::Config(problems)) => Err(problems),
+        Err(e) => Err(e.to_string()),
+    }));
+
+    checks.push(("encryption key", match config.output_config.encryption.resolve_key() {
+        Ok(Some(_)) => Ok("valid 256-bit key".to_string()),
+        Ok(None) => Ok("not set, artifacts are stored unencrypted".to_string()),
+        Err(e) => Err(e),
+    }));
+
+    let storage_backend = config.output_config.storage.clone().with_env()
+        .and_then(|storage_config| storage::from_config(&storage_config));
+    checks.push(("object storage", match storage_backend {
+        Ok(Some(_)) => Ok("configured".to_string()),
+        Ok(None) => Ok("not configured, artifacts stay local".to_string()),
+        Err(e) => Err(e.to_string()),
+    }));
+
+    checks.push(("response cache", match cache::backend::from_config(&config.cache) {
+        Ok(Some(_)) => Ok(format!(
+            "responses kept for {}s, revalidated after {}s", config.cache.ttl_secs, config.cache.revalidate_secs
+        )),
+        Ok(None) => Ok("off, API responses are fetched every time".to_string()),
+        Err(e) => Err(e.to_string()),
+    }));
+
+    checks.push(("github token", match &config.github_token {
+        None => Ok("not set, unauthenticated requests are limited to 60 per hour".to_string()),
+        Some(token) => check_github_token(token).await,
+    }));
+
+    checks
+}
+
+/// Check that `dir` exists or can be created, and accepts new files
+fn check_writable(dir: &Path) -> std::result::Result<String, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let probe = dir.join(".write-test");
+    std::fs::write(&probe, b"").map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(format!("{} is writable", dir.display()))
+}
+
+/// Ask GitHub whether `token` is accepted
+async fn check_github_token(token: &str) -> std::result::Result<String, String> {
+    let request = common::create_client_with_user_agent()
+        .map_err(|e| e.to_string())?
+        .get("https://api.github.com/user")
+        .bearer_auth(token);
+    let response = http::send(request)
+        .await
+        .map_err(|e| format!("could not reach GitHub: {}", e))?;
+    match response.status() {
+        status if status.is_success() => {
+            let user: serde_json::Value = response.json().await.unwrap_or_default();
+            Ok(format!("accepted for {}", user["login"].as_str().unwrap_or("unknown user")))
+        }
+        reqwest::StatusCode::UNAUTHORIZED => Err("rejected by GitHub (401 Unauthorized)".to_string()),
+        status => Err(format!("GitHub answered {}", status)),
+    }
+}
+
+/// List the processor plugins found in the plugins directory
+fn run_plugins() -> CommandReport {
+    let mut report = CommandReport::new("plugins");
+    let dir = plugin::plugin_dir();
+    report.target = dir.as_ref().map(|dir| dir.display().to_string());
+    let plugins = plugin::plugins();
+    if plugins.is_empty() {
+        status!("No plugins found in {}", report.target.as_deref().unwrap_or("the plugins directory"));
+    }
+    for plugin in plugins {
+        let manifest = plugin.manifest();
+        output!("{}  {}  {}", manifest.name.bright_white(), manifest.prefixes.join(", "), plugin.path().display());
+    }
+    report.metrics.insert("plugins", plugins.len() as u64);
+    report.details = plugins.iter()
+        .map(|plugin| serde_json::json!({ "manifest": plugin.manifest(), "path": plugin.path() }))
+        .collect();
+    report
+}
+
+/// Keeps responses, summaries and analyses in the cache `config` describes for the rest of the process
+fn share_response_cache(config: &Config) -> Result<()> {
+    if let Some(backend) = cache::backend::from_config(&config.cache)? {
+        cache::backend::set_shared_cache(backend, config.cache.revalidate_after());
+    }
+    Ok(())
+}
+
+/// Stream an AI analysis of `target` to stdout
+async fn run_explain(target: &str, analysis_type: &str, context: Option<String>, summarize: bool, config: &Config) -> Result<CommandReport> {
+    let mut report = CommandReport::new("explain");
+    report.target = Some(target.to_string());
+    share_response_cache(config)?;
+    let mut agent = OpenAIAgent::from_env()?.with_processing_config(config.clone());
+    if summarize {
+        agent = agent.with_summaries(true);
+    }
+    status!("{} {} with {}", "Analyzing".bright_green(), target, agent.model());
+    let mut answer = agent.analyze_repository_stream(AnalysisRequest {
+        repository: target.to_string(),
+        analysis_type: AnalysisType::from_name(analysis_type),
+        context,
+        parameters: Default::default(),
+    }).await?;
+
+    let mut content = String::new();
+    let mut out: Box<dyn Write> = if JSON_REPORT.load(Ordering::Relaxed) { Box::new(io::stderr()) } else { Box::new(io::stdout()) };
+    while let Some(token) = answer.next().await {
+        let token = token?;
+        write!(out, "{}", token)?;
+        out.flush()?;
+        content.push_str(&token);
+    }
+    writeln!(out)?;
+    report.metrics.insert("completion_tokens", chunking::count_tokens(&content) as u64);
+    report.details = serde_json::json!({ "model": agent.model(), "analysis_type": analysis_type, "content": content });
+    Ok(report)
+}
+
+/// Ask about a repository in a new or stored conversation, keeping it in the output directory
+///
+/// With `export`, the embeddings of a new conversation are also written to that directory.
+async fn run_chat(
+    message: &str,
+    repo: Option<&str>,
+    resume: Option<&str>,
+    export: Option<(&Path, ExportFormat)>,
+    config: &Config,
+) -> Result<CommandReport> {
+    let mut report = CommandReport::new("chat");
+    let store = ConversationStore::open(&config.output_dir.join(CONVERSATIONS_DB_FILE))?;
+    share_response_cache(config)?;
+    let agent = OpenAIAgent::from_env()?.with_processing_config(config.clone());
+    let mut context = match (resume, repo) {
+        (Some(id), _) => store.load(id)?
+            .ok_or_else(|| ProcessorError::Validation(format!("no conversation {} in {}", id, config.output_dir.display())))?,
+        (None, Some(repo)) => {
+            status!("{} {} with {}", "Reading".bright_green(), repo, agent.model());
+            let context = agent.start_conversation(repo.to_string()).await?;
+            store.save(&context)?;
+            if let Some((dir, format)) = export {
+                let index = context.index.as_ref().ok_or_else(|| {
+                    ProcessorError::Processing(format!("{} could not be embedded, so there is nothing to export", repo))
+                })?;
+                let files = embeddings::export(index.chunks(), dir, format)?;
+                status!("{} {} embeddings to {}", "Exported".bright_green(), index.len(), dir.display());
+                report.artifacts.extend(files);
+            }
+            context
+        }
+        (None, None) => unreachable!("clap requires --repo or --resume"),
+    };
+    report.target = Some(context.repository.clone());
+
+    let reply = agent.continue_conversation(&mut context, message.to_string()).await?;
+    store.save(&context)?;
+    output!("{}", reply);
+    status!("{} llamapackageservice chat --resume {} \"...\"", "Continue with".bright_green(), context.id);
+    report.metrics.insert("messages", context.messages.len() as u64);
+    report.details = serde_json::json!({ "conversation_id": context.id, "model": agent.model(), "reply": reply });
+    Ok(report)
+}
+
+/// Analyze the repositories listed in `input` in one batch, checking on it every `poll_interval` until it ends
+///
+/// Without a `poll_interval`, the batch is submitted or checked on once.
+async fn run_analyze_batch(
+    input: Option<&Path>,
+    analysis_type: &str,
+    context: Option<String>,
+    poll_interval: Option<Duration>,
+    config: &Config,
+) -> Result<CommandReport> {
+    let mut report = CommandReport::new("analyze-batch");
+    let (source, content) = match input {
+        Some(path) if path != Path::new("-") => (path.display().to_string(), tokio::fs::read_to_string(path).await?),
+        _ => {
+            let mut content = String::new();
+            tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut content).await?;
+            ("stdin".to_string(), content)
+        }
+    };
+    let repositories: Vec<String> = content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(llamapackageservice::utils::normalize_url_or_path)
+        .collect();
+    report.target = Some(source.clone());
+    share_response_cache(config)?;
+    let agent = OpenAIAgent::from_env()?.with_processing_config(config.clone());
+
+    let mut state = agent.submit_batch(&source, &repositories, analysis_type, context).await?;
+    if state.batch_id.is_empty() {
+        status!("{} every analysis was cached", "Nothing to submit:".bright_green());
+    } else {
+        status!("{} {} with {} requests ({})", "Batch".bright_green(), state.batch_id, state.pending.len(), state.status);
+    }
+    while !agent.poll_batch(&mut state).await? {
+        let Some(interval) = poll_interval else {
+            status!("{} run the same command again to collect the analyses", "Submitted;".bright_green());
+            break;
+        };
+        status!("{} {} is {}", "Waiting:".bright_yellow(), state.batch_id, state.status);
+        tokio::time::sleep(interval).await;
+    }
+    if state.is_finished() {
+        status!("{} {} analyses in {}, {} failed",
+            Paint::green("[OK]"),
+            state.written.len(),
+            config.output_dir.join(batch::ANALYSES_DIR).display(),
+            state.failed.len()
+        );
+        for (repository, reason) in &state.failed {
+            status!("  {} {}: {}", "[FAILED]".bright_red(), repository, reason);
+        }
+    }
+    report.metrics.insert("written", state.written.len() as u64);
+    report.metrics.insert("failed", state.failed.len() as u64);
+    report.metrics.insert("pending", state.pending.len() as u64);
+    report.details = serde_json::to_value(&state)?;
+    Ok(report)
+}
+
+/// Report on or clear the persistent cache in `config.dir` and the response cache `config` describes
+async fn run_cache(config: &CacheConfig, command: &CacheCommand, github_token: Option<&str>) -> Result<CommandReport> {
+    let dir = config.dir.as_path();
+    let mut report = CommandReport::new("cache");
+    report.target = Some(dir.display().to_string());
+    let kind = format!("{:?}", config.backend).to_lowercase();
+    match command {
+        CacheCommand::Stats => {
+            let cache = StringCache::new(dir).await?;
+            cache.load().await?;
+            let responses = match cache::backend::from_config(config)? {
+                Some(backend) => backend.stats().await?,
+                None => Vec::new(),
+            };
+            let bytes: u64 = walkdir::WalkDir::new(dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum();
+            output!("{} {}", "Cache:".bright_cyan(), dir.display());
+            output!("  Entries: {}", cache.len().await);
+            output!("  Size on disk: {}", output_organizer::format_file_size(bytes));
+            output!("{} {}", "Responses:".bright_cyan(), kind);
+            if responses.is_empty() {
+                output!("  (none)");
+            }
+            for namespace in &responses {
+                output!(
+                    "  {:<10} {:>6} entries  {:>10}  oldest {}",
+                    namespace.processor,
+                    namespace.entries,
+                    output_organizer::format_file_size(namespace.bytes),
+                    namespace.oldest.map_or_else(|| "-".to_string(), |oldest| oldest.format("%Y-%m-%d %H:%M").to_string()),
+                );
+            }
+            report.metrics.insert("entries", cache.len().await as u64);
+            report.metrics.insert("bytes", bytes);
+            report.metrics.insert("responses", responses.iter().map(|namespace| namespace.entries).sum());
+            report.details = serde_json::json!({ "backend": kind, "responses": responses });
+        }
+        CacheCommand::Clear { processor, older_than } => {
+            let filter = ClearFilter { processor: processor.clone(), older_than: *older_than };
+            let removed = match cache::backend::from_config(config)? {
+                Some(backend) => backend.clear(&filter).await?,
+                None => 0,
+            };
+            report.metrics.insert("removed", removed);
+            if filter == ClearFilter::default() {
+                if dir.exists() {
+                    tokio::fs::remove_dir_all(dir).await?;
+                }
+                tokio::fs::create_dir_all(dir).await?;
+                status!("{} Cleared cache at {}", "[SUCCESS]".bright_green(), dir.display());
+            } else {
+                status!("{} Removed {} cached responses from the {} cache", "[SUCCESS]".bright_green(), removed, kind);
+            }
+        }
+        CacheCommand::Warm { file, concurrency } => {
+            let backend = match config.backend {
+                CacheBackendKind::None => return Err(ProcessorError::Config("caching is off; set cache.backend to sqlite or redis".into())),
+                CacheBackendKind::Memory => return Err(ProcessorError::Config(
+                    "the memory cache ends with this command; set cache.backend to sqlite or redis to warm it".into()
+                )),
+                _ => cache::backend::from_config(config)?.expect("caching is on"),
+            };
+            let content = if file == Path::new("-") {
+                let mut content = String::new();
+                tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut content).await?;
+                content
+            } else {
+                tokio::fs::read_to_string(file).await?
+            };
+            let urls: Vec<String> = content.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                // Batch priorities after the URL make no difference to warming
+                .filter_map(|line| line.split_whitespace().next())
+                .map(str::to_string)
+                .collect();
+            status!("{} metadata for {} URLs into the {} cache", Paint::green("Fetching"), urls.len(), kind);
+            let warmed = cache::warm::warm(backend.as_ref(), &urls, *concurrency, github_token).await?;
+            for url in &warmed.skipped {
+                warn!("Skipping {}: no registry metadata to fetch", url);
+            }
+            for (url, error) in &warmed.failed {
+                status!("{} {}: {}", "[FAILED]".bright_red(), url, error);
+            }
+            status!("{} Warmed {} targets ({} failed, {} skipped)",
+                "[SUCCESS]".bright_green(), warmed.warmed, warmed.failed.len(), warmed.skipped.len());
+            report.target = Some(file.display().to_string());
+            report.metrics.insert("warmed", warmed.warmed);
+            report.metrics.insert("failed", warmed.failed.len() as u64);
+            report.metrics.insert("skipped", warmed.skipped.len() as u64);
+            report.errors = warmed.failed.iter().map(|(url, error)| format!("{}: {}", url, error)).collect();
+            if !warmed.failed.is_empty() {
+                report.success = false;
+                report.exit_code = exit_code::PARTIAL_FAILURE;
+            }
+            report.details = serde_json::json!(warmed);
+        }
+    }
+    Ok(report)
+}
+
+/// List the jobs of a running API server, following pages with `--all`
+async fn run_jobs(command: &JobsCommand) -> Result<CommandReport> {
+    let JobsCommand::List { server_url, api_key, status, source, since, search, sort, order, limit, page, all } = command;
+    let mut report = CommandReport::new("jobs");
+    let base = server_url.clone()
+        .or_else(|| std::env::var(API_URL_ENV_VAR).ok())
+        .unwrap_or_else(|| format!("http://127.0.0.1:{}", server::DEFAULT_PORT));
+    let endpoint = format!("{}/api/jobs", base.trim_end_matches('/'));
+    report.target = Some(base.clone());
+    let api_key = api_key.clone().or_else(|| std::env::var(API_KEY_ENV_VAR).ok());
+    let mut params = JobListParams {
+        status: (!status.is_empty()).then(|| status.join(",")),
+        source: source.clone(),
+        since: since.clone(),
+        q: search.clone(),
+        sort: sort.clone(),
+        order: order.clone(),
+        limit: Some(*limit),
+        page: page.clone(),
+    };
+    
+    let client = http::client()?;
+    let mut jobs = Vec::new();
+    let (total, next_page) = loop {
+        let mut request = client.get(&endpoint).query(&params);
+        if let Some(key) = &api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await
+            .map_err(|e| ProcessorError::Network(format!("cannot reach the server at {}: {}", base, e)))?;
+        let code = response.status();
+        if !code.is_success() {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            let message = body["error"].as_str().map_or_else(|| code.to_string(), str::to_string);
+            return Err(match code {
+                reqwest::StatusCode::BAD_REQUEST => ProcessorError::Validation(message),
+                reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => ProcessorError::Config(format!(
+                    "the server refused the request ({}); pass --api-key or set {}", message, API_KEY_ENV_VAR
+                )),
+                reqwest::StatusCode::TOO_MANY_REQUESTS => ProcessorError::RateLimitExceeded(message),
+                _ => ProcessorError::Network(format!("the server answered {}: {}", code, message)),
+            });
+        }
+        let list: JobList = response.json().await?;
+        jobs.extend(list.jobs);
+        match list.next_page {
+            Some(token) if *all => params.page = Some(token),
+            next_page => break (list.total, next_page),
+        }
+    };
+    
+    for job in &jobs {
+        let status = format!("{:<10}", job.status.name());
+        let status = match job.status {
+            api::JobStatusType::Completed => status.bright_green(),
+            api::JobStatusType::Failed => status.bright_red(),
+            api::JobStatusType::Cancelled => status.bright_black(),
+            _ => status.bright_yellow(),
+        };
+        output!(
+            "{}  {}  {:>3}%  {}  {}",
+            job.job_id.get(..8).unwrap_or(&job.job_id).bright_white(),
+            status,
+            job.progress,
+            job.created_at.format("%Y-%m-%d %H:%M"),
+            job.url,
+        );
+    }
+    status!("Showing {} of {} matching jobs", jobs.len(), total);
+    if let Some(token) = &next_page {
+        status!("Next page: --page {}", token);
+    }
+    report.metrics.insert("jobs", jobs.len() as u64);
+    report.metrics.insert("total", total as u64);
+    report.details = serde_json::json!({ "jobs": jobs, "total": total, "next_page": next_page });
+    Ok(report)
+}
+
+/// Print lines of generated artifacts containing `query`, ignoring case
+/// Runs an `analytics` subcommand against the processed targets in `output_dir`
+async fn run_analytics(output_dir: &Path, command: AnalyticsCommand) -> Result<CommandReport> {
+    let mut report = CommandReport::new("analytics");
+    match command {
+        AnalyticsCommand::Compare { targets } => {
+            let comparison = Comparison::load(output_dir, &targets)?;
+            let stem = format!(
+                "{}_{}_comparison",
+                comparison.generated_at.format("%Y%m%d_%H%M%S"),
+                output_organizer::package_slug(&targets.join(" vs ")),
+            );
+            let markdown = common::write_artifact(&comparison.to_markdown(), &output_dir.join(format!("{}.md", stem))).await?;
+            let json = common::write_artifact(&serde_json::to_string_pretty(&comparison)?, &output_dir.join(format!("{}.json", stem))).await?;
+            status!("{} Comparison of {} targets written to {}", "[SUCCESS]".bright_green(), targets.len(), markdown.display());
+            report.target = Some(targets.join(" "));
+            report.metrics.insert("targets", targets.len() as u64);
+            report.metrics.insert("common_dependencies", comparison.common_dependencies.len() as u64);
+            report.artifacts.extend([markdown, json]);
+            report.details = serde_json::to_value(&comparison)?;
+        }
+        AnalyticsCommand::History { target, limit } => {
+            let runs = MetricsHistory::open(&output_dir.join(METRICS_DB_FILE))?.find_runs(&target, limit.max(1))?;
+            if runs.is_empty() {
+                return Err(ProcessorError::Validation(format!("No runs of {} recorded in {}", target, output_dir.display())));
+            }
+            output!("{:<20} {:>8} {:>10} {:>10} {:>11}", "Run", "Files", "Lines", "Functions", "Complexity");
+            for run in &runs {
+                let when = run.timestamp.as_deref()
+                    .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                    .map_or_else(|| "-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+                let (functions, complexity) = run.complexity.as_ref()
+                    .map_or(("-".to_string(), "-".to_string()), |c| (c.functions.to_string(), format!("{:.0}/100", c.score)));
+                output!("{:<20} {:>8} {:>10} {:>10} {:>11}", when, run.files_processed, run.total_lines, functions, complexity);
+            }
+            if let Some(trends) = history::trend_section(&runs) {
+                output!("\n{}", trends.trim_end());
+            }
+            report.target = Some(target);
+            report.metrics.insert("runs", runs.len() as u64);
+            report.details = serde_json::to_value(&runs)?;
+        }
+    }
+    Ok(report)
+}
 
-    // Spawn detached child; stdout/stderr inherited so logs appear in the same terminal
-    TokioCommand::new(server_exe)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
+fn run_search(output_dir: &Path, query: &str, limit: usize) -> Result<CommandReport> {
+    use std::io::BufRead;
+    
+    let mut report = CommandReport::new("search");
+    report.target = Some(query.to_string());
+    let needle = query.to_lowercase();
+    let mut matches = Vec::new();
+    // Hidden indexes and generated bookkeeping (`_site`, `_runs`, ...) are not searched
+    let artifacts = walkdir::WalkDir::new(output_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with(['.', '_']))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file());
+    
+    'files: for entry in artifacts {
+        let Ok(reader) = compression::open_artifact(entry.path()) else { continue };
+        let relative = entry.path().strip_prefix(output_dir).unwrap_or(entry.path());
+        for (number, line) in io::BufReader::new(reader).lines().enumerate() {
+            // Binary or undecodable content ends the file
+            let Ok(line) = line else { continue 'files };
+            if !line.to_lowercase().contains(&needle) {
+                continue;
+            }
+            let snippet: String = line.trim().chars().take(160).collect();
+            output!("{}:{}: {}", relative.display().to_string().bright_blue(), number + 1, snippet);
+            matches.push(serde_json::json!({ "path": relative, "line": number + 1, "text": snippet }));
+            if !report.artifacts.iter().any(|a| a == entry.path()) {
+                report.artifacts.push(entry.path().to_path_buf());
+            }
+            if matches.len() >= limit {
+                status!("{} Stopped after {} matches (use --limit to see more)", "[INFO]".bright_yellow(), limit);
+                break 'files;
+            }
+        }
+    }
+    
+    if matches.is_empty() {
+        status!("No matches for '{}' in {}", query, output_dir.display());
+    }
+    report.metrics.insert("matches", matches.len() as u64);
+    report.details = serde_json::Value::Array(matches);
+    Ok(report)
+}
 
+/// Polls a local health endpoint until it answers or `timeout` elapses
+async fn wait_for_server(health_url: &str, timeout: Duration) -> bool {
+    let Ok(client) = http::client() else {
+        return false;
+    };
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        if let Ok(response) = client.get(health_url).send().await {
+            if response.status().is_success() {
+                return true;
+            }
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+    false
+}
+
+/// Opens `url` with the platform's default browser
+fn open_in_browser(url: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command.arg(url).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_command_report_json() {
+        let mut report = CommandReport::new("process");
+        report.target = Some("./project".into());
+        report.artifacts.push(PathBuf::from("out/project_analysis.txt"));
+        report.metrics.insert("artifacts", 1);
+        
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["command"], "process");
+        assert_eq!(json["success"], true);
+        assert_eq!(json["target"], "./project");
+        assert_eq!(json["artifacts"], serde_json::json!(["out/project_analysis.txt"]));
+        assert_eq!(json["metrics"]["artifacts"], 1);
+        assert_eq!(json["errors"], serde_json::json!([]));
+        assert_eq!(json["exit_code"], 0);
+        // Empty optional sections are left out rather than written as null
+        assert!(json.get("details").is_none());
+        assert!(json.get("caches").is_none());
+        
+        report.target = None;
+        report.details = serde_json::json!({ "reply": "ok" });
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json.get("target").is_none());
+        assert_eq!(json["details"]["reply"], "ok");
+    }
+    
+    #[test]
+    fn test_command_report_exit_code() {
+        let mut report = CommandReport::new("batch");
+        report.exit_code = exit_code::PARTIAL_FAILURE;
+        assert_eq!(report.exit_code(), exit_code::SUCCESS);
+        report.success = false;
+        assert_eq!(report.exit_code(), exit_code::PARTIAL_FAILURE);
+        report.exit_code = exit_code::SUCCESS;
+        assert_eq!(report.exit_code(), exit_code::INTERNAL);
+    }
+    
+    #[test]
+    fn test_cli_flags() {
+        let cli = Cli::try_parse_from(["llamapackageservice", "--format", "json", "-vv", "process", "."]).unwrap();
+        assert!(cli.format == OutputFormat::Json);
+        assert_eq!(cli.verbose, 2);
+        assert!(matches!(cli.command, Some(Command::Process { .. })));
+        
+        let conflict = Cli::try_parse_from(["llamapackageservice", "-q", "-v", "process", "."]).err().unwrap();
+        assert_eq!(conflict.kind(), clap::error::ErrorKind::ArgumentConflict);
+        
+        let cli = Cli::try_parse_from(["llamapackageservice", "serve", "--port", "9000", "--bind", "0.0.0.0", "--workers", "2"]).unwrap();
+        match cli.command {
+            Some(Command::Serve { port, bind, workers, .. }) => {
+                assert_eq!(port, 9000);
+                assert_eq!(bind, "0.0.0.0".parse::<IpAddr>().unwrap());
+                assert_eq!(workers, Some(2));
+            }
+            _ => panic!("expected serve"),
+        }
+        assert!(Cli::try_parse_from(["llamapackageservice", "serve", "--bind", "localhost:80"]).is_err());
+    }
+}
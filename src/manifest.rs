@@ -0,0 +1,345 @@
+//! Per-run manifest of generated artifacts
+//!
+//! Every run writes `_runs/<run id>/manifest.json` listing each artifact it
+//! produced with its size and SHA-256, plus the source URL and processor
+//! when the write happened inside [`with_target`]. Downstream systems can
+//! check a directory against a manifest with [`RunManifest::verify`].
+//!
+//! Writes are recorded by the shared output helpers between [`start_run`] and
+//! [`finish_run`]; outside a run, such as in the API server, only
+//! [`collect`] and [`stream`] see them. Files produced through other paths
+//! are picked up when the run is finished by looking for anything created or
+//! modified since the run started.
+//!
+//! The manifest also records the tokens and estimated cost of the AI
+//! requests made during the run.
+
+use crate::agents::usage::{self, UsageSummary};
+use crate::error::Result;
+use crate::perf;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use walkdir::WalkDir;
+
+/// Directory, relative to the output directory, holding per-run manifests
+pub const RUNS_DIR: &str = "_runs";
+
+/// File name of a run manifest
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Target an artifact was generated for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Target {
+    /// URL or path that was processed
+    pub source_url: String,
+    /// Processor that handled it
+    pub processor: String,
+}
+
+tokio::task_local! {
+    static CURRENT_TARGET: Target;
+    static COLLECTED: Arc<Mutex<Vec<PathBuf>>>;
+    static STREAMED: mpsc::UnboundedSender<PathBuf>;
+}
+
+struct RunState {
+    started_at: DateTime<Utc>,
+    started: SystemTime,
+    /// Whether a run is in progress, and so writes are recorded
+    active: bool,
+    recorded: Vec<(PathBuf, Option<Target>, DateTime<Utc>)>,
+}
+
+static RUN: Lazy<Mutex<RunState>> = Lazy::new(|| {
+    Mutex::new(RunState {
+        started_at: Utc::now(),
+        started: SystemTime::now(),
+        active: false,
+        recorded: Vec::new(),
+    })
+});
+
+/// One generated artifact
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactEntry {
+    /// Path relative to the output directory
+    pub path: String,
+    /// URL or path the artifact was generated from, when known
+    pub source_url: Option<String>,
+    /// Processor that generated it, when known
+    pub processor: Option<String>,
+    /// When the artifact was written
+    pub timestamp: DateTime<Utc>,
+    /// Size on disk in bytes
+    pub size: u64,
+    /// SHA-256 of the file on disk, hex encoded
+    pub sha256: String,
+}
+
+/// Manifest of everything a run generated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// Identifier of the run, also the name of its directory under `_runs`
+    pub run_id: String,
+    /// When the run started
+    pub started_at: DateTime<Utc>,
+    /// When the manifest was written
+    pub finished_at: DateTime<Utc>,
+    /// Generated artifacts, sorted by path
+    pub artifacts: Vec<ArtifactEntry>,
+    /// AI requests made during the run, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ai_usage: Option<UsageSummary>,
+}
+
+/// Problem found when verifying a directory against a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// Artifact listed in the manifest is not on disk
+    Missing(String),
+    /// Artifact exists but its size or checksum differs
+    Modified(String),
+}
+
+/// Runs `fut` with `source_url` and `processor` attached to every artifact it records
+pub async fn with_target<F: Future>(source_url: &str, processor: &str, fut: F) -> F::Output {
+    let target = Target {
+        source_url: source_url.to_string(),
+        processor: processor.to_string(),
+    };
+    CURRENT_TARGET.scope(target, fut).await
+}
+
+/// Runs `fut`, also returning the paths of every artifact it recorded
+///
+/// Unlike the run manifest this only sees writes made by `fut` itself, so
+/// concurrent jobs in one process can each tell which files they produced.
+pub async fn collect<F: Future>(fut: F) -> (F::Output, Vec<PathBuf>) {
+    let collected = Arc::new(Mutex::new(Vec::new()));
+    let output = COLLECTED.scope(Arc::clone(&collected), fut).await;
+    let paths = std::mem::take(&mut *collected.lock().unwrap_or_else(|e| e.into_inner()));
+    (output, paths)
+}
+
+/// Runs `fut`, sending the path of every artifact it records to `sink` as soon as it is written
+pub async fn stream<F: Future>(sink: mpsc::UnboundedSender<PathBuf>, fut: F) -> F::Output {
+    STREAMED.scope(sink, fut).await
+}
+
+/// Modification times are stored coarsely (the kernel's clock tick, or 2 s on
+/// FAT), so a file written just after the run started can appear older
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// Resets the current run, starting the clock used to find unrecorded artifacts
+pub fn start_run() {
+    let mut run = RUN.lock().unwrap_or_else(|e| e.into_inner());
+    run.started_at = Utc::now();
+    run.started = SystemTime::now();
+    run.active = true;
+    run.recorded.clear();
+    usage::start_run();
+}
+
+/// Identifier of the current run
+pub fn current_run_id() -> String {
+    run_id(RUN.lock().unwrap_or_else(|e| e.into_inner()).started_at)
+}
+
+fn run_id(started_at: DateTime<Utc>) -> String {
+    started_at.format("%Y%m%d_%H%M%S").to_string()
+}
+
+/// Records that `path` was written, attributing it to the current target if any
+///
+/// Outside a run the write is only passed to [`collect`] and [`stream`].
+pub fn record(path: &Path) {
+    let target = CURRENT_TARGET.try_with(Clone::clone).ok();
+    let _ = COLLECTED.try_with(|collected| {
+        collected.lock().unwrap_or_else(|e| e.into_inner()).push(path.to_path_buf());
+    });
+    let _ = STREAMED.try_with(|sink| sink.send(path.to_path_buf()));
+    let mut run = RUN.lock().unwrap_or_else(|e| e.into_inner());
+    if run.active {
+        run.recorded.push((path.to_path_buf(), target, Utc::now()));
+    }
+}
+
+/// Builds the manifest of the current run and writes it under `<output_dir>/_runs`
+///
+/// Returns the path of the manifest written. Writes are no longer recorded
+/// until the next [`start_run`].
+pub fn finish_run(output_dir: &Path) -> Result<PathBuf> {
+    let (started_at, started, recorded) = {
+        let mut run = RUN.lock().unwrap_or_else(|e| e.into_inner());
+        run.active = false;
+        (run.started_at, run.started, std::mem::take(&mut run.recorded))
+    };
+
+    // Recorded files may have been moved into category directories since they were
+    // written, so fall back to matching by file name
+    let mut by_path: HashMap<PathBuf, (Option<Target>, DateTime<Utc>)> = HashMap::new();
+    let mut by_name: HashMap<String, (Option<Target>, DateTime<Utc>)> = HashMap::new();
+    for (path, target, timestamp) in recorded {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if let Some(name) = path.file_name() {
+            by_name.insert(name.to_string_lossy().into_owned(), (target.clone(), timestamp));
+        }
+        by_path.insert(canonical, (target, timestamp));
+    }
+
+    let mut artifacts = Vec::new();
+    for entry in WalkDir::new(output_dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !is_excluded(e.file_name().to_string_lossy().as_ref()))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let known = by_path.get(&canonical).or_else(|| by_name.get(&name)).cloned();
+
+        let metadata = entry.metadata().map_err(std::io::Error::from)?;
+        let modified = metadata.modified().ok();
+        let (target, timestamp) = match known {
+            Some(known) => known,
+            None if modified.is_some_and(|m| m + MTIME_GRANULARITY >= started) => (None, DateTime::<Utc>::from(modified.unwrap())),
+            None => continue,
+        };
+
+        artifacts.push(ArtifactEntry {
+            path: relative(path, output_dir),
+            source_url: target.as_ref().map(|t| t.source_url.clone()),
+            processor: target.map(|t| t.processor),
+            timestamp,
+            size: metadata.len(),
+            sha256: perf::hash_file(path)?,
+        });
+    }
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let ai_usage = usage::run_usage();
+    let manifest = RunManifest {
+        run_id: run_id(started_at),
+        started_at,
+        finished_at: Utc::now(),
+        artifacts,
+        ai_usage: (!ai_usage.is_empty()).then_some(ai_usage),
+    };
+    let dir = output_dir.join(RUNS_DIR).join(&manifest.run_id);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(MANIFEST_FILE_NAME);
+    fs::write(&path, serde_json::to_vec_pretty(&manifest)?)?;
+    Ok(path)
+}
+
+impl RunManifest {
+    /// Loads a manifest from disk
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Checks every listed artifact under `output_dir`, returning the problems found
+    pub fn verify(&self, output_dir: &Path) -> Vec<VerifyIssue> {
+        self.artifacts.iter()
+            .filter_map(|artifact| {
+                let path = output_dir.join(&artifact.path);
+                let Ok(metadata) = fs::metadata(&path) else {
+                    return Some(VerifyIssue::Missing(artifact.path.clone()));
+                };
+                let intact = metadata.len() == artifact.size
+                    && perf::hash_file(&path).is_ok_and(|hash| hash == artifact.sha256);
+                (!intact).then(|| VerifyIssue::Modified(artifact.path.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Hidden bookkeeping files and previous manifests are not artifacts
+fn is_excluded(name: &str) -> bool {
+    name.starts_with('.') || name == RUNS_DIR
+}
+
+fn relative(path: &Path, base: &Path) -> String {
+    path.strip_prefix(base).unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_manifest_lists_and_verifies_artifacts() {
+        let dir = tempdir().unwrap();
+        start_run();
+
+        let recorded = dir.path().join("pypi_packages").join("requests.txt");
+        with_target("https://pypi.org/project/requests", "pypi", async {
+            fs::create_dir_all(recorded.parent().unwrap()).unwrap();
+            fs::write(&recorded, "analysis").unwrap();
+            record(&recorded);
+        }).await;
+        fs::write(dir.path().join("unrecorded.md"), "extra").unwrap();
+        fs::write(dir.path().join(".content-hashes.json"), "{}").unwrap();
+
+        let manifest_path = finish_run(dir.path()).unwrap();
+        // Writes after the run are not kept for a run that never ends
+        record(&recorded);
+        assert!(RUN.lock().unwrap().recorded.is_empty());
+        let manifest = RunManifest::load(&manifest_path).unwrap();
+        let paths: Vec<&str> = manifest.artifacts.iter().map(|a| a.path.as_str()).collect();
+        assert_eq!(paths, ["pypi_packages/requests.txt", "unrecorded.md"]);
+
+        let requests = &manifest.artifacts[0];
+        assert_eq!(requests.source_url.as_deref(), Some("https://pypi.org/project/requests"));
+        assert_eq!(requests.processor.as_deref(), Some("pypi"));
+        assert_eq!(requests.size, 8);
+        assert_eq!(requests.sha256, perf::hash_bytes(b"analysis"));
+        assert!(manifest.artifacts[1].source_url.is_none());
+
+        assert!(manifest.verify(dir.path()).is_empty());
+        fs::write(&recorded, "tampered").unwrap();
+        fs::remove_file(dir.path().join("unrecorded.md")).unwrap();
+        assert_eq!(
+            manifest.verify(dir.path()),
+            vec![
+                VerifyIssue::Modified("pypi_packages/requests.txt".into()),
+                VerifyIssue::Missing("unrecorded.md".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_sees_only_its_own_writes() {
+        let dir = tempdir().unwrap();
+        let outside = dir.path().join("outside.txt");
+        let inside = dir.path().join("inside.txt");
+        record(&outside);
+        let ((), collected) = collect(async { record(&inside) }).await;
+        assert_eq!(collected, [inside]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_sends_each_write_as_it_happens() {
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.txt");
+        let (sink, mut written) = mpsc::unbounded_channel();
+        stream(sink, async {
+            record(&first);
+            assert_eq!(written.try_recv().unwrap(), first);
+            record(&second);
+        }).await;
+        assert_eq!(written.try_recv().unwrap(), second);
+        assert!(written.try_recv().is_err());
+    }
+}
@@ -1,9 +1,22 @@
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
+use crate::cache::CacheStats;
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tracing::{info, debug};
 
+/// Prefix of every exported metric
+pub const PREFIX: &str = "llamapackageservice";
+
+/// Environment variable holding the Pushgateway URL, preferred over the config file
+pub const PUSHGATEWAY_URL_ENV_VAR: &str = "LLAMA_PUSHGATEWAY_URL";
+
 /// Collects and tracks application metrics
 pub struct Metrics {
     counters: Arc<RwLock<HashMap<String, u64>>>,
@@ -103,6 +116,366 @@ impl Metrics {
     }
 }
 
+/// Upper bounds, in seconds, of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0];
+
+/// Metrics in the Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct PrometheusText {
+    out: String,
+}
+
+impl PrometheusText {
+    /// An empty exposition
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a metric family: `kind` is `counter`, `gauge` or `histogram`
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        self.out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+        self
+    }
+
+    /// Adds a sample of the current family
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) -> &mut Self {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                .collect();
+            self.out.push_str(&format!("{{{}}}", labels.join(",")));
+        }
+        self.out.push_str(&format!(" {}\n", value));
+        self
+    }
+
+    /// The exposition text
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Counts and latencies of HTTP requests by method, route and status
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    routes: std::sync::Mutex<HashMap<(String, String), RouteStats>>,
+}
+
+#[derive(Debug, Default)]
+struct RouteStats {
+    by_status: HashMap<u16, u64>,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64,
+}
+
+impl RequestMetrics {
+    /// No requests recorded yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a request; `route` should be the route pattern, not the
+    /// requested path, so ids do not each get their own series
+    pub fn record(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = routes.entry((method.to_string(), route.to_string())).or_default();
+        *stats.by_status.entry(status).or_insert(0) += 1;
+        for (bucket, bound) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        stats.count += 1;
+        stats.sum_seconds += seconds;
+    }
+
+    /// Adds `<prefix>_http_requests_total` and `<prefix>_http_request_duration_seconds`
+    pub fn render(&self, prefix: &str, text: &mut PrometheusText) {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut keys: Vec<&(String, String)> = routes.keys().collect();
+        keys.sort();
+
+        let total = format!("{}_http_requests_total", prefix);
+        text.family(&total, "counter", "HTTP requests by method, route and status");
+        for key in &keys {
+            let mut statuses: Vec<(&u16, &u64)> = routes[*key].by_status.iter().collect();
+            statuses.sort();
+            for (status, count) in statuses {
+                let status = status.to_string();
+                text.sample(&total, &[("method", &key.0), ("route", &key.1), ("status", &status)], *count as f64);
+            }
+        }
+
+        let duration = format!("{}_http_request_duration_seconds", prefix);
+        text.family(&duration, "histogram", "HTTP request latency by method and route");
+        for key in &keys {
+            let stats = &routes[*key];
+            let labels = [("method", key.0.as_str()), ("route", key.1.as_str())];
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                let le = bound.to_string();
+                text.sample(&format!("{}_bucket", duration), &[labels[0], labels[1], ("le", &le)], count as f64);
+            }
+            text.sample(&format!("{}_bucket", duration), &[labels[0], labels[1], ("le", "+Inf")], stats.count as f64);
+            text.sample(&format!("{}_sum", duration), &labels, stats.sum_seconds);
+            text.sample(&format!("{}_count", duration), &labels, stats.count as f64);
+        }
+    }
+}
+
+static CACHES: Lazy<Mutex<BTreeMap<String, Arc<CacheCounters>>>> = Lazy::new(Default::default);
+
+/// Hit, miss, eviction and size counters of a cache
+///
+/// Caches registered under one name with [`cache_counters`] add up into one
+/// set, so every link checker of a batch run reports as one cache.
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    entries: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Counts a lookup that found a live entry
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a lookup that found nothing or an expired entry
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an entry of `bytes` coming in
+    pub fn added(&self, bytes: u64) {
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Counts `entries` entries of `bytes` in total going out
+    pub fn removed(&self, entries: u64, bytes: u64) {
+        let _ = self.entries.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n.saturating_sub(entries)));
+        let _ = self.bytes.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n.saturating_sub(bytes)));
+    }
+
+    /// Counts an entry of `bytes` evicted to stay within the cache's limits
+    pub fn evicted(&self, bytes: u64) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        self.removed(1, bytes);
+    }
+
+    /// Counts `entries` entries of `bytes` in total dropped because their TTL ran out
+    pub fn expired(&self, entries: u64, bytes: u64) {
+        self.expirations.fetch_add(entries, Ordering::Relaxed);
+        self.removed(entries, bytes);
+    }
+
+    /// The counters as they are now
+    pub fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.load(Ordering::Relaxed) as usize,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The counters of the cache called `name`, registering them on first use
+pub fn cache_counters(name: &str) -> Arc<CacheCounters> {
+    let mut caches = CACHES.lock().unwrap_or_else(|e| e.into_inner());
+    caches.entry(name.to_string()).or_default().clone()
+}
+
+/// Counters of every registered cache by name
+pub fn cache_stats() -> BTreeMap<String, CacheStats> {
+    let caches = CACHES.lock().unwrap_or_else(|e| e.into_inner());
+    caches.iter().map(|(name, counters)| (name.clone(), counters.snapshot())).collect()
+}
+
+/// Adds `<prefix>_cache_*` counters and gauges for every registered cache
+pub fn render_cache_metrics(prefix: &str, text: &mut PrometheusText) {
+    let caches = cache_stats();
+    let families: [(&str, &str, &str, fn(&CacheStats) -> u64); 6] = [
+        ("hits_total", "counter", "Cache lookups that found a live entry", |s| s.hits),
+        ("misses_total", "counter", "Cache lookups that found nothing or an expired entry", |s| s.misses),
+        ("evictions_total", "counter", "Cache entries evicted to stay within the limits", |s| s.evictions),
+        ("expirations_total", "counter", "Cache entries dropped because their TTL ran out", |s| s.expirations),
+        ("entries", "gauge", "Entries held by the cache", |s| s.entries as u64),
+        ("bytes", "gauge", "Estimated bytes held by the cache", |s| s.bytes),
+    ];
+    for (suffix, kind, help, value) in families {
+        let name = format!("{}_cache_{}", prefix, suffix);
+        text.family(&name, kind, help);
+        for (cache, stats) in &caches {
+            text.sample(&name, &[("cache", cache)], value(stats) as f64);
+        }
+    }
+    let name = format!("{}_cache_hit_ratio", prefix);
+    text.family(&name, "gauge", "Share of cache lookups that found a live entry");
+    for (cache, stats) in &caches {
+        let lookups = stats.hits + stats.misses;
+        let ratio = if lookups == 0 { 0.0 } else { stats.hits as f64 / lookups as f64 };
+        text.sample(&name, &[("cache", cache)], ratio);
+    }
+}
+
+/// Upper bounds, in seconds, of the processing duration histogram buckets
+const PROCESSING_BUCKETS: [f64; 9] = [1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+static PROCESSING: Lazy<Mutex<BTreeMap<String, ProcessingStats>>> = Lazy::new(Default::default);
+static DOWNLOADED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Default)]
+struct ProcessingStats {
+    succeeded: u64,
+    failed: u64,
+    errors: BTreeMap<&'static str, u64>,
+    buckets: [u64; PROCESSING_BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64,
+}
+
+/// Runs `processing` of one target by `processor` and records how it went
+///
+/// Interrupted runs are left out, as they are picked up again later.
+pub async fn record_processing<T>(processor: &str, processing: impl Future<Output = Result<T>>) -> Result<T> {
+    let started = Instant::now();
+    let result = processing.await;
+    if !matches!(result, Err(ProcessorError::Cancelled)) {
+        record_processed(processor, result.as_ref().err(), started.elapsed());
+    }
+    result
+}
+
+/// Records a target `processor` finished with after `elapsed`, failing with `error` if any
+pub fn record_processed(processor: &str, error: Option<&ProcessorError>, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    let mut processors = PROCESSING.lock().unwrap_or_else(|e| e.into_inner());
+    let stats = processors.entry(processor.to_string()).or_default();
+    match error {
+        Some(error) => {
+            stats.failed += 1;
+            *stats.errors.entry(error.kind()).or_insert(0) += 1;
+        }
+        None => stats.succeeded += 1,
+    }
+    for (bucket, bound) in stats.buckets.iter_mut().zip(PROCESSING_BUCKETS) {
+        if seconds <= bound {
+            *bucket += 1;
+        }
+    }
+    stats.count += 1;
+    stats.sum_seconds += seconds;
+}
+
+/// Counts `bytes` of response bodies downloaded
+pub fn record_download(bytes: u64) {
+    DOWNLOADED_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// Adds `<prefix>_packages_processed_total`, `<prefix>_processing_duration_seconds`,
+/// `<prefix>_processing_errors_total` and `<prefix>_downloaded_bytes_total`
+pub fn render_processing_metrics(prefix: &str, text: &mut PrometheusText) {
+    let processors = PROCESSING.lock().unwrap_or_else(|e| e.into_inner());
+
+    let total = format!("{}_packages_processed_total", prefix);
+    text.family(&total, "counter", "Targets processed by processor and outcome");
+    for (processor, stats) in processors.iter() {
+        text.sample(&total, &[("processor", processor), ("outcome", "success")], stats.succeeded as f64)
+            .sample(&total, &[("processor", processor), ("outcome", "failure")], stats.failed as f64);
+    }
+
+    let duration = format!("{}_processing_duration_seconds", prefix);
+    text.family(&duration, "histogram", "Time taken to process a target by processor");
+    for (processor, stats) in processors.iter() {
+        for (bound, count) in PROCESSING_BUCKETS.iter().zip(stats.buckets) {
+            let le = bound.to_string();
+            text.sample(&format!("{}_bucket", duration), &[("processor", processor), ("le", &le)], count as f64);
+        }
+        text.sample(&format!("{}_bucket", duration), &[("processor", processor), ("le", "+Inf")], stats.count as f64);
+        text.sample(&format!("{}_sum", duration), &[("processor", processor)], stats.sum_seconds);
+        text.sample(&format!("{}_count", duration), &[("processor", processor)], stats.count as f64);
+    }
+
+    let errors = format!("{}_processing_errors_total", prefix);
+    text.family(&errors, "counter", "Failed targets by processor and error type");
+    for (processor, stats) in processors.iter() {
+        for (kind, count) in &stats.errors {
+            text.sample(&errors, &[("processor", processor), ("type", kind)], *count as f64);
+        }
+    }
+
+    let name = format!("{}_downloaded_bytes_total", prefix);
+    text.family(&name, "counter", "Bytes of response bodies downloaded from GitHub and the registries")
+        .sample(&name, &[], DOWNLOADED_BYTES.load(Ordering::Relaxed) as f64);
+}
+
+/// Exporting the metrics of command-line runs, the `[metrics]` section of the config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Prometheus Pushgateway each `process` and `batch` run pushes its metrics to,
+    /// such as `http://pushgateway:9091`; `LLAMA_PUSHGATEWAY_URL` takes precedence
+    pub pushgateway_url: Option<String>,
+    /// Job the pushed metrics are grouped under
+    pub job: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { pushgateway_url: None, job: PREFIX.to_string() }
+    }
+}
+
+impl MetricsConfig {
+    /// The Pushgateway to push to, from the environment or the config
+    pub fn pushgateway_url(&self) -> Option<String> {
+        std::env::var(PUSHGATEWAY_URL_ENV_VAR).ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| self.pushgateway_url.clone())
+    }
+}
+
+/// Processing, cache and AI usage metrics of this run
+pub fn run_metrics() -> String {
+    let mut text = PrometheusText::new();
+    render_processing_metrics(PREFIX, &mut text);
+    render_cache_metrics(PREFIX, &mut text);
+    crate::agents::usage::render_metrics(PREFIX, &mut text);
+    text.finish()
+}
+
+/// Pushes `metrics` to the Pushgateway at `url`, replacing what `job` pushed before
+pub async fn push(url: &str, job: &str, metrics: String) -> Result<()> {
+    let mut endpoint = reqwest::Url::parse(url)?;
+    endpoint.path_segments_mut()
+        .map_err(|_| ProcessorError::Config(format!("{} cannot take a path", url)))?
+        .pop_if_empty()
+        .extend(["metrics", "job", job]);
+    let request = http::client()?
+        .put(endpoint)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(metrics);
+    let response = crate::http::send(request).await?;
+    if !response.status().is_success() {
+        return Err(ProcessorError::Network(format!("the Pushgateway answered {}", response.status())));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,4 +515,77 @@ mod tests {
         let value = metrics.get_gauge("test_gauge").await.unwrap();
         assert_eq!(value, 42.5);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_request_metrics_render_as_prometheus_text() {
+        let metrics = RequestMetrics::new();
+        metrics.record("GET", "/api/jobs/:job_id", 200, Duration::from_millis(20));
+        metrics.record("GET", "/api/jobs/:job_id", 404, Duration::from_secs(3));
+
+        let mut text = PrometheusText::new();
+        text.family("lps_up", "gauge", "Whether the service is up").sample("lps_up", &[], 1.0);
+        metrics.render("lps", &mut text);
+        let text = text.finish();
+
+        assert!(text.contains("# TYPE lps_up gauge\nlps_up 1\n"));
+        assert!(text.contains("lps_http_requests_total{method=\"GET\",route=\"/api/jobs/:job_id\",status=\"404\"} 1\n"));
+        assert!(text.contains("lps_http_request_duration_seconds_bucket{method=\"GET\",route=\"/api/jobs/:job_id\",le=\"0.025\"} 1\n"));
+        assert!(text.contains("lps_http_request_duration_seconds_bucket{method=\"GET\",route=\"/api/jobs/:job_id\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("lps_http_request_duration_seconds_count{method=\"GET\",route=\"/api/jobs/:job_id\"} 2\n"));
+        assert_eq!(escape_label("a\"b\\"), "a\\\"b\\\\");
+    }
+
+    #[test]
+    fn test_cache_counters_are_shared_by_name() {
+        let counters = cache_counters("test_shared");
+        counters.added(10);
+        counters.hit();
+        let other = cache_counters("test_shared");
+        other.added(5);
+        other.miss();
+        other.evicted(10);
+
+        let stats = cache_stats()["test_shared"];
+        assert_eq!((stats.entries, stats.bytes, stats.hits, stats.misses, stats.evictions), (1, 5, 1, 1, 1));
+        let mut text = PrometheusText::new();
+        render_cache_metrics("lps", &mut text);
+        let text = text.finish();
+        assert!(text.contains("lps_cache_hits_total{cache=\"test_shared\"} 1\n"));
+        assert!(text.contains("lps_cache_hit_ratio{cache=\"test_shared\"} 0.5\n"));
+    }
+
+    #[tokio::test]
+    async fn test_processing_runs_are_exported_and_pushed() {
+        record_processed("Test Processor", None, Duration::from_secs(3));
+        record_processed("Test Processor", Some(&ProcessorError::Network("reset".into())), Duration::from_secs(40));
+        let cancelled = record_processing("Test Processor", async { Err::<(), _>(ProcessorError::Cancelled) }).await;
+        assert!(cancelled.is_err());
+        record_download(1024);
+
+        let mut text = PrometheusText::new();
+        render_processing_metrics("lps", &mut text);
+        let text = text.finish();
+        assert!(text.contains("lps_packages_processed_total{processor=\"Test Processor\",outcome=\"success\"} 1\n"));
+        assert!(text.contains("lps_packages_processed_total{processor=\"Test Processor\",outcome=\"failure\"} 1\n"));
+        assert!(text.contains("lps_processing_duration_seconds_bucket{processor=\"Test Processor\",le=\"5\"} 1\n"));
+        assert!(text.contains("lps_processing_duration_seconds_bucket{processor=\"Test Processor\",le=\"60\"} 2\n"));
+        assert!(text.contains("lps_processing_duration_seconds_sum{processor=\"Test Processor\"} 43\n"));
+        // Interrupted runs are not counted
+        assert!(text.contains("lps_processing_duration_seconds_count{processor=\"Test Processor\"} 2\n"));
+        assert!(text.contains("lps_processing_errors_total{processor=\"Test Processor\",type=\"network\"} 1\n"));
+        assert!(!text.contains("type=\"cancelled\""));
+        assert!(text.contains("# TYPE lps_downloaded_bytes_total counter\nlps_downloaded_bytes_total "));
+
+        let mut server = mockito::Server::new_async().await;
+        let gateway = server.mock("PUT", "/metrics/job/lps%20nightly")
+            .match_header("content-type", "text/plain; version=0.0.4")
+            .match_body(mockito::Matcher::Regex("llamapackageservice_packages_processed_total".into()))
+            .with_status(200)
+            .create_async().await;
+        push(&format!("{}/", server.url()), "lps nightly", run_metrics()).await.unwrap();
+        gateway.assert_async().await;
+        let missing = server.mock("PUT", "/metrics/job/gone").with_status(404).create_async().await;
+        assert!(push(&server.url(), "gone", run_metrics()).await.is_err());
+        missing.assert_async().await;
+    }
+}
@@ -1,5 +1,6 @@
 use crate::error::{ProcessorError, Result};
 use crate::config::Config;
+use crate::http;
 use super::types::*;
 use reqwest::Client;
 use std::time::Duration;
@@ -16,7 +17,7 @@ pub struct MLXClient {
 
 impl MLXClient {
     pub fn new(config: &Config) -> Result<Self> {
-        let client = Client::builder()
+        let client = http::client_builder()
             .timeout(Duration::from_secs(config.mlx_settings.timeout_seconds))
             .build()
             .map_err(|e| ProcessorError::Message(e.to_string()))?;
@@ -1,5 +1,6 @@
 use crate::error::ProcessorError;
 use crate::config::Config;
+use crate::http;
 use crate::mlx::types::*;
 use serde::{Serialize, Deserialize};
 use reqwest::Client;
@@ -17,7 +18,7 @@ pub struct MLXClient {
 
 impl MLXClient {
     pub fn new(config: &Config) -> Result<Self, ProcessorError> {
-        let client = Client::builder()
+        let client = http::client_builder()
             .timeout(Duration::from_secs(MLX_API_TIMEOUT))
             .build()
             .map_err(|e| ProcessorError::Network(e.to_string()))?;
@@ -0,0 +1,824 @@
+//! Release monitoring and "what's new" feeds
+//!
+//! Users register packages they care about; the monitor periodically asks the
+//! registry for the latest version, runs the normal processor on anything new
+//! and records a feed entry pointing at a delta report and a summary of the
+//! upstream release notes. Entries are exposed as Atom and JSON Feed by the
+//! server.
+//!
+//! Packages registered from a workspace are only listed to that workspace, are
+//! processed into its directory and stay out of the public feeds.
+//!
+//! The delta report compares the files and dependencies of the release archive
+//! with those of the previously analyzed release, whose manifest is kept under
+//! `_monitor/manifests`.
+
+use crate::chunking::{self, Boundary};
+use crate::config::Config;
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::processors::{common, ProcessorFactory};
+use crate::run_diff::{self, RunSnapshot};
+use crate::workspace;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Number of feed entries kept; older ones are dropped
+const MAX_FEED_ENTRIES: usize = 200;
+
+/// Token budget for the changelog summary attached to each entry
+const CHANGELOG_SUMMARY_TOKENS: usize = 120;
+
+/// Package registries the monitor can poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ecosystem {
+    /// Python Package Index
+    PyPi,
+    /// npm registry
+    Npm,
+    /// crates.io
+    Crates,
+}
+
+impl Ecosystem {
+    /// Short lowercase name used in identifiers
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ecosystem::PyPi => "pypi",
+            Ecosystem::Npm => "npm",
+            Ecosystem::Crates => "crates",
+        }
+    }
+
+    /// URL handed to the processor factory when a new release is processed
+    pub fn package_url(&self, name: &str) -> String {
+        match self {
+            Ecosystem::PyPi => format!("https://pypi.org/project/{}/", name),
+            Ecosystem::Npm => format!("https://www.npmjs.com/package/{}", name),
+            Ecosystem::Crates => format!("https://crates.io/crates/{}", name),
+        }
+    }
+
+    /// Registry metadata endpoint for the package
+    fn metadata_url(&self, name: &str) -> String {
+        match self {
+            Ecosystem::PyPi => format!("https://pypi.org/pypi/{}/json", name),
+            Ecosystem::Npm => format!("https://registry.npmjs.org/{}/latest", name),
+            Ecosystem::Crates => format!("https://crates.io/api/v1/crates/{}", name),
+        }
+    }
+}
+
+/// A package registered for monitoring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedPackage {
+    /// Stable identifier, `<ecosystem>:<name>`, prefixed with `<workspace>:`
+    /// for a workspace's packages
+    pub id: String,
+    /// Registry the package lives in
+    pub ecosystem: Ecosystem,
+    /// Package name
+    pub name: String,
+    /// Latest version that has been analyzed
+    pub last_version: Option<String>,
+    /// When the package was registered
+    pub added_at: DateTime<Utc>,
+    /// When the registry was last polled
+    pub last_checked: Option<DateTime<Utc>>,
+    /// Workspace that registered the package; `None` for operators
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+}
+
+impl WatchedPackage {
+    /// Whether a caller confined to `workspace` may see the package; callers
+    /// without a workspace see every package
+    pub fn is_visible_to(&self, workspace: Option<&str>) -> bool {
+        workspace.map_or(true, |name| self.workspace.as_deref() == Some(name))
+    }
+}
+
+/// One "new version analyzed" entry in the feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedEntry {
+    /// Unique entry identifier
+    pub id: String,
+    /// Identifier of the watched package
+    pub package_id: String,
+    /// Package name
+    pub name: String,
+    /// Registry the package lives in
+    pub ecosystem: Ecosystem,
+    /// Newly analyzed version
+    pub version: String,
+    /// Version analyzed before this one, if any
+    pub previous_version: Option<String>,
+    /// When the analysis finished
+    pub analyzed_at: DateTime<Utc>,
+    /// Summary of the upstream release notes, if any were found
+    pub changelog_summary: Option<String>,
+    /// Path of the delta report on disk
+    pub report_path: PathBuf,
+    /// Workspace of the watched package; only entries without one are published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+}
+
+/// Latest release information fetched from a registry
+#[derive(Debug, Clone)]
+pub struct Release {
+    /// Latest published version
+    pub version: String,
+    /// Source repository URL, used to look up release notes
+    pub repository: Option<String>,
+    /// URL of the release archive, used to list its files
+    pub archive: Option<String>,
+    /// Dependencies the registry lists for the release, name to requirement
+    pub dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MonitorState {
+    packages: Vec<WatchedPackage>,
+    entries: Vec<FeedEntry>,
+}
+
+/// Tracks watched packages and the feed of analyzed releases
+///
+/// State is persisted as JSON under `<output_dir>/_monitor` so registrations
+/// and feed history survive restarts.
+pub struct PackageMonitor {
+    state: Mutex<MonitorState>,
+    /// Held for a whole `check_all` pass so the poller and API calls never process a release twice
+    checking: Mutex<()>,
+    dir: PathBuf,
+    config: std::sync::RwLock<Arc<Config>>,
+    client: Client,
+}
+
+impl PackageMonitor {
+    /// Loads the monitor state stored under the configured output directory
+    pub fn load(config: Arc<Config>) -> Result<Self> {
+        let dir = monitor_dir(&config.output_dir);
+        std::fs::create_dir_all(dir.join("reports"))?;
+
+        let state = match std::fs::read(dir.join("state.json")) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MonitorState::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            state: Mutex::new(state),
+            checking: Mutex::new(()),
+            dir,
+            config: std::sync::RwLock::new(config),
+            client: common::create_client_with_user_agent()?,
+        })
+    }
+
+    /// Switches to `config` for the checks made from now on, such as with a rotated GitHub token
+    pub fn set_config(&self, config: Arc<Config>) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Registers a package for `workspace`; registering an already watched package is a no-op
+    pub async fn watch(&self, ecosystem: Ecosystem, name: &str, workspace: Option<&str>) -> Result<WatchedPackage> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ProcessorError::Validation("Package name cannot be empty".into()));
+        }
+
+        let mut state = self.state.lock().await;
+        let id = match workspace {
+            Some(workspace) => format!("{}:{}:{}", workspace, ecosystem.as_str(), name),
+            None => format!("{}:{}", ecosystem.as_str(), name),
+        };
+        if let Some(existing) = state.packages.iter().find(|p| p.id == id) {
+            return Ok(existing.clone());
+        }
+
+        let package = WatchedPackage {
+            id,
+            ecosystem,
+            name: name.to_string(),
+            last_version: None,
+            added_at: Utc::now(),
+            last_checked: None,
+            workspace: workspace.map(str::to_string),
+        };
+        state.packages.push(package.clone());
+        self.persist(&state)?;
+        Ok(package)
+    }
+
+    /// Stops watching a package visible from `workspace`, returning whether it was registered
+    pub async fn unwatch(&self, id: &str, workspace: Option<&str>) -> Result<bool> {
+        let mut state = self.state.lock().await;
+        let before = state.packages.len();
+        state.packages.retain(|p| p.id != id || !p.is_visible_to(workspace));
+        let removed = state.packages.len() != before;
+        if removed {
+            self.persist(&state)?;
+        }
+        Ok(removed)
+    }
+
+    /// Lists the watched packages visible from `workspace`
+    pub async fn packages(&self, workspace: Option<&str>) -> Vec<WatchedPackage> {
+        self.state.lock().await.packages.iter()
+            .filter(|p| p.is_visible_to(workspace))
+            .cloned()
+            .collect()
+    }
+
+    /// Lists the public feed entries, newest first
+    pub async fn entries(&self) -> Vec<FeedEntry> {
+        self.state.lock().await.entries.iter()
+            .filter(|e| e.workspace.is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the delta report for a public feed entry
+    pub async fn report(&self, entry_id: &str) -> Result<String> {
+        let path = {
+            let state = self.state.lock().await;
+            state.entries.iter()
+                .find(|e| e.id == entry_id && e.workspace.is_none())
+                .map(|e| e.report_path.clone())
+                .ok_or_else(|| ProcessorError::Message(format!("Feed entry not found: {}", entry_id)))?
+        };
+        Ok(tokio::fs::read_to_string(path).await?)
+    }
+
+    /// Polls every watched package visible from `workspace` once, processing any new releases
+    ///
+    /// Returns the entries added by this pass. Failures for one package are
+    /// logged and do not stop the others from being checked.
+    pub async fn check_all(&self, workspace: Option<&str>) -> Vec<FeedEntry> {
+        let _checking = self.checking.lock().await;
+        let packages = self.packages(workspace).await;
+        let mut added = Vec::new();
+
+        for package in packages {
+            match self.check_package(&package).await {
+                Ok(Some(entry)) => added.push(entry),
+                Ok(None) => {}
+                Err(e) => warn!("Release check failed for {}: {}", package.id, e),
+            }
+        }
+
+        added
+    }
+
+    /// Polls the registry at a fixed interval until the task is dropped
+    pub fn spawn(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let added = self.check_all(None).await;
+                if !added.is_empty() {
+                    info!("Analyzed {} new release(s)", added.len());
+                }
+            }
+        })
+    }
+
+    async fn check_package(&self, package: &WatchedPackage) -> Result<Option<FeedEntry>> {
+        let release = self.latest_release(package.ecosystem, &package.name).await?;
+
+        if package.last_version.as_deref() == Some(release.version.as_str()) {
+            self.update_package(&package.id, |p| p.last_checked = Some(Utc::now())).await?;
+            return Ok(None);
+        }
+
+        info!("New release of {}: {}", package.id, release.version);
+        let url = package.ecosystem.package_url(&package.name);
+        let processor = ProcessorFactory::create_processor(&url)?;
+        let mut config = Config::clone(&self.config());
+        if let Some(name) = &package.workspace {
+            config.output_dir = workspace::root(&config.output_dir, name);
+        }
+        processor.process(&url, &config.output_dir, &config).await?;
+
+        let changelog_summary = match &release.repository {
+            Some(repo) => self.release_notes(repo, &release.version).await
+                .map(|notes| chunking::truncate(&notes, CHANGELOG_SUMMARY_TOKENS, Boundary::Paragraph)),
+            None => None,
+        };
+        // The report is still worth publishing without the file and dependency changes
+        let manifest = match self.release_manifest(package, &release).await {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                warn!("Could not list the contents of {} {}: {}", package.id, release.version, e);
+                None
+            }
+        };
+
+        let entry = self.record_release(package, &release.version, changelog_summary, manifest).await?;
+        Ok(Some(entry))
+    }
+
+    /// Records a newly analyzed release and writes its delta report
+    ///
+    /// `manifest` lists the files and dependencies of the release; it is
+    /// compared with the one stored for the previous release and then replaces it.
+    pub async fn record_release(
+        &self,
+        package: &WatchedPackage,
+        version: &str,
+        changelog_summary: Option<String>,
+        manifest: Option<RunSnapshot>,
+    ) -> Result<FeedEntry> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let report_path = self.dir.join("reports").join(format!(
+            "{}-{}.md",
+            common::sanitize_filename(&package.id),
+            common::sanitize_filename(version),
+        ));
+
+        let entry = FeedEntry {
+            id,
+            package_id: package.id.clone(),
+            name: package.name.clone(),
+            ecosystem: package.ecosystem,
+            version: version.to_string(),
+            previous_version: package.last_version.clone(),
+            analyzed_at: Utc::now(),
+            changelog_summary,
+            report_path,
+            workspace: package.workspace.clone(),
+        };
+        let manifest_path = self.manifest_path(&package.id);
+        let previous: Option<RunSnapshot> = match tokio::fs::read(&manifest_path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).ok(),
+            Err(_) => None,
+        };
+        let changes = match (&previous, &manifest) {
+            (Some(previous), Some(current)) => Some(contents_changes(previous, current)),
+            _ => None,
+        };
+        tokio::fs::write(&entry.report_path, delta_report(&entry, changes.as_deref())).await?;
+        if let Some(manifest) = &manifest {
+            if let Some(parent) = manifest_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&manifest_path, serde_json::to_vec_pretty(manifest)?).await?;
+        }
+
+        let mut state = self.state.lock().await;
+        if let Some(p) = state.packages.iter_mut().find(|p| p.id == package.id) {
+            p.last_version = Some(version.to_string());
+            p.last_checked = Some(entry.analyzed_at);
+        }
+        state.entries.insert(0, entry.clone());
+        state.entries.truncate(MAX_FEED_ENTRIES);
+        self.persist(&state)?;
+
+        Ok(entry)
+    }
+
+    async fn update_package(&self, id: &str, f: impl FnOnce(&mut WatchedPackage)) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(p) = state.packages.iter_mut().find(|p| p.id == id) {
+            f(p);
+        }
+        self.persist(&state)
+    }
+
+    /// Path of the stored manifest of the latest analyzed release of a package
+    fn manifest_path(&self, package_id: &str) -> PathBuf {
+        self.dir.join("manifests").join(format!("{}.json", common::sanitize_filename(package_id)))
+    }
+
+    /// Downloads the release archive and lists its files and dependencies
+    ///
+    /// Returns `None` when the registry does not say where the archive is.
+    async fn release_manifest(&self, package: &WatchedPackage, release: &Release) -> Result<Option<RunSnapshot>> {
+        let Some(url) = &release.archive else { return Ok(None) };
+        let response = http::fetch(self.client.get(url)).await?;
+        if !response.status().is_success() {
+            return Err(ProcessorError::Network(format!("{} returned {}", url, response.status())));
+        }
+        let (id, dependencies) = (package.id.clone(), release.dependencies.clone());
+        tokio::task::spawn_blocking(move || archive_manifest(&id, response.bytes(), dependencies))
+            .await
+            .map_err(|e| ProcessorError::Processing(format!("Archive listing panicked: {}", e)))?
+            .map(Some)
+    }
+
+    fn persist(&self, state: &MonitorState) -> Result<()> {
+        let json = serde_json::to_vec_pretty(state)?;
+        std::fs::write(self.dir.join("state.json"), json)?;
+        Ok(())
+    }
+
+    /// Fetches the latest version published to the registry
+    pub async fn latest_release(&self, ecosystem: Ecosystem, name: &str) -> Result<Release> {
+        let response = http::send(self.client.get(ecosystem.metadata_url(name))).await?;
+        if !response.status().is_success() {
+            let message = format!("{} returned {} for {}", ecosystem.metadata_url(name), response.status(), name);
+            return Err(match ecosystem {
+                Ecosystem::PyPi => ProcessorError::PyPiApi(message),
+                Ecosystem::Npm => ProcessorError::NpmApi(message),
+                Ecosystem::Crates => ProcessorError::Network(message),
+            });
+        }
+        let body: Value = response.json().await?;
+
+        let (version, repository) = match ecosystem {
+            Ecosystem::PyPi => (
+                body["info"]["version"].as_str(),
+                body["info"]["project_urls"].as_object()
+                    .and_then(|urls| urls.values().filter_map(Value::as_str).find(|u| u.contains("github.com")))
+                    .or_else(|| body["info"]["home_page"].as_str()),
+            ),
+            Ecosystem::Npm => (
+                body["version"].as_str(),
+                body["repository"]["url"].as_str().or_else(|| body["repository"].as_str()),
+            ),
+            Ecosystem::Crates => (
+                body["crate"]["max_stable_version"].as_str().or_else(|| body["crate"]["max_version"].as_str()),
+                body["crate"]["repository"].as_str(),
+            ),
+        };
+
+        let version = version
+            .ok_or_else(|| ProcessorError::Parse(format!("No version found for {}", name)))?
+            .to_string();
+        let (archive, dependencies) = match ecosystem {
+            // Prefer the source distribution; wheels leave out tests and packaging files
+            Ecosystem::PyPi => (
+                body["urls"].as_array().and_then(|files| {
+                    files.iter().find(|f| f["packagetype"] == "sdist").or_else(|| files.first())
+                }).and_then(|f| f["url"].as_str()).map(str::to_string),
+                body["info"]["requires_dist"].as_array()
+                    .map(|requirements| requirements.iter().filter_map(Value::as_str).filter_map(python_requirement).collect())
+                    .unwrap_or_default(),
+            ),
+            Ecosystem::Npm => (
+                body["dist"]["tarball"].as_str().map(str::to_string),
+                body["dependencies"].as_object()
+                    .map(|deps| deps.iter().map(|(name, req)| (name.clone(), req.as_str().unwrap_or("*").to_string())).collect())
+                    .unwrap_or_default(),
+            ),
+            // The crate archive carries its Cargo.toml
+            Ecosystem::Crates => (
+                Some(format!("https://crates.io/api/v1/crates/{}/{}/download", name, version)),
+                BTreeMap::new(),
+            ),
+        };
+        Ok(Release { version, repository: repository.map(str::to_string), archive, dependencies })
+    }
+
+    /// Looks up GitHub release notes for a version, trying `v`-prefixed tags too
+    async fn release_notes(&self, repository: &str, version: &str) -> Option<String> {
+        let (owner, repo) = github_owner_repo(repository)?;
+        for tag in [format!("v{}", version), version.to_string()] {
+            let url = format!("https://api.github.com/repos/{}/{}/releases/tags/{}", owner, repo, tag);
+            let mut request = self.client.get(&url);
+            if let Some(token) = &self.config().github_token {
+                request = request.bearer_auth(token);
+            }
+            let Ok(response) = http::send(request).await else { continue };
+            if !response.status().is_success() {
+                continue;
+            }
+            if let Ok(body) = response.json::<Value>().await {
+                if let Some(notes) = body["body"].as_str().filter(|b| !b.trim().is_empty()) {
+                    return Some(notes.to_string());
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Extracts `(owner, repo)` from a GitHub repository URL in any common form
+fn github_owner_repo(url: &str) -> Option<(String, String)> {
+    let rest = &url[url.find("github.com")? + "github.com".len()..];
+    let mut parts = rest.trim_start_matches([':', '/']).split('/');
+    let owner = parts.next().filter(|s| !s.is_empty())?;
+    let repo = parts.next()?.trim_end_matches(".git");
+    if repo.is_empty() {
+        return None;
+    }
+    Some((owner.to_string(), repo.to_string()))
+}
+
+/// Lists the files and dependencies of a release archive, a gzipped tarball or a zip
+///
+/// Archives usually hold everything in one top-level directory named after the
+/// version; paths are taken relative to it so they line up between releases.
+/// `dependencies` declared by the registry are added to those found in the
+/// archive's manifests.
+fn archive_manifest(package_id: &str, archive: &[u8], dependencies: BTreeMap<String, String>) -> Result<RunSnapshot> {
+    let dir = tempfile::tempdir()?;
+    if archive.starts_with(b"PK\x03\x04") {
+        common::extract_archive(archive, dir.path())?;
+    } else {
+        tar::Archive::new(flate2::read::GzDecoder::new(archive)).unpack(dir.path())?;
+    }
+    let entries: Vec<_> = std::fs::read_dir(dir.path())?.filter_map(|e| e.ok()).collect();
+    let root = match entries.as_slice() {
+        [only] if only.path().is_dir() => only.path(),
+        _ => dir.path().to_path_buf(),
+    };
+    let mut manifest = RunSnapshot::capture(package_id, &root);
+    manifest.dependencies.extend(dependencies);
+    Ok(manifest)
+}
+
+/// Splits a PEP 508 requirement such as `idna (<4,>=2.5) ; extra == "x"` into its name and version
+fn python_requirement(requirement: &str) -> Option<(String, String)> {
+    let requirement = requirement.split(';').next()?.trim();
+    let end = requirement.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))).unwrap_or(requirement.len());
+    let (name, version) = requirement.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+    let version = version.trim().trim_start_matches('(').trim_end_matches(')').trim();
+    Some((name.to_lowercase(), if version.is_empty() { "*".to_string() } else { version.to_string() }))
+}
+
+/// Markdown sections with the files and dependencies that changed between two releases
+fn contents_changes(previous: &RunSnapshot, current: &RunSnapshot) -> String {
+    let mut out = String::new();
+    run_diff::push_file_changes(&mut out, previous, current);
+    run_diff::push_dependency_changes(&mut out, previous, current);
+    out
+}
+
+/// Builds the markdown delta report for a feed entry
+///
+/// `changes` holds the file and dependency sections when both releases could be listed.
+fn delta_report(entry: &FeedEntry, changes: Option<&str>) -> String {
+    let mut report = format!("# {} {}\n\n", entry.name, entry.version);
+    report.push_str(&format!("**Registry:** {}\n", entry.ecosystem.package_url(&entry.name)));
+    match &entry.previous_version {
+        Some(previous) => report.push_str(&format!("**Change:** {} → {}\n", previous, entry.version)),
+        None => report.push_str("**Change:** first analyzed version\n"),
+    }
+    report.push_str(&format!("**Analyzed:** {}\n\n", entry.analyzed_at.format("%Y-%m-%d %H:%M:%S UTC")));
+    match (changes, &entry.previous_version) {
+        (Some(changes), _) => report.push_str(changes),
+        (None, Some(previous)) => report.push_str(&format!("The files and dependencies could not be compared with {}.\n\n", previous)),
+        (None, None) => {}
+    }
+    report.push_str("## Release Notes\n\n");
+    report.push_str(entry.changelog_summary.as_deref().unwrap_or("No release notes were published for this version."));
+    report.push('\n');
+    report
+}
+
+fn entry_title(entry: &FeedEntry) -> String {
+    match &entry.previous_version {
+        Some(previous) => format!("{} {} analyzed (was {})", entry.name, entry.version, previous),
+        None => format!("{} {} analyzed", entry.name, entry.version),
+    }
+}
+
+/// Renders feed entries as an Atom document
+///
+/// `base_url` is the public address of the server, used to build links to
+/// the delta reports.
+pub fn atom_feed(entries: &[FeedEntry], base_url: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let updated = entries.first().map_or_else(Utc::now, |e| e.analyzed_at);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str("  <title>LlamaPackageService: new versions analyzed</title>\n");
+    xml.push_str(&format!("  <id>{}/api/feed.atom</id>\n", xml_escape(base_url)));
+    xml.push_str(&format!("  <link rel=\"self\" href=\"{}/api/feed.atom\"/>\n", xml_escape(base_url)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+
+    for entry in entries {
+        let link = format!("{}/api/monitor/reports/{}", base_url, entry.id);
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>urn:uuid:{}</id>\n", entry.id));
+        xml.push_str(&format!("    <title>{}</title>\n", xml_escape(&entry_title(entry))));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", xml_escape(&link)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", entry.analyzed_at.to_rfc3339()));
+        if let Some(summary) = &entry.changelog_summary {
+            xml.push_str(&format!("    <summary>{}</summary>\n", xml_escape(summary)));
+        }
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Renders feed entries as a JSON Feed 1.1 document
+pub fn json_feed(entries: &[FeedEntry], base_url: &str) -> Value {
+    let base_url = base_url.trim_end_matches('/');
+    let items: Vec<Value> = entries.iter().map(|entry| json!({
+        "id": entry.id,
+        "title": entry_title(entry),
+        "url": format!("{}/api/monitor/reports/{}", base_url, entry.id),
+        "external_url": entry.ecosystem.package_url(&entry.name),
+        "content_text": entry.changelog_summary.clone().unwrap_or_default(),
+        "date_published": entry.analyzed_at.to_rfc3339(),
+        "tags": [entry.ecosystem, entry.name],
+        "_package": {
+            "id": entry.package_id,
+            "version": entry.version,
+            "previous_version": entry.previous_version,
+        },
+    })).collect();
+
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": "LlamaPackageService: new versions analyzed",
+        "home_page_url": base_url,
+        "feed_url": format!("{}/api/feed.json", base_url),
+        "items": items,
+    })
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns the directory the monitor stores its state in
+pub fn monitor_dir(output_dir: &Path) -> PathBuf {
+    output_dir.join("_monitor")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn monitor_in(dir: &Path) -> PackageMonitor {
+        PackageMonitor::load(Arc::new(Config::new(dir.to_path_buf()))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_watch_and_record_release() {
+        let dir = tempdir().unwrap();
+        let monitor = monitor_in(dir.path());
+
+        let package = monitor.watch(Ecosystem::Crates, "serde", None).await.unwrap();
+        assert_eq!(package.id, "crates:serde");
+        monitor.watch(Ecosystem::Crates, "serde", None).await.unwrap();
+        assert_eq!(monitor.packages(None).await.len(), 1);
+
+        let entry = monitor.record_release(&package, "1.0.200", Some("Bug fixes".into()), None).await.unwrap();
+        let report = monitor.report(&entry.id).await.unwrap();
+        assert!(report.contains("first analyzed version"));
+        assert!(report.contains("Bug fixes"));
+
+        // State survives a reload
+        let reloaded = monitor_in(dir.path());
+        assert_eq!(reloaded.packages(None).await[0].last_version.as_deref(), Some("1.0.200"));
+        assert_eq!(reloaded.entries().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_workspace_packages_stay_private() {
+        let dir = tempdir().unwrap();
+        let monitor = monitor_in(dir.path());
+        monitor.watch(Ecosystem::Crates, "serde", None).await.unwrap();
+        let package = monitor.watch(Ecosystem::Crates, "serde", Some("team-a")).await.unwrap();
+        assert_eq!(package.id, "team-a:crates:serde");
+
+        assert_eq!(monitor.packages(None).await.len(), 2);
+        assert_eq!(monitor.packages(Some("team-a")).await.len(), 1);
+        assert!(monitor.packages(Some("team-b")).await.is_empty());
+        assert!(!monitor.unwatch(&package.id, Some("team-b")).await.unwrap());
+        assert!(!monitor.unwatch("crates:serde", Some("team-a")).await.unwrap());
+
+        let entry = monitor.record_release(&package, "1.0.200", None, None).await.unwrap();
+        assert!(monitor.entries().await.is_empty());
+        assert!(monitor.report(&entry.id).await.is_err());
+        assert!(monitor.unwatch(&package.id, Some("team-a")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reports_are_kept_per_package() {
+        let dir = tempdir().unwrap();
+        let monitor = monitor_in(dir.path());
+        let crate_package = monitor.watch(Ecosystem::Crates, "demo", None).await.unwrap();
+        let npm_package = monitor.watch(Ecosystem::Npm, "demo", None).await.unwrap();
+        let private = monitor.watch(Ecosystem::Crates, "demo", Some("team-a")).await.unwrap();
+
+        let mut paths = Vec::new();
+        for package in [&crate_package, &npm_package, &private] {
+            let notes = format!("Notes for {}", package.id);
+            let entry = monitor.record_release(package, "1.0.0", Some(notes), None).await.unwrap();
+            paths.push(entry.report_path);
+        }
+        assert_eq!(paths.iter().collect::<std::collections::HashSet<_>>().len(), 3);
+        let report = std::fs::read_to_string(&paths[0]).unwrap();
+        assert!(report.contains("Notes for crates:demo"));
+    }
+
+    fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_report_compares_files_and_dependencies() {
+        let dir = tempdir().unwrap();
+        let monitor = monitor_in(dir.path());
+        let package = monitor.watch(Ecosystem::Crates, "demo", None).await.unwrap();
+
+        let first = tarball(&[
+            ("demo-1.0.0/Cargo.toml", "[package]\nname = \"demo\"\n\n[dependencies]\nserde = \"1.0\"\n"),
+            ("demo-1.0.0/src/lib.rs", "pub fn a() {}\n"),
+            ("demo-1.0.0/README.md", "# Demo\n"),
+        ]);
+        let manifest = archive_manifest(&package.id, &first, BTreeMap::new()).unwrap();
+        assert_eq!(manifest.dependencies.get("serde").map(String::as_str), Some("1.0"));
+        monitor.record_release(&package, "1.0.0", None, Some(manifest)).await.unwrap();
+
+        let second = tarball(&[
+            ("demo-1.1.0/Cargo.toml", "[package]\nname = \"demo\"\n\n[dependencies]\nserde = \"1.1\"\nregex = \"1\"\n"),
+            ("demo-1.1.0/src/lib.rs", "pub fn a() {}\npub fn b() {}\n"),
+            ("demo-1.1.0/src/new.rs", "\n"),
+        ]);
+        let manifest = archive_manifest(&package.id, &second, BTreeMap::new()).unwrap();
+        let package = monitor.packages(None).await.remove(0);
+        let entry = monitor.record_release(&package, "1.1.0", None, Some(manifest)).await.unwrap();
+
+        let report = monitor.report(&entry.id).await.unwrap();
+        assert!(report.contains("1 added, 1 removed, 2 modified"), "{}", report);
+        assert!(report.contains("- `src/new.rs`"));
+        assert!(report.contains("- `README.md`"));
+        assert!(report.contains("- Changed `serde` 1.0 → 1.1"));
+        assert!(report.contains("- Added `regex` 1"));
+    }
+
+    #[test]
+    fn test_python_requirement() {
+        assert_eq!(python_requirement("idna (<4,>=2.5)"), Some(("idna".into(), "<4,>=2.5".into())));
+        assert_eq!(python_requirement("PySocks!=1.5.7,>=1.5.6; extra == \"socks\""), Some(("pysocks".into(), "!=1.5.7,>=1.5.6".into())));
+        assert_eq!(python_requirement("certifi"), Some(("certifi".into(), "*".into())));
+        assert_eq!(python_requirement("; extra == \"x\""), None);
+    }
+
+    #[test]
+    fn test_feeds_link_reports_and_escape() {
+        let entry = FeedEntry {
+            id: "abc".into(),
+            package_id: "npm:left-pad".into(),
+            name: "left-pad".into(),
+            ecosystem: Ecosystem::Npm,
+            version: "2.0.0".into(),
+            previous_version: Some("1.3.0".into()),
+            analyzed_at: Utc::now(),
+            changelog_summary: Some("Fixes <script> & more".into()),
+            report_path: PathBuf::from("report.md"),
+            workspace: None,
+        };
+
+        let atom = atom_feed(std::slice::from_ref(&entry), "http://localhost:8000/");
+        assert!(atom.contains("<link href=\"http://localhost:8000/api/monitor/reports/abc\"/>"));
+        assert!(atom.contains("Fixes &lt;script&gt; &amp; more"));
+        assert!(atom.contains("left-pad 2.0.0 analyzed (was 1.3.0)"));
+
+        let json = json_feed(&[entry], "http://localhost:8000");
+        assert_eq!(json["items"][0]["url"], "http://localhost:8000/api/monitor/reports/abc");
+        assert_eq!(json["items"][0]["_package"]["previous_version"], "1.3.0");
+    }
+
+    #[test]
+    fn test_github_owner_repo() {
+        assert_eq!(
+            github_owner_repo("git+https://github.com/serde-rs/serde.git"),
+            Some(("serde-rs".into(), "serde".into()))
+        );
+        assert_eq!(
+            github_owner_repo("git@github.com:psf/requests"),
+            Some(("psf".into(), "requests".into()))
+        );
+        assert_eq!(github_owner_repo("https://gitlab.com/a/b"), None);
+    }
+}
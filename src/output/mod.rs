@@ -0,0 +1,3 @@
+//! Output destinations
+
+pub mod storage;
@@ -7,6 +7,12 @@ use chrono::{DateTime, Utc, NaiveDateTime};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use crate::templates::{self, TemplateKind};
+use crate::compression::{self, CompressionIndex};
+use crate::encryption;
+use crate::site;
+use crate::retention;
+use log::info;
 
 /// Structure that manages paths to output directories for different package types
 ///
@@ -107,21 +113,230 @@ impl OutputPaths {
     }
 }
 
+/// Name of the per-directory record of content hashes used for de-duplication
+pub const CONTENT_HASH_INDEX: &str = ".content-hashes.json";
+
+static OUTPUT_NAMING: once_cell::sync::OnceCell<OutputNaming> = once_cell::sync::OnceCell::new();
+
+/// How generated artifacts are named
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamingScheme {
+    /// `{timestamp}_{name}_{type}_processed.txt`, a new file per run
+    #[default]
+    Timestamped,
+    /// `{slug}_{version}_{type}.txt`, identical across runs for the same package and version
+    Stable,
+}
+
+/// What to do when the target file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and do not write
+    Skip,
+    /// Write alongside it as `name.v2.txt`, `name.v3.txt`, ...
+    #[default]
+    Version,
+}
+
+impl std::str::FromStr for NamingScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "timestamped" => Ok(NamingScheme::Timestamped),
+            "stable" => Ok(NamingScheme::Stable),
+            other => Err(format!("unknown naming scheme '{}' (expected timestamped or stable)", other)),
+        }
+    }
+}
+
+impl std::str::FromStr for OverwritePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "overwrite" => Ok(OverwritePolicy::Overwrite),
+            "skip" => Ok(OverwritePolicy::Skip),
+            "version" => Ok(OverwritePolicy::Version),
+            other => Err(format!("unknown overwrite policy '{}' (expected overwrite, skip or version)", other)),
+        }
+    }
+}
+
+/// Naming and conflict handling for generated artifacts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputNaming {
+    /// File naming scheme
+    pub scheme: NamingScheme,
+    /// Behavior when the target already exists
+    pub policy: OverwritePolicy,
+    /// Skip writing content identical to a file already in the directory
+    pub dedupe: bool,
+}
+
+impl Default for OutputNaming {
+    fn default() -> Self {
+        Self {
+            scheme: NamingScheme::Timestamped,
+            policy: OverwritePolicy::Version,
+            dedupe: true,
+        }
+    }
+}
+
+/// Where an artifact should be written, or why it should not be
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Write the artifact to this path
+    Write(PathBuf),
+    /// An existing file already holds identical content
+    Duplicate(PathBuf),
+    /// The target exists and the policy says to leave it alone
+    Skipped(PathBuf),
+}
+
+/// Sets the naming used for artifacts written during this run
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_output_naming(naming: OutputNaming) {
+    let _ = OUTPUT_NAMING.set(naming);
+}
+
+/// Returns the naming used for artifacts written during this run
+pub fn output_naming() -> OutputNaming {
+    OUTPUT_NAMING.get().copied().unwrap_or_default()
+}
+
+/// Lowercase, filesystem-safe slug of a package name (no underscores, so names stay parseable)
+pub fn package_slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() || c == '.' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// Builds the file name for an artifact according to the naming scheme
+pub fn artifact_file_name(
+    package_name: &str,
+    package_type: &str,
+    version: Option<&str>,
+    scheme: NamingScheme,
+) -> String {
+    match scheme {
+        NamingScheme::Timestamped => format!(
+            "{}_{}_{}_processed.txt",
+            Utc::now().format("%Y%m%d_%H%M%S"),
+            crate::processors::common::sanitize_filename(package_name),
+            package_type,
+        ),
+        NamingScheme::Stable => format!(
+            "{}_{}_{}.txt",
+            package_slug(package_name),
+            version.map(package_slug).filter(|v| !v.is_empty()).unwrap_or_else(|| "latest".to_string()),
+            package_type,
+        ),
+    }
+}
+
+/// Decides where `content` should be written in `dir` under `file_name`
+///
+/// Identical content already present in the directory is reported as a
+/// duplicate when de-duplication is enabled; otherwise the overwrite policy
+/// decides what happens to an existing file. Compressed variants of the
+/// target count as existing.
+pub fn resolve_output_target(dir: &Path, file_name: &str, content: &[u8], naming: &OutputNaming) -> OutputTarget {
+    if naming.dedupe {
+        let hash = crate::perf::hash_bytes(content);
+        if let Some(existing) = load_hash_index(dir).get(&hash) {
+            let existing = dir.join(existing);
+            if existing.exists() {
+                return OutputTarget::Duplicate(existing);
+            }
+        }
+    }
+
+    let target = dir.join(file_name);
+    let Some(existing) = existing_variant(&target) else {
+        return OutputTarget::Write(target);
+    };
+
+    match naming.policy {
+        OverwritePolicy::Overwrite => OutputTarget::Write(target),
+        OverwritePolicy::Skip => OutputTarget::Skipped(existing),
+        OverwritePolicy::Version => {
+            let (stem, ext) = match file_name.rsplit_once('.') {
+                Some((stem, ext)) => (stem, format!(".{}", ext)),
+                None => (file_name, String::new()),
+            };
+            (2..)
+                .map(|n| dir.join(format!("{}.v{}{}", stem, n, ext)))
+                .find(|candidate| existing_variant(candidate).is_none())
+                .map(OutputTarget::Write)
+                .unwrap_or(OutputTarget::Skipped(existing))
+        }
+    }
+}
+
+/// Records the hash of content written to `path` so later identical output is de-duplicated
+pub fn record_output_hash(path: &Path, content: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut index = load_hash_index(dir);
+    index.insert(
+        crate::perf::hash_bytes(content),
+        path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+    );
+    fs::write(dir.join(CONTENT_HASH_INDEX), serde_json::to_vec_pretty(&index)?)
+}
+
+fn load_hash_index(dir: &Path) -> HashMap<String, String> {
+    fs::read(dir.join(CONTENT_HASH_INDEX))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Returns the path of the target or of a compressed or encrypted copy of it, if any exists
+fn existing_variant(target: &Path) -> Option<PathBuf> {
+    if target.exists() {
+        return Some(target.to_path_buf());
+    }
+    ["gz", "zst", "enc", "gz.enc", "zst.enc"].iter()
+        .map(|ext| {
+            let mut name = target.file_name().unwrap_or_default().to_os_string();
+            name.push(".");
+            name.push(ext);
+            target.with_file_name(name)
+        })
+        .find(|path| path.exists())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-struct PackageInfo {
-    name: String,
-    version: Option<String>,
-    timestamp: DateTime<Utc>,
-    source: String,
-    description: Option<String>,
-    file_path: PathBuf,
-    file_size: u64,
+pub(crate) struct PackageInfo {
+    pub(crate) name: String,
+    pub(crate) version: Option<String>,
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) source: String,
+    pub(crate) description: Option<String>,
+    pub(crate) file_path: PathBuf,
+    pub(crate) file_size: u64,
 }
 
 /// Displays a formatted list of all output files organized by category
 ///
 /// This function scans the output directory structure and prints a hierarchical
 /// listing of all generated files, including their size and modification date.
+/// Compressed artifacts are listed under their original name, with both the
+/// on-disk and the uncompressed size taken from the directory's compression index.
 ///
 /// # Arguments
 /// * `output_dir` - The base output directory to list files from
@@ -144,12 +359,14 @@ pub fn list_output_files(output_dir: &Path) {
         println!("\n{}", label.bright_yellow());
         
         if dir_path.exists() {
+            let compression_index = CompressionIndex::load(&dir_path);
             let mut files = Vec::new();
             for entry in WalkDir::new(&dir_path)
                 .min_depth(1)
                 .max_depth(1)
                 .into_iter()
                 .filter_map(|e| e.ok())
+                .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
             {
                 files.push(entry.path().to_path_buf());
             }
@@ -177,12 +394,16 @@ pub fn list_output_files(output_dir: &Path) {
                     let prefix = if is_last { "  └─ " } else { "  ├─ " };
                     
                     let filename = path.file_name().unwrap_or_default().to_string_lossy();
-                    let package_name = extract_package_name(&filename);
+                    let package_name = extract_package_name(compression::logical_name(&filename));
                     
                     // Get file size
                     if let Ok(metadata) = path.metadata() {
                         let size = metadata.len();
-                        let size_str = format_file_size(size);
+                        let size_str = match compression_index.get(path) {
+                            Some(entry) => format!("{} → {}",
+                                format_file_size(size), format_file_size(entry.uncompressed_size)),
+                            None => format_file_size(size),
+                        };
                         
                         // Get modified time
                         if let Ok(time) = metadata.modified() {
@@ -246,12 +467,13 @@ pub fn format_file_size(size: u64) -> String {
     }
 }
 
-fn extract_package_name(filename: &str) -> String {
+pub(crate) fn extract_package_name(filename: &str) -> String {
     // Extract package name from filename patterns like:
     // 20250301_193131_duckduckgo-search_pypi.txt
     // Format: {timestamp}_{package_name}_{source}.txt
+    // or, with stable naming: duckduckgo-search_7.5.0_pypi.txt
     
-    let re = Regex::new(r"\d{8}_\d{6}_([^_]+)_(\w+)\.txt$").unwrap();
+    let re = Regex::new(r"\d{8}_\d{6}_([^_]+)_(\w+?)(?:\.v\d+)?\.txt$").unwrap();
     
     if let Some(captures) = re.captures(filename) {
         if captures.len() >= 2 {
@@ -259,6 +481,11 @@ fn extract_package_name(filename: &str) -> String {
         }
     }
     
+    let stable = Regex::new(r"^([a-z0-9.\-]+)_([^_]+)_(\w+?)(?:\.v\d+)?\.txt$").unwrap();
+    if let Some(captures) = stable.captures(filename) {
+        return format!("{} {}", &captures[1], &captures[2]);
+    }
+    
     // Fallback - remove timestamp prefix if present
     let parts: Vec<&str> = filename.splitn(3, '_').collect();
     if parts.len() >= 3 && parts[0].len() == 8 && parts[1].len() == 6 {
@@ -272,7 +499,8 @@ fn extract_package_name(filename: &str) -> String {
 ///
 /// This function analyzes output files, determines their package type, and
 /// moves or copies them to the correct category directory. It helps maintain
-/// a clean and structured output organization.
+/// a clean and structured output organization. Afterwards the configured
+/// retention policy is enforced on the category directories.
 ///
 /// # Arguments
 /// * `output_dir` - The base output directory to organize
@@ -287,6 +515,8 @@ pub fn organize_output(output_dir: &Path) -> std::io::Result<()> {
         fs::create_dir_all(output_dir.join(dir))?;
     }
 
+    let root_index = CompressionIndex::load(output_dir);
+    
     // Move any misplaced files to their correct directories
     for entry in fs::read_dir(output_dir)? {
         let entry = entry?;
@@ -328,20 +558,35 @@ pub fn organize_output(output_dir: &Path) -> std::io::Result<()> {
                 if filename.contains("_github_repo") {
                     fs::copy(&path, &new_path)?;
                 } else {
-                    fs::rename(path, new_path)?;
+                    fs::rename(&path, &new_path)?;
+                }
+                
+                // Carry the size record of compressed artifacts over to the new directory
+                if let Some(entry) = root_index.get(&path).copied() {
+                    let target = output_dir.join(target_dir);
+                    let mut index = CompressionIndex::load(&target);
+                    index.files.insert(filename.to_string(), entry);
+                    index.save(&target)?;
                 }
             }
         }
     }
 
+    // Enforce the configured retention limits
+    let deletions = retention::enforce(output_dir, &retention::retention_policy())?;
+    if !deletions.is_empty() {
+        let freed: u64 = deletions.iter().map(|d| d.size).sum();
+        info!("Retention: removed {} old artifacts ({})", deletions.len(), format_file_size(freed));
+    }
+
     Ok(())
 }
 
-/// Generates an HTML index of all processed packages
+/// Generates the index of all processed packages
 ///
-/// This function creates a browsable HTML index of all processed packages,
-/// allowing for easy navigation and access to package documentation through
-/// a web browser.
+/// Writes `_index/index.md` and `_index/index.json`, and builds the static
+/// documentation site under `_site` (see [`crate::site`]) for browsing the
+/// packages in a web browser.
 ///
 /// # Arguments
 /// * `output_dir` - The base output directory containing package files
@@ -349,12 +594,46 @@ pub fn organize_output(output_dir: &Path) -> std::io::Result<()> {
 /// # Returns
 /// IO Result indicating success or failure of index generation
 pub fn generate_index(output_dir: &Path) -> std::io::Result<()> {
-    println!("Generating index of processed packages...");
+    info!("Generating index of processed packages...");
+    
+    let packages = collect_packages(output_dir)?;
+    
+    // Create index directory if it doesn't exist
+    let index_dir = output_dir.join("_index");
+    fs::create_dir_all(&index_dir)?;
+    
+    // Generate index file, preferring a user-provided template when installed
+    let index_path = index_dir.join("index.md");
+    let mut context = tera::Context::new();
+    context.insert("generated", &Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    context.insert("total", &packages.len());
+    context.insert("packages", &packages);
+    match templates::render_user_template(TemplateKind::Index, &context) {
+        Some(rendered) => fs::write(&index_path, rendered)?,
+        None => write_markdown_index(&index_path, output_dir, &packages)?,
+    }
     
+    // Also generate JSON index for programmatic access
+    let json_index_path = index_dir.join("index.json");
+    let json = serde_json::to_string_pretty(&packages)?;
+    fs::write(json_index_path, json)?;
+    
+    // Build the browsable static site
+    let site_index = site::generate_site(output_dir, &packages, &output_dir.join(site::SITE_DIR))?;
+    
+    info!("Index generated at {}", index_path.display());
+    info!("Site generated at {}", site_index.display());
+    
+    Ok(())
+}
+
+/// Scans the category directories for processed packages, newest first
+pub(crate) fn collect_packages(output_dir: &Path) -> std::io::Result<Vec<PackageInfo>> {
     let mut packages = Vec::new();
     
     // The regex to extract information from filenames
-    let filename_regex = Regex::new(r"(\d{8}_\d{6})_([^_]+)_(\w+)_(\w+)\.txt$").unwrap();
+    let filename_regex = Regex::new(r"(\d{8}_\d{6})_([^_]+)_(\w+)_(\w+?)(?:\.v\d+)?\.txt$").unwrap();
+    let stable_regex = Regex::new(r"^([a-z0-9.\-]+)_([^_]+)_(\w+?)(?:\.v\d+)?\.txt$").unwrap();
     
     // Scan all category directories
     for dir in ["local_repositories", "github_repos", "github_orgs", "pypi_packages", 
@@ -373,11 +652,12 @@ pub fn generate_index(output_dir: &Path) -> std::io::Result<()> {
                 continue;
             }
             
-            let filename = path.file_name().unwrap_or_default().to_string_lossy();
+            let raw_filename = path.file_name().unwrap_or_default().to_string_lossy();
+            let filename = compression::logical_name(&raw_filename);
             
-            // Extract information from both .txt and .md files
+            // Extract information from both .txt and .md files, compressed or not
             if filename.ends_with(".txt") {
-                if let Some(captures) = filename_regex.captures(&filename) {
+                if let Some(captures) = filename_regex.captures(filename) {
                     let timestamp_str = &captures[1];
                     let name = captures[2].to_string();
                     let source = captures[3].to_string();
@@ -409,6 +689,20 @@ pub fn generate_index(output_dir: &Path) -> std::io::Result<()> {
                         file_path: path,
                         file_size,
                     });
+                } else if let Some(captures) = stable_regex.captures(filename) {
+                    // Stable names carry the version instead of a timestamp
+                    let metadata = fs::metadata(&path)?;
+                    let version = captures[2].to_string();
+                    
+                    packages.push(PackageInfo {
+                        name: captures[1].to_string(),
+                        version: (version != "latest").then_some(version),
+                        timestamp: DateTime::<Utc>::from(metadata.modified()?),
+                        source: captures[3].to_string(),
+                        description: extract_description(&path)?,
+                        file_path: path,
+                        file_size: metadata.len(),
+                    });
                 }
             } else if filename.ends_with(".md") {
                 // For markdown files, extract info from content
@@ -454,13 +748,12 @@ pub fn generate_index(output_dir: &Path) -> std::io::Result<()> {
     // Sort packages by timestamp (newest first)
     packages.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
     
-    // Create index directory if it doesn't exist
-    let index_dir = output_dir.join("_index");
-    fs::create_dir_all(&index_dir)?;
-    
-    // Generate index file
-    let index_path = index_dir.join("index.md");
-    let mut file = File::create(&index_path)?;
+    Ok(packages)
+}
+
+/// Writes the built-in markdown index layout
+fn write_markdown_index(index_path: &Path, output_dir: &Path, packages: &[PackageInfo]) -> std::io::Result<()> {
+    let mut file = File::create(index_path)?;
     
     writeln!(file, "# Package Index\n")?;
     writeln!(file, "Generated: {}\n", Utc::now().format("%Y-%m-%d %H:%M:%S UTC"))?;
@@ -469,7 +762,7 @@ pub fn generate_index(output_dir: &Path) -> std::io::Result<()> {
     // Group by source type
     let mut sources = std::collections::HashMap::new();
     
-    for package in &packages {
+    for package in packages {
         let source = package.source.split_whitespace().next().unwrap_or("unknown");
         let entry = sources.entry(source.to_string()).or_insert_with(Vec::new);
         entry.push(package);
@@ -503,173 +796,20 @@ pub fn generate_index(output_dir: &Path) -> std::io::Result<()> {
         writeln!(file)?;
     }
     
-    // Also generate JSON index for programmatic access
-    let json_index_path = index_dir.join("index.json");
-    let json = serde_json::to_string_pretty(&packages)?;
-    fs::write(json_index_path, json)?;
-    
-    // Create an HTML index for better browsing
-    generate_html_index(output_dir, &packages, &index_dir)?;
-    
-    println!("[SUCCESS] Index generated at {}", index_path.display());
-    
-    Ok(())
-}
-
-/// Generate an HTML index file for better browsing of packages
-fn generate_html_index(output_dir: &Path, packages: &[PackageInfo], index_dir: &Path) -> std::io::Result<()> {
-    let html_path = index_dir.join("index.html");
-    let mut file = File::create(&html_path)?;
-    
-    // Write HTML header
-    writeln!(file, r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Package Index</title>
-    <style>
-        body {{ font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Helvetica, Arial, sans-serif; line-height: 1.6; padding: 20px; max-width: 1200px; margin: 0 auto; }}
-        h1, h2 {{ color: #2c3e50; }}
-        table {{ border-collapse: collapse; width: 100%; margin-bottom: 20px; }}
-        th, td {{ text-align: left; padding: 12px; }}
-        th {{ background-color: #34495e; color: white; }}
-        tr:nth-child(even) {{ background-color: #f2f2f2; }}
-        tr:hover {{ background-color: #ddd; }}
-        .search {{ margin-bottom: 20px; padding: 10px; width: 100%; }}
-        .timestamp {{ white-space: nowrap; }}
-        .size {{ white-space: nowrap; }}
-        .package-count {{ color: #7f8c8d; font-size: 0.9em; }}
-        .source-filter {{ margin-right: 10px; cursor: pointer; padding: 5px 10px; border-radius: 4px; }}
-        .source-filter.active {{ background-color: #3498db; color: white; }}
-        .filters {{ margin-bottom: 20px; }}
-    </style>
-</head>
-<body>
-    <h1>Package Index</h1>
-    <p>Generated: {timestamp}</p>
-    <p class="package-count">Total Packages: {total}</p>
-    
-    <input type="text" id="searchBox" class="search" placeholder="Search for packages...">
-    
-    <div class="filters">
-        <button class="source-filter active" data-source="all">All</button>
-"#, timestamp=Utc::now().format("%Y-%m-%d %H:%M:%S UTC"), total=packages.len())?;
-
-    // Collect unique sources for filter buttons
-    let mut sources = std::collections::HashSet::new();
-    for package in packages {
-        let source = package.source.split_whitespace().next().unwrap_or("unknown");
-        sources.insert(source.to_string());
-    }
-    
-    // Add source filter buttons
-    for source in sources {
-        writeln!(file, r#"        <button class="source-filter" data-source="{source}">{source}</button>"#, source=source)?;
-    }
-
-    // Write table headers
-    writeln!(file, r#"    </div>
-    
-    <table id="packagesTable">
-        <tr>
-            <th>Package</th>
-            <th>Source</th>
-            <th>Processed</th>
-            <th>Size</th>
-            <th>Description</th>
-        </tr>"#)?;
-
-    // Write table rows for each package
-    for package in packages {
-        let rel_path = pathdiff::diff_paths(&package.file_path, output_dir)
-            .unwrap_or_else(|| package.file_path.clone());
-        
-        let source = package.source.split_whitespace().next().unwrap_or("unknown");
-        
-        writeln!(file, r#"        <tr data-source="{source}">
-            <td><a href="{path}">{name}</a></td>
-            <td>{source}</td>
-            <td class="timestamp">{timestamp}</td>
-            <td class="size">{size}</td>
-            <td>{description}</td>
-        </tr>"#,
-            source=source,
-            path=rel_path.display().to_string().replace(' ', "%20"),
-            name=package.name,
-            timestamp=package.timestamp.format("%Y-%m-%d %H:%M"),
-            size=format_file_size(package.file_size),
-            description=package.description.as_deref().unwrap_or(""),
-        )?;
-    }
-
-    // Write HTML footer with search and filter script
-    writeln!(file, r#"    </table>
-
-    <script>
-        // Search functionality
-        document.getElementById('searchBox').addEventListener('keyup', function() {{
-            const search = this.value.toLowerCase();
-            const rows = document.querySelectorAll('#packagesTable tr:not(:first-child)');
-            
-            rows.forEach(row => {{
-                const text = row.textContent.toLowerCase();
-                const display = text.includes(search) ? '' : 'none';
-                
-                // Only hide if also passes the current source filter
-                if (display === 'none') {{
-                    row.style.display = 'none';
-                }} else {{
-                    // Check against source filter
-                    const activeSource = document.querySelector('.source-filter.active').dataset.source;
-                    if (activeSource === 'all' || row.dataset.source === activeSource) {{
-                        row.style.display = '';
-                    }} else {{
-                        row.style.display = 'none';
-                    }}
-                }}
-            }});
-        }});
-        
-        // Source filter functionality
-        document.querySelectorAll('.source-filter').forEach(button => {{
-            button.addEventListener('click', function() {{
-                // Update active button
-                document.querySelectorAll('.source-filter').forEach(btn => btn.classList.remove('active'));
-                this.classList.add('active');
-                
-                const source = this.dataset.source;
-                const rows = document.querySelectorAll('#packagesTable tr:not(:first-child)');
-                
-                // Apply filter
-                rows.forEach(row => {{
-                    if (source === 'all' || row.dataset.source === source) {{
-                        // Also check against search filter
-                        const search = document.getElementById('searchBox').value.toLowerCase();
-                        const text = row.textContent.toLowerCase();
-                        row.style.display = text.includes(search) ? '' : 'none';
-                    }} else {{
-                        row.style.display = 'none';
-                    }}
-                }});
-            }});
-        }});
-    </script>
-</body>
-</html>"#)?;
-
-    println!("[SUCCESS] HTML index generated at {}", html_path.display());
-    
     Ok(())
 }
 
 // Enhance the description extraction function to handle more formats
 fn extract_description(file_path: &Path) -> std::io::Result<Option<String>> {
-    let file = File::open(file_path)?;
-    let mut content = String::new();
+    // The index is written in plain text, so it must not quote encrypted artifacts
+    if encryption::is_encrypted(file_path) {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
     
-    // Read only the first 8KB to look for description
-    file.take(8192).read_to_string(&mut content)?;
+    // Read only the first 8KB to look for description, decompressing if needed
+    compression::open_artifact(file_path)?.take(8192).read_to_end(&mut bytes)?;
+    let content = String::from_utf8_lossy(&bytes).into_owned();
     
     // Pattern matching for various description formats
     let patterns = [
@@ -747,4 +887,57 @@ fn extract_description(file_path: &Path) -> std::io::Result<Option<String>> {
     }
     
     Ok(None)
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_stable_names_are_deterministic() {
+        let first = artifact_file_name("Flask_Login", "pypi", Some("0.6.3"), NamingScheme::Stable);
+        let second = artifact_file_name("Flask_Login", "pypi", Some("0.6.3"), NamingScheme::Stable);
+        assert_eq!(first, "flask-login_0.6.3_pypi.txt");
+        assert_eq!(first, second);
+        assert_eq!(extract_package_name(&first), "flask-login 0.6.3");
+    }
+
+    #[test]
+    fn test_overwrite_policies() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("pkg_1.0_npm.txt"), "old").unwrap();
+        let naming = |policy| OutputNaming { scheme: NamingScheme::Stable, policy, dedupe: false };
+
+        assert_eq!(
+            resolve_output_target(dir.path(), "pkg_1.0_npm.txt", b"new", &naming(OverwritePolicy::Overwrite)),
+            OutputTarget::Write(dir.path().join("pkg_1.0_npm.txt"))
+        );
+        assert_eq!(
+            resolve_output_target(dir.path(), "pkg_1.0_npm.txt", b"new", &naming(OverwritePolicy::Skip)),
+            OutputTarget::Skipped(dir.path().join("pkg_1.0_npm.txt"))
+        );
+        assert_eq!(
+            resolve_output_target(dir.path(), "pkg_1.0_npm.txt", b"new", &naming(OverwritePolicy::Version)),
+            OutputTarget::Write(dir.path().join("pkg_1.0_npm.v2.txt"))
+        );
+    }
+
+    #[test]
+    fn test_identical_content_is_deduplicated() {
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("20250101_000000_pkg_npm_processed.txt");
+        fs::write(&first, "same").unwrap();
+        record_output_hash(&first, b"same").unwrap();
+
+        let naming = OutputNaming::default();
+        assert_eq!(
+            resolve_output_target(dir.path(), "20250102_000000_pkg_npm_processed.txt", b"same", &naming),
+            OutputTarget::Duplicate(first)
+        );
+        assert!(matches!(
+            resolve_output_target(dir.path(), "20250102_000000_pkg_npm_processed.txt", b"changed", &naming),
+            OutputTarget::Write(_)
+        ));
+    }
+}
@@ -1,52 +1,203 @@
-use std::sync::Arc;
-use tokio::sync::Semaphore;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
 use std::future::Future;
+use std::sync::Mutex;
+use log::error;
+use tokio::task::JoinSet;
+use crate::batch_report::UrlResult;
+use crate::checkpoint::Checkpoint;
 use crate::error::ProcessorError;
-use std::pin::Pin;
+
+/// A future to run, with how urgent it is and the host it talks to
+pub struct Task<F> {
+    future: F,
+    priority: i32,
+    host: Option<String>,
+}
+
+impl<F> Task<F> {
+    /// Creates a task of priority 0 that counts against no host's limit
+    pub fn new(future: F) -> Self {
+        Self { future, priority: 0, host: None }
+    }
+
+    /// Runs the task before waiting tasks of lower priority
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Counts the task against `host`'s concurrency limit
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+}
 
 /// Executes tasks in parallel with a specified concurrency limit
+///
+/// Waiting tasks start in order of priority. Among tasks of the same
+/// priority, hosts take turns, so a long run of URLs on one host does not
+/// hold up the others, and no host has more than its limit running at once.
 pub struct ParallelProcessor {
     max_concurrent: usize,
-    semaphore: Arc<Semaphore>,
+    max_per_host: usize,
 }
 
 impl ParallelProcessor {
     /// Creates a new parallel processor with the specified concurrency limit
     pub fn new(max_concurrent: usize) -> Self {
         Self {
-            max_concurrent,
-            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent: max_concurrent.max(1),
+            max_per_host: 0,
         }
     }
 
+    /// Runs at most `limit` tasks for any one host at the same time (0 for no limit)
+    pub fn with_host_limit(mut self, limit: usize) -> Self {
+        self.max_per_host = limit;
+        self
+    }
+
     /// Processes a collection of futures concurrently and returns their results
     pub async fn process<F, T>(&self, tasks: Vec<F>) -> Vec<Result<T, ProcessorError>>
     where
         F: Future<Output = Result<T, ProcessorError>> + Send + 'static,
         T: Send + 'static,
     {
-        let mut handles = Vec::with_capacity(tasks.len());
-        
-        for task in tasks {
-            let permit = self.semaphore.clone().acquire_owned().await.unwrap();
-            handles.push(tokio::spawn(async move {
-                let result = task.await;
-                drop(permit);
-                result
-            }));
+        self.process_tasks(tasks.into_iter().map(Task::new).collect()).await
+    }
+
+    /// Processes tasks concurrently by priority and host, returning their results in the order given
+    pub async fn process_tasks<F, T>(&self, tasks: Vec<Task<F>>) -> Vec<Result<T, ProcessorError>>
+    where
+        F: Future<Output = Result<T, ProcessorError>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run(tasks, |_| {}).await
+    }
+
+    /// Processes batch tasks like `process_tasks`, journaling each outcome to `checkpoint`
+    ///
+    /// An outcome is appended the moment its task finishes, so a run that
+    /// crashes or is killed resumes after the last finished task. Tasks that
+    /// fail before producing an outcome, such as cancelled ones, are not
+    /// recorded and stay pending.
+    pub async fn process_checkpointed<F>(
+        &self,
+        tasks: Vec<Task<F>>,
+        checkpoint: &Mutex<Checkpoint>,
+    ) -> Vec<Result<UrlResult, ProcessorError>>
+    where
+        F: Future<Output = Result<UrlResult, ProcessorError>> + Send + 'static,
+    {
+        self.run(tasks, |result| {
+            if let Ok(outcome) = result {
+                if let Err(e) = checkpoint.lock().unwrap_or_else(|e| e.into_inner()).record(outcome.clone()) {
+                    error!("Failed to update batch checkpoint: {}", e);
+                }
+            }
+        }).await
+    }
+
+    /// Runs the tasks, calling `on_finish` with each result as its task completes
+    async fn run<F, T>(
+        &self,
+        tasks: Vec<Task<F>>,
+        mut on_finish: impl FnMut(&Result<T, ProcessorError>),
+    ) -> Vec<Result<T, ProcessorError>>
+    where
+        F: Future<Output = Result<T, ProcessorError>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut results: Vec<Option<Result<T, ProcessorError>>> = tasks.iter().map(|_| None).collect();
+        let mut schedule = Schedule::new(self.max_per_host);
+        for (index, task) in tasks.into_iter().enumerate() {
+            schedule.push(index, task);
+        }
+
+        let mut running = JoinSet::new();
+        loop {
+            while running.len() < self.max_concurrent {
+                let Some((index, host, future)) = schedule.next() else { break };
+                running.spawn(async move { (index, host, future.await) });
+            }
+            let Some(finished) = running.join_next().await else { break };
+            let (index, host, result) = finished.unwrap();
+            schedule.finished(&host);
+            on_finish(&result);
+            results[index] = Some(result);
         }
+        results.into_iter().map(|result| result.expect("every task runs")).collect()
+    }
+}
+
+/// Tasks of one host waiting to start
+struct HostQueue<F> {
+    /// Tasks by descending priority, in the order given within a priority
+    waiting: VecDeque<(i32, usize, F)>,
+    running: usize,
+    /// When the host last started a task, counted in tasks started
+    last_started: u64,
+}
+
+/// Picks which waiting task starts next
+struct Schedule<F> {
+    max_per_host: usize,
+    hosts: BTreeMap<Option<String>, HostQueue<F>>,
+    started: u64,
+}
 
-        let mut results = Vec::with_capacity(handles.len());
-        for handle in handles {
-            results.push(handle.await.unwrap());
+impl<F> Schedule<F> {
+    fn new(max_per_host: usize) -> Self {
+        Self { max_per_host, hosts: BTreeMap::new(), started: 0 }
+    }
+
+    fn push(&mut self, index: usize, task: Task<F>) {
+        let queue = self.hosts.entry(task.host).or_insert_with(|| HostQueue {
+            waiting: VecDeque::new(),
+            running: 0,
+            last_started: 0,
+        });
+        let at = queue.waiting.partition_point(|(priority, _, _)| *priority >= task.priority);
+        queue.waiting.insert(at, (task.priority, index, task.future));
+    }
+
+    /// The most urgent task of a host below its limit, from the host that waited longest
+    ///
+    /// Tasks without a host are never held back by the limit. Hosts that have
+    /// not started anything yet go in the order their first task was given.
+    fn next(&mut self) -> Option<(usize, Option<String>, F)> {
+        let max_per_host = self.max_per_host;
+        let (host, queue) = self.hosts.iter_mut()
+            .filter(|(host, queue)| {
+                host.is_none() || max_per_host == 0 || queue.running < max_per_host
+            })
+            .filter_map(|(host, queue)| {
+                let &(priority, index, _) = queue.waiting.front()?;
+                Some(((priority, Reverse(queue.last_started), Reverse(index)), host, queue))
+            })
+            .max_by_key(|(key, _, _)| *key)
+            .map(|(_, host, queue)| (host.clone(), queue))?;
+        let (_, index, future) = queue.waiting.pop_front()?;
+        self.started += 1;
+        queue.last_started = self.started;
+        queue.running += 1;
+        Some((index, host, future))
+    }
+
+    fn finished(&mut self, host: &Option<String>) {
+        if let Some(queue) = self.hosts.get_mut(host) {
+            queue.running -= 1;
         }
-        results
     }
-} 
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::checkpoint::ResumeMode;
+    use std::pin::Pin;
     use tokio::time::sleep;
     use std::time::Duration;
 
@@ -98,4 +249,86 @@ mod tests {
         assert!(results[1].is_err());
         assert!(results[2].is_ok());
     }
+
+    #[tokio::test]
+    async fn test_checkpointed_outcomes_are_journaled() {
+        let dir = tempfile::tempdir().unwrap();
+        let urls: Vec<String> = vec!["a".into(), "b".into()];
+        let checkpoint = Checkpoint::open(dir.path(), &urls, ResumeMode::Restart).unwrap();
+        let checkpoint = Mutex::new(checkpoint);
+
+        let outcome = |url: &str| UrlResult {
+            url: url.to_string(),
+            processor: "local".to_string(),
+            succeeded: true,
+            error: None,
+            duration_ms: 1,
+        };
+        let tasks: Vec<Pin<Box<dyn Future<Output = Result<UrlResult, ProcessorError>> + Send>>> = vec![
+            Box::pin(std::future::ready(Ok(outcome("a")))),
+            Box::pin(std::future::ready(Err(ProcessorError::Cancelled))),
+        ];
+        let results = ParallelProcessor::new(2)
+            .process_checkpointed(tasks.into_iter().map(Task::new).collect(), &checkpoint)
+            .await;
+        assert_eq!(results.len(), 2);
+
+        let resumed = Checkpoint::open(dir.path(), &urls, ResumeMode::Resume).unwrap();
+        assert_eq!(resumed.pending(&urls, ResumeMode::Resume), ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_tasks_start_by_priority_taking_turns_across_hosts() {
+        let started = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let task = |index: usize, host: Option<&str>, priority: i32| {
+            let started = started.clone();
+            let task = Task::new(async move {
+                started.lock().unwrap().push(index);
+                Ok::<_, ProcessorError>(index)
+            }).with_priority(priority);
+            match host {
+                Some(host) => task.with_host(host),
+                None => task,
+            }
+        };
+        let tasks = vec![
+            task(0, Some("github.com"), 0),
+            task(1, Some("github.com"), 0),
+            task(2, Some("github.com"), 0),
+            task(3, Some("pypi.org"), 0),
+            task(4, Some("github.com"), 5),
+            task(5, None, 0),
+        ];
+
+        let results = ParallelProcessor::new(1).process_tasks(tasks).await;
+        let results: Vec<usize> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results, vec![0, 1, 2, 3, 4, 5]);
+        // The urgent task first, then the hosts that waited longest
+        assert_eq!(*started.lock().unwrap(), vec![4, 3, 5, 0, 1, 2]);
+
+        // No host runs more than its limit while others use the free slots
+        let running = std::sync::Arc::new(std::sync::Mutex::new(BTreeMap::<&str, (usize, usize)>::new()));
+        let tasks = ["github.com", "github.com", "github.com", "github.com", "pypi.org", "pypi.org"]
+            .into_iter()
+            .map(|host| {
+                let running = running.clone();
+                Task::new(async move {
+                    {
+                        let mut running = running.lock().unwrap();
+                        let (now, peak) = running.entry(host).or_default();
+                        *now += 1;
+                        *peak = (*peak).max(*now);
+                    }
+                    sleep(Duration::from_millis(50)).await;
+                    running.lock().unwrap().get_mut(host).unwrap().0 -= 1;
+                    Ok::<_, ProcessorError>(())
+                }).with_host(host)
+            })
+            .collect();
+
+        let results = ParallelProcessor::new(4).with_host_limit(2).process_tasks(tasks).await;
+        assert!(results.iter().all(Result::is_ok));
+        let peaks: Vec<(&str, usize)> = running.lock().unwrap().iter().map(|(host, (_, peak))| (*host, *peak)).collect();
+        assert_eq!(peaks, vec![("github.com", 2), ("pypi.org", 2)]);
+    }
 }
@@ -0,0 +1,165 @@
+//! Hot-path helpers for extraction, hashing and line counting
+//!
+//! These routines back the CPU-heavy parts of repository processing. Line
+//! counting uses `memchr` (SIMD on x86_64 and aarch64), hashing uses `sha2`
+//! which picks up the SHA extensions on Apple Silicon and modern x86 at
+//! runtime, and large files are memory-mapped instead of read into a buffer.
+//! Archive extraction is spread across the rayon pool, whose size can be
+//! overridden with `--threads`.
+
+use crate::error::{ProcessorError, Result};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Files at least this large are memory-mapped rather than read into memory
+const MMAP_THRESHOLD: u64 = 256 * 1024;
+
+/// Configures the global worker pool used for CPU-bound work
+///
+/// `None` keeps the default of one thread per logical core. Returns the
+/// number of threads the pool will use.
+pub fn configure_threads(threads: Option<usize>) -> Result<usize> {
+    if let Some(threads) = threads {
+        if threads == 0 {
+            return Err(ProcessorError::Config("--threads must be at least 1".into()));
+        }
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| ProcessorError::Config(format!("Failed to configure worker threads: {}", e)))?;
+    }
+    Ok(rayon::current_num_threads())
+}
+
+/// Counts lines the same way `str::lines` does, without decoding UTF-8
+pub fn count_lines(data: &[u8]) -> usize {
+    let newlines = memchr::memchr_iter(b'\n', data).count();
+    match data.last() {
+        Some(b'\n') | None => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+/// Counts the lines of a file, memory-mapping it when it is large
+pub fn count_file_lines(path: &Path) -> io::Result<usize> {
+    with_file_bytes(path, count_lines)
+}
+
+/// Returns the hex-encoded SHA-256 digest of `data`
+pub fn hash_bytes(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Returns the hex-encoded SHA-256 digest of a file, memory-mapping it when it is large
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    with_file_bytes(path, hash_bytes)
+}
+
+fn with_file_bytes<T>(path: &Path, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    if len >= MMAP_THRESHOLD {
+        // SAFETY: the mapping is read-only and dropped before returning. Output
+        // and source files are not modified while they are being analyzed.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        return Ok(f(&map));
+    }
+
+    let mut buffer = Vec::with_capacity(len as usize);
+    file.read_to_end(&mut buffer)?;
+    Ok(f(&buffer))
+}
+
+/// Extracts a ZIP archive using every thread of the worker pool
+///
+/// Each worker opens its own view of the in-memory archive and inflates a
+/// disjoint, interleaved subset of the entries, so large archives are no
+/// longer decompressed on a single core.
+pub fn extract_zip_parallel(archive_bytes: &[u8], extract_path: &Path) -> Result<()> {
+    let entry_count = ZipArchive::new(Cursor::new(archive_bytes))?.len();
+    let workers = rayon::current_num_threads().clamp(1, entry_count.max(1));
+
+    (0..workers).into_par_iter().try_for_each(|worker| -> Result<()> {
+        let mut archive = ZipArchive::new(Cursor::new(archive_bytes))?;
+
+        for index in (worker..entry_count).step_by(workers) {
+            let mut entry = archive.by_index(index)?;
+            let relative = match entry.enclosed_name() {
+                Some(path) => path.to_path_buf(),
+                None => continue, // Skip entries that would escape the target directory
+            };
+            let target = extract_path.join(relative);
+
+            if entry.is_dir() {
+                std::fs::create_dir_all(&target)?;
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut out = File::create(&target)?;
+            io::copy(&mut entry, &mut out)?;
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_count_lines_matches_str_lines() {
+        for input in ["", "a", "a\n", "a\nb", "a\nb\n", "\n\n", "a\r\nb\r\n"] {
+            assert_eq!(count_lines(input.as_bytes()), input.lines().count(), "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_hash_bytes() {
+        assert_eq!(
+            hash_bytes(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_hash_and_count_large_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("large.txt");
+        let content = "line\n".repeat((MMAP_THRESHOLD as usize / 5) + 10);
+        std::fs::write(&path, &content).unwrap();
+
+        assert_eq!(count_file_lines(&path).unwrap(), content.lines().count());
+        assert_eq!(hash_file(&path).unwrap(), hash_bytes(content.as_bytes()));
+    }
+
+    #[test]
+    fn test_extract_zip_parallel() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        writer.add_directory("repo/", options).unwrap();
+        for i in 0..20 {
+            writer.start_file(format!("repo/src/file{}.rs", i), options).unwrap();
+            writer.write_all(format!("fn f{}() {{}}\n", i).as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let dir = tempdir().unwrap();
+        extract_zip_parallel(&bytes, dir.path()).unwrap();
+
+        for i in 0..20 {
+            let content = std::fs::read_to_string(dir.path().join(format!("repo/src/file{}.rs", i))).unwrap();
+            assert_eq!(content, format!("fn f{}() {{}}\n", i));
+        }
+    }
+}
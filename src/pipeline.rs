@@ -0,0 +1,170 @@
+//! Backpressure between the stages of processing a package
+//!
+//! Processors download a package, extract it, analyze its files and write the
+//! result. At most `processing.max_concurrent_downloads` packages are
+//! downloading at once, `max_concurrent_extractions` extracting and
+//! `max_concurrent_analyses` being analyzed. A package takes its slot in the
+//! next stage before giving up the one it holds, the way a bounded channel
+//! hands items on: when analysis falls behind, finished extractions wait in
+//! their slots, downloads wait behind them and no new download starts, so a
+//! batch never holds more archives and extracted trees in memory than the
+//! stages have slots. Writing is not limited, so it frees an analysis slot
+//! straight away.
+//!
+//! Slots are held by the task running inside [`staged`]; outside it,
+//! [`enter`] does nothing.
+
+use crate::config::ProcessingConfig;
+use once_cell::sync::OnceCell;
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A stage of processing a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching the package archive
+    Download,
+    /// Unpacking the archive
+    Extract,
+    /// Reading and analyzing the files
+    Analyze,
+    /// Writing the output
+    Write,
+}
+
+/// Slots of each limited stage, shared by every package processed
+#[derive(Debug, Clone)]
+pub struct StageLimits {
+    downloads: Arc<Semaphore>,
+    extractions: Arc<Semaphore>,
+    analyses: Arc<Semaphore>,
+}
+
+impl StageLimits {
+    /// Limits with the given number of slots per stage (at least one each)
+    pub fn new(downloads: usize, extractions: usize, analyses: usize) -> Self {
+        Self {
+            downloads: Arc::new(Semaphore::new(downloads.max(1))),
+            extractions: Arc::new(Semaphore::new(extractions.max(1))),
+            analyses: Arc::new(Semaphore::new(analyses.max(1))),
+        }
+    }
+
+    /// Limits from the `processing` section of the configuration
+    pub fn from_config(config: &ProcessingConfig) -> Self {
+        Self::new(config.max_concurrent_downloads, config.max_concurrent_extractions, config.max_concurrent_analyses)
+    }
+
+    fn slots(&self, stage: Stage) -> Option<&Arc<Semaphore>> {
+        match stage {
+            Stage::Download => Some(&self.downloads),
+            Stage::Extract => Some(&self.extractions),
+            Stage::Analyze => Some(&self.analyses),
+            Stage::Write => None,
+        }
+    }
+}
+
+impl Default for StageLimits {
+    fn default() -> Self {
+        Self::from_config(&ProcessingConfig::default())
+    }
+}
+
+static STAGE_LIMITS: OnceCell<StageLimits> = OnceCell::new();
+
+/// Sets the stage limits packages are processed under during this run
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_stage_limits(limits: StageLimits) {
+    let _ = STAGE_LIMITS.set(limits);
+}
+
+/// The stage limits packages are processed under
+pub fn stage_limits() -> &'static StageLimits {
+    STAGE_LIMITS.get_or_init(StageLimits::default)
+}
+
+/// The limits a task runs under and the slot it holds
+struct Staged {
+    limits: StageLimits,
+    slot: RefCell<Option<OwnedSemaphorePermit>>,
+}
+
+tokio::task_local! {
+    static STAGED: Staged;
+}
+
+/// Runs `fut` as one package moving through the stages under the run's limits
+pub async fn staged<F: Future>(fut: F) -> F::Output {
+    staged_with(stage_limits().clone(), fut).await
+}
+
+/// Runs `fut` as one package moving through the stages under `limits`
+pub async fn staged_with<F: Future>(limits: StageLimits, fut: F) -> F::Output {
+    STAGED.scope(Staged { limits, slot: RefCell::new(None) }, fut).await
+}
+
+/// Moves the current package into `stage`, waiting for a free slot
+///
+/// The slot of the stage the package was in is released only once it has
+/// one in `stage`.
+pub async fn enter(stage: Stage) {
+    let Ok(slots) = STAGED.try_with(|staged| staged.limits.slots(stage).cloned()) else {
+        return;
+    };
+    let slot = match slots {
+        Some(slots) => Some(slots.acquire_owned().await.expect("stage slots are never closed")),
+        None => None,
+    };
+    let _ = STAGED.try_with(|staged| staged.slot.replace(slot));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_slow_analysis_holds_back_downloads() {
+        let limits = StageLimits::new(1, 1, 1);
+        let downloaded = Arc::new(AtomicUsize::new(0));
+        let (release, released) = oneshot::channel::<()>();
+        let mut released = Some(released);
+
+        let packages: Vec<_> = (0..4)
+            .map(|_| {
+                let downloaded = downloaded.clone();
+                let released = released.take();
+                tokio::spawn(staged_with(limits.clone(), async move {
+                    enter(Stage::Download).await;
+                    downloaded.fetch_add(1, Ordering::SeqCst);
+                    enter(Stage::Extract).await;
+                    enter(Stage::Analyze).await;
+                    // The first package is slow to analyze
+                    if let Some(released) = released {
+                        let _ = released.await;
+                    }
+                    enter(Stage::Write).await;
+                }))
+            })
+            .collect();
+
+        // One package analyzing, one waiting to analyze, one waiting to extract
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(downloaded.load(Ordering::SeqCst), 3);
+
+        release.send(()).unwrap();
+        for package in packages {
+            package.await.unwrap();
+        }
+        assert_eq!(downloaded.load(Ordering::SeqCst), 4);
+
+        // Outside a staged task there are no slots to wait for
+        enter(Stage::Download).await;
+    }
+}
@@ -1,10 +1,20 @@
 use crate::error::{ProcessorError, Result};
-use reqwest::{Client, StatusCode};
+use crate::templates::{self, TemplateKind};
+use crate::cache::backend::{self, CacheBackend};
+use crate::compression;
+use crate::manifest;
+use crate::output::storage;
+use crate::http;
+use crate::output_organizer::{self, OutputTarget};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use reqwest::header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::io::Cursor;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
 use tracing::warn;
 use tokio::time::sleep;
 use std::time::Duration;
@@ -17,8 +27,8 @@ use std::fmt::Write as FmtWrite;
 use std::path::PathBuf;
 
 /// Creates a GitHub API client with retry capability
-pub fn create_github_client_with_retry(token: Option<&str>) -> Client {
-    let mut builder = Client::builder();
+pub fn create_github_client_with_retry(token: Option<&str>) -> Result<Client> {
+    let mut builder = http::client_builder();
     
     // Add authorization if token provided
     if let Some(token) = token {
@@ -26,7 +36,8 @@ pub fn create_github_client_with_retry(token: Option<&str>) -> Client {
         let auth_value = format!("token {}", token);
         headers.insert(
             reqwest::header::AUTHORIZATION,
-            reqwest::header::HeaderValue::from_str(&auth_value).unwrap(),
+            reqwest::header::HeaderValue::from_str(&auth_value)
+                .map_err(|_| ProcessorError::Config("GitHub token contains invalid characters".into()))?,
         );
         builder = builder.default_headers(headers);
     }
@@ -38,18 +49,14 @@ pub fn create_github_client_with_retry(token: Option<&str>) -> Client {
         reqwest::header::HeaderValue::from_static("code2txt"),
     );
     
-    builder.default_headers(headers)
+    Ok(builder.default_headers(headers)
         .timeout(Duration::from_secs(30))
-        .build()
-        .unwrap_or_else(|_| Client::new())
+        .build()?)
 }
 
 /// Checks if the GitHub API rate limit has been exceeded
 pub async fn check_rate_limit(client: &Client) -> Result<()> {
-    let response = client.get("https://api.github.com/rate_limit")
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?;
+    let response = http::send(client.get("https://api.github.com/rate_limit")).await?;
         
     if response.status() == StatusCode::FORBIDDEN {
         return Err(ProcessorError::RateLimitExceeded("GitHub API rate limit exceeded".into()));
@@ -69,12 +76,116 @@ pub async fn check_rate_limit(client: &Client) -> Result<()> {
     Ok(())
 }
 
+/// Outcome of a request made through [`fetch_cached_json`]
+#[derive(Debug, PartialEq)]
+pub enum CachedJson {
+    /// The response body, fresh from the server or reused from the cache
+    Found(Value),
+    /// The server answered with this unsuccessful status
+    Failed(StatusCode),
+}
+
+/// A cached response with the validators needed to revalidate it
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// When the server last confirmed the body, in seconds since the epoch
+    checked_at: i64,
+    body: Value,
+}
+
+/// Sends a GET for JSON through the shared cache
+///
+/// The response is cached under `processor` and `target`, the package or
+/// repository being processed, so it can be inspected and cleared with them.
+/// A response cached less than `cache.revalidate_secs` ago is returned without
+/// a request. An older one is revalidated with `If-None-Match` and
+/// `If-Modified-Since`, and a `304 Not Modified` returns the cached body.
+/// Cache failures are logged and never fail the fetch.
+pub async fn fetch_cached_json(request: RequestBuilder, processor: &str, target: &str, url: &str) -> Result<CachedJson> {
+    let cache = backend::shared_cache().map(|cache| cache.as_ref());
+    let key = backend::namespaced_key(processor, target, url);
+    fetch_json_through(cache, backend::revalidate_after(), request, &key, url).await
+}
+
+pub(crate) async fn fetch_json_through(
+    cache: Option<&dyn CacheBackend>,
+    revalidate_after: Duration,
+    mut request: RequestBuilder,
+    key: &str,
+    url: &str,
+) -> Result<CachedJson> {
+    let mut cached = None;
+    if let Some(cache) = cache {
+        match cache.get(key).await {
+            Ok(Some(bytes)) => match serde_json::from_slice::<CachedResponse>(&bytes) {
+                Ok(entry) => cached = Some(entry),
+                Err(e) => warn!("Ignoring unreadable cached response for {}: {}", url, e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Cache lookup for {} failed: {}", url, e),
+        }
+    }
+
+    let now = Utc::now().timestamp();
+    if let Some(entry) = &cached {
+        let age = u64::try_from(now - entry.checked_at).unwrap_or(0);
+        if age < revalidate_after.as_secs() {
+            return Ok(CachedJson::Found(entry.body.clone()));
+        }
+        if let Some(etag) = &entry.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = http::fetch(request).await?;
+    let status = response.status();
+    let entry = match cached {
+        Some(entry) if status == StatusCode::NOT_MODIFIED => CachedResponse { checked_at: now, ..entry },
+        _ if status.is_success() => {
+            let header = |name| response.headers().get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_string);
+            let etag = header(ETAG);
+            let last_modified = header(LAST_MODIFIED);
+            let body = response.json()?;
+            CachedResponse { etag, last_modified, checked_at: now, body }
+        }
+        _ => return Ok(CachedJson::Failed(status)),
+    };
+    if let Some(cache) = cache {
+        let stored = match serde_json::to_vec(&entry) {
+            Ok(bytes) => cache.set(key, bytes).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = stored {
+            warn!("Failed to cache the response for {}: {}", url, e);
+        }
+    }
+    Ok(CachedJson::Found(entry.body))
+}
+
+/// Fetches package metadata from a registry API as JSON
+///
+/// Goes through the shared cache with [`fetch_cached_json`], so the same
+/// package is fetched again only when the registry reports a change.
+pub async fn fetch_registry_json(client: &Client, processor: &str, package: &str, url: &str) -> Result<Value> {
+    match fetch_cached_json(client.get(url), processor, package, url).await? {
+        CachedJson::Found(value) => Ok(value),
+        CachedJson::Failed(status) => Err(ProcessorError::Network(format!(
+            "Failed to fetch package info: HTTP {}",
+            status
+        ))),
+    }
+}
+
 /// Downloads a file from a URL to the specified path
 pub async fn download_file(client: &Client, url: &str, output_path: &Path) -> Result<()> {
-    let response = client.get(url)
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?;
+    let response = http::fetch(client.get(url)).await?;
         
     if !response.status().is_success() {
         return Err(ProcessorError::Network(format!(
@@ -83,38 +194,87 @@ pub async fn download_file(client: &Client, url: &str, output_path: &Path) -> Re
         )));
     }
     
-    let bytes = response.bytes()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?;
-        
-    fs::write(output_path, bytes)
+    fs::write(output_path, response.bytes())
         .await
         .map_err(|e| ProcessorError::IO(e))
 }
 
+/// Artifacts a processor wrote under `dir`, in path order
+///
+/// Bookkeeping such as `_snapshots` and hidden indexes is not content.
+pub fn content_artifacts(dir: &Path) -> Vec<PathBuf> {
+    let mut artifacts: Vec<PathBuf> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with(['.', '_']))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+    artifacts.sort();
+    artifacts
+}
+
 /// Extracts an archive to the specified directory
 pub fn extract_archive(archive_bytes: &[u8], extract_path: &Path) -> Result<()> {
-    let reader = Cursor::new(archive_bytes);
-    let mut archive = zip::ZipArchive::new(reader)
-        .map_err(|e| ProcessorError::Zip(e))?;
-        
-    archive.extract(extract_path)
-        .map_err(|e| ProcessorError::Zip(e))
+    crate::perf::extract_zip_parallel(archive_bytes, extract_path)
 }
 
 /// Saves content to a file
-pub async fn save_output_file(content: &str, output_path: &Path) -> Result<()> {
+///
+/// The file is compressed when output compression is enabled, so the returned
+/// path may carry an extra `.gz` or `.zst` extension.
+pub async fn save_output_file(content: &str, output_path: &Path) -> Result<PathBuf> {
     if let Some(parent) = output_path.parent() {
         fs::create_dir_all(parent).await
             .map_err(|e| ProcessorError::IO(e))?;
     }
     
-    fs::write(output_path, content).await
-        .map_err(|e| ProcessorError::IO(e))
+    write_artifact(content, output_path).await
+}
+
+/// Writes an artifact into `dir`, honouring the configured naming and overwrite policy
+///
+/// Returns the path holding the content, which is the existing file when the
+/// write was skipped or its content was already present.
+pub async fn write_named_artifact(content: &str, dir: &Path, file_name: &str) -> Result<PathBuf> {
+    let naming = output_organizer::output_naming();
+    match output_organizer::resolve_output_target(dir, file_name, content.as_bytes(), &naming) {
+        OutputTarget::Write(path) => {
+            let written = write_artifact(content, &path).await?;
+            output_organizer::record_output_hash(&written, content.as_bytes())?;
+            Ok(written)
+        }
+        OutputTarget::Duplicate(existing) | OutputTarget::Skipped(existing) => Ok(existing),
+    }
+}
+
+/// Writes a generated artifact, applying the configured output compression
+pub async fn write_artifact(content: &str, output_path: &Path) -> Result<PathBuf> {
+    let content = content.to_owned();
+    let output_path = output_path.to_path_buf();
+    let written = tokio::task::spawn_blocking(move || compression::write_artifact(&output_path, content.as_bytes()))
+        .await
+        .map_err(|e| ProcessorError::Processing(format!("Artifact writer panicked: {}", e)))?
+        .map_err(ProcessorError::IO)?;
+    manifest::record(&written);
+    storage::upload_artifact(&written).await?;
+    Ok(written)
+}
+
+static PROGRESS_HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Hides progress bars styled by [`setup_progress_style`], e.g. for `--quiet`
+///
+/// Visible progress bars always draw to stderr.
+pub fn set_progress_hidden(hidden: bool) {
+    PROGRESS_HIDDEN.store(hidden, Ordering::Relaxed);
 }
 
 /// Configures a progress bar with a consistent style
 pub fn setup_progress_style(pb: &ProgressBar) {
+    if PROGRESS_HIDDEN.load(Ordering::Relaxed) {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
     pb.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.green} [{elapsed_precise}] {msg}")
@@ -143,19 +303,13 @@ pub async fn download_github_repo(github_url: &str, pb: &ProgressBar) -> Result<
     let archive_url = format!("https://github.com/{}/{}/archive/refs/heads/main.zip", owner, repo);
     
     // Download the archive
-    let client = reqwest::Client::new();
-    let response = client.get(&archive_url)
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?;
+    let client = http::client()?;
+    let response = http::fetch(client.get(&archive_url)).await?;
     
     if !response.status().is_success() {
         // Try with 'master' branch if 'main' fails
         let archive_url = format!("https://github.com/{}/{}/archive/refs/heads/master.zip", owner, repo);
-        let response = client.get(&archive_url)
-            .send()
-            .await
-            .map_err(|e| ProcessorError::Network(e.to_string()))?;
+        let response = http::fetch(client.get(&archive_url)).await?;
             
         if !response.status().is_success() {
             return Err(ProcessorError::Network(format!(
@@ -164,19 +318,13 @@ pub async fn download_github_repo(github_url: &str, pb: &ProgressBar) -> Result<
             )));
         }
         
-        let bytes = response.bytes()
-            .await
-            .map_err(|e| ProcessorError::Network(e.to_string()))?
-            .to_vec();
+        let bytes = response.bytes().to_vec();
             
         pb.set_message("Download completed");
         return Ok(bytes);
     }
     
-    let bytes = response.bytes()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?
-        .to_vec();
+    let bytes = response.bytes().to_vec();
         
     pb.set_message("Download completed");
     Ok(bytes)
@@ -256,7 +404,7 @@ async fn some_function() -> Result<()> {
 }
 
 async fn another_function() -> Result<()> {
-    let response = reqwest::Client::new().get("https://example.com")
+    let response = http::client()?.get("https://example.com")
         .send()
         .await
         .map_err(|e| ProcessorError::Http(e))?;
@@ -337,18 +485,17 @@ pub async fn extract_archive_async(archive_bytes: &[u8], extract_path: &Path, pr
 ///
 /// # Returns
 /// A configured reqwest Client ready to make HTTP requests
-pub fn create_client_with_user_agent() -> Client {
+pub fn create_client_with_user_agent() -> Result<Client> {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
         reqwest::header::USER_AGENT,
         reqwest::header::HeaderValue::from_static("llama-package-service"),
     );
     
-    Client::builder()
+    Ok(http::client_builder()
         .timeout(Duration::from_secs(30))
         .default_headers(headers)
-        .build()
-        .unwrap_or_else(|_| Client::new())
+        .build()?)
 }
 
 /// Creates a standardized progress bar for all processors
@@ -359,16 +506,16 @@ pub fn create_progress_bar() -> ProgressBar {
 }
 
 /// Common utility function to save processor output in multiple formats
+///
+/// `version` is the resolved version of the package, used by the stable naming scheme.
 pub async fn save_processor_output(
     package_name: &str,
     package_type: &str,
+    version: Option<&str>,
     content: &str,
     output_dir: &Path,
     pb: &ProgressBar
 ) -> Result<PathBuf> {
-    // Create timestamp for filename
-    let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
-    
     // Create specific directory if it doesn't exist
     let type_dir = match package_type {
         "github_repo" => "github_repos",
@@ -387,17 +534,14 @@ pub async fn save_processor_output(
     
     // Save markdown version
     let md_filename = format!("{}.md", package_name);
-    let md_path = target_dir.join(&md_filename);
-    fs::write(&md_path, content).await
-        .map_err(|e| ProcessorError::IO(e))?;
+    let md_path = write_artifact(content, &target_dir.join(&md_filename)).await?;
     
-    // Save txt version with timestamp for better organization
-    let txt_filename = format!("{}_{}_{}_{}.txt", timestamp, package_name, package_type, "processed");
-    let txt_path = target_dir.join(&txt_filename);
-    fs::write(&txt_path, content).await
-        .map_err(|e| ProcessorError::IO(e))?;
+    // Save txt version named according to the configured scheme
+    let naming = output_organizer::output_naming();
+    let txt_filename = output_organizer::artifact_file_name(package_name, package_type, version, naming.scheme);
+    let txt_path = write_named_artifact(content, &target_dir, &txt_filename).await?;
     
-    pb.set_message(format!("[SAVED] Output to {} and {}", md_filename, txt_filename));
+    pb.set_message(format!("[SAVED] Output to {} and {}", md_filename, txt_path.display()));
     
     // Return the markdown path as primary output
     Ok(md_path)
@@ -447,11 +591,8 @@ pub fn generate_package_info_section(
 pub async fn download_package(url: &str, output_path: &Path, pb: &ProgressBar) -> Result<()> {
     pb.set_message(format!("Downloading package from {}", url));
     
-    let client = create_client_with_user_agent();
-    let response = client.get(url)
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Http(e))?;
+    let client = create_client_with_user_agent()?;
+    let response = http::fetch(client.get(url)).await?;
     
     if !response.status().is_success() {
         return Err(ProcessorError::Network(format!(
@@ -460,11 +601,7 @@ pub async fn download_package(url: &str, output_path: &Path, pb: &ProgressBar) -
         )));
     }
     
-    let content = response.bytes()
-        .await
-        .map_err(|e| ProcessorError::Http(e))?;
-    
-    fs::write(output_path, content).await
+    fs::write(output_path, response.bytes()).await
         .map_err(|e| ProcessorError::IO(e))?;
     
     pb.set_message(format!("[DOWNLOADED] Package to {}", output_path.display()));
@@ -516,6 +653,7 @@ pub fn extract_package_info(json: &Value) -> Vec<(&'static str, String)> {
 /// # Arguments
 /// * `package_name` - Name of the package being processed
 /// * `package_type` - Type of package (github, pypi, npm, crate, go)
+/// * `version` - Resolved version of the package, used by the stable naming scheme
 /// * `content` - The content to save
 /// * `output_dir` - Directory to save output to
 /// * `pb` - Progress bar for displaying status
@@ -525,13 +663,11 @@ pub fn extract_package_info(json: &Value) -> Vec<(&'static str, String)> {
 pub async fn save_comprehensive_output(
     package_name: &str,
     package_type: &str,
+    version: Option<&str>,
     content: &str,
     output_dir: &Path,
     pb: &ProgressBar,
 ) -> Result<PathBuf> {
-    // Create timestamp for unique filenames
-    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S").to_string();
-    
     // Create sanitized package name (remove invalid filename chars)
     let sanitized_name = sanitize_filename(package_name);
     
@@ -552,19 +688,15 @@ pub async fn save_comprehensive_output(
     tokio::fs::create_dir_all(&target_dir).await?;
     
     // Generate filenames
-    let txt_filename = format!("{}_{}_{}_{}.txt", timestamp, sanitized_name, package_type, "processed");
+    let naming = output_organizer::output_naming();
+    let txt_filename = output_organizer::artifact_file_name(package_name, package_type, version, naming.scheme);
     let md_filename = format!("{}.md", sanitized_name);
-    
-    // Create paths
-    let txt_path = target_dir.join(&txt_filename);
     let md_path = target_dir.join(&md_filename);
     
     // Also save a standardized version to the root output directory if it's a GitHub repo
     let _root_output_path = if package_type == "github" {
         let root_filename = format!("{}_github_repo.txt", sanitized_name);
-        let path = output_dir.join(&root_filename);
-        tokio::fs::write(&path, content).await?;
-        Some(path)
+        Some(write_artifact(content, &output_dir.join(&root_filename)).await?)
     } else {
         None
     };
@@ -572,8 +704,8 @@ pub async fn save_comprehensive_output(
     // Save both formats
     pb.set_message(format!("Saving {} documentation...", package_name));
     
-    tokio::fs::write(&txt_path, content).await?;
-    tokio::fs::write(&md_path, content).await?;
+    let txt_path = write_named_artifact(content, &target_dir, &txt_filename).await?;
+    write_artifact(content, &md_path).await?;
     
     // Return the path to the primary output file (txt)
     Ok(txt_path)
@@ -589,6 +721,16 @@ pub async fn save_comprehensive_output(
 /// # Returns
 /// Organized and cleaned content
 pub fn organize_content(content: &str, package_name: &str, package_type: &str) -> String {
+    // A user-provided overview template replaces the built-in layout entirely
+    let mut context = tera::Context::new();
+    context.insert("package_name", package_name);
+    context.insert("package_type", package_type);
+    context.insert("generated", &chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    context.insert("content", content);
+    if let Some(rendered) = templates::render_user_template(TemplateKind::Overview, &context) {
+        return rendered;
+    }
+    
     let mut organized = String::new();
     
     // Add title and metadata
@@ -661,11 +803,55 @@ pub fn organize_content(content: &str, package_name: &str, package_type: &str) -
 ///
 /// # Returns
 /// A sanitized string valid for filenames
-fn sanitize_filename(name: &str) -> String {
+pub fn sanitize_filename(name: &str) -> String {
     name.chars()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
             _ => c
         })
         .collect()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::backend::{CacheConfig, MemoryBackend};
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_cached_json_is_revalidated() {
+        let mut server = mockito::Server::new_async().await;
+        let url = format!("{}/pypi/requests/json", server.url());
+        let full = server.mock("GET", "/pypi/requests/json")
+            .match_header("if-none-match", Matcher::Missing)
+            .with_header("etag", "\"v1\"")
+            .with_body(r#"{"version": "2.32.0"}"#)
+            .expect(1)
+            .create_async().await;
+        let not_modified = server.mock("GET", "/pypi/requests/json")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .expect(1)
+            .create_async().await;
+
+        let client = Client::new();
+        let cache = MemoryBackend::new(&CacheConfig::default());
+        let expected = CachedJson::Found(serde_json::json!({"version": "2.32.0"}));
+        let hour = Duration::from_secs(3600);
+
+        // Fetched once, then served from the cache while fresh
+        for _ in 0..2 {
+            let fetched = fetch_json_through(Some(&cache), hour, client.get(&url), "pypi:requests", &url).await.unwrap();
+            assert_eq!(fetched, expected);
+        }
+        // Revalidated once stale, reusing the body on 304
+        let fetched = fetch_json_through(Some(&cache), Duration::ZERO, client.get(&url), "pypi:requests", &url).await.unwrap();
+        assert_eq!(fetched, expected);
+        full.assert_async().await;
+        not_modified.assert_async().await;
+
+        server.mock("GET", "/missing").with_status(404).create_async().await;
+        let missing = format!("{}/missing", server.url());
+        let fetched = fetch_json_through(Some(&cache), hour, client.get(&missing), "pypi:missing", &missing).await.unwrap();
+        assert_eq!(fetched, CachedJson::Failed(StatusCode::NOT_FOUND));
+    }
+}
@@ -5,6 +5,7 @@ use std::io::Write;
 use colored::*;
 use tracing::info;
 use crate::error::{ProcessorError, Result};
+use crate::http;
 use crate::processors::common;
 use crate::processors::common::setup_progress_style;
 use crate::processors::github::process_github_content;
@@ -77,8 +78,8 @@ impl CrateInfo {
     /// # Returns
     /// A Result containing the documentation content as a String or an error
     pub async fn fetch_docs(&self) -> Result<String> {
-        let client = reqwest::Client::new();
-        let response = client.get(&self.download_url).send().await?;
+        let client = http::client()?;
+        let response = http::send(client.get(&self.download_url)).await?;
 
         if !response.status().is_success() {
             return Err(ProcessorError::Validation("No documentation available".to_string()));
@@ -97,8 +98,8 @@ impl CrateInfo {
 /// # Returns
 /// Result indicating success or failure of the download operation
 pub async fn download_crate(crate_info: &CrateInfo, output_path: &str) -> Result<()> {
-    let client = reqwest::Client::new();
-    let response = client.get(&crate_info.download_url).send().await?;
+    let client = http::client()?;
+    let response = http::fetch(client.get(&crate_info.download_url)).await?;
 
     if !response.status().is_success() {
         return Err(ProcessorError::Download(format!(
@@ -107,8 +108,7 @@ pub async fn download_crate(crate_info: &CrateInfo, output_path: &str) -> Result
         )));
     }
 
-    let bytes = response.bytes().await?;
-    tokio::fs::write(output_path, &bytes).await?;
+    tokio::fs::write(output_path, response.bytes()).await?;
 
     Ok(())
 }
@@ -161,18 +161,18 @@ impl RustCrateProcessor {
     /// Creates a new RustCrateProcessor with default configuration
     ///
     /// # Returns
-    /// A new RustCrateProcessor instance
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+    /// A new RustCrateProcessor instance, or an error if the HTTP client cannot be built
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http::client()?,
+        })
     }
 }
 
 impl Clone for RustCrateProcessor {
     fn clone(&self) -> Self {
         Self {
-            client: Client::new(),
+            client: self.client.clone(),
         }
     }
 }
@@ -220,15 +220,9 @@ pub async fn process_rust_crate(
         .ok_or_else(|| ProcessorError::Validation("Invalid crates.io URL".into()))?;
 
     // Fetch crate metadata from the Crates.io API
-    let client = Client::new();
+    let client = http::client()?;
     let api_url = format!("https://crates.io/api/v1/crates/{}", crate_name);
-    let response = client.get(&api_url)
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?;
-    let data: Value = response.json()
-        .await
-        .map_err(|e| ProcessorError::Processing(e.to_string()))?;
+    let data = common::fetch_registry_json(&client, "crates", crate_name, &api_url).await?;
 
     // Start composing the output content with crate metadata:
     let mut content = String::new();
@@ -261,13 +255,10 @@ pub async fn process_rust_crate(
 
         pb.set_message("Downloading crate archive...");
 
-        let response = client.get(&download_url)
-            .send()
-            .await
-            .map_err(|e| ProcessorError::Network(e.to_string()))?;
-        let bytes = response.bytes()
+        let response = http::fetch(client.get(&download_url))
             .await
             .map_err(|e| ProcessorError::Network(e.to_string()))?;
+        let bytes = response.bytes();
 
         // Create a temporary directory for extracting the archive.
         let temp_dir = tempdir().map_err(|e| ProcessorError::IO(e))?;
@@ -375,11 +366,11 @@ impl CratesProcessor {
     /// Creates a new CratesProcessor with default configuration
     ///
     /// # Returns
-    /// A new CratesProcessor instance
-    pub fn new() -> Self {
-        Self {
-            client: Client::new()
-        }
+    /// A new CratesProcessor instance, or an error if the HTTP client cannot be built
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http::client()?
+        })
     }
 
     async fn validate_url(&self, url: &str) -> Result<()> {
@@ -391,8 +382,7 @@ impl CratesProcessor {
 
     async fn get_crate_info(&self, crate_name: &str) -> Result<serde_json::Value> {
         let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
-        let response = self.client.get(&url)
-            .send()
+        let response = http::send(self.client.get(&url))
             .await?;
             
         let data = response.json::<serde_json::Value>()
@@ -466,7 +456,7 @@ async fn process_crate_by_name(crate_name: &str, output_dir: &Path, pb: &Progres
     pb.set_message(format!("Processing crate: {}", crate_name));
     
     // Create the client with proper user agent
-    let client = reqwest::Client::builder()
+    let client = http::client_builder()
         .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
         .build()
         .map_err(|e| ProcessorError::Network(e.to_string()))?;
@@ -477,8 +467,7 @@ async fn process_crate_by_name(crate_name: &str, output_dir: &Path, pb: &Progres
     pb.set_message(format!("Fetching docs.rs page for: {}", crate_name));
     
     // Fetch docs.rs HTML page
-    let response = client.get(&docs_url)
-        .send()
+    let response = http::send(client.get(&docs_url))
         .await
         .map_err(|e| ProcessorError::Network(e.to_string()))?;
         
@@ -490,8 +479,7 @@ async fn process_crate_by_name(crate_name: &str, output_dir: &Path, pb: &Progres
     pb.set_message(format!("Fetching lib.rs page for: {}", crate_name));
     
     // Fetch lib.rs HTML page
-    let librs_response = client.get(&librs_url)
-        .send()
+    let librs_response = http::send(client.get(&librs_url))
         .await
         .map_err(|e| ProcessorError::Network(e.to_string()))?;
         
@@ -1,9 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Write;
-use colored::*;
 use tracing::info;
 use crate::error::ProcessorError;
+use crate::http;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CrateInfo {
@@ -17,7 +17,7 @@ pub struct CrateInfo {
 
 impl CrateInfo {
     pub async fn from_url(url: &str) -> Result<Self, ProcessorError> {
-        let client = reqwest::Client::new();
+        let client = http::client()?;
         
         info!("Extracting crate info from URL: {}", url);
         
@@ -28,7 +28,7 @@ impl CrateInfo {
         
         let api_url = format!("https://crates.io/api/v1/crates/{}", crate_name);
         info!("Fetching crate info from: {}", api_url);
-        println!("Fetching crate info from: {}", api_url.cyan());
+        info!("Fetching crate info from: {}", api_url);
         
         let response = client.get(&api_url)
             .send()
@@ -98,10 +98,10 @@ impl CrateInfo {
 }
 
 pub async fn download_crate(crate_info: &CrateInfo, output_path: &str) -> Result<(), ProcessorError> {
-    let client = reqwest::Client::new();
+    let client = http::client()?;
     
     info!("Downloading crate from: {}", crate_info.download_url);
-    println!("Downloading crate from: {}", crate_info.download_url.cyan());
+    info!("Downloading crate from: {}", crate_info.download_url);
     
     let response = client.get(&crate_info.download_url)
         .send()
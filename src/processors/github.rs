@@ -1,7 +1,21 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
 use crate::config::Config;
+use crate::chunking;
+use crate::docs_health;
+use crate::analytics::complexity::ComplexityReport;
+use crate::analytics::duplication::DuplicationReport;
+use crate::analytics::freshness::FreshnessChecker;
+use crate::analytics::lines::{self, LineCounts};
+use crate::analytics::maintainability::MaintainabilityReport;
+use crate::link_checker::LinkChecker;
+use crate::run_diff;
+use crate::cancellation;
+use crate::progress;
+use crate::pipeline::{self, Stage};
+use crate::batch_report::{self, PackageSummary};
 use crate::processors::common::{
-    self, check_rate_limit, download_file, 
+    self, check_rate_limit, download_file, fetch_cached_json, CachedJson,
     extract_archive as common_extract_archive, 
     save_output_file, 
     setup_progress_style as common_setup_progress_style,
@@ -229,6 +243,7 @@ pub async fn process_github_url(
     url: &str,
     output_dir: &Path,
     pb: &ProgressBar,
+    config: &Config,
 ) -> Result<()> {
     common::setup_progress_style(pb);
     pb.set_message("Processing GitHub URL...");
@@ -240,7 +255,7 @@ pub async fn process_github_url(
             process_single_repo(&owner, &repo, url, output_dir, pb).await?;
         }
         GitHubUrlType::Organization { org } => {
-            process_org(&org, output_dir, pb).await?;
+            process_org(&org, output_dir, pb, config).await?;
         }
     }
 
@@ -275,13 +290,9 @@ async fn determine_github_url_type(url: &str) -> Result<GitHubUrlType> {
         
         // Check if it's a valid repository
         let repo_url = format!("https://api.github.com/repos/{}/{}", owner, repo);
-        let response = client.get(&repo_url)
-            .header("User-Agent", "LlamaSearch")
-            .send()
-            .await
-            .map_err(|e| ProcessorError::Network(e.to_string()))?;
+        let response = fetch_cached_json(client.get(&repo_url), "github", &format!("{}/{}", owner, repo), &repo_url).await?;
 
-        if response.status() == StatusCode::OK {
+        if matches!(response, CachedJson::Found(_)) {
             return Ok(GitHubUrlType::Repository {
                 owner: owner.to_string(),
                 repo: repo.to_string(),
@@ -292,13 +303,9 @@ async fn determine_github_url_type(url: &str) -> Result<GitHubUrlType> {
     // If not a repository, check if it's an organization
     let org = segments[0];
     let org_url = format!("https://api.github.com/orgs/{}", org);
-    let response = client.get(&org_url)
-        .header("User-Agent", "LlamaSearch")
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Network(e.to_string()))?;
+    let response = fetch_cached_json(client.get(&org_url), "github", org, &org_url).await?;
 
-    if response.status() == StatusCode::OK {
+    if matches!(response, CachedJson::Found(_)) {
         Ok(GitHubUrlType::Organization {
             org: org.to_string(),
         })
@@ -312,6 +319,7 @@ async fn process_org(
     org: &str,
     output_dir: &Path,
     pb: &ProgressBar,
+    config: &Config,
 ) -> Result<()> {
     let output_structure = OutputStructure::new(output_dir).await?;
     let org_dir = output_structure.get_org_dir(org);
@@ -397,12 +405,10 @@ async fn process_org(
     
     for repo in sorted_repos.iter().take(20) {
         let name = repo["name"].as_str().unwrap_or("Unknown");
-        let description = repo["description"].as_str().unwrap_or("").replace("|", "\\|");
-        let truncated_desc = if description.len() > 80 {
-            format!("{}...", &description[..77])
-        } else {
-            description
-        };
+        let description = repo["description"].as_str().unwrap_or("");
+        let truncated_desc = config.truncation.description.apply(description)
+            .replace(chunking::TRUNCATION_MARKER, "...")
+            .replace("|", "\\|");
         let stars = repo["stargazers_count"].as_u64().unwrap_or(0);
         let forks = repo["forks_count"].as_u64().unwrap_or(0);
         let language = repo["language"].as_str().unwrap_or("Unknown");
@@ -452,9 +458,8 @@ async fn process_org(
             repo_name
         );
         
-        let readme_response = client.get(&readme_url)
-            .header("Accept", "application/vnd.github.v3.raw")
-            .send()
+        let readme_response = http::send(client.get(&readme_url)
+            .header("Accept", "application/vnd.github.v3.raw"))
             .await;
             
         if let Ok(response) = readme_response {
@@ -462,15 +467,8 @@ async fn process_org(
                 if let Ok(readme_content) = response.text().await {
                     content.push_str("\n#### README Excerpt\n\n");
                     
-                    // Truncate very long READMEs
-                    let max_chars = 2000;
-                    let truncated = if readme_content.len() > max_chars {
-                        format!("{}... (truncated)", &readme_content[..max_chars])
-                    } else {
-                        readme_content
-                    };
-                    
-                    content.push_str(&truncated);
+                    // Keep long READMEs within the configured token budget
+                    content.push_str(&config.truncation.readme_excerpt.apply(&readme_content));
                     content.push_str("\n\n");
                 }
             }
@@ -487,18 +485,24 @@ async fn process_org(
     // Create timestamp for unique filename
     let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
     let output_filename = format!("{}_github_org_{}.txt", org, timestamp);
-    let output_path = output_dir.join(&output_filename);
     
     // Save output
-    tokio_fs::write(&output_path, &organized_content)
-        .await
-        .map_err(|e| ProcessorError::IO(e))?;
+    let output_path = common::write_artifact(&organized_content, &output_dir.join(&output_filename)).await?;
         
     // Also save as .md for better viewing
-    let md_path = output_dir.join(format!("{}.md", org));
-    tokio_fs::write(&md_path, &organized_content)
-        .await
-        .map_err(|e| ProcessorError::IO(e))?;
+    common::write_artifact(&organized_content, &output_dir.join(format!("{}.md", org))).await?;
+    
+    // Combined report across the organization's repositories
+    let summaries: Vec<PackageSummary> = repos.iter()
+        .map(|repo| PackageSummary {
+            name: format!("{}/{}", org, repo["name"].as_str().unwrap_or("unknown")),
+            languages: repo["language"].as_str()
+                .map(|language| [(language.to_string(), 1)].into())
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+        .collect();
+    batch_report::write(output_dir, org, &summaries).await?;
     
     pb.finish_with_message(format!("[SUCCESS] Organization {} processed successfully. Output saved to: {}", 
                                  org, output_path.display()));
@@ -562,23 +566,15 @@ async fn process_single_repo(
     let output = OutputStructure::new(output_dir).await?;
     
     // Get repository details
-    let repo_details = match client
-        .get(&format!("https://api.github.com/repos/{}/{}", owner, repo))
-        .header("User-Agent", "llama-package-service")
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                response.json::<Value>().await.map_err(|e| ProcessorError::Processing(e.to_string()))?
-            } else {
-                return Err(ProcessorError::Message(format!(
-                    "Failed to get repository details: {}",
-                    response.status()
-                )));
-            }
+    let details_url = format!("https://api.github.com/repos/{}/{}", owner, repo);
+    let repo_details = match fetch_cached_json(client.get(&details_url), "github", &format!("{}/{}", owner, repo), &details_url).await? {
+        CachedJson::Found(details) => details,
+        CachedJson::Failed(status) => {
+            return Err(ProcessorError::Message(format!(
+                "Failed to get repository details: {}",
+                status
+            )));
         }
-        Err(e) => return Err(ProcessorError::Http(e)),
     };
     
     // Extract repository information
@@ -598,7 +594,7 @@ async fn process_single_repo(
     }
     
     // Write the content
-    tokio_fs::write(&repo_path, repo_content).map_err(|e| ProcessorError::IO(e)).await?;
+    common::write_artifact(&repo_content, &repo_path).await?;
     
     // Create overview file
     let description = repo_details["description"].as_str().unwrap_or("No description");
@@ -640,7 +636,7 @@ async fn process_single_repo(
     
     // Save overview
     let overview_path = output.get_repo_overview_path(owner, repo);
-    tokio_fs::write(&overview_path, overview_content).map_err(|e| ProcessorError::IO(e)).await?;
+    common::write_artifact(&overview_content, &overview_path).await?;
     
     pb.finish_with_message(format!("✨ Repository {}/{} processed successfully", owner, repo));
     Ok(())
@@ -648,30 +644,20 @@ async fn process_single_repo(
 
 async fn fetch_repo_info(client: &Client, owner: &str, repo: &str) -> Result<Value> {
     let url = format!("{}/repos/{}/{}", github_api_base(), owner, repo);
-    
-    let response = client
-        .get(&url)
-        .header("User-Agent", "llama-package-service")
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Http(e))?;
-        
-    if !response.status().is_success() {
-        return Err(ProcessorError::Message(format!(
+    let request = client.get(&url).header("User-Agent", "llama-package-service");
+
+    match fetch_cached_json(request, "github", &format!("{}/{}", owner, repo), &url).await? {
+        CachedJson::Found(info) => Ok(info),
+        CachedJson::Failed(status) => Err(ProcessorError::Message(format!(
             "Failed to fetch repo info: HTTP {}",
-            response.status()
-        )));
+            status
+        ))),
     }
-    
-    response
-        .json::<Value>()
-        .await
-        .map_err(|e| ProcessorError::Processing(e.to_string()))
 }
 
 async fn fetch_org_info(client: &Client, org: &str) -> Result<Value> {
     let url = format!("{}/orgs/{}", github_api_base(), org);
-    make_github_request(client, &url).await
+    make_github_request(client, org, &url).await
 }
 
 async fn fetch_org_repos(client: &Client, org: &str) -> Result<Vec<Value>> {
@@ -686,7 +672,7 @@ async fn fetch_org_repos(client: &Client, org: &str) -> Result<Vec<Value>> {
             page
         );
         
-        let response = make_github_request(client, &url).await?;
+        let response = make_github_request(client, org, &url).await?;
         
         match response.as_array() {
             Some(repos) => {
@@ -714,8 +700,7 @@ async fn download_repo_archive(
         owner, repo, branch
     );
     
-    let response = client.get(&archive_url)
-        .send()
+    let mut response = http::send(client.get(&archive_url))
         .await
         .map_err(|e| ProcessorError::Network(e.to_string()))?;
 
@@ -726,10 +711,13 @@ async fn download_repo_archive(
         )));
     }
 
-    response.bytes()
-        .await
-        .map(|b| b.to_vec())
-        .map_err(|e| ProcessorError::Network(e.to_string()))
+    // Read in chunks so the bytes received so far can be reported
+    let mut archive = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| ProcessorError::Network(e.to_string()))? {
+        archive.extend_from_slice(&chunk);
+        progress::downloaded(archive.len() as u64);
+    }
+    Ok(archive)
 }
 
 // Helper functions for processing repository contents
@@ -743,35 +731,7 @@ async fn extract_archive(archive_bytes: &[u8], extract_path: &Path) -> Result<()
     
     // Use tokio::task::spawn_blocking for CPU-bound operations
     tokio::task::spawn_blocking(move || -> Result<()> {
-        let reader = std::io::Cursor::new(bytes);
-        let mut archive = zip::ZipArchive::new(reader)?;
-        
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
-            let outpath = match file.enclosed_name() {
-                Some(path) => path::Path::new(&path).to_path_buf(),
-                None => continue,
-            };
-            
-            let outpath = path.join(outpath);
-            
-            if file.name().ends_with('/') {
-                // Directory
-                std_fs::create_dir_all(&outpath)?;
-            } else {
-                // File
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        std_fs::create_dir_all(p)?;
-                    }
-                }
-                
-                let mut outfile = std_fs::File::create(&outpath)?;
-                std::io::copy(&mut file, &mut outfile)?;
-            }
-        }
-        
-        Ok(())
+        crate::perf::extract_zip_parallel(&bytes, &path)
     }).await.map_err(|e| ProcessorError::Processing(format!("Join error: {}", e)))?
 }
 
@@ -866,7 +826,7 @@ pub async fn generate_repo_index(repo_path: &Path) -> Result<String> {
 /// It internally calls process_github_url and then searches the temporary directory for the generated file.
 pub async fn process_github_content(github_url: &str, temp_dir: &Path) -> Result<String> {
     let pb = ProgressBar::hidden();
-    process_github_url(github_url, temp_dir, &pb).await?;
+    process_github_url(github_url, temp_dir, &pb, &Config::default()).await?;
     
     let mut entries = tokio_fs::read_dir(temp_dir).await?;
     while let Some(entry) = entries.next_entry().await? {
@@ -885,12 +845,10 @@ pub async fn download_repo(owner: &str, repo: &str, output_path: &Path) -> Resul
     let client = create_github_client()?;
     let url = format!("https://api.github.com/repos/{}/{}/zipball", owner, repo);
     
-    let response = client
+    let response = http::send(client
         .get(&url)
-        .header("User-Agent", "llama-package-service")
-        .send()
-        .await
-        .map_err(|e| ProcessorError::Http(e))?;
+        .header("User-Agent", "llama-package-service"))
+        .await?;
 
     if !response.status().is_success() {
         return Err(ProcessorError::Processing(format!(
@@ -956,11 +914,11 @@ impl GitHubProcessor {
     /// Creates a new GitHub processor with default configuration
     ///
     /// # Returns
-    /// A new GitHubProcessor instance
-    pub fn new() -> Self {
-        Self {
-            client: Client::new()
-        }
+    /// A new GitHubProcessor instance, or an error if the HTTP client cannot be built
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http::client()?
+        })
     }
 
     async fn validate_url(&self, url: &str) -> Result<()> {
@@ -990,32 +948,27 @@ impl GitHubProcessor {
 
     async fn get_repo_info(&self, owner: &str, repo: &str) -> Result<Value> {
         let url = format!("https://api.github.com/repos/{}/{}", owner, repo);
-        let response = self.client.get(&url)
-            .send()
+        let response = http::fetch(self.client.get(&url))
             .await
             .map_err(|e| ProcessorError::new(&format!("Failed to fetch repo info: {}", e)))?;
             
-        response.json().await
+        response.json()
             .map_err(|e| ProcessorError::new(&format!("Failed to parse repo info: {}", e)))
     }
 
     async fn download_repo(&self, owner: &str, repo: &str, branch: &str, output_dir: &Path) -> Result<PathBuf> {
         let url = format!("https://api.github.com/repos/{}/{}/zipball/{}", owner, repo, branch);
-        let response = self.client.get(&url)
-            .send()
+        let response = http::fetch(self.client.get(&url))
             .await?;
         
-        let bytes = response.bytes()
-            .await
-            .map_err(|e| ProcessorError::new(&format!("Failed to read response: {}", e)))?;
-        
+        let bytes = response.bytes();
         let archive_path = output_dir.join("repo.zip");
-        tokio_fs::write(&archive_path, &bytes).await?;
+        tokio_fs::write(&archive_path, bytes).await?;
         
         let extract_path = output_dir.join("repo");
         tokio_fs::create_dir_all(&extract_path).await?;
         
-        common_extract_archive(&bytes, &extract_path)?;
+        common_extract_archive(bytes, &extract_path)?;
         
         let entries = list_directory_entries(&extract_path).await?;
         let root_dir = entries.into_iter()
@@ -1140,7 +1093,7 @@ impl PackageProcessor for GitHubProcessor {
         Ok(())
     }
     
-    async fn process(&self, url: &str, output_dir: &Path, _config: &Config) -> Result<()> {
+    async fn process(&self, url: &str, output_dir: &Path, config: &Config) -> Result<()> {
         let pb = ProgressBar::new_spinner();
         setup_github_progress_bar(&pb);
         
@@ -1148,11 +1101,12 @@ impl PackageProcessor for GitHubProcessor {
         let repo_details = extract_github_details(url)?;
         
         // Process directly without calling process_github_url
-        process_github_repo_inner(&repo_details.owner, &repo_details.repo, output_dir, &pb).await?;
+        let links = if config.processing.check_links { Some(LinkChecker::shared(config)?) } else { None };
+        let readme_health = process_github_repo_inner(&repo_details.owner, &repo_details.repo, output_dir, &pb, links).await?;
         
         pb.finish_with_message(format!("✨ GitHub repository {}/{} processed successfully", 
                                      repo_details.owner, repo_details.repo));
-        Ok(())
+        docs_health::require_score(readme_health.as_ref(), config.processing.docs_min_score)
     }
 }
 
@@ -1172,7 +1126,7 @@ fn create_github_client() -> Result<Client> {
         reqwest::header::HeaderValue::from_static("llama-package-service"),
     );
     
-    let client = Client::builder()
+    let client = http::client_builder()
         .timeout(Duration::from_secs(API_TIMEOUT_SECS))
         .default_headers(headers)
         .build()
@@ -1181,15 +1135,14 @@ fn create_github_client() -> Result<Client> {
     Ok(client)
 }
 
-async fn make_github_request(client: &Client, url: &str) -> Result<Value> {
-    let response = client.get(url).send().await?;
-    if !response.status().is_success() {
-        return Err(ProcessorError::GitHubApi(format!(
+async fn make_github_request(client: &Client, target: &str, url: &str) -> Result<Value> {
+    match fetch_cached_json(client.get(url), "github", target, url).await? {
+        CachedJson::Found(value) => Ok(value),
+        CachedJson::Failed(status) => Err(ProcessorError::GitHubApi(format!(
             "GitHub API request failed: HTTP {}",
-            response.status()
-        )));
+            status
+        ))),
     }
-    Ok(response.json().await?)
 }
 
 /// Sets up a progress bar with GitHub-specific styling
@@ -1219,7 +1172,7 @@ pub mod llm {
         
         let output_dir = PathBuf::from(args.output_path);
         
-        match process_github_url(&args.url, &output_dir, &pb).await {
+        match process_github_url(&args.url, &output_dir, &pb, &Config::default()).await {
             Ok(_) => Ok("Successfully processed GitHub repository".to_string()),
             Err(e) => Err(e.to_string()),
         }
@@ -1589,7 +1542,14 @@ fn extract_github_details(url: &str) -> Result<GitHubRepoDetails> {
 }
 
 // Add the actual processing function that doesn't create more processors
-async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, pb: &ProgressBar) -> Result<()> {
+/// Processes a repository, returning the documentation health of its README if it has one
+async fn process_github_repo_inner(
+    owner: &str,
+    repo: &str,
+    output_dir: &Path,
+    pb: &ProgressBar,
+    links: Option<&LinkChecker>,
+) -> Result<Option<docs_health::DocsHealthReport>> {
     // Use the progress bar
     pb.set_message(format!("Processing GitHub repository {}/{}", owner, repo));
     
@@ -1601,21 +1561,33 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
     let repo_info = fetch_repo_info(&client, owner, repo).await?;
     let default_branch = repo_info["default_branch"].as_str().unwrap_or("main");
     
+    // Download the repo archive; Ctrl-C abandons the transfer
+    pb.set_message(format!("Downloading {}/{} (branch: {})", owner, repo, default_branch));
+    progress::stage("Downloading repository");
+    pipeline::enter(Stage::Download).await;
+    let archive_bytes = cancellation::or_cancelled(download_repo_archive(&client, owner, repo, default_branch)).await?;
+    
     // Create repo directory in output_dir
     let repo_dir = output_dir.join(format!("github_{}_{}_{}", owner, repo, chrono::Utc::now().format("%Y%m%d%H%M%S")));
     tokio_fs::create_dir_all(&repo_dir).await?;
     
-    // Download the repo archive
-    pb.set_message(format!("Downloading {}/{} (branch: {})", owner, repo, default_branch));
-    let archive_bytes = download_repo_archive(&client, owner, repo, default_branch).await?;
-    
     // Create a temp directory for extraction
     let extract_dir = repo_dir.join("repo_content");
     tokio_fs::create_dir_all(&extract_dir).await?;
     
     // Extract the archive
     pb.set_message("Extracting repository contents");
+    progress::stage("Extracting repository");
+    pipeline::enter(Stage::Extract).await;
     extract_archive(&archive_bytes, &extract_dir).await?;
+    drop(archive_bytes);
+    pipeline::enter(Stage::Analyze).await;
+    
+    // Nothing has been analyzed yet, so an interrupted run leaves nothing behind
+    if cancellation::is_cancelled() {
+        let _ = tokio_fs::remove_dir_all(&repo_dir).await;
+        return Err(ProcessorError::Cancelled);
+    }
     
     // Find the root directory in the extracted content (usually there's one top-level directory)
     let mut entries = tokio_fs::read_dir(&extract_dir).await?;
@@ -1687,30 +1659,42 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
     // Look for README file
     pb.set_message("Processing README file");
     content.push_str("## README Content\n\n");
-    let readme = find_and_read_file(&root_dir, "README.md").await;
-    if let Ok(readme_content) = readme {
-        content.push_str(&readme_content);
-    } else {
-        // Try README.txt, README.rst, or just README
-        let readme_txt = find_and_read_file(&root_dir, "README.txt").await;
-        if let Ok(readme_txt_content) = readme_txt {
-            content.push_str(&readme_txt_content);
-        } else {
-            let readme_rst = find_and_read_file(&root_dir, "README.rst").await;
-            if let Ok(readme_rst_content) = readme_rst {
-                content.push_str(&readme_rst_content);
-            } else {
-                let readme_no_ext = find_and_read_file(&root_dir, "README").await;
-                if let Ok(readme_no_ext_content) = readme_no_ext {
-                    content.push_str(&readme_no_ext_content);
-                } else {
-                    content.push_str("No README found in repository.\n");
-                }
-            }
+    // Try README.md first, then README.txt, README.rst, or just README
+    let mut readme = None;
+    for name in ["README.md", "README.txt", "README.rst", "README"] {
+        if let Ok(readme_content) = find_and_read_file(&root_dir, name).await {
+            readme = Some(readme_content);
+            break;
         }
     }
+    match &readme {
+        Some(readme_content) => content.push_str(readme_content),
+        None => content.push_str("No README found in repository.\n"),
+    }
     content.push_str("\n\n");
     
+    // Grade the README so documentation gaps show up next to the content
+    let mut readme_health = None;
+    if let Some(readme_content) = &readme {
+        let version = docs_health::declared_version(&root_dir);
+        let mut report = docs_health::audit_readme(readme_content, Some(root_dir.as_path()), version.as_deref());
+        if let Some(links) = links {
+            docs_health::verify_external_links(&mut report, links).await;
+        }
+        content.push_str(&report.to_markdown());
+        readme_health = Some(report);
+    }
+    
+    // Probe documentation links for dead, redirected and moved references
+    if let Some(links) = links {
+        pb.set_message("Checking documentation links");
+        let link_report = links.check_repository(&root_dir).await;
+        if link_report.checked > 0 {
+            content.push_str("## Findings\n\n");
+            content.push_str(&link_report.to_markdown());
+        }
+    }
+    
     // Look for package files
     pb.set_message("Processing dependency files");
     content.push_str("## Dependencies\n\n");
@@ -1727,13 +1711,23 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
         content.push_str("No dependency files found.\n\n");
     }
     
+    // Compare the declared dependencies with their latest releases
+    pb.set_message("Checking dependency freshness");
+    let freshness = FreshnessChecker::default().check_repository(&root_dir).await;
+    if !freshness.dependencies.is_empty() {
+        content.push_str(&freshness.to_markdown());
+    }
+    
     // Process code files
     pb.set_message("Processing code files");
+    progress::stage("Processing code files");
     content.push_str("## Code Files\n\n");
     
     // Find all code files
     let mut code_files = Vec::new();
     find_code_files(&root_dir, &mut code_files).await?;
+    let mut files_done = 0;
+    let mut interrupted = false;
     
     if code_files.is_empty() {
         content.push_str("No code files found in the repository.\n\n");
@@ -1768,6 +1762,19 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
             content.push_str(&size_analysis);
         }
         
+        // Measure the complexity of every function, the code repeated across files and what
+        // they cost to maintain, listing the worst
+        let (complexity, duplication, maintainability) = measure_sources(&extract_dir, &code_files).await;
+        if complexity.functions > 0 {
+            content.push_str(&complexity.to_markdown());
+        }
+        if duplication.files > 0 {
+            content.push_str(&duplication.to_markdown());
+        }
+        if maintainability.files > 0 {
+            content.push_str(&maintainability.to_markdown());
+        }
+        
         // Include the full set of code files (no artificial limit)
         let important_files = code_files.clone();
         
@@ -1786,8 +1793,8 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
         let mut dirs: Vec<_> = files_by_dir.keys().collect();
         dirs.sort();
         
-        // Process each directory
-        for dir in dirs {
+        // Process each directory, stopping early on Ctrl-C
+        'dirs: for dir in dirs {
             if let Some(files) = files_by_dir.get(dir) {
                 let dir_name = dir.to_string_lossy();
                 if !dir_name.is_empty() {
@@ -1795,6 +1802,12 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
                 }
                 
                 for file_path in files {
+                    if cancellation::is_cancelled() {
+                        interrupted = true;
+                        break 'dirs;
+                    }
+                    files_done += 1;
+                    progress::files(files_done, code_files.len());
                     let rel_path = file_path.strip_prefix(&extract_dir)
                         .unwrap_or(&file_path)
                         .to_string_lossy();
@@ -1838,8 +1851,26 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
         }
     }
     
+    // Mark the output as partial and save it as is
+    if interrupted {
+        content.insert_str(0, &cancellation::incomplete_notice(files_done, code_files.len(), "source files"));
+        let organized_content = common::organize_content(&content, &format!("{}/{}", owner, repo), "github");
+        let output_path = common::save_comprehensive_output(
+            &format!("{}-{}", owner, repo),
+            "github",
+            Some(default_branch),
+            &organized_content,
+            output_dir,
+            pb
+        ).await?;
+        common::write_artifact(&organized_content, &output_dir.join(format!("{}-{}_github_repo.txt", owner, repo))).await?;
+        pb.finish_with_message(format!("[INTERRUPTED] Partial output for {}/{} saved to: {}", owner, repo, output_path.display()));
+        return Err(ProcessorError::Cancelled);
+    }
+    
     // Look for documentation
     pb.set_message("Processing documentation");
+    progress::stage("Processing documentation");
     content.push_str("## Documentation\n\n");
     
     // Check for documentation in common locations
@@ -1908,6 +1939,7 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
     if !found_docs {
         content.push_str("No additional documentation found.\n\n");
     }
+    pipeline::enter(Stage::Write).await;
     
     // Organize and enhance the content
     let organized_content = common::organize_content(&content, &format!("{}/{}", owner, repo), "github");
@@ -1916,6 +1948,7 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
     let output_path = common::save_comprehensive_output(
         &format!("{}-{}", owner, repo),
         "github",
+        Some(default_branch),
         &organized_content,
         output_dir,
         pb
@@ -1923,12 +1956,16 @@ async fn process_github_repo_inner(owner: &str, repo: &str, output_dir: &Path, p
     
     // Also save a copy to the main output directory with a standardized name format
     let main_output_filename = format!("{}-{}_github_repo.txt", owner, repo);
-    let main_output_path = output_dir.join(&main_output_filename);
-    tokio::fs::write(&main_output_path, &organized_content).await?;
+    common::write_artifact(&organized_content, &output_dir.join(&main_output_filename)).await?;
+    
+    // Summarize what changed since the last time this repository was processed
+    if let Some(diff_path) = run_diff::record_run_logged(output_dir, &format!("{}/{}", owner, repo), &root_dir).await {
+        info!("Changes since previous run written to {}", diff_path.display());
+    }
     
     pb.finish_with_message(format!("[SUCCESS] Repository {}/{} processed successfully. Output saved to: {}", 
                                  owner, repo, output_path.display()));
-    Ok(())
+    Ok(readme_health)
 }
 
 // Helper function to build a file tree (fix recursion with Box::pin)
@@ -2152,9 +2189,9 @@ async fn calculate_code_size_metrics(root_dir: &Path) -> Result<String> {
     // Group by language/extension
     let mut lang_files: std::collections::HashMap<String, Vec<PathBuf>> = std::collections::HashMap::new();
     let mut lang_sizes: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
-    let mut lang_loc: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    let mut lang_lines: std::collections::HashMap<String, LineCounts> = std::collections::HashMap::new();
     let mut total_size: u64 = 0;
-    let mut total_loc: usize = 0;
+    let mut total_lines = LineCounts::default();
     
     for file_path in &code_files {
         if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
@@ -2162,24 +2199,24 @@ async fn calculate_code_size_metrics(root_dir: &Path) -> Result<String> {
             lang_files.entry(lang.clone()).or_default().push(file_path.clone());
             
             // Get file size
-            if let Ok(metadata) = std::fs::metadata(file_path) {
+            if let Ok(metadata) = tokio::fs::metadata(file_path).await {
                 let size = metadata.len();
                 *lang_sizes.entry(lang.clone()).or_default() += size;
                 total_size += size;
                 
-                // Count lines of code
+                // Tell code from comment and blank lines
                 if let Ok(content) = tokio::fs::read_to_string(file_path).await {
-                    let loc = content.lines().count();
-                    *lang_loc.entry(lang.clone()).or_default() += loc;
-                    total_loc += loc;
+                    let counts = lines::count(&file_path.to_string_lossy(), &content);
+                    lang_lines.entry(lang.clone()).or_default().add(counts);
+                    total_lines.add(counts);
                 }
             }
         }
     }
     
     // Display metrics by language
-    result.push_str("| Language | Files | Lines of Code | Size (KB) | % of Codebase |\n");
-    result.push_str("|----------|-------|---------------|-----------|---------------|\n");
+    result.push_str("| Language | Files | Lines of Code | Comments | Blanks | Size (KB) | % of Codebase |\n");
+    result.push_str("|----------|-------|---------------|----------|--------|-----------|---------------|\n");
     
     let mut langs: Vec<_> = lang_files.keys().collect();
     langs.sort();
@@ -2187,24 +2224,38 @@ async fn calculate_code_size_metrics(root_dir: &Path) -> Result<String> {
     for lang in langs {
         let files = lang_files.get(lang).unwrap();
         let size = lang_sizes.get(lang).unwrap_or(&0);
-        let lines = lang_loc.get(lang).unwrap_or(&0);
+        let lines = lang_lines.get(lang).copied().unwrap_or_default();
         let percentage = if total_size > 0 { 
             (*size as f64 / total_size as f64) * 100.0 
         } else { 
             0.0 
         };
         
-        result.push_str(&format!("| {} | {} | {} | {:.2} | {:.1}% |\n", 
-            lang, files.len(), lines, (*size as f64) / 1024.0, percentage));
+        result.push_str(&format!("| {} | {} | {} | {} | {} | {:.2} | {:.1}% |\n", 
+            lang, files.len(), lines.code, lines.comments, lines.blanks, (*size as f64) / 1024.0, percentage));
     }
     
     // Add totals
-    result.push_str(&format!("| **Total** | **{}** | **{}** | **{:.2}** | **100%** |\n\n", 
-        code_files.len(), total_loc, (total_size as f64) / 1024.0));
+    result.push_str(&format!("| **Total** | **{}** | **{}** | **{}** | **{}** | **{:.2}** | **100%** |\n\n", 
+        code_files.len(), total_lines.code, total_lines.comments, total_lines.blanks, (total_size as f64) / 1024.0));
         
     Ok(result)
 }
 
+/// Complexity of the functions in `files`, the code they repeat and their maintainability,
+/// named by their path under `root`
+async fn measure_sources(root: &Path, files: &[PathBuf]) -> (ComplexityReport, DuplicationReport, MaintainabilityReport) {
+    let mut sources = Vec::new();
+    for file_path in files {
+        if let Ok(source) = tokio::fs::read_to_string(file_path).await {
+            let rel_path = file_path.strip_prefix(root).unwrap_or(file_path).to_string_lossy().into_owned();
+            sources.push((rel_path, source));
+        }
+    }
+    let sources = || sources.iter().map(|(path, content)| (path.as_str(), content.as_str()));
+    (ComplexityReport::from_sources(sources()), DuplicationReport::from_sources(sources()), MaintainabilityReport::from_sources(sources()))
+}
+
 /// Recursively find all files in a directory
 async fn find_all_files(dir: &Path, result: &mut Vec<PathBuf>) -> io::Result<()> {
     if dir.is_dir() {
@@ -2244,7 +2295,8 @@ pub async fn process_github_repo(url: &str, output_dir: &Path, pb: &ProgressBar)
     let repo_details = extract_github_details(url)?;
     
     // Process the repository
-    process_github_repo_inner(&repo_details.owner, &repo_details.repo, output_dir, pb).await
+    process_github_repo_inner(&repo_details.owner, &repo_details.repo, output_dir, pb, None).await?;
+    Ok(())
 }
 
 // ... existing code ...
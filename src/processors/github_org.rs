@@ -1,4 +1,5 @@
 use crate::error::ProcessorError;
+use crate::http;
 use crate::processors::common::{self, setup_progress_style};
 use indicatif::{ProgressBar, ProgressStyle};
 use reqwest::Client;
@@ -106,7 +107,7 @@ pub async fn process_github_org(
 }
 
 async fn get_org_info(org_name: &str) -> Result<Value, ProcessorError> {
-    let client = Client::new();
+    let client = http::client()?;
     let api_url = format!("https://api.github.com/orgs/{}", org_name);
     
     let response = client.get(&api_url)
@@ -121,7 +122,7 @@ async fn get_org_info(org_name: &str) -> Result<Value, ProcessorError> {
 }
 
 async fn get_org_repos(org_name: &str) -> Result<Vec<Value>, ProcessorError> {
-    let client = Client::new();
+    let client = http::client()?;
     let mut all_repos = Vec::new();
     let mut page = 1;
     
@@ -1,4 +1,5 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
 use crate::processors::common::{self, setup_progress_style, save_output_file};
 use crate::config::Config;
 use crate::processors::PackageProcessor;
@@ -51,7 +52,7 @@ impl GoProcessor {
     /// # Returns
     /// A new GoProcessor instance wrapped in a Result
     pub fn new() -> Result<Self> {
-        let client = common::create_client_with_user_agent();
+        let client = common::create_client_with_user_agent()?;
         
         Ok(Self {
             client,
@@ -61,10 +62,7 @@ impl GoProcessor {
     async fn fetch_module_info(&self, module_path: &str) -> Result<GoModule> {
         let url = format!("https://proxy.golang.org/{module_path}/@v/list");
         
-        let response = self.client.get(&url)
-            .send()
-            .await
-            .map_err(|e| ProcessorError::Http(e))?;
+        let response = http::send(self.client.get(&url)).await?;
 
         if !response.status().is_success() {
             return Err(ProcessorError::Message(
@@ -169,7 +167,7 @@ pub async fn process_go_package(
     let package_name = package_path.split('/').last().unwrap_or(&package_path);
     
     // Create HTTP client
-    let client = Arc::new(reqwest::Client::builder()
+    let client = Arc::new(http::client_builder()
         .timeout(Duration::from_secs(30))
         .build()?);
     
@@ -191,9 +189,8 @@ pub async fn process_go_package(
     pb.set_message(format!("Fetching documentation for: {}", package_path));
     
     let doc_url = format!("https://pkg.go.dev/{}", package_path);
-    let response = client.get(&doc_url)
-        .header("User-Agent", "Mozilla/5.0")
-        .send()
+    let response = http::send(client.get(&doc_url)
+        .header("User-Agent", "Mozilla/5.0"))
         .await?;
     
     if !response.status().is_success() {
@@ -290,6 +287,7 @@ pub async fn process_go_package(
     let _output_path = common::save_comprehensive_output(
         package_name,
         "go",
+        version.as_deref().map(str::trim),
         &organized_content,
         output_dir,
         pb
@@ -502,10 +500,10 @@ async fn fetch_repository_data(repo_url: &str, client: &Client, content: &Arc<Mu
             
             // Fetch repository information
             let repo_api_url = format!("https://api.github.com/repos/{}/{}", owner, repo);
-            match client.get(&repo_api_url).send().await {
+            match http::fetch(client.get(&repo_api_url)).await {
                 Ok(response) => {
                     if response.status().is_success() {
-                        if let Ok(repo_info) = response.json::<serde_json::Value>().await {
+                        if let Ok(repo_info) = response.json::<serde_json::Value>() {
                             // Extract all the data we need from the JSON before locking the mutex
                             let mut lines = Vec::new();
                             lines.push("\n### Repository Information\n\n".to_string());
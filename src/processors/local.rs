@@ -2,6 +2,17 @@ use crate::error::{ProcessorError, Result};
 use crate::config::Config;
 use crate::processors::common::{self, save_output_file, setup_progress_style, create_progress_bar};
 use crate::processors::PackageProcessor;
+use crate::templates::{self, TemplateKind};
+use crate::docs_health::{self, DocsHealthReport};
+use crate::link_checker::LinkChecker;
+use crate::analytics::complexity::ComplexityReport;
+use crate::analytics::duplication::DuplicationReport;
+use crate::analytics::lines;
+use crate::analytics::maintainability::MaintainabilityReport;
+use crate::analytics::ownership::OwnershipReport;
+use crate::run_diff;
+use crate::cancellation;
+use crate::progress;
 use std::fs as std_fs;
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
@@ -66,6 +77,17 @@ pub struct LocalRepoInfo {
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// A single source file included in a local repository analysis
+#[derive(Debug, Clone, Serialize)]
+struct SourceFile {
+    /// Path relative to the analyzed directory
+    path: String,
+    /// Language hint used for the code fence
+    language: String,
+    /// File contents
+    content: String,
+}
+
 /// Processor for local directories and files
 pub struct LocalProcessor;
 
@@ -102,7 +124,7 @@ impl LocalProcessor {
     }
 
     /// Process a local directory or file
-    async fn process_path(&self, path: &str, output_dir: &Path, _config: &Config) -> Result<()> {
+    async fn process_path(&self, path: &str, output_dir: &Path, config: &Config) -> Result<()> {
         let normalized = crate::utils::normalize_user_input_path(path);
         let input_path = normalized.as_path();
         
@@ -146,6 +168,8 @@ impl LocalProcessor {
 
         let pb = create_progress_bar();
         setup_progress_style(&pb);
+        let links = if config.processing.check_links { Some(LinkChecker::shared(config)?) } else { None };
+        let mut readme_health = None;
         
         if input_path.is_file() {
             pb.set_message("Processing file...");
@@ -160,8 +184,8 @@ impl LocalProcessor {
         } else if input_path.is_dir() {
             pb.set_message("Processing directory...");
             // Process directory, skipping inaccessible parts
-            match self.process_directory(input_path, output_dir, &pb).await {
-                Ok(_) => {},
+            match self.process_directory(input_path, output_dir, &pb, links).await {
+                Ok(report) => readme_health = report,
                 Err(e) => {
                     warn!("Partial processing due to: {}", e);
                     // Continue anyway - we processed what we could
@@ -170,10 +194,13 @@ impl LocalProcessor {
         } else {
             warn!("Unusual path type for: '{}'. Attempting to process as directory.", input_path.display());
             // Try to process as directory anyway
-            let _ = self.process_directory(input_path, output_dir, &pb).await;
+            let _ = self.process_directory(input_path, output_dir, &pb, links).await;
         }
 
         pb.finish_with_message("Processing completed");
+        if input_path.is_dir() {
+            docs_health::require_score(readme_health.as_ref(), config.processing.docs_min_score)?;
+        }
         Ok(())
     }
 
@@ -198,14 +225,20 @@ impl LocalProcessor {
         let output_filename = format!("{}_{}_{}.txt", timestamp, file_name, ANALYSIS_SUFFIX);
         let output_path = local_output_dir.join(output_filename);
         
-        save_output_file(&analysis, &output_path).await?;
+        let output_path = save_output_file(&analysis, &output_path).await?;
         
-        println!("Processed file: {} -> {}", file_path.display(), output_path.display());
+        info!("Processed file: {} -> {}", file_path.display(), output_path.display());
         Ok(())
     }
 
-    /// Process a directory
-    async fn process_directory(&self, dir_path: &Path, output_dir: &Path, pb: &ProgressBar) -> Result<()> {
+    /// Process a directory, returning the documentation health of its README if it has one
+    async fn process_directory(
+        &self,
+        dir_path: &Path,
+        output_dir: &Path,
+        pb: &ProgressBar,
+        links: Option<&LinkChecker>,
+    ) -> Result<Option<DocsHealthReport>> {
         let dir_name = dir_path.file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("unknown_directory");
@@ -234,9 +267,9 @@ impl LocalProcessor {
             analysis.push_str("No accessible files found.\n");
             analysis.push_str("All files may be inaccessible due to permissions or the directory may be empty.\n");
             
-            save_output_file(&analysis, &output_path).await?;
+            let output_path = save_output_file(&analysis, &output_path).await?;
             info!("Created minimal analysis at: {}", output_path.display());
-            return Ok(());
+            return Ok(None);
         }
         
         pb.set_length(file_count as u64);
@@ -244,7 +277,92 @@ impl LocalProcessor {
         // Analyze the repository structure
         let repo_info = self.analyze_repository(dir_path, &files).await?;
         
-        // Generate comprehensive analysis
+        let directory_tree = self.generate_directory_tree(dir_path, 0, 3).await?;
+        
+        // Read every source file up front so both the template and the built-in layout can use them
+        progress::stage("Reading files");
+        let mut sources = Vec::with_capacity(files.len());
+        for file_path in &files {
+            // Ctrl-C keeps the files read so far
+            if cancellation::is_cancelled() {
+                break;
+            }
+            let rel_path = file_path.strip_prefix(dir_path).unwrap_or(file_path);
+            pb.inc(1);
+            pb.set_message(format!("Reading {}", rel_path.display()));
+            
+            let content = tokio_fs::read_to_string(file_path).await
+                .unwrap_or_else(|_| "/* Unable to read file */".to_string());
+            sources.push(SourceFile {
+                path: rel_path.display().to_string(),
+                language: self.detect_file_type(file_path),
+                content,
+            });
+            progress::files(sources.len(), file_count);
+        }
+        
+        // Grade the top-level README, if there is one
+        let version = docs_health::declared_version(dir_path);
+        let mut docs_health = sources.iter()
+            .find(|s| !s.path.contains(std::path::MAIN_SEPARATOR) && s.path.to_lowercase().starts_with("readme"))
+            .map(|readme| docs_health::audit_readme(&readme.content, Some(dir_path), version.as_deref()));
+        if let (Some(report), Some(links)) = (docs_health.as_mut(), links) {
+            docs_health::verify_external_links(report, links).await;
+        }
+        let complexity = ComplexityReport::from_sources(sources.iter().map(|s| (s.path.as_str(), s.content.as_str())));
+        let duplication = DuplicationReport::from_sources(sources.iter().map(|s| (s.path.as_str(), s.content.as_str())));
+        let maintainability = MaintainabilityReport::from_sources(sources.iter().map(|s| (s.path.as_str(), s.content.as_str())));
+        // Who wrote what, when the directory is a git checkout
+        let ownership = OwnershipReport::from_git(dir_path).await;
+        
+        let mut context = tera::Context::new();
+        context.insert("repo", &repo_info);
+        context.insert("docs_health", &docs_health);
+        context.insert("complexity", &complexity);
+        context.insert("duplication", &duplication);
+        context.insert("maintainability", &maintainability);
+        context.insert("ownership", &ownership);
+        context.insert("generated", &Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
+        context.insert("directory_tree", &directory_tree);
+        context.insert("files", &sources);
+        
+        let mut analysis = match templates::render_user_template(TemplateKind::Analysis, &context) {
+            Some(rendered) => rendered,
+            None => Self::render_default_analysis(dir_name, &repo_info, &directory_tree, docs_health.as_ref(), &complexity, &duplication, &maintainability, ownership.as_ref(), &sources),
+        };
+        let interrupted = sources.len() < file_count;
+        if interrupted {
+            analysis.insert_str(0, &cancellation::incomplete_notice(sources.len(), file_count, "files"));
+        }
+        
+        // Save the analysis (output_path already defined at start of function)
+        let output_path = save_output_file(&analysis, &output_path).await?;
+        if interrupted {
+            warn!("Interrupted; partial analysis of {} saved to {}", dir_path.display(), output_path.display());
+            return Err(ProcessorError::Cancelled);
+        }
+        
+        let target = dir_path.canonicalize().unwrap_or_else(|_| dir_path.to_path_buf());
+        if let Some(diff_path) = run_diff::record_run_logged(output_dir, &target.to_string_lossy(), dir_path).await {
+            info!("Changes since previous run: {}", diff_path.display());
+        }
+        
+        info!("Processed directory: {} -> {}", dir_path.display(), output_path.display());
+        Ok(docs_health)
+    }
+
+    /// Builds the built-in analysis layout used when no user template is installed
+    fn render_default_analysis(
+        dir_name: &str,
+        repo_info: &LocalRepoInfo,
+        directory_tree: &str,
+        docs_health: Option<&DocsHealthReport>,
+        complexity: &ComplexityReport,
+        duplication: &DuplicationReport,
+        maintainability: &MaintainabilityReport,
+        ownership: Option<&OwnershipReport>,
+        sources: &[SourceFile],
+    ) -> String {
         let mut analysis = String::new();
         analysis.push_str(&format!("# Local Repository Analysis: {}\n\n", dir_name));
         analysis.push_str(&format!("**Author:** Nik Jois <nikjois@llamasearch.ai>\n"));
@@ -273,38 +391,39 @@ impl LocalProcessor {
         // Directory structure
         analysis.push_str("## Directory Structure\n\n");
         analysis.push_str("```\n");
-        analysis.push_str(&self.generate_directory_tree(dir_path, 0, 3).await?);
+        analysis.push_str(directory_tree);
         analysis.push_str("```\n\n");
+        
+        if let Some(report) = docs_health {
+            analysis.push_str(&report.to_markdown());
+        }
+        
+        if complexity.functions > 0 {
+            analysis.push_str(&complexity.to_markdown());
+        }
+        if duplication.files > 0 {
+            analysis.push_str(&duplication.to_markdown());
+        }
+        if maintainability.files > 0 {
+            analysis.push_str(&maintainability.to_markdown());
+        }
+        if let Some(report) = ownership {
+            analysis.push_str(&report.to_markdown());
+        }
 
         // ----------------------------------------------------------------
         // Full source files dump
         // ----------------------------------------------------------------
         analysis.push_str("## Full Source Files\n\n");
 
-        for file_path in files {
-            let rel_path = file_path.strip_prefix(dir_path).unwrap_or(&file_path);
-            pb.inc(1);
-            pb.set_message(format!("Reading {}", rel_path.display()));
-
-            analysis.push_str(&format!("### `{}`\n\n", rel_path.display()));
-
-            let lang_hint = self.detect_file_type(&file_path);
-            analysis.push_str(&format!("```{}\n", lang_hint));
-
-            if let Ok(src) = tokio_fs::read_to_string(file_path).await {
-                analysis.push_str(&src);
-            } else {
-                analysis.push_str("/* Unable to read file */");
-            }
-
+        for source in sources {
+            analysis.push_str(&format!("### `{}`\n\n", source.path));
+            analysis.push_str(&format!("```{}\n", source.language));
+            analysis.push_str(&source.content);
             analysis.push_str("\n```\n\n");
         }
         
-        // Save the analysis (output_path already defined at start of function)
-        save_output_file(&analysis, &output_path).await?;
-        
-        println!("Processed directory: {} -> {}", dir_path.display(), output_path.display());
-        Ok(())
+        analysis
     }
 
     /// Collect all files in a directory, respecting ignore patterns
@@ -535,7 +654,9 @@ impl LocalProcessor {
         analysis.push_str(&format!("- **Path:** {}\n", file_path.display()));
         analysis.push_str(&format!("- **Type:** {}\n", file_type));
         analysis.push_str(&format!("- **Size:** {} bytes\n", content.len()));
-        analysis.push_str(&format!("- **Lines:** {}\n", content.lines().count()));
+        let lines = lines::count(&file_path.to_string_lossy(), content);
+        analysis.push_str(&format!("- **Lines:** {} ({} code, {} comments, {} blank)\n",
+            crate::perf::count_lines(content.as_bytes()), lines.code, lines.comments, lines.blanks));
         
         if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
             analysis.push_str(&format!("- **Language:** {}\n", self.extension_to_language(ext)));
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use crate::error::{ProcessorError, Result};
 use crate::config::Config;
+use crate::http;
 use std::path::Path;
 
 /// Module for GitHub repository processing
@@ -21,6 +22,8 @@ pub mod npm;
 pub mod crates;
 /// Module for local file/directory processing  
 pub mod local;
+/// Module for external processor plugins
+pub mod plugin;
 
 /// Interface for package processors
 #[async_trait]
@@ -38,7 +41,60 @@ pub trait PackageProcessor: Send + Sync {
 /// Factory for creating package processors
 pub struct ProcessorFactory;
 
+/// Processor names accepted by [`ProcessorFactory::create_named`]; installed plugins add their own
+pub const PROCESSOR_NAMES: &[&str] = &["local", "github", "pypi", "pypi-profile", "npm", "crates", "go"];
+
 impl ProcessorFactory {
+    /// Creates the processor called `name`, bypassing URL detection
+    ///
+    /// `name` is one of [`PROCESSOR_NAMES`] or the name of an installed plugin,
+    /// compared case-insensitively.
+    pub fn create_named(name: &str) -> Result<Box<dyn PackageProcessor + Send + Sync>> {
+        Ok(match name.to_lowercase().as_str() {
+            "local" => Box::new(local::LocalProcessor::new()),
+            "github" => Box::new(github::GitHubProcessor::new()?),
+            "pypi" => Box::new(pypi::PyPiProcessor::new()?),
+            "pypi-profile" => Box::new(PyPiProfileProcessor::new()?),
+            "npm" => Box::new(npm::NpmProcessor::new()?),
+            "crates" => Box::new(crates::CratesProcessor::new()?),
+            "go" => Box::new(go::GoProcessor::new()?),
+            _ => match plugin::plugins().iter().find(|p| p.manifest().name.eq_ignore_ascii_case(name)) {
+                Some(plugin) => Box::new(plugin.clone()),
+                None => return Err(ProcessorError::Validation(format!(
+                    "Unknown processor '{}'; expected one of {} or an installed plugin",
+                    name,
+                    PROCESSOR_NAMES.join(", ")
+                ))),
+            },
+        })
+    }
+    
+    /// Creates the processor called `processor` when given, otherwise the one detected for `url`
+    pub fn create_for(url: &str, processor: Option<&str>) -> Result<Box<dyn PackageProcessor + Send + Sync>> {
+        match processor {
+            Some(name) => Self::create_named(name),
+            None => Self::create_processor(url),
+        }
+    }
+    
+    /// Whether `url` would be read from the server's own filesystem or handed
+    /// to an external plugin, rather than fetched from a public registry
+    pub fn runs_on_host(url: &str, processor: Option<&str>) -> bool {
+        match processor {
+            Some(name) => name.eq_ignore_ascii_case("local")
+                || !PROCESSOR_NAMES.iter().any(|known| known.eq_ignore_ascii_case(name)),
+            None => local::LocalProcessor::is_local_path(url) || plugin::find(url).is_some(),
+        }
+    }
+    
+    /// Describes how `url` is processed, naming the processor when it was chosen explicitly
+    pub fn url_type(url: &str, processor: Option<&str>) -> String {
+        match processor {
+            Some(name) => format!("{} (forced)", name),
+            None => Self::detect_url_type(url),
+        }
+    }
+    
     /// Creates the appropriate processor for the given URL
     pub fn create_processor(url: &str) -> Result<Box<dyn PackageProcessor + Send + Sync>> {
         let url_lower = url.to_lowercase();
@@ -48,6 +104,11 @@ impl ProcessorFactory {
             return Ok(Box::new(local::LocalProcessor::new()));
         }
         
+        // External plugins, for URL schemes the built-in processors do not know
+        if let Some(plugin) = plugin::find(url) {
+            return Ok(Box::new(plugin.clone()));
+        }
+        
         // Go package detection
         if url_lower.contains("pkg.go.dev") || url_lower.contains("golang.org/pkg") {
             return Ok(Box::new(go::GoProcessor::new()?));
@@ -55,43 +116,43 @@ impl ProcessorFactory {
         
         // GitHub repository detection
         if url_lower.contains("github.com") {
-            return Ok(Box::new(github::GitHubProcessor::new()));
+            return Ok(Box::new(github::GitHubProcessor::new()?));
         }
         
         // PyPI profile detection
         if url_lower.contains("pypi.org/user/") {
-            return Ok(Box::new(PyPiProfileProcessor::new()));
+            return Ok(Box::new(PyPiProfileProcessor::new()?));
         }
         
         // PyPI package detection
         if url_lower.contains("pypi.org/project/") || 
            url_lower.contains("pip install") || 
            url.starts_with("pip ") {
-            return Ok(Box::new(pypi::PyPiProcessor::new()));
+            return Ok(Box::new(pypi::PyPiProcessor::new()?));
         }
         
         // NPM package detection
         if url_lower.contains("npmjs.com") || 
            url_lower.contains("npm install") || 
            url.starts_with("npm ") {
-            return Ok(Box::new(npm::NpmProcessor::new()));
+            return Ok(Box::new(npm::NpmProcessor::new()?));
         }
         
         // Rust crate detection
         if url_lower.contains("crates.io") || 
            url_lower.contains("docs.rs") || 
            url.starts_with("cargo add ") {
-            return Ok(Box::new(crates::CratesProcessor::new()));
+            return Ok(Box::new(crates::CratesProcessor::new()?));
         }
         
         // Try to guess the type from domain or format
         if url_lower.contains("gitlab.com") || url_lower.contains("bitbucket.org") {
             // Treat other repository hosts like GitHub
-            return Ok(Box::new(github::GitHubProcessor::new()));
+            return Ok(Box::new(github::GitHubProcessor::new()?));
         }
         
         Err(ProcessorError::Message(format!(
-            "Unsupported URL: {}. Please use a URL from a supported source (GitHub, PyPI, NPM, crates.io, pkg.go.dev) or provide a local file/directory path",
+            "Unsupported URL: {}. Please use a URL from a supported source (GitHub, PyPI, NPM, crates.io, pkg.go.dev or an installed plugin) or provide a local file/directory path",
             url
         )))
     }
@@ -102,6 +163,8 @@ impl ProcessorFactory {
         
         if local::LocalProcessor::is_local_path(url) {
             return "Local File/Directory".to_string();
+        } else if let Some(plugin) = plugin::find(url) {
+            return format!("{} (plugin)", plugin.manifest().name);
         } else if url_lower.contains("github.com") {
             return "GitHub Repository".to_string();
         } else if url_lower.contains("pypi.org/user/") {
@@ -134,11 +197,11 @@ impl PyPiProfileProcessor {
     /// Creates a new PyPI profile processor with default configuration
     ///
     /// # Returns
-    /// A new PyPiProfileProcessor instance
-    pub fn new() -> Self {
-        Self {
-            client: reqwest::Client::new(),
-        }
+    /// A new PyPiProfileProcessor instance, or an error if the HTTP client cannot be built
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http::client()?,
+        })
     }
     
     fn extract_username(&self, url: &str) -> Result<String> {
@@ -181,7 +244,7 @@ impl PackageProcessor for PyPiProfileProcessor {
 
 impl Clone for PyPiProfileProcessor {
     fn clone(&self) -> Self {
-        Self::new()
+        Self { client: self.client.clone() }
     }
 }
 
@@ -209,6 +272,17 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_create_named_overrides_detection() {
+        for name in PROCESSOR_NAMES {
+            assert!(ProcessorFactory::create_named(name).is_ok(), "{} should be known", name);
+        }
+        let forced = ProcessorFactory::create_for("https://github.com/owner/repo", Some("Local")).unwrap();
+        assert_eq!(forced.name(), local::LocalProcessor::new().name());
+        assert!(ProcessorFactory::create_named("cobol").is_err());
+        assert_eq!(ProcessorFactory::url_type("https://github.com/a/b", Some("go")), "go (forced)");
+    }
+
     #[tokio::test]
     async fn test_processor_validation() -> Result<()> {
         let github_url = "https://github.com/owner/repo";
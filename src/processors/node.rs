@@ -1,4 +1,5 @@
 use crate::error::ProcessorError;
+use crate::http;
 use indicatif::ProgressBar;
 use reqwest::Client;
 use regex::Regex;
@@ -16,7 +17,7 @@ use tokio;
 /// extracts it, generates an index using the common function,
 /// and writes the index into the node_packages output folder.
 pub async fn process_node_package(url: &str, output_dir: &Path, pb: &ProgressBar) -> Result<(), ProcessorError> {
-    let client = Client::new();
+    let client = http::client()?;
     let response = client.get(url)
         .send().await.map_err(|e| ProcessorError::Network(e.to_string()))?;
     if !response.status().is_success() {
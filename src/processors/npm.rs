@@ -1,4 +1,6 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::pipeline::{self, Stage};
 use crate::processors::common::{self, setup_progress_style, save_output_file};
 use crate::processors::PackageProcessor;
 use crate::config::Config;
@@ -28,10 +30,10 @@ pub struct NpmProcessor {
 
 impl NpmProcessor {
     /// Creates a new NPM processor with default configuration
-    pub fn new() -> Self {
-        Self {
-            client: Client::new()
-        }
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http::client()?
+        })
     }
 
     /// Validates if a URL is a valid NPM package URL
@@ -66,14 +68,14 @@ impl NpmProcessor {
     /// * `package_name` - The name of the NPM package
     async fn get_package_info(&self, package_name: &str) -> Result<serde_json::Value> {
         let url = format!("https://registry.npmjs.org/{}", package_name);
-        let response = self.client.get(&url).send().await?;
+        let response = http::send(self.client.get(&url)).await?;
         let json = response.json().await?;
         Ok(json)
     }
 
     async fn download_package(&self, package_name: &str, temp_dir: &Path) -> Result<PathBuf> {
         let url = format!("https://registry.npmjs.org/{}/-/{}-latest.tgz", package_name, package_name);
-        let response = self.client.get(&url).send().await?;
+        let response = http::send(self.client.get(&url)).await?;
         let bytes = response.bytes().await?;
         
         let archive_path = temp_dir.join("package.tgz");
@@ -99,7 +101,7 @@ impl NpmProcessor {
             structure
         );
 
-        fs::write(output_dir.join("output.md"), content).await?;
+        common::write_artifact(&content, &output_dir.join("output.md")).await?;
         Ok(())
     }
 
@@ -231,21 +233,12 @@ async fn process_npm_package_by_name(package_name: &str, output_dir: &Path, pb:
     pb.set_message(format!("Fetching NPM package: {}", package_name));
     
     // Create a client to fetch package information
-    let client = Arc::new(Client::new());
+    let client = Arc::new(http::client()?);
     
     // Get package information from NPM registry
     let url = format!("{}/{}", NPM_REGISTRY_API, package_name);
     pb.set_message(format!("Fetching package info from NPM registry: {}", url));
-    let response = client.get(&url).send().await?;
-    
-    if !response.status().is_success() {
-        return Err(ProcessorError::Network(format!(
-            "Failed to fetch package info: HTTP {}", 
-            response.status()
-        )));
-    }
-    
-    let package_info: serde_json::Value = response.json().await?;
+    let package_info = common::fetch_registry_json(&client, "npm", package_name, &url).await?;
     
     // Create temp directory for downloads
     let temp_dir = tempdir().map_err(|e| ProcessorError::IO(e))?;
@@ -421,7 +414,8 @@ async fn process_npm_package_by_name(package_name: &str, output_dir: &Path, pb:
     };
     
     // Download tarball
-    let response = client.get(tarball_url).send().await?;
+    pipeline::enter(Stage::Download).await;
+    let response = http::fetch(client.get(tarball_url)).await?;
     if !response.status().is_success() {
         return Err(ProcessorError::Network(format!(
             "Failed to download package tarball: HTTP {}", 
@@ -429,11 +423,13 @@ async fn process_npm_package_by_name(package_name: &str, output_dir: &Path, pb:
         )));
     }
     
-    let tarball_bytes = response.bytes().await?;
+    let tarball_bytes = response.bytes();
     let package_path = temp_dir.path().join(format!("{}-{}.tgz", package_name, latest_version));
     fs::write(&package_path, &tarball_bytes).await?;
+    drop(response);
     
     // Extract tarball
+    pipeline::enter(Stage::Extract).await;
     let extract_dir = temp_dir.path().join("extract");
     fs::create_dir_all(&extract_dir).await?;
     
@@ -452,6 +448,7 @@ async fn process_npm_package_by_name(package_name: &str, output_dir: &Path, pb:
             "Failed to extract package: exit code {:?}", status.code()
         )));
     }
+    pipeline::enter(Stage::Analyze).await;
     
     // Find the package.json in extracted files
     pb.set_message("Processing package content...");
@@ -585,12 +582,14 @@ async fn process_npm_package_by_name(package_name: &str, output_dir: &Path, pb:
     }
     
     // Organize and save the content
+    pipeline::enter(Stage::Write).await;
     let organized_content = common::organize_content(&content, package_name, "npm");
     
     // Save comprehensive output
     let output_path = common::save_comprehensive_output(
         package_name,
         "npm",
+        Some(latest_version).filter(|v| *v != "unknown"),
         &organized_content,
         output_dir,
         pb
@@ -715,7 +714,7 @@ struct PackageInfo {
 /// # Returns
 /// Result indicating success or failure
 async fn save_output(output_path: &Path, output: String) -> Result<()> {
-    fs::write(output_path, output).await?;
+    common::write_artifact(&output, output_path).await?;
     Ok(())
 }
 
@@ -724,14 +723,14 @@ mod tests {
     
     #[tokio::test]
     async fn test_npm_processor() {
-        let processor = NpmProcessor::new();
+        let processor = NpmProcessor::new().unwrap();
         assert!(processor.accepts("https://www.npmjs.com/package/express"));
         assert!(!processor.accepts("https://pypi.org/project/requests"));
     }
     
     #[tokio::test]
     async fn test_validation() {
-        let processor = NpmProcessor::new();
+        let processor = NpmProcessor::new().unwrap();
         let result1 = processor.validate("https://www.npmjs.com/package/express").await;
         assert!(result1.is_ok());
         
@@ -0,0 +1,375 @@
+//! External processor plugins
+//!
+//! A plugin is an executable in the plugins directory (`LLAMA_PLUGIN_DIR`, or
+//! `plugins/` next to the config file) that handles URLs the built-in
+//! processors do not. The host talks to it with newline-delimited JSON over
+//! stdio, starting one process per request:
+//!
+//! - `{"type": "describe"}` is answered with one line naming the plugin and the
+//!   URL prefixes it accepts: `{"name": "mercurial", "prefixes": ["hg+https://"]}`
+//! - `{"type": "process", "url": ..., "output_dir": ..., "config": ...}` is
+//!   answered with any number of `progress`, `artifact` and `error` lines:
+//!   `{"type": "artifact", "name": "repo_processed.txt", "content": "..."}`
+//!
+//! Artifacts are written by the host, so compression, encryption and the run
+//! manifest apply to them like to any other output. A non-zero exit status or
+//! an `error` line fails the request; the plugin's stderr is passed through.
+
+use crate::config::Config;
+use crate::error::{ProcessorError, Result};
+use crate::processors::{common, PackageProcessor};
+use async_trait::async_trait;
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::process::Child;
+
+/// Environment variable overriding the plugins directory
+pub const PLUGIN_DIR_ENV_VAR: &str = "LLAMA_PLUGIN_DIR";
+
+/// Longest a plugin may take to describe itself unless configured otherwise
+pub const DEFAULT_DESCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+static PLUGINS: OnceCell<Vec<PluginProcessor>> = OnceCell::new();
+static DESCRIBE_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+
+/// Sets how long plugins get to describe themselves during discovery
+pub fn set_describe_timeout(timeout: Duration) {
+    let _ = DESCRIBE_TIMEOUT.set(timeout);
+}
+
+/// What a plugin reports about itself in answer to `describe`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    /// Name shown in progress messages and used with `--processor`
+    pub name: String,
+    /// URL prefixes the plugin handles, matched case-insensitively
+    pub prefixes: Vec<String>,
+    /// One-line description
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Request sent to a plugin on its stdin
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request<'a> {
+    Describe,
+    Process { url: &'a str, output_dir: &'a Path, config: Config },
+}
+
+/// Message read from a plugin's stdout while it processes a URL
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Progress { message: String },
+    Artifact { name: String, content: String },
+    Error { message: String },
+}
+
+/// Processor backed by an external plugin executable
+#[derive(Debug, Clone)]
+pub struct PluginProcessor {
+    path: PathBuf,
+    manifest: PluginManifest,
+    name: &'static str,
+}
+
+impl PluginProcessor {
+    /// Starts the plugin at `path` and asks it to describe itself, killing it after `timeout`
+    pub async fn describe(path: &Path, timeout: Duration) -> Result<Self> {
+        let mut child = spawn(path)?;
+        let request = serde_json::to_string(&Request::Describe)?;
+        let answer = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(format!("{}\n", request).as_bytes()).await?;
+            }
+            let mut line = String::new();
+            if let Some(stdout) = child.stdout.take() {
+                tokio::io::BufReader::new(stdout).read_line(&mut line).await?;
+            }
+            Ok::<_, ProcessorError>((line, child.wait().await?))
+        };
+        let (line, status) = match tokio::time::timeout(timeout, answer).await {
+            Ok(answer) => answer?,
+            Err(_) => {
+                let _ = child.kill().await;
+                return Err(ProcessorError::Processing(format!(
+                    "plugin {} did not describe itself within {:?}", path.display(), timeout
+                )));
+            }
+        };
+        if !status.success() {
+            return Err(ProcessorError::Processing(format!("plugin {} exited with {}", path.display(), status)));
+        }
+        let manifest: PluginManifest = serde_json::from_str(&line).map_err(|e| {
+            ProcessorError::Processing(format!("plugin {} sent an invalid description: {}", path.display(), e))
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            // Processor names are static; plugins are discovered once per process
+            name: Box::leak(manifest.name.clone().into_boxed_str()),
+            manifest,
+        })
+    }
+
+    /// What the plugin reported about itself
+    pub fn manifest(&self) -> &PluginManifest {
+        &self.manifest
+    }
+
+    /// Path of the plugin executable
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[async_trait]
+impl PackageProcessor for PluginProcessor {
+    async fn process(&self, url: &str, output_dir: &Path, config: &Config) -> Result<()> {
+        let pb = indicatif::ProgressBar::new_spinner();
+        common::setup_progress_style(&pb);
+        pb.set_message(format!("Processing {} with plugin {}", url, self.name));
+
+        let mut child = spawn(&self.path)?;
+        let request = Request::Process { url, output_dir, config: config.redacted() };
+        let run = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                let mut line = serde_json::to_vec(&request)?;
+                line.push(b'\n');
+                stdin.write_all(&line).await?;
+            }
+
+            let stdout = child.stdout.take()
+                .ok_or_else(|| ProcessorError::Processing("plugin stdout is not captured".into()))?;
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            let mut failure = None;
+            while let Some(line) = lines.next_line().await? {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<Message>(&line) {
+                    Ok(Message::Progress { message }) => pb.set_message(message),
+                    Ok(Message::Artifact { name, content }) => {
+                        let path = output_dir.join(artifact_path(&name)?);
+                        if let Some(dir) = path.parent() {
+                            tokio::fs::create_dir_all(dir).await?;
+                        }
+                        let written = common::write_artifact(&content, &path).await?;
+                        debug!("Plugin {} wrote {}", self.name, written.display());
+                    }
+                    Ok(Message::Error { message }) => failure = Some(message),
+                    Err(e) => warn!("Ignoring malformed message from plugin {}: {}", self.name, e),
+                }
+            }
+            Ok::<_, ProcessorError>((failure, child.wait().await?))
+        };
+
+        // Plugins get `processing.plugin_timeout_secs` per URL; 0 disables the limit
+        let limit = config.processing.plugin_timeout_secs;
+        let outcome = if limit == 0 {
+            Ok(run.await)
+        } else {
+            tokio::time::timeout(Duration::from_secs(limit), run).await
+        };
+        pb.finish_and_clear();
+        let (failure, status) = match outcome {
+            Ok(outcome) => outcome?,
+            Err(_) => {
+                let _ = child.kill().await;
+                return Err(ProcessorError::Processing(format!("plugin {} timed out after {}s", self.name, limit)));
+            }
+        };
+        match failure {
+            Some(message) => Err(ProcessorError::Processing(format!("plugin {}: {}", self.name, message))),
+            None if !status.success() => Err(ProcessorError::Processing(format!("plugin {} exited with {}", self.name, status))),
+            None => Ok(()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn accepts(&self, url: &str) -> bool {
+        let url = url.trim().to_lowercase();
+        self.manifest.prefixes.iter().any(|prefix| url.starts_with(&prefix.to_lowercase()))
+    }
+
+    async fn validate(&self, url: &str) -> Result<()> {
+        if self.accepts(url) {
+            Ok(())
+        } else {
+            Err(ProcessorError::Validation(format!("plugin {} does not handle {}", self.name, url)))
+        }
+    }
+}
+
+/// Directory plugins are discovered in
+pub fn plugin_dir() -> Option<PathBuf> {
+    match std::env::var_os(PLUGIN_DIR_ENV_VAR) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Config::config_path().ok()?.parent().map(|dir| dir.join("plugins")),
+    }
+}
+
+/// Describes every executable in `dir`, skipping plugins that fail to answer within `timeout`
+pub async fn discover(dir: &Path, timeout: Duration) -> Vec<PluginProcessor> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .filter(|path| is_executable(path))
+        .collect();
+    paths.sort();
+    let mut plugins = Vec::with_capacity(paths.len());
+    for path in &paths {
+        match PluginProcessor::describe(path, timeout).await {
+            Ok(plugin) => plugins.push(plugin),
+            Err(e) => warn!("Skipping plugin {}: {}", path.display(), e),
+        }
+    }
+    plugins
+}
+
+/// Plugins in the plugins directory, discovered on first use
+///
+/// Discovery runs on a thread with its own runtime, so this works both from
+/// synchronous code and from inside the main runtime.
+pub fn plugins() -> &'static [PluginProcessor] {
+    PLUGINS.get_or_init(|| {
+        let Some(dir) = plugin_dir() else {
+            return Vec::new();
+        };
+        let timeout = DESCRIBE_TIMEOUT.get().copied().unwrap_or(DEFAULT_DESCRIBE_TIMEOUT);
+        std::thread::scope(|scope| {
+            scope.spawn(|| match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime.block_on(discover(&dir, timeout)),
+                Err(e) => {
+                    warn!("Could not start plugin discovery: {}", e);
+                    Vec::new()
+                }
+            }).join().unwrap_or_default()
+        })
+    })
+}
+
+/// The first plugin that accepts `url`
+pub fn find(url: &str) -> Option<&'static PluginProcessor> {
+    plugins().iter().find(|plugin| plugin.accepts(url))
+}
+
+/// Starts a plugin with piped stdio; it is killed if its handle is dropped
+fn spawn(path: &Path) -> Result<Child> {
+    Ok(tokio::process::Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()?)
+}
+
+/// Checks that an artifact name stays inside the output directory
+fn artifact_path(name: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(name);
+    let inside = !name.is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)));
+    if inside {
+        Ok(path)
+    } else {
+        Err(ProcessorError::Validation(format!("plugin artifact name '{}' must be a relative path", name)))
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    const ECHO_PLUGIN: &str = r#"#!/bin/sh
+read request
+case "$request" in
+  *'"describe"'*) echo '{"name":"echo","prefixes":["echo://"]}' ;;
+  *) echo '{"type":"progress","message":"working"}'
+     echo '{"type":"artifact","name":"echo_processed.txt","content":"hello from echo"}' ;;
+esac
+"#;
+
+    const SILENT_PLUGIN: &str = "#!/bin/sh\nsleep 30\n";
+
+    const SLOW_PLUGIN: &str = r#"#!/bin/sh
+read request
+case "$request" in
+  *'"describe"'*) echo '{"name":"slow","prefixes":["slow://"]}' ;;
+  *) sleep 30 ;;
+esac
+"#;
+
+    #[tokio::test]
+    async fn test_discover_and_process() {
+        let plugins = tempdir().unwrap();
+        let script = plugins.path().join("echo");
+        std::fs::write(&script, ECHO_PLUGIN).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(plugins.path().join("README"), "not a plugin").unwrap();
+
+        let found = discover(plugins.path(), DEFAULT_DESCRIBE_TIMEOUT).await;
+        assert_eq!(found.len(), 1);
+        let plugin = &found[0];
+        assert_eq!(plugin.name(), "echo");
+        assert!(plugin.accepts("ECHO://anything"));
+        assert!(!plugin.accepts("https://github.com/a/b"));
+
+        let out = tempdir().unwrap();
+        plugin.process("echo://anything", out.path(), &Config::default()).await.unwrap();
+        let written = std::fs::read_to_string(out.path().join("echo_processed.txt")).unwrap();
+        assert_eq!(written, "hello from echo");
+    }
+
+    #[tokio::test]
+    async fn test_slow_plugins_are_killed() {
+        let plugins = tempdir().unwrap();
+        for (name, script) in [("silent", SILENT_PLUGIN), ("slow", SLOW_PLUGIN)] {
+            let path = plugins.path().join(name);
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let found = discover(plugins.path(), Duration::from_millis(500)).await;
+        assert_eq!(found.iter().map(|p| p.name()).collect::<Vec<_>>(), ["slow"]);
+
+        let mut config = Config::default();
+        config.processing.plugin_timeout_secs = 1;
+        let out = tempdir().unwrap();
+        let started = std::time::Instant::now();
+        let err = found[0].process("slow://x", out.path(), &config).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_artifact_path_stays_inside_output() {
+        assert!(artifact_path("sub/dump.txt").is_ok());
+        assert!(artifact_path("../escape.txt").is_err());
+        assert!(artifact_path("/etc/passwd").is_err());
+        assert!(artifact_path("").is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::pipeline::{self, Stage};
 use crate::config::Config;
 use crate::processors::common::{self, download_file, setup_progress_style};
 use crate::processors::PackageProcessor;
@@ -18,6 +20,7 @@ use tokio::process::Command as TokioCommand;
 use tokio::io::AsyncWriteExt;
 use std::io::{self, Write};
 use tempfile::TempDir;
+use log::{debug, info};
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
 use zip::ZipArchive;
@@ -44,22 +47,21 @@ impl PyPiProcessor {
     /// Creates a new PyPI processor with an extended timeout for handling large packages
     ///
     /// # Returns
-    /// A new PyPiProcessor instance configured for PyPI package processing
-    pub fn new() -> Self {
-        println!("Creating new PyPiProcessor");
-        Self {
-            client: Client::builder()
+    /// A new PyPiProcessor instance configured for PyPI package processing, or
+    /// an error if the HTTP client cannot be built
+    pub fn new() -> Result<Self> {
+        debug!("Creating new PyPiProcessor");
+        Ok(Self {
+            client: http::client_builder()
                 .timeout(std::time::Duration::from_secs(120)) // Increase timeout for large packages
-                .build()
-                .unwrap_or_else(|_| Client::new())
-        }
+                .build()?
+        })
     }
 
     async fn validate_pypi_package(&self, package_name: &str) -> Result<()> {
-        println!("Validating PyPI package: {}", package_name);
+        debug!("Validating PyPI package: {}", package_name);
         let url = format!("{}/{}/json", PYPI_API_BASE, package_name);
-        let response = self.client.get(&url)
-            .send()
+        let response = http::send(self.client.get(&url))
             .await
             .map_err(|e| ProcessorError::Network(e.to_string()))?;
             
@@ -71,7 +73,7 @@ impl PyPiProcessor {
     }
     
     async fn extract_package_name(&self, url: &str) -> Result<String> {
-        println!("Extracting package name from: {}", url);
+        debug!("Extracting package name from: {}", url);
         // Handle various PyPI URL formats
         // 1. https://pypi.org/project/package-name/
         if let Some(name) = self.extract_from_pypi_url(url) {
@@ -107,10 +109,8 @@ impl PyPiProcessor {
     
     async fn get_package_info(&self, package_name: &str) -> Result<Value> {
         let url = format!("{}/{}/json", PYPI_API_BASE, package_name);
-        let response = self.client.get(&url)
-            .send()
-            .await
-            .map_err(|e| ProcessorError::Http(e))?;
+        let response = http::send(self.client.get(&url))
+            .await?;
             
         if !response.status().is_success() {
             return Err(ProcessorError::Processing(format!("Failed to fetch PyPI package info: HTTP {}", response.status())));
@@ -162,21 +162,15 @@ impl PyPiProcessor {
         let output_path = temp_dir.join(filename);
         
         // Download the file
-        println!("Downloading package from {}", download_url);
-        let response = self.client.get(download_url)
-            .send()
-            .await
-            .map_err(|e| ProcessorError::Http(e))?;
+        info!("Downloading package from {}", download_url);
+        let response = http::fetch(self.client.get(download_url))
+            .await?;
         
         if !response.status().is_success() {
             return Err(ProcessorError::Processing(format!("Failed to download package: HTTP {}", response.status())));
         }
         
-        let content = response.bytes()
-            .await
-            .map_err(|e| ProcessorError::Http(e))?;
-        
-        tokio::fs::write(&output_path, content).await
+        tokio::fs::write(&output_path, response.bytes()).await
             .map_err(|e| ProcessorError::IO(e))?;
         
         Ok(output_path)
@@ -184,7 +178,7 @@ impl PyPiProcessor {
 
     /// Extract the downloaded package
     async fn extract_package(&self, package_path: &Path, extract_dir: &Path) -> Result<()> {
-        println!("Extracting package: {}", package_path.display());
+        info!("Extracting package: {}", package_path.display());
         
         let package_path_str = package_path.to_string_lossy().to_string();
         
@@ -239,7 +233,7 @@ impl PyPiProcessor {
 
     /// Process the extracted package content
     async fn process_package_content(&self, extract_dir: &Path) -> Result<String> {
-        println!("Processing package content in {}", extract_dir.display());
+        info!("Processing package content in {}", extract_dir.display());
         
         // Find all Python files in the extracted directory
         let output = TokioCommand::new("find")
@@ -358,7 +352,7 @@ impl PyPiProcessor {
     }
 
     async fn process_package(&self, package_name: &str, output_dir: &Path) -> Result<()> {
-        println!("Processing package: {}", package_name);
+        info!("Processing package: {}", package_name);
         
         // Get package information
         let package_info = self.get_package_info(package_name).await?;
@@ -438,11 +432,9 @@ impl PyPiProcessor {
         let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
         let sanitized_name = package_name.replace('/', "_").replace('\\', "_");
         let filename = format!("{}_{}_pypi.txt", timestamp, sanitized_name);
-        let output_path = output_dir.join(filename);
+        let output_path = common::write_artifact(&content, &output_dir.join(filename)).await?;
         
-        fs::write(&output_path, content).await?;
-        
-        println!("[SUCCESS] Package processed successfully. Output saved to: {}", output_path.display());
+        info!("Package processed successfully. Output saved to: {}", output_path.display());
         
         Ok(())
     }
@@ -501,7 +493,7 @@ pub async fn process_pypi_package_thoroughly(package_name: &str, output_dir: &Pa
     pb.set_message(format!("Processing PyPI package: {}", package_name));
     
     // Create client
-    let client = Arc::new(Client::builder()
+    let client = Arc::new(http::client_builder()
         .timeout(Duration::from_secs(300))  // Increased timeout for large packages
         .build()
         .unwrap_or_default());
@@ -509,16 +501,7 @@ pub async fn process_pypi_package_thoroughly(package_name: &str, output_dir: &Pa
     // Get package info
     pb.set_message(format!("Fetching package info for: {}", package_name));
     let url = format!("https://pypi.org/pypi/{}/json", package_name);
-    let response = client.get(&url).send().await?;
-    
-    if !response.status().is_success() {
-        return Err(ProcessorError::Network(format!(
-            "Failed to fetch package info: HTTP {}", 
-            response.status()
-        )));
-    }
-    
-    let package_info: serde_json::Value = response.json().await?;
+    let package_info = common::fetch_registry_json(&client, "pypi", package_name, &url).await?;
     
     // Create temp directory
     let temp_dir = tempdir().map_err(|e| ProcessorError::IO(e))?;
@@ -552,12 +535,14 @@ pub async fn process_pypi_package_thoroughly(package_name: &str, output_dir: &Pa
     let package_path = temp_dir.path().join(format!("{}{}", package_name, file_extension));
     
     // Download package
-    let response = client.get(&download_url).send().await?;
-    let bytes = response.bytes().await?;
-    tokio::fs::write(&package_path, &bytes).await?;
+    pipeline::enter(Stage::Download).await;
+    let response = http::fetch(client.get(&download_url)).await?;
+    tokio::fs::write(&package_path, response.bytes()).await?;
+    drop(response);
     
     // Extract the package
     pb.set_message(format!("Extracting package: {}", package_name));
+    pipeline::enter(Stage::Extract).await;
     let extract_dir = temp_dir.path().join("extract");
     tokio::fs::create_dir_all(&extract_dir).await?;
     
@@ -598,6 +583,7 @@ pub async fn process_pypi_package_thoroughly(package_name: &str, output_dir: &Pa
     } else {
         return Err(ProcessorError::Processing("Unsupported archive format".to_string()));
     }
+    pipeline::enter(Stage::Analyze).await;
     
     // Start building comprehensive output
     let mut content = String::new();
@@ -827,12 +813,11 @@ pub async fn process_pypi_package_thoroughly(package_name: &str, output_dir: &Pa
     content.push_str(&format!("*Documentation generated by LlamaPackageService for {} (pypi)*\n", package_name));
     
     // Save comprehensive output
+    pipeline::enter(Stage::Write).await;
     let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
     let sanitized_name = package_name.replace('/', "_").replace('\\', "_");
     let filename = format!("{}_{}_pypi_processed.txt", timestamp, sanitized_name);
-    let output_path = output_dir.join(filename);
-    
-    tokio::fs::write(&output_path, content).await?;
+    let output_path = common::write_artifact(&content, &output_dir.join(filename)).await?;
     
     pb.finish_with_message(format!("Package processed successfully. Output saved to: {}", output_path.display()));
     Ok(())
@@ -1118,13 +1103,13 @@ pub async fn process_pypi_url(url: &str, output_dir: &Path, pb: &ProgressBar) ->
     });
     
     if already_processing {
-        println!("Avoiding recursive call to process_pypi_url");
+        debug!("Avoiding recursive call to process_pypi_url");
         return Ok(());
     }
     
     pb.set_message(format!("Processing PyPI package: {}", url));
     
-    let processor = PyPiProcessor::new();
+    let processor = PyPiProcessor::new().unwrap();
     let package_name = processor.extract_package_name(url).await?;
     
     // Use the new comprehensive processing function
@@ -1146,7 +1131,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_extract_package_name() {
-        let processor = PyPiProcessor::new();
+        let processor = PyPiProcessor::new().unwrap();
         
         assert_eq!(
             processor.extract_package_name("https://pypi.org/project/requests/").await.unwrap(),
@@ -1167,7 +1152,7 @@ mod tests {
     #[tokio::test]
     async fn test_pypi_processor() {
         let _temp_dir = TempDir::new().unwrap();
-        let processor = PyPiProcessor::new();
+        let processor = PyPiProcessor::new().unwrap();
         
         // This is a simplified test that only checks the URL extraction
         // In a real test environment, we would mock the PyPI API responses
@@ -1,4 +1,5 @@
 use crate::error::{ProcessorError, Result};
+use crate::http;
 use crate::processors::common::{self, save_output_file};
 use crate::processors::pypi::process_pypi_url as process_pypi_package;
 use colored::Colorize;
@@ -40,7 +41,7 @@ pub async fn process_pypi_profile(
     info!("Created user directory: {}", user_dir.display());
     
     // Configure HTTP client with appropriate headers
-    let client = Client::builder()
+    let client = http::client_builder()
         .user_agent(USER_AGENT)
         .build()?;
     
@@ -52,9 +53,8 @@ pub async fn process_pypi_profile(
     
     // Try the PyPI JSON API to get projects
     let api_url = format!("https://pypi.org/pypi/{}/json", username);
-    let api_response = client.get(&api_url)
-        .header(header::ACCEPT, "application/json")
-        .send()
+    let api_response = http::send(client.get(&api_url)
+        .header(header::ACCEPT, "application/json"))
         .await;
     
     // If the API call succeeds, try parsing it as the user's own package
@@ -79,9 +79,8 @@ pub async fn process_pypi_profile(
     let search_url = format!("https://pypi.org/search/?q=author%3A{}&o=", username);
     
     // Use a regular GET request to the search page
-    let search_response = client.get(&search_url)
-        .header(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
-        .send()
+    let search_response = http::send(client.get(&search_url)
+        .header(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8"))
         .await?;
     
     if search_response.status().is_success() {
@@ -113,9 +112,7 @@ pub async fn process_pypi_profile(
         
         // Try fetching recent packages by this author from PyPI's simple index
         let simple_index_url = format!("https://pypi.org/simple/");
-        let simple_response = client.get(&simple_index_url)
-            .send()
-            .await;
+        let simple_response = http::send(client.get(&simple_index_url)).await;
             
         if let Ok(response) = simple_response {
             if response.status().is_success() {
@@ -180,8 +177,7 @@ pub async fn process_pypi_profile(
     }
     
     // Save summary
-    let summary_path = user_dir.join(format!("profile_{}_summary.txt", username));
-    fs::write(&summary_path, summary).await?;
+    let summary_path = common::write_artifact(&summary, &user_dir.join(format!("profile_{}_summary.txt", username))).await?;
     info!("Saved profile summary to: {}", summary_path.display());
     
     // Process each package
@@ -48,18 +48,20 @@ impl ProcessorRegistry {
     /// * `name` - The name of the processor to retrieve
     ///
     /// # Returns
-    /// Some(processor) if the name is registered, None otherwise
-    pub async fn get(&self, name: &str) -> Option<Box<dyn PackageProcessor + Send + Sync + 'static>> {
+    /// Some(processor) if the name is registered, None otherwise, or an error
+    /// if the processor cannot be created
+    pub async fn get(&self, name: &str) -> Result<Option<Box<dyn PackageProcessor + Send + Sync + 'static>>> {
         let processors = self.processors.read().await;
-        processors.get(name).map(|_p| {
-            match name {
-                "GitHub" => Box::new(github::GitHubProcessor::new()) as Box<dyn PackageProcessor + Send + Sync + 'static>,
-                "PyPI" => Box::new(pypi::PyPiProcessor::new()) as Box<dyn PackageProcessor + Send + Sync + 'static>,
-                "npm" => Box::new(npm::NpmProcessor::new()) as Box<dyn PackageProcessor + Send + Sync + 'static>,
-                "crates" => Box::new(crates::CratesProcessor::new()) as Box<dyn PackageProcessor + Send + Sync + 'static>,
-                _ => Box::new(github::GitHubProcessor::new()) as Box<dyn PackageProcessor + Send + Sync + 'static>,
-            }
-        })
+        if !processors.contains_key(name) {
+            return Ok(None);
+        }
+        Ok(Some(match name {
+            "GitHub" => Box::new(github::GitHubProcessor::new()?) as Box<dyn PackageProcessor + Send + Sync + 'static>,
+            "PyPI" => Box::new(pypi::PyPiProcessor::new()?) as Box<dyn PackageProcessor + Send + Sync + 'static>,
+            "npm" => Box::new(npm::NpmProcessor::new()?) as Box<dyn PackageProcessor + Send + Sync + 'static>,
+            "crates" => Box::new(crates::CratesProcessor::new()?) as Box<dyn PackageProcessor + Send + Sync + 'static>,
+            _ => Box::new(github::GitHubProcessor::new()?) as Box<dyn PackageProcessor + Send + Sync + 'static>,
+        }))
     }
 }
 
@@ -1,4 +1,5 @@
 use crate::error::ProcessorError;
+use crate::http;
 use crate::processors::common::{self, setup_progress_style};
 use indicatif::ProgressBar;
 use reqwest::Client;
@@ -56,7 +57,7 @@ pub async fn process_rust_crate(
 }
 
 async fn get_crate_github_url(crate_name: &str) -> Result<String, ProcessorError> {
-    let client = Client::new();
+    let client = http::client()?;
     let api_url = format!("https://crates.io/api/v1/crates/{}", crate_name);
     
     let response = client.get(&api_url)
@@ -197,7 +197,7 @@ mod tests {
     #[tokio::test]
     async fn test_github_processor() -> Result<()> {
         let (_temp_dir, output_dir) = setup_test_dir().await;
-        let processor = GitHubProcessor::new();
+        let processor = GitHubProcessor::new().unwrap();
         
         // Test URL validation
         assert!(processor.validate("https://github.com/rust-lang/rust").await.is_ok());
@@ -248,7 +248,7 @@ mod tests {
     #[tokio::test]
     async fn test_crates_processor() -> Result<()> {
         let (_temp_dir, output_dir) = setup_test_dir().await;
-        let processor = CratesProcessor::new();
+        let processor = CratesProcessor::new().unwrap();
         
         // Test URL validation
         assert!(processor.validate("https://crates.io/crates/tokio").await.is_ok());
@@ -264,10 +264,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_processor_clone() -> Result<()> {
-        let github = GitHubProcessor::new();
+        let github = GitHubProcessor::new().unwrap();
         let npm = NPMProcessor::new();
         let pypi = PyPIProcessor::new();
-        let crates = CratesProcessor::new();
+        let crates = CratesProcessor::new().unwrap();
         
         let _github_clone = github.clone();
         let _npm_clone = npm.clone();
@@ -279,7 +279,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_error_handling() -> Result<()> {
-        let processor = GitHubProcessor::new();
+        let processor = GitHubProcessor::new().unwrap();
         
         // Test invalid URL
         let result = processor.validate("invalid-url").await;
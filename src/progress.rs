@@ -0,0 +1,264 @@
+//! Progress reported by processors while they work
+//!
+//! Processors report the stage they are in, how many files they have processed
+//! and how many bytes they have downloaded. Reports go to the sink installed
+//! for the current task with [`with_sink`], which is how the REST API streams
+//! job progress; without a sink they are dropped, so reporting costs nothing on
+//! the command line.
+//!
+//! A [`ProgressAggregator`] combines what several tasks report, such as the
+//! URLs of a batch, into an overall percentage and an estimate of the time left.
+
+use crate::output_organizer::format_file_size;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One progress report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// Started a new stage, such as downloading or reading files
+    Stage {
+        /// What is being done
+        stage: String,
+    },
+    /// Files processed so far
+    Files {
+        /// Files done
+        processed: usize,
+        /// Files in total
+        total: usize,
+    },
+    /// Bytes downloaded so far by the current transfer
+    Download {
+        /// Bytes received
+        bytes: u64,
+    },
+}
+
+/// Receiver of the progress reported inside [`with_sink`]
+pub type ProgressSink = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+tokio::task_local! {
+    static SINK: ProgressSink;
+}
+
+/// Runs `fut`, sending everything it reports to `sink`
+pub async fn with_sink<F: Future>(sink: ProgressSink, fut: F) -> F::Output {
+    SINK.scope(sink, fut).await
+}
+
+/// Reports `event` to the current task's sink, if any
+pub fn report(event: ProgressEvent) {
+    let _ = SINK.try_with(|sink| sink(event));
+}
+
+/// Reports the start of a stage
+pub fn stage(stage: impl Into<String>) {
+    report(ProgressEvent::Stage { stage: stage.into() });
+}
+
+/// Reports how many of `total` files have been processed
+pub fn files(processed: usize, total: usize) {
+    report(ProgressEvent::Files { processed, total });
+}
+
+/// Reports how many bytes the current download has received
+pub fn downloaded(bytes: u64) {
+    report(ProgressEvent::Download { bytes });
+}
+
+/// Share of a task counted as done once it reports anything
+const STARTED_SHARE: f64 = 0.1;
+
+/// What one task of a [`ProgressAggregator`] has reported
+#[derive(Debug, Clone, Default)]
+struct TaskProgress {
+    started: bool,
+    files: Option<(usize, usize)>,
+    bytes: u64,
+    done: bool,
+}
+
+impl TaskProgress {
+    /// How much of the task is done, from 0 to 1
+    fn fraction(&self) -> f64 {
+        match (self.done, self.started, self.files) {
+            (true, _, _) => 1.0,
+            (false, _, Some((processed, total))) if total > 0 => {
+                STARTED_SHARE + (1.0 - STARTED_SHARE) * (processed.min(total) as f64 / total as f64)
+            }
+            (false, true, _) => STARTED_SHARE,
+            (false, false, _) => 0.0,
+        }
+    }
+}
+
+/// Overall progress of a set of tasks, estimated from what each reports
+///
+/// A task counts as a tenth done once it reports anything, and the rest in
+/// proportion to the files it has processed. The time left is extrapolated
+/// from the time taken so far.
+#[derive(Clone)]
+pub struct ProgressAggregator {
+    tasks: Arc<Mutex<Vec<TaskProgress>>>,
+    started: Instant,
+}
+
+impl ProgressAggregator {
+    /// Aggregates the progress of `tasks` tasks, starting the clock now
+    pub fn new(tasks: usize) -> Self {
+        Self { tasks: Arc::new(Mutex::new(vec![TaskProgress::default(); tasks])), started: Instant::now() }
+    }
+
+    /// Sink recording what task number `task` reports
+    pub fn sink(&self, task: usize) -> ProgressSink {
+        let tasks = Arc::clone(&self.tasks);
+        Arc::new(move |event| {
+            let mut tasks = tasks.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(progress) = tasks.get_mut(task) else {
+                return;
+            };
+            progress.started = true;
+            match event {
+                ProgressEvent::Stage { .. } => {}
+                ProgressEvent::Files { processed, total } => progress.files = Some((processed, total)),
+                ProgressEvent::Download { bytes } => progress.bytes = bytes,
+            }
+        })
+    }
+
+    /// Marks task number `task` as done, whether it succeeded or not
+    pub fn finish(&self, task: usize) {
+        if let Some(progress) = self.tasks.lock().unwrap_or_else(|e| e.into_inner()).get_mut(task) {
+            progress.done = true;
+        }
+    }
+
+    /// Overall progress so far
+    pub fn snapshot(&self) -> OverallProgress {
+        self.snapshot_after(self.started.elapsed())
+    }
+
+    fn snapshot_after(&self, elapsed: Duration) -> OverallProgress {
+        let tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        let tasks_done = tasks.iter().filter(|task| task.done).count();
+        let fraction = match tasks.len() {
+            0 => 1.0,
+            total => tasks.iter().map(TaskProgress::fraction).sum::<f64>() / total as f64,
+        };
+        let eta = (fraction > 0.0).then(|| elapsed.mul_f64((1.0 - fraction) / fraction));
+        let percent = (fraction * 100.0).floor() as u8;
+        OverallProgress {
+            tasks_done,
+            tasks_total: tasks.len(),
+            files_processed: tasks.iter().filter_map(|task| task.files).map(|(processed, _)| processed).sum(),
+            files_total: tasks.iter().filter_map(|task| task.files).map(|(_, total)| total).sum(),
+            bytes_downloaded: tasks.iter().map(|task| task.bytes).sum(),
+            // 100% only once every task is done
+            percent: if tasks_done == tasks.len() { 100 } else { percent.min(99) },
+            elapsed,
+            eta,
+        }
+    }
+}
+
+/// Overall progress of the tasks of a [`ProgressAggregator`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverallProgress {
+    /// Tasks finished
+    pub tasks_done: usize,
+    /// Tasks in total
+    pub tasks_total: usize,
+    /// Files processed by all tasks
+    pub files_processed: usize,
+    /// Files the tasks that counted them have in total
+    pub files_total: usize,
+    /// Bytes downloaded by all tasks
+    pub bytes_downloaded: u64,
+    /// Overall percentage done (0-100)
+    pub percent: u8,
+    /// Time since the aggregator was created
+    pub elapsed: Duration,
+    /// Estimated time left, once there is anything to extrapolate from
+    pub eta: Option<Duration>,
+}
+
+impl fmt::Display for OverallProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.percent)?;
+        if self.tasks_total > 1 {
+            write!(f, ", {}/{} done", self.tasks_done, self.tasks_total)?;
+        }
+        if self.files_total > 0 {
+            write!(f, ", {}/{} files", self.files_processed, self.files_total)?;
+        }
+        if self.bytes_downloaded > 0 {
+            write!(f, ", {} downloaded", format_file_size(self.bytes_downloaded))?;
+        }
+        match self.eta {
+            Some(eta) if self.percent < 100 => write!(f, ", ETA {}", format_duration(eta)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Formats `duration` to the second, such as `1h 02m`, `3m 05s` or `12s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reports_reach_only_the_installed_sink() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink_seen = Arc::clone(&seen);
+        let sink: ProgressSink = Arc::new(move |event| sink_seen.lock().unwrap().push(event));
+
+        stage("ignored");
+        with_sink(sink, async {
+            stage("Reading files");
+            files(1, 2);
+            downloaded(512);
+        }).await;
+
+        assert_eq!(*seen.lock().unwrap(), vec![
+            ProgressEvent::Stage { stage: "Reading files".into() },
+            ProgressEvent::Files { processed: 1, total: 2 },
+            ProgressEvent::Download { bytes: 512 },
+        ]);
+    }
+
+    #[test]
+    fn test_aggregated_progress_extrapolates_the_time_left() {
+        let aggregator = ProgressAggregator::new(2);
+        assert_eq!(aggregator.snapshot_after(Duration::from_secs(5)).eta, None);
+
+        let first = aggregator.sink(0);
+        first(ProgressEvent::Download { bytes: 2048 });
+        first(ProgressEvent::Files { processed: 5, total: 10 });
+        aggregator.sink(7)(ProgressEvent::Files { processed: 1, total: 1 });
+        let overall = aggregator.snapshot_after(Duration::from_secs(11));
+        // (0.1 + 0.9 * 0.5) / 2 of the way there after 11 seconds
+        assert_eq!((overall.percent, overall.files_processed, overall.bytes_downloaded), (27, 5, 2048));
+        assert_eq!(overall.eta, Some(Duration::from_secs(29)));
+        assert_eq!(overall.to_string(), "27%, 0/2 done, 5/10 files, 2.0KB downloaded, ETA 29s");
+
+        aggregator.finish(0);
+        aggregator.finish(1);
+        let overall = aggregator.snapshot_after(Duration::from_secs(40));
+        assert_eq!((overall.percent, overall.tasks_done, overall.eta), (100, 2, Some(Duration::ZERO)));
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1h 02m");
+    }
+}
@@ -0,0 +1,112 @@
+//! Offline end-to-end demo on a bundled fixture repository
+//!
+//! The fixture is compiled into the binary, so `quickstart` works from any
+//! directory without network access or API tokens. It runs the local
+//! processor over the fixture twice (the second pass produces a run diff),
+//! writes compressed copies of the analysis and builds the markdown and
+//! JSON indexes and the static site.
+
+use crate::compression::{self, Compression};
+use crate::config::Config;
+use crate::error::Result;
+use crate::output_organizer;
+use crate::processors::{local::LocalProcessor, PackageProcessor};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Name of the bundled fixture repository
+pub const FIXTURE_NAME: &str = "llama-demo";
+
+/// Files of the bundled fixture repository, relative to its root
+pub const FIXTURE_FILES: &[(&str, &str)] = &[
+    ("README.md", include_str!("../assets/quickstart/llama-demo/README.md")),
+    ("Cargo.toml", include_str!("../assets/quickstart/llama-demo/Cargo.toml")),
+    ("src/lib.rs", include_str!("../assets/quickstart/llama-demo/src/lib.rs")),
+    ("docs/usage.md", include_str!("../assets/quickstart/llama-demo/docs/usage.md")),
+];
+
+/// Result of a quickstart run
+#[derive(Debug, Clone)]
+pub struct QuickstartReport {
+    /// Where the fixture repository was written
+    pub fixture_dir: PathBuf,
+    /// Directory holding everything the run generated
+    pub output_dir: PathBuf,
+    /// Every artifact generated, relative to `output_dir`
+    pub artifacts: Vec<PathBuf>,
+}
+
+/// Writes the bundled fixture repository under `dir`, returning its root
+pub fn write_fixture(dir: &Path) -> Result<PathBuf> {
+    let root = dir.join(FIXTURE_NAME);
+    for (relative, content) in FIXTURE_FILES {
+        let path = root.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    Ok(root)
+}
+
+/// Runs the quickstart under `base_dir`, replacing the results of any previous run
+pub async fn run(base_dir: &Path) -> Result<QuickstartReport> {
+    if base_dir.exists() {
+        fs::remove_dir_all(base_dir)?;
+    }
+    let fixture_dir = write_fixture(&base_dir.join("fixture"))?;
+    let output_dir = base_dir.join("output");
+    let config = Config::new(output_dir.clone());
+    fs::create_dir_all(&output_dir)?;
+
+    let processor = LocalProcessor::new();
+    let target = fixture_dir.to_string_lossy().into_owned();
+    for _ in 0..2 {
+        processor.process(&target, &output_dir, &config).await?;
+    }
+
+    // Compressed copies of every plain text analysis
+    let analyses: Vec<PathBuf> = WalkDir::new(&output_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|ext| ext == "txt"))
+        .map(|e| e.into_path())
+        .collect();
+    for path in &analyses {
+        let content = fs::read(path)?;
+        for algorithm in [Compression::Gzip, Compression::Zstd] {
+            compression::write_artifact_with(path, &content, algorithm)?;
+        }
+    }
+
+    output_organizer::generate_index(&output_dir)?;
+
+    let mut artifacts: Vec<PathBuf> = WalkDir::new(&output_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(&output_dir).ok().map(Path::to_path_buf))
+        .collect();
+    artifacts.sort();
+
+    Ok(QuickstartReport { fixture_dir, output_dir, artifacts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_quickstart_generates_all_formats() {
+        let dir = tempdir().unwrap();
+        let report = run(&dir.path().join("quickstart")).await.unwrap();
+
+        assert!(report.fixture_dir.join("src/lib.rs").exists());
+        let names: Vec<String> = report.artifacts.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        for suffix in [".txt", ".txt.gz", ".txt.zst", "_diff.md", "index.md", "index.json", "_site/index.html", "search-index.js"] {
+            assert!(names.iter().any(|n| n.ends_with(suffix)), "missing {} in {:?}", suffix, names);
+        }
+    }
+}
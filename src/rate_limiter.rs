@@ -1,13 +1,22 @@
+use crate::config::RateLimits;
+use crate::error::{ProcessorError, Result};
+use once_cell::sync::OnceCell;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 use tracing::{info, warn};
 
+static UPSTREAM_LIMITS: OnceCell<UpstreamLimits> = OnceCell::new();
+
 /// Limits the rate of operations to prevent API rate limit issues
 pub struct RateLimiter {
     limits: HashMap<String, RateLimit>,
+    default_limit: RateLimit,
     state: Arc<Mutex<HashMap<String, RateLimitState>>>,
 }
 
@@ -45,16 +54,17 @@ impl RateLimiter {
         
         Self {
             limits,
+            default_limit: RateLimit {
+                requests: 10,
+                window: Duration::from_secs(60),
+            },
             state: Arc::new(Mutex::new(HashMap::new())),
         }
     }
     
     /// Acquires a permit for the specified key, waiting if necessary
     pub async fn acquire(&self, key: &str) {
-        let limit = self.limits.get(key).cloned().unwrap_or(RateLimit {
-            requests: 10,
-            window: Duration::from_secs(60),
-        });
+        let limit = self.limits.get(key).cloned().unwrap_or_else(|| self.default_limit.clone());
         
         loop {
             let mut state = self.state.lock().await;
@@ -78,6 +88,11 @@ impl RateLimiter {
         }
     }
     
+    /// Sets the limit applied to keys without an explicit limit
+    pub fn set_default_limit(&mut self, requests: usize, window: Duration) {
+        self.default_limit = RateLimit { requests, window };
+    }
+    
     /// Adds a rate limit for the specified key
     pub fn add_limit(&mut self, key: &str, requests: usize, window: Duration) {
         self.limits.insert(
@@ -88,4 +103,444 @@ impl RateLimiter {
             },
         );
     }
-} 
\ No newline at end of file
+} 
+
+/// Token bucket: holds up to `capacity` tokens and refills continuously
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket of `capacity` tokens refilled at `refill_per_sec`
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        Self {
+            capacity: capacity.max(1) as f64,
+            refill_per_sec,
+            tokens: capacity.max(1) as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available
+    pub fn try_take(&mut self) -> std::result::Result<(), Duration> {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> std::result::Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec))
+    }
+
+    /// Whether the bucket is full again, i.e. indistinguishable from a new one
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+
+    /// Holds up to `capacity` tokens refilled at `refill_per_sec` from now on,
+    /// keeping the tokens left up to the new capacity
+    pub fn set_rate(&mut self, capacity: u32, refill_per_sec: f64) {
+        self.refill(Instant::now());
+        self.capacity = capacity.max(1) as f64;
+        self.refill_per_sec = refill_per_sec;
+        self.tokens = self.tokens.min(self.capacity);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated = now;
+    }
+}
+
+/// One token bucket per client, for limiting inbound requests
+pub struct ClientRateLimiter {
+    burst: AtomicU32,
+    requests_per_minute: AtomicU32,
+    buckets: std::sync::Mutex<HashMap<String, TokenBucket>>,
+    limited: AtomicU64,
+}
+
+impl ClientRateLimiter {
+    /// Buckets are pruned once this many clients are tracked
+    const PRUNE_AT: usize = 10_000;
+
+    /// Allows each client `requests_per_minute` on average and bursts of `burst`;
+    /// `None` when `requests_per_minute` is 0, meaning unlimited
+    pub fn new(requests_per_minute: u32, burst: u32) -> Option<Self> {
+        (requests_per_minute > 0).then(|| Self {
+            burst: AtomicU32::new(burst.max(1)),
+            requests_per_minute: AtomicU32::new(requests_per_minute),
+            buckets: std::sync::Mutex::new(HashMap::new()),
+            limited: AtomicU64::new(0),
+        })
+    }
+
+    /// Counts a request from `client`, or returns how long it has to wait
+    pub fn check(&self, client: &str) -> std::result::Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= Self::PRUNE_AT {
+            let now = Instant::now();
+            buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+        let result = buckets.entry(client.to_string())
+            .or_insert_with(|| TokenBucket::new(self.burst.load(Ordering::Relaxed), self.refill_per_sec()))
+            .try_take();
+        if result.is_err() {
+            self.limited.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Allows each client `requests_per_minute` (more than 0) and bursts of
+    /// `burst` from now on, keeping what clients have used so far
+    pub fn set_rate(&self, requests_per_minute: u32, burst: u32) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        self.burst.store(burst.max(1), Ordering::Relaxed);
+        self.requests_per_minute.store(requests_per_minute.max(1), Ordering::Relaxed);
+        for bucket in buckets.values_mut() {
+            bucket.set_rate(burst, self.refill_per_sec());
+        }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        f64::from(self.requests_per_minute.load(Ordering::Relaxed)) / 60.0
+    }
+
+    /// Requests refused so far
+    pub fn limited(&self) -> u64 {
+        self.limited.load(Ordering::Relaxed)
+    }
+
+    /// Clients currently tracked
+    pub fn clients(&self) -> usize {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// Longest an outbound request waits for a rate limit by default
+pub const DEFAULT_MAX_PAUSE: Duration = Duration::from_secs(15 * 60);
+
+/// Requests per minute and burst allowed to each host unless configured otherwise
+///
+/// GitHub allows 5000 API requests an hour with a token, and crates.io asks
+/// crawlers for at most one request a second.
+pub const DEFAULT_HOST_LIMITS: &[(&str, u32, u32)] = &[
+    ("api.github.com", 80, 10),
+    ("github.com", 60, 10),
+    ("pypi.org", 300, 20),
+    ("registry.npmjs.org", 300, 20),
+    ("crates.io", 60, 1),
+];
+
+/// Requests are spread out once less than this share of a host's budget is left
+const LOW_BUDGET_SHARE: f64 = 0.1;
+
+/// Requests are spread out below this many left when the host sends no limit
+const LOW_BUDGET_REQUESTS: u64 = 10;
+
+/// Pause after a 429 that says nothing about when to retry
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// What an upstream host last said about its rate limit
+#[derive(Debug, Clone, Default)]
+struct HostBudget {
+    /// Requests left in the current window, counting down as requests go out
+    remaining: Option<u64>,
+    /// Requests allowed per window
+    limit: Option<u64>,
+    /// When the window resets
+    reset: Option<Instant>,
+    /// No requests before this, from `Retry-After`
+    paused_until: Option<Instant>,
+    /// When the next request may go out while requests are spread out
+    next_slot: Option<Instant>,
+}
+
+/// Throttles outbound requests to each host's own budget and to the budget
+/// it reports in its `X-RateLimit-Remaining`, `X-RateLimit-Reset` and
+/// `Retry-After` headers
+///
+/// Hosts with a configured limit get a token bucket shared by every request
+/// to them, so concurrent processors cannot add up to more than the limit.
+/// On top of that, while plenty of the budget is left requests go out at once. Once little is
+/// left they are spread evenly over the time until the window resets, and
+/// once it is spent, or the host asks to retry later, they wait. A wait longer
+/// than the maximum pause fails the request with a rate limit error instead.
+#[derive(Debug)]
+pub struct UpstreamLimits {
+    buckets: std::sync::RwLock<HashMap<String, std::sync::Mutex<TokenBucket>>>,
+    hosts: std::sync::Mutex<HashMap<String, HostBudget>>,
+    max_pause: std::sync::RwLock<Duration>,
+}
+
+impl UpstreamLimits {
+    /// Limits that wait at most `max_pause` for a host's budget, with no host limits
+    pub fn new(max_pause: Duration) -> Self {
+        Self {
+            buckets: std::sync::RwLock::new(HashMap::new()),
+            hosts: std::sync::Mutex::new(HashMap::new()),
+            max_pause: std::sync::RwLock::new(max_pause),
+        }
+    }
+
+    /// Limits with the configured maximum pause and host limits
+    pub fn from_config(config: &RateLimits) -> Self {
+        config.host_limits().into_iter().fold(Self::new(config.max_pause()), |limits, (host, limit)| {
+            limits.with_host_limit(&host, limit.requests_per_minute, limit.burst)
+        })
+    }
+
+    /// Allows `host` `requests_per_minute` on average and bursts of `burst`;
+    /// 0 requests per minute leaves it unlimited
+    pub fn with_host_limit(mut self, host: &str, requests_per_minute: u32, burst: u32) -> Self {
+        let buckets = self.buckets.get_mut().unwrap_or_else(|e| e.into_inner());
+        if requests_per_minute == 0 {
+            buckets.remove(host);
+        } else {
+            let bucket = TokenBucket::new(burst, f64::from(requests_per_minute) / 60.0);
+            buckets.insert(host.to_string(), std::sync::Mutex::new(bucket));
+        }
+        self
+    }
+
+    /// Switches to the configured maximum pause and host limits
+    ///
+    /// What hosts reported about their budgets is kept; host buckets start full.
+    pub fn reconfigure(&self, config: &RateLimits) {
+        let configured = Self::from_config(config);
+        *self.buckets.write().unwrap_or_else(|e| e.into_inner()) =
+            configured.buckets.into_inner().unwrap_or_else(|e| e.into_inner());
+        *self.max_pause.write().unwrap_or_else(|e| e.into_inner()) = config.max_pause();
+    }
+
+    /// Learns `host`'s budget from the status and headers of a response
+    pub fn observe(&self, host: &str, status: StatusCode, headers: &HeaderMap) {
+        self.observe_at(host, status, headers, Instant::now(), SystemTime::now());
+    }
+
+    fn observe_at(&self, host: &str, status: StatusCode, headers: &HeaderMap, now: Instant, wall: SystemTime) {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+        let remaining = header("x-ratelimit-remaining").and_then(|value| value.parse().ok());
+        let limit = header("x-ratelimit-limit").and_then(|value| value.parse().ok());
+        let reset = header("x-ratelimit-reset").and_then(|value| value.parse::<u64>().ok()).map(|reset| {
+            let since_epoch = wall.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+            now + Duration::from_secs(reset.saturating_sub(since_epoch))
+        });
+        let retry_after = headers.get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, wall))
+            .or_else(|| (status == StatusCode::TOO_MANY_REQUESTS).then_some(DEFAULT_RETRY_AFTER));
+        if remaining.is_none() && retry_after.is_none() {
+            return;
+        }
+
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let budget = hosts.entry(host.to_string()).or_default();
+        if remaining.is_some() {
+            budget.remaining = remaining;
+            budget.limit = limit.or(budget.limit);
+            budget.reset = reset;
+        }
+        if let Some(retry_after) = retry_after {
+            budget.paused_until = Some(now + retry_after);
+        }
+    }
+
+    /// Waits until `host`'s budget allows another request
+    pub async fn wait(&self, host: &str) -> Result<()> {
+        while let Err(delay) = self.take_token(host) {
+            sleep(delay).await;
+        }
+        let delay = self.delay_at(host, Instant::now())?;
+        if delay >= Duration::from_secs(5) {
+            warn!("Pausing requests to {} for {}s to stay within its rate limit", host, delay.as_secs());
+        }
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    /// Takes a token from `host`'s bucket, or returns how long until one is available
+    fn take_token(&self, host: &str) -> std::result::Result<(), Duration> {
+        match self.buckets.read().unwrap_or_else(|e| e.into_inner()).get(host) {
+            Some(bucket) => bucket.lock().unwrap_or_else(|e| e.into_inner()).try_take(),
+            None => Ok(()),
+        }
+    }
+
+    /// Reserves a request to `host` and returns how long it has to wait
+    fn delay_at(&self, host: &str, now: Instant) -> Result<Duration> {
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(budget) = hosts.get_mut(host) else {
+            return Ok(Duration::ZERO);
+        };
+        // A window that has reset no longer limits anything
+        if budget.reset.is_some_and(|reset| reset <= now) {
+            *budget = HostBudget { paused_until: budget.paused_until, ..HostBudget::default() };
+        }
+        let mut start = budget.paused_until.filter(|until| *until > now).unwrap_or(now);
+        if let (Some(remaining), Some(reset)) = (budget.remaining, budget.reset) {
+            if remaining == 0 {
+                start = start.max(reset);
+            } else {
+                let low = budget.limit.map_or(remaining < LOW_BUDGET_REQUESTS, |limit| {
+                    (remaining as f64) < limit as f64 * LOW_BUDGET_SHARE
+                });
+                if low {
+                    start = start.max(budget.next_slot.unwrap_or(now));
+                    let spacing = reset.saturating_duration_since(start) / u32::try_from(remaining).unwrap_or(u32::MAX);
+                    budget.next_slot = Some(start + spacing);
+                }
+                budget.remaining = Some(remaining - 1);
+            }
+        }
+        let delay = start.saturating_duration_since(now);
+        let max_pause = *self.max_pause.read().unwrap_or_else(|e| e.into_inner());
+        if delay > max_pause {
+            return Err(ProcessorError::RateLimitExceeded(format!(
+                "{} allows no more requests for {}s, longer than the {}s rate_limits.max_pause_secs allows",
+                host, delay.as_secs(), max_pause.as_secs()
+            )));
+        }
+        Ok(delay)
+    }
+}
+
+impl Default for UpstreamLimits {
+    fn default() -> Self {
+        Self::from_config(&RateLimits::default())
+    }
+}
+
+/// `Retry-After` as a number of seconds or an HTTP date
+fn parse_retry_after(value: &str, wall: SystemTime) -> Option<Duration> {
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let at = SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(at.timestamp()).ok()?);
+    Some(at.duration_since(wall).unwrap_or_default())
+}
+
+/// Sets the limits outbound requests are throttled by during this run
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_upstream_limits(limits: UpstreamLimits) {
+    let _ = UPSTREAM_LIMITS.set(limits);
+}
+
+/// The limits outbound requests are throttled by
+pub fn upstream_limits() -> &'static UpstreamLimits {
+    UPSTREAM_LIMITS.get_or_init(UpstreamLimits::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, 1.0);
+        bucket.updated = start;
+        assert!(bucket.try_take_at(start).is_ok());
+        assert!(bucket.try_take_at(start).is_ok());
+        let wait = bucket.try_take_at(start).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+        assert!(bucket.try_take_at(start + Duration::from_millis(1500)).is_ok());
+        assert!(!bucket.is_full(start + Duration::from_millis(1500)));
+        assert!(bucket.is_full(start + Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn test_clients_have_separate_buckets() {
+        assert!(ClientRateLimiter::new(0, 10).is_none());
+        let limiter = ClientRateLimiter::new(60, 1).unwrap();
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        assert!(limiter.check("b").is_ok());
+        assert_eq!(limiter.limited(), 1);
+        assert_eq!(limiter.clients(), 2);
+
+        // A new rate applies to clients already tracked
+        limiter.set_rate(60, 3);
+        assert!(limiter.check("a").is_err());
+        assert!(limiter.check("c").is_ok() && limiter.check("c").is_ok());
+    }
+
+    #[test]
+    fn test_upstream_budget_throttles_and_pauses() {
+        let limits = UpstreamLimits::new(Duration::from_secs(600));
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let epoch = wall.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        let headers = |pairs: &[(&'static str, String)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        assert_eq!(limits.delay_at("api.github.com", now).unwrap(), Duration::ZERO);
+
+        // Plenty left: no waiting
+        limits.observe_at("api.github.com", StatusCode::OK, &headers(&[
+            ("x-ratelimit-limit", "5000".into()),
+            ("x-ratelimit-remaining", "4000".into()),
+            ("x-ratelimit-reset", (epoch + 100).to_string()),
+        ]), now, wall);
+        assert_eq!(limits.delay_at("api.github.com", now).unwrap(), Duration::ZERO);
+
+        // Little left: the 4 remaining requests are spread over the 100s to the reset
+        limits.observe_at("api.github.com", StatusCode::OK, &headers(&[
+            ("x-ratelimit-remaining", "4".into()),
+            ("x-ratelimit-reset", (epoch + 100).to_string()),
+        ]), now, wall);
+        let delays: Vec<u64> = (0..4).map(|_| limits.delay_at("api.github.com", now).unwrap().as_secs()).collect();
+        assert_eq!(delays, [0, 25, 50, 75]);
+        // Spent: wait for the reset
+        assert_eq!(limits.delay_at("api.github.com", now).unwrap(), Duration::from_secs(100));
+        assert_eq!(limits.delay_at("api.github.com", now + Duration::from_secs(100)).unwrap(), Duration::ZERO);
+
+        // Retry-After, in seconds or as a date, and waits longer than the maximum
+        limits.observe_at("crates.io", StatusCode::TOO_MANY_REQUESTS, &headers(&[("retry-after", "30".into())]), now, wall);
+        assert_eq!(limits.delay_at("crates.io", now).unwrap(), Duration::from_secs(30));
+        let date = chrono::DateTime::<chrono::Utc>::from(wall + Duration::from_secs(1200)).to_rfc2822();
+        limits.observe_at("pypi.org", StatusCode::SERVICE_UNAVAILABLE, &headers(&[("retry-after", date)]), now, wall);
+        assert!(matches!(limits.delay_at("pypi.org", now), Err(ProcessorError::RateLimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_hosts_share_their_token_bucket() {
+        let limits = UpstreamLimits::new(DEFAULT_MAX_PAUSE)
+            .with_host_limit("crates.io", 60, 2)
+            .with_host_limit("pypi.org", 0, 5);
+        assert!(limits.take_token("crates.io").is_ok());
+        assert!(limits.take_token("crates.io").is_ok());
+        let wait = limits.take_token("crates.io").unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        // Unlisted and unlimited hosts never wait
+        assert!((0..100).all(|_| limits.take_token("pypi.org").is_ok() && limits.take_token("example.com").is_ok()));
+
+        let mut config = RateLimits::default();
+        config.hosts.insert("pypi.org".into(), crate::config::HostRateLimit { requests_per_minute: 60, burst: 1 });
+        limits.reconfigure(&config);
+        assert!(limits.take_token("pypi.org").is_ok());
+        assert!(limits.take_token("pypi.org").is_err());
+
+        let defaults = UpstreamLimits::default();
+        for (host, _, burst) in DEFAULT_HOST_LIMITS {
+            assert!((0..*burst).all(|_| defaults.take_token(host).is_ok()));
+            assert!(defaults.take_token(host).is_err(), "{} allows more than its burst", host);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Readiness of the API server to take work
+//!
+//! Orchestrators route traffic to a server once `/readyz` passes: the output
+//! and cache directories must be writable and at least one package registry
+//! must be reachable. A single registry being down makes only its own jobs
+//! fail, so it is reported without taking the server out of rotation.
+//! Registry checks go over the network, so their results are reused for
+//! [`REGISTRY_CHECK_TTL`].
+
+use crate::http;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long registry check results are reused
+pub const REGISTRY_CHECK_TTL: Duration = Duration::from_secs(30);
+
+/// Registries checked for reachability, by name
+pub const REGISTRIES: &[(&str, &str)] = &[
+    ("github", "https://api.github.com"),
+    ("pypi", "https://pypi.org/simple/"),
+    ("npm", "https://registry.npmjs.org/"),
+    ("crates", "https://index.crates.io/config.json"),
+];
+
+/// Outcome of one check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    /// What was checked
+    pub name: String,
+    /// Whether it passed
+    pub ok: bool,
+    /// Why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, outcome: std::result::Result<(), String>) -> Self {
+        Self { name: name.into(), ok: outcome.is_ok(), error: outcome.err() }
+    }
+}
+
+/// Outcome of every check, and whether the server is ready
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// Whether the server can take work
+    pub ready: bool,
+    /// Individual checks
+    pub checks: Vec<Check>,
+}
+
+/// Runs the readiness checks for one server
+pub struct Readiness {
+    output_dir: PathBuf,
+    cache_dir: PathBuf,
+    client: reqwest::Client,
+    registries: Mutex<Option<(Instant, Vec<Check>)>>,
+}
+
+impl Readiness {
+    /// Checks `output_dir` and `cache_dir`, and the registries in [`REGISTRIES`]
+    pub fn new(output_dir: PathBuf, cache_dir: PathBuf) -> Self {
+        Self {
+            output_dir,
+            cache_dir,
+            client: http::client_builder()
+                .timeout(Duration::from_secs(3))
+                .user_agent(concat!("llamapackageservice/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap_or_default(),
+            registries: Mutex::new(None),
+        }
+    }
+
+    /// Runs the checks, reusing recent registry results
+    pub async fn check(&self) -> Report {
+        let mut checks = vec![
+            Check::new("output_dir", check_writable(&self.output_dir).await),
+            Check::new("cache", check_writable(&self.cache_dir).await),
+        ];
+        checks.extend(self.registry_checks().await);
+        Report { ready: is_ready(&checks), checks }
+    }
+
+    async fn registry_checks(&self) -> Vec<Check> {
+        let mut cached = self.registries.lock().await;
+        if let Some((checked_at, checks)) = cached.as_ref() {
+            if checked_at.elapsed() < REGISTRY_CHECK_TTL {
+                return checks.clone();
+            }
+        }
+        let checks = futures::future::join_all(REGISTRIES.iter().map(|(name, url)| async move {
+            // Any response, even an error status, shows the registry is reachable
+            let outcome = self.client.head(*url).send().await.map(|_| ()).map_err(|e| e.to_string());
+            Check::new(format!("registry:{}", name), outcome)
+        })).await;
+        *cached = Some((Instant::now(), checks.clone()));
+        checks
+    }
+}
+
+/// Ready when every local check passes and some registry is reachable
+fn is_ready(checks: &[Check]) -> bool {
+    let (registries, local): (Vec<&Check>, Vec<&Check>) =
+        checks.iter().partition(|check| check.name.starts_with("registry:"));
+    local.iter().all(|check| check.ok) && (registries.is_empty() || registries.iter().any(|check| check.ok))
+}
+
+/// Creates `dir` if needed and writes and removes a probe file in it
+async fn check_writable(dir: &Path) -> std::result::Result<(), String> {
+    let probe = dir.join(".readyz-probe");
+    let outcome = async {
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(&probe, b"ok").await?;
+        tokio::fs::remove_file(&probe).await
+    }.await;
+    outcome.map_err(|e| format!("{} is not writable: {}", dir.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_writable_directories() {
+        let dir = tempdir().unwrap();
+        assert!(check_writable(&dir.path().join("output")).await.is_ok());
+        assert!(!dir.path().join("output/.readyz-probe").exists());
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(check_writable(&file.join("output")).await.is_err());
+    }
+
+    #[test]
+    fn test_one_reachable_registry_is_enough() {
+        let ok = |name: &str| Check::new(name, Ok(()));
+        let failed = |name: &str| Check::new(name, Err("unreachable".into()));
+
+        assert!(is_ready(&[ok("output_dir"), ok("cache"), failed("registry:npm"), ok("registry:pypi")]));
+        assert!(!is_ready(&[ok("output_dir"), ok("cache"), failed("registry:npm"), failed("registry:pypi")]));
+        assert!(!is_ready(&[failed("output_dir"), ok("cache"), ok("registry:pypi")]));
+    }
+}
@@ -0,0 +1,254 @@
+//! Retention policy for the output directory
+//!
+//! Limits how long and how much output is kept: artifacts older than a
+//! maximum age, versions of a package beyond a maximum count, and finally the
+//! oldest artifacts until the directory fits a total size budget. The policy
+//! is enforced by [`crate::output_organizer::organize_output`] and by the
+//! `clean` command, which can also list what would be deleted without
+//! deleting anything.
+
+use crate::compression::{self, CompressionIndex};
+use crate::output_organizer;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Category directories retention applies to
+const CATEGORY_DIRS: &[&str] = &[
+    "local_repositories", "github_repos", "github_orgs", "pypi_packages",
+    "pypi_profiles", "crates", "rust_crates", "npm_packages", "go_packages",
+];
+
+static RETENTION_POLICY: OnceCell<RetentionPolicy> = OnceCell::new();
+
+/// Limits on how much output is kept; unset limits are not enforced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Delete artifacts older than this many days
+    pub max_age_days: Option<u64>,
+    /// Delete the oldest artifacts until the category directories fit in this many bytes
+    pub max_total_bytes: Option<u64>,
+    /// Keep at most this many artifacts per package and format
+    pub max_versions: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// Whether any limit is set
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days.is_some() || self.max_total_bytes.is_some() || self.max_versions.is_some()
+    }
+}
+
+/// Why an artifact is to be deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Older than the maximum age
+    Expired,
+    /// Beyond the maximum number of versions of its package
+    ExcessVersion,
+    /// Removed to bring the directory under the size budget
+    OverBudget,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Reason::Expired => "older than max age",
+            Reason::ExcessVersion => "exceeds max versions",
+            Reason::OverBudget => "over total size budget",
+        })
+    }
+}
+
+/// An artifact selected for deletion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deletion {
+    /// Path of the artifact
+    pub path: PathBuf,
+    /// Size on disk in bytes
+    pub size: u64,
+    /// Why it is deleted
+    pub reason: Reason,
+}
+
+/// Sets the retention policy enforced during this run
+///
+/// Only the first call takes effect; later calls are ignored.
+pub fn set_retention_policy(policy: RetentionPolicy) {
+    let _ = RETENTION_POLICY.set(policy);
+}
+
+/// Returns the retention policy enforced during this run
+pub fn retention_policy() -> RetentionPolicy {
+    RETENTION_POLICY.get().copied().unwrap_or_default()
+}
+
+/// Parses a size such as `500MB`, `2G` or `1048576` into bytes
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size '{}'", s))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        "T" | "TB" => 1 << 40,
+        other => return Err(format!("unknown size unit '{}' (expected B, KB, MB, GB or TB)", other)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    group: (String, String, String),
+}
+
+/// Works out which artifacts under `output_dir` the policy would delete, oldest first within each rule
+pub fn plan(output_dir: &Path, policy: &RetentionPolicy) -> io::Result<Vec<Deletion>> {
+    let mut candidates = Vec::new();
+    for dir in CATEGORY_DIRS {
+        let dir_path = output_dir.join(dir);
+        if !dir_path.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir_path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let raw_name = entry.file_name().to_string_lossy().into_owned();
+            if !metadata.is_file() || raw_name.starts_with('.') {
+                continue;
+            }
+            let name = compression::logical_name(&raw_name);
+            let package = output_organizer::extract_package_name(name);
+            let package = package.split(' ').next().unwrap_or_default().trim_end_matches(".md").to_string();
+            let format = name.rsplit('.').next().unwrap_or_default().to_string();
+            candidates.push(Candidate {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+                group: (dir.to_string(), package, format),
+            });
+        }
+    }
+    // Newest first
+    candidates.sort_by(|a, b| b.modified.cmp(&a.modified));
+
+    let mut deletions = Vec::new();
+    let mut kept = Vec::new();
+
+    let cutoff = policy.max_age_days
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 60 * 60)));
+    let mut versions: HashMap<(String, String, String), usize> = HashMap::new();
+
+    for candidate in candidates {
+        let reason = if cutoff.is_some_and(|cutoff| candidate.modified < cutoff) {
+            Some(Reason::Expired)
+        } else {
+            let seen = versions.entry(candidate.group.clone()).or_insert(0);
+            *seen += 1;
+            policy.max_versions.filter(|&max| *seen > max).map(|_| Reason::ExcessVersion)
+        };
+        match reason {
+            Some(reason) => deletions.push(Deletion { path: candidate.path, size: candidate.size, reason }),
+            None => kept.push(candidate),
+        }
+    }
+
+    if let Some(budget) = policy.max_total_bytes {
+        let mut total: u64 = kept.iter().map(|c| c.size).sum();
+        while total > budget {
+            let Some(oldest) = kept.pop() else { break };
+            total -= oldest.size;
+            deletions.push(Deletion { path: oldest.path, size: oldest.size, reason: Reason::OverBudget });
+        }
+    }
+
+    Ok(deletions)
+}
+
+/// Deletes the planned artifacts, dropping them from their directory's compression index
+pub fn apply(deletions: &[Deletion]) -> io::Result<u64> {
+    let mut freed = 0;
+    for deletion in deletions {
+        match fs::remove_file(&deletion.path) {
+            Ok(()) => freed += deletion.size,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+        let dir = deletion.path.parent().unwrap_or_else(|| Path::new("."));
+        let mut index = CompressionIndex::load(dir);
+        let name = deletion.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if index.files.remove(&name).is_some() {
+            index.save(dir)?;
+        }
+    }
+    Ok(freed)
+}
+
+/// Plans and applies the policy, returning what was deleted
+pub fn enforce(output_dir: &Path, policy: &RetentionPolicy) -> io::Result<Vec<Deletion>> {
+    if !policy.is_enabled() {
+        return Ok(Vec::new());
+    }
+    let deletions = plan(output_dir, policy)?;
+    apply(&deletions)?;
+    Ok(deletions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    fn write_aged(path: &Path, size: usize, age_days: u64) {
+        fs::write(path, vec![b'x'; size]).unwrap();
+        let mtime = SystemTime::now() - Duration::from_secs(age_days * 24 * 60 * 60);
+        File::options().write(true).open(path).unwrap().set_modified(mtime).unwrap();
+    }
+
+    #[test]
+    fn test_plan_applies_age_versions_and_budget() {
+        let dir = tempdir().unwrap();
+        let pypi = dir.path().join("pypi_packages");
+        fs::create_dir_all(&pypi).unwrap();
+        write_aged(&pypi.join("20250101_000000_requests_pypi_processed.txt"), 100, 40);
+        write_aged(&pypi.join("20250201_000000_requests_pypi_processed.txt"), 100, 3);
+        write_aged(&pypi.join("20250202_000000_requests_pypi_processed.txt"), 100, 2);
+        write_aged(&pypi.join("20250203_000000_requests_pypi_processed.txt"), 100, 1);
+        write_aged(&pypi.join("20250203_000000_flask_pypi_processed.txt"), 100, 1);
+
+        let policy = RetentionPolicy { max_age_days: Some(30), max_versions: Some(2), max_total_bytes: Some(250) };
+        let deletions = plan(dir.path(), &policy).unwrap();
+        let summary: Vec<(String, Reason)> = deletions.iter()
+            .map(|d| (d.path.file_name().unwrap().to_string_lossy().into_owned(), d.reason))
+            .collect();
+        assert_eq!(summary, vec![
+            ("20250201_000000_requests_pypi_processed.txt".to_string(), Reason::ExcessVersion),
+            ("20250101_000000_requests_pypi_processed.txt".to_string(), Reason::Expired),
+            ("20250202_000000_requests_pypi_processed.txt".to_string(), Reason::OverBudget),
+        ]);
+
+        // Planning alone deletes nothing
+        assert_eq!(fs::read_dir(&pypi).unwrap().count(), 5);
+        assert_eq!(apply(&deletions).unwrap(), 300);
+        assert_eq!(fs::read_dir(&pypi).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576"), Ok(1 << 20));
+        assert_eq!(parse_size("500MB"), Ok(500 << 20));
+        assert_eq!(parse_size("1.5 GB"), Ok(3 << 29));
+        assert!(parse_size("10 parsecs").is_err());
+    }
+}
@@ -0,0 +1,448 @@
+//! Diff reports between successive runs of the same target
+//!
+//! Each time a repository is processed a small snapshot is stored under
+//! `<output_dir>/_snapshots`: file hashes, declared dependencies, a few size
+//! metrics and the README split into sections. When a previous snapshot
+//! exists, a `*_diff.md` artifact summarizes what changed since then. The
+//! metrics of every run also go into the target's history in
+//! [`crate::analytics::history`], and the diff ends with how they trend.
+
+use crate::analytics::complexity::{self, ComplexityReport, HOTSPOTS};
+use crate::analytics::lines::{self, LineCounts};
+use crate::analytics::history::{self, MetricsHistory, METRICS_DB_FILE};
+use crate::analytics::RepositoryMetrics;
+use crate::compression;
+use crate::encryption;
+use crate::error::Result;
+use crate::output_organizer::package_slug;
+use crate::perf;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Runs the trends in a diff report look back over
+const TREND_RUNS: usize = 30;
+
+/// Directories that never contribute to a snapshot
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules", "target", "__pycache__", ".venv", "venv", "dist", "build"];
+
+/// State of a target at the end of one run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunSnapshot {
+    /// Target identifier (URL or path) the snapshot belongs to
+    pub target: String,
+    /// When the run finished
+    pub generated_at: DateTime<Utc>,
+    /// Relative file path to content hash
+    pub files: BTreeMap<String, String>,
+    /// Dependency name to declared version requirement
+    pub dependencies: BTreeMap<String, String>,
+    /// Named size metrics
+    pub metrics: BTreeMap<String, u64>,
+    /// README heading to section text
+    pub readme_sections: BTreeMap<String, String>,
+    /// Complexity of the functions in the source files, when there were any
+    #[serde(default)]
+    pub complexity: Option<ComplexityReport>,
+}
+
+impl RunSnapshot {
+    /// Captures a snapshot of the repository rooted at `root`
+    pub fn capture(target: &str, root: &Path) -> Self {
+        let mut snapshot = RunSnapshot {
+            target: target.to_string(),
+            generated_at: Utc::now(),
+            ..Default::default()
+        };
+        let mut lines = 0u64;
+        let mut line_counts = LineCounts::default();
+        let mut bytes = 0u64;
+        let mut functions = Vec::new();
+
+        for entry in WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|e| !SKIPPED_DIRS.contains(&e.file_name().to_string_lossy().as_ref()))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let Ok(data) = std::fs::read(entry.path()) else { continue };
+            let rel = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/");
+            lines += perf::count_lines(&data) as u64;
+            bytes += data.len() as u64;
+            if let Ok(text) = std::str::from_utf8(&data) {
+                line_counts.add(lines::count(&rel, text));
+                functions.extend(complexity::analyze(&rel, text));
+            }
+            snapshot.files.insert(rel, perf::hash_bytes(&data));
+        }
+        if !functions.is_empty() {
+            snapshot.complexity = Some(ComplexityReport::from_functions(functions, HOTSPOTS));
+        }
+
+        snapshot.metrics.insert("files".into(), snapshot.files.len() as u64);
+        snapshot.metrics.insert("lines".into(), lines);
+        snapshot.metrics.insert("code".into(), line_counts.code as u64);
+        snapshot.metrics.insert("comments".into(), line_counts.comments as u64);
+        snapshot.metrics.insert("blanks".into(), line_counts.blanks as u64);
+        snapshot.metrics.insert("bytes".into(), bytes);
+        snapshot.dependencies = read_dependencies(root);
+        snapshot.metrics.insert("dependencies".into(), snapshot.dependencies.len() as u64);
+
+        if let Some(readme) = ["README.md", "README.rst", "README.txt", "README", "readme.md"]
+            .iter()
+            .find_map(|name| std::fs::read_to_string(root.join(name)).ok())
+        {
+            snapshot.readme_sections = readme_sections(&readme);
+        }
+
+        snapshot
+    }
+
+    /// Path of the stored snapshot for a target
+    pub fn path_for(output_dir: &Path, target: &str) -> PathBuf {
+        output_dir.join("_snapshots").join(format!("{}.json", package_slug(target)))
+    }
+
+    /// Loads the previous snapshot for a target, if any
+    pub fn load(output_dir: &Path, target: &str) -> Option<Self> {
+        let path = Self::path_for(output_dir, target);
+        let encrypted = encryption::encrypted_path(&path);
+        let bytes = compression::read_artifact(if encrypted.exists() { &encrypted } else { &path }).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Stores this snapshot as the latest for its target
+    ///
+    /// It quotes the README, so it is encrypted like the artifacts when output encryption is on.
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let plain = Self::path_for(output_dir, &self.target);
+        if let Some(parent) = plain.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec_pretty(self)?;
+        let encrypted = encryption::encrypted_path(&plain);
+        let (path, bytes, stale) = match encryption::output_encryption() {
+            Some(key) => (&encrypted, encryption::encrypt(&json, key)?, &plain),
+            None => (&plain, json, &encrypted),
+        };
+        std::fs::write(path, bytes)?;
+        if stale.exists() {
+            std::fs::remove_file(stale)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the markdown diff between two snapshots of the same target
+pub fn render_diff(previous: &RunSnapshot, current: &RunSnapshot) -> String {
+    let mut out = format!("# Changes in {}\n\n", current.target);
+    out.push_str(&format!(
+        "**Previous run:** {}\n**This run:** {}\n\n",
+        previous.generated_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        current.generated_at.format("%Y-%m-%d %H:%M:%S UTC"),
+    ));
+
+    push_file_changes(&mut out, previous, current);
+    push_dependency_changes(&mut out, previous, current);
+
+    // Metrics
+    out.push_str("## Metrics\n\n| Metric | Previous | Current | Change |\n|--------|----------|---------|--------|\n");
+    for (name, &value) in &current.metrics {
+        let old = previous.metrics.get(name).copied().unwrap_or(0);
+        let delta = value as i64 - old as i64;
+        out.push_str(&format!("| {} | {} | {} | {:+} |\n", name, old, value, delta));
+    }
+    out.push('\n');
+
+    // README
+    out.push_str("## README\n\n");
+    let mut readme_changes = Vec::new();
+    for (heading, text) in &current.readme_sections {
+        match previous.readme_sections.get(heading) {
+            None => readme_changes.push(format!("- New section: {}", heading)),
+            Some(old) if old != text => readme_changes.push(format!("- Changed section: {}", heading)),
+            _ => {}
+        }
+    }
+    for heading in previous.readme_sections.keys() {
+        if !current.readme_sections.contains_key(heading) {
+            readme_changes.push(format!("- Removed section: {}", heading));
+        }
+    }
+    if readme_changes.is_empty() {
+        out.push_str("No README changes.\n");
+    } else {
+        out.push_str(&readme_changes.join("\n"));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Appends the `## Files` section listing files added, removed and modified between two snapshots
+pub(crate) fn push_file_changes(out: &mut String, previous: &RunSnapshot, current: &RunSnapshot) {
+    let added: Vec<&String> = current.files.keys().filter(|f| !previous.files.contains_key(*f)).collect();
+    let removed: Vec<&String> = previous.files.keys().filter(|f| !current.files.contains_key(*f)).collect();
+    let modified: Vec<&String> = current.files.iter()
+        .filter(|(f, hash)| previous.files.get(*f).is_some_and(|old| old != *hash))
+        .map(|(f, _)| f)
+        .collect();
+
+    out.push_str("## Files\n\n");
+    if added.is_empty() && removed.is_empty() && modified.is_empty() {
+        out.push_str("No file changes.\n\n");
+    } else {
+        out.push_str(&format!("{} added, {} removed, {} modified\n\n", added.len(), removed.len(), modified.len()));
+        push_list(out, "Added", &added);
+        push_list(out, "Removed", &removed);
+        push_list(out, "Modified", &modified);
+    }
+}
+
+/// Appends the `## Dependencies` section listing dependencies added, removed and changed between two snapshots
+pub(crate) fn push_dependency_changes(out: &mut String, previous: &RunSnapshot, current: &RunSnapshot) {
+    out.push_str("## Dependencies\n\n");
+    let mut dep_changes = Vec::new();
+    for (name, version) in &current.dependencies {
+        match previous.dependencies.get(name) {
+            None => dep_changes.push(format!("- Added `{}` {}", name, version)),
+            Some(old) if old != version => dep_changes.push(format!("- Changed `{}` {} → {}", name, old, version)),
+            _ => {}
+        }
+    }
+    for (name, version) in &previous.dependencies {
+        if !current.dependencies.contains_key(name) {
+            dep_changes.push(format!("- Removed `{}` {}", name, version));
+        }
+    }
+    if dep_changes.is_empty() {
+        out.push_str("No dependency changes.\n\n");
+    } else {
+        out.push_str(&dep_changes.join("\n"));
+        out.push_str("\n\n");
+    }
+}
+
+/// Captures a snapshot, writes a diff against the previous run if there is one, and stores the snapshot
+///
+/// Returns the path of the `*_diff.md` artifact when one was written.
+pub fn record_run(output_dir: &Path, target: &str, root: &Path, report_dir: &Path) -> Result<Option<PathBuf>> {
+    let current = RunSnapshot::capture(target, root);
+    let trends = record_metrics(output_dir, &current);
+    let diff_path = match RunSnapshot::load(output_dir, target) {
+        Some(previous) => {
+            std::fs::create_dir_all(report_dir)?;
+            let path = report_dir.join(format!(
+                "{}_{}_diff.md",
+                current.generated_at.format("%Y%m%d_%H%M%S"),
+                package_slug(target),
+            ));
+            let mut diff = render_diff(&previous, &current);
+            if let Some(trends) = trends {
+                diff.push('\n');
+                diff.push_str(&trends);
+            }
+            std::fs::write(&path, diff)?;
+            Some(path)
+        }
+        None => None,
+    };
+    current.save(output_dir)?;
+    Ok(diff_path)
+}
+
+/// Adds the metrics of the run to the target's history, returning how they trend
+///
+/// History is a convenience like the diff itself; failures are logged.
+fn record_metrics(output_dir: &Path, snapshot: &RunSnapshot) -> Option<String> {
+    let recorded = MetricsHistory::open(&output_dir.join(METRICS_DB_FILE)).and_then(|history| {
+        history.record(&RepositoryMetrics::from_snapshot(snapshot))?;
+        history.runs(&snapshot.target, TREND_RUNS)
+    });
+    match recorded {
+        Ok(runs) => history::trend_section(&runs),
+        Err(e) => {
+            log::warn!("Could not record the metrics of {}: {}", snapshot.target, e);
+            None
+        }
+    }
+}
+
+/// Async wrapper around [`record_run`] that logs instead of failing the run
+///
+/// Diff reports are a convenience; a failure to write one should never abort processing.
+pub async fn record_run_logged(output_dir: &Path, target: &str, root: &Path) -> Option<PathBuf> {
+    let (output_dir, target, root) = (output_dir.to_path_buf(), target.to_string(), root.to_path_buf());
+    match tokio::task::spawn_blocking(move || record_run(&output_dir, &target, &root, &output_dir)).await {
+        Ok(Ok(path)) => path,
+        Ok(Err(e)) => {
+            log::warn!("Could not write run diff: {}", e);
+            None
+        }
+        Err(e) => {
+            log::warn!("Run diff task failed: {}", e);
+            None
+        }
+    }
+}
+
+fn push_list(out: &mut String, label: &str, items: &[&String]) {
+    const SHOWN: usize = 50;
+    if items.is_empty() {
+        return;
+    }
+    out.push_str(&format!("### {}\n\n", label));
+    for item in items.iter().take(SHOWN) {
+        out.push_str(&format!("- `{}`\n", item));
+    }
+    if items.len() > SHOWN {
+        out.push_str(&format!("- ... and {} more\n", items.len() - SHOWN));
+    }
+    out.push('\n');
+}
+
+/// Splits a markdown README into sections keyed by heading
+fn readme_sections(readme: &str) -> BTreeMap<String, String> {
+    let mut sections = BTreeMap::new();
+    let mut heading = "(introduction)".to_string();
+    let mut body = String::new();
+    for line in readme.lines() {
+        if line.starts_with('#') {
+            if !body.trim().is_empty() || heading != "(introduction)" {
+                sections.insert(heading.clone(), body.trim().to_string());
+            }
+            heading = line.trim_start_matches('#').trim().to_string();
+            body.clear();
+        } else {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    if !body.trim().is_empty() || heading != "(introduction)" {
+        sections.insert(heading, body.trim().to_string());
+    }
+    sections
+}
+
+/// Manifests whose declared dependencies are read
+pub(crate) const DEPENDENCY_MANIFESTS: [&str; 4] = ["Cargo.toml", "package.json", "requirements.txt", "go.mod"];
+
+/// Reads declared dependencies from the manifests at the repository root
+fn read_dependencies(root: &Path) -> BTreeMap<String, String> {
+    let mut deps = BTreeMap::new();
+    for name in DEPENDENCY_MANIFESTS {
+        if let Ok(content) = std::fs::read_to_string(root.join(name)) {
+            manifest_dependencies(name, &content, &mut deps);
+        }
+    }
+    deps
+}
+
+/// Adds the dependencies declared in `content`, a manifest called `name`, to `deps`
+pub(crate) fn manifest_dependencies(name: &str, content: &str, deps: &mut BTreeMap<String, String>) {
+    match name {
+        "Cargo.toml" => {
+            if let Ok(manifest) = content.parse::<toml::Value>() {
+                for table in ["dependencies", "dev-dependencies", "build-dependencies"] {
+                    if let Some(entries) = manifest.get(table).and_then(|t| t.as_table()) {
+                        for (name, spec) in entries {
+                            let version = spec.as_str()
+                                .or_else(|| spec.get("version").and_then(|v| v.as_str()))
+                                .unwrap_or("*");
+                            deps.insert(name.clone(), version.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        "package.json" => {
+            if let Ok(manifest) = serde_json::from_str::<serde_json::Value>(content) {
+                for table in ["dependencies", "devDependencies", "peerDependencies"] {
+                    if let Some(entries) = manifest[table].as_object() {
+                        for (name, version) in entries {
+                            deps.insert(name.clone(), version.as_str().unwrap_or("*").to_string());
+                        }
+                    }
+                }
+            }
+        }
+        "requirements.txt" => {
+            for line in content.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('-')) {
+                let split = line.find(|c: char| "=<>!~;[ ".contains(c)).unwrap_or(line.len());
+                let (name, spec) = line.split_at(split);
+                deps.insert(name.to_string(), if spec.trim().is_empty() { "*".into() } else { spec.trim().to_string() });
+            }
+        }
+        "go.mod" => {
+            let mut in_block = false;
+            for line in content.lines().map(str::trim) {
+                let spec = if in_block {
+                    if line == ")" {
+                        in_block = false;
+                        continue;
+                    }
+                    line
+                } else if line == "require (" {
+                    in_block = true;
+                    continue;
+                } else if let Some(single) = line.strip_prefix("require ") {
+                    single
+                } else {
+                    continue;
+                };
+                let mut parts = spec.split_whitespace();
+                if let (Some(name), Some(version)) = (parts.next(), parts.next()) {
+                    deps.insert(name.to_string(), version.to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_second_run_writes_diff() {
+        let output = tempdir().unwrap();
+        let repo = tempdir().unwrap();
+        std::fs::write(repo.path().join("README.md"), "# Demo\n\nIntro\n\n## Usage\n\nold\n").unwrap();
+        std::fs::write(repo.path().join("Cargo.toml"), "[package]\nname = \"demo\"\n\n[dependencies]\nserde = \"1.0\"\n").unwrap();
+        std::fs::write(repo.path().join("a.rs"), "fn a() {}\n").unwrap();
+
+        let first = record_run(output.path(), "demo", repo.path(), output.path()).unwrap();
+        assert!(first.is_none());
+
+        std::fs::write(repo.path().join("README.md"), "# Demo\n\nIntro\n\n## Usage\n\nnew\n\n## FAQ\n\nq\n").unwrap();
+        std::fs::write(repo.path().join("Cargo.toml"), "[package]\nname = \"demo\"\n\n[dependencies]\nserde = \"1.1\"\nregex = \"1\"\n").unwrap();
+        std::fs::remove_file(repo.path().join("a.rs")).unwrap();
+        std::fs::write(repo.path().join("b.rs"), "fn b() {}\n").unwrap();
+
+        let diff_path = record_run(output.path(), "demo", repo.path(), output.path()).unwrap().unwrap();
+        assert!(diff_path.to_string_lossy().ends_with("_demo_diff.md"));
+        let diff = std::fs::read_to_string(diff_path).unwrap();
+        assert!(diff.contains("- `b.rs`"));
+        assert!(diff.contains("### Removed\n\n- `a.rs`"));
+        assert!(diff.contains("Changed `serde` 1.0 → 1.1"));
+        assert!(diff.contains("Added `regex` 1"));
+        assert!(diff.contains("Changed section: Usage"));
+        assert!(diff.contains("New section: FAQ"));
+        assert!(diff.contains("## Trends\n\n- **Lines:**"));
+    }
+
+    #[test]
+    fn test_requirements_parsing() {
+        let repo = tempdir().unwrap();
+        std::fs::write(repo.path().join("requirements.txt"), "# pinned\nrequests==2.31.0\nnumpy>=1.24\nflask\n-r dev.txt\n").unwrap();
+        let deps = read_dependencies(repo.path());
+        assert_eq!(deps["requests"], "==2.31.0");
+        assert_eq!(deps["numpy"], ">=1.24");
+        assert_eq!(deps["flask"], "*");
+        assert_eq!(deps.len(), 3);
+    }
+}
@@ -0,0 +1,373 @@
+//! Jobs the server queues on a schedule
+//!
+//! Entries under `server.schedules` queue a job for their URL whenever their
+//! cron expression matches in their time zone, so recurring work such as a
+//! nightly re-analysis of an organization needs no one to trigger it:
+//!
+//! ```toml
+//! [[server.schedules]]
+//! name = "nightly-org"
+//! cron = "30 2 * * *"       # minute, hour, day of month, month, day of week
+//! timezone = "Europe/Berlin"
+//! url = "https://github.com/llamasearchai"
+//! analyses = ["documentation"]
+//! jitter_secs = 600
+//! blackouts = [{ from = "2026-12-24", until = "2026-12-26" }]
+//! ```
+//!
+//! Runs that fall in a blackout window are skipped, as are runs whose URL is
+//! still queued from the previous one. Each run is delayed by a random amount
+//! of up to `jitter_secs`, so schedules sharing a time do not all reach the
+//! registries at once. Times that do not exist on the day clocks go forward
+//! are skipped; times that happen twice when they go back run once.
+
+use crate::api::{AnalysisRequest, JobManager, ProcessRequest};
+use crate::error::{ProcessorError, Result};
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// A recurring job in the `server` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// Name the schedule is logged under
+    pub name: String,
+    /// When to run: five cron fields, or `@hourly`, `@daily`, `@weekly`, `@monthly` or `@yearly`
+    pub cron: String,
+    /// IANA time zone the expression and blackouts are read in, such as `Europe/Berlin`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// URL or path to process
+    pub url: String,
+    /// Processor to use instead of detecting one from the URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processor: Option<String>,
+    /// Workspace the jobs are queued in, and whose quota they count against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// AI analyses to queue as well, such as `documentation` or `security`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub analyses: Vec<String>,
+    /// Longest random delay added to each run (seconds)
+    #[serde(default)]
+    pub jitter_secs: u64,
+    /// Periods in which runs are skipped, such as holidays or freezes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blackouts: Vec<Blackout>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+/// A period in which a schedule does not run
+///
+/// Both ends are `YYYY-MM-DD` or `YYYY-MM-DD HH:MM` in the schedule's time
+/// zone. A date as `until` includes that whole day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blackout {
+    /// Start of the period
+    pub from: String,
+    /// End of the period
+    pub until: String,
+}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether days of the month and of the week are both restricted, in which case either matching is enough
+    either_day: bool,
+}
+
+const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    /// Parses `expression`: minute, hour, day of month, month and day of week, or a macro such as `@daily`
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(ProcessorError::Config(format!(
+                "'{}' has {} fields; a cron expression has five: minute, hour, day of month, month, day of week",
+                expression, fields.len()
+            )));
+        };
+        // Sunday is both 0 and 7
+        let weekdays = parse_field(weekday, "day of week", 0, 7, WEEKDAYS, 0)?;
+        Ok(Self {
+            minutes: parse_field(minute, "minute", 0, 59, &[], 0)?,
+            hours: parse_field(hour, "hour", 0, 23, &[], 0)?,
+            days: parse_field(day, "day of month", 1, 31, &[], 1)?,
+            months: parse_field(month, "month", 1, 12, MONTHS, 1)?,
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+
+    /// Whether the expression runs on `date`
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        if self.months & 1 << date.month() == 0 {
+            return false;
+        }
+        let day = self.days & 1 << date.day() != 0;
+        let weekday = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        if self.either_day { day || weekday } else { day && weekday }
+    }
+
+    /// The first time after `after` the expression matches, in `after`'s time zone
+    pub fn next_after<Z: TimeZone>(&self, after: &DateTime<Z>) -> Option<DateTime<Z>> {
+        let timezone = after.timezone();
+        let local = after.naive_local();
+        let start = local.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // February 29th can be eight years away
+        for date in start.date().iter_days().take(366 * 8) {
+            if !self.runs_on(date) {
+                continue;
+            }
+            for hour in (0..24).filter(|hour| self.hours & 1 << hour != 0) {
+                for minute in (0..60).filter(|minute| self.minutes & 1 << minute != 0) {
+                    let time = date.and_hms_opt(hour, minute, 0)?;
+                    if time < start {
+                        continue;
+                    }
+                    match timezone.from_local_datetime(&time) {
+                        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => return Some(time),
+                        LocalResult::None => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Values a cron field allows, as bits offset by 0
+fn parse_field(field: &str, name: &str, min: u32, max: u32, names: &[&str], first_name: u32) -> Result<u64> {
+    let invalid = |reason: String| ProcessorError::Config(format!("{} field '{}': {}", name, field, reason));
+    let value = |text: &str| -> Result<u32> {
+        let lower = text.to_ascii_lowercase();
+        let value = match names.iter().position(|candidate| *candidate == lower) {
+            Some(index) => index as u32 + first_name,
+            None => text.parse().map_err(|_| invalid(format!("'{}' is not a number", text)))?,
+        };
+        if value < min || value > max {
+            return Err(invalid(format!("{} is outside {}-{}", value, min, max)));
+        }
+        Ok(value)
+    };
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)
+                .ok_or_else(|| invalid(format!("step '{}' is not a positive number", step)))?),
+            None => (item, 1),
+        };
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (value(low)?, value(high)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if low > high {
+            return Err(invalid(format!("range {}-{} runs backwards", low, high)));
+        }
+        for value in (low..=high).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// A schedule ready to compute its runs
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    config: ScheduleConfig,
+    cron: Cron,
+    timezone: Tz,
+    blackouts: Vec<(NaiveDateTime, NaiveDateTime)>,
+}
+
+impl Schedule {
+    /// Parses the expression, time zone and blackouts of `config`
+    pub fn new(config: &ScheduleConfig) -> Result<Self> {
+        let cron = Cron::parse(&config.cron)?;
+        let timezone = config.timezone.parse::<Tz>().map_err(|_| {
+            ProcessorError::Config(format!("unknown time zone '{}'; use a name such as UTC or Europe/Berlin", config.timezone))
+        })?;
+        let blackouts = config.blackouts.iter()
+            .map(|blackout| {
+                let from = parse_time(&blackout.from, false)?;
+                let until = parse_time(&blackout.until, true)?;
+                if until <= from {
+                    return Err(ProcessorError::Config(format!("blackout ends ({}) before it starts ({})", blackout.until, blackout.from)));
+                }
+                Ok((from, until))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { config: config.clone(), cron, timezone, blackouts })
+    }
+
+    /// Name of the schedule
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// The first run after `after` outside the blackouts, if there is one
+    pub fn next_run(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut next = after.with_timezone(&self.timezone);
+        // Every minute of a long blackout is a candidate for `* * * * *`
+        for _ in 0..100_000 {
+            next = self.cron.next_after(&next)?;
+            let local = next.naive_local();
+            if !self.blackouts.iter().any(|(from, until)| (*from..*until).contains(&local)) {
+                return Some(next.with_timezone(&Utc));
+            }
+        }
+        None
+    }
+
+    /// Queues the schedule's job and analyses, unless its URL is still queued
+    async fn queue(&self, jobs: &JobManager) {
+        let (url, workspace) = (&self.config.url, self.config.workspace.as_deref());
+        if let Ok(Some(job)) = jobs.queued_job(url, workspace).await {
+            warn!("Schedule {} skipped {}: job {} is still queued", self.name(), url, job.job_id);
+            return;
+        }
+        info!("Queueing {} on schedule {}", url, self.name());
+        let request = ProcessRequest { url: url.clone(), output_dir: None, config: None, processor: self.config.processor.clone() };
+        if let Err(e) = jobs.submit_job(request, workspace).await {
+            warn!("Schedule {} failed to queue {}: {}", self.name(), url, e);
+            return;
+        }
+        for analysis in &self.config.analyses {
+            let request = AnalysisRequest { repository: url.clone(), analysis_type: analysis.clone(), context: None };
+            if let Err(e) = jobs.submit_analysis(request, workspace).await {
+                warn!("Schedule {} failed to queue the {} analysis of {}: {}", self.name(), analysis, url, e);
+            }
+        }
+    }
+}
+
+/// A time in a blackout; a bare date is the start of the day, or as `end` the end of it
+fn parse_time(text: &str, end: bool) -> Result<NaiveDateTime> {
+    let text = text.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let date = if end { date.succ_opt().unwrap_or(date) } else { date };
+        return Ok(date.and_time(chrono::NaiveTime::MIN));
+    }
+    ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .ok_or_else(|| ProcessorError::Config(format!("'{}' is neither YYYY-MM-DD nor YYYY-MM-DD HH:MM", text)))
+}
+
+/// Queues the jobs of every configured schedule as it comes due
+pub struct Scheduler {
+    schedules: Vec<Schedule>,
+}
+
+impl Scheduler {
+    /// A scheduler for `schedules`, failing on the first that does not parse
+    pub fn from_config(schedules: &[ScheduleConfig]) -> Result<Self> {
+        let schedules = schedules.iter()
+            .map(|config| Schedule::new(config).map_err(|e| match e {
+                ProcessorError::Config(message) => ProcessorError::Config(format!("schedule {}: {}", config.name, message)),
+                other => other,
+            }))
+            .collect::<Result<_>>()?;
+        Ok(Self { schedules })
+    }
+
+    /// Runs each schedule in the background, queueing its jobs with `jobs`
+    pub fn spawn(self, jobs: Arc<JobManager>) {
+        for schedule in self.schedules {
+            let jobs = Arc::clone(&jobs);
+            tokio::spawn(async move {
+                let mut after = Utc::now();
+                loop {
+                    let Some(next) = schedule.next_run(after) else {
+                        warn!("Schedule {} has no more runs", schedule.name());
+                        return;
+                    };
+                    info!("Schedule {} runs next at {}", schedule.name(), next.with_timezone(&schedule.timezone));
+                    let jitter = match schedule.config.jitter_secs {
+                        0 => 0,
+                        secs => rand::thread_rng().gen_range(0..=secs),
+                    };
+                    let delay = (next - Utc::now()).to_std().unwrap_or_default() + std::time::Duration::from_secs(jitter);
+                    tokio::time::sleep(delay).await;
+                    schedule.queue(&jobs).await;
+                    // A server that slept through runs does not catch up on each of them
+                    after = next.max(Utc::now());
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_runs_follow_the_expression_time_zone_and_blackouts() {
+        let cron = Cron::parse("*/15 9-17 * * mon-fri").unwrap();
+        // Friday evening to Monday morning
+        assert_eq!(cron.next_after(&utc("2026-10-16T17:50:00Z")), Some(utc("2026-10-19T09:00:00Z")));
+        assert_eq!(cron.next_after(&utc("2026-10-19T09:00:00Z")), Some(utc("2026-10-19T09:15:00Z")));
+        // Either day field matches when both are restricted: the 13th, or any Friday
+        let either = Cron::parse("0 0 13 * 5").unwrap();
+        assert_eq!(either.next_after(&utc("2026-10-13T00:00:00Z")), Some(utc("2026-10-16T00:00:00Z")));
+        assert_eq!(Cron::parse("0 0 29 2 *").unwrap().next_after(&utc("2026-03-01T00:00:00Z")), Some(utc("2028-02-29T00:00:00Z")));
+        assert_eq!(Cron::parse("@weekly").unwrap(), Cron::parse("0 0 * * 7").unwrap());
+        assert!(Cron::parse("0 0 * *").unwrap_err().to_string().contains("has 4 fields"));
+        assert!(Cron::parse("0 24 * * *").unwrap_err().to_string().contains("hour field '24': 24 is outside 0-23"));
+
+        let config = ScheduleConfig {
+            name: "nightly".into(),
+            cron: "30 2 * * *".into(),
+            timezone: "Europe/Berlin".into(),
+            url: "https://github.com/llamasearchai".into(),
+            processor: None,
+            workspace: None,
+            analyses: Vec::new(),
+            jitter_secs: 0,
+            blackouts: vec![Blackout { from: "2026-12-24".into(), until: "2026-12-26".into() }],
+        };
+        let schedule = Schedule::new(&config).unwrap();
+        // 02:30 in Berlin is 00:30 UTC in summer and 01:30 UTC in winter
+        assert_eq!(schedule.next_run(utc("2026-10-16T12:00:00Z")), Some(utc("2026-10-17T00:30:00Z")));
+        assert_eq!(schedule.next_run(utc("2026-12-01T12:00:00Z")), Some(utc("2026-12-02T01:30:00Z")));
+        // 02:30 does not exist when clocks go forward
+        assert_eq!(schedule.next_run(utc("2027-03-27T12:00:00Z")), Some(utc("2027-03-29T00:30:00Z")));
+        // Christmas is skipped, the 26th included
+        assert_eq!(schedule.next_run(utc("2026-12-23T12:00:00Z")), Some(utc("2026-12-27T01:30:00Z")));
+
+        let unknown = ScheduleConfig { timezone: "Mars/Olympus".into(), ..config.clone() };
+        assert!(Schedule::new(&unknown).unwrap_err().to_string().contains("unknown time zone 'Mars/Olympus'"));
+        let backwards = ScheduleConfig { blackouts: vec![Blackout { from: "2026-12-26".into(), until: "2026-12-24 08:00".into() }], ..config };
+        assert!(Schedule::new(&backwards).is_err());
+    }
+}
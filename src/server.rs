@@ -0,0 +1,1895 @@
+//! REST API server
+//!
+//! The Axum application behind `llamapackageservice serve` and the `server`
+//! binary: processing jobs (submit, status, artifacts and their download, cancel), AI analysis and conversations, release
+//! monitoring with Atom and JSON feeds, and the API documentation.
+//!
+//! `/healthz`, `/readyz` and `/metrics` are for orchestrators and monitoring:
+//! liveness, readiness (see [`crate::readiness`]) and Prometheus metrics.
+//! `/webhooks/github` queues jobs for repositories GitHub reports changed
+//! (see [`crate::webhook`]). `/api/worker` is where remote workers claim and
+//! report jobs when `server.remote_workers` is set (see [`crate::worker`]).
+//! Edits to the config file's rate limits, job concurrency and tokens apply
+//! while serving (see [`crate::config::reload`]).
+//!
+//! Routes other than health, metrics, webhooks, documentation and feeds require a credential
+//! with the matching scope once authentication is configured; see
+//! [`crate::auth`]. Credentials tied to a workspace only see that workspace's
+//! jobs, conversations and watched packages; see [`crate::workspace`].
+
+use crate::api::{self, JobListParams, JobManager, JobStatus, JobStatusType, ProcessRequest, AnalysisRequest, ConversationRequest, MessageRequest, WorkerOutcome, WorkerProgress};
+use crate::agents::conversation::{ConversationStore, CONVERSATIONS_DB_FILE};
+use crate::agents::ConversationContext;
+use crate::auth::{AuthError, Authenticator, Principal, Scope};
+use crate::cache::DEFAULT_CACHE_DIR;
+use crate::config::reload::{self, ConfigChange, ConfigWatcher};
+use crate::config::ServerConfig;
+use crate::metrics::{self, PrometheusText, RequestMetrics};
+use crate::readiness::Readiness;
+use crate::scheduling::Scheduler;
+use crate::webhook::{Delivery, GitHubWebhook};
+use crate::rate_limiter::{self, ClientRateLimiter};
+use crate::error::ProcessorError;
+use crate::monitor::{self, Ecosystem, PackageMonitor};
+use crate::Config;
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, DefaultBodyLimit, Extension, MatchedPath, Request, State, Path, Json, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json as ResponseJson, Response,
+    },
+    routing::{delete, get, post, put},
+    Router,
+};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use axum_extra::routing::RouterExt;
+use serde_json::{json, Value};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tower_http::{cors::CorsLayer, services::ServeFile, trace::TraceLayer};
+use tracing::{info, warn, error};
+
+/// Application state shared across handlers
+#[derive(Clone)]
+struct AppState {
+    job_manager: Arc<JobManager>,
+    monitor: Arc<PackageMonitor>,
+    /// Checks credentials and manages API keys
+    auth: Arc<Authenticator>,
+    /// Per-client request limits
+    limits: Arc<ClientLimits>,
+    /// Counts and latencies of the requests served
+    http_metrics: Arc<RequestMetrics>,
+    /// Checks behind `/readyz`
+    readiness: Arc<Readiness>,
+    /// Conversations, kept across requests and restarts
+    conversations: Arc<ConversationStore>,
+    /// Verifies and routes GitHub webhook deliveries, when a secret is set
+    github_webhook: Option<Arc<GitHubWebhook>>,
+    /// Public address used for links in feeds
+    base_url: String,
+}
+
+/// Request body for registering a package to monitor
+#[derive(Debug, Deserialize)]
+struct WatchRequest {
+    ecosystem: Ecosystem,
+    name: String,
+}
+
+/// Request limits for clients with and without credentials
+struct ClientLimits {
+    /// Keyed by a hash of the credential sent
+    keys: RwLock<Option<ClientRateLimiter>>,
+    /// Keyed by IP address, for requests without a credential
+    ips: RwLock<Option<ClientRateLimiter>>,
+}
+
+impl ClientLimits {
+    fn new(config: &ServerConfig) -> Self {
+        Self {
+            keys: RwLock::new(ClientRateLimiter::new(config.key_requests_per_minute, config.key_burst)),
+            ips: RwLock::new(ClientRateLimiter::new(config.ip_requests_per_minute, config.ip_burst)),
+        }
+    }
+
+    /// Switches to the limits in `config`, keeping what clients have used so far
+    fn update(&self, config: &ServerConfig) {
+        let update = |limiter: &RwLock<Option<ClientRateLimiter>>, requests_per_minute: u32, burst: u32| {
+            let mut limiter = limiter.write().unwrap_or_else(|e| e.into_inner());
+            match limiter.as_ref() {
+                Some(current) if requests_per_minute > 0 => current.set_rate(requests_per_minute, burst),
+                _ => *limiter = ClientRateLimiter::new(requests_per_minute, burst),
+            }
+        };
+        update(&self.keys, config.key_requests_per_minute, config.key_burst);
+        update(&self.ips, config.ip_requests_per_minute, config.ip_burst);
+    }
+
+    /// Counts a request from `client` against the key limits, or the IP limits without `key`
+    fn check(&self, client: &str, key: bool) -> Option<std::result::Result<(), Duration>> {
+        let limiter = if key { &self.keys } else { &self.ips };
+        limiter.read().unwrap_or_else(|e| e.into_inner()).as_ref().map(|limiter| limiter.check(client))
+    }
+
+    /// Requests refused so far and clients tracked, by the key or the IP limits
+    fn stats(&self, key: bool) -> (u64, usize) {
+        let limiter = if key { &self.keys } else { &self.ips };
+        limiter.read().unwrap_or_else(|e| e.into_inner()).as_ref().map_or((0, 0), |limiter| (limiter.limited(), limiter.clients()))
+    }
+}
+
+/// Request body for issuing an API key
+#[derive(Debug, Deserialize)]
+struct CreateKeyRequest {
+    name: String,
+    scopes: Vec<Scope>,
+    /// Workspace to confine the key to
+    #[serde(default)]
+    workspace: Option<String>,
+}
+
+/// Port the server listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 8000;
+
+/// Where the server listens and how it runs
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// Address to bind to
+    pub bind: IpAddr,
+    /// Port to listen on
+    pub port: u16,
+    /// Public address used for links in feeds; defaults to the listening address
+    pub base_url: Option<String>,
+    /// How often watched packages are checked for new releases
+    pub monitor_interval: Duration,
+    /// Port for the gRPC job service, if it should be served; needs the `grpc` feature
+    pub grpc_port: Option<u16>,
+}
+
+impl Default for ServeOptions {
+    /// Listens on 127.0.0.1:8000, honouring `PUBLIC_BASE_URL`, `MONITOR_INTERVAL_SECS` and `GRPC_PORT`
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            base_url: std::env::var("PUBLIC_BASE_URL").ok(),
+            monitor_interval: Duration::from_secs(
+                std::env::var("MONITOR_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600),
+            ),
+            grpc_port: std::env::var("GRPC_PORT").ok().and_then(|v| v.parse().ok()),
+        }
+    }
+}
+
+impl ServeOptions {
+    /// Socket address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+}
+
+/// Serves the API for `config` until the listener fails
+///
+/// Output storage and encryption are process-wide and are expected to be set
+/// up by the caller.
+pub async fn run(config: Config, options: ServeOptions) -> crate::error::Result<()> {
+    config.ensure_directories_exist().await?;
+    
+    // Start the release monitor for watched packages
+    let monitor = Arc::new(PackageMonitor::load(Arc::new(config.clone()))?);
+    Arc::clone(&monitor).spawn(options.monitor_interval);
+    
+    let addr = options.addr();
+    let base_url = options.base_url.clone().unwrap_or_else(|| format!("http://{}", addr));
+    let limits = Arc::new(ClientLimits::new(&config.server));
+    let github_webhook = GitHubWebhook::from_config(&config.server.github_webhook)?.map(Arc::new);
+    if github_webhook.is_none() && !config.server.github_webhook.repositories.is_empty() {
+        warn!("GitHub webhooks are off: set {} to receive them", crate::webhook::SECRET_ENV_VAR);
+    }
+    let scheduler = Scheduler::from_config(&config.server.schedules)?;
+    // Jobs queued before a restart are picked up again by the workers
+    let remote_workers = config.server.remote_workers;
+    let job_manager = Arc::new(JobManager::new(config)?);
+    scheduler.spawn(Arc::clone(&job_manager));
+    if remote_workers {
+        info!("Leaving queued jobs to remote workers");
+        Arc::clone(&job_manager).spawn_lease_keeper();
+    } else {
+        Arc::clone(&job_manager).spawn_workers();
+    }
+    let auth = Arc::new(Authenticator::from_env()?);
+    if !auth.is_enabled() && !addr.ip().is_loopback() {
+        warn!("Listening on {} without authentication; set LLAMA_API_ADMIN_KEY or LLAMA_API_JWT_SECRET", addr);
+    }
+    if let Some(port) = options.grpc_port {
+        serve_grpc(SocketAddr::new(options.bind, port), Arc::clone(&job_manager), Arc::clone(&auth));
+    }
+    let readiness = Arc::new(Readiness::new(job_manager.output_dir().to_path_buf(), DEFAULT_CACHE_DIR.into()));
+    let http_metrics = Arc::new(RequestMetrics::new());
+    let conversations = Arc::new(ConversationStore::open(&job_manager.output_dir().join(CONVERSATIONS_DB_FILE))?);
+    let state = AppState { job_manager, monitor, auth, limits, http_metrics, readiness, conversations, github_webhook, base_url };
+    spawn_config_reload(state.clone());
+    
+    info!("LlamaPackageService Web Server Starting...");
+    info!("Output directory: {}", state.job_manager.output_dir().display());
+    info!("API documentation: {}/docs", state.base_url);
+    info!("Health check: {}/health", state.base_url);
+    info!("Metrics: {}/metrics", state.base_url);
+    
+    let app = create_app(state);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Server listening on http://{}", addr);
+    
+    // Client addresses are needed for per-IP rate limits
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+    Ok(())
+}
+
+/// Applies edits to the config file's reloadable settings while serving
+///
+/// See [`crate::config::reload`] for what is applied and what waits for a restart.
+fn spawn_config_reload(state: AppState) {
+    let mut watcher = match ConfigWatcher::new() {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Not watching the config file for changes: {}", e);
+            return;
+        }
+    };
+    info!("Watching {} for configuration changes", watcher.path().display());
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(reload::RELOAD_INTERVAL).await;
+            let applied = watcher.poll().and_then(|changes| match changes {
+                Some(changes) => apply_config_changes(&state, &changes),
+                None => Ok(()),
+            });
+            if let Err(e) = applied {
+                warn!("Keeping the running configuration: {}", e);
+            }
+        }
+    });
+}
+
+/// Applies the reloadable `changes` to the running server, recording each in the audit log
+fn apply_config_changes(state: &AppState, changes: &[ConfigChange]) -> crate::error::Result<()> {
+    let mut config = Config::clone(&state.job_manager.config());
+    reload::apply(changes, &mut config)?;
+    state.limits.update(&config.server);
+    rate_limiter::upstream_limits().reconfigure(&config.rate_limits);
+    state.monitor.set_config(Arc::new(config.clone()));
+    state.job_manager.set_config(config);
+
+    for change in changes {
+        if change.is_reloadable() {
+            info!(
+                target: reload::AUDIT_TARGET,
+                setting = %change.key, old = %change.old, new = %change.new, source = %change.source,
+                "Applied configuration change"
+            );
+        } else {
+            warn!("{} changed; restart the server to apply it", change.key);
+        }
+    }
+    Ok(())
+}
+
+/// Starts the gRPC job service on `addr` in the background
+#[cfg(feature = "grpc")]
+fn serve_grpc(addr: SocketAddr, job_manager: Arc<JobManager>, auth: Arc<Authenticator>) {
+    info!("gRPC job service listening on {}", addr);
+    tokio::spawn(async move {
+        if let Err(e) = crate::grpc::serve(addr, job_manager, auth).await {
+            error!("{}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(addr: SocketAddr, _job_manager: Arc<JobManager>, _auth: Arc<Authenticator>) {
+    warn!("Not serving gRPC on {}: this build lacks the `grpc` feature", addr);
+}
+
+/// Create the main application with all routes
+fn create_app(state: AppState) -> Router {
+    let limits = (Arc::clone(&state.limits), Arc::clone(&state.auth));
+    let http_metrics = Arc::clone(&state.http_metrics);
+    let auth = Arc::clone(&state.auth);
+    let require = move |scope: Scope| middleware::from_fn_with_state((Arc::clone(&auth), scope), authorize);
+    
+    Router::new()
+        // Health and status endpoints
+        .route("/", get(index))
+        .route("/health", get(health_check))
+        .route("/api/health", get(health_check))
+        .route("/healthz", get(liveness))
+        .route("/readyz", get(readiness))
+        .route("/metrics", get(metrics))
+        .route("/status", get(status).route_layer(require(Scope::Read)))
+        
+        // Processing endpoints
+        .route("/api/process", post(process_repository).route_layer(require(Scope::Submit)))
+        .route("/api/jobs", get(list_jobs).route_layer(require(Scope::Read))
+            .merge(post(process_repository).route_layer(require(Scope::Submit))))
+        .route("/api/jobs/:job_id", get(get_job_status).route_layer(require(Scope::Read))
+            .merge(delete(delete_job).route_layer(require(Scope::Submit))))
+        .route("/api/jobs/:job_id/artifacts", get(get_job_artifacts).route_layer(require(Scope::Read)))
+        .route("/api/jobs/:job_id/events", get(job_events).route_layer(require(Scope::Read)))
+        .route("/artifacts/:job_id/*path", get(download_artifact).route_layer(require(Scope::Read)))
+        .route("/api/workspaces/:workspace/usage", get(workspace_usage).route_layer(require(Scope::Read)))
+        
+        // Remote workers claim jobs, report progress and upload what they generate
+        .route("/api/worker/claim", post(claim_job).route_layer(require(Scope::Work)))
+        .route("/api/worker/jobs/:job_id/progress", post(report_progress).route_layer(require(Scope::Work)))
+        // Artifacts can be far larger than request bodies elsewhere, and only workers send them
+        .route("/api/worker/jobs/:job_id/artifacts/*path", put(upload_artifact)
+            .route_layer(require(Scope::Work))
+            .layer(DefaultBodyLimit::disable()))
+        .route("/api/worker/jobs/:job_id/finish", post(finish_job).route_layer(require(Scope::Work)))
+        
+        // AI Analysis endpoints
+        .route("/api/analyze", post(analyze_repository).route_layer(require(Scope::Submit)))
+        .route("/api/analyses", get(list_analyses).route_layer(require(Scope::Read))
+            .merge(post(submit_analysis).route_layer(require(Scope::Submit))))
+        .route("/api/analyses/:job_id", get(get_analysis).route_layer(require(Scope::Read)))
+        .route("/api/conversation", post(start_conversation).route_layer(require(Scope::Submit)))
+        .route("/api/conversation/:conversation_id", get(get_conversation).route_layer(require(Scope::Read))
+            .merge(delete(delete_conversation).route_layer(require(Scope::Submit))))
+        .route("/api/conversation/:conversation_id/message", post(send_message).route_layer(require(Scope::Submit)))
+        
+        // Release monitoring; feeds and the reports they link to stay public for feed readers
+        .route("/api/monitor/packages", get(list_watched).route_layer(require(Scope::Read))
+            .merge(post(watch_package).route_layer(require(Scope::Submit))))
+        .route("/api/monitor/packages/:package_id", delete(unwatch_package).route_layer(require(Scope::Submit)))
+        .route("/api/monitor/check", post(check_releases).route_layer(require(Scope::Submit)))
+        .route("/api/monitor/reports/:entry_id", get(get_delta_report))
+        .route("/api/feed.atom", get(atom_feed))
+        .route("/api/feed.json", get(json_feed))
+        
+        // GitHub webhooks authenticate with their signature instead of a credential
+        .route("/webhooks/github", post(github_webhook))
+        
+        // API key management
+        .route("/api/keys", get(list_keys).post(create_key).route_layer(require(Scope::Admin)))
+        .route("/api/keys/:key_id", delete(revoke_key).route_layer(require(Scope::Admin)))
+        
+        // Documentation
+        .route("/docs", get(api_docs))
+        .route("/openapi.json", get(openapi_spec))
+        
+        // Add middleware
+        .layer(middleware::from_fn_with_state(limits, rate_limit))
+        .layer(middleware::from_fn_with_state(http_metrics, track_requests))
+        .layer(CorsLayer::permissive())
+        .layer(TraceLayer::new_for_http())
+        // Outermost, so the token is gone before the request is traced or logged
+        .layer(middleware::from_fn(take_query_token))
+        .with_state(state)
+}
+
+/// `access_token` query parameter of a job event stream request
+#[derive(Clone)]
+struct QueryToken(String);
+
+/// Whether `path` is a job's event stream, the one route taking a token in its query
+fn is_event_stream(path: &str) -> bool {
+    path.strip_prefix("/api/jobs/")
+        .and_then(|rest| rest.strip_suffix("/events"))
+        .is_some_and(|job_id| !job_id.is_empty() && !job_id.contains('/'))
+}
+
+/// Moves an `access_token` query parameter out of the URI
+///
+/// `EventSource` clients cannot set headers, so the job event stream accepts
+/// its credential in the query; other routes ignore it. Either way it is
+/// removed from the URI, so the request log never records it.
+async fn take_query_token(mut request: Request, next: Next) -> Response {
+    let Some(query) = request.uri().query() else {
+        return next.run(request).await;
+    };
+    let (tokens, rest): (Vec<_>, Vec<_>) = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .partition(|(name, _)| name == "access_token");
+    if tokens.is_empty() {
+        return next.run(request).await;
+    }
+
+    let path = request.uri().path().to_string();
+    if is_event_stream(&path) {
+        if let Some((_, token)) = tokens.into_iter().next() {
+            request.extensions_mut().insert(QueryToken(token));
+        }
+    }
+    let query = url::form_urlencoded::Serializer::new(String::new()).extend_pairs(rest).finish();
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = match query.is_empty() {
+        true => path.parse().ok(),
+        false => format!("{}?{}", path, query).parse().ok(),
+    };
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *request.uri_mut() = uri;
+    }
+    next.run(request).await
+}
+
+/// Rejects requests whose credentials do not grant `scope`
+///
+/// Credentials come from `Authorization: Bearer`, `X-API-Key`, or, on the job
+/// event stream only, an `access_token` query parameter for clients such as
+/// `EventSource` that cannot set headers. The caller's identity is added to the
+/// request extensions.
+async fn authorize(
+    State((auth, scope)): State<(Arc<Authenticator>, Scope)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let token = credential(&request);
+    match auth.authorize(token.as_deref(), scope) {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(AuthError::Forbidden(scope)) => (
+            StatusCode::FORBIDDEN,
+            ResponseJson(json!({ "error": format!("this credential lacks the '{}' scope", scope) })),
+        ).into_response(),
+        Err(e) => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            ResponseJson(json!({ "error": match e {
+                AuthError::Missing => "credentials required",
+                _ => "invalid or expired credentials",
+            } })),
+        ).into_response(),
+    }
+}
+
+/// Answers `429 Too Many Requests` to clients over their limit
+///
+/// Requests with a valid credential count against its key or JWT subject,
+/// everything else against the client's IP address, so made-up tokens do not
+/// get a fresh allowance. Health checks and metrics scrapes are never limited.
+async fn rate_limit(
+    State((limits, auth)): State<(Arc<ClientLimits>, Arc<Authenticator>)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if matches!(request.uri().path(), "/health" | "/api/health" | "/healthz" | "/readyz" | "/metrics") {
+        return next.run(request).await;
+    }
+    let token = credential(&request);
+    let subject = match auth.authorize(token.as_deref(), Scope::Read) {
+        _ if !auth.is_enabled() => None,
+        Ok(principal) => Some(principal.subject),
+        Err(AuthError::Forbidden(_)) => token.map(|token| hex::encode(Sha256::digest(token.as_bytes()))),
+        Err(_) => None,
+    };
+    let checked = match subject {
+        Some(subject) => limits.check(&subject, true),
+        None => {
+            let ip = request.extensions().get::<ConnectInfo<SocketAddr>>()
+                .map_or_else(|| "unknown".to_string(), |ConnectInfo(addr)| addr.ip().to_string());
+            limits.check(&ip, false)
+        }
+    };
+    match checked {
+        Some(Err(wait)) => {
+            let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds.to_string())],
+                ResponseJson(json!({ "error": "rate limit exceeded", "retry_after_seconds": seconds })),
+            ).into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// Records the count and latency of every request by route
+async fn track_requests(State(metrics): State<Arc<RequestMetrics>>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // Unmatched paths share one series so scanners cannot create unbounded ones
+    let route = request.extensions().get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics.record(&method, &route, response.status().as_u16(), started.elapsed());
+    response
+}
+
+/// Bearer token, API key header or event stream `access_token` of a request
+fn credential(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    let bearer = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = headers.get("x-api-key").and_then(|value| value.to_str().ok());
+    let from_query = || request.extensions().get::<QueryToken>().map(|QueryToken(token)| token.clone());
+    bearer.or(api_key).map(str::to_string).or_else(from_query)
+}
+
+/// Root endpoint - returns basic service information
+async fn index() -> ResponseJson<Value> {
+    ResponseJson(json!({
+        "service": "LlamaPackageService",
+        "version": env!("CARGO_PKG_VERSION"),
+        "description": "Transform code repositories into structured text representations",
+        "author": "Nik Jois <nikjois@llamasearch.ai>",
+        "endpoints": {
+            "health": "/health",
+            "liveness": "/healthz",
+            "readiness": "/readyz",
+            "metrics": "/metrics",
+            "jobs": "/api/jobs",
+            "analyze": "/api/analyze",
+            "analyses": "/api/analyses",
+            "conversation": "/api/conversation",
+            "monitor": "/api/monitor/packages",
+            "feed": "/api/feed.atom",
+            "github_webhook": "/webhooks/github",
+            "documentation": "/docs"
+        }
+    }))
+}
+
+/// Health check endpoint
+async fn health_check(State(state): State<AppState>) -> ResponseJson<Value> {
+    let health = state.job_manager.get_health().await;
+    ResponseJson(json!(health))
+}
+
+/// Liveness probe: the process is up and serving requests
+async fn liveness() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe: `503` until the server can take work
+async fn readiness(State(state): State<AppState>) -> (StatusCode, ResponseJson<Value>) {
+    let report = state.readiness.check().await;
+    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, ResponseJson(json!(report)))
+}
+
+/// Prometheus metrics: jobs, HTTP requests, caches, rate limiting and AI token usage
+async fn metrics(State(state): State<AppState>) -> Response {
+    const PREFIX: &str = metrics::PREFIX;
+    let health = state.job_manager.get_health().await;
+    let jobs = state.job_manager.list_jobs(None).await.unwrap_or_default();
+    let mut text = PrometheusText::new();
+
+    let name = format!("{}_build_info", PREFIX);
+    text.family(&name, "gauge", "Version of the running server")
+        .sample(&name, &[("version", env!("CARGO_PKG_VERSION"))], 1.0);
+    let name = format!("{}_uptime_seconds", PREFIX);
+    text.family(&name, "gauge", "Seconds since the server started")
+        .sample(&name, &[], health.uptime as f64);
+
+    let name = format!("{}_jobs", PREFIX);
+    text.family(&name, "gauge", "Jobs in the queue by state");
+    for status in JobStatusType::ALL {
+        let count = jobs.iter().filter(|job| job.status == status).count();
+        text.sample(&name, &[("state", status.name())], count as f64);
+    }
+
+    state.http_metrics.render(PREFIX, &mut text);
+    metrics::render_processing_metrics(PREFIX, &mut text);
+    metrics::render_cache_metrics(PREFIX, &mut text);
+
+    let name = format!("{}_rate_limited_requests_total", PREFIX);
+    text.family(&name, "counter", "Requests refused with 429 by client kind")
+        .sample(&name, &[("client", "key")], state.limits.stats(true).0 as f64)
+        .sample(&name, &[("client", "ip")], state.limits.stats(false).0 as f64);
+
+    // Deleting jobs forgets their usage, so this is a gauge rather than a counter
+    let name = format!("{}_analysis_tokens", PREFIX);
+    text.family(&name, "gauge", "Tokens used by the AI analysis jobs on record");
+    let mut prompt = 0;
+    let mut completion = 0;
+    for usage in jobs.iter().filter_map(|job| job.usage.as_ref()) {
+        prompt += usage.prompt_tokens;
+        completion += usage.completion_tokens;
+    }
+    text.sample(&name, &[("kind", "prompt")], prompt as f64)
+        .sample(&name, &[("kind", "completion")], completion as f64);
+    crate::agents::usage::render_metrics(PREFIX, &mut text);
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        text.finish(),
+    ).into_response()
+}
+
+/// Service status endpoint
+async fn status(State(state): State<AppState>) -> ResponseJson<Value> {
+    let health = state.job_manager.get_health().await;
+    ResponseJson(json!({
+        "status": "operational",
+        "uptime_seconds": health.uptime,
+        "active_jobs": health.active_jobs,
+        "completed_jobs": health.completed_jobs,
+        "memory_usage": get_memory_usage(),
+        "rate_limits": {
+            "limited_key_requests": state.limits.stats(true).0,
+            "limited_ip_requests": state.limits.stats(false).0,
+            "tracked_clients": state.limits.stats(true).1 + state.limits.stats(false).1
+        },
+        "rust_version": std::env::var("RUSTC_VERSION").unwrap_or_else(|_| "unknown".to_string()),
+        "build_time": std::env::var("BUILD_TIME").unwrap_or_else(|_| "unknown".to_string())
+    }))
+}
+
+/// Process a repository endpoint
+async fn process_repository(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<ProcessRequest>,
+) -> Result<ResponseJson<Value>, (StatusCode, ResponseJson<Value>)> {
+    info!("Processing repository: {}", request.url);
+    
+    match state.job_manager.submit_job(request, principal.workspace.as_deref()).await {
+        Ok(response) => {
+            info!("Job submitted successfully: {}", response.job_id);
+            Ok(ResponseJson(json!(response)))
+        },
+        Err(e) => {
+            error!("Failed to submit job: {}", e);
+            let status = match e {
+                ProcessorError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+                ProcessorError::Forbidden(_) => StatusCode::FORBIDDEN,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            Err((status, ResponseJson(json!({ "error": e.to_string() }))))
+        }
+    }
+}
+
+/// A job, provided it exists and the caller's workspace may see it
+///
+/// Jobs of other workspaces are reported as missing rather than forbidden, so
+/// their ids reveal nothing.
+async fn visible_job(state: &AppState, principal: &Principal, job_id: &str) -> Result<JobStatus, StatusCode> {
+    state.job_manager.get_job_status(job_id).await
+        .ok()
+        .filter(|job| job.is_visible_to(principal.workspace.as_deref()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Get job status endpoint
+async fn get_job_status(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    let status = visible_job(&state, &principal, &job_id).await?;
+    Ok(ResponseJson(json!(status)))
+}
+
+/// List jobs endpoint, filtered, sorted and paged by the query parameters
+async fn list_jobs(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(params): Query<JobListParams>,
+) -> Result<ResponseJson<Value>, (StatusCode, ResponseJson<Value>)> {
+    match state.job_manager.query_jobs(&params, principal.workspace.as_deref()).await {
+        Ok(list) => Ok(ResponseJson(json!(list))),
+        Err(ProcessorError::Validation(message)) => {
+            Err((StatusCode::BAD_REQUEST, ResponseJson(json!({ "error": message }))))
+        }
+        Err(e) => {
+            error!("Failed to list jobs: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, ResponseJson(json!({ "error": "failed to list jobs" }))))
+        }
+    }
+}
+
+/// Files generated by a job
+async fn get_job_artifacts(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    visible_job(&state, &principal, &job_id).await?;
+    match state.job_manager.get_job_artifacts(&job_id).await {
+        Ok(artifacts) => Ok(ResponseJson(json!({
+            "job_id": job_id,
+            "artifacts": artifacts,
+            "total": artifacts.len()
+        }))),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Download a file a job generated
+///
+/// Serves a single byte `Range` for partial or resumed downloads, with a
+/// strong `ETag` for `If-None-Match` and `If-Range`. Files are sent as
+/// stored, so compressed artifacts keep their `.zst` or `.gz` encoding.
+async fn download_artifact(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path((job_id, path)): Path<(String, String)>,
+    mut request: Request,
+) -> std::result::Result<Response, StatusCode> {
+    visible_job(&state, &principal, &job_id).await?;
+    let file = state.job_manager.artifact_file(&job_id, &path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let metadata = tokio::fs::metadata(&file).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let etag = artifact_etag(&metadata);
+
+    let headers = request.headers_mut();
+    if headers.get(header::IF_NONE_MATCH).is_some_and(|value| etag_matches(value, &etag)) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    // Resuming with a range of a file that has since been rewritten would
+    // corrupt the download, so send the whole file instead
+    if let Some(if_range) = headers.remove(header::IF_RANGE) {
+        if if_range != etag.as_str() {
+            headers.remove(header::RANGE);
+        }
+    }
+
+    let mut response = ServeFile::new(&file).try_call(request).await.map_err(|e| {
+        error!("Failed to read artifact {}: {}", file.display(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    Ok(response.into_response())
+}
+
+/// Strong entity tag of an artifact, from its size and modification time
+fn artifact_etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+/// Whether an `If-None-Match` header names `etag`
+fn etag_matches(value: &HeaderValue, etag: &str) -> bool {
+    value.to_str().is_ok_and(|value| {
+        value.split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    })
+}
+
+/// Queue processing of a repository GitHub reports changed
+///
+/// Deliveries must carry a valid signature. Events for repositories that are
+/// not configured are acknowledged and ignored, and a change arriving while
+/// the repository is still queued does not queue it again.
+async fn github_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, ResponseJson<Value>) {
+    let Some(webhook) = state.github_webhook.as_deref() else {
+        return (StatusCode::NOT_FOUND, ResponseJson(json!({ "error": "GitHub webhooks are not configured" })));
+    };
+    let header_value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if !webhook.verify(&body, header_value("x-hub-signature-256")) {
+        warn!("Rejected GitHub delivery {} with an invalid signature", header_value("x-github-delivery").unwrap_or("-"));
+        return (StatusCode::UNAUTHORIZED, ResponseJson(json!({ "error": "invalid signature" })));
+    }
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return (StatusCode::BAD_REQUEST, ResponseJson(json!({ "error": format!("invalid payload: {}", e) }))),
+    };
+    let (repository, url, reason) = match webhook.route(header_value("x-github-event").unwrap_or_default(), &payload) {
+        Delivery::Process { repository, url, reason } => (repository, url, reason),
+        Delivery::Ignored(reason) => {
+            return (StatusCode::OK, ResponseJson(json!({ "status": "ignored", "reason": reason })));
+        }
+    };
+    let workspace = repository.workspace.as_deref();
+    if let Ok(Some(job)) = state.job_manager.queued_job(&url, workspace).await {
+        return (StatusCode::OK, ResponseJson(json!({ "status": "already queued", "job_id": job.job_id })));
+    }
+
+    info!("Queueing {} after {}", url, reason);
+    let request = ProcessRequest { url: url.clone(), output_dir: None, config: None, processor: None };
+    let mut jobs = match state.job_manager.submit_job(request, workspace).await {
+        Ok(response) => vec![response.job_id],
+        Err(e) => {
+            error!("Failed to queue {} from a GitHub webhook: {}", url, e);
+            let status = match e {
+                ProcessorError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return (status, ResponseJson(json!({ "error": e.to_string() })));
+        }
+    };
+    let mut errors = Vec::new();
+    for analysis in &repository.analyses {
+        let request = AnalysisRequest { repository: url.clone(), analysis_type: analysis.clone(), context: None };
+        match state.job_manager.submit_analysis(request, workspace).await {
+            Ok(response) => jobs.push(response.job_id),
+            Err(e) => {
+                warn!("Failed to queue the {} analysis of {}: {}", analysis, url, e);
+                errors.push(format!("{} analysis: {}", analysis, e));
+            }
+        }
+    }
+    (StatusCode::ACCEPTED, ResponseJson(json!({ "status": "queued", "reason": reason, "jobs": jobs, "errors": errors })))
+}
+
+/// Stream a job's progress as Server-Sent Events
+///
+/// Sends the job's current state first, then a `progress` event whenever it
+/// changes, and ends after the event for the finished, failed or cancelled job.
+async fn job_events(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>, StatusCode> {
+    visible_job(&state, &principal, &job_id).await?;
+    let events = state.job_manager.watch_job(&job_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let stream = events.map(|event| {
+        let data = serde_json::to_string(&event).unwrap_or_default();
+        Ok(Event::default().event("progress").data(data))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Cancel a queued or running job, or forget a finished one
+async fn delete_job(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    visible_job(&state, &principal, &job_id).await?;
+    match state.job_manager.delete_job(&job_id).await {
+        Ok(job) => {
+            info!("Job {} deleted ({:?})", job_id, job.status);
+            Ok(ResponseJson(json!(job)))
+        }
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Status of a worker request about a job: whether it is still the worker's, or why it failed
+fn worker_status(result: crate::error::Result<bool>) -> StatusCode {
+    match result {
+        Ok(true) => StatusCode::NO_CONTENT,
+        // Cancelled, or queued again for another worker after the lease ran out
+        Ok(false) => StatusCode::CONFLICT,
+        Err(ProcessorError::Validation(_)) => StatusCode::BAD_REQUEST,
+        Err(ProcessorError::Message(_)) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to record a worker's report: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Hand the oldest queued job to a remote worker; `204 No Content` when there is none
+async fn claim_job(State(state): State<AppState>) -> Result<Response, StatusCode> {
+    match state.job_manager.claim_remote().await {
+        Ok(Some(job)) => {
+            info!("Job {} claimed by a remote worker", job.job_id);
+            Ok(ResponseJson(json!(job)).into_response())
+        }
+        Ok(None) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(e) => {
+            error!("Failed to claim a job: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Record a remote worker's progress; `409 Conflict` tells it to drop the job
+async fn report_progress(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+    Json(progress): Json<WorkerProgress>,
+) -> StatusCode {
+    worker_status(state.job_manager.report_remote(&job_id, &progress))
+}
+
+/// Store a file a remote worker generated for a job
+async fn upload_artifact(
+    State(state): State<AppState>,
+    Path((job_id, path)): Path<(String, String)>,
+    body: Bytes,
+) -> StatusCode {
+    worker_status(state.job_manager.store_remote_artifact(&job_id, &path, &body).await)
+}
+
+/// Record the outcome of a job a remote worker ran
+async fn finish_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+    Json(outcome): Json<WorkerOutcome>,
+) -> StatusCode {
+    let status = worker_status(state.job_manager.finish_remote(&job_id, outcome).await);
+    if status == StatusCode::NO_CONTENT {
+        info!("Job {} finished by a remote worker", job_id);
+    }
+    status
+}
+
+/// Jobs submitted today, storage used and quota of a workspace
+///
+/// Callers confined to a workspace may only ask about their own.
+async fn workspace_usage(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(workspace): Path<String>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    if !principal.workspace.as_deref().map_or(true, |own| own == workspace) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    crate::workspace::validate_name(&workspace).map_err(|_| StatusCode::BAD_REQUEST)?;
+    match state.job_manager.workspace_usage(&workspace).await {
+        Ok(usage) => Ok(ResponseJson(json!(usage))),
+        Err(e) => {
+            error!("Failed to measure workspace {}: {}", workspace, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Queue an AI analysis as a job
+async fn submit_analysis(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<AnalysisRequest>,
+) -> Result<(StatusCode, ResponseJson<Value>), (StatusCode, ResponseJson<Value>)> {
+    info!("AI analysis queued for repository: {}", request.repository);
+    match state.job_manager.submit_analysis(request, principal.workspace.as_deref()).await {
+        Ok(response) => Ok((StatusCode::ACCEPTED, ResponseJson(json!(response)))),
+        Err(e) => {
+            error!("Failed to queue analysis: {}", e);
+            let status = match e {
+                ProcessorError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+                ProcessorError::Forbidden(_) => StatusCode::FORBIDDEN,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            Err((status, ResponseJson(json!({ "error": e.to_string() }))))
+        }
+    }
+}
+
+/// Analysis jobs with the tokens and estimated cost they used in total
+async fn list_analyses(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    match state.job_manager.list_analyses(principal.workspace.as_deref()).await {
+        Ok((analyses, usage)) => Ok(ResponseJson(json!({
+            "analyses": analyses,
+            "total": analyses.len(),
+            "usage": usage
+        }))),
+        Err(e) => {
+            error!("Failed to list analyses: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// An analysis job, with its result once it has completed
+async fn get_analysis(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    let job = visible_job(&state, &principal, &job_id).await?;
+    if job.url_type != api::ANALYSIS_JOB_TYPE {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let result = match job.output_files.iter().find(|file| file.ends_with(".md")) {
+        Some(file) => tokio::fs::read_to_string(job.output_dir.join(file)).await.ok(),
+        None => None,
+    };
+    Ok(ResponseJson(json!({
+        "job": job,
+        "result": result
+    })))
+}
+
+/// Analyze repository with AI endpoint
+async fn analyze_repository(
+    State(_state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<AnalysisRequest>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    info!("AI analysis requested for repository: {}", request.repository);
+    
+    // This would use the OpenAI agents integration
+    match api::analyze_repository(request, principal.workspace.as_deref()).await {
+        Ok(response) => Ok(ResponseJson(json!(response))),
+        Err(ProcessorError::Forbidden(_)) => Err(StatusCode::FORBIDDEN),
+        Err(e) => {
+            error!("Analysis failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Start conversation endpoint
+async fn start_conversation(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<ConversationRequest>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    info!("Starting conversation for repository: {}", request.repository);
+    
+    let workspace = principal.workspace.as_deref();
+    match api::start_conversation(&state.conversations, &state.job_manager.config(), request, workspace).await {
+        Ok(response) => Ok(ResponseJson(json!(response))),
+        Err(ProcessorError::Forbidden(_)) => Err(StatusCode::FORBIDDEN),
+        Err(e) => {
+            error!("Failed to start conversation: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Send message to conversation endpoint
+async fn send_message(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(conversation_id): Path<String>,
+    Json(mut request): Json<MessageRequest>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    info!("Sending message to conversation: {}", conversation_id);
+    
+    // Set conversation ID from path
+    request.conversation_id = conversation_id;
+    
+    match api::send_message(&state.conversations, &state.job_manager.config(), request, principal.workspace.as_deref()).await {
+        Ok(Some(response)) => Ok(ResponseJson(json!(response))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to send message: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// A conversation, provided it exists and the caller's workspace may see it
+fn visible_conversation(state: &AppState, principal: &Principal, conversation_id: &str) -> crate::error::Result<Option<ConversationContext>> {
+    Ok(state.conversations.load(conversation_id)?
+        .filter(|context| context.is_visible_to(principal.workspace.as_deref())))
+}
+
+/// A conversation with its messages
+async fn get_conversation(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(conversation_id): Path<String>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    match visible_conversation(&state, &principal, &conversation_id) {
+        Ok(Some(context)) => Ok(ResponseJson(json!(context))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load conversation {}: {}", conversation_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Delete a conversation
+async fn delete_conversation(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(conversation_id): Path<String>,
+) -> StatusCode {
+    let deleted = visible_conversation(&state, &principal, &conversation_id).and_then(|context| match context {
+        Some(_) => state.conversations.delete(&conversation_id),
+        None => Ok(false),
+    });
+    match deleted {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to delete conversation {}: {}", conversation_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// List issued API keys
+async fn list_keys(State(state): State<AppState>) -> ResponseJson<Value> {
+    let keys = state.auth.list_keys();
+    ResponseJson(json!({
+        "keys": keys,
+        "total": keys.len()
+    }))
+}
+
+/// Issue an API key; the key itself is only returned here
+async fn create_key(
+    State(state): State<AppState>,
+    Json(request): Json<CreateKeyRequest>,
+) -> Result<(StatusCode, ResponseJson<Value>), StatusCode> {
+    match state.auth.create_key(&request.name, request.scopes, request.workspace) {
+        Ok(issued) => {
+            info!("Issued API key {} ({})", issued.info.id, issued.info.name);
+            Ok((StatusCode::CREATED, ResponseJson(json!(issued))))
+        }
+        Err(e) => {
+            warn!("Failed to issue API key: {}", e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Revoke an API key
+async fn revoke_key(
+    State(state): State<AppState>,
+    Path(key_id): Path<String>,
+) -> StatusCode {
+    match state.auth.revoke_key(&key_id) {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to revoke API key: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// List watched packages endpoint
+async fn list_watched(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> ResponseJson<Value> {
+    let packages = state.monitor.packages(principal.workspace.as_deref()).await;
+    ResponseJson(json!({
+        "packages": packages,
+        "total": packages.len()
+    }))
+}
+
+/// Register a package for release monitoring
+async fn watch_package(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<WatchRequest>,
+) -> Result<ResponseJson<Value>, StatusCode> {
+    match state.monitor.watch(request.ecosystem, &request.name, principal.workspace.as_deref()).await {
+        Ok(package) => Ok(ResponseJson(json!(package))),
+        Err(e) => {
+            warn!("Failed to watch package: {}", e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Stop monitoring a package
+async fn unwatch_package(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(package_id): Path<String>,
+) -> StatusCode {
+    match state.monitor.unwatch(&package_id, principal.workspace.as_deref()).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to unwatch package: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Check the watched packages visible to the caller for new releases now
+async fn check_releases(State(state): State<AppState>, Extension(principal): Extension<Principal>) -> ResponseJson<Value> {
+    let added = state.monitor.check_all(principal.workspace.as_deref()).await;
+    ResponseJson(json!({
+        "new_entries": added,
+        "total": added.len()
+    }))
+}
+
+/// Delta report for a feed entry
+async fn get_delta_report(
+    State(state): State<AppState>,
+    Path(entry_id): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    match state.monitor.report(&entry_id).await {
+        Ok(report) => Ok(([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], report)),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Atom feed of newly analyzed versions
+async fn atom_feed(State(state): State<AppState>) -> impl IntoResponse {
+    let entries = state.monitor.entries().await;
+    (
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        monitor::atom_feed(&entries, &state.base_url),
+    )
+}
+
+/// JSON Feed of newly analyzed versions
+async fn json_feed(State(state): State<AppState>) -> ResponseJson<Value> {
+    let entries = state.monitor.entries().await;
+    ResponseJson(monitor::json_feed(&entries, &state.base_url))
+}
+
+/// API documentation endpoint
+async fn api_docs() -> &'static str {
+    r##"
+    <!DOCTYPE html>
+    <html>
+    <head>
+        <title>LlamaPackageService API Documentation</title>
+        <style>
+            body { font-family: Arial, sans-serif; margin: 40px; }
+            h1 { color: #333; }
+            h2 { color: #666; }
+            code { background: #f4f4f4; padding: 2px 4px; border-radius: 3px; }
+            pre { background: #f4f4f4; padding: 10px; border-radius: 5px; overflow-x: auto; }
+            .endpoint { margin: 20px 0; padding: 15px; background: #f9f9f9; border-radius: 5px; }
+        </style>
+    </head>
+    <body>
+        <h1>LlamaPackageService API Documentation</h1>
+        <p>Author: <strong>Nik Jois &lt;nikjois@llamasearch.ai&gt;</strong></p>
+        
+        <h2>Authentication</h2>
+        <p>Once the server has API keys, an admin key (<code>LLAMA_API_ADMIN_KEY</code>) or a JWT secret
+        (<code>LLAMA_API_JWT_SECRET</code>), requests need <code>Authorization: Bearer &lt;key or JWT&gt;</code>
+        with the right scope: <code>read</code> for status and artifacts, <code>submit</code> for jobs, analyses
+        and monitoring changes, <code>admin</code> for keys. Health, metrics, documentation and feeds stay public, and
+        GitHub webhooks are checked against their signature instead.</p>
+        
+        <h2>Workspaces</h2>
+        <p>Keys issued with a <code>workspace</code>, and JWTs with a <code>workspace</code> claim, only see that
+        workspace's jobs and write under <code>workspaces/&lt;name&gt;/</code> in the output directory, where a
+        job's <code>output_dir</code> must be a relative path. Submissions beyond the workspace's daily job or
+        storage quota get <code>429</code>.</p>
+        
+        <h2>Rate Limits</h2>
+        <p>Each API key or JWT subject, and each IP address sending requests without a valid credential,
+        has its own request allowance. Clients over it get <code>429 Too Many Requests</code> with a
+        <code>Retry-After</code> header. Health checks and metrics are not limited.</p>
+        
+        <h2>Endpoints</h2>
+        
+        <div class="endpoint">
+            <h3>GET /health</h3>
+            <p>Health check endpoint</p>
+            <pre>Response: {"service": "...", "status": "...", "uptime": 123}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /healthz</h3>
+            <p>Liveness probe: <code>200 ok</code> while the process is serving requests</p>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /readyz</h3>
+            <p>Readiness probe: <code>200</code> once the output and cache directories are writable and at least
+            one package registry is reachable, <code>503</code> otherwise</p>
+            <pre>Response: {"ready": true, "checks": [{"name": "output_dir", "ok": true}, {"name": "registry:npm", "ok": false, "error": "..."}]}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /metrics</h3>
+            <p>Prometheus metrics: jobs by state, HTTP requests and latency by route, rate-limited requests and
+            AI analysis token usage</p>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/jobs</h3>
+            <p>Submit a URL or path for processing (also available as <code>POST /api/process</code>)</p>
+            <pre>Request: {"url": "https://github.com/user/repo", "output_dir": "/optional/path", "processor": "optional, e.g. go", "config": {"organize_output": true, "generate_index": false}}</pre>
+            <pre>Response: {"job_id": "uuid", "status": "queued", "message": "..."}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/jobs</h3>
+            <p>List jobs, newest first. Filter with <code>status</code> (comma-separated), <code>source</code>
+            (text in the URL type, e.g. github), <code>since</code> (RFC 3339 or YYYY-MM-DD) and <code>q</code> (text in the URL); order with
+            <code>sort=created|updated|url</code> and <code>order=asc|desc</code>. Pages hold <code>limit</code>
+            jobs (50, at most 500); pass <code>next_page</code> back as <code>page</code> for the next one.</p>
+            <pre>GET /api/jobs?status=failed&amp;source=github&amp;q=tokio&amp;limit=20</pre>
+            <pre>Response: {"jobs": [...], "total": 73, "next_page": "eyJzb3J0Ijoi..."}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/jobs/{job_id}</h3>
+            <p>Get job status, progress and error</p>
+            <pre>Response: {"job_id": "uuid", "status": "completed", "progress": 100, "error_message": null, "output_files": [...]}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/jobs/{job_id}/artifacts</h3>
+            <p>Files the job generated, relative to its output directory</p>
+            <pre>Response: {"job_id": "uuid", "artifacts": [{"path": "github_repos/owner-repo_github_repo.txt", "size": 1234}], "total": 1}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /artifacts/{job_id}/{path}</h3>
+            <p>Download one of a job's files, as listed by its artifacts. Supports a single byte
+            <code>Range</code> (<code>206 Partial Content</code>) to fetch part of a file or resume a download, and
+            an <code>ETag</code> for <code>If-None-Match</code> and <code>If-Range</code>.</p>
+            <pre>curl -C - -O -H "Authorization: Bearer $KEY" http://localhost:8000/artifacts/uuid/github_repos/owner-repo_github_repo.txt</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/jobs/{job_id}/events</h3>
+            <p>Live progress as Server-Sent Events; each <code>progress</code> event is a full snapshot, and the stream ends once the job is finished</p>
+            <pre>event: progress
+data: {"job_id": "uuid", "status": "processing", "progress": 42, "stage": "Processing code files", "files_processed": 120, "files_total": 480, "bytes_downloaded": 5242880, "eta_seconds": 95, "error_message": null}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>DELETE /api/jobs/{job_id}</h3>
+            <p>Cancel a queued or running job, or remove a finished job from the list; generated files are kept</p>
+            <pre>Response: {"job_id": "uuid", "status": "cancelled", ...}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/workspaces/{name}/usage</h3>
+            <p>Jobs submitted in the last 24 hours, storage used and quota of a workspace; workspace credentials may only ask about their own</p>
+            <pre>Response: {"workspace": "team-a", "jobs_last_day": 12, "storage_bytes": 52428800, "quota": {"jobs_per_day": 100, "storage_mb": 1024}}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/analyze</h3>
+            <p>AI-powered repository analysis</p>
+            <pre>Request: {"repository": "https://github.com/user/repo", "analysis_type": "Documentation"}</pre>
+            <pre>Response: {"id": "uuid", "result": "...", "confidence": 0.95, "usage": {"model": "gpt-4", "prompt_tokens": 1200, "completion_tokens": 800, "estimated_cost_usd": 0.084}}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/analyses</h3>
+            <p>Queue an AI analysis as a job (<code>documentation</code>, <code>security</code>, <code>code_review</code>, <code>examples</code>, <code>api_docs</code>, or a custom prompt); follow it with the job endpoints</p>
+            <pre>Request: {"repository": "https://github.com/user/repo", "analysis_type": "security", "context": "optional"}</pre>
+            <pre>Response (202): {"job_id": "uuid", "status": "queued", "url_type": "analysis", ...}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/analyses, GET /api/analyses/{job_id}</h3>
+            <p>Analysis jobs with their token use and the total estimated cost, or one analysis with its result once completed</p>
+            <pre>Response: {"analyses": [...], "total": 3, "usage": {"model": "gpt-4", "prompt_tokens": 3600, "completion_tokens": 2400, "estimated_cost_usd": 0.252}}</pre>
+            <pre>Response: {"job": {"job_id": "uuid", "status": "completed", "usage": {...}, ...}, "result": "# Security review..."}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/conversation</h3>
+            <p>Start AI conversation about repository</p>
+            <pre>Request: {"repository": "https://github.com/user/repo"}</pre>
+            <pre>Response: {"conversation_id": "uuid"}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/conversation/{id}/message</h3>
+            <p>Send message to AI conversation</p>
+            <pre>Request: {"message": "What does this code do?"}</pre>
+            <pre>Response: {"response": "This code implements..."}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET|POST /api/keys, DELETE /api/keys/{id}</h3>
+            <p>List, issue or revoke API keys (admin scope). The key is only shown in the response that issues it.</p>
+            <pre>Request: {"name": "ci", "scopes": ["submit", "read"], "workspace": "optional, e.g. team-a"}</pre>
+            <pre>Response: {"key": "lps_...", "id": "3f9a1c2b7d4e", "name": "ci", "scopes": ["submit", "read"], "created_at": "..."}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/worker/claim</h3>
+            <p>Claim the oldest queued job as a remote worker (work scope, <code>server.remote_workers</code>); 204 when none is queued</p>
+            <pre>Response: {"job_id": "uuid", "request": {"url": "https://github.com/user/repo", ...}}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/worker/jobs/{id}/progress, PUT /api/worker/jobs/{id}/artifacts/{path}, POST /api/worker/jobs/{id}/finish</h3>
+            <p>Report a claimed job's progress, upload a file it generated, and record its outcome (work scope). 409 means the job was cancelled or handed to another worker.</p>
+            <pre>Request: {"progress": 40, "operation": "Processing code files"}</pre>
+            <pre>Request: {"output_files": ["github_repos/owner-repo_github_repo.txt"], "error": null}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET|POST /api/monitor/packages</h3>
+            <p>List watched packages, or register one for release monitoring</p>
+            <pre>Request: {"ecosystem": "pypi", "name": "requests"}</pre>
+            <pre>Response: {"id": "pypi:requests", "last_version": null, ...}</pre>
+        </div>
+        
+        <div class="endpoint">
+            <h3>DELETE /api/monitor/packages/{id}</h3>
+            <p>Stop watching a package</p>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /api/monitor/check</h3>
+            <p>Check watched packages for new releases immediately</p>
+        </div>
+        
+        <div class="endpoint">
+            <h3>GET /api/feed.atom, GET /api/feed.json</h3>
+            <p>Atom and JSON feeds of newly analyzed versions, linking to delta reports at <code>/api/monitor/reports/{entry_id}</code></p>
+        </div>
+        
+        <div class="endpoint">
+            <h3>POST /webhooks/github</h3>
+            <p>GitHub webhook (content type <code>application/json</code>) for the repositories under
+            <code>server.github_webhook</code>. Deliveries need a valid <code>X-Hub-Signature-256</code>; pushes to the
+            default branch and published releases queue a processing job and any configured analyses.</p>
+            <pre>Response: {"status": "queued", "reason": "push of 0123456 to main", "jobs": ["uuid"], "errors": []}</pre>
+        </div>
+    </body>
+    </html>
+    "##
+}
+
+/// OpenAPI specification endpoint
+async fn openapi_spec() -> ResponseJson<Value> {
+    ResponseJson(json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "LlamaPackageService API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Transform code repositories into structured text representations",
+            "contact": {
+                "name": "Nik Jois",
+                "email": "nikjois@llamasearch.ai"
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "API key or HS256 JWT with read, submit or admin scope"
+                }
+            },
+            "responses": {
+                "TooManyRequests": {
+                    "description": "The client is over its rate limit (any endpoint but health may answer this), or its workspace over its quota",
+                    "headers": {
+                        "Retry-After": {
+                            "description": "Seconds to wait before retrying",
+                            "schema": {"type": "integer"}
+                        }
+                    }
+                }
+            }
+        },
+        "security": [{"bearer": []}],
+        "servers": [
+            {
+                "url": "http://localhost:8000",
+                "description": "Development server"
+            }
+        ],
+        "paths": {
+            "/health": {
+                "get": {
+                    "summary": "Health check",
+                    "security": [],
+                    "responses": {
+                        "200": {
+                            "description": "Service health information"
+                        }
+                    }
+                }
+            },
+            "/healthz": {
+                "get": {
+                    "summary": "Liveness probe",
+                    "security": [],
+                    "responses": {
+                        "200": {
+                            "description": "The process is serving requests",
+                            "content": {"text/plain": {"schema": {"type": "string"}}}
+                        }
+                    }
+                }
+            },
+            "/readyz": {
+                "get": {
+                    "summary": "Readiness probe",
+                    "description": "Ready once the output and cache directories are writable and at least one package registry is reachable",
+                    "security": [],
+                    "responses": {
+                        "200": {"description": "Ready; the report lists each check"},
+                        "503": {"description": "Not ready; the report lists the failed checks"}
+                    }
+                }
+            },
+            "/webhooks/github": {
+                "post": {
+                    "summary": "GitHub webhook for configured repositories",
+                    "description": "Authenticated by X-Hub-Signature-256. Pushes to the default branch and published releases queue a processing job and any configured analyses.",
+                    "security": [],
+                    "responses": {
+                        "200": {"description": "Delivery ignored, or the repository is already queued"},
+                        "202": {"description": "Jobs queued"},
+                        "401": {"description": "Missing or invalid signature"},
+                        "404": {"description": "No webhook secret is configured"},
+                        "429": {"description": "The repository's workspace is over its quota"}
+                    }
+                }
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Prometheus metrics",
+                    "security": [],
+                    "responses": {
+                        "200": {
+                            "description": "Metrics in the Prometheus text format",
+                            "content": {"text/plain": {"schema": {"type": "string"}}}
+                        }
+                    }
+                }
+            },
+            "/api/process": {
+                "post": {
+                    "summary": "Process repository",
+                    "requestBody": {
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "url": {"type": "string"},
+                                        "output_dir": {"type": "string"},
+                                        "processor": {
+                                            "type": "string",
+                                            "description": "Processor to use instead of detecting one from the URL (local, github, pypi, pypi-profile, npm, crates, go, or a plugin name)"
+                                        }
+                                    },
+                                    "required": ["url"]
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Job submitted successfully"
+                        },
+                        "403": {"description": "A workspace credential asked for a local path or a plugin"},
+                        "429": {"$ref": "#/components/responses/TooManyRequests"}
+                    }
+                }
+            },
+            "/api/jobs": {
+                "get": {
+                    "summary": "List jobs, filtered, sorted and paged",
+                    "parameters": [
+                        {"name": "status", "in": "query", "schema": {"type": "string"}, "description": "Comma-separated statuses"},
+                        {"name": "source", "in": "query", "schema": {"type": "string"}, "description": "Text in the URL type, e.g. github"},
+                        {"name": "since", "in": "query", "schema": {"type": "string"}, "description": "RFC 3339 time or YYYY-MM-DD"},
+                        {"name": "q", "in": "query", "schema": {"type": "string"}, "description": "Text the URL contains"},
+                        {"name": "sort", "in": "query", "schema": {"type": "string", "enum": ["created", "updated", "url"]}},
+                        {"name": "order", "in": "query", "schema": {"type": "string", "enum": ["desc", "asc"]}},
+                        {"name": "limit", "in": "query", "schema": {"type": "integer", "default": 50, "maximum": 500}},
+                        {"name": "page", "in": "query", "schema": {"type": "string"}, "description": "next_page of the previous page"}
+                    ],
+                    "responses": {
+                        "200": {"description": "A page of jobs, the total matching and the next page token"},
+                        "400": {"description": "Invalid filter, sort or page token"}
+                    }
+                },
+                "post": {
+                    "summary": "Submit a job; same body as /api/process",
+                    "responses": {
+                        "200": {"description": "Job submitted successfully"},
+                        "400": {"description": "Invalid URL or processor"}
+                    }
+                }
+            },
+            "/api/jobs/{job_id}": {
+                "get": {
+                    "summary": "Job status, progress and error",
+                    "responses": {
+                        "200": {"description": "Job status"},
+                        "404": {"description": "Unknown job"}
+                    }
+                },
+                "delete": {
+                    "summary": "Cancel a queued or running job, or forget a finished one",
+                    "responses": {
+                        "200": {"description": "The job as it was left"},
+                        "404": {"description": "Unknown job"}
+                    }
+                }
+            },
+            "/api/jobs/{job_id}/events": {
+                "get": {
+                    "summary": "Live job progress as Server-Sent Events",
+                    "responses": {
+                        "200": {
+                            "description": "A stream of progress events ending when the job finishes",
+                            "content": {"text/event-stream": {}}
+                        },
+                        "404": {"description": "Unknown job"}
+                    }
+                }
+            },
+            "/api/keys": {
+                "get": {
+                    "summary": "List API keys (admin)",
+                    "responses": {"200": {"description": "Issued keys, without the keys themselves"}}
+                },
+                "post": {
+                    "summary": "Issue an API key (admin)",
+                    "responses": {
+                        "201": {"description": "The new key; it is not shown again"},
+                        "400": {"description": "Missing scopes, or an invalid workspace"}
+                    }
+                }
+            },
+            "/api/keys/{key_id}": {
+                "delete": {
+                    "summary": "Revoke an API key (admin)",
+                    "responses": {
+                        "204": {"description": "Revoked"},
+                        "404": {"description": "Unknown key"}
+                    }
+                }
+            },
+            "/api/worker/claim": {
+                "post": {
+                    "summary": "Claim the oldest queued job as a remote worker (work)",
+                    "responses": {
+                        "200": {"description": "The job id and its request"},
+                        "204": {"description": "No job is queued"}
+                    }
+                }
+            },
+            "/api/worker/jobs/{job_id}/progress": {
+                "post": {
+                    "summary": "Report a claimed job's progress (work)",
+                    "responses": {
+                        "204": {"description": "Recorded; carry on"},
+                        "404": {"description": "Unknown job"},
+                        "409": {"description": "The job was cancelled or queued again; drop it"}
+                    }
+                }
+            },
+            "/api/worker/jobs/{job_id}/artifacts/{path}": {
+                "put": {
+                    "summary": "Upload a file a claimed job generated (work)",
+                    "responses": {
+                        "204": {"description": "Stored in the job's output directory"},
+                        "400": {"description": "The path leaves the output directory"},
+                        "404": {"description": "Unknown job"},
+                        "409": {"description": "The job was cancelled or queued again; drop it"}
+                    }
+                }
+            },
+            "/api/worker/jobs/{job_id}/finish": {
+                "post": {
+                    "summary": "Record the outcome of a claimed job (work)",
+                    "responses": {
+                        "204": {"description": "Recorded"},
+                        "404": {"description": "Unknown job"},
+                        "409": {"description": "The job was cancelled or queued again"}
+                    }
+                }
+            },
+            "/api/jobs/{job_id}/artifacts": {
+                "get": {
+                    "summary": "Files generated by the job",
+                    "responses": {
+                        "200": {"description": "Artifact paths and sizes"},
+                        "404": {"description": "Unknown job"}
+                    }
+                }
+            },
+            "/artifacts/{job_id}/{path}": {
+                "get": {
+                    "summary": "Download a file generated by the job",
+                    "parameters": [
+                        {"name": "Range", "in": "header", "required": false, "schema": {"type": "string"}, "description": "A single byte range, e.g. bytes=1048576-"},
+                        {"name": "If-Range", "in": "header", "required": false, "schema": {"type": "string"}, "description": "Only honour Range if the file still has this ETag"},
+                        {"name": "If-None-Match", "in": "header", "required": false, "schema": {"type": "string"}}
+                    ],
+                    "responses": {
+                        "200": {"description": "The whole file, with its ETag"},
+                        "206": {"description": "The requested range, with Content-Range"},
+                        "304": {"description": "The file still has the ETag given in If-None-Match"},
+                        "404": {"description": "Unknown job or file"},
+                        "416": {"description": "The range lies outside the file"}
+                    }
+                }
+            },
+            "/api/analyses": {
+                "get": {
+                    "summary": "Analysis jobs and the tokens they used in total",
+                    "responses": {"200": {"description": "Analysis jobs, newest first, with summed token usage and estimated cost"}}
+                },
+                "post": {
+                    "summary": "Queue an AI analysis as a job",
+                    "requestBody": {
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "repository": {"type": "string"},
+                                        "analysis_type": {
+                                            "type": "string",
+                                            "description": "documentation, security, code_review, examples, api_docs, or a custom prompt"
+                                        },
+                                        "context": {"type": "string"}
+                                    },
+                                    "required": ["repository", "analysis_type"]
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "202": {"description": "Analysis queued; follow it with the job endpoints"},
+                        "400": {"description": "Missing fields, or analysis is not configured"},
+                        "429": {"$ref": "#/components/responses/TooManyRequests"}
+                    }
+                }
+            },
+            "/api/analyses/{job_id}": {
+                "get": {
+                    "summary": "An analysis job and, once completed, its result",
+                    "responses": {
+                        "200": {"description": "The job with its token usage, and the result in Markdown"},
+                        "404": {"description": "Unknown analysis"}
+                    }
+                }
+            },
+            "/api/workspaces/{workspace}/usage": {
+                "get": {
+                    "summary": "Quota and usage of a workspace",
+                    "responses": {
+                        "200": {"description": "Jobs submitted in the last 24 hours, storage used and quota"},
+                        "404": {"description": "Another workspace's usage"}
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// Get memory usage information
+fn get_memory_usage() -> Value {
+    // This is a placeholder - in a real implementation, you'd use system metrics
+    json!({
+        "rss_bytes": 0,
+        "heap_bytes": 0,
+        "message": "Memory metrics not implemented"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::Service;
+
+    #[tokio::test]
+    async fn test_revoking_the_last_key_keeps_the_api_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api-keys.json");
+        let auth = Arc::new(Authenticator::new(Some(path.clone()), None, None).unwrap());
+        let issued = auth.create_key("ci", vec![Scope::Read], None).unwrap();
+        assert!(auth.revoke_key(&issued.info.id).unwrap());
+
+        let mut app = axum::Router::new()
+            .route("/api/jobs", axum::routing::get(|| async { "jobs" }))
+            .route_layer(axum::middleware::from_fn_with_state((auth, Scope::Read), authorize));
+        for key in [None, Some(issued.key.as_str())] {
+            let mut request = axum::http::Request::builder().uri("/api/jobs");
+            if let Some(key) = key {
+                request = request.header("x-api-key", key);
+            }
+            let response = app.call(request.body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        // The API stays closed after a restart too
+        assert!(Authenticator::new(Some(path), None, None).unwrap().is_enabled());
+    }
+
+    fn state_in(dir: &std::path::Path) -> AppState {
+        let config = Config::new(dir.to_path_buf());
+        AppState {
+            job_manager: Arc::new(JobManager::new(config.clone()).unwrap()),
+            monitor: Arc::new(PackageMonitor::load(Arc::new(config.clone())).unwrap()),
+            auth: Arc::new(Authenticator::new(Some(dir.join("api-keys.json")), None, None).unwrap()),
+            limits: Arc::new(ClientLimits::new(&config.server)),
+            http_metrics: Arc::new(RequestMetrics::new()),
+            readiness: Arc::new(Readiness::new(dir.to_path_buf(), dir.join("cache"))),
+            conversations: Arc::new(ConversationStore::open(&dir.join(CONVERSATIONS_DB_FILE)).unwrap()),
+            github_webhook: None,
+            base_url: "http://localhost:8000".to_string(),
+        }
+    }
+
+    async fn send(app: &mut Router, method: &str, uri: &str, key: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-api-key", key)
+            .header(header::CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let response = app.call(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_query_tokens_only_open_event_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = state_in(dir.path());
+        let key = state.auth.create_key("sse", vec![Scope::Read], None).unwrap().key;
+        let mut app = create_app(state);
+        let mut fetch = |uri: String| {
+            let request = axum::http::Request::builder().uri(uri).body(Body::empty()).unwrap();
+            app.call(request)
+        };
+
+        assert_eq!(fetch(format!("/api/jobs?access_token={}", key)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(fetch("/api/jobs/missing/events".into()).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(fetch(format!("/api/jobs/missing/events?access_token={}", key)).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // Handlers, and so the request log, see the URI without the token
+        let mut echo = axum::Router::new()
+            .route("/api/jobs/:job_id/events", axum::routing::get(|uri: Uri| async move { uri.to_string() }))
+            .layer(middleware::from_fn(take_query_token));
+        let request = axum::http::Request::builder()
+            .uri(format!("/api/jobs/j1/events?since=3&access_token={}", key))
+            .body(Body::empty())
+            .unwrap();
+        let body = axum::body::to_bytes(echo.call(request).await.unwrap().into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"/api/jobs/j1/events?since=3");
+    }
+
+    #[tokio::test]
+    async fn test_workspaces_cannot_reach_each_others_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = state_in(dir.path());
+        let scopes = || vec![Scope::Read, Scope::Submit];
+        let a = state.auth.create_key("a", scopes(), Some("team-a".into())).unwrap().key;
+        let b = state.auth.create_key("b", scopes(), Some("team-b".into())).unwrap().key;
+        let conversation = ConversationContext {
+            id: "c1".to_string(),
+            repository: "https://github.com/acme/demo".to_string(),
+            messages: Vec::new(),
+            repository_context: String::new(),
+            session_state: Default::default(),
+            index: None,
+            files: None,
+            workspace: Some("team-b".to_string()),
+        };
+        state.conversations.save(&conversation).unwrap();
+        let mut app = create_app(state.clone());
+
+        let (status, job) = send(&mut app, "POST", "/api/process", &b, Some(json!({ "url": "https://github.com/acme/demo" }))).await;
+        assert_eq!(status, StatusCode::OK);
+        let job = format!("/api/jobs/{}", job["job_id"].as_str().unwrap());
+        assert_eq!(send(&mut app, "GET", &job, &a, None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&mut app, "GET", &job, &b, None).await.0, StatusCode::OK);
+
+        // Paths on the server and plugins are for operators only
+        let local = json!({ "url": dir.path(), "repository": dir.path(), "analysis_type": "documentation" });
+        for uri in ["/api/process", "/api/analyses", "/api/analyze", "/api/conversation"] {
+            assert_eq!(send(&mut app, "POST", uri, &a, Some(local.clone())).await.0, StatusCode::FORBIDDEN, "{}", uri);
+        }
+
+        assert_eq!(send(&mut app, "GET", "/api/conversation/c1", &a, None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&mut app, "POST", "/api/conversation/c1/message", &a, Some(json!({ "message": "hi" }))).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&mut app, "DELETE", "/api/conversation/c1", &a, None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&mut app, "GET", "/api/conversation/c1", &b, None).await.0, StatusCode::OK);
+        assert!(state.conversations.load("c1").unwrap().is_some());
+
+        let (status, package) = send(&mut app, "POST", "/api/monitor/packages", &b, Some(json!({ "ecosystem": "npm", "name": "left-pad" }))).await;
+        assert_eq!(status, StatusCode::OK);
+        let package = format!("/api/monitor/packages/{}", package["id"].as_str().unwrap());
+        assert_eq!(send(&mut app, "GET", "/api/monitor/packages", &a, None).await.1["total"], 0);
+        assert_eq!(send(&mut app, "POST", "/api/monitor/check", &a, None).await.1["total"], 0);
+        assert_eq!(send(&mut app, "DELETE", &package, &a, None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&mut app, "GET", "/api/monitor/packages", &b, None).await.1["total"], 1);
+    }
+}
@@ -0,0 +1,361 @@
+//! Static documentation site for the organized output directory
+//!
+//! Builds a self-contained HTML site: a landing page with per-source
+//! overviews, one page per source type, one page per processed package with
+//! its full analysis, and client-side search over names, descriptions and
+//! the start of each analysis. The search index is shipped as a script file
+//! so the site also works when opened straight from disk.
+
+use crate::compression;
+use crate::encryption;
+use crate::output_organizer::{self, format_file_size, package_slug, PackageInfo};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Directory, relative to the output directory, the site is written to by default
+pub const SITE_DIR: &str = "_site";
+
+/// Largest analysis rendered inline on a package page; bigger ones are cut and linked
+const MAX_INLINE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Characters of each analysis included in the search index
+const SEARCH_EXCERPT_CHARS: usize = 1000;
+
+/// File marking a directory as a generated site that may be replaced on the next build
+const SITE_MARKER: &str = ".llama-site";
+
+const STYLE: &str = r#"body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Helvetica, Arial, sans-serif; line-height: 1.6; padding: 20px; max-width: 1200px; margin: 0 auto; color: #2c3e50; }
+nav { margin-bottom: 20px; }
+nav a { margin-right: 12px; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 20px; }
+th, td { text-align: left; padding: 10px; vertical-align: top; }
+th { background-color: #34495e; color: white; }
+tr:nth-child(even) { background-color: #f2f2f2; }
+.muted { color: #7f8c8d; font-size: 0.9em; }
+.nowrap { white-space: nowrap; }
+.search { padding: 10px; width: 100%; box-sizing: border-box; margin-bottom: 10px; }
+#results li { margin-bottom: 6px; }
+pre { background: #f7f7f7; padding: 16px; overflow-x: auto; white-space: pre-wrap; word-wrap: break-word; }
+.cards { display: flex; flex-wrap: wrap; gap: 12px; margin-bottom: 20px; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 12px 16px; min-width: 160px; }
+.card strong { font-size: 1.4em; display: block; }
+"#;
+
+const SEARCH_SCRIPT: &str = r#"(function () {
+  var box = document.getElementById('search');
+  var results = document.getElementById('results');
+  if (!box || !results || !window.SEARCH_INDEX) { return; }
+  var root = box.dataset.root || '';
+  box.addEventListener('input', function () {
+    var terms = box.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.innerHTML = '';
+    if (!terms.length) { return; }
+    var hits = window.SEARCH_INDEX.filter(function (p) {
+      var hay = (p.name + ' ' + p.source + ' ' + (p.version || '') + ' ' + (p.description || '') + ' ' + p.text).toLowerCase();
+      return terms.every(function (t) { return hay.indexOf(t) !== -1; });
+    }).slice(0, 50);
+    hits.forEach(function (p) {
+      var li = document.createElement('li');
+      var a = document.createElement('a');
+      a.href = root + p.url;
+      a.textContent = p.name + (p.version ? ' ' + p.version : '');
+      li.appendChild(a);
+      var meta = document.createElement('span');
+      meta.className = 'muted';
+      meta.textContent = ' (' + p.source + ') ' + (p.description || '');
+      li.appendChild(meta);
+      results.appendChild(li);
+    });
+    if (!hits.length) { results.innerHTML = '<li class="muted">No matches</li>'; }
+  });
+})();
+"#;
+
+/// Entry of the client-side search index
+#[derive(Debug, Serialize)]
+struct SearchEntry<'a> {
+    name: &'a str,
+    source: &'a str,
+    version: Option<&'a str>,
+    description: Option<&'a str>,
+    url: String,
+    text: String,
+}
+
+/// Builds the site for `output_dir` into `site_dir` (defaults to `<output_dir>/_site`)
+///
+/// Returns the path of the site's `index.html`.
+pub fn build(output_dir: &Path, site_dir: Option<&Path>) -> io::Result<PathBuf> {
+    let packages = output_organizer::collect_packages(output_dir)?;
+    let site_dir = site_dir.map(Path::to_path_buf).unwrap_or_else(|| output_dir.join(SITE_DIR));
+    generate_site(output_dir, &packages, &site_dir)
+}
+
+/// Writes the site for already collected packages, replacing any previous site in `site_dir`
+///
+/// Only a directory holding a site marker (or the default `_site`) is cleared;
+/// any other non-empty directory is refused so `--out` cannot wipe unrelated files.
+pub(crate) fn generate_site(output_dir: &Path, packages: &[PackageInfo], site_dir: &Path) -> io::Result<PathBuf> {
+    if site_dir.exists() {
+        if site_dir.join(SITE_MARKER).exists() || site_dir == output_dir.join(SITE_DIR) {
+            fs::remove_dir_all(site_dir)?;
+        } else if fs::read_dir(site_dir)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty and was not generated as a site; choose an empty directory", site_dir.display()),
+            ));
+        }
+    }
+    let packages_dir = site_dir.join("packages");
+    let sources_dir = site_dir.join("sources");
+    fs::create_dir_all(&packages_dir)?;
+    fs::create_dir_all(&sources_dir)?;
+    fs::write(site_dir.join(SITE_MARKER), "")?;
+    fs::write(site_dir.join("style.css"), STYLE)?;
+    fs::write(site_dir.join("search.js"), SEARCH_SCRIPT)?;
+
+    // Assign every package a unique page name
+    let mut used = HashSet::new();
+    let pages: Vec<String> = packages.iter()
+        .map(|package| {
+            let base = format!("{}-{}", package_slug(&package.name), package.timestamp.format("%Y%m%d%H%M%S"));
+            let mut page = format!("{}.html", base);
+            let mut n = 2;
+            while !used.insert(page.clone()) {
+                page = format!("{}-{}.html", base, n);
+                n += 1;
+            }
+            page
+        })
+        .collect();
+
+    let mut by_source: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, package) in packages.iter().enumerate() {
+        by_source.entry(source_key(package)).or_default().push(i);
+    }
+
+    let mut search = Vec::with_capacity(packages.len());
+    for (package, page) in packages.iter().zip(&pages) {
+        // Encrypted artifacts are only linked, so the site holds none of their text
+        let (content, truncated) = if encryption::is_encrypted(&package.file_path) {
+            (String::new(), false)
+        } else {
+            read_inline(&package.file_path)?
+        };
+        let raw_link = relative_link(&package.file_path, &packages_dir);
+        write_package_page(&packages_dir.join(page), package, &content, truncated, &raw_link)?;
+
+        search.push(SearchEntry {
+            name: &package.name,
+            source: source_key(package),
+            version: package.version.as_deref(),
+            description: package.description.as_deref(),
+            url: format!("packages/{}", page),
+            text: content.chars().take(SEARCH_EXCERPT_CHARS).collect(),
+        });
+    }
+    let search_json = serde_json::to_string(&search)?;
+    fs::write(site_dir.join("search-index.js"), format!("window.SEARCH_INDEX = {};\n", search_json))?;
+
+    for (source, indices) in &by_source {
+        let mut rows = String::new();
+        for &i in indices {
+            rows.push_str(&package_row(&packages[i], &format!("../packages/{}", pages[i])));
+        }
+        let body = format!(
+            "<h1>{source} packages</h1>\n<p class=\"muted\">{count} processed</p>\n{table}",
+            source = escape(source),
+            count = indices.len(),
+            table = package_table(&rows),
+        );
+        fs::write(sources_dir.join(format!("{}.html", package_slug(source))), page_html(source, "../", &body))?;
+    }
+
+    let mut cards = String::new();
+    for (source, indices) in &by_source {
+        cards.push_str(&format!(
+            "<a class=\"card\" href=\"sources/{slug}.html\"><strong>{count}</strong>{source}</a>\n",
+            slug = package_slug(source),
+            count = indices.len(),
+            source = escape(source),
+        ));
+    }
+    let mut recent = String::new();
+    for (i, package) in packages.iter().enumerate().take(25) {
+        recent.push_str(&package_row(package, &format!("packages/{}", pages[i])));
+    }
+    let body = format!(
+        "<h1>Package Index</h1>\n<p class=\"muted\">Generated {generated} from {output} &middot; {total} packages</p>\n\
+         <input type=\"search\" id=\"search\" class=\"search\" data-root=\"\" placeholder=\"Search packages and analyses...\">\n\
+         <ul id=\"results\"></ul>\n<div class=\"cards\">\n{cards}</div>\n<h2>Recently processed</h2>\n{table}",
+        generated = Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        output = escape(&output_dir.display().to_string()),
+        total = packages.len(),
+        cards = cards,
+        table = package_table(&recent),
+    );
+    let index_path = site_dir.join("index.html");
+    fs::write(&index_path, page_html("Package Index", "", &body))?;
+
+    Ok(index_path)
+}
+
+/// Source type without the file type suffix, e.g. `pypi` for `pypi (processed)`
+fn source_key(package: &PackageInfo) -> &str {
+    package.source.split_whitespace().next().unwrap_or("unknown")
+}
+
+/// Reads an analysis for inline display, decompressing and capping its size
+fn read_inline(path: &Path) -> io::Result<(String, bool)> {
+    let mut bytes = Vec::new();
+    compression::open_artifact(path)?.take(MAX_INLINE_BYTES + 1).read_to_end(&mut bytes)?;
+    let truncated = bytes.len() as u64 > MAX_INLINE_BYTES;
+    bytes.truncate(MAX_INLINE_BYTES as usize);
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), truncated))
+}
+
+/// Link from pages in `from_dir` to `target`, falling back to an absolute path
+fn relative_link(target: &Path, from_dir: &Path) -> String {
+    let target = target.canonicalize().unwrap_or_else(|_| target.to_path_buf());
+    let from_dir = from_dir.canonicalize().unwrap_or_else(|_| from_dir.to_path_buf());
+    pathdiff::diff_paths(&target, &from_dir)
+        .unwrap_or(target)
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace(' ', "%20")
+}
+
+fn write_package_page(path: &Path, package: &PackageInfo, content: &str, truncated: bool, raw_link: &str) -> io::Result<()> {
+    let source = source_key(package);
+    let mut body = format!(
+        "<h1>{name}</h1>\n<table>\n\
+         <tr><th>Source</th><td><a href=\"../sources/{source_slug}.html\">{source}</a></td></tr>\n\
+         <tr><th>Version</th><td>{version}</td></tr>\n\
+         <tr><th>Processed</th><td>{processed}</td></tr>\n\
+         <tr><th>Size</th><td>{size}</td></tr>\n\
+         <tr><th>Description</th><td>{description}</td></tr>\n\
+         <tr><th>Artifact</th><td><a href=\"{raw}\">{file}</a></td></tr>\n</table>\n",
+        name = escape(&package.name),
+        source_slug = package_slug(source),
+        source = escape(source),
+        version = escape(package.version.as_deref().unwrap_or("-")),
+        processed = package.timestamp.format("%Y-%m-%d %H:%M"),
+        size = format_file_size(package.file_size),
+        description = escape(package.description.as_deref().unwrap_or("")),
+        raw = raw_link,
+        file = escape(&package.file_path.file_name().unwrap_or_default().to_string_lossy()),
+    );
+    if encryption::is_encrypted(&package.file_path) {
+        body.push_str("<p class=\"muted\">The artifact is encrypted; open it with the output key to read the analysis.</p>\n");
+        return fs::write(path, page_html(&package.name, "../", &body));
+    }
+    if truncated {
+        body.push_str("<p class=\"muted\">Analysis truncated for display; open the artifact for the full text.</p>\n");
+    }
+    body.push_str(&format!("<pre>{}</pre>\n", escape(content)));
+    fs::write(path, page_html(&package.name, "../", &body))
+}
+
+fn package_table(rows: &str) -> String {
+    format!(
+        "<table>\n<tr><th>Package</th><th>Version</th><th>Processed</th><th>Size</th><th>Description</th></tr>\n{}</table>\n",
+        rows
+    )
+}
+
+fn package_row(package: &PackageInfo, href: &str) -> String {
+    format!(
+        "<tr><td><a href=\"{href}\">{name}</a></td><td>{version}</td><td class=\"nowrap\">{processed}</td><td class=\"nowrap\">{size}</td><td>{description}</td></tr>\n",
+        href = href,
+        name = escape(&package.name),
+        version = escape(package.version.as_deref().unwrap_or("")),
+        processed = package.timestamp.format("%Y-%m-%d %H:%M"),
+        size = format_file_size(package.file_size),
+        description = escape(package.description.as_deref().unwrap_or("")),
+    )
+}
+
+/// Wraps a page body in the shared layout; `root` is the relative path back to the site root
+fn page_html(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n\
+         <title>{title}</title>\n<link rel=\"stylesheet\" href=\"{root}style.css\">\n</head>\n<body>\n\
+         <nav><a href=\"{root}index.html\">Index</a></nav>\n{body}\
+         <script src=\"{root}search-index.js\"></script>\n<script src=\"{root}search.js\"></script>\n</body>\n</html>\n",
+        title = escape(title),
+        root = root,
+        body = body,
+    )
+}
+
+/// Escapes text for inclusion in HTML
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_site_has_source_package_and_search_pages() {
+        let dir = tempdir().unwrap();
+        let pypi = dir.path().join("pypi_packages");
+        fs::create_dir_all(&pypi).unwrap();
+        fs::write(
+            pypi.join("20250301_193131_requests_pypi_processed.txt"),
+            "# requests\n\n**Description**: HTTP for <humans>\n",
+        ).unwrap();
+
+        let index = build(dir.path(), None).unwrap();
+        let site = dir.path().join(SITE_DIR);
+        assert_eq!(index, site.join("index.html"));
+        assert!(fs::read_to_string(&index).unwrap().contains("sources/pypi.html"));
+        assert!(site.join("sources/pypi.html").exists());
+
+        let page = fs::read_dir(site.join("packages")).unwrap().next().unwrap().unwrap().path();
+        let html = fs::read_to_string(page).unwrap();
+        assert!(html.contains("HTTP for &lt;humans&gt;"));
+        assert!(html.contains("../../pypi_packages/20250301_193131_requests_pypi_processed.txt"));
+
+        let search = fs::read_to_string(site.join("search-index.js")).unwrap();
+        assert!(search.starts_with("window.SEARCH_INDEX = ["));
+        assert!(search.contains("\"name\":\"requests\""));
+    }
+
+    #[test]
+    fn test_site_only_replaces_generated_directories() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("site");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("notes.txt"), "keep me").unwrap();
+
+        assert!(build(dir.path(), Some(&out)).is_err());
+        assert!(out.join("notes.txt").exists());
+
+        let empty = dir.path().join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        build(dir.path(), Some(&empty)).unwrap();
+        fs::write(empty.join("stale.html"), "old").unwrap();
+        build(dir.path(), Some(&empty)).unwrap();
+        assert!(!empty.join("stale.html").exists());
+        assert!(empty.join("index.html").exists());
+    }
+}
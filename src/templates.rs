@@ -0,0 +1,145 @@
+//! Report templating
+//!
+//! Teams can override the structure of generated overview, analysis and index
+//! files by dropping Tera templates into a `templates` folder next to the
+//! config file (by default `~/.config/llama-package-service/templates`).
+//! When no template is present for a report the built-in layout is used.
+
+use crate::config::Config;
+use crate::error::{ProcessorError, Result};
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera};
+
+/// The kinds of report that can be customized with a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    /// Package/repository overview written by the remote processors
+    Overview,
+    /// Local repository analysis written by the local processor
+    Analysis,
+    /// Markdown index generated by `generate_index`
+    Index,
+}
+
+impl TemplateKind {
+    /// File name the template is looked up under
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            TemplateKind::Overview => "overview.md.tera",
+            TemplateKind::Analysis => "analysis.md.tera",
+            TemplateKind::Index => "index.md.tera",
+        }
+    }
+}
+
+/// Holds the user-provided templates loaded from disk
+pub struct TemplateEngine {
+    tera: Tera,
+}
+
+impl TemplateEngine {
+    /// Returns the directory user templates are loaded from
+    ///
+    /// This is the `templates` folder beside the config file, so `--config`
+    /// and `LLAMA_CONFIG` move the templates along with it.
+    pub fn default_dir() -> Option<PathBuf> {
+        let config_path = Config::config_path().ok()?;
+        Some(config_path.parent()?.join("templates"))
+    }
+
+    /// Loads every known template present in `dir`
+    ///
+    /// Missing templates are not an error; only templates that fail to parse are.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut tera = Tera::default();
+
+        for kind in [TemplateKind::Overview, TemplateKind::Analysis, TemplateKind::Index] {
+            let path = dir.join(kind.file_name());
+            if path.is_file() {
+                tera.add_template_file(&path, Some(kind.file_name()))
+                    .map_err(|e| ProcessorError::Template(format!("{}: {}", path.display(), e)))?;
+            }
+        }
+
+        Ok(Self { tera })
+    }
+
+    /// Checks whether a template was provided for the given report kind
+    pub fn has(&self, kind: TemplateKind) -> bool {
+        self.tera.get_template_names().any(|name| name == kind.file_name())
+    }
+
+    /// Renders the template for `kind` with the given context
+    pub fn render(&self, kind: TemplateKind, context: &Context) -> Result<String> {
+        self.tera.render(kind.file_name(), context)
+            .map_err(|e| ProcessorError::Template(format!("{}: {}", kind.file_name(), e)))
+    }
+}
+
+static USER_TEMPLATES: Lazy<Option<TemplateEngine>> = Lazy::new(|| {
+    let dir = TemplateEngine::default_dir()?;
+    if !dir.is_dir() {
+        return None;
+    }
+    match TemplateEngine::load(&dir) {
+        Ok(engine) => Some(engine),
+        Err(e) => {
+            log::warn!("Ignoring user templates: {}", e);
+            None
+        }
+    }
+});
+
+/// Renders the user template for `kind` if one is installed
+///
+/// Returns `None` when no template exists, so callers fall back to their
+/// built-in layout. Render failures are logged and also fall back.
+pub fn render_user_template(kind: TemplateKind, context: &Context) -> Option<String> {
+    let engine = USER_TEMPLATES.as_ref()?;
+    if !engine.has(kind) {
+        return None;
+    }
+    match engine.render(kind, context) {
+        Ok(rendered) => Some(rendered),
+        Err(e) => {
+            log::warn!("Falling back to built-in layout: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_render_custom_overview() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("overview.md.tera"),
+            "# {{ package_name }} ({{ package_type }})\n{{ content }}",
+        ).unwrap();
+
+        let engine = TemplateEngine::load(dir.path()).unwrap();
+        assert!(engine.has(TemplateKind::Overview));
+        assert!(!engine.has(TemplateKind::Index));
+
+        let mut context = Context::new();
+        context.insert("package_name", "serde");
+        context.insert("package_type", "crate");
+        context.insert("content", "body");
+
+        let rendered = engine.render(TemplateKind::Overview, &context).unwrap();
+        assert_eq!(rendered, "# serde (crate)\nbody");
+    }
+
+    #[test]
+    fn test_invalid_template_is_reported() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("index.md.tera"), "{% if %}").unwrap();
+
+        assert!(matches!(TemplateEngine::load(dir.path()), Err(ProcessorError::Template(_))));
+    }
+}
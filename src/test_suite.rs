@@ -8,6 +8,7 @@ use reqwest::Client;
 use which::which;
 use std::future::Future;
 use std::pin::Pin;
+use crate::http;
 use crate::output_organizer::OutputPaths;
 use tempfile::TempDir;
 
@@ -115,7 +116,7 @@ pub async fn run_installation_tests() -> Result<(), ProcessorError> {
 }
 
 async fn test_github_connection() -> Result<(), ProcessorError> {
-    let client = reqwest::Client::new();
+    let client = http::client()?;
     let response = client
         .get("https://api.github.com/rate_limit")
         .header("User-Agent", "LlamaSearch")
@@ -140,7 +141,7 @@ fn test_file_permissions(test_dir: &PathBuf) -> Result<(), ProcessorError> {
 }
 
 async fn test_network_connectivity() -> Result<(), ProcessorError> {
-    let client = reqwest::Client::new();
+    let client = http::client()?;
     client
         .get("https://api.github.com")
         .send()
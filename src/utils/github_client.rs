@@ -1,5 +1,5 @@
 pub fn create_github_client_with_retry(token: Option<String>) -> reqwest::Client {
-    reqwest::Client::builder()
+    crate::http::client_builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()
         .unwrap()
@@ -0,0 +1,251 @@
+//! GitHub webhooks that keep processed repositories current
+//!
+//! Repositories listed under `server.github_webhook` get a job queued when
+//! GitHub reports a push to their default branch or a published release, so
+//! their documentation and analyses follow the code. Deliveries are accepted
+//! only with a valid `X-Hub-Signature-256`, the HMAC-SHA256 of the body under
+//! the secret shared with GitHub; without a secret the endpoint stays off.
+//!
+//! ```toml
+//! [server.github_webhook]
+//! secret = "..."   # or LLAMA_GITHUB_WEBHOOK_SECRET
+//!
+//! [[server.github_webhook.repositories]]
+//! name = "owner/repo"
+//! events = ["push", "release"]
+//! analyses = ["documentation"]
+//! ```
+
+use crate::error::{ProcessorError, Result};
+use crate::workspace;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+/// Environment variable holding the webhook secret, preferred over the config file
+pub const SECRET_ENV_VAR: &str = "LLAMA_GITHUB_WEBHOOK_SECRET";
+
+/// Events a repository can be processed on
+pub const EVENTS: &[&str] = &["push", "release"];
+
+/// Webhook settings in the `server` section of the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitHubWebhookConfig {
+    /// Secret shared with GitHub; `LLAMA_GITHUB_WEBHOOK_SECRET` takes precedence
+    pub secret: Option<String>,
+    /// Repositories to process when they change
+    pub repositories: Vec<WebhookRepository>,
+}
+
+/// A repository processed on webhook deliveries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookRepository {
+    /// Repository as `owner/repo`
+    pub name: String,
+    /// Events that queue a job: `push` (to the default branch) and `release`
+    #[serde(default = "default_events")]
+    pub events: Vec<String>,
+    /// Workspace the jobs are queued in, and whose quota they count against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// AI analyses to queue as well, such as `documentation` or `security`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub analyses: Vec<String>,
+}
+
+fn default_events() -> Vec<String> {
+    EVENTS.iter().map(|event| event.to_string()).collect()
+}
+
+/// What to do with a delivery
+#[derive(Debug, PartialEq)]
+pub enum Delivery<'a> {
+    /// Process the repository at `url`
+    Process {
+        /// The configured repository
+        repository: &'a WebhookRepository,
+        /// URL to submit
+        url: String,
+        /// What changed, for logs and the response
+        reason: String,
+    },
+    /// Nothing to do, and why
+    Ignored(String),
+}
+
+/// Verifies and routes GitHub deliveries
+pub struct GitHubWebhook {
+    secret: Vec<u8>,
+    repositories: Vec<WebhookRepository>,
+}
+
+impl GitHubWebhook {
+    /// The webhook described by `config`, or `None` when no secret is set
+    ///
+    /// Fails on repositories that are not `owner/repo`, unknown events and
+    /// invalid workspace names, so mistakes show at startup.
+    pub fn from_config(config: &GitHubWebhookConfig) -> Result<Option<Self>> {
+        let secret = std::env::var(SECRET_ENV_VAR).ok()
+            .or_else(|| config.secret.clone())
+            .filter(|secret| !secret.is_empty());
+        let Some(secret) = secret else {
+            return Ok(None);
+        };
+        for repository in &config.repositories {
+            let mut parts = repository.name.split('/');
+            let valid_name = matches!((parts.next(), parts.next(), parts.next()),
+                (Some(owner), Some(repo), None) if !owner.is_empty() && !repo.is_empty());
+            if !valid_name {
+                return Err(ProcessorError::Config(format!(
+                    "webhook repository '{}' must be given as owner/repo", repository.name
+                )));
+            }
+            if let Some(event) = repository.events.iter().find(|event| !EVENTS.contains(&event.as_str())) {
+                return Err(ProcessorError::Config(format!(
+                    "webhook repository '{}' lists unknown event '{}' (use {})",
+                    repository.name, event, EVENTS.join(" or ")
+                )));
+            }
+            if let Some(name) = &repository.workspace {
+                workspace::validate_name(name)?;
+            }
+        }
+        Ok(Some(Self { secret: secret.into_bytes(), repositories: config.repositories.clone() }))
+    }
+
+    /// Whether `signature`, an `X-Hub-Signature-256` header, signs `body`
+    pub fn verify(&self, body: &[u8], signature: Option<&str>) -> bool {
+        let Some(expected) = signature
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .and_then(|hex_digest| hex::decode(hex_digest).ok())
+        else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(body);
+        mac.verify_slice(&expected).is_ok()
+    }
+
+    /// Decides what a verified delivery of `event` asks for
+    pub fn route(&self, event: &str, payload: &Value) -> Delivery<'_> {
+        if event == "ping" {
+            return Delivery::Ignored("ping received".to_string());
+        }
+        let Some(full_name) = payload["repository"]["full_name"].as_str() else {
+            return Delivery::Ignored(format!("'{}' event without a repository", event));
+        };
+        let Some(repository) = self.repositories.iter().find(|r| r.name.eq_ignore_ascii_case(full_name)) else {
+            return Delivery::Ignored(format!("{} is not configured", full_name));
+        };
+        if !repository.events.iter().any(|configured| configured == event) {
+            return Delivery::Ignored(format!("'{}' events are not processed for {}", event, full_name));
+        }
+        let reason = match event {
+            "push" => {
+                let default_branch = payload["repository"]["default_branch"].as_str().unwrap_or("main");
+                let pushed = payload["ref"].as_str().unwrap_or_default();
+                if pushed != format!("refs/heads/{}", default_branch) {
+                    return Delivery::Ignored(format!("push to {} rather than {}", pushed, default_branch));
+                }
+                if payload["deleted"].as_bool().unwrap_or(false) {
+                    return Delivery::Ignored(format!("{} was deleted", pushed));
+                }
+                let commit = payload["after"].as_str().unwrap_or_default();
+                format!("push of {} to {}", commit.get(..7).unwrap_or(commit), default_branch)
+            }
+            "release" => {
+                let action = payload["action"].as_str().unwrap_or_default();
+                if action != "published" {
+                    return Delivery::Ignored(format!("release {}", action));
+                }
+                format!("release {}", payload["release"]["tag_name"].as_str().unwrap_or("without a tag"))
+            }
+            other => return Delivery::Ignored(format!("'{}' events are not processed", other)),
+        };
+        Delivery::Process { repository, url: format!("https://github.com/{}", full_name), reason }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn webhook() -> GitHubWebhook {
+        let config: GitHubWebhookConfig = toml::from_str(r#"
+            secret = "It's a Secret to Everybody"
+
+            [[repositories]]
+            name = "Owner/Repo"
+
+            [[repositories]]
+            name = "owner/releases-only"
+            events = ["release"]
+        "#).unwrap();
+        GitHubWebhook::from_config(&config).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_signatures() {
+        // Example delivery from GitHub's webhook documentation
+        let webhook = webhook();
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(webhook.verify(b"Hello, World!", Some(signature)));
+        assert!(!webhook.verify(b"Hello, World?", Some(signature)));
+        assert!(!webhook.verify(b"Hello, World!", Some("sha256=00")));
+        assert!(!webhook.verify(b"Hello, World!", None));
+    }
+
+    #[test]
+    fn test_routing() {
+        let webhook = webhook();
+        let push = |name: &str, pushed: &str| json!({
+            "ref": pushed,
+            "after": "0123456789abcdef",
+            "deleted": false,
+            "repository": {"full_name": name, "default_branch": "main"},
+        });
+
+        match webhook.route("push", &push("owner/repo", "refs/heads/main")) {
+            Delivery::Process { repository, url, reason } => {
+                assert_eq!(repository.name, "Owner/Repo");
+                assert_eq!(url, "https://github.com/owner/repo");
+                assert_eq!(reason, "push of 0123456 to main");
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(webhook.route("push", &push("owner/repo", "refs/heads/dev")), Delivery::Ignored(_)));
+        assert!(matches!(webhook.route("push", &push("owner/repo", "refs/tags/v1")), Delivery::Ignored(_)));
+        assert!(matches!(webhook.route("push", &push("someone/else", "refs/heads/main")), Delivery::Ignored(_)));
+        assert!(matches!(webhook.route("push", &push("owner/releases-only", "refs/heads/main")), Delivery::Ignored(_)));
+
+        let release = |action: &str| json!({
+            "action": action,
+            "release": {"tag_name": "v1.2.0"},
+            "repository": {"full_name": "owner/releases-only"},
+        });
+        assert!(matches!(webhook.route("release", &release("published")),
+            Delivery::Process { reason, .. } if reason == "release v1.2.0"));
+        assert!(matches!(webhook.route("release", &release("edited")), Delivery::Ignored(_)));
+        assert!(matches!(webhook.route("ping", &json!({})), Delivery::Ignored(_)));
+    }
+
+    #[test]
+    fn test_config_is_checked() {
+        let config = |name: &str, events: &[&str]| GitHubWebhookConfig {
+            secret: Some("secret".to_string()),
+            repositories: vec![WebhookRepository {
+                name: name.to_string(),
+                events: events.iter().map(|e| e.to_string()).collect(),
+                workspace: None,
+                analyses: Vec::new(),
+            }],
+        };
+        assert!(GitHubWebhook::from_config(&config("owner/repo", &["push"])).unwrap().is_some());
+        assert!(GitHubWebhook::from_config(&config("owner", &["push"])).is_err());
+        assert!(GitHubWebhook::from_config(&config("owner/repo", &["issues"])).is_err());
+        assert!(GitHubWebhook::from_config(&GitHubWebhookConfig::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,290 @@
+//! Remote workers that process the API server's jobs on other machines
+//!
+//! With `server.remote_workers` set, the server queues jobs without processing
+//! them, and any number of `llamapackageservice serve --worker <server-url>`
+//! processes pull them over HTTP, one job at a time each:
+//!
+//! 1. `POST /api/worker/claim` hands over the oldest queued job, or answers
+//!    `204 No Content` when there is none
+//! 2. the worker processes it into a scratch directory under its own
+//!    configuration, reporting progress to `POST /api/worker/jobs/<id>/progress`
+//!    at least every [`HEARTBEAT_INTERVAL`]
+//! 3. every file the job generated goes to `PUT /api/worker/jobs/<id>/artifacts/<path>`
+//! 4. `POST /api/worker/jobs/<id>/finish` records the outcome
+//!
+//! The server answers a report with `409 Conflict` once the job was cancelled,
+//! and the worker drops it. A worker that stops reporting for
+//! [`REMOTE_LEASE`](crate::api::REMOTE_LEASE) loses its job to the queue, so
+//! another worker picks it up. Workers authenticate with a credential that has
+//! the `work` scope.
+
+use crate::api::{self, ClaimedJob, JobRequest, WorkerOutcome, WorkerProgress};
+use crate::cancellation;
+use crate::config::Config;
+use crate::error::{ProcessorError, Result};
+use crate::http;
+use crate::progress::{ProgressAggregator, ProgressEvent, ProgressSink};
+use log::{info, warn};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Longest a worker goes without reporting progress on its job
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a worker waits before asking again when no job is queued
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Processes jobs claimed from an API server
+pub struct Worker {
+    client: Client,
+    server_url: String,
+    api_key: Option<String>,
+    poll_interval: Duration,
+    config: Config,
+}
+
+impl Worker {
+    /// Creates a worker for the server at `server_url`, processing under `config`
+    pub fn new(config: Config, server_url: &str) -> Result<Self> {
+        Ok(Self {
+            client: http::client()?,
+            server_url: server_url.trim_end_matches('/').to_string(),
+            api_key: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            config,
+        })
+    }
+
+    /// Authenticates with `api_key`, an API key or JWT with the `work` scope
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    /// Waits `interval` before asking again when no job is queued
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Processes jobs until interrupted with Ctrl-C
+    ///
+    /// A server that cannot be reached is tried again after the poll interval.
+    pub async fn run(&self) -> Result<()> {
+        info!("Working for {}", self.server_url);
+        while !cancellation::is_cancelled() {
+            match self.run_once().await {
+                Ok(true) => continue,
+                Ok(false) => {}
+                // A server refusing the credential will not change its mind
+                Err(e @ ProcessorError::Config(_)) => return Err(e),
+                Err(e) => warn!("{}", e),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.poll_interval) => {}
+                _ = cancellation::cancelled() => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Claims one job and processes it, returning whether there was a job
+    pub async fn run_once(&self) -> Result<bool> {
+        let Some(job) = self.claim().await? else {
+            return Ok(false);
+        };
+        info!("Processing job {}", job.job_id);
+        self.process(&job).await?;
+        Ok(true)
+    }
+
+    /// Processes `job` in a scratch directory, then uploads what it generated and reports the outcome
+    async fn process(&self, job: &ClaimedJob) -> Result<()> {
+        let scratch = tempfile::tempdir()?;
+        let latest = Arc::new(Mutex::new(WorkerProgress { progress: 0, operation: "Claimed by a worker".into(), eta_seconds: None }));
+        let step = {
+            let latest = Arc::clone(&latest);
+            move |progress: u8, operation: &str| {
+                *latest.lock().unwrap_or_else(|e| e.into_inner()) = WorkerProgress { progress, operation: operation.to_string(), eta_seconds: None };
+            }
+        };
+        // Processing runs from 30% to 80% of the job, as on the server
+        let sink: ProgressSink = {
+            let latest = Arc::clone(&latest);
+            let aggregator = ProgressAggregator::new(1);
+            let record = aggregator.sink(0);
+            Arc::new(move |event| {
+                record(event.clone());
+                let overall = aggregator.snapshot();
+                let mut latest = latest.lock().unwrap_or_else(|e| e.into_inner());
+                if let ProgressEvent::Stage { stage } = event {
+                    latest.operation = stage;
+                }
+                latest.progress = latest.progress.max(30 + overall.percent / 2);
+                latest.eta_seconds = overall.eta.map(|eta| eta.as_secs());
+            })
+        };
+
+        let work = async {
+            match &job.request {
+                JobRequest::Process(request) => api::run_process(&self.config, request, scratch.path(), sink, step)
+                    .await
+                    .map(|files| (files, None)),
+                JobRequest::Analysis(request) => api::run_analysis(&self.config, request, &job.job_id, scratch.path(), step)
+                    .await
+                    .map(|(files, usage)| (files, Some(usage))),
+            }
+        };
+        tokio::pin!(work);
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut work => break outcome,
+                _ = heartbeat.tick() => {
+                    let progress = latest.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    if !self.report(&job.job_id, &progress).await? {
+                        warn!("Job {} was cancelled; dropping it", job.job_id);
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let outcome = match outcome {
+            Ok((files, usage)) => {
+                let uploading = WorkerProgress { progress: 90, operation: "Uploading artifacts".into(), eta_seconds: None };
+                if !self.report(&job.job_id, &uploading).await? {
+                    return Ok(());
+                }
+                for file in &files {
+                    if !self.upload(&job.job_id, scratch.path(), file).await? {
+                        return Ok(());
+                    }
+                }
+                WorkerOutcome { output_files: files, error: None, usage }
+            }
+            Err(e) => WorkerOutcome { error: Some(e.to_string()), ..WorkerOutcome::default() },
+        };
+        self.finish(&job.job_id, &outcome).await
+    }
+
+    /// Asks the server for the oldest queued job
+    async fn claim(&self) -> Result<Option<ClaimedJob>> {
+        let response = self.send(self.request(Method::POST, &["claim"])?).await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            _ => Ok(Some(response.json().await?)),
+        }
+    }
+
+    /// Reports progress on a job, returning whether the job is still the worker's
+    async fn report(&self, job_id: &str, progress: &WorkerProgress) -> Result<bool> {
+        let request = self.request(Method::POST, &["jobs", job_id, "progress"])?.json(progress);
+        Ok(self.send(request).await?.status() != StatusCode::CONFLICT)
+    }
+
+    /// Uploads the file at `path` under `dir`, returning whether the job is still the worker's
+    async fn upload(&self, job_id: &str, dir: &Path, path: &str) -> Result<bool> {
+        let data = tokio::fs::read(dir.join(path)).await?;
+        let mut segments = vec!["jobs", job_id, "artifacts"];
+        segments.extend(path.split(['/', '\\']).filter(|segment| !segment.is_empty()));
+        let request = self.request(Method::PUT, &segments)?.body(data);
+        Ok(self.send(request).await?.status() != StatusCode::CONFLICT)
+    }
+
+    /// Reports the outcome of a job
+    async fn finish(&self, job_id: &str, outcome: &WorkerOutcome) -> Result<()> {
+        let request = self.request(Method::POST, &["jobs", job_id, "finish"])?.json(outcome);
+        if self.send(request).await?.status() == StatusCode::CONFLICT {
+            warn!("Job {} was cancelled before it finished", job_id);
+        }
+        Ok(())
+    }
+
+    /// Request to the worker endpoint at `segments` under `/api/worker`
+    fn request(&self, method: Method, segments: &[&str]) -> Result<RequestBuilder> {
+        let mut url = url::Url::parse(&self.server_url)?;
+        url.path_segments_mut()
+            .map_err(|_| ProcessorError::Config(format!("not a server URL: {}", self.server_url)))?
+            .pop_if_empty()
+            .extend(["api", "worker"])
+            .extend(segments);
+        let request = self.client.request(method, url);
+        Ok(match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        })
+    }
+
+    /// Sends `request`, turning error statuses other than `409 Conflict` into errors
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await
+            .map_err(|e| ProcessorError::Network(format!("cannot reach the server at {}: {}", self.server_url, e)))?;
+        let code = response.status();
+        if code.is_success() || code == StatusCode::CONFLICT {
+            return Ok(response);
+        }
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        let message = body["error"].as_str().map_or_else(|| code.to_string(), str::to_string);
+        Err(match code {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ProcessorError::Config(format!(
+                "the server refused the worker ({}); it needs a credential with the work scope", message
+            )),
+            _ => ProcessorError::Network(format!("the server answered {}: {}", code, message)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_claimed_jobs_are_processed_uploaded_and_finished() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("lib.py"), "def answer():\n    return 42\n").unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let mut config = Config::new(output.path().to_path_buf());
+        config.output_dir = output.path().to_path_buf();
+
+        let mut server = mockito::Server::new_async().await;
+        let claim = server.mock("POST", "/api/worker/claim")
+            .match_header("authorization", "Bearer lps_worker")
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "job_id": "job-1",
+                "request": { "url": source.path().to_string_lossy(), "output_dir": "/srv/elsewhere" },
+            }).to_string())
+            .create_async().await;
+        let progress = server.mock("POST", "/api/worker/jobs/job-1/progress")
+            .with_status(204)
+            .expect_at_least(1)
+            .create_async().await;
+        let upload = server.mock("PUT", Matcher::Regex(r"^/api/worker/jobs/job-1/artifacts/.+".into()))
+            .match_body(Matcher::Regex("answer".into()))
+            .with_status(204)
+            .expect_at_least(1)
+            .create_async().await;
+        let finish = server.mock("POST", "/api/worker/jobs/job-1/finish")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::PartialJson(serde_json::json!({ "error": null })),
+                Matcher::Regex(r#""output_files":\["[^"]+"#.into()),
+            ]))
+            .with_status(204)
+            .create_async().await;
+
+        let worker = Worker::new(config, &format!("{}/", server.url())).unwrap().with_api_key(Some("lps_worker".into()));
+        assert!(worker.run_once().await.unwrap());
+        claim.assert_async().await;
+        progress.assert_async().await;
+        upload.assert_async().await;
+        finish.assert_async().await;
+
+        // Nothing queued
+        server.mock("POST", "/api/worker/claim").with_status(204).create_async().await;
+        assert!(!worker.run_once().await.unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+//! Workspaces for teams sharing one API server
+//!
+//! A credential tied to a workspace (an API key issued with a `workspace`, or
+//! a JWT with a `workspace` claim) only sees that workspace's jobs, writes its
+//! output under `<output>/workspaces/<name>/`, and is held to the workspace's
+//! [`WorkspaceQuota`]: jobs submitted per day and disk space used by its
+//! output. It may only process sources fetched from the network: local paths
+//! and plugins run on the server itself. Credentials without a workspace are
+//! operators and see everything.
+
+use crate::config::WorkspaceQuota;
+use crate::error::{ProcessorError, Result};
+use crate::processors::ProcessorFactory;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Directory under the output directory holding one directory per workspace
+pub const WORKSPACES_DIR: &str = "workspaces";
+
+/// Checks that `name` can be used as a workspace: 1 to 64 lowercase letters,
+/// digits, `-` or `_`
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ProcessorError::Validation(format!(
+            "invalid workspace name '{}' (use up to 64 lowercase letters, digits, '-' or '_')",
+            name
+        )))
+    }
+}
+
+/// Directory holding everything a workspace's jobs write
+pub fn root(output_dir: &Path, name: &str) -> PathBuf {
+    output_dir.join(WORKSPACES_DIR).join(name)
+}
+
+/// Output directory for a job in the workspace at `root`
+///
+/// A requested directory must be relative and is placed inside the
+/// workspace, so one team cannot write into another's output.
+pub fn resolve_output_dir(root: &Path, requested: Option<&str>) -> Result<PathBuf> {
+    let Some(requested) = requested.filter(|dir| !dir.trim().is_empty()) else {
+        return Ok(root.to_path_buf());
+    };
+    let relative = Path::new(requested.trim());
+    if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(ProcessorError::Validation(format!(
+            "output_dir '{}' must be a relative path inside the workspace",
+            requested
+        )));
+    }
+    Ok(root.join(relative))
+}
+
+/// Bytes used by the files under `dir`
+pub fn storage_used(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Refuses a new job once `quota` is used up; 0 in a quota means no limit
+pub fn check_quota(name: &str, quota: &WorkspaceQuota, jobs_today: usize, storage_bytes: u64) -> Result<()> {
+    if quota.jobs_per_day > 0 && jobs_today >= quota.jobs_per_day as usize {
+        return Err(ProcessorError::QuotaExceeded(format!(
+            "workspace '{}' has submitted its {} jobs for the last 24 hours",
+            name, quota.jobs_per_day
+        )));
+    }
+    if quota.storage_mb > 0 && storage_bytes >= quota.storage_mb * 1024 * 1024 {
+        return Err(ProcessorError::QuotaExceeded(format!(
+            "workspace '{}' uses {} MB of its {} MB storage; delete old output to submit more jobs",
+            name,
+            storage_bytes / (1024 * 1024),
+            quota.storage_mb
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses a source that workspace `name` may not process: a path on the
+/// server, or one handled by a plugin
+pub fn check_source(name: &str, url: &str, processor: Option<&str>) -> Result<()> {
+    if ProcessorFactory::runs_on_host(url, processor) {
+        return Err(ProcessorError::Forbidden(format!(
+            "workspace '{}' cannot process '{}': local paths and plugins are limited to operators",
+            name, url
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_names_and_paths() {
+        assert!(validate_name("team-a_2").is_ok());
+        for bad in ["", "Team", "a/b", "..", &"x".repeat(65)] {
+            assert!(validate_name(bad).is_err(), "{:?}", bad);
+        }
+
+        let root = root(Path::new("output"), "team-a");
+        assert_eq!(root, Path::new("output/workspaces/team-a"));
+        assert_eq!(resolve_output_dir(&root, None).unwrap(), root);
+        assert_eq!(resolve_output_dir(&root, Some("nightly/run")).unwrap(), root.join("nightly/run"));
+        assert!(resolve_output_dir(&root, Some("../team-b")).is_err());
+        assert!(resolve_output_dir(&root, Some("/tmp")).is_err());
+    }
+
+    #[test]
+    fn test_quotas() {
+        let quota = WorkspaceQuota { jobs_per_day: 2, storage_mb: 1 };
+        assert!(check_quota("a", &quota, 1, 0).is_ok());
+        assert!(matches!(check_quota("a", &quota, 2, 0), Err(ProcessorError::QuotaExceeded(_))));
+        assert!(matches!(check_quota("a", &quota, 0, 1024 * 1024), Err(ProcessorError::QuotaExceeded(_))));
+        assert!(check_quota("a", &WorkspaceQuota { jobs_per_day: 0, storage_mb: 0 }, 1000, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_workspaces_only_process_network_sources() {
+        assert!(check_source("a", "https://github.com/acme/demo", None).is_ok());
+        assert!(check_source("a", "https://github.com/acme/demo", Some("github")).is_ok());
+        for (url, processor) in [("./src", None), ("/etc", None), ("https://github.com/acme/demo", Some("local")), ("demo", Some("my-plugin"))] {
+            assert!(matches!(check_source("a", url, processor), Err(ProcessorError::Forbidden(_))), "{} {:?}", url, processor);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use assert_cmd::prelude::*;
+use serde_json::Value;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn project() -> TempDir {
+    let dir = TempDir::new().expect("failed to create project dir");
+    fs::write(dir.path().join("README.md"), "# Tool\n\nA tool.\n").expect("failed to write file");
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n").expect("failed to write file");
+    dir
+}
+
+fn run(out_dir: &Path, args: &[&str]) -> Output {
+    Command::cargo_bin("llamapackageservice")
+        .expect("binary not found")
+        .arg("--output")
+        .arg(out_dir)
+        .args(args)
+        .output()
+        .expect("failed to run binary")
+}
+
+// `--format json` prints one JSON report on stdout, listing what the run wrote
+#[test]
+fn format_json_reports_the_run() {
+    let project = project();
+    let out_dir = TempDir::new().expect("failed to create output dir");
+
+    let output = run(out_dir.path(), &["--format", "json", "process", project.path().to_str().unwrap()]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let report: Value = serde_json::from_slice(&output.stdout).expect("stdout is not a single JSON document");
+    assert_eq!(report["command"], "process");
+    assert_eq!(report["success"], true);
+    assert_eq!(report["exit_code"], 0);
+    let artifacts = report["artifacts"].as_array().expect("no artifacts");
+    assert!(!artifacts.is_empty());
+    assert!(artifacts.iter().all(|path| Path::new(path.as_str().unwrap()).exists()), "{:?}", artifacts);
+}
+
+// Failures are reported in JSON too, with the same exit code as the process
+#[test]
+fn format_json_reports_failures() {
+    let project = project();
+    let out_dir = TempDir::new().expect("failed to create output dir");
+
+    let output = run(out_dir.path(), &["--format", "json", "process", project.path().to_str().unwrap(), "--stdout"]);
+    assert_eq!(output.status.code(), Some(2));
+    let report: Value = serde_json::from_slice(&output.stdout).expect("stdout is not a single JSON document");
+    assert_eq!(report["success"], false);
+    assert_eq!(report["exit_code"], 2);
+    assert!(report["errors"][0].as_str().unwrap().contains("--stdout"));
+}
+
+// Progress and decoration go to stderr, so redirected stdout has no escape codes; -q silences stderr
+#[test]
+fn stdout_stays_clean_for_results() {
+    let project = project();
+    let out_dir = TempDir::new().expect("failed to create output dir");
+
+    let output = run(out_dir.path(), &["process", project.path().to_str().unwrap()]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(!output.stdout.contains(&0x1b), "escape codes on stdout: {}", String::from_utf8_lossy(&output.stdout));
+
+    let quiet = run(out_dir.path(), &["-q", "process", project.path().to_str().unwrap()]);
+    assert!(quiet.status.success());
+    assert!(String::from_utf8_lossy(&quiet.stderr).trim().is_empty(), "stderr: {}", String::from_utf8_lossy(&quiet.stderr));
+
+    let conflict = run(out_dir.path(), &["-q", "-v", "process", project.path().to_str().unwrap()]);
+    assert_eq!(conflict.status.code(), Some(2));
+}
+
+// `serve` runs the API in the foreground on the requested port until it is stopped
+#[test]
+fn serve_runs_in_the_foreground() {
+    let out_dir = TempDir::new().expect("failed to create output dir");
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+    let mut server = Command::cargo_bin("llamapackageservice")
+        .expect("binary not found")
+        .arg("--output")
+        .arg(out_dir.path())
+        .args(["serve", "--port", &port.to_string(), "--bind", "127.0.0.1", "--workers", "1"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start server");
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let response = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            panic!("server exited early with {}", status);
+        }
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response);
+            break response;
+        }
+        assert!(Instant::now() < deadline, "server did not start listening");
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
@@ -8,7 +8,7 @@ mod doc_tests {
     #[tokio::test]
     async fn test_readme_example() -> Result<(), Box<dyn std::error::Error>> {
         let config = Config::default();
-        let processor = GitHubProcessor::new();
+        let processor = GitHubProcessor::new()?;
         let temp_dir = TempDir::new()?;
         let result = processor.process("https://github.com/rust-lang/rust", temp_dir.path(), &config).await;
         assert!(result.is_ok());
@@ -0,0 +1,66 @@
+use assert_cmd::prelude::*;
+use serde_json::{json, Value};
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+// `chat --export-embeddings` writes the embeddings the conversation was built on, one line per chunk
+#[test]
+fn chat_exports_the_conversation_embeddings() {
+    let project = TempDir::new().expect("failed to create project dir");
+    fs::write(project.path().join("README.md"), "# Parser\n\nParses tokens into a tree.\n")
+        .expect("failed to write file");
+    let out_dir = TempDir::new().expect("failed to create output dir");
+    let export_dir = out_dir.path().join("vectors");
+
+    let mut server = mockito::Server::new();
+    let embeddings = server.mock("POST", "/v1/embeddings")
+        .with_body_from_request(|request| {
+            let body: Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+            let data: Vec<Value> = (0..body["input"].as_array().unwrap().len())
+                .map(|index| json!({"index": index, "embedding": [0.5, 0.25, index as f32]}))
+                .collect();
+            json!({"data": data}).to_string().into_bytes()
+        })
+        .expect_at_least(1)
+        .create();
+    server.mock("POST", "/v1/chat/completions")
+        .with_body(r#"{"choices": [{"message": {"content": "It parses tokens."}, "finish_reason": "stop"}]}"#)
+        .create();
+
+    Command::cargo_bin("llamapackageservice")
+        .expect("binary not found")
+        .env("LLAMA_AI_PROVIDER", "openai")
+        .env("OPENAI_BASE_URL", format!("{}/v1", server.url()))
+        .env("OPENAI_API_KEY", "test")
+        .arg("--output")
+        .arg(out_dir.path())
+        .args(["chat", "What does it do?", "--repo"])
+        .arg(project.path())
+        .arg("--export-embeddings")
+        .arg(&export_dir)
+        .args(["--embedding-format", "jsonl"])
+        .assert()
+        .success();
+    embeddings.assert();
+
+    let exported = fs::read_to_string(export_dir.join("embeddings.jsonl")).expect("no embeddings were exported");
+    let rows: Vec<Value> = exported.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(!rows.is_empty());
+    for row in &rows {
+        assert_eq!(row["vector"].as_array().unwrap().len(), 3);
+        assert!(row["repo"].as_str().is_some_and(|repo| !repo.is_empty()), "{}", row);
+        assert!(row["file"].as_str().is_some_and(|file| !file.is_empty()), "{}", row);
+    }
+}
+
+// Exporting needs a conversation to embed, so it cannot be combined with --resume
+#[test]
+fn chat_export_requires_a_repository() {
+    Command::cargo_bin("llamapackageservice")
+        .expect("binary not found")
+        .args(["chat", "Hi", "--resume", "abc", "--export-embeddings", "vectors"])
+        .assert()
+        .failure()
+        .code(2);
+}
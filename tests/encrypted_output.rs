@@ -0,0 +1,48 @@
+use assert_cmd::prelude::*;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const MARKER: &str = "plaintext-marker-7f3c";
+
+fn run(out_dir: &Path, args: &[&str]) {
+    Command::cargo_bin("llamapackageservice")
+        .expect("binary not found")
+        .env("LLAMA_OUTPUT_KEY", KEY)
+        .arg("--output")
+        .arg(out_dir)
+        .args(args)
+        .assert()
+        .success();
+}
+
+// With encryption on, no artifact, index or site page may contain the processed content in plain text
+#[test]
+fn encrypted_run_leaves_no_plaintext() {
+    let project = TempDir::new().expect("failed to create project dir");
+    fs::write(project.path().join("README.md"), format!("# Secret\n\n{MARKER}\n"))
+        .expect("failed to write file");
+    let out_dir = TempDir::new().expect("failed to create output dir");
+
+    run(out_dir.path(), &["process", project.path().to_str().unwrap(), "--index"]);
+    run(out_dir.path(), &["site"]);
+
+    let mut encrypted = 0;
+    for entry in walkdir::WalkDir::new(out_dir.path()).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let bytes = fs::read(entry.path()).expect("failed to read output file");
+        assert!(
+            !String::from_utf8_lossy(&bytes).contains(MARKER),
+            "plaintext found in {}",
+            entry.path().display()
+        );
+        if entry.path().extension().is_some_and(|e| e == "enc") {
+            encrypted += 1;
+        }
+    }
+    assert!(encrypted > 0, "no encrypted artifacts were written");
+}
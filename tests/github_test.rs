@@ -7,7 +7,7 @@ use tempfile::TempDir;
 #[ignore]
 async fn test_github_processor() {
     let config = Config::default();
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let temp_dir = TempDir::new().unwrap();
     
     let result = processor.process("https://github.com/rust-lang/rust", temp_dir.path(), &config).await;
@@ -23,7 +23,7 @@ async fn test_github_processor_success() {
         .with_body(read_test_json("repo.json"))
         .create();
 
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let result = processor.process("https://github.com/rust-lang/rust", temp_dir.path(), &config).await;
     assert!(result.is_ok());
 }
@@ -42,7 +42,7 @@ async fn test_github_rate_limit() {
         .with_body(r#"{"message": "API rate limit exceeded"}"#)
         .create();
 
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let result = processor.process("https://github.com/rust-lang/rust", temp_dir.path(), &config).await;
     assert!(matches!(result, Err(ProcessorError::RateLimitExceeded(_))));
 }
@@ -61,7 +61,7 @@ async fn test_github_invalid_repo() {
         .with_body(r#"{"message": "Not Found"}"#)
         .create();
 
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let result = processor.process("https://github.com/invalid/repo", temp_dir.path(), &config).await;
     assert!(matches!(result, Err(ProcessorError::GitHubApi(_))));
 }
@@ -96,7 +96,7 @@ async fn test_github_org_processing() {
         .with_body(read_test_json("repo.json"))
         .create();
 
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let _result = processor.process("https://github.com/rust-lang", temp_dir.path(), &config).await;
     
     // This might fail if your processor doesn't handle orgs - adjust as needed
@@ -25,7 +25,7 @@ async fn test_github_processor() {
         .with_header("content-type", "application/json")
         .with_body(include_str!("../test_data/github_response.json"));
 
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let result = processor.process("https://github.com/rust-lang/rust", temp_dir.path(), &config).await;
     assert!(result.is_ok());
 }
@@ -42,7 +42,7 @@ async fn test_rate_limiting() {
         .with_header("content-type", "application/json")
         .with_body(r#"{"message": "API rate limit exceeded"}"#);
 
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     let result = processor.process("https://github.com/test/repo", temp_dir.path(), &config).await;
     assert!(matches!(result, Err(ProcessorError::RateLimitExceeded(_))));
 }
@@ -53,7 +53,7 @@ async fn test_parallel_processing() {
     let (temp_dir, config) = setup_test_environment().await;
     
     // Test multiple sequential processing instead of parallel to avoid async block type issues
-    let processor = GitHubProcessor::new();
+    let processor = GitHubProcessor::new().unwrap();
     
     // Test processing multiple repos sequentially
     let result1 = processor.process("https://github.com/rust-lang/rust", temp_dir.path(), &config).await;
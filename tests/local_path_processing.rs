@@ -39,3 +39,61 @@ fn process_local_path_with_trailing_space() {
 }
 
 
+
+// Pipe mode prints the analysis and leaves the output directory untouched
+#[test]
+fn process_local_path_to_stdout_writes_no_files() {
+    let project_dir = TempDir::new().expect("failed to create project dir");
+    fs::write(project_dir.path().join("README.md"), "# Piped Project\n\nstdout-marker-41d2\n")
+        .expect("failed to write file");
+    let out_dir = TempDir::new().expect("failed to create output dir");
+
+    let mut cmd = Command::cargo_bin("llamapackageservice").expect("binary not found");
+    let output = cmd
+        .arg("--output")
+        .arg(out_dir.path())
+        .arg("process")
+        .arg(project_dir.path())
+        .arg("--stdout")
+        .output()
+        .expect("failed to run binary");
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("stdout-marker-41d2"));
+    let written: Vec<_> = walkdir::WalkDir::new(out_dir.path())
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().to_path_buf())
+        .collect();
+    assert!(written.is_empty(), "files written in pipe mode: {:?}", written);
+}
+
+// A README below --docs-min-score fails the run, after its analysis has been written
+#[test]
+fn process_local_path_below_docs_min_score_fails() {
+    let project_dir = TempDir::new().expect("failed to create project dir");
+    fs::write(project_dir.path().join("README.md"), "# Tool\n\nA tool.\n")
+        .expect("failed to write file");
+    let out_dir = TempDir::new().expect("failed to create output dir");
+
+    let run = |min_score: &str| {
+        Command::cargo_bin("llamapackageservice")
+            .expect("binary not found")
+            .arg("--output")
+            .arg(out_dir.path())
+            .args(["--docs-min-score", min_score, "process"])
+            .arg(project_dir.path())
+            .output()
+            .expect("failed to run binary")
+    };
+
+    let failed = run("90");
+    assert_eq!(failed.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("Documentation health"),
+        "stderr: {}", String::from_utf8_lossy(&failed.stderr));
+    assert!(out_dir.path().join("local_repositories").read_dir().expect("no analysis written").next().is_some());
+
+    let passed = run("10");
+    assert!(passed.status.success(), "stderr: {}", String::from_utf8_lossy(&passed.stderr));
+}
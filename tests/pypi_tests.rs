@@ -20,7 +20,7 @@ async fn test_pypi_package_success() {
         .with_body(read_test_json("pypi_package.json"))
         .create();
 
-    let processor = PyPiProcessor::new();
+    let processor = PyPiProcessor::new().unwrap();
     let result = processor.process("requests", temp_dir.path(), &config).await;
     assert!(result.is_ok());
 }
@@ -38,14 +38,14 @@ async fn test_pypi_package_not_found() {
         .with_body(r#"{"message": "Package not found"}"#)
         .create();
 
-    let processor = PyPiProcessor::new();
+    let processor = PyPiProcessor::new().unwrap();
     let result = processor.process("nonexistent-package", temp_dir.path(), &config).await;
     assert!(result.is_err());
 }
 
 #[tokio::test]
 async fn test_pypi_url_extraction() {
-    let processor = PyPiProcessor::new();
+    let processor = PyPiProcessor::new().unwrap();
     
     // Test with direct PyPI URL
     let _config = create_test_config();